@@ -0,0 +1,111 @@
+//! Read-only VirtIO block driver for the bootloader shell
+
+use spin::Mutex;
+use elinos_common::blockcache::{BlockCache, CacheMode, SectorDevice};
+use elinos_common::virtio::mmio::VIRTIO_ID_BLOCK;
+use elinos_common::virtio::transport::{Buffer, Device, Queue, QueueMemory};
+
+pub use elinos_common::ext2::structures::SECTOR_SIZE;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// Polls before a request is considered lost
+const REQUEST_TIMEOUT: usize = 10_000_000;
+
+/// Sectors kept by the cache in front of the filesystem reader
+const CACHE_SECTORS: usize = 32;
+
+static mut QUEUE_MEMORY: QueueMemory = QueueMemory::new();
+
+/// Request header, as the device expects it
+#[repr(C)]
+struct BlockRequest {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+pub struct BlockDevice {
+    device: Device,
+    queue: Queue,
+    capacity: u64,
+}
+
+impl BlockDevice {
+    /// Find and initialize the first VirtIO block device
+    fn probe() -> Option<BlockDevice> {
+        let device = Device::find(VIRTIO_ID_BLOCK)?;
+        device.negotiate(0)?;
+        let queue = device.setup_queue(0, unsafe { &mut *core::ptr::addr_of_mut!(QUEUE_MEMORY) })?;
+        device.driver_ok();
+
+        // Config space starts with the capacity in sectors
+        let capacity = device.config_u32(0) as u64 | (device.config_u32(4) as u64) << 32;
+        Some(BlockDevice { device, queue, capacity })
+    }
+
+    /// Capacity in 512-byte sectors
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn mmio_base(&self) -> usize {
+        self.device.base()
+    }
+
+    /// Read one sector
+    pub fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        if sector >= self.capacity {
+            return Err("Sector out of range");
+        }
+
+        let request = BlockRequest { kind: VIRTIO_BLK_T_IN, reserved: 0, sector };
+        let mut status: u8 = 0xff;
+        let chain = [
+            Buffer { addr: &request as *const _ as usize, len: core::mem::size_of::<BlockRequest>(), writable: false },
+            Buffer { addr: buf.as_mut_ptr() as usize, len: SECTOR_SIZE, writable: true },
+            Buffer { addr: &mut status as *mut u8 as usize, len: 1, writable: true },
+        ];
+        self.queue.submit(0, &chain);
+        self.device.notify(&self.queue);
+
+        for _ in 0..REQUEST_TIMEOUT {
+            if self.queue.pop_used().is_some() {
+                let status = unsafe { core::ptr::read_volatile(&status) };
+                return if status == VIRTIO_BLK_S_OK { Ok(()) } else { Err("Disk read failed") };
+            }
+            core::hint::spin_loop();
+        }
+        Err("Disk read timed out")
+    }
+}
+
+impl SectorDevice for BlockDevice {
+    type Error = &'static str;
+
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        BlockDevice::read_sector(self, sector, buf)
+    }
+
+    fn write_sector(&mut self, _sector: u64, _buf: &[u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        Err("Disk is read-only in the bootloader")
+    }
+}
+
+static BLOCK_DEVICE: Mutex<Option<BlockDevice>> = Mutex::new(None);
+static BLOCK_CACHE: Mutex<BlockCache<CACHE_SECTORS>> = Mutex::new(BlockCache::new(CacheMode::WriteThrough));
+
+/// Run `f` with the block device, initializing it on first use
+pub fn with_device<T>(f: impl FnOnce(&mut BlockDevice) -> Result<T, &'static str>) -> Result<T, &'static str> {
+    let mut device = BLOCK_DEVICE.lock();
+    if device.is_none() {
+        *device = Some(BlockDevice::probe().ok_or("No VirtIO block device")?);
+    }
+    f(device.as_mut().unwrap())
+}
+
+/// Read one sector through the cache
+pub fn read_cached(sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+    with_device(|dev| BLOCK_CACHE.lock().read(dev, sector, buf))
+}
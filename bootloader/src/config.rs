@@ -0,0 +1,244 @@
+//! Boot configuration, parsed from the boot.cfg built into the bootloader
+//! and overridden by settings saved in the config store on the boot disk
+
+use heapless::String;
+use elinos_common::cmdline::is_secexec_arg;
+use elinos_common::configstore::{self, STORE_PATH, STORE_SIZE};
+use elinos_common::warn_println;
+use crate::ext2;
+use crate::net::NetConfig;
+
+const BOOT_CFG: &str = include_str!("../boot.cfg");
+
+pub const MAX_CMDLINE_LEN: usize = 256;
+const MAX_FILENAME_LEN: usize = 64;
+
+/// Kernel image slot
+#[derive(Clone, Copy, PartialEq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Slot::A => "A",
+            Slot::B => "B",
+        }
+    }
+
+    pub fn other(&self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+/// Where the kernel image comes from
+#[derive(Clone, Copy, PartialEq)]
+pub enum BootSource {
+    /// The image QEMU loaded as the initrd
+    Local,
+    /// Downloaded over the network with TFTP
+    Tftp,
+}
+
+impl BootSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BootSource::Local => "local (initrd)",
+            BootSource::Tftp => "network (TFTP)",
+        }
+    }
+}
+
+pub struct BootConfig {
+    pub menu: bool,
+    pub timeout_secs: u64,
+    pub slot: Slot,
+    pub source: BootSource,
+    pub cmdline: String<MAX_CMDLINE_LEN>,
+    pub net: NetConfig,
+    pub tftp_server: [u8; 4],
+    pub tftp_file_a: String<MAX_FILENAME_LEN>,
+    pub tftp_file_b: String<MAX_FILENAME_LEN>,
+}
+
+impl BootConfig {
+    fn defaults() -> Self {
+        let mut config = BootConfig {
+            menu: true,
+            timeout_secs: 3,
+            slot: Slot::A,
+            source: BootSource::Local,
+            cmdline: String::new(),
+            net: NetConfig {
+                ip: [10, 0, 2, 15],
+                netmask: [255, 255, 255, 0],
+                gateway: [10, 0, 2, 2],
+            },
+            tftp_server: [10, 0, 2, 2],
+            tftp_file_a: String::new(),
+            tftp_file_b: String::new(),
+        };
+        let _ = config.tftp_file_a.push_str("kernel");
+        let _ = config.tftp_file_b.push_str("kernel-b");
+        config
+    }
+
+    /// Load the built-in boot.cfg, then the config store. Unknown keys and
+    /// bad values are reported and skipped.
+    pub fn load() -> Self {
+        let mut config = Self::defaults();
+
+        for (number, line) in BOOT_CFG.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let applied = match line.split_once('=') {
+                Some((key, value)) => config.apply(key.trim(), value.trim()),
+                None => false,
+            };
+            if !applied {
+                warn_println!("boot.cfg:{}: ignoring '{}'", number + 1, line);
+            }
+        }
+
+        config.apply_store();
+        config
+    }
+
+    /// Apply the settings the running system saved in the config store:
+    /// `boot.<key>` for any boot.cfg key, such as `boot.slot`, and the
+    /// `net.*` addresses, which mean the same to the kernel. A disk without
+    /// a store changes nothing. Anything that can write the root
+    /// filesystem can write the store, so `secexec.*` arguments in a
+    /// `boot.cmdline` there are dropped and those of boot.cfg kept. The store is only read here; an interrupted
+    /// update is recovered from, and written back, by the kernel.
+    fn apply_store(&mut self) {
+        let mut image = [0u8; STORE_SIZE];
+        let mut filled = 0;
+        let read = ext2::mount().and_then(|fs| {
+            let inode = fs.lookup(STORE_PATH)?;
+            fs.read_file(&inode, |chunk| {
+                let len = chunk.len().min(STORE_SIZE - filled);
+                image[filled..filled + len].copy_from_slice(&chunk[..len]);
+                filled += len;
+                Ok(())
+            })
+        });
+        if read.is_err() {
+            return;
+        }
+        let Some(committed) = configstore::select(&image) else {
+            return;
+        };
+        if committed.recovered {
+            warn_println!("Config store: last update was interrupted, using slot {}", committed.slot);
+        }
+
+        for (key, value) in configstore::entries(committed.data) {
+            let name = match key {
+                "net.ip" | "net.netmask" | "net.gateway" => key,
+                _ => match key.strip_prefix("boot.") {
+                    Some(name) => name,
+                    None => continue,
+                },
+            };
+            let applied = match name {
+                "cmdline" => self.apply_store_cmdline(value),
+                _ => self.apply(name, value),
+            };
+            if !applied {
+                warn_println!("{}: ignoring '{}={}'", STORE_PATH, key, value);
+            }
+        }
+    }
+
+    /// Take `value` from the config store as the command line, with the
+    /// `secexec.*` arguments of the one it replaces instead of its own
+    fn apply_store_cmdline(&mut self, value: &str) -> bool {
+        let mut cmdline = String::<MAX_CMDLINE_LEN>::new();
+        let stored = value.split_whitespace().filter(|arg| !is_secexec_arg(arg));
+        let builtin = self.cmdline.split_whitespace().filter(|arg| is_secexec_arg(arg));
+        for arg in stored.chain(builtin) {
+            if (!cmdline.is_empty() && cmdline.push(' ').is_err()) || cmdline.push_str(arg).is_err() {
+                return false;
+            }
+        }
+        self.cmdline = cmdline;
+        true
+    }
+
+    fn apply(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "menu" => match value {
+                "true" | "yes" | "1" => self.menu = true,
+                "false" | "no" | "0" => self.menu = false,
+                _ => return false,
+            },
+            "timeout" => match value.parse() {
+                Ok(secs) => self.timeout_secs = secs,
+                Err(_) => return false,
+            },
+            "slot" => match value {
+                "a" | "A" => self.slot = Slot::A,
+                "b" | "B" => self.slot = Slot::B,
+                _ => return false,
+            },
+            "source" => match value {
+                "local" => self.source = BootSource::Local,
+                "tftp" => self.source = BootSource::Tftp,
+                _ => return false,
+            },
+            "cmdline" => return set_string(&mut self.cmdline, value),
+            "net.ip" => return set_ip(&mut self.net.ip, value),
+            "net.netmask" => return set_ip(&mut self.net.netmask, value),
+            "net.gateway" => return set_ip(&mut self.net.gateway, value),
+            "tftp.server" => return set_ip(&mut self.tftp_server, value),
+            "tftp.file.a" => return set_string(&mut self.tftp_file_a, value),
+            "tftp.file.b" => return set_string(&mut self.tftp_file_b, value),
+            _ => return false,
+        }
+        true
+    }
+
+    /// TFTP file name for `slot`
+    pub fn tftp_file(&self, slot: Slot) -> &str {
+        match slot {
+            Slot::A => &self.tftp_file_a,
+            Slot::B => &self.tftp_file_b,
+        }
+    }
+}
+
+fn set_string<const N: usize>(target: &mut String<N>, value: &str) -> bool {
+    target.clear();
+    target.push_str(value).is_ok()
+}
+
+fn set_ip(target: &mut [u8; 4], value: &str) -> bool {
+    match parse_ip(value) {
+        Some(ip) => {
+            *target = ip;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Parse dotted-quad notation
+pub fn parse_ip(s: &str) -> Option<[u8; 4]> {
+    let mut ip = [0u8; 4];
+    let mut parts = s.split('.');
+    for octet in ip.iter_mut() {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(ip)
+}
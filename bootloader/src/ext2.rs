@@ -0,0 +1,20 @@
+//! The shared ext2 reader on the bootloader's block device
+
+use elinos_common::ext2::reader::{Ext2Reader, SectorRead};
+use crate::block::{self, SECTOR_SIZE};
+
+/// The VirtIO disk found by `block`
+pub struct BootDisk;
+
+impl SectorRead for BootDisk {
+    fn read_sector(&self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str> {
+        block::read_cached(sector, buf)
+    }
+}
+
+pub type Ext2 = Ext2Reader<BootDisk>;
+
+/// Mount the ext2 filesystem on the boot disk
+pub fn mount() -> Result<Ext2, &'static str> {
+    Ext2Reader::mount(BootDisk)
+}
@@ -3,6 +3,7 @@
 
 use core::panic::PanicInfo;
 use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use heapless::Vec;
 
 // Import shared library components
@@ -10,12 +11,25 @@ use elinos_common as common;
 use common::elf::{ElfUtils, ElfLoader, Elf64Header, Elf64Phdr, PT_LOAD};
 
 // Re-export commonly used macros and functions from shared library
-pub use common::{console_print, console_println, debug_print, debug_println};
+pub use common::{console_print, console_println, ok_println, err_println, warn_println, info_println, debug_print, debug_println};
 use common::memory::search_memory_pattern;
+use common::cmdline::Redacted;
 
 // Import modules from the bootloader library (only what bootloader needs)
 // Note: Most functionality moved to kernel
 
+// Boot menu and the drivers it needs
+mod config;
+mod block;
+mod net;
+mod tftp;
+mod ext2;
+mod menu;
+mod shell;
+
+use config::{BootConfig, BootSource, Slot, MAX_CMDLINE_LEN};
+use shell::ShellExit;
+
 // Global UART instance is now in the shared library
 pub use common::uart::UART;
 
@@ -33,12 +47,12 @@ impl ElfLoader for BootloaderElfLoader {
             // Destination: Use virtual address (kernel is linked to run at its virtual addresses)
             let dest_addr = phdr.p_vaddr as *mut u8;
             
-            console_println!("[i] LOAD segment: vaddr=0x{:x}, paddr=0x{:x}, filesz=0x{:x}, memsz=0x{:x}, offset=0x{:x}", 
+            info_println!("LOAD segment: vaddr=0x{:x}, paddr=0x{:x}, filesz=0x{:x}, memsz=0x{:x}, offset=0x{:x}", 
                            phdr.p_vaddr, phdr.p_paddr, phdr.p_filesz, phdr.p_memsz, phdr.p_offset);
             
             // Copy file content to memory
             if phdr.p_filesz > 0 {
-                console_println!("[i] Copying 0x{:x} bytes from 0x{:x} to 0x{:x}", 
+                info_println!("Copying 0x{:x} bytes from 0x{:x} to 0x{:x}", 
                                phdr.p_filesz, src_addr as usize, dest_addr as usize);
                 core::ptr::copy_nonoverlapping(src_addr, dest_addr, phdr.p_filesz as usize);
             }
@@ -47,7 +61,7 @@ impl ElfLoader for BootloaderElfLoader {
             if phdr.p_memsz > phdr.p_filesz {
                 let bss_start = dest_addr.add(phdr.p_filesz as usize);
                 let bss_size = phdr.p_memsz - phdr.p_filesz;
-                console_println!("[i] Zeroing BSS: 0x{:x} bytes at 0x{:x}", 
+                info_println!("Zeroing BSS: 0x{:x} bytes at 0x{:x}", 
                                bss_size, bss_start as usize);
                 core::ptr::write_bytes(bss_start, 0, bss_size as usize);
             }
@@ -58,13 +72,39 @@ impl ElfLoader for BootloaderElfLoader {
 
 // Bootloader-specific constants
 
+/// Where a kernel read from the network or disk is staged before its
+/// segments are loaded
+const LOAD_AREA_ADDR: usize = 0x84000000;
+const LOAD_AREA_SIZE: usize = 32 * 1024 * 1024;
+
+/// How far past slot A's image to look for slot B's
+const SLOT_B_SEARCH_SIZE: usize = 32 * 1024 * 1024;
+
+/// Kernel command line handed to the kernel (outside the kernel's load area)
+static mut KERNEL_CMDLINE: [u8; MAX_CMDLINE_LEN] = [0; MAX_CMDLINE_LEN];
+
+/// Device tree blob the firmware passed, handed on to the kernel
+static DTB_ADDR: AtomicUsize = AtomicUsize::new(0);
+
+/// Hart the firmware started us on, handed on to the kernel
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// What to boot
+#[derive(Clone, Copy)]
+pub enum BootTarget {
+    /// The kernel selected by the boot configuration
+    Configured,
+    /// A kernel ELF image already in memory at this address
+    Image(usize),
+}
+
 #[link_section = ".text.boot"]
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     unsafe {
         asm!(
             "li sp, 0x805f0000",  // Bootloader stack (after 0x80200000 + space for bootloader)
-            "j {main}",
+            "j {main}",           // a0 = hart id, a1 = device tree, as the firmware passed them
             main = sym bootloader_main,
             options(noreturn)
         );
@@ -74,10 +114,10 @@ pub extern "C" fn _start() -> ! {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // Print the panic message
-    console_println!("[x]  BOOTLOADER PANIC: {}", info.message());
+    err_println!("BOOTLOADER PANIC: {}", info.message());
     
     if let Some(location) = info.location() {
-        console_println!("[i] Location: {}:{}:{}", location.file(), location.line(), location.column());
+        info_println!("Location: {}:{}:{}", location.file(), location.line(), location.column());
     }
     
     loop {
@@ -96,12 +136,23 @@ struct BootloaderInfo {
     kernel_base: usize,
     available_ram_start: usize,
     available_ram_size: usize,
+    cmdline_addr: usize,
+    cmdline_len: usize,
+    /// Device tree blob from the firmware, 0 if none
+    dtb_addr: usize,
+    /// Hart the firmware booted on
+    boot_hart: usize,
 }
 
 const BOOTLOADER_MAGIC: u64 = 0xEA15_0000_B007_AB1E;
 
 #[no_mangle]
-pub extern "C" fn bootloader_main() -> ! {
+pub extern "C" fn bootloader_main(hart_id: usize, dtb_addr: usize) -> ! {
+    // Find RAM and the UART before anything is printed
+    if let Some(fdt) = unsafe { common::fdt::Fdt::from_addr(dtb_addr) } {
+        common::platform::init(&fdt);
+    }
+
     // Initialize basic console system first
     if let Err(e) = common::console::init_console() {
         // If console fails, we can't do much, just hang
@@ -115,34 +166,92 @@ pub extern "C" fn bootloader_main() -> ! {
     console_println!();
     console_println!();
     console_println!("elinOS Bootloader Starting...");
-    console_println!("[o] Console initialized");
+    ok_println!("Console initialized");
+    DTB_ADDR.store(dtb_addr, Ordering::Relaxed);
+    BOOT_HART.store(hart_id, Ordering::Relaxed);
 
     // Initialize hardware detection and memory layout
-    console_println!("[i] Detecting system memory...");
+    info_println!("Detecting system memory...");
     let memory_region = common::memory::hardware::detect_main_ram()
         .unwrap_or_else(|| common::memory::hardware::get_fallback_ram());
-    console_println!("[o] Memory detection complete");
-    console_println!("[i] Main RAM: 0x{:x} - 0x{:x} ({} MB)", 
+    ok_println!("Memory detection complete");
+    info_println!("Main RAM: 0x{:x} - 0x{:x} ({} MB)", 
                      memory_region.start, 
                      memory_region.start + memory_region.size, 
                      memory_region.size / (1024 * 1024));
 
     // Get memory layout info (bootloader doesn't need full memory manager)
     let _memory_layout = common::memory::layout::get_memory_layout();
-    console_println!("[o] Memory layout available for kernel handoff");
+    ok_println!("Memory layout available for kernel handoff");
 
-    console_println!("[i] Bootloader initialization complete");
-    console_println!("[i] Starting kernel...");
-    console_println!();
+    info_println!("Bootloader initialization complete");
+
+    // Boot menu: slot, source and command line for this boot
+    let mut config = BootConfig::load();
+    let target = menu::run(&mut config);
 
     // Stage 2: Load and jump to separate kernel binary
-    load_and_start_kernel()
+    boot(&mut config, target)
 }
 
-/// Load and start the kernel binary
-fn load_and_start_kernel() -> ! {
-    console_println!("[i] Loading kernel binary...");
-    
+/// Load the kernel and start it. If it cannot be loaded, open the recovery
+/// shell until the user picks something that does.
+fn boot(config: &mut BootConfig, mut target: BootTarget) -> ! {
+    loop {
+        let result = match target {
+            BootTarget::Configured => {
+                info_println!("Loading kernel (slot {}, {})...", config.slot.name(), config.source.name());
+                match config.source {
+                    // Load kernel from initrd (QEMU loads it to a known location)
+                    BootSource::Local => locate_kernel_from_initrd(config.slot),
+                    BootSource::Tftp => fetch_kernel_over_tftp(config),
+                }
+            }
+            BootTarget::Image(addr) => load_kernel_image(addr),
+        };
+
+        match result {
+            Ok(()) => start_kernel(config),
+            Err(e) => {
+                err_println!("Kernel not loaded: {}", e);
+                warn_println!("Entering the recovery shell");
+            }
+        }
+
+        target = loop {
+            match shell::run(config) {
+                ShellExit::Boot(target) => break target,
+                ShellExit::Menu => break menu::open(config),
+            }
+        };
+    }
+}
+
+/// Load the kernel ELF image at `addr`
+fn load_kernel_image(addr: usize) -> Result<(), &'static str> {
+    if !is_kernel_elf(addr) {
+        return Err("Not a 64-bit RISC-V ELF image");
+    }
+    if !load_elf_segments(addr) {
+        return Err("Failed to load ELF segments");
+    }
+    ok_println!("Kernel ELF loaded from 0x{:x}", addr);
+    Ok(())
+}
+
+/// Whether `addr` holds a 64-bit little-endian RISC-V ELF header
+fn is_kernel_elf(addr: usize) -> bool {
+    unsafe {
+        let magic = core::slice::from_raw_parts(addr as *const u8, 4);
+        let ei_class = core::ptr::read_volatile((addr + 4) as *const u8);
+        let ei_data = core::ptr::read_volatile((addr + 5) as *const u8);
+        let e_machine = core::ptr::read_volatile((addr + 18) as *const u16);
+        magic == [0x7f, b'E', b'L', b'F'] && ei_class == 2 && ei_data == 1 && e_machine == 0xf3
+    }
+}
+
+/// Start the kernel loaded at the kernel base
+fn start_kernel(config: &BootConfig) -> ! {
     // Get memory info from hardware detection
     let memory_region = common::memory::hardware::detect_main_ram()
         .unwrap_or_else(|| common::memory::hardware::get_fallback_ram());
@@ -154,6 +263,13 @@ fn load_and_start_kernel() -> ! {
     let available_ram_start = 0x80400000 + 2 * 1024 * 1024; // Start after kernel space
     let available_ram_size = memory_region.size.saturating_sub(kernel_memory_usage);
     
+    let cmdline = config.cmdline.as_bytes();
+    let cmdline_addr = unsafe {
+        let buffer = &mut *core::ptr::addr_of_mut!(KERNEL_CMDLINE);
+        buffer[..cmdline.len()].copy_from_slice(cmdline);
+        buffer.as_ptr() as usize
+    };
+    
     let bootloader_info = BootloaderInfo {
         magic: BOOTLOADER_MAGIC,
         memory_base: memory_region.start,
@@ -161,9 +277,13 @@ fn load_and_start_kernel() -> ! {
         kernel_base: 0x80400000, // Kernel loads at this address
         available_ram_start,
         available_ram_size,
+        cmdline_addr,
+        cmdline_len: cmdline.len(),
+        dtb_addr: DTB_ADDR.load(Ordering::Relaxed),
+        boot_hart: BOOT_HART.load(Ordering::Relaxed),
     };
     
-    console_println!("[i] Bootloader info created:");
+    info_println!("Bootloader info created:");
     console_println!("    Magic: 0x{:x}", bootloader_info.magic);
     console_println!("    Memory: 0x{:x} - 0x{:x} ({} MB)", 
                      bootloader_info.memory_base,
@@ -174,22 +294,23 @@ fn load_and_start_kernel() -> ! {
                      bootloader_info.available_ram_start,
                      bootloader_info.available_ram_start + bootloader_info.available_ram_size,
                      bootloader_info.available_ram_size / (1024 * 1024));
+    console_println!("    Command line: {}", Redacted(&config.cmdline));
+    console_println!("    Device tree: 0x{:x}", bootloader_info.dtb_addr);
     
-    // Load kernel from initrd (QEMU loads it to a known location)
-    let kernel_base = locate_kernel_from_initrd();
+    let kernel_base = bootloader_info.kernel_base;
     
     // Use the known kernel entry point (since ELF header reading might be corrupted)
     let kernel_entry_point = 0x80400000_usize;
     
-    console_println!("[i] Kernel base: 0x{:x}", kernel_base);
-    console_println!("[i] Kernel entry point: 0x{:x}", kernel_entry_point);
-    console_println!("[i] Jumping to kernel...");
+    info_println!("Kernel base: 0x{:x}", kernel_base);
+    info_println!("Kernel entry point: 0x{:x}", kernel_entry_point);
+    info_println!("Jumping to kernel...");
     console_println!();
     
     // Jump to kernel with bootloader info
     unsafe {
-        let kernel_main: extern "C" fn(usize) -> ! = core::mem::transmute(kernel_entry_point);
-        kernel_main(&bootloader_info as *const _ as usize);
+        let kernel_main: extern "C" fn(usize, usize) -> ! = core::mem::transmute(kernel_entry_point);
+        kernel_main(&bootloader_info as *const _ as usize, bootloader_info.dtb_addr);
     }
 }
 
@@ -199,11 +320,11 @@ fn search_kernel_elf(memory_regions: &[(usize, usize)]) -> Option<usize> {
     // ELF magic: 0x7f, 'E', 'L', 'F'
     let elf_magic = [0x7f, b'E', b'L', b'F'];
     
-    console_println!("[i] Searching for ELF magic pattern in memory regions...");
+    info_println!("Searching for ELF magic pattern in memory regions...");
     
     for (i, &(start, size)) in memory_regions.iter().enumerate() {
         let end = start + size;
-        console_println!("[i] Searching region {}: 0x{:x} - 0x{:x} ({} MB)", 
+        info_println!("Searching region {}: 0x{:x} - 0x{:x} ({} MB)", 
                          i, start, end, size / (1024 * 1024));
         
         // Try different alignments: 1 byte, 4 bytes, 64 bytes, 4KB
@@ -211,7 +332,7 @@ fn search_kernel_elf(memory_regions: &[(usize, usize)]) -> Option<usize> {
         
         for &alignment in &alignments {
             if let Some(addr) = unsafe { search_memory_pattern(start, end, &elf_magic, alignment) } {
-                console_println!("[o] Found ELF magic at 0x{:x} (alignment {})", addr, alignment);
+                ok_println!("Found ELF magic at 0x{:x} (alignment {})", addr, alignment);
                 
                 // Verify it's a valid 64-bit RISC-V ELF
                 unsafe {
@@ -219,22 +340,22 @@ fn search_kernel_elf(memory_regions: &[(usize, usize)]) -> Option<usize> {
                     let ei_data = core::ptr::read_volatile((addr + 5) as *const u8);
                     let e_machine = core::ptr::read_volatile((addr + 18) as *const u16);
                     
-                    console_println!("[i] ELF validation: class={}, data={}, machine=0x{:x}", 
+                    info_println!("ELF validation: class={}, data={}, machine=0x{:x}", 
                                      ei_class, ei_data, e_machine);
                     
                     // Check for 64-bit (class=2), little-endian (data=1), RISC-V (machine=0xf3)
                     if ei_class == 2 && ei_data == 1 && e_machine == 0xf3 {
-                        console_println!("[o] Valid 64-bit RISC-V ELF found!");
+                        ok_println!("Valid 64-bit RISC-V ELF found!");
                         return Some(addr);
                     } else {
-                        console_println!("[!] ELF validation failed, continuing search...");
+                        warn_println!("ELF validation failed, continuing search...");
                     }
                 }
             }
         }
     }
     
-    console_println!("[x] No valid ELF binary found in any memory region");
+    err_println!("No valid ELF binary found in any memory region");
     None
 }
 
@@ -247,7 +368,7 @@ fn load_elf_segments(elf_addr: usize) -> bool {
         
         // Validate ELF header
         if !ElfUtils::validate_elf_header(elf_data) {
-            console_println!("[x] Invalid ELF header");
+            err_println!("Invalid ELF header");
             return false;
         }
         
@@ -255,12 +376,12 @@ fn load_elf_segments(elf_addr: usize) -> bool {
         let header = match ElfUtils::get_header(elf_data) {
             Some(h) => h,
             None => {
-                console_println!("[x] Failed to get ELF header");
+                err_println!("Failed to get ELF header");
                 return false;
             }
         };
         
-        console_println!("[i] Loading ELF segments: phoff=0x{:x}, phentsize={}, phnum={}", 
+        info_println!("Loading ELF segments: phoff=0x{:x}, phentsize={}, phnum={}", 
                          header.e_phoff, header.e_phentsize, header.e_phnum);
         
         let loader = BootloaderElfLoader;
@@ -271,25 +392,64 @@ fn load_elf_segments(elf_addr: usize) -> bool {
                 // Only process LOAD segments
                 if ElfUtils::is_loadable_segment(&phdr) {
                     if loader.load_segment(&phdr, elf_data).is_err() {
-                        console_println!("[x] Failed to load segment {}", i);
+                        err_println!("Failed to load segment {}", i);
                         return false;
                     }
                 }
             }
         }
         
-        console_println!("[o] All ELF segments loaded successfully");
+        ok_println!("All ELF segments loaded successfully");
         true
     }
 }
 
 
+/// End address of the ELF image at `elf_addr`: the furthest of its section
+/// header table and segment contents
+fn elf_image_end(elf_addr: usize) -> usize {
+    let elf_data = unsafe { core::slice::from_raw_parts(elf_addr as *const u8, 64 * 1024 * 1024) };
+    let header = match ElfUtils::get_header(elf_data) {
+        Some(header) => header,
+        None => return elf_addr,
+    };
+
+    let mut end = header.e_shoff as usize + header.e_shnum as usize * header.e_shentsize as usize;
+    for i in 0..header.e_phnum {
+        if let Some(phdr) = ElfUtils::get_program_header(elf_data, header, i as usize) {
+            end = end.max((phdr.p_offset + phdr.p_filesz) as usize);
+        }
+    }
+    elf_addr + end
+}
+
+/// Download the kernel for the configured slot with TFTP and load it
+fn fetch_kernel_over_tftp(config: &BootConfig) -> Result<(), &'static str> {
+    let filename = config.tftp_file(config.slot);
+    let server = config.tftp_server;
+
+    let mut net = net::NetDevice::probe(config.net)?;
+    let mac = net.mac();
+    info_println!("Network boot: {}.{}.{}.{} (MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x})",
+                  config.net.ip[0], config.net.ip[1], config.net.ip[2], config.net.ip[3],
+                  mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]);
+    info_println!("Fetching '{}' from {}.{}.{}.{}",
+                  filename, server[0], server[1], server[2], server[3]);
+
+    let dest = unsafe { core::slice::from_raw_parts_mut(LOAD_AREA_ADDR as *mut u8, LOAD_AREA_SIZE) };
+    let fetched = tftp::fetch(&mut net, server, filename, dest);
+    console_println!();
+    let size = fetched?;
+    ok_println!("Downloaded {} bytes to 0x{:x}", size, LOAD_AREA_ADDR);
+
+    load_kernel_image(LOAD_AREA_ADDR)
+}
+
 /// Locate the kernel binary from initrd using comprehensive memory search
-/// QEMU loads the initrd to a specific location in memory
-fn locate_kernel_from_initrd() -> usize {
-    let kernel_dest = 0x80400000_usize;   // Where kernel should be loaded
-    
-    console_println!("[i] Starting comprehensive kernel search...");
+/// QEMU loads the initrd to a specific location in memory.
+/// Slot B is a second image appended right after slot A's.
+fn locate_kernel_from_initrd(slot: Slot) -> Result<(), &'static str> {
+    info_println!("Starting comprehensive kernel search...");
     
     // Define memory regions to search
     let memory_region = common::memory::hardware::detect_main_ram()
@@ -309,37 +469,33 @@ fn locate_kernel_from_initrd() -> usize {
     ];
     
     // Use the comprehensive search API
-    if let Some(kernel_addr) = search_kernel_elf(&search_regions) {
-        console_println!("[o] Kernel ELF found at 0x{:x}!", kernel_addr);
+    let mut found = search_kernel_elf(&search_regions);
+    if slot == Slot::B {
+        found = found.and_then(|slot_a| {
+            let start = elf_image_end(slot_a);
+            let ram_end = memory_region.start + memory_region.size;
+            info_println!("Slot A image ends at 0x{:x}, looking for slot B", start);
+            search_kernel_elf(&[(start, SLOT_B_SEARCH_SIZE.min(ram_end.saturating_sub(start)))])
+        });
+        if found.is_none() {
+            return Err("No slot B kernel image in the initrd");
+        }
+    }
+    
+    if let Some(kernel_addr) = found {
+        ok_println!("Kernel ELF found at 0x{:x}!", kernel_addr);
         
         // Load ELF segments properly instead of raw copy
         if load_elf_segments(kernel_addr) {
-            console_println!("[o] Kernel ELF loaded successfully from comprehensive search");
-            return kernel_dest;
+            ok_println!("Kernel ELF loaded successfully from comprehensive search");
+            return Ok(());
         } else {
-            console_println!("[x] Failed to load ELF segments");
+            return Err("Failed to load ELF segments");
         }
     }
     
-    // Final failure - halt the system properly
-    console_println!("[x] CRITICAL: Cannot find kernel ELF binary anywhere in memory!");
-    console_println!("[!] Searched entire RAM space comprehensively");
-    console_println!("[!] This indicates QEMU initrd loading is fundamentally broken");
-    console_println!("[!] System will halt to prevent infinite restart loop");
-    
-    // Force halt - do not try to jump to invalid kernel location
-    console_println!("[!] === BOOTLOADER HALTED ===");
-    
-    // Disable interrupts and halt
-    unsafe {
-        core::arch::asm!(
-            "csrci sstatus, 2",  // Disable interrupts
-            "1:",
-            "wfi",               // Wait for interrupt (but interrupts are disabled)
-            "j 1b",              // Loop forever
-            options(noreturn)
-        );
-    }
+    warn_println!("Searched the usual initrd locations without finding a kernel");
+    Err("Cannot find kernel ELF binary in memory")
 }
 
 
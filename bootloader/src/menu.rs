@@ -0,0 +1,93 @@
+//! Boot menu on the serial console
+//!
+//! If enabled in boot.cfg, a key press within the timeout opens a menu to
+//! pick the kernel slot and boot source, edit the kernel command line for
+//! this boot, or open the bootloader shell. Otherwise the configured
+//! defaults boot. The menu is also reachable from the recovery shell when
+//! the kernel fails to load.
+
+use elinos_common::{console_print, console_println};
+use crate::config::{BootConfig, BootSource};
+use crate::shell::{self, ShellExit};
+use elinos_common::timer;
+use elinos_common::cmdline::Redacted;
+use crate::{BootTarget, UART};
+
+/// Offer the menu and let the user adjust `config` until they choose to boot
+pub fn run(config: &mut BootConfig) -> BootTarget {
+    if !config.menu || !wait_for_key(config.timeout_secs) {
+        return BootTarget::Configured;
+    }
+    open(config)
+}
+
+/// Show the menu without a countdown until the user chooses what to boot
+pub fn open(config: &mut BootConfig) -> BootTarget {
+    loop {
+        show_menu(config);
+        console_print!("Choice: ");
+        let key = UART.lock().getc();
+        console_println!();
+
+        match key {
+            b'1' => config.slot = config.slot.other(),
+            b'2' => {
+                config.source = match config.source {
+                    BootSource::Local => BootSource::Tftp,
+                    BootSource::Tftp => BootSource::Local,
+                }
+            }
+            b'3' => {
+                console_print!("cmdline: ");
+                shell::read_line(&mut config.cmdline);
+            }
+            b'4' => {
+                if let ShellExit::Boot(target) = shell::run(config) {
+                    return target;
+                }
+            }
+            b'r' | b'R' => elinos_common::sbi::system_reset(),
+            b'\r' | b'\n' | b'b' | b'B' => return BootTarget::Configured,
+            _ => {}
+        }
+    }
+}
+
+/// Count down `timeout_secs`, returning true if a key was pressed
+fn wait_for_key(timeout_secs: u64) -> bool {
+    let start = timer::ticks();
+    let mut shown = u64::MAX;
+
+    while timer::elapsed_ms(start) < timeout_secs * 1000 {
+        let remaining = timeout_secs - timer::elapsed_ms(start) / 1000;
+        if remaining != shown {
+            console_print!("\rPress any key for the boot menu... {} ", remaining);
+            shown = remaining;
+        }
+        if UART.lock().getchar().is_some() {
+            console_println!();
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+
+    console_println!();
+    false
+}
+
+fn show_menu(config: &BootConfig) {
+    console_println!();
+    console_println!("=====================================");
+    console_println!("          elinOS Boot Menu           ");
+    console_println!("=====================================");
+    console_println!("  1. Kernel slot:   {}", config.slot.name());
+    console_println!("  2. Boot source:   {}", config.source.name());
+    if config.cmdline.is_empty() {
+        console_println!("  3. Command line:  (none)");
+    } else {
+        console_println!("  3. Command line:  {}", Redacted(&config.cmdline));
+    }
+    console_println!("  4. Bootloader shell");
+    console_println!();
+    console_println!("  Enter/b: boot    r: reboot");
+}
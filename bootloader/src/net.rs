@@ -0,0 +1,294 @@
+//! VirtIO network driver and a minimal Ethernet/ARP/IPv4/UDP path for
+//! network boot. Only what a TFTP client needs: static addressing, ARP for
+//! the next hop, and unfragmented UDP without checksums.
+
+use elinos_common::timer;
+use elinos_common::virtio::mmio::{VIRTIO_ID_NET, VIRTIO_F_VERSION_1};
+use elinos_common::virtio::transport::{Buffer, Device, Queue, QueueMemory, QUEUE_SIZE};
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// Header length without merged buffers (legacy), and with VERSION_1
+const HEADER_LEN_LEGACY: usize = 10;
+const HEADER_LEN_MODERN: usize = 12;
+
+const BUFFER_SIZE: usize = 2048;
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const IP_PROTO_UDP: u8 = 17;
+const ETH_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const ARP_PACKET_LEN: usize = 28;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// Largest UDP payload in one frame
+pub const MAX_UDP_PAYLOAD: usize = MAX_FRAME_SIZE - ETH_HEADER_LEN - IPV4_HEADER_LEN - UDP_HEADER_LEN;
+
+/// Milliseconds to wait for an ARP reply
+const ARP_TIMEOUT_MS: u64 = 1000;
+const ARP_RETRIES: usize = 3;
+
+#[repr(C, align(16))]
+struct RxBuffers([[u8; BUFFER_SIZE]; QUEUE_SIZE as usize]);
+
+static mut RX_QUEUE_MEMORY: QueueMemory = QueueMemory::new();
+static mut TX_QUEUE_MEMORY: QueueMemory = QueueMemory::new();
+static mut RX_BUFFERS: RxBuffers = RxBuffers([[0; BUFFER_SIZE]; QUEUE_SIZE as usize]);
+static mut TX_BUFFER: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
+
+/// Static IPv4 settings for network boot
+#[derive(Clone, Copy)]
+pub struct NetConfig {
+    pub ip: [u8; 4],
+    pub netmask: [u8; 4],
+    pub gateway: [u8; 4],
+}
+
+/// A received UDP datagram (payload is in the caller's buffer)
+pub struct Datagram {
+    pub src_ip: [u8; 4],
+    pub src_port: u16,
+    pub len: usize,
+}
+
+pub struct NetDevice {
+    device: Device,
+    rx: Queue,
+    tx: Queue,
+    header_len: usize,
+    mac: [u8; 6],
+    config: NetConfig,
+}
+
+impl NetDevice {
+    /// Find and initialize the VirtIO network device. The device takes the
+    /// static queue memory, so this must only be called once.
+    pub fn probe(config: NetConfig) -> Result<NetDevice, &'static str> {
+        let device = Device::find(VIRTIO_ID_NET).ok_or("No VirtIO network device")?;
+        let features = device.negotiate(VIRTIO_NET_F_MAC).ok_or("Network device rejected features")?;
+
+        let (rx, tx) = unsafe {
+            (device.setup_queue(RX_QUEUE, &mut *core::ptr::addr_of_mut!(RX_QUEUE_MEMORY)),
+             device.setup_queue(TX_QUEUE, &mut *core::ptr::addr_of_mut!(TX_QUEUE_MEMORY)))
+        };
+        let (rx, tx) = match (rx, tx) {
+            (Some(rx), Some(tx)) => (rx, tx),
+            _ => return Err("Network queue setup failed"),
+        };
+        device.driver_ok();
+
+        let mut mac = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01]; // locally administered fallback
+        if features & VIRTIO_NET_F_MAC != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = device.config_u8(i);
+            }
+        }
+
+        let header_len = if features & VIRTIO_F_VERSION_1 != 0 { HEADER_LEN_MODERN } else { HEADER_LEN_LEGACY };
+        let mut net = NetDevice { device, rx, tx, header_len, mac, config };
+        for slot in 0..QUEUE_SIZE {
+            net.post_rx(slot);
+        }
+        net.device.notify(&net.rx);
+        Ok(net)
+    }
+
+    pub fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /// Give receive buffer `slot` (descriptor `slot`) to the device
+    fn post_rx(&mut self, slot: u16) {
+        let addr = unsafe { (*core::ptr::addr_of!(RX_BUFFERS)).0[slot as usize].as_ptr() as usize };
+        self.rx.submit(slot, &[Buffer { addr, len: BUFFER_SIZE, writable: true }]);
+    }
+
+    /// Send an Ethernet frame and wait for the device to take it
+    fn send_frame(&mut self, dst_mac: [u8; 6], ethertype: u16, payload: &[u8]) -> Result<(), &'static str> {
+        let frame_len = ETH_HEADER_LEN + payload.len();
+        if frame_len > MAX_FRAME_SIZE {
+            return Err("Frame too large");
+        }
+
+        let buffer = unsafe { &mut *core::ptr::addr_of_mut!(TX_BUFFER) };
+        let frame = &mut buffer[..self.header_len + frame_len];
+        frame[..self.header_len].fill(0);
+        let eth = &mut frame[self.header_len..];
+        eth[0..6].copy_from_slice(&dst_mac);
+        eth[6..12].copy_from_slice(&self.mac);
+        eth[12..14].copy_from_slice(&ethertype.to_be_bytes());
+        eth[ETH_HEADER_LEN..].copy_from_slice(payload);
+
+        let len = frame.len();
+        self.tx.submit(0, &[Buffer { addr: buffer.as_ptr() as usize, len, writable: false }]);
+        self.device.notify(&self.tx);
+
+        let start = timer::ticks();
+        while timer::elapsed_ms(start) < 100 {
+            if self.tx.pop_used().is_some() {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err("Network transmit timed out")
+    }
+
+    /// Copy the next received frame into `frame`, answering ARP requests for
+    /// our address on the way. Returns the frame length.
+    fn receive_frame(&mut self, frame: &mut [u8; MAX_FRAME_SIZE]) -> Option<usize> {
+        let (slot, written) = self.rx.pop_used()?;
+        let slot = slot % QUEUE_SIZE;
+        let len = (written as usize).saturating_sub(self.header_len).min(MAX_FRAME_SIZE);
+        unsafe {
+            let buffer = &(*core::ptr::addr_of!(RX_BUFFERS)).0[slot as usize];
+            frame[..len].copy_from_slice(&buffer[self.header_len..self.header_len + len]);
+        }
+        self.post_rx(slot);
+        self.device.notify(&self.rx);
+
+        if len >= ETH_HEADER_LEN + ARP_PACKET_LEN && u16::from_be_bytes([frame[12], frame[13]]) == ETHERTYPE_ARP {
+            self.answer_arp(&frame[ETH_HEADER_LEN..len]);
+        }
+        Some(len)
+    }
+
+    fn send_arp(&mut self, op: u16, target_mac: [u8; 6], target_ip: [u8; 4], dst_mac: [u8; 6]) -> Result<(), &'static str> {
+        let mut packet = [0u8; ARP_PACKET_LEN];
+        packet[0..2].copy_from_slice(&1u16.to_be_bytes()); // Ethernet
+        packet[2..4].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        packet[4] = 6;
+        packet[5] = 4;
+        packet[6..8].copy_from_slice(&op.to_be_bytes());
+        packet[8..14].copy_from_slice(&self.mac);
+        packet[14..18].copy_from_slice(&self.config.ip);
+        packet[18..24].copy_from_slice(&target_mac);
+        packet[24..28].copy_from_slice(&target_ip);
+        self.send_frame(dst_mac, ETHERTYPE_ARP, &packet)
+    }
+
+    fn answer_arp(&mut self, packet: &[u8]) {
+        // op == request && target IP == ours
+        if packet[6..8] == [0, 1] && packet[24..28] == self.config.ip {
+            let mut sender_mac = [0u8; 6];
+            sender_mac.copy_from_slice(&packet[8..14]);
+            let mut sender_ip = [0u8; 4];
+            sender_ip.copy_from_slice(&packet[14..18]);
+            let _ = self.send_arp(2, sender_mac, sender_ip, sender_mac);
+        }
+    }
+
+    /// Resolve the MAC address of the next hop towards `ip`
+    pub fn resolve(&mut self, ip: [u8; 4]) -> Result<[u8; 6], &'static str> {
+        let local = (0..4).all(|i| ip[i] & self.config.netmask[i] == self.config.ip[i] & self.config.netmask[i]);
+        let next_hop = if local { ip } else { self.config.gateway };
+
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        for _ in 0..ARP_RETRIES {
+            self.send_arp(1, [0; 6], next_hop, BROADCAST_MAC)?;
+            let start = timer::ticks();
+            while timer::elapsed_ms(start) < ARP_TIMEOUT_MS {
+                if let Some(len) = self.receive_frame(&mut frame) {
+                    let arp = &frame[ETH_HEADER_LEN..len];
+                    if u16::from_be_bytes([frame[12], frame[13]]) == ETHERTYPE_ARP
+                        && arp.len() >= ARP_PACKET_LEN && arp[6..8] == [0, 2] && arp[14..18] == next_hop
+                    {
+                        let mut mac = [0u8; 6];
+                        mac.copy_from_slice(&arp[8..14]);
+                        return Ok(mac);
+                    }
+                }
+            }
+        }
+        Err("Host unreachable (no ARP reply)")
+    }
+
+    /// Send a UDP datagram to a host whose next-hop MAC is `dst_mac`
+    pub fn send_udp(&mut self, dst_mac: [u8; 6], dst_ip: [u8; 4], src_port: u16, dst_port: u16, data: &[u8]) -> Result<(), &'static str> {
+        if data.len() > MAX_UDP_PAYLOAD {
+            return Err("Datagram too large");
+        }
+
+        let mut packet = [0u8; MAX_FRAME_SIZE - ETH_HEADER_LEN];
+        let total_len = IPV4_HEADER_LEN + UDP_HEADER_LEN + data.len();
+        packet[0] = 0x45; // version 4, 20-byte header
+        packet[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+        packet[6] = 0x40; // don't fragment
+        packet[8] = 64;   // TTL
+        packet[9] = IP_PROTO_UDP;
+        packet[12..16].copy_from_slice(&self.config.ip);
+        packet[16..20].copy_from_slice(&dst_ip);
+        let sum = checksum(&packet[..IPV4_HEADER_LEN]);
+        packet[10..12].copy_from_slice(&sum.to_be_bytes());
+
+        // UDP checksum 0: none (optional over IPv4)
+        let udp = &mut packet[IPV4_HEADER_LEN..total_len];
+        udp[0..2].copy_from_slice(&src_port.to_be_bytes());
+        udp[2..4].copy_from_slice(&dst_port.to_be_bytes());
+        udp[4..6].copy_from_slice(&((UDP_HEADER_LEN + data.len()) as u16).to_be_bytes());
+        udp[UDP_HEADER_LEN..].copy_from_slice(data);
+
+        self.send_frame(dst_mac, ETHERTYPE_IPV4, &packet[..total_len])
+    }
+
+    /// Wait up to `timeout_ms` for a UDP datagram to `port`, copying its
+    /// payload into `buf`
+    pub fn receive_udp(&mut self, port: u16, buf: &mut [u8], timeout_ms: u64) -> Option<Datagram> {
+        let mut frame = [0u8; MAX_FRAME_SIZE];
+        let start = timer::ticks();
+        while timer::elapsed_ms(start) < timeout_ms {
+            let len = match self.receive_frame(&mut frame) {
+                Some(len) => len,
+                None => continue,
+            };
+            if len < ETH_HEADER_LEN + IPV4_HEADER_LEN + UDP_HEADER_LEN
+                || u16::from_be_bytes([frame[12], frame[13]]) != ETHERTYPE_IPV4
+            {
+                continue;
+            }
+
+            let ip = &frame[ETH_HEADER_LEN..len];
+            let header_len = ((ip[0] & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+            if ip[0] >> 4 != 4 || ip[9] != IP_PROTO_UDP || ip[16..20] != self.config.ip
+                || header_len < IPV4_HEADER_LEN || total_len > ip.len() || total_len < header_len + UDP_HEADER_LEN
+            {
+                continue;
+            }
+
+            let udp = &ip[header_len..total_len];
+            if u16::from_be_bytes([udp[2], udp[3]]) != port {
+                continue;
+            }
+            let udp_len = (u16::from_be_bytes([udp[4], udp[5]]) as usize).clamp(UDP_HEADER_LEN, udp.len());
+            let payload = &udp[UDP_HEADER_LEN..udp_len];
+            let copied = payload.len().min(buf.len());
+            buf[..copied].copy_from_slice(&payload[..copied]);
+
+            return Some(Datagram {
+                src_ip: [ip[12], ip[13], ip[14], ip[15]],
+                src_port: u16::from_be_bytes([udp[0], udp[1]]),
+                len: copied,
+            });
+        }
+        None
+    }
+}
+
+/// Internet checksum
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for pair in data.chunks(2) {
+        let word = if pair.len() == 2 { u16::from_be_bytes([pair[0], pair[1]]) } else { (pair[0] as u16) << 8 };
+        sum += word as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
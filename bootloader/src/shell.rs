@@ -0,0 +1,247 @@
+//! Minimal bootloader shell for inspecting the disk and memory before boot
+//!
+//! It doubles as the recovery shell when the kernel cannot be loaded: files
+//! on the ext2 disk can be listed and read, and a kernel can be loaded from
+//! a path or booted from an image already in memory.
+
+use heapless::String;
+use elinos_common::{console_print, console_println, err_println, info_println, ok_println};
+use elinos_common::cmdline::Redacted;
+use crate::block::{self, SECTOR_SIZE};
+use crate::config::BootConfig;
+use crate::ext2;
+use crate::{BootTarget, LOAD_AREA_ADDR, LOAD_AREA_SIZE, UART};
+
+const MAX_LINE_LEN: usize = 256;
+
+/// Largest memory dump `mem` prints
+const MAX_DUMP_LEN: usize = 4096;
+
+/// What the user asked for when leaving the shell
+pub enum ShellExit {
+    /// Go back to the boot menu
+    Menu,
+    /// Boot the given kernel
+    Boot(BootTarget),
+}
+
+/// Read a line from the serial console into `line`, keeping its current
+/// contents as editable initial text
+pub fn read_line<const N: usize>(line: &mut String<N>) {
+    console_print!("{}", line);
+    loop {
+        let ch = UART.lock().getc();
+        match ch {
+            b'\r' | b'\n' => {
+                console_println!();
+                return;
+            }
+            0x08 | 0x7f => {
+                if line.pop().is_some() {
+                    console_print!("\x08 \x08");
+                }
+            }
+            0x20..=0x7e => {
+                if line.push(ch as char).is_ok() {
+                    console_print!("{}", ch as char);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Run the shell until the user leaves it
+pub fn run(config: &BootConfig) -> ShellExit {
+    console_println!();
+    info_println!("Bootloader shell. Type 'help' for commands.");
+
+    // Kernel staged by `loadkernel`, booted by `boot`
+    let mut staged = None;
+
+    loop {
+        console_print!("boot> ");
+        let mut line = String::<MAX_LINE_LEN>::new();
+        read_line(&mut line);
+
+        let mut parts = line.split_whitespace();
+        let command = match parts.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let arg = parts.next();
+
+        let result = match command {
+            "help" => {
+                cmd_help();
+                Ok(())
+            }
+            "info" => cmd_info(config),
+            "sector" => cmd_sector(arg),
+            "mem" => cmd_mem(arg, parts.next()),
+            "ls" => cmd_ls(arg),
+            "cat" => cmd_cat(arg),
+            "loadkernel" => cmd_loadkernel(arg).map(|addr| staged = Some(addr)),
+            "bootaddr" => match parse_hex(arg).ok_or("Usage: bootaddr <hex addr>") {
+                Ok(addr) => return ShellExit::Boot(BootTarget::Image(addr)),
+                Err(e) => Err(e),
+            },
+            "exit" | "menu" => return ShellExit::Menu,
+            "boot" => return ShellExit::Boot(match staged {
+                Some(addr) => BootTarget::Image(addr),
+                None => BootTarget::Configured,
+            }),
+            "reboot" => elinos_common::sbi::system_reset(),
+            _ => Err("Unknown command (try 'help')"),
+        };
+
+        if let Err(e) = result {
+            err_println!("{}", e);
+        }
+    }
+}
+
+fn cmd_help() {
+    console_println!("  info              Show devices and boot settings");
+    console_println!("  sector <n>        Hex dump disk sector n");
+    console_println!("  mem <addr> [len]  Hex dump memory (hex address)");
+    console_println!("  ls [path]         List an ext2 directory");
+    console_println!("  cat <path>        Print an ext2 file");
+    console_println!("  loadkernel <path> Load a kernel ELF from ext2 for 'boot'");
+    console_println!("  bootaddr <addr>   Boot the kernel ELF at a hex address");
+    console_println!("  exit              Back to the boot menu");
+    console_println!("  boot              Boot the loaded kernel, or the current settings");
+    console_println!("  reboot            Reset the machine");
+}
+
+fn cmd_info(config: &BootConfig) -> Result<(), &'static str> {
+    console_println!("  Slot: {}  Source: {}", config.slot.name(), config.source.name());
+    console_println!("  Command line: {}", Redacted(&config.cmdline));
+    match block::with_device(|dev| Ok((dev.mmio_base(), dev.capacity()))) {
+        Ok((base, sectors)) => console_println!("  Disk: VirtIO block at 0x{:x}, {} sectors ({} MB)",
+                                                base, sectors, sectors * SECTOR_SIZE as u64 / (1024 * 1024)),
+        Err(e) => console_println!("  Disk: {}", e),
+    }
+    Ok(())
+}
+
+fn cmd_sector(arg: Option<&str>) -> Result<(), &'static str> {
+    let sector = arg.and_then(|s| parse_number(s)).ok_or("Usage: sector <n>")?;
+    let mut buf = [0u8; SECTOR_SIZE];
+    block::with_device(|dev| dev.read_sector(sector as u64, &mut buf))?;
+    hexdump(sector * SECTOR_SIZE, &buf);
+    Ok(())
+}
+
+fn cmd_mem(addr: Option<&str>, len: Option<&str>) -> Result<(), &'static str> {
+    let addr = parse_hex(addr).ok_or("Usage: mem <hex addr> [len]")?;
+    let len = match len {
+        Some(len) => parse_number(len).ok_or("Bad length")?,
+        None => 256,
+    }.min(MAX_DUMP_LEN);
+
+    let data = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+    hexdump(addr, data);
+    Ok(())
+}
+
+fn cmd_ls(path: Option<&str>) -> Result<(), &'static str> {
+    let fs = ext2::mount()?;
+    let dir = fs.lookup(path.unwrap_or("/"))?;
+    if !dir.is_dir() {
+        console_println!("  {:>10}  {}", dir.size, path.unwrap_or("/"));
+        return Ok(());
+    }
+
+    let mut failed = None;
+    fs.for_each_entry(&dir, |name, number, _| {
+        match fs.read_inode(number) {
+            Ok(inode) if inode.is_dir() => console_println!("  {:>10}  {}/", "<dir>", name),
+            Ok(inode) => console_println!("  {:>10}  {}", inode.size, name),
+            Err(e) => failed = Some(e),
+        }
+    })?;
+    failed.map_or(Ok(()), Err)
+}
+
+fn cmd_cat(path: Option<&str>) -> Result<(), &'static str> {
+    let fs = ext2::mount()?;
+    let inode = fs.lookup(path.ok_or("Usage: cat <path>")?)?;
+    if !inode.is_file() {
+        return Err("Not a regular file");
+    }
+
+    let mut last = b'\n';
+    fs.read_file(&inode, |chunk| {
+        for &byte in chunk {
+            match byte {
+                b'\n' => console_println!(),
+                b'\t' | 0x20..=0x7e => console_print!("{}", byte as char),
+                _ => console_print!("."),
+            }
+        }
+        last = chunk.last().copied().unwrap_or(last);
+        Ok(())
+    })?;
+    if last != b'\n' {
+        console_println!();
+    }
+    Ok(())
+}
+
+/// Copy a kernel ELF from the disk into the load area and check its header
+fn cmd_loadkernel(path: Option<&str>) -> Result<usize, &'static str> {
+    let fs = ext2::mount()?;
+    let inode = fs.lookup(path.ok_or("Usage: loadkernel <path>")?)?;
+    if !inode.is_file() {
+        return Err("Not a regular file");
+    }
+    if inode.size > LOAD_AREA_SIZE as u64 {
+        return Err("File is larger than the load area");
+    }
+
+    let dest = unsafe { core::slice::from_raw_parts_mut(LOAD_AREA_ADDR as *mut u8, LOAD_AREA_SIZE) };
+    let mut offset = 0;
+    fs.read_file(&inode, |chunk| {
+        dest[offset..offset + chunk.len()].copy_from_slice(chunk);
+        offset += chunk.len();
+        Ok(())
+    })?;
+
+    if !crate::is_kernel_elf(LOAD_AREA_ADDR) {
+        return Err("Not a 64-bit RISC-V ELF image");
+    }
+    ok_println!("Loaded {} bytes to 0x{:x}; 'boot' starts it", offset, LOAD_AREA_ADDR);
+    Ok(LOAD_AREA_ADDR)
+}
+
+/// Parse a hex address, with or without 0x
+fn parse_hex(s: Option<&str>) -> Option<usize> {
+    s.and_then(|s| usize::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+}
+
+/// Parse a decimal or 0x-prefixed hex number
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Print `data` 16 bytes per line, labelled from `offset`
+fn hexdump(offset: usize, data: &[u8]) {
+    for (i, row) in data.chunks(16).enumerate() {
+        console_print!("{:08x}  ", offset + i * 16);
+        for byte in row {
+            console_print!("{:02x} ", byte);
+        }
+        for _ in row.len()..16 {
+            console_print!("   ");
+        }
+        console_print!(" ");
+        for &byte in row {
+            console_print!("{}", if (0x20..0x7f).contains(&byte) { byte as char } else { '.' });
+        }
+        console_println!();
+    }
+}
@@ -0,0 +1,109 @@
+//! TFTP client (RFC 1350, octet mode) for network boot
+
+use elinos_common::console_print;
+use crate::net::{NetDevice, MAX_UDP_PAYLOAD};
+use elinos_common::timer;
+
+const TFTP_PORT: u16 = 69;
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+
+const BLOCK_SIZE: usize = 512;
+const TIMEOUT_MS: u64 = 1000;
+const MAX_RETRIES: usize = 5;
+
+/// Print a progress dot every this many bytes
+const PROGRESS_INTERVAL: usize = 64 * 1024;
+
+/// Download `filename` from `server` into `dest`. Returns the file size.
+pub fn fetch(net: &mut NetDevice, server: [u8; 4], filename: &str, dest: &mut [u8]) -> Result<usize, &'static str> {
+    let server_mac = net.resolve(server)?;
+    // Pick a client port from the clock so a restarted transfer is not
+    // confused with a previous one
+    let local_port = 49152 + (timer::ticks() % 16384) as u16;
+
+    let mut request = [0u8; 2 + 128 + 1 + 6];
+    if filename.is_empty() || filename.len() > 128 {
+        return Err("Bad TFTP file name");
+    }
+    request[0..2].copy_from_slice(&OP_RRQ.to_be_bytes());
+    request[2..2 + filename.len()].copy_from_slice(filename.as_bytes());
+    let mode_start = 2 + filename.len() + 1;
+    request[mode_start..mode_start + 5].copy_from_slice(b"octet");
+    let request_len = mode_start + 6;
+
+    // Until the first DATA arrives, retries resend the request; after that,
+    // the last ACK
+    let mut server_port = None;
+    let mut expected_block: u16 = 1;
+    let mut received = 0;
+    let mut packet = [0u8; MAX_UDP_PAYLOAD];
+
+    net.send_udp(server_mac, server, local_port, TFTP_PORT, &request[..request_len])?;
+
+    loop {
+        let mut retries = 0;
+        let datagram = loop {
+            if let Some(datagram) = net.receive_udp(local_port, &mut packet, TIMEOUT_MS) {
+                if datagram.src_ip == server && server_port.map_or(true, |port| port == datagram.src_port) {
+                    break datagram;
+                }
+                continue;
+            }
+
+            retries += 1;
+            if retries > MAX_RETRIES {
+                return Err("TFTP transfer timed out");
+            }
+            match server_port {
+                None => net.send_udp(server_mac, server, local_port, TFTP_PORT, &request[..request_len])?,
+                Some(port) => send_ack(net, server_mac, server, local_port, port, expected_block.wrapping_sub(1))?,
+            }
+        };
+
+        if datagram.len < 4 {
+            continue;
+        }
+        let opcode = u16::from_be_bytes([packet[0], packet[1]]);
+        let block = u16::from_be_bytes([packet[2], packet[3]]);
+
+        match opcode {
+            OP_DATA => {
+                let port = *server_port.get_or_insert(datagram.src_port);
+                if block != expected_block {
+                    // Duplicate of a block we already have: re-acknowledge it
+                    send_ack(net, server_mac, server, local_port, port, block)?;
+                    continue;
+                }
+
+                let data = &packet[4..datagram.len];
+                if received + data.len() > dest.len() {
+                    return Err("File too large for the load area");
+                }
+                dest[received..received + data.len()].copy_from_slice(data);
+                if (received + data.len()) / PROGRESS_INTERVAL != received / PROGRESS_INTERVAL {
+                    console_print!(".");
+                }
+                received += data.len();
+
+                send_ack(net, server_mac, server, local_port, port, block)?;
+                expected_block = expected_block.wrapping_add(1);
+                if data.len() < BLOCK_SIZE {
+                    return Ok(received);
+                }
+            }
+            OP_ERROR => return Err("TFTP server returned an error (file not found?)"),
+            _ => {}
+        }
+    }
+}
+
+fn send_ack(net: &mut NetDevice, mac: [u8; 6], server: [u8; 4], local_port: u16, port: u16, block: u16) -> Result<(), &'static str> {
+    let mut ack = [0u8; 4];
+    ack[0..2].copy_from_slice(&OP_ACK.to_be_bytes());
+    ack[2..4].copy_from_slice(&block.to_be_bytes());
+    net.send_udp(mac, server, local_port, port, &ack)
+}
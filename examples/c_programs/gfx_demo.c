@@ -0,0 +1,111 @@
+#include <stddef.h>
+
+// System call numbers (must match kernel definitions)
+#define SYS_WRITE       64
+#define SYS_GFX_INFO    920
+#define SYS_GFX_FILL    921
+#define SYS_GFX_LINE    922
+#define SYS_GFX_CIRCLE  923
+#define SYS_GFX_BLIT    924
+#define SYS_GFX_IMAGE   925
+#define SYS_GFX_PRESENT 926
+
+#define GFX_FILLED 1
+#define GFX_BLEND  1
+
+#define SPRITE_SIZE 32
+
+struct gfx_info {
+    unsigned int width;
+    unsigned int height;
+    unsigned int pitch;
+    unsigned int bpp;
+};
+
+struct gfx_area {
+    int x;
+    int y;
+    unsigned int width;
+    unsigned int height;
+};
+
+// Simple syscall wrapper
+static inline long syscall(long num, long arg1, long arg2, long arg3, long arg4, long arg5, long arg6) {
+    register long a7 asm("a7") = num;
+    register long a0 asm("a0") = arg1;
+    register long a1 asm("a1") = arg2;
+    register long a2 asm("a2") = arg3;
+    register long a3 asm("a3") = arg4;
+    register long a4 asm("a4") = arg5;
+    register long a5 asm("a5") = arg6;
+
+    asm volatile ("ecall" : "+r"(a0) : "r"(a7), "r"(a1), "r"(a2), "r"(a3), "r"(a4), "r"(a5) : "memory");
+    return a0;
+}
+
+void print(const char* str) {
+    size_t len = 0;
+    while (str[len]) len++;
+    syscall(SYS_WRITE, 1, (long)str, len, 0, 0, 0);
+}
+
+// A translucent disc, its alpha falling off towards the edge
+static unsigned int sprite[SPRITE_SIZE * SPRITE_SIZE];
+
+void make_sprite(void) {
+    int center = SPRITE_SIZE / 2;
+    for (int y = 0; y < SPRITE_SIZE; y++) {
+        for (int x = 0; x < SPRITE_SIZE; x++) {
+            int dx = x - center, dy = y - center;
+            int distance2 = dx * dx + dy * dy;
+            int alpha = 255 - distance2 * 255 / (center * center);
+            if (alpha < 0) alpha = 0;
+            sprite[y * SPRITE_SIZE + x] = ((unsigned int)alpha << 24) | 0x00FFCC00;
+        }
+    }
+}
+
+int main() {
+    print("=== elinOS Graphics Demo ===\n");
+
+    struct gfx_info info;
+    if (syscall(SYS_GFX_INFO, (long)&info, 0, 0, 0, 0, 0) < 0) {
+        print("No framebuffer, nothing to draw\n");
+        print("Graphics demo done\n");
+        return 0;
+    }
+
+    // Checkered backdrop in the right half of the screen
+    static const unsigned char checker[8] = { 0xF0, 0xF0, 0xF0, 0xF0, 0x0F, 0x0F, 0x0F, 0x0F };
+    int left = info.width / 2;
+    struct gfx_area backdrop = { left, 0, info.width - left, info.height };
+    syscall(SYS_GFX_FILL, (long)&backdrop, 0x00202040, 0x00101020, (long)checker, 0, 0);
+
+    // A fan of lines from the corner
+    for (int i = 0; i <= 8; i++) {
+        syscall(SYS_GFX_LINE, left, 0, left + i * 30, 240, 0x0000FF00 + i * 0x00100000, 0);
+    }
+
+    // A ring and a disc
+    syscall(SYS_GFX_CIRCLE, left + 80, 320, 50, 0x00FF4040, 0, 0);
+    syscall(SYS_GFX_CIRCLE, left + 80, 320, 30, 0x004080FF, GFX_FILLED, 0);
+
+    // Blend the sprite over the disc, then copy the lot at twice the size
+    make_sprite();
+    syscall(SYS_GFX_IMAGE, (long)sprite, SPRITE_SIZE, SPRITE_SIZE, left + 64, 304, GFX_BLEND);
+    struct gfx_area src = { left + 30, 270, 100, 100 };
+    struct gfx_area dst = { left + 130, 260, 180, 180 };
+    syscall(SYS_GFX_BLIT, (long)&src, (long)&dst, 0, 0, 0, 0);
+
+    syscall(SYS_GFX_PRESENT, 0, 0, 0, 0, 0, 0);
+    print("Graphics demo done\n");
+    return 0;
+}
+
+// Entry point required by linker - ensure it's at the start of text section
+__attribute__((section(".text.start")))
+int _start() {
+    int result = main();
+    // Return the result instead of infinite loop
+    return result;
+}
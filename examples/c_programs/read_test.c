@@ -0,0 +1,75 @@
+#include <stddef.h>
+
+// System call numbers (must match kernel definitions)
+#define SYS_OPENAT  56
+#define SYS_CLOSE   57
+#define SYS_READ    63
+#define SYS_WRITE   64
+
+#define AT_FDCWD  -100
+#define O_RDONLY  0
+#define O_WRONLY  1
+#define O_CREAT   64
+#define O_TRUNC   512
+
+// Both live in pages the kernel only reads in when they are first touched,
+// and the first touch is the read or write system call itself
+static char buffer[4096] = { 1 };
+static const char message[] = "This line was written from a page not yet read in\n";
+
+// Simple syscall wrapper
+static inline long syscall(long num, long arg1, long arg2, long arg3, long arg4) {
+    register long a7 asm("a7") = num;
+    register long a0 asm("a0") = arg1;
+    register long a1 asm("a1") = arg2;
+    register long a2 asm("a2") = arg3;
+    register long a3 asm("a3") = arg4;
+
+    asm volatile ("ecall" : "+r"(a0) : "r"(a7), "r"(a1), "r"(a2), "r"(a3) : "memory");
+    return a0;
+}
+
+void print(const char* str) {
+    size_t len = 0;
+    while (str[len]) len++;
+    syscall(SYS_WRITE, 1, (long)str, len, 0);
+}
+
+int main() {
+    print("=== elinOS Read Test ===\n");
+
+    int fd = syscall(SYS_OPENAT, AT_FDCWD, (long)"/read_test.out", O_WRONLY | O_CREAT | O_TRUNC, 0);
+    if (fd < 0) {
+        print("ERROR: open for writing failed\n");
+        return 1;
+    }
+    if (syscall(SYS_WRITE, fd, (long)message, sizeof(message) - 1, 0) != sizeof(message) - 1) {
+        print("ERROR: write from static data failed\n");
+        return 1;
+    }
+    syscall(SYS_CLOSE, fd, 0, 0, 0);
+
+    fd = syscall(SYS_OPENAT, AT_FDCWD, (long)"/test.txt", O_RDONLY, 0);
+    if (fd < 0) {
+        print("ERROR: open /test.txt failed\n");
+        return 1;
+    }
+    long n = syscall(SYS_READ, fd, (long)buffer, sizeof(buffer) - 1, 0);
+    syscall(SYS_CLOSE, fd, 0, 0, 0);
+    if (n <= 0) {
+        print("ERROR: read into static buffer failed\n");
+        return 1;
+    }
+    buffer[n] = '\0';
+    print(buffer);
+    print("Read test done\n");
+    return 0;
+}
+
+// Entry point required by linker - ensure it's at the start of text section
+__attribute__((section(".text.start")))
+int _start() {
+    int result = main();
+    // Return the result instead of infinite loop
+    return result;
+}
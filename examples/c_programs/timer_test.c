@@ -0,0 +1,119 @@
+#include <stddef.h>
+
+// System call numbers (must match kernel definitions)
+#define SYS_CLOSE           57
+#define SYS_READ            63
+#define SYS_WRITE           64
+#define SYS_PPOLL           73
+#define SYS_TIMERFD_CREATE  85
+#define SYS_TIMERFD_SETTIME 86
+
+#define CLOCK_MONOTONIC 1
+#define POLLIN          0x001
+
+// Ticks to wait for and the period between them
+#define TICKS      5
+#define PERIOD_NS  250000000
+
+struct timespec {
+    long tv_sec;
+    long tv_nsec;
+};
+
+struct itimerspec {
+    struct timespec it_interval;
+    struct timespec it_value;
+};
+
+struct pollfd {
+    int fd;
+    short events;
+    short revents;
+};
+
+// Simple syscall wrapper
+static inline long syscall(long num, long arg1, long arg2, long arg3, long arg4) {
+    register long a7 asm("a7") = num;
+    register long a0 asm("a0") = arg1;
+    register long a1 asm("a1") = arg2;
+    register long a2 asm("a2") = arg3;
+    register long a3 asm("a3") = arg4;
+
+    asm volatile ("ecall" : "+r"(a0) : "r"(a7), "r"(a1), "r"(a2), "r"(a3) : "memory");
+    return a0;
+}
+
+void print(const char* str) {
+    size_t len = 0;
+    while (str[len]) len++;
+    syscall(SYS_WRITE, 1, (long)str, len, 0);
+}
+
+void print_number(unsigned long num) {
+    char buf[21];
+    int i = 20;
+    buf[i] = '\0';
+    do {
+        buf[--i] = '0' + (num % 10);
+        num /= 10;
+    } while (num > 0);
+    print(&buf[i]);
+}
+
+int main() {
+    print("=== elinOS Timer Test ===\n");
+
+    int fd = syscall(SYS_TIMERFD_CREATE, CLOCK_MONOTONIC, 0, 0, 0);
+    if (fd < 0) {
+        print("ERROR: timerfd_create failed\n");
+        return 1;
+    }
+
+    // First expiry after one period, then every period
+    struct itimerspec setting = {
+        .it_interval = { 0, PERIOD_NS },
+        .it_value = { 0, PERIOD_NS },
+    };
+    if (syscall(SYS_TIMERFD_SETTIME, fd, 0, (long)&setting, 0) < 0) {
+        print("ERROR: timerfd_settime failed\n");
+        return 1;
+    }
+
+    unsigned long total = 0;
+    for (int tick = 1; tick <= TICKS; tick++) {
+        // Sleep in the kernel until the timer is due, at most a second
+        struct pollfd pfd = { fd, POLLIN, 0 };
+        struct timespec timeout = { 1, 0 };
+        if (syscall(SYS_PPOLL, (long)&pfd, 1, (long)&timeout, 0) != 1) {
+            print("ERROR: ppoll timed out\n");
+            return 1;
+        }
+
+        unsigned long expirations = 0;
+        if (syscall(SYS_READ, fd, (long)&expirations, sizeof(expirations), 0) != sizeof(expirations)) {
+            print("ERROR: read from timer failed\n");
+            return 1;
+        }
+        total += expirations;
+
+        print("Tick ");
+        print_number(tick);
+        print(": ");
+        print_number(expirations);
+        print(" expiration(s)\n");
+    }
+
+    syscall(SYS_CLOSE, fd, 0, 0, 0);
+    print("Timer test done, ");
+    print_number(total);
+    print(" expirations in total\n");
+    return 0;
+}
+
+// Entry point required by linker - ensure it's at the start of text section
+__attribute__((section(".text.start")))
+int _start() {
+    int result = main();
+    // Return the result instead of infinite loop
+    return result;
+}
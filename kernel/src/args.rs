@@ -0,0 +1,207 @@
+//! Argument parsing for shell commands
+//!
+//! A small getopt-style parser shared by the built-ins: boolean flags,
+//! options taking a value, positional arguments and `--` to end option
+//! parsing. Short flags may be grouped (`-la`), values may be attached
+//! (`-n5`, `--count=5`) or separate (`-n 5`, `--count 5`).
+//!
+//! `-h` and `--help` are accepted by every command and print the usage line
+//! from the command registry. Parse errors print the usage automatically.
+
+use heapless::Vec;
+use elinos_common::console_println;
+
+/// Maximum number of arguments (flags, values and positionals) per command
+pub const MAX_ARGS: usize = 16;
+
+/// One option a command accepts
+pub struct OptSpec {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub takes_value: bool,
+}
+
+impl OptSpec {
+    /// An option that is either present or not
+    pub const fn flag(short: char, long: &'static str) -> Self {
+        OptSpec { short: Some(short), long, takes_value: false }
+    }
+
+    /// An option followed by a value
+    pub const fn value(short: char, long: &'static str) -> Self {
+        OptSpec { short: Some(short), long, takes_value: true }
+    }
+}
+
+/// Argument specification for a command
+pub struct CommandSpec {
+    /// Registry name, used for error messages and `--help`
+    pub name: &'static str,
+    pub options: &'static [OptSpec],
+    pub min_positional: usize,
+    pub max_positional: usize,
+}
+
+/// Why an argument list was rejected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgError<'a> {
+    UnknownOption(&'a str),
+    MissingValue(&'static str),
+    UnexpectedValue(&'static str),
+    MissingArgument,
+    TooManyArguments,
+}
+
+impl<'a> core::fmt::Display for ArgError<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ArgError::UnknownOption(opt) => write!(f, "unknown option '{}'", opt),
+            ArgError::MissingValue(opt) => write!(f, "option '--{}' requires a value", opt),
+            ArgError::UnexpectedValue(opt) => write!(f, "option '--{}' does not take a value", opt),
+            ArgError::MissingArgument => write!(f, "missing argument"),
+            ArgError::TooManyArguments => write!(f, "too many arguments"),
+        }
+    }
+}
+
+/// Result of a successful parse
+pub struct ParsedArgs<'a> {
+    flags: Vec<&'static str, MAX_ARGS>,
+    values: Vec<(&'static str, &'a str), MAX_ARGS>,
+    positional: Vec<&'a str, MAX_ARGS>,
+    help: bool,
+}
+
+impl<'a> ParsedArgs<'a> {
+    /// Whether the flag with this long name was given
+    pub fn has(&self, long: &str) -> bool {
+        self.flags.iter().any(|f| *f == long)
+    }
+
+    /// The value of an option; the last occurrence wins
+    pub fn value(&self, long: &str) -> Option<&'a str> {
+        self.values.iter().rev().find(|(name, _)| *name == long).map(|(_, v)| *v)
+    }
+
+    /// Positional arguments in order
+    pub fn positional(&self) -> &[&'a str] {
+        &self.positional
+    }
+
+    /// The positional argument at `index`, if present
+    pub fn arg(&self, index: usize) -> Option<&'a str> {
+        self.positional.get(index).copied()
+    }
+
+    /// Whether `-h` or `--help` was given
+    pub fn wants_help(&self) -> bool {
+        self.help
+    }
+}
+
+impl CommandSpec {
+    fn find_long(&self, long: &str) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.long == long)
+    }
+
+    fn find_short(&self, short: char) -> Option<&'static OptSpec> {
+        self.options.iter().find(|o| o.short == Some(short))
+    }
+
+    /// Parse a whitespace-separated argument string against this spec
+    pub fn parse<'a>(&self, args: &'a str) -> Result<ParsedArgs<'a>, ArgError<'a>> {
+        let mut parsed = ParsedArgs {
+            flags: Vec::new(),
+            values: Vec::new(),
+            positional: Vec::new(),
+            help: false,
+        };
+
+        let mut words = args.split_whitespace();
+        let mut options_done = false;
+
+        while let Some(word) = words.next() {
+            if options_done || word == "-" || !word.starts_with('-') {
+                parsed.positional.push(word).map_err(|_| ArgError::TooManyArguments)?;
+            } else if word == "--" {
+                options_done = true;
+            } else if let Some(long) = word.strip_prefix("--") {
+                let (name, attached) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+
+                if name == "help" {
+                    parsed.help = true;
+                    continue;
+                }
+
+                let spec = self.find_long(name).ok_or(ArgError::UnknownOption(word))?;
+                if spec.takes_value {
+                    let value = match attached {
+                        Some(value) => value,
+                        None => words.next().ok_or(ArgError::MissingValue(spec.long))?,
+                    };
+                    parsed.values.push((spec.long, value)).map_err(|_| ArgError::TooManyArguments)?;
+                } else if attached.is_some() {
+                    return Err(ArgError::UnexpectedValue(spec.long));
+                } else {
+                    parsed.flags.push(spec.long).map_err(|_| ArgError::TooManyArguments)?;
+                }
+            } else {
+                // Group of short options, the last of which may take a value
+                let group = &word[1..];
+                for (i, ch) in group.char_indices() {
+                    if ch == 'h' && self.find_short('h').is_none() {
+                        parsed.help = true;
+                        continue;
+                    }
+
+                    let spec = self.find_short(ch).ok_or(ArgError::UnknownOption(word))?;
+                    if spec.takes_value {
+                        let rest = &group[i + ch.len_utf8()..];
+                        let value = if !rest.is_empty() {
+                            rest
+                        } else {
+                            words.next().ok_or(ArgError::MissingValue(spec.long))?
+                        };
+                        parsed.values.push((spec.long, value)).map_err(|_| ArgError::TooManyArguments)?;
+                        break;
+                    }
+                    parsed.flags.push(spec.long).map_err(|_| ArgError::TooManyArguments)?;
+                }
+            }
+        }
+
+        if !parsed.help {
+            if parsed.positional.len() < self.min_positional {
+                return Err(ArgError::MissingArgument);
+            }
+            if parsed.positional.len() > self.max_positional {
+                return Err(ArgError::TooManyArguments);
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Parse arguments for a shell command, handling `--help` and usage errors.
+///
+/// Returns `Ok(None)` when help was printed and the command should not run.
+pub fn parse_command<'a>(spec: &CommandSpec, args: &'a str) -> Result<Option<ParsedArgs<'a>>, &'static str> {
+    match spec.parse(args) {
+        Ok(parsed) if parsed.wants_help() => {
+            crate::help::show_usage(spec.name)?;
+            Ok(None)
+        }
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(e) => {
+            console_println!("{}: {}", spec.name, e);
+            if let Some(cmd) = crate::help::find_command(spec.name) {
+                crate::msg_println!(crate::messages::Msg::Usage, cmd.usage);
+            }
+            Err("Invalid arguments")
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Boot timing: how long each stage of bringing the kernel up took
+//!
+//! `kernel_core_main` wraps each stage in `begin` and `end`, and drivers
+//! that probe several devices wrap each probe the same way, one level down.
+//! Times come from the `time` CSR, which counts from reset at the timebase
+//! frequency, so the report also shows what firmware and the bootloader
+//! took before the kernel's first stage. Stages are kept once boot is
+//! over, for the `bootchart` command and /proc/bootchart to show; a new
+//! subsystem that slows boot down shows up as a stage that grew.
+
+use core::fmt::Write;
+use elinos_common::timer;
+use heapless::{String, Vec};
+use spin::Mutex;
+
+/// Stages kept; more are not timed
+const MAX_STAGES: usize = 32;
+
+/// Stages open at once, the top level and the probes within it
+const MAX_DEPTH: usize = 2;
+
+/// Width of the bar drawn for the longest stage
+const BAR_WIDTH: u64 = 20;
+
+/// A stage and its start and end in timer ticks
+#[derive(Debug, Clone)]
+struct Stage {
+    name: String<32>,
+    depth: usize,
+    start: u64,
+    end: Option<u64>,
+}
+
+struct Chart {
+    stages: Vec<Stage, MAX_STAGES>,
+    /// Indices of the stages begun and not yet ended, innermost last
+    open: Vec<usize, MAX_DEPTH>,
+    /// When the shell was about to start, once it was
+    done: Option<u64>,
+}
+
+static CHART: Mutex<Chart> = Mutex::new(Chart { stages: Vec::new(), open: Vec::new(), done: None });
+
+/// Start timing the stage `name`, within the stage open now if there is
+/// one. Does nothing once boot is over, so probes run again later by a
+/// rescan are not timed.
+pub fn begin(name: &str) {
+    let now = timer::ticks();
+    let mut chart = CHART.lock();
+    if chart.done.is_some() || chart.open.is_full() {
+        return;
+    }
+    let mut stage = Stage { name: String::new(), depth: chart.open.len(), start: now, end: None };
+    for ch in name.chars() {
+        if stage.name.push(ch).is_err() {
+            break;
+        }
+    }
+    let index = chart.stages.len();
+    if chart.stages.push(stage).is_ok() {
+        let _ = chart.open.push(index);
+    }
+}
+
+/// End the innermost stage begun
+pub fn end() {
+    let now = timer::ticks();
+    let mut chart = CHART.lock();
+    if chart.done.is_some() {
+        return;
+    }
+    if let Some(index) = chart.open.pop() {
+        chart.stages[index].end = Some(now);
+    }
+}
+
+/// Note that boot is over, ending any stage still open, and log the total
+pub fn finish() {
+    let now = timer::ticks();
+    let mut chart = CHART.lock();
+    if chart.done.is_some() {
+        return;
+    }
+    while let Some(index) = chart.open.pop() {
+        chart.stages[index].end = Some(now);
+    }
+    chart.done = Some(now);
+    drop(chart);
+    elinos_common::info_println!("Boot took {} ms", timer::ticks_to_ns(now) / 1_000_000);
+}
+
+/// Write the report: when each stage started and how long it took, both
+/// in milliseconds, its share of the boot and a bar scaled to the longest
+pub fn write_report(out: &mut impl Write) -> core::fmt::Result {
+    let chart = CHART.lock();
+    let Some(first) = chart.stages.first() else {
+        return writeln!(out, "No boot stages recorded");
+    };
+    let total = chart.done.unwrap_or_else(timer::ticks);
+    let firmware = first.start;
+    let longest = chart.stages.iter()
+        .map(|stage| stage.end.unwrap_or(total) - stage.start)
+        .chain([firmware])
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    writeln!(out, "{:<34}{:>10}{:>10}{:>6}", "Stage", "Start", "Took", "%")?;
+    let row = |out: &mut dyn Write, name: &str, depth: usize, start: u64, took: u64| {
+        let mut label = String::<40>::new();
+        for _ in 0..depth {
+            let _ = label.push_str("  ");
+        }
+        let _ = label.push_str(name);
+        let mut bar = String::<{ BAR_WIDTH as usize }>::new();
+        for _ in 0..(took * BAR_WIDTH).div_ceil(longest) {
+            let _ = bar.push('#');
+        }
+        writeln!(out, "{:<34}{:>10}{:>10}{:>5}% {}", label, Millis(start), Millis(took),
+            took * 100 / total.max(1), bar)
+    };
+    row(out, "Firmware and bootloader", 0, 0, firmware)?;
+    for stage in &chart.stages {
+        let took = stage.end.unwrap_or(total) - stage.start;
+        row(out, &stage.name, stage.depth, stage.start, took)?;
+    }
+    match chart.done {
+        Some(done) => writeln!(out, "Shell started at {}", Millis(done)),
+        None => writeln!(out, "Boot still under way at {}", Millis(total)),
+    }
+}
+
+/// Ticks shown as milliseconds with one decimal
+struct Millis(u64);
+
+impl core::fmt::Display for Millis {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let tenths = timer::ticks_to_ns(self.0) / 100_000;
+        let mut text = String::<24>::new();
+        let _ = write!(text, "{}.{} ms", tenths / 10, tenths % 10);
+        f.pad(&text)
+    }
+}
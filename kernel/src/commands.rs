@@ -1,92 +1,62 @@
 use crate::syscall;
 use crate::filesystem::traits::{FileSystem, FilesystemError};
 use crate::memory::{self, BufferUsage, AllocationMode};
-use heapless::String;
+use crate::args::{self, CommandSpec};
+use crate::messages::{self, Msg};
+use crate::net;
+use crate::filesystem::path::{self, PathBuf};
+use heapless::{String, Vec};
 use core::fmt::Write;
-use elinos_common::{console_println, console_print};
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println, console_print};
+use elinos_common::console::{ConsoleEncoding, console_encoding, set_console_encoding};
 
 // Shell commands that use system calls
 
-const MAX_PATH_LEN: usize = 256;
-static mut CURRENT_PATH: String<MAX_PATH_LEN> = String::new();
+/// Exit status for a command that was not found
+pub const STATUS_NOT_FOUND: i32 = 127;
+/// Exit status for a file that exists but cannot be executed
+pub const STATUS_NOT_EXECUTABLE: i32 = 126;
 
-// Helper to initialize CWD if it's empty (e.g., on first command)
-fn ensure_cwd_initialized() {
-    unsafe {
-        if CURRENT_PATH.is_empty() {
-            // Initialize to root path "/"
-            CURRENT_PATH.clear(); // Ensure it's empty before pushing
-            if CURRENT_PATH.push('/').is_err() {
-                // This should ideally not fail with a single char if MAX_PATH_LEN > 0
-                // Consider a panic or a more robust error handling if path initialization is critical
-                console_println!("CRITICAL: Failed to initialize CWD to root!");
-            }
-        }
-    }
-}
+/// Exit status reported by the command currently running, if it has one
+/// more specific than success or failure (e.g. a program's exit code)
+static COMMAND_STATUS: spin::Mutex<Option<i32>> = spin::Mutex::new(None);
 
-// Helper function to resolve a path argument to an absolute path
-fn resolve_path(path_arg: &str) -> String<MAX_PATH_LEN> {
-    ensure_cwd_initialized(); // Ensure CURRENT_PATH is valid before use
-    unsafe { // To access CURRENT_PATH
-        let mut components: heapless::Vec<&str, 32> = heapless::Vec::new();
+fn set_command_status(status: i32) {
+    *COMMAND_STATUS.lock() = Some(status);
+}
 
-        // Determine the starting components based on whether path_arg is absolute
-        if path_arg.starts_with('/') {
-            // Absolute path, start fresh. Add a placeholder if path_arg is only "/" to avoid empty components later.
-            // if path_arg == "/" { let _ = components.push(""); } // This logic is tricky, split handles it.
-        } else {
-            // Relative path, start with CURRENT_PATH components
-            // Trim CURRENT_PATH to avoid empty strings if it's just "/"
-            // and split by '/'
-            for component in CURRENT_PATH.trim_matches('/').split('/') {
-                if !component.is_empty() { // Avoid pushing empty strings from multiple slashes or root
-                    let _ = components.push(component);
-                }
-            }
-        }
+/// Run a command and return its exit status: the program's exit code for
+/// ELF binaries, otherwise 0 on success. Errors are passed through so the
+/// shell can report them; their status is 1 unless the command set one.
+pub fn execute_command(command: &str) -> (i32, Result<(), &'static str>) {
+    with_status(|| process_command(command))
+}
 
-        // Process path_arg components
-        // Trim path_arg to handle cases like "dir/" or "/abs/path/"
-        for component in path_arg.trim_matches('/').split('/') {
-            if component.is_empty() || component == "." {
-                continue; // Skip empty parts (e.g. '//') or current dir '.'
-            }
-            if component == ".." {
-                if !components.is_empty() {
-                    components.pop(); // Go up one level
-                }
-                // If components is empty, ".." at root stays at root, effectively.
-            } else {
-                if components.push(component).is_err() {
-                    // Path too deep or too many components, handle error or truncate
-                    console_println!("Warning: Path too long or complex, may be truncated.");
-                    break; 
-                }
-            }
+/// Start the program `command` names in the background, as `command &`
+/// does, and return the status as `execute_command` does
+pub fn execute_background(command: &str) -> (i32, Result<(), &'static str>) {
+    with_status(|| match command.split_whitespace().next() {
+        None => Err("Usage: PROGRAM &"),
+        Some(name) if get_available_commands().any(|builtin| builtin == name) => {
+            Err("Only programs can run in the background")
         }
+        Some(_) => cmd_run_program(command, true),
+    })
+}
 
-        // Construct the final path
-        let mut final_path = String::<MAX_PATH_LEN>::new();
-        if final_path.push('/').is_err() { /* error handling */ } // Always starts with /
+/// Run a command and return its status with its result
+fn with_status(run: impl FnOnce() -> Result<(), &'static str>) -> (i32, Result<(), &'static str>) {
+    COMMAND_STATUS.lock().take();
+    let result = run();
+    let status = COMMAND_STATUS.lock().take()
+        .unwrap_or(if result.is_ok() { 0 } else { 1 });
+    (status, result)
+}
 
-        for (i, comp) in components.iter().enumerate() {
-            if i > 0 { // Add separator for subsequent components
-                if final_path.push('/').is_err() { break; }
-            }
-            if final_path.push_str(comp).is_err() { break; }
-        }
-        
-        // If final_path is still just "/", it's correct.
-        // If it became empty somehow (shouldn't with this logic), reset to "/"
-        if final_path.is_empty() && components.is_empty() {
-             // This case should ideally be covered by final_path.push('/') above
-             // but as a safeguard:
-            final_path.clear();
-            final_path.push('/').unwrap_or_default();
-        }
-        final_path
-    }
+/// `path_arg` as an absolute path, taken from the working directory if it
+/// is relative
+fn resolve_path(path_arg: &str) -> Result<PathBuf, &'static str> {
+    path::resolve(path_arg).map_err(|_| "Path too long")
 }
 
 // Helper to print FilesystemError
@@ -101,7 +71,6 @@ fn print_filesystem_error(e: &FilesystemError) {
 
 // Central command processor - main.rs calls this function
 pub fn process_command(command: &str) -> Result<(), &'static str> {
-    ensure_cwd_initialized(); // Initialize CWD on first command
     let command = command.trim();
     
     let result = match command {
@@ -112,20 +81,70 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
         "heap" => cmd_heap(),
         "heap-reset" => cmd_heap_reset(),
         "mmap" => cmd_mmap(),
+        "peek" => cmd_peek(""),
+        "poke" => cmd_poke(""),
         "devices" => cmd_devices(),
         "graphics" => cmd_graphics(),
+        "gfxbench" => cmd_gfxbench(""),
+        "screenshot" => cmd_screenshot(""),
         // "gfxtest" => cmd_graphics_test(), // Removed - TTY console doesn't need complex graphics tests
-        "syscall" => cmd_syscall(),
+        "syscalls" | "syscall" => cmd_syscalls(""),
         "fscheck" => cmd_fscheck(),
+        "sync" => cmd_sync(),
+        "rescan" => cmd_rescan(),
+        "mount" => cmd_mount(""),
+        "parts" => cmd_parts(""),
+        "lsblk" => cmd_lsblk(""),
+        "fsck" => cmd_fsck(""),
+        "diskstats" => cmd_diskstats(),
+        "health" => cmd_health(),
+        "traps" => cmd_traps(),
+        "usb" => cmd_usb(""),
+        "beep" => cmd_beep(""),
+        "memdebug" => cmd_memdebug(""),
+        "kconfig" => cmd_kconfig(""),
+        "ln" => cmd_ln(""),
+        "mv" => cmd_mv(""),
+        "bootchart" => cmd_bootchart(),
+        "umount" => cmd_umount(""),
+        "shred" => cmd_shred(""),
+        "wipefree" => cmd_wipefree(""),
+        "update" => cmd_update(""),
+        "abitest" => cmd_abitest(""),
+        "selftest" => cmd_selftest(),
         "config" => cmd_config(),
+        "secexec" => cmd_secexec(""),
+        "ps" => cmd_ps(),
+        "jobs" => cmd_jobs(),
+        "fg" => cmd_fg(""),
+        "kill" => cmd_kill(""),
+        "pmap" => cmd_pmap(""),
+        "swapon" => cmd_swapon(""),
+        "swapoff" => cmd_swapoff(""),
+        "dmesg" => cmd_dmesg(""),
+        "loglevel" => cmd_loglevel(""),
+        "logrotate" => cmd_logrotate(),
+        "locale" => cmd_locale(""),
+        "charset" => cmd_charset(""),
+        "ifconfig" | "net" => cmd_ifconfig(""),
+        "ping" => cmd_ping(""),
         
         // File operations (working via modular filesystem)
-        "ls" => cmd_ls(None),
-        "cat" => {
-            console_println!("Usage: cat <filename>");
+        "ls" => cmd_ls(""),
+        "cat" => cmd_cat(""),
+        "echo" => cmd_echo(""),
+        "true" => Ok(()),
+        "false" => {
+            set_command_status(1);
             Ok(())
         },
-        "echo" => cmd_echo(""),
+        "test" => cmd_test(""),
+        "sleep" => cmd_sleep(""),
+        "uptime" => cmd_uptime(),
+        "sysctl" => cmd_sysctl(""),
+        "date" => cmd_date(""),
+        "at" => cmd_at(""),
+        "cron" => cmd_cron(""),
         "pwd" => cmd_pwd(),
 
         // New file/dir operations
@@ -154,19 +173,66 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
         "reboot" => cmd_reboot(),
         
         // Commands with arguments
-        cmd if cmd.starts_with("ls ") => {
-            let path_arg = &cmd[3..].trim();
-            cmd_ls(Some(path_arg))
+        cmd if cmd.starts_with("ls ") => cmd_ls(&cmd[3..]),
+        cmd if cmd.starts_with("cat ") => cmd_cat(&cmd[4..]),
+        "man" => {
+            console_println!("Usage: man <command>");
+            Ok(())
         },
-        cmd if cmd.starts_with("cat ") => {
-            let path_arg = &cmd[4..].trim();
-            if path_arg.is_empty() {
-                console_println!("Usage: cat <filename>");
-                Ok(())
-            } else {
-                let full_path = resolve_path(path_arg);
-                cmd_cat(&full_path)
-            }
+        cmd if cmd.starts_with("man ") => {
+            let name = cmd.strip_prefix("man ").unwrap_or("").trim();
+            crate::help::show_man(name)
+        },
+        cmd if cmd.starts_with("help ") => {
+            let name = cmd.strip_prefix("help ").unwrap_or("").trim();
+            crate::help::show_usage(name)
+        },
+        cmd if cmd.starts_with("locale ") => cmd_locale(&cmd[7..]),
+        cmd if cmd.starts_with("charset ") => cmd_charset(&cmd[8..]),
+        cmd if cmd.starts_with("ifconfig ") => cmd_ifconfig(&cmd[9..]),
+        cmd if cmd.starts_with("net ") => cmd_ifconfig(&cmd[4..]),
+        cmd if cmd.starts_with("ping ") => cmd_ping(&cmd[5..]),
+        cmd if cmd.starts_with("dmesg ") => cmd_dmesg(&cmd[6..]),
+        cmd if cmd.starts_with("loglevel ") => cmd_loglevel(&cmd[9..]),
+        cmd if cmd.starts_with("swapon ") => cmd_swapon(&cmd[7..]),
+        cmd if cmd.starts_with("swapoff ") => cmd_swapoff(&cmd[8..]),
+        cmd if cmd.starts_with("pmap ") => cmd_pmap(&cmd[5..]),
+        cmd if cmd.starts_with("fg ") => cmd_fg(&cmd[3..]),
+        cmd if cmd.starts_with("kill ") => cmd_kill(&cmd[5..]),
+        cmd if cmd.starts_with("syscalls ") => cmd_syscalls(&cmd[9..]),
+        cmd if cmd.starts_with("abitest ") => cmd_abitest(&cmd[8..]),
+        cmd if cmd.starts_with("mount ") => cmd_mount(&cmd[6..]),
+        cmd if cmd.starts_with("parts ") => cmd_parts(&cmd[6..]),
+        cmd if cmd.starts_with("usb ") => cmd_usb(&cmd[4..]),
+        cmd if cmd.starts_with("beep ") => cmd_beep(&cmd[5..]),
+        cmd if cmd.starts_with("memdebug ") => cmd_memdebug(&cmd[9..]),
+        cmd if cmd.starts_with("kconfig ") => cmd_kconfig(&cmd[8..]),
+        cmd if cmd.starts_with("ln ") => cmd_ln(&cmd[3..]),
+        cmd if cmd.starts_with("mv ") => cmd_mv(&cmd[3..]),
+        cmd if cmd.starts_with("lsblk ") => cmd_lsblk(&cmd[6..]),
+        cmd if cmd.starts_with("fsck ") => cmd_fsck(&cmd[5..]),
+        cmd if cmd.starts_with("umount ") => cmd_umount(&cmd[7..]),
+        cmd if cmd.starts_with("sleep ") => cmd_sleep(&cmd[6..]),
+        cmd if cmd.starts_with("test ") => cmd_test(&cmd[5..]),
+        cmd if cmd.starts_with("[ ") => match cmd.strip_suffix(']') {
+            Some(condition) => cmd_test(&condition[2..]),
+            None => Err("test: missing ']'"),
+        },
+        cmd if cmd.starts_with("date ") => cmd_date(&cmd[5..]),
+        cmd if cmd.starts_with("at ") => cmd_at(&cmd[3..]),
+        cmd if cmd.starts_with("cron ") => cmd_cron(&cmd[5..]),
+        cmd if cmd.starts_with("config ") => cmd_config_store(&cmd[7..]),
+        cmd if cmd.starts_with("sysctl ") => cmd_sysctl(&cmd[7..]),
+        cmd if cmd.starts_with("gfxbench ") => cmd_gfxbench(&cmd[9..]),
+        cmd if cmd.starts_with("screenshot ") => cmd_screenshot(&cmd[11..]),
+        cmd if cmd.starts_with("peek ") => cmd_peek(&cmd[5..]),
+        cmd if cmd.starts_with("poke ") => cmd_poke(&cmd[5..]),
+        cmd if cmd.starts_with("shred ") => cmd_shred(&cmd[6..]),
+        cmd if cmd.starts_with("wipefree ") => cmd_wipefree(&cmd[9..]),
+        cmd if cmd.starts_with("update ") => cmd_update(&cmd[7..]),
+        cmd if cmd.starts_with("secexec ") => {
+            let mode = cmd.strip_prefix("secexec ").unwrap_or("").trim();
+            cmd_secexec(mode)
         },
         cmd if cmd.starts_with("echo ") => {
             let message = &cmd[5..];
@@ -180,7 +246,7 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
                 console_println!("Usage: touch <filename>");
                 Ok(())
             } else {
-                let full_path = resolve_path(path_arg);
+                let full_path = resolve_path(path_arg)?;
                 cmd_touch(&full_path)
             }
         },
@@ -190,7 +256,7 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
                 console_println!("Usage: mkdir <dirname>");
                 Ok(())
             } else {
-                let full_path = resolve_path(path_arg);
+                let full_path = resolve_path(path_arg)?;
                 cmd_mkdir(&full_path)
             }
         },
@@ -200,7 +266,7 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
                 console_println!("Usage: rm <filename>");
                 Ok(())
             } else {
-                let full_path = resolve_path(path_arg);
+                let full_path = resolve_path(path_arg)?;
                 cmd_rm(&full_path)
             }
         },
@@ -210,7 +276,7 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
                 console_println!("Usage: rmdir <dirname>");
                 Ok(())
             } else {
-                let full_path = resolve_path(path_arg);
+                let full_path = resolve_path(path_arg)?;
                 cmd_rmdir(&full_path)
             }
         },
@@ -226,100 +292,432 @@ pub fn process_command(command: &str) -> Result<(), &'static str> {
         "" => Ok(()),
         
         // Unknown command - try to execute as ELF binary
-        _ => {
-            // Try to execute as ELF binary
-            let full_path = resolve_path(command);
-            
-            // Check if file exists and try to execute it
-            match crate::filesystem::read_file(&full_path) {
-                Ok(file_data) => {
-                    
-                    // Check if it's an ELF file by looking at magic bytes
-                    if file_data.len() >= 4 && &file_data[0..4] == b"\x7fELF" {
-                        cmd_execute_elf(&full_path, &file_data)
-                    } else {
-                        console_println!("[x] Not an executable file: {}", command);
-                        console_println!("(expected ELF magic: 7f 45 4c 46)");
-                        Err("Not an executable")
-                    }
-                }
-                Err(_) => {
-                    console_println!("Unknown command: {}", command);
-                    console_println!("Type 'help' for available commands.");
-                    Ok(())
-                }
-            }
-        }
+        _ => cmd_run_program(command, false),
     };
 
     result
 }
 
+/// Run the ELF binary at `command`, a path, waiting for it unless it goes
+/// in the `background`
+fn cmd_run_program(command: &str, background: bool) -> Result<(), &'static str> {
+    let full_path = resolve_path(command)?;
+    
+    // Check if file exists and try to execute it. Only the magic is
+    // read here, so binaries of any size can be probed.
+    let mut magic = [0u8; 4];
+    match crate::filesystem::read_file_at(&full_path, 0, &mut magic) {
+        Ok(read) => {
+            
+            // Check if it's an ELF file by looking at magic bytes
+            if read == 4 && &magic == b"\x7fELF" {
+                cmd_execute_elf(&full_path, background.then_some(command))
+            } else {
+                crate::msg_println!(Msg::NotExecutable, command);
+                crate::msg_println!(Msg::ElfMagicHint);
+                set_command_status(STATUS_NOT_EXECUTABLE);
+                Err("Not an executable")
+            }
+        }
+        Err(_) => {
+            crate::msg_println!(Msg::UnknownCommand, command);
+            crate::msg_println!(Msg::HelpHint);
+            set_command_status(STATUS_NOT_FOUND);
+            Ok(())
+        }
+    }
+}
+
 // Get list of all available commands (for help and autocomplete)
-pub fn get_available_commands() -> &'static [&'static str] {
-    &[
-        "help", "version", "memory", "heap", "mmap", "devices", "syscall", "fscheck", "config",
-        "ls", "cat", "echo", "pwd",
-        "touch", "mkdir", "rm", "rmdir", "cd",
-        "shutdown", "reboot"
-    ]
+pub fn get_available_commands() -> impl Iterator<Item = &'static str> {
+    crate::help::COMMAND_REGISTRY.iter().map(|cmd| cmd.name)
 }
 
 // === INDIVIDUAL COMMAND IMPLEMENTATIONS ===
 
 pub fn cmd_help() -> Result<(), &'static str> {
-    console_println!("[i] ElinOS Commands");
-    console_println!("===============================================");
-    console_println!();
+    crate::help::show_help();
+    Ok(())
+}
+
+/// List processes in the process table
+fn cmd_ps() -> Result<(), &'static str> {
+    crate::process::show_processes();
+    Ok(())
+}
+
+/// List the programs running in the background
+fn cmd_jobs() -> Result<(), &'static str> {
+    crate::jobs::show();
+    Ok(())
+}
+
+/// Run a background job, the newest unless one is named by its number
+/// (`%2` or `2`), in the foreground until it exits
+fn cmd_fg(args: &str) -> Result<(), &'static str> {
+    let args = args.trim();
+    let id = match args {
+        "" => None,
+        job => Some(job.strip_prefix('%').unwrap_or(job).parse::<usize>().map_err(|_| "Usage: fg [%job]")?),
+    };
+    let finished = crate::jobs::foreground(id)?;
+    crate::msg_println!(Msg::ProcessExited, finished.pid, finished.status);
+    set_command_status(finished.status);
+    Ok(())
+}
+
+/// End a background job by its PID
+fn cmd_kill(args: &str) -> Result<(), &'static str> {
+    let pid = args.trim().parse::<i32>().map_err(|_| "Usage: kill <pid>")?;
+    crate::jobs::report(&crate::jobs::kill(pid)?);
+    Ok(())
+}
+
+/// Show the address space of a process: each mapping's size, resident
+/// pages, permissions and what backs it
+fn cmd_pmap(args: &str) -> Result<(), &'static str> {
+    let pid = args.trim().parse::<i32>().map_err(|_| "Usage: pmap <pid>")?;
+    let name = {
+        let pm = crate::process::PROCESS_MANAGER.lock();
+        pm.get_process(pid).ok_or("No such process")?.name.clone()
+    };
+    console_println!("{}: {}", pid, name);
+    let Some(vmas) = crate::process::process_vmas(pid) else {
+        console_println!("  runs in the kernel's address space, no user mappings");
+        return Ok(());
+    };
+
+    console_println!("{:<17} {:>8} {:>8} {:<5} {:<7} {}", "Address", "Kbytes", "RSS", "Mode", "Backing", "Mapping");
+    let (mut total, mut resident) = (0, 0);
+    for vma in &vmas {
+        let perms = vma.perms_str();
+        let kbytes = (vma.end - vma.start) / 1024;
+        let rss = vma.resident * crate::memory::mmu::PAGE_SIZE / 1024;
+        total += kbytes;
+        resident += rss;
+        console_println!("{:08x}-{:08x} {:>8} {:>8} {:<5} {:<7} {}", vma.start, vma.end, kbytes, rss,
+            core::str::from_utf8(&perms).unwrap_or("----"), vma.backing.name(), vma.name);
+    }
+    console_println!("{:<17} {:>8} {:>8}", "total kB", total, resident);
+    Ok(())
+}
+
+const DMESG_SPEC: CommandSpec = CommandSpec {
+    name: "dmesg",
+    options: &[
+        args::OptSpec::flag('r', "raw"),
+        args::OptSpec::flag('c', "read-clear"),
+        args::OptSpec::flag('C', "clear"),
+        args::OptSpec::value('l', "level"),
+    ],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// Print the kernel log buffer
+fn cmd_dmesg(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&DMESG_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let level = match parsed.value("level") {
+        Some(level) => Some(level.parse::<u8>().ok().filter(|&l| l <= 8).ok_or("Invalid level, expected 0-8")?),
+        None => None,
+    };
+    if !parsed.has("clear") {
+        crate::klog::dump(parsed.has("raw"), level);
+    }
+    if parsed.has("clear") || parsed.has("read-clear") {
+        crate::klog::clear();
+    }
+    Ok(())
+}
+
+const LOGLEVEL_SPEC: CommandSpec = CommandSpec {
+    name: "loglevel",
+    options: &[],
+    min_positional: 0,
+    max_positional: 2,
+};
+
+/// Show or change the console log level, globally or for one subsystem
+fn cmd_loglevel(args: &str) -> Result<(), &'static str> {
+    use elinos_common::console;
+
+    let parsed = match args::parse_command(&LOGLEVEL_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let parse_level = |text: &str| console::parse_log_level(text)
+        .ok_or("Invalid level, expected quiet, error, warn, notice, info, debug, trace or 0-9");
+    match (parsed.arg(0), parsed.arg(1)) {
+        (None, _) => {
+            let level = console::log_level();
+            console_println!("{:<8} {} ({})", "global", level, console::log_level_name(level));
+            for &subsystem in console::SUBSYSTEMS {
+                match console::subsystem_log_level(subsystem) {
+                    Some(level) => console_println!("{:<8} {} ({})", subsystem, level, console::log_level_name(level)),
+                    None => console_println!("{:<8} global", subsystem),
+                }
+            }
+        }
+        (Some(subsystem), None) if console::SUBSYSTEMS.contains(&subsystem) => {
+            let level = console::target_log_level(subsystem);
+            console_println!("{:<8} {} ({})", subsystem, level, console::log_level_name(level));
+        }
+        (Some(level), None) => {
+            console::set_log_level(parse_level(level)?);
+        }
+        (Some(subsystem), Some(level)) => {
+            let level = match level {
+                "global" | "default" => None,
+                level => Some(parse_level(level)?),
+            };
+            if !console::set_subsystem_log_level(subsystem, level) {
+                return Err("Unknown subsystem, expected one of virtio, ext2, fs, mm, net");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rotate the kernel log file now
+fn cmd_logrotate() -> Result<(), &'static str> {
+    use crate::klog;
+
+    let rotated = klog::flush_to_disk().and_then(|()| klog::rotate()).map_err(|e| {
+        print_filesystem_error(&e);
+        "Failed to rotate the kernel log"
+    })?;
+    if !rotated {
+        return Err("No kernel log file; enable it with sysctl kernel.log.disk=on");
+    }
+    ok_println!("Rotated {} to {}", klog::LOG_PATH, klog::rotated_path(1));
+    Ok(())
+}
+
+/// Show or change the secure exec policy
+fn cmd_secexec(mode: &str) -> Result<(), &'static str> {
+    use crate::elf::verify;
+    
+    match mode {
+        "" => {}
+        "on" => {
+            if !verify::has_key() {
+                err_println!("No signing key: boot with {}<hex>", verify::KEY_ARG);
+                return Err("Signing key not available");
+            }
+            verify::enable().map_err(|e| {
+                print_filesystem_error(&e);
+                "Enabled until reboot, but not saved in the config store"
+            })?;
+        }
+        "off" if verify::is_secure_exec() => return Err("Secure exec stays on until reboot"),
+        "off" => {}
+        _ => {
+            console_println!("Usage: secexec [on|off]");
+            return Ok(());
+        }
+    }
+    
+    info_println!("Secure exec: {}", if verify::is_secure_exec() { "enabled" } else { "disabled" });
+    console_println!("   Key: {}", if verify::has_key() { "given at boot" } else { "none" });
+    console_println!("   Signature: HMAC-SHA256 trailer + \"ELINSIG1\"");
+    Ok(())
+}
+
+const LOCALE_SPEC: CommandSpec = CommandSpec {
+    name: "locale",
+    options: &[args::OptSpec::flag('s', "save")],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+/// Show or change the output locale
+fn cmd_locale(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&LOCALE_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
     
-    console_println!("[i] System Information:");
-    console_println!("  help            - Show this help message");
-    console_println!("  version         - Show kernel version and features");
-    console_println!("  memory          - Show memory regions and allocator statistics");
-    console_println!("  heap            - Show heap usage information");
-    console_println!("  mmap            - Show memory mapping information");
-    console_println!("  devices         - List detected VirtIO devices");
-    console_println!("  graphics        - Show graphics information");
-    // console_println!("  gfxtest         - Test graphics drawing"); // Removed
-    console_println!("  syscall         - Show system call information");
-    console_println!("  fscheck         - Check filesystem status and metadata");
-    console_println!("  config          - Show system configuration");
+    if let Some(name) = parsed.arg(0) {
+        let locale = messages::Locale::from_name(name).ok_or("Unknown locale")?;
+        messages::set_locale(locale);
+    }
+    
+    if parsed.has("save") {
+        messages::save_locale_file()?;
+        crate::msg_println!(Msg::LocaleSaved, messages::LOCALE_FILE_PATH);
+    } else if parsed.arg(0).is_none() {
+        let mut names: String<32> = String::new();
+        for locale in messages::Locale::ALL.iter() {
+            let _ = write!(names, "{} ", locale.name());
+        }
+        crate::msg_println!(Msg::LocaleCurrent, messages::current_locale().name());
+        crate::msg_println!(Msg::LocaleAvailable, names.trim_end());
+    }
+    Ok(())
+}
 
-    console_println!();
-    console_println!("[i]  Filesystem Operations:");
-    console_println!("  ls [path]       - List files/dirs (default: current directory)");
-    console_println!("  cat <path>      - Display file contents");
-    console_println!("  echo [message]  - Print a message (newline if no message)");
-    console_println!("  pwd             - Print current working directory");
-    console_println!("  touch <path>    - Create an empty file at the specified path");
-    console_println!("  mkdir <path>    - Create a directory at the specified path");
-    console_println!("  rm <path>       - Remove a file at the specified path");
-    console_println!("  rmdir <path>    - Remove an empty directory at the specified path");
-    console_println!("  cd [path]       - Change directory (default: root, use '/', '..')");
+const CHARSET_SPEC: CommandSpec = CommandSpec {
+    name: "charset",
+    options: &[args::OptSpec::flag('s', "save")],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+/// Show or change what the console can display
+fn cmd_charset(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&CHARSET_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
     
-    console_println!();
-    console_println!("[i] Program Execution:");
-    console_println!("  hello_simple    - Execute ELF binary directly by name");
-    console_println!("  ./hello_simple  - Execute with explicit relative path");
-    console_println!("  /programs/hello - Execute with absolute path");
+    if let Some(name) = parsed.arg(0) {
+        let encoding = ConsoleEncoding::from_name(name).ok_or("Unknown charset")?;
+        set_console_encoding(encoding);
+    }
     
-    console_println!();
-    console_println!("[i] System Control:");
-    console_println!("  shutdown        - Shutdown the system via SBI");
-    console_println!("  reboot          - Reboot the system via SBI");
+    if parsed.has("save") {
+        messages::save_locale_file()?;
+        crate::msg_println!(Msg::LocaleSaved, messages::LOCALE_FILE_PATH);
+    } else if parsed.arg(0).is_none() {
+        crate::msg_println!(Msg::CharsetCurrent, console_encoding().name());
+        crate::msg_println!(Msg::LocaleAvailable, "utf8 ascii");
+    }
+    Ok(())
+}
+
+const IFCONFIG_SPEC: CommandSpec = CommandSpec {
+    name: "ifconfig",
+    options: &[args::OptSpec::value('m', "netmask"), args::OptSpec::value('g', "gateway")],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+fn cmd_ifconfig(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&IFCONFIG_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    match parsed.arg(0) {
+        None => {
+            info_println!("Network interface:");
+            net::config::show();
+        }
+        Some("dhcp") => {
+            info_println!("Requesting an address with DHCP...");
+            match net::config::run_dhcp() {
+                Ok(lease) => {
+                    ok_println!("Leased {} from {} (gateway {})", lease.ip, lease.server, lease.gateway);
+                    save_network_config();
+                }
+                Err(e) => {
+                    err_println!("DHCP failed: {}", e);
+                    set_command_status(1);
+                }
+            }
+        }
+        Some(addr) => {
+            let current = net::interface().map_err(|_| "Network is down")?;
+            let ip = net::Ipv4Addr::parse(addr).ok_or("Invalid IP address")?;
+            let netmask = match parsed.value("netmask") {
+                Some(mask) => net::config::parse_netmask(mask).map_err(|_| "Invalid netmask")?,
+                None => current.netmask,
+            };
+            let gateway = match parsed.value("gateway") {
+                Some(gw) => net::Ipv4Addr::parse(gw).ok_or("Invalid gateway address")?,
+                None => current.gateway,
+            };
+            net::config::set_static(ip, netmask, gateway).map_err(|_| "Network is down")?;
+            ok_println!("Address {} netmask {} gateway {}", ip, netmask, gateway);
+            save_network_config();
+        }
+    }
+    Ok(())
+}
+
+/// Keep the interface settings for the next boot
+fn save_network_config() {
+    if let Err(e) = net::config::save() {
+        warn_println!("Network settings not saved: {}", e);
+    }
+}
+
+const PING_SPEC: CommandSpec = CommandSpec {
+    name: "ping",
+    options: &[args::OptSpec::value('c', "count")],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+/// How long to wait for each echo reply
+const PING_TIMEOUT_MS: u64 = 1000;
+
+fn cmd_ping(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&PING_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    let dst = parsed.arg(0).and_then(net::Ipv4Addr::parse).ok_or("Invalid IP address")?;
+    let count = match parsed.value("count") {
+        Some(count) => count.parse::<u16>().ok().filter(|&c| c > 0).ok_or("Invalid count")?,
+        None => 4,
+    };
+    net::interface().map_err(|_| "Network is down")?;
+    
+    info_println!("PING {}", dst);
+    let mut received = 0u16;
+    let (mut min_us, mut max_us, mut total_us) = (u64::MAX, 0u64, 0u64);
+    for sequence in 0..count {
+        match net::icmp::ping(dst, sequence, PING_TIMEOUT_MS) {
+            Ok(reply) => {
+                console_println!("{} bytes from {}: seq={} time={}.{:03} ms",
+                                 reply.len, dst, sequence, reply.rtt_us / 1000, reply.rtt_us % 1000);
+                received += 1;
+                min_us = min_us.min(reply.rtt_us);
+                max_us = max_us.max(reply.rtt_us);
+                total_us += reply.rtt_us;
+            }
+            Err(e) => console_println!("From {}: seq={} {}", dst, sequence, e),
+        }
+    }
     
+    console_println!("--- {} ping statistics ---", dst);
+    console_println!("{} sent, {} received, {}% loss", count, received, (count - received) as u32 * 100 / count as u32);
+    if received > 0 {
+        let avg_us = total_us / received as u64;
+        console_println!("rtt min/avg/max = {}.{:03}/{}.{:03}/{}.{:03} ms",
+                         min_us / 1000, min_us % 1000, avg_us / 1000, avg_us % 1000, max_us / 1000, max_us % 1000);
+    } else {
+        set_command_status(1);
+    }
     Ok(())
 }
 
 pub fn cmd_config() -> Result<(), &'static str> {
-    console_println!("[i] Dynamic System Configuration");
+    info_println!("Dynamic System Configuration");
     console_println!("=====================================");
     console_println!();
     
     // Get memory statistics
     let mem_stats = memory::get_memory_stats();
     
-    console_println!("[i] Hardware Detection Results:");
+    info_println!("Boot:");
+    let cmdline = crate::boot_cmdline();
+    console_print!("  Command line:");
+    if cmdline.is_empty() {
+        console_print!(" (none)");
+    }
+    for arg in cmdline.split_whitespace() {
+        console_print!(" {}", elinos_common::cmdline::redact_arg(arg));
+    }
+    console_println!();
+    console_println!();
+    
+    info_println!("Hardware Detection Results:");
     
     console_print!("  Total RAM: ");
     show_number_mb(mem_stats.detected_ram_size);
@@ -337,7 +735,7 @@ pub fn cmd_config() -> Result<(), &'static str> {
     }
     
     console_println!();
-    console_println!("[i] Calculated Memory Allocations:");
+    info_println!("Calculated Memory Allocations:");
     
     console_print!("  Kernel Heap: ");
     show_number_kb(mem_stats.heap_size);
@@ -357,7 +755,7 @@ pub fn cmd_config() -> Result<(), &'static str> {
     }
     
     console_println!();
-    console_println!("[i] Dynamic Buffer Sizes:");
+    info_println!("Dynamic Buffer Sizes:");
     
     let sector_buf_size = memory::get_optimal_buffer_size(BufferUsage::SectorIO);
     console_print!("  Sector I/O: ");
@@ -382,6 +780,123 @@ pub fn cmd_config() -> Result<(), &'static str> {
     Ok(())
 }
 
+const CONFIG_SPEC: CommandSpec = CommandSpec {
+    name: "config",
+    options: &[],
+    min_positional: 1,
+    max_positional: args::MAX_ARGS,
+};
+
+/// Read and change the config store: `config list`, `config get <key>`,
+/// `config set <key> <value>`, `config unset <key>`
+fn cmd_config_store(args: &str) -> Result<(), &'static str> {
+    use crate::filesystem::configstore;
+
+    let parsed = match args::parse_command(&CONFIG_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    match (parsed.arg(0), parsed.arg(1)) {
+        (Some("list"), None) => {
+            for (key, value) in configstore::entries().iter() {
+                console_println!("{}={}", key, value);
+            }
+        }
+        (Some("get"), Some(key)) => {
+            let value = configstore::get(key).ok_or("No such key")?;
+            console_println!("{}", value);
+        }
+        (Some("set" | "unset"), Some(key))
+            if key == crate::elf::verify::STORE_KEY && crate::elf::verify::is_secure_exec()
+                && parsed.arg(2) != Some("on") => {
+            return Err("Secure exec is on; it stays on");
+        }
+        (Some("set"), Some("boot.cmdline"))
+            if parsed.positional().iter().skip(2).any(|arg| elinos_common::cmdline::is_secexec_arg(arg)) => {
+            return Err("secexec arguments are only taken from the bootloader's boot.cfg");
+        }
+        (Some("set"), Some(key)) => {
+            if !configstore::is_valid_key(key) {
+                return Err("Keys are letters, digits, '.', '_' and '-'");
+            }
+            // The value is the rest of the line
+            let mut value: String<{ configstore::MAX_VALUE_LEN }> = String::new();
+            for (i, word) in parsed.positional().iter().skip(2).enumerate() {
+                if i > 0 {
+                    value.push(' ').map_err(|_| "Value too long")?;
+                }
+                value.push_str(word).map_err(|_| "Value too long")?;
+            }
+            configstore::set(key, &value).map_err(|e| {
+                print_filesystem_error(&e);
+                "Failed to save the config store"
+            })?;
+        }
+        (Some("unset"), Some(key)) => match configstore::unset(key) {
+            Ok(true) => {}
+            Ok(false) => return Err("No such key"),
+            Err(e) => {
+                print_filesystem_error(&e);
+                return Err("Failed to save the config store");
+            }
+        },
+        _ => return Err("Usage: config [list | get <key> | set <key> <value> | unset <key>]"),
+    }
+    Ok(())
+}
+
+const SYSCTL_SPEC: CommandSpec = CommandSpec {
+    name: "sysctl",
+    options: &[args::OptSpec::flag('s', "save"), args::OptSpec::flag('d', "describe")],
+    min_positional: 0,
+    max_positional: args::MAX_ARGS,
+};
+
+fn cmd_sysctl(args: &str) -> Result<(), &'static str> {
+    use crate::sysctl;
+
+    let parsed = match args::parse_command(&SYSCTL_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let show = |tunable: &sysctl::Tunable| {
+        if parsed.has("describe") {
+            console_println!("{}: {} ({})", tunable.name, tunable.description, tunable.range());
+        } else {
+            console_println!("{} = {}", tunable.name, tunable.value());
+        }
+    };
+    if parsed.positional().is_empty() {
+        sysctl::tunables().for_each(show);
+        return Ok(());
+    }
+
+    for arg in parsed.positional() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (*arg, None),
+        };
+        let tunable = match sysctl::find(name) {
+            Some(tunable) => tunable,
+            None => {
+                err_println!("sysctl: unknown parameter '{}'", name);
+                return Err("Run sysctl without arguments to list them");
+            }
+        };
+        if let Some(value) = value {
+            tunable.set(value)?;
+        }
+        if parsed.has("save") {
+            sysctl::save(tunable).map_err(|e| {
+                print_filesystem_error(&e);
+                "Failed to save the config store"
+            })?;
+        }
+        show(tunable);
+    }
+    Ok(())
+}
+
 // Helper functions for number display without format! macro
 fn show_number(mut num: usize) {
     if num == 0 {
@@ -415,7 +930,7 @@ fn show_number_mb(bytes: usize) {
 pub fn cmd_memory() -> Result<(), &'static str> {
     // Call the memory info syscall
     let result = syscall::syscall_handler(
-        syscall::memory::SYS_GETMEMINFO,
+        syscall::SYS_GETMEMINFO,
         0,
         0,
         0,
@@ -429,20 +944,50 @@ pub fn cmd_memory() -> Result<(), &'static str> {
 }
 
 pub fn cmd_devices() -> Result<(), &'static str> {
-    syscall::sys_device_info()
+    syscall::sys_device_info()?;
+    crate::virtio::hotplug::show();
+    let input = crate::virtio::VIRTIO_INPUT.lock();
+    if input.is_initialized() {
+        console_println!("Input device:");
+        input.show_info();
+    }
+    drop(input);
+    let sound = crate::virtio::VIRTIO_SOUND.lock();
+    if sound.is_initialized() {
+        console_println!("Sound device:");
+        sound.show_info();
+    }
+    Ok(())
 }
 
-pub fn cmd_ls(path_arg_opt: Option<&str>) -> Result<(), &'static str> {
-    ensure_cwd_initialized();
-    let list_target_path: String<MAX_PATH_LEN>;
-    unsafe { // Access CURRENT_PATH
-        list_target_path = match path_arg_opt {
-            Some(path_arg) => resolve_path(path_arg),
-            None => String::try_from(CURRENT_PATH.as_str()).unwrap_or_default(),
-        };
+fn cmd_rescan() -> Result<(), &'static str> {
+    let changes = crate::virtio::hotplug::scan();
+    if changes == 0 {
+        console_println!("No VirtIO device changes");
     }
+    crate::filesystem::automount::handle_events();
+    Ok(())
+}
+
+const LS_SPEC: CommandSpec = CommandSpec {
+    name: "ls",
+    options: &[args::OptSpec::flag('l', "long")],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+pub fn cmd_ls(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&LS_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    let list_target_path = match parsed.arg(0) {
+        Some(path_arg) => resolve_path(path_arg)?,
+        None => crate::process::current_cwd(),
+    };
 
-    console_println!("Listing for target '{}':", list_target_path);
+    crate::msg_println!(Msg::ListingFor, list_target_path);
 
     // Use the new path-aware directory listing
     match crate::filesystem::list_directory(&list_target_path) {
@@ -454,9 +999,13 @@ pub fn cmd_ls(path_arg_opt: Option<&str>) -> Result<(), &'static str> {
             drop(fs);
             
             if files.is_empty() {
-                console_println!("(No files found)");
+                crate::msg_println!(Msg::NoFiles);
             } else {
                 for (name, _size, is_directory) in &files {
+                    if parsed.has("long") {
+                        print_long_entry(&list_target_path, name);
+                        continue;
+                    }
                     if *is_directory {
                         console_print!("  DIR   ");
                     } else {
@@ -464,108 +1013,180 @@ pub fn cmd_ls(path_arg_opt: Option<&str>) -> Result<(), &'static str> {
                     }
                     console_println!("{}", name.as_str());
                 }
-                console_print!("\nTotal files: ");
-                show_number(files.len());
                 console_println!();
+                crate::msg_println!(Msg::TotalFiles, files.len());
             }
             
             Ok(())
         }
         Err(_) => {
-            console_println!("Failed to list directory");
+            crate::msg_println!(Msg::ListFailed);
             Err("Failed to list directory")
         }
     }
 }
 
-pub fn cmd_cat(filename: &str) -> Result<(), &'static str> {
-    if filename.is_empty() {
-        return Err("Filename cannot be empty for cat");
-    }
-    
-    // Use modular filesystem API
-    match crate::filesystem::read_file(filename) {
-        Ok(content) => {
-            // Get filesystem type for display
-            let fs = crate::filesystem::FILESYSTEM.lock();
-            let fs_type = fs.get_filesystem_type();
-            drop(fs);
-            
-            console_println!("[i] Reading file: {}", filename);
-            
-            if let Ok(content_str) = core::str::from_utf8(&content) {
-                console_println!(" content:");
-                console_print!("{}", content_str);
-                console_println!();
-            } else {
-                console_println!("(Binary file - ");
-                console_println!("bytes count not displayed");
-                console_println!(")");
-            }
-            
-            Ok(())
-        }
+/// One `ls -l` line: mode, links, owner, group, size, time of the last
+/// change to the data, and name, with a symbolic link's target after it
+fn print_long_entry(dir: &str, name: &str) {
+    let stat = path::join(dir, name).and_then(|full| {
+        let stat = crate::filesystem::stat(&full, false)?;
+        let target = if stat.is_symlink() { crate::filesystem::read_link(&full).ok() } else { None };
+        Ok((stat, target))
+    });
+    let (stat, target) = match stat {
+        Ok(found) => found,
         Err(_) => {
-            console_println!("[x] File '{}' not found", filename);
-            Err("File not found")
+            console_println!("  ?????????? {}", name);
+            return;
         }
+    };
+
+    let date = elinos_common::rtc::DateTime::from_unix(stat.mtime);
+    console_print!("  {} {:>3} {:>5} {:>5} {:>8} {:04}-{:02}-{:02} {:02}:{:02} {}",
+        stat.mode_text(), stat.links, stat.uid, stat.gid, stat.size,
+        date.year, date.month, date.day, date.hour, date.minute, name);
+    match target {
+        Some(target) => console_println!(" -> {}", target),
+        None => console_println!(),
     }
 }
 
-pub fn cmd_syscall() -> Result<(), &'static str> {
-    console_println!("System Call Information:");
-     
-    console_println!("Currently Implemented System Calls:");
-    console_println!("  File I/O Operations:");
-    console_println!("    SYS_WRITE (64)     - Write to file descriptor");
-    console_println!("    SYS_READ (63)      - Read from file descriptor");
-    console_println!("    SYS_OPENAT (56)    - Open file (modern Linux openat)");
-    console_println!("    SYS_CLOSE (57)     - Close file descriptor");
-    console_println!("    SYS_GETDENTS64 (61) - List directory entries");
-
-    console_println!("  Memory Management:");
-    console_println!("    SYS_GETMEMINFO (960) - Memory information (elinOS)");
-
-    console_println!("  Process Management:");
-    console_println!("    SYS_EXIT (93)      - Exit process");
-    console_println!("    SYS_GETPID (172)   - Get process ID");
-    console_println!("    SYS_GETPPID (173)  - Get parent process ID");
-    console_println!("    SYS_FORK (220)     - Create child process");
-    console_println!("    SYS_WAIT4 (260)    - Wait for child process");
-
-    console_println!("  Device Management:");
-    console_println!("    SYS_GETDEVICES (950) - Device information (elinOS)");
+const CAT_SPEC: CommandSpec = CommandSpec {
+    name: "cat",
+    options: &[],
+    min_positional: 1,
+    max_positional: args::MAX_ARGS,
+};
 
-    console_println!("  elinOS-Specific (System Control):");
-    console_println!("    SYS_ELINOS_VERSION (902)  - Show version");
-    console_println!("    SYS_ELINOS_SHUTDOWN (903) - Shutdown system");
-    console_println!("    SYS_ELINOS_REBOOT (904)   - Reboot system");
-
-    console_println!();
-    console_println!("Numbers in parentheses are Linux-compatible syscall numbers.");
-    console_println!("This makes elinOS easier to understand for Linux developers!");
+pub fn cmd_cat(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&CAT_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    for path_arg in parsed.positional() {
+        let full_path = resolve_path(path_arg)?;
+        cat_file(&full_path)?;
+    }
     Ok(())
 }
 
-pub fn cmd_version() -> Result<(), &'static str> {
-    let result = syscall::syscall_handler(
-        syscall::elinos::SYS_ELINOS_VERSION,
-        0,
-        0,
-        0,
-        0,
-    );
+fn cat_file(filename: &str) -> Result<(), &'static str> {
+    // Look at the start of the file to tell text from binary
+    let mut head = [0u8; 512];
+    let head_len = match crate::filesystem::read_file_at(filename, 0, &mut head) {
+        Ok(len) => len,
+        Err(_) => {
+            crate::msg_println!(Msg::FileNotFound, filename);
+            return Err("File not found");
+        }
+    };
     
-    match result {
-        syscall::SysCallResult::Success(_) => Ok(()),
-        syscall::SysCallResult::Error(_) => Err("Syscall failed"),
+    crate::msg_println!(Msg::ReadingFile, filename);
+    
+    let is_text = match core::str::from_utf8(&head[..head_len]) {
+        Ok(_) => true,
+        // A character cut off at the end of the sample is still text
+        Err(e) => e.error_len().is_none(),
+    };
+    if !is_text {
+        let size = crate::filesystem::get_file_size(filename).unwrap_or(head_len);
+        crate::msg_println!(Msg::BinaryFile, size);
+        return Ok(());
+    }
+    
+    console_println!(" content:");
+    let mut carry: Vec<u8, 4> = Vec::new();
+    crate::filesystem::read_file_chunked(filename, |chunk| print_text_chunk(&mut carry, chunk))
+        .map_err(|_| "Failed to read file")?;
+    console_println!();
+    Ok(())
+}
+
+/// Print a piece of a UTF-8 file. A character split across pieces is held
+/// in `carry` until the rest arrives; invalid bytes print as U+FFFD.
+fn print_text_chunk(carry: &mut Vec<u8, 4>, mut data: &[u8]) {
+    while !carry.is_empty() && !data.is_empty() {
+        let _ = carry.push(data[0]);
+        data = &data[1..];
+        match core::str::from_utf8(carry) {
+            Ok(s) => {
+                console_print!("{}", s);
+                carry.clear();
+            }
+            Err(e) if e.error_len().is_none() => {}
+            Err(_) => {
+                console_print!("\u{fffd}");
+                carry.clear();
+            }
+        }
+    }
+    
+    loop {
+        match core::str::from_utf8(data) {
+            Ok(s) => {
+                console_print!("{}", s);
+                return;
+            }
+            Err(e) => {
+                let (valid, rest) = data.split_at(e.valid_up_to());
+                console_print!("{}", core::str::from_utf8(valid).unwrap_or(""));
+                match e.error_len() {
+                    None => {
+                        let _ = carry.extend_from_slice(rest);
+                        return;
+                    }
+                    Some(len) => {
+                        console_print!("\u{fffd}");
+                        data = &rest[len..];
+                    }
+                }
+            }
+        }
+    }
+}
+
+const SYSCALLS_SPEC: CommandSpec = CommandSpec {
+    name: "syscalls",
+    options: &[args::OptSpec::flag('H', "header")],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+pub fn cmd_syscalls(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&SYSCALLS_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    if parsed.has("header") {
+        syscall::sys_print_c_header();
+        Ok(())
+    } else {
+        syscall::sys_show_categories()
+    }
+}
+
+pub fn cmd_version() -> Result<(), &'static str> {
+    let result = syscall::syscall_handler(
+        syscall::SYS_ELINOS_VERSION,
+        0,
+        0,
+        0,
+        0,
+    );
+    
+    match result {
+        syscall::SysCallResult::Success(_) => Ok(()),
+        syscall::SysCallResult::Error(_) => Err("Syscall failed"),
     }
 }
 
 
 pub fn cmd_shutdown() -> Result<(), &'static str> {
     let result = syscall::syscall_handler(
-        syscall::elinos::SYS_ELINOS_SHUTDOWN,
+        syscall::SYS_ELINOS_SHUTDOWN,
         0,
         0,
         0,
@@ -581,7 +1202,7 @@ pub fn cmd_shutdown() -> Result<(), &'static str> {
 
 pub fn cmd_reboot() -> Result<(), &'static str> {
     let result = syscall::syscall_handler(
-        syscall::elinos::SYS_ELINOS_REBOOT,
+        syscall::SYS_ELINOS_REBOOT,
         0,
         0,
         0,
@@ -600,6 +1221,232 @@ pub fn cmd_echo(message: &str) -> Result<(), &'static str> {
     Ok(())
 }
 
+/// Check a condition, for `&&`, `||` and scripts: the status is 0 if it
+/// holds and 1 if not, 2 if it cannot be read. `!` in front negates it.
+pub fn cmd_test(args: &str) -> Result<(), &'static str> {
+    let mut words: Vec<&str, 4> = Vec::new();
+    for word in args.split_whitespace() {
+        words.push(word).map_err(|_| {
+            set_command_status(2);
+            "test: too many arguments"
+        })?;
+    }
+    let negate = words.first() == Some(&"!");
+    let words = if negate { &words[1..] } else { &words[..] };
+    
+    let holds = evaluate_test(words).inspect_err(|_| set_command_status(2))?;
+    if holds == negate {
+        set_command_status(1);
+    }
+    Ok(())
+}
+
+/// Whether the condition in `words` holds
+fn evaluate_test(words: &[&str]) -> Result<bool, &'static str> {
+    let number = |word: &str| word.parse::<i64>().map_err(|_| "test: integer expected");
+    match *words {
+        [] => Ok(false),
+        [word] => Ok(!word.is_empty()),
+        ["-n", word] => Ok(!word.is_empty()),
+        ["-z", word] => Ok(word.is_empty()),
+        [test @ ("-e" | "-f" | "-d" | "-L" | "-s"), path_arg] => {
+            let full_path = resolve_path(path_arg)?;
+            let Ok(stat) = crate::filesystem::stat(&full_path, test != "-L") else {
+                return Ok(false);
+            };
+            Ok(match test {
+                "-f" => stat.is_file(),
+                "-d" => stat.is_directory(),
+                "-L" => stat.is_symlink(),
+                "-s" => stat.size > 0,
+                _ => true,
+            })
+        }
+        [left, "=", right] => Ok(left == right),
+        [left, "!=", right] => Ok(left != right),
+        [left, operator @ ("-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge"), right] => {
+            let (left, right) = (number(left)?, number(right)?);
+            Ok(match operator {
+                "-eq" => left == right,
+                "-ne" => left != right,
+                "-lt" => left < right,
+                "-le" => left <= right,
+                "-gt" => left > right,
+                _ => left >= right,
+            })
+        }
+        _ => Err("test: unknown condition"),
+    }
+}
+
+/// Sleep for a number of seconds, which may have a fraction (`sleep 0.25`)
+pub fn cmd_sleep(args: &str) -> Result<(), &'static str> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Err("Usage: sleep <seconds>");
+    }
+    let (whole, fraction) = args.split_once('.').unwrap_or((args, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("Invalid number of seconds");
+    }
+    let seconds: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| "Invalid number of seconds")? };
+    let nanos = fraction.bytes().chain(core::iter::repeat(b'0')).take(9)
+        .fold(0i64, |nanos, digit| nanos * 10 + (digit - b'0') as i64);
+    let request = syscall::Timespec { tv_sec: seconds, tv_nsec: nanos };
+
+    let result = syscall::syscall_handler(
+        syscall::SYS_NANOSLEEP,
+        &request as *const syscall::Timespec as usize,
+        0,
+        0,
+        0,
+    );
+
+    match result {
+        syscall::SysCallResult::Success(_) => Ok(()),
+        syscall::SysCallResult::Error(_) => Err("Invalid number of seconds"),
+    }
+}
+
+/// Show the time since boot
+pub fn cmd_uptime() -> Result<(), &'static str> {
+    let mut now = syscall::Timespec { tv_sec: 0, tv_nsec: 0 };
+    let result = syscall::syscall_handler(
+        syscall::SYS_CLOCK_GETTIME,
+        syscall::CLOCK_MONOTONIC,
+        &mut now as *mut syscall::Timespec as usize,
+        0,
+        0,
+    );
+    if result.is_error() {
+        return Err("Syscall failed");
+    }
+
+    let seconds = now.tv_sec;
+    console_println!("up {}:{:02}:{:02}.{:03}, {} ticks at {} Hz",
+        seconds / 3600, seconds / 60 % 60, seconds % 60, now.tv_nsec / 1_000_000,
+        crate::trap::tick_count(), crate::trap::TICK_HZ);
+    Ok(())
+}
+
+/// Queue a command to run later, list the queue (no arguments) or take a
+/// job off it (`-d ID`). Everything after the time is the command, options
+/// included, so it is not parsed as arguments to `at`.
+fn cmd_at(args: &str) -> Result<(), &'static str> {
+    use crate::cron;
+
+    let args = args.trim();
+    if args.is_empty() {
+        let jobs = cron::at_jobs();
+        if jobs.is_empty() {
+            console_println!("No jobs queued");
+        }
+        for job in &jobs {
+            console_println!("{:>4}  in {:>6}s  {}", job.id, cron::seconds_until(job.due), job.command);
+        }
+        return Ok(());
+    }
+
+    let (when, command) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let command = command.trim();
+    if when == "-d" {
+        let id = command.parse::<u32>().map_err(|_| "Usage: at -d <id>")?;
+        if !cron::remove(id) {
+            return Err("No such job");
+        }
+        ok_println!("Removed job {}", id);
+        return Ok(());
+    }
+    if command.is_empty() {
+        return Err("Usage: at <+seconds|HH:MM> <command>");
+    }
+    let seconds = cron::parse_when(when)?;
+    let id = cron::at(seconds, command)?;
+    ok_println!("Job {} runs in {}s", id, seconds);
+    Ok(())
+}
+
+/// Show the periodic jobs, or read `/etc/cron.cfg` again (`reload`)
+fn cmd_cron(args: &str) -> Result<(), &'static str> {
+    use crate::cron;
+
+    match args.trim() {
+        "" => {}
+        "reload" => {
+            let count = cron::load_table();
+            ok_println!("{} periodic job(s) from {}", count, cron::CRON_TABLE_PATH);
+            return Ok(());
+        }
+        _ => return Err("Usage: cron [reload]"),
+    }
+
+    let jobs = cron::periodic_jobs();
+    if jobs.is_empty() {
+        console_println!("No periodic jobs; list them in {}", cron::CRON_TABLE_PATH);
+        return Ok(());
+    }
+    console_println!("{:>8}  {:>8}  {:>5}  {:>6}  COMMAND", "EVERY", "NEXT", "RUNS", "STATUS");
+    for job in &jobs {
+        let mut status: String<12> = String::new();
+        match job.last_status {
+            Some(code) => { let _ = write!(status, "{}", code); }
+            None => { let _ = status.push('-'); }
+        }
+        console_println!("{:>7}s  {:>7}s  {:>5}  {:>6}  {}",
+            job.interval_secs, cron::seconds_until(job.due), job.runs, status, job.command);
+    }
+    Ok(())
+}
+
+/// Show the date and time in UTC. `+FORMAT` picks the fields: %Y %m %d
+/// %H %M %S, %F (%Y-%m-%d), %T (%H:%M:%S), %s (seconds since the epoch)
+/// and %%.
+pub fn cmd_date(args: &str) -> Result<(), &'static str> {
+    let format = match args.trim() {
+        "" => "%F %T UTC",
+        format => format.strip_prefix('+').ok_or("Usage: date [+FORMAT]")?,
+    };
+
+    let mut now = syscall::Timeval { tv_sec: 0, tv_usec: 0 };
+    let result = syscall::syscall_handler(
+        syscall::SYS_GETTIMEOFDAY,
+        &mut now as *mut syscall::Timeval as usize,
+        0,
+        0,
+        0,
+    );
+    if result.is_error() {
+        return Err("Syscall failed");
+    }
+    if elinos_common::rtc::unix_time().is_none() {
+        warn_println!("RTC not set, counting from boot");
+    }
+
+    let seconds = now.tv_sec as u64;
+    let date = elinos_common::rtc::DateTime::from_unix(seconds);
+    let mut text: String<128> = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let _ = match (c, if c == '%' { chars.next() } else { None }) {
+            ('%', Some('Y')) => write!(text, "{:04}", date.year),
+            ('%', Some('m')) => write!(text, "{:02}", date.month),
+            ('%', Some('d')) => write!(text, "{:02}", date.day),
+            ('%', Some('H')) => write!(text, "{:02}", date.hour),
+            ('%', Some('M')) => write!(text, "{:02}", date.minute),
+            ('%', Some('S')) => write!(text, "{:02}", date.second),
+            ('%', Some('F')) => write!(text, "{:04}-{:02}-{:02}", date.year, date.month, date.day),
+            ('%', Some('T')) => write!(text, "{:02}:{:02}:{:02}", date.hour, date.minute, date.second),
+            ('%', Some('s')) => write!(text, "{}", seconds),
+            ('%', Some('%')) => write!(text, "%"),
+            ('%', Some(other)) => write!(text, "%{}", other),
+            ('%', None) => write!(text, "%"),
+            (c, _) => write!(text, "{}", c),
+        };
+    }
+    console_println!("{}", text);
+    Ok(())
+}
+
 pub fn cmd_fscheck() -> Result<(), &'static str> {
     match crate::filesystem::check_filesystem() {
         Ok(()) => Ok(()),
@@ -610,16 +1457,606 @@ pub fn cmd_fscheck() -> Result<(), &'static str> {
     }
 }
 
-fn cmd_pwd() -> Result<(), &'static str> {
-    ensure_cwd_initialized();
-    unsafe {
-        console_println!("{}", CURRENT_PATH);
+const ABITEST_SPEC: CommandSpec = CommandSpec {
+    name: "abitest",
+    options: &[],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+fn cmd_abitest(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&ABITEST_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    
+    let file = parsed.arg(0).map(resolve_path).transpose()?;
+    let summary = syscall::abitest::run(file.as_deref());
+    if summary.failed > 0 {
+        set_command_status(1);
+    }
+    Ok(())
+}
+
+/// Run the crypto known-answer tests
+fn cmd_selftest() -> Result<(), &'static str> {
+    info_println!("Crypto known-answer tests");
+    let checks = elinos_common::crypto::selftest::run();
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    for check in checks.iter() {
+        if check.passed {
+            ok_println!("{}", check.name);
+        } else {
+            err_println!("{}: wrong result", check.name);
+        }
+    }
+    console_println!("{} passed, {} failed", checks.len() - failed, failed);
+    if failed > 0 {
+        set_command_status(1);
+    }
+    Ok(())
+}
+
+fn cmd_sync() -> Result<(), &'static str> {
+    crate::klog::flush_to_disk().map_err(|_| "Failed to write the kernel log")?;
+    crate::filesystem::append::flush_all().map_err(|_| "Failed to write pending appends")?;
+    crate::filesystem::cache::flush().map_err(|_| "Failed to write cached data to disk")
+}
+
+const MOUNT_SPEC: CommandSpec = CommandSpec {
+    name: "mount",
+    options: &[args::OptSpec::flag('r', "read-only"), args::OptSpec::flag('b', "backup-superblock")],
+    min_positional: 0,
+    max_positional: 2,
+};
+
+fn cmd_mount(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&MOUNT_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let name = match parsed.arg(0) {
+        Some(name) => name,
+        None => {
+            let fs = crate::filesystem::FILESYSTEM.lock();
+            match fs.volume() {
+                Some(volume) => console_println!("{} mounted on /", volume),
+                None => console_println!("No filesystem mounted"),
+            }
+            for mount in fs.mounts() {
+                console_println!("{} mounted on {} ({})",
+                    mount.volume, mount.path, if mount.read_only { "ro" } else { "rw" });
+            }
+            return Ok(());
+        }
+    };
+
+    let volume = crate::filesystem::find_volume(name).ok_or("No such disk, partition, label or UUID")?;
+    if let Some(dir) = parsed.arg(1) {
+        let path = resolve_path(dir)?;
+        let options = crate::filesystem::MountOptions {
+            read_only: parsed.has("read-only"),
+            backup_superblock: parsed.has("backup-superblock"),
+        };
+        return match crate::filesystem::mount_at(volume, &path, options) {
+            Ok(()) => Ok(()),
+            Err(FilesystemError::FileAlreadyExists) => Err("Device or directory already mounted"),
+            Err(FilesystemError::FileNotFound) => Err("Mount directory not found"),
+            Err(_) => Err("Failed to mount block device"),
+        };
+    }
+    if parsed.has("read-only") {
+        return Err("The root filesystem cannot be mounted read-only");
+    }
+    crate::filesystem::mount_volume(volume, parsed.has("backup-superblock"))
+        .map_err(|_| "Failed to mount block device")?;
+
+    // The old working directory may not exist on the new filesystem
+    crate::process::set_current_cwd("/");
+    Ok(())
+}
+
+const PARTS_SPEC: CommandSpec = CommandSpec {
+    name: "parts",
+    options: &[],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+fn cmd_parts(args: &str) -> Result<(), &'static str> {
+    use crate::virtio::block::partition;
+
+    let parsed = match args::parse_command(&PARTS_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let devices = match parsed.arg(0) {
+        Some(name) => {
+            let index = crate::virtio::parse_device_name(name).ok_or("Unknown block device")?;
+            index..index + 1
+        }
+        None => 0..partition::MAX_DISKS,
+    };
+
+    for index in devices {
+        let Some(capacity) = partition::capacity(index) else { continue };
+        let disk = crate::virtio::Volume::disk(index);
+        let table = match partition::partition_table(index) {
+            Some(table) => table,
+            None => {
+                console_println!("{}: {} MB, no partition table", disk, capacity / 2048);
+                print_volume_id(disk);
+                continue;
+            }
+        };
+
+        console_println!("{}: {} MB, {}, {} partitions",
+            disk, capacity / 2048, table.scheme.name(), table.partitions.len());
+        for part in &table.partitions {
+            let volume = crate::virtio::Volume { device: index, partition: part.number };
+            console_println!("  {:<8} start {:>10}  {:>6} MB  {} {}",
+                volume, part.start, part.sectors / 2048, part.kind.description(), part.name);
+            print_volume_id(volume);
+        }
+    }
+    Ok(())
+}
+
+const USB_SPEC: CommandSpec = CommandSpec {
+    name: "usb",
+    options: &[],
+    min_positional: 0,
+    max_positional: 3,
+};
+
+/// List the USB controllers, devices and disks, or dump a sector of a
+/// disk (`read usbN SECTOR`)
+fn cmd_usb(args: &str) -> Result<(), &'static str> {
+    use crate::usb;
+
+    let parsed = match args::parse_command(&USB_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    match (parsed.arg(0), parsed.arg(1), parsed.arg(2)) {
+        (None, _, _) => {}
+        (Some("read"), Some(disk), Some(sector)) => {
+            let index = disk.strip_prefix("usb").and_then(|index| index.parse::<usize>().ok())
+                .ok_or("Unknown USB disk")?;
+            let sector = parse_number(sector).ok_or("Invalid sector number")? as u64;
+            let mut buffer = [0u8; 512];
+            usb::storage::with_disk(index, |disk| disk.read_sector(sector, &mut buffer))
+                .map_err(|_| "Unknown USB disk")?
+                .map_err(|_| "Read failed")?;
+            print_dump(sector as usize * 512, &buffer, 1);
+            return Ok(());
+        }
+        _ => return Err("Usage: usb [read usbN SECTOR]"),
+    }
+
+    let controllers = usb::controllers();
+    if controllers.is_empty() {
+        console_println!("No USB host controller in the device tree");
+        return Ok(());
+    }
+    for (name, base, ports) in &controllers {
+        console_println!("{} at 0x{:x}, {} ports", name, base, ports);
+    }
+    for device in usb::devices() {
+        console_println!("  port {} address {}: {:04x}:{:04x} class {:02x}, {}",
+            device.port, device.address, device.vendor, device.product, device.class, device.speed.name());
+    }
+    for (index, (model, sectors, table)) in usb::storage::disks().iter().enumerate() {
+        console_println!("usb{}: {}, {}", index, model, format_sectors(*sectors));
+        for part in table.iter().flat_map(|table| table.partitions.iter()) {
+            console_println!("  usb{}p{:<3} start {:>10}  {:>6} MB  {} {}",
+                index, part.number, part.start, part.sectors / 2048, part.kind.description(), part.name);
+        }
+    }
+    Ok(())
+}
+
+const MEMDEBUG_SPEC: CommandSpec = CommandSpec {
+    name: "memdebug",
+    options: &[args::OptSpec::flag('a', "all")],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// Show live allocations by caller, and with `-a` each one
+fn cmd_memdebug(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&MEMDEBUG_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    if !crate::memory::memdebug::show(parsed.has("all")) {
+        console_println!("Allocation tracking is off; turn it on with sysctl vm.alloc_tracking=on");
+    }
+    Ok(())
+}
+
+const KCONFIG_SPEC: CommandSpec = CommandSpec {
+    name: "kconfig",
+    options: &[],
+    min_positional: 1,
+    max_positional: 2,
+};
+
+/// Show the effective configuration, or save it to a file
+fn cmd_kconfig(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&KCONFIG_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    match (parsed.arg(0), parsed.arg(1)) {
+        (Some("dump"), None) => crate::kconfig::print().map_err(|_| "Configuration report failed"),
+        (Some("dump"), Some(path)) => {
+            let path = resolve_path(path)?;
+            crate::kconfig::save(&path).map_err(|_| "Failed to write file")?;
+            console_println!("Configuration written to {}", path);
+            Ok(())
+        }
+        _ => Err("Usage: kconfig dump [FILE]"),
+    }
+}
+
+const BEEP_SPEC: CommandSpec = CommandSpec {
+    name: "beep",
+    options: &[args::OptSpec::value('f', "frequency"), args::OptSpec::value('l', "length")],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// Play a tone: `-f HZ` (default 440) for `-l MS` (default 200)
+fn cmd_beep(args: &str) -> Result<(), &'static str> {
+    use crate::sound;
+
+    let parsed = match args::parse_command(&BEEP_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let frequency = match parsed.value("frequency") {
+        Some(hz) => hz.parse::<u32>().ok()
+            .filter(|hz| (sound::MIN_FREQUENCY..=sound::MAX_FREQUENCY).contains(hz))
+            .ok_or("Frequency must be 20 to 20000 Hz")?,
+        None => 440,
+    };
+    let length = match parsed.value("length") {
+        Some(ms) => ms.parse::<u32>().ok()
+            .filter(|&ms| ms <= sound::MAX_DURATION_MS)
+            .ok_or("Length must be 0 to 10000 ms")?,
+        None => 200,
+    };
+    let output = sound::beep(frequency, length);
+    console_println!("{} Hz for {} ms on the {}", frequency, length, output.name());
+    Ok(())
+}
+
+/// Print the filesystem type, label and UUID of `volume`, if it has a
+/// filesystem
+fn print_volume_id(volume: crate::virtio::Volume) {
+    if let Some(id) = crate::filesystem::probe_volume(volume) {
+        console_println!("           {} LABEL=\"{}\" UUID={}",
+            id.fs_type, id.label.as_deref().unwrap_or(""), id.uuid);
+    }
+}
+
+const LSBLK_SPEC: CommandSpec = CommandSpec {
+    name: "lsblk",
+    options: &[],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+/// A size in sectors as a short human-readable string, e.g. "64.0M"
+fn format_sectors(sectors: u64) -> String<16> {
+    let mut out = String::new();
+    let bytes = sectors * 512;
+    let units = [(1u64 << 40, 'T'), (1 << 30, 'G'), (1 << 20, 'M'), (1 << 10, 'K')];
+    match units.iter().find(|&&(unit, _)| bytes >= unit) {
+        Some(&(unit, suffix)) => {
+            let tenths = bytes * 10 / unit;
+            let _ = write!(out, "{}.{}{}", tenths / 10, tenths % 10, suffix);
+        }
+        None => {
+            let _ = write!(out, "{}B", bytes);
+        }
+    }
+    out
+}
+
+fn cmd_lsblk(args: &str) -> Result<(), &'static str> {
+    use crate::virtio::{Volume, block::partition};
+
+    let parsed = match args::parse_command(&LSBLK_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let devices = match parsed.arg(0) {
+        Some(name) => {
+            let index = crate::virtio::parse_device_name(name).ok_or("Unknown block device")?;
+            index..index + 1
+        }
+        None => 0..partition::MAX_DISKS,
+    };
+
+    // Where each volume is mounted
+    let mut mountpoints: Vec<(Volume, String<64>), { crate::filesystem::MAX_MOUNTS + 1 }> = Vec::new();
+    {
+        let fs = crate::filesystem::FILESYSTEM.lock();
+        if let Some(root) = fs.volume() {
+            let _ = mountpoints.push((root, String::try_from("/").unwrap_or_default()));
+        }
+        for mount in fs.mounts() {
+            let _ = mountpoints.push((mount.volume, mount.path.clone()));
+        }
+    }
+
+    let print_row = |name: &str, volume: Volume, sectors: u64, kind: &str, state: &str| {
+        let id = crate::filesystem::probe_volume(volume);
+        let mountpoint = mountpoints.iter()
+            .find(|(mounted, _)| *mounted == volume)
+            .map_or("", |(_, path)| path.as_str());
+        let (fs_type, label, uuid) = match &id {
+            Some(id) => {
+                let mut fs_type = String::<8>::new();
+                let _ = write!(fs_type, "{}", id.fs_type);
+                (fs_type, id.label.as_deref().unwrap_or(""), id.uuid.as_str())
+            }
+            None => (String::new(), "", ""),
+        };
+        console_println!("{:<11} {:>7} {:<4} {:<8} {:<6} {:<16} {:<36} {}",
+            name, format_sectors(sectors), kind, state, fs_type, label, uuid, mountpoint);
+    };
+
+    console_println!("{:<11} {:>7} {:<4} {:<8} {:<6} {:<16} {:<36} {}",
+        "NAME", "SIZE", "TYPE", "STATE", "FSTYPE", "LABEL", "UUID", "MOUNTPOINT");
+    for index in devices {
+        let Some(capacity) = partition::capacity(index) else { continue };
+        // USB disks keep no health counters
+        let mut state_name = String::<8>::new();
+        match crate::virtio::block_device(index) {
+            Some(device) => {
+                let _ = write!(state_name, "{}", device.lock().health().state());
+            }
+            None => {
+                let _ = state_name.push_str("ok");
+            }
+        }
+
+        let disk = Volume::disk(index);
+        let mut name = String::<16>::new();
+        let _ = write!(name, "{}", disk);
+        print_row(&name, disk, capacity, "disk", &state_name);
+
+        let table = match partition::partition_table(index) {
+            Some(table) => table,
+            None => continue,
+        };
+        for (i, part) in table.partitions.iter().enumerate() {
+            let volume = Volume { device: index, partition: part.number };
+            let branch = if i + 1 == table.partitions.len() { "└─" } else { "├─" };
+            name.clear();
+            let _ = write!(name, "{}{}", branch, volume);
+            print_row(&name, volume, part.sectors, "part", "");
+        }
+    }
+    Ok(())
+}
+
+/// Show the error and event counters, traps by cause and what needs a look
+fn cmd_health() -> Result<(), &'static str> {
+    let mut summary = String::<2048>::new();
+    crate::health::write_summary(&mut summary).map_err(|_| "Health summary too long")?;
+    console_print!("{}", summary);
+    Ok(())
+}
+
+/// Show the traps taken by cause and how exceptions ended
+fn cmd_traps() -> Result<(), &'static str> {
+    let mut table = String::<4096>::new();
+    crate::health::write_traps(&mut table).map_err(|_| "Trap table too long")?;
+    console_print!("{}", table);
+    Ok(())
+}
+
+/// Show how long each boot stage took
+fn cmd_bootchart() -> Result<(), &'static str> {
+    let mut report = String::<4096>::new();
+    crate::bootchart::write_report(&mut report).map_err(|_| "Boot report too long")?;
+    console_print!("{}", report);
+    Ok(())
+}
+
+/// Show the I/O counters, error counts and state of each block device
+fn cmd_diskstats() -> Result<(), &'static str> {
+    console_println!("{:<6} {:>10} {:>10} {:>7} {:>7} {:>7} {}",
+        "DEVICE", "READS", "WRITES", "RD_ERR", "WR_ERR", "RETRY", "STATE");
+    let mut policy = None;
+    for index in 0..crate::virtio::MAX_BLOCK_DEVICES {
+        let device = match crate::virtio::block_device(index) {
+            Some(device) => device.lock(),
+            None => continue,
+        };
+        if !device.is_initialized() {
+            continue;
+        }
+        let health = device.health();
+        console_println!("blk{:<3} {:>10} {:>10} {:>7} {:>7} {:>7} {}",
+            index, health.reads, health.writes, health.read_errors, health.write_errors,
+            health.retries, health.state());
+        policy = Some(device.retry_policy());
+    }
+
+    match policy {
+        Some(policy) => console_println!("Retry policy: {} retries, backoff from {} spins, offline after {} failed requests in a row",
+            policy.retries, policy.backoff_spins, crate::virtio::block::health::OFFLINE_AFTER_FAILURES),
+        None => console_println!("No block devices"),
+    }
+    Ok(())
+}
+
+const FSCK_SPEC: CommandSpec = CommandSpec {
+    name: "fsck",
+    options: &[args::OptSpec::flag('r', "restore")],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+fn cmd_fsck(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&FSCK_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let name = parsed.arg(0).unwrap_or_default();
+    let volume = crate::filesystem::find_volume(name).ok_or("No such disk, partition, label or UUID")?;
+    match crate::filesystem::check_superblocks(volume, parsed.has("restore")) {
+        Ok(()) => Ok(()),
+        Err(FilesystemError::Busy) => Err("Unmount the filesystem before restoring its superblock"),
+        Err(FilesystemError::InvalidSuperblock) => Err("No valid backup superblock to restore from"),
+        Err(_) => Err("Superblock check failed"),
+    }
+}
+
+const UMOUNT_SPEC: CommandSpec = CommandSpec {
+    name: "umount",
+    options: &[],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+fn cmd_umount(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&UMOUNT_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let path = resolve_path(parsed.arg(0).unwrap_or_default())?;
+    match crate::filesystem::unmount(&path) {
+        Ok(()) => {}
+        Err(FilesystemError::NotMounted) => return Err("Nothing is mounted there"),
+        Err(FilesystemError::DirectoryNotEmpty) => return Err("Another filesystem is mounted inside it"),
+        Err(FilesystemError::Busy) => return Err("Swap is on it"),
+        Err(_) => return Err("Failed to unmount"),
+    }
+
+    // Leave a working directory that was on the unmounted filesystem
+    let cwd = crate::process::current_cwd();
+    let inside = cwd.strip_prefix(path.as_str())
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    if inside {
+        crate::process::set_current_cwd("/");
     }
     Ok(())
 }
 
+const SWAPON_SPEC: CommandSpec = CommandSpec {
+    name: "swapon",
+    options: &[args::OptSpec::value('c', "create")],
+    min_positional: 0,
+    max_positional: 1,
+};
+
+/// Start swapping to a file or volume, or show the swap area and counters
+fn cmd_swapon(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&SWAPON_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let Some(target) = parsed.arg(0) else {
+        show_swap();
+        return Ok(());
+    };
+
+    let is_volume = crate::virtio::parse_volume_name(target).is_some()
+        || target.starts_with("LABEL=") || target.starts_with("UUID=");
+    let pages = if is_volume {
+        if parsed.value("create").is_some() {
+            return Err("Only a swap file can be created");
+        }
+        let volume = crate::filesystem::find_volume(target).ok_or("No such disk, partition, label or UUID")?;
+        crate::memory::swap::swapon_volume(volume)?
+    } else {
+        let path = resolve_path(target)?;
+        if let Some(kb) = parsed.value("create") {
+            let kb = kb.parse::<usize>().ok().filter(|&kb| kb > 0).ok_or("Invalid size, expected KB")?;
+            create_swap_file(&path, kb * 1024)?;
+        }
+        crate::memory::swap::swapon_file(&path)?
+    };
+    ok_println!("Swap on {}: {} KB", target, pages * crate::memory::mmu::PAGE_SIZE / 1024);
+    Ok(())
+}
+
+/// Create `path` as a file of `size` bytes of zeros, every block allocated
+fn create_swap_file(path: &str, size: usize) -> Result<(), &'static str> {
+    if crate::filesystem::file_exists(path) {
+        return Err("File already exists");
+    }
+    crate::filesystem::write_file(path, "").map_err(|_| "Failed to create swap file")?;
+    let zeros = [0u8; 4096];
+    let mut offset = 0;
+    while offset < size {
+        let len = zeros.len().min(size - offset);
+        crate::filesystem::write_file_at(path, offset, &zeros[..len]).map_err(|_| "Failed to fill swap file")?;
+        offset += len;
+    }
+    Ok(())
+}
+
+fn show_swap() {
+    let stats = crate::memory::swap::stats();
+    let kb = |pages: usize| pages * crate::memory::mmu::PAGE_SIZE / 1024;
+    match (&stats.name, &stats.kind) {
+        (Some(name), Some(kind)) => {
+            console_println!("{:<24} {:<10} {:>8} {:>8}", "Name", "Type", "Size", "Used");
+            console_println!("{:<24} {:<10} {:>7}K {:>7}K", name, kind.name(), kb(stats.pages), kb(stats.used));
+        }
+        _ => console_println!("Swap is off"),
+    }
+    console_println!("Swapped out: {}  Swapped in: {}  Reclaims: {} ({} found nothing)",
+        stats.swap_outs, stats.swap_ins, stats.reclaims, stats.reclaim_failures);
+}
+
+const SWAPOFF_SPEC: CommandSpec = CommandSpec {
+    name: "swapoff",
+    options: &[],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// Read every swapped out page back in and stop swapping
+fn cmd_swapoff(args: &str) -> Result<(), &'static str> {
+    if args::parse_command(&SWAPOFF_SPEC, args)?.is_none() {
+        return Ok(());
+    }
+    crate::memory::swap::swapoff()?;
+    ok_println!("Swap off");
+    Ok(())
+}
+
+fn cmd_pwd() -> Result<(), &'static str> {
+    console_println!("{}", crate::process::current_cwd());
+    Ok(())
+}
+
+/// Create `path` empty, or if it is there, set its access and modification
+/// times to now
 fn cmd_touch(path: &str) -> Result<(), &'static str> {
-    match crate::filesystem::FILESYSTEM.lock().create_file(path) {
+    let mut fs = crate::filesystem::FILESYSTEM.lock();
+    if fs.file_exists(path) {
+        let now = crate::syscall::time::clock_ns(crate::syscall::time::CLOCK_REALTIME).unwrap_or(0) / 1_000_000_000;
+        return fs.set_times(path, Some(now), Some(now)).map_err(|e| {
+            print_filesystem_error(&e);
+            "Failed to update file times"
+        });
+    }
+    match fs.create_file(path) {
         Ok(entry) => {
             console_println!("Created file '{}' at path '{}'.", entry.name, path);
             Ok(())
@@ -645,9 +2082,10 @@ fn cmd_mkdir(path: &str) -> Result<(), &'static str> {
 }
 
 fn cmd_rm(path: &str) -> Result<(), &'static str> { // For files
+    crate::filesystem::append::discard_file(path);
     match crate::filesystem::FILESYSTEM.lock().delete_file(path) {
         Ok(()) => {
-            console_println!("[o] Removed file '{}'.", path);
+            ok_println!("Removed file '{}'.", path);
             Ok(())
         }
         Err(e) => {
@@ -657,10 +2095,213 @@ fn cmd_rm(path: &str) -> Result<(), &'static str> { // For files
     }
 }
 
+const LN_SPEC: CommandSpec = CommandSpec {
+    name: "ln",
+    options: &[args::OptSpec::flag('s', "symbolic")],
+    min_positional: 2,
+    max_positional: 2,
+};
+
+/// `ln [-s] TARGET LINK`. A symbolic link's target is stored as typed, so
+/// a relative one is taken from the link's directory when it is followed;
+/// a hard link's is resolved now.
+fn cmd_ln(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&LN_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let target = parsed.arg(0).unwrap_or_default();
+    let link = resolve_path(parsed.arg(1).unwrap_or_default())?;
+    let result = if parsed.has("symbolic") {
+        crate::filesystem::create_symlink(&link, target)
+    } else {
+        crate::filesystem::link(&resolve_path(target)?, &link)
+    };
+    match result {
+        Ok(()) => {
+            ok_println!("Linked '{}' -> '{}'.", link, target);
+            Ok(())
+        }
+        Err(FilesystemError::FileNotFound) => Err("No such file"),
+        Err(FilesystemError::FileAlreadyExists) => Err("File exists"),
+        Err(FilesystemError::IsADirectory) => Err("Hard links to directories are not allowed"),
+        Err(FilesystemError::InvalidPath) => Err("Hard links cannot cross filesystems"),
+        Err(FilesystemError::ReadOnly) => Err("Read-only filesystem"),
+        Err(e) => {
+            print_filesystem_error(&e);
+            Err("Failed to create link")
+        }
+    }
+}
+
+const MV_SPEC: CommandSpec = CommandSpec {
+    name: "mv",
+    options: &[],
+    min_positional: 2,
+    max_positional: 2,
+};
+
+/// `mv FROM TO`: a rename, so no data is copied. An existing directory
+/// as TO takes FROM under its own name.
+fn cmd_mv(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&MV_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let from = resolve_path(parsed.arg(0).unwrap_or_default())?;
+    let mut to = resolve_path(parsed.arg(1).unwrap_or_default())?;
+    let into_dir = crate::filesystem::FILESYSTEM.lock().get_file_entry(&to)
+        .is_ok_and(|entry| entry.is_directory);
+    if into_dir {
+        to = path::join(&to, path::split_parent(&from).1).map_err(|_| "Path too long")?;
+    }
+
+    match crate::filesystem::rename(&from, &to) {
+        Ok(()) => {
+            ok_println!("Moved '{}' to '{}'.", from, to);
+            Ok(())
+        }
+        Err(FilesystemError::FileNotFound) => Err("No such file or directory"),
+        Err(FilesystemError::InvalidPath) => Err("Cannot move across filesystems or into itself"),
+        Err(FilesystemError::DirectoryNotEmpty) => Err("Directory not empty"),
+        Err(FilesystemError::Busy) => Err("Device or resource busy"),
+        Err(FilesystemError::ReadOnly) => Err("Read-only filesystem"),
+        Err(e) => {
+            print_filesystem_error(&e);
+            Err("Failed to move")
+        }
+    }
+}
+
+const SHRED_SPEC: CommandSpec = CommandSpec {
+    name: "shred",
+    options: &[args::OptSpec::value('n', "iterations")],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+const WIPEFREE_SPEC: CommandSpec = CommandSpec {
+    name: "wipefree",
+    options: &[args::OptSpec::value('n', "iterations")],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+/// Passes asked for with `-n`, or the default
+fn wipe_passes(parsed: &args::ParsedArgs) -> Result<usize, &'static str> {
+    use crate::filesystem::wipe::{DEFAULT_PASSES, MAX_PASSES};
+    match parsed.value("iterations") {
+        Some(passes) => passes.parse::<usize>().ok()
+            .filter(|passes| (1..=MAX_PASSES).contains(passes))
+            .ok_or("Invalid number of passes"),
+        None => Ok(DEFAULT_PASSES),
+    }
+}
+
+fn print_wipe_report(what: &str, report: &crate::filesystem::wipe::WipeReport) {
+    let kib = report.sectors * 512 / 1024;
+    ok_println!("{}: {} passes over {} KB", what, report.passes, kib);
+    if report.discarded {
+        console_println!("Blocks discarded on the device");
+    } else if report.sectors > 0 {
+        console_println!("Device cannot discard; blocks were only overwritten");
+    }
+}
+
+fn cmd_shred(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&SHRED_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let passes = wipe_passes(&parsed)?;
+
+    let path = resolve_path(parsed.arg(0).unwrap_or_default())?;
+    match crate::filesystem::shred_file(&path, passes) {
+        Ok(report) => {
+            print_wipe_report(&path, &report);
+            Ok(())
+        }
+        Err(FilesystemError::FileNotFound) => Err("No such file"),
+        Err(FilesystemError::IsADirectory) => Err("Is a directory"),
+        Err(FilesystemError::ReadOnly) => Err("Read-only filesystem"),
+        Err(e) => {
+            print_filesystem_error(&e);
+            Err("Failed to shred file")
+        }
+    }
+}
+
+fn cmd_wipefree(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&WIPEFREE_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let passes = wipe_passes(&parsed)?;
+
+    let path = resolve_path(parsed.arg(0).unwrap_or_default())?;
+    info_println!("Overwriting free space of the filesystem at {}...", path);
+    match crate::filesystem::wipe_free_space(&path, passes) {
+        Ok(report) => {
+            print_wipe_report("Free space", &report);
+            Ok(())
+        }
+        Err(FilesystemError::ReadOnly) => Err("Read-only filesystem"),
+        Err(FilesystemError::NotMounted) => Err("No filesystem mounted there"),
+        Err(e) => {
+            print_filesystem_error(&e);
+            Err("Failed to wipe free space")
+        }
+    }
+}
+
+const UPDATE_SPEC: CommandSpec = CommandSpec {
+    name: "update",
+    options: &[],
+    min_positional: 1,
+    max_positional: args::MAX_ARGS,
+};
+
+fn cmd_update(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&UPDATE_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+
+    let mut paths: heapless::Vec<(PathBuf, PathBuf), { args::MAX_ARGS }> = heapless::Vec::new();
+    for pair in parsed.positional() {
+        let (dest, src) = pair.split_once('=').ok_or("Expected DEST=SRC")?;
+        if dest.is_empty() || src.is_empty() {
+            return Err("Expected DEST=SRC");
+        }
+        let _ = paths.push((resolve_path(dest)?, resolve_path(src)?));
+    }
+
+    let mut transaction = crate::filesystem::transaction::Transaction::new();
+    for (dest, src) in &paths {
+        if let Err(e) = transaction.copy(dest, src) {
+            print_filesystem_error(&e);
+            return Err("Cannot update that file");
+        }
+    }
+    let count = transaction.len();
+    match transaction.commit() {
+        Ok(()) => {
+            ok_println!("Replaced {} file(s)", count);
+            Ok(())
+        }
+        Err(e) => {
+            print_filesystem_error(&e);
+            Err("Update failed, no file was changed")
+        }
+    }
+}
+
 fn cmd_rmdir(path: &str) -> Result<(), &'static str> { // For directories
     match crate::filesystem::FILESYSTEM.lock().delete_directory(path) {
         Ok(()) => {
-            console_println!("[o] Removed directory '{}'.", path);
+            ok_println!("Removed directory '{}'.", path);
             Ok(())
         }
         Err(e) => {
@@ -671,18 +2312,12 @@ fn cmd_rmdir(path: &str) -> Result<(), &'static str> { // For directories
 }
 
 fn cmd_cd(path_arg: &str) -> Result<(), &'static str> {
-    let new_path_str = resolve_path(path_arg);
-    // Optimistic CD: we just set the path.
-    // Validation would ideally occur here by checking if new_path_str is a directory.
-    // For now, we update and print.
-    unsafe {
-        CURRENT_PATH.clear();
-        if CURRENT_PATH.push_str(&new_path_str).is_err() {
-            console_println!("Error: New path too long for CWD buffer.");
-            return Err("Path too long");
-        }
+    match path::change_directory(path_arg) {
+        Ok(()) => Ok(()),
+        Err(FilesystemError::NotADirectory) => Err("Not a directory"),
+        Err(FilesystemError::FilenameTooLong) => Err("Path too long"),
+        Err(_) => Err("No such directory"),
     }
-    Ok(())
 }
 
 // === ELF OPERATIONS ===
@@ -690,14 +2325,14 @@ fn cmd_cd(path_arg: &str) -> Result<(), &'static str> {
 // Removed unused function: cmd_elf_info
 
 fn cmd_elf_load(filename: &str) -> Result<(), &'static str> {
-    console_println!("[i] Loading ELF Binary: {}", filename);
+    info_println!("Loading ELF Binary: {}", filename);
     
     // Read file from filesystem
     match crate::filesystem::read_file(filename) {
         Ok(file_data) => {
             // Call ELF load syscall
             let result = syscall::syscall_handler(
-                crate::syscall::elinos::SYS_LOAD_ELF,
+                crate::syscall::SYS_LOAD_ELF,
                 file_data.as_ptr() as usize,
                 file_data.len(),
                 0,
@@ -706,101 +2341,114 @@ fn cmd_elf_load(filename: &str) -> Result<(), &'static str> {
             
             match result {
                 syscall::SysCallResult::Success(entry_point) => {
-                    console_println!("[o] ELF loaded successfully!");
+                    ok_println!("ELF loaded successfully!");
                     console_println!("   Entry point: 0x{:08x}", entry_point as u32);
                     Ok(())
                 }
                 syscall::SysCallResult::Error(_) => {
-                    console_println!("[x] ELF loading failed");
+                    err_println!("ELF loading failed");
                     Err("ELF loading failed")
                 }
             }
         }
         Err(_) => {
-            console_println!("[x] File not found: {}", filename);
+            err_println!("File not found: {}", filename);
             Err("File not found")
         }
     }
 }
 
-// Unified ELF execution function - parse, load, and execute in one step
-fn cmd_execute_elf(filename: &str, file_data: &[u8]) -> Result<(), &'static str> {
-    console_println!("[i] Executing: {}", filename);
+// Unified ELF execution function - parse, load, and execute in one step.
+// With `background`, the command as typed, the program is started as a job
+// instead of being waited for.
+fn cmd_execute_elf(filename: &str, background: Option<&str>) -> Result<(), &'static str> {
+    info_println!("Executing: {}", filename);
     
     // Handle ELF execution (like "./hello_simple")
-    if filename.starts_with("./") || filename.starts_with("/") {
-        let elf_filename = if filename.starts_with("./") {
-            &filename[2..]
-        } else {
-            &filename[1..]
+    let elf_filename = if filename.starts_with("./") {
+        &filename[2..]
+    } else if filename.starts_with("/") {
+        &filename[1..]
+    } else {
+        filename
+    };
+    
+    let loader = crate::elf::ElfLoader::new();
+    
+    // Signed binaries are verified as a whole and must run from the verified
+    // copy; without Sv39 there are no page faults to page segments in
+    let demand_paging = crate::memory::mmu::is_hardware_paging()
+        && !crate::elf::verify::is_secure_exec();
+    
+    let loaded = if demand_paging {
+        // Only the headers are read now; segments fault in from the file
+        let mut header = [0u8; 4096];
+        let read = crate::filesystem::read_file_at(elf_filename, 0, &mut header)
+            .map_err(|_| "Failed to read ELF file")?;
+        loader.load_elf_lazy(&header[..read], elf_filename)
+    } else {
+        // Signature checks need the whole file in memory
+        let elf_data = match crate::filesystem::read_elf_file(elf_filename) {
+            Ok(data) => data,
+            Err(err) => {
+                err_println!("Failed to read ELF file '{}': {}", elf_filename, err);
+                set_command_status(STATUS_NOT_EXECUTABLE);
+                return Ok(());
+            }
         };
+        info_println!("Read {} bytes from {}", elf_data.len(), elf_filename);
         
-        console_println!("[i] Executing: {}", filename);
-        
-        // Use the new ELF file reader that supports larger files
-        match crate::filesystem::read_elf_file(elf_filename) {
-            Ok(elf_data) => {
-                console_println!("[i] Read {} bytes from {}", elf_data.len(), elf_filename);
-                
-                let loader = crate::elf::ElfLoader::new();
-                
-                // Load the ELF binary
-                match loader.load_elf(&elf_data) {
-                    Ok(loaded_elf) => {
-                        console_println!("[o] ELF loaded, attempting execution...");
-                        
-                        // Execute the loaded ELF
-                        match crate::elf::execute_elf(&loaded_elf) {
-                            Ok(()) => {
-                                console_println!("[o] ELF execution completed successfully!");
-                            }
-                            Err(err) => {
-                                console_println!("[x] Execution failed: {:?}", err);
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        console_println!("[x] ELF loading failed: {:?}", err);
-                    }
-                }
-            }
+        // Enforce the secure exec policy before loading anything
+        let elf_image = match crate::elf::verify::check_exec_policy(&elf_data) {
+            Ok(image) => image,
             Err(err) => {
-                console_println!("[x] Failed to read ELF file '{}': {}", elf_filename, err);
+                err_println!("Exec denied: {}", err);
+                set_command_status(STATUS_NOT_EXECUTABLE);
+                return Err("Permission denied");
             }
-        }
-        return Ok(());
-    }
-    
-    // Execute the ELF via syscall
-    let result = syscall::syscall_handler(
-        crate::syscall::elinos::SYS_EXEC_ELF,
-        file_data.as_ptr() as usize,
-        file_data.len(),
-        0,
-        0,
-    );
+        };
+        
+        loader.load_elf(elf_image)
+    };
     
-    match result {
-        syscall::SysCallResult::Success(entry_point) => {
-            console_println!("[o] Program completed successfully!");
-            Ok(())
+    match loaded {
+        Ok(loaded_elf) if let Some(command) = background => {
+            let (id, pid) = crate::jobs::start(command, elf_filename, &loaded_elf)?;
+            console_println!("[{}] {}", id, pid);
+        }
+        Ok(loaded_elf) => {
+            ok_println!("ELF loaded, attempting execution...");
+            
+            // Run as a child of the shell and wait for it to exit
+            match crate::process::run_program(elf_filename, &loaded_elf) {
+                Ok((pid, exit_code)) => {
+                    crate::msg_println!(Msg::ProcessExited, pid, exit_code);
+                    set_command_status(exit_code);
+                }
+                Err(err) => {
+                    err_println!("Execution failed: {:?}", err);
+                    set_command_status(1);
+                }
+            }
         }
-        syscall::SysCallResult::Error(_) => {
-            console_println!("[x] Execution failed");
-            Err("Execution failed")
+        Err(err) => {
+            err_println!("ELF loading failed: {:?}", err);
+            set_command_status(STATUS_NOT_EXECUTABLE);
         }
     }
+    
+    Ok(())
 }
 
 fn cmd_elf_exec(filename: &str) -> Result<(), &'static str> {
-    console_println!("[i] Executing ELF Binary: {}", filename);
+    info_println!("Executing ELF Binary: {}", filename);
     
     // Read file from filesystem
     match crate::filesystem::read_file(filename) {
         Ok(file_data) => {
             // Call ELF exec syscall
             let result = syscall::syscall_handler(
-                crate::syscall::elinos::SYS_EXEC_ELF,
+                crate::syscall::SYS_EXEC_ELF,
                 file_data.as_ptr() as usize,
                 file_data.len(),
                 0,
@@ -809,18 +2457,18 @@ fn cmd_elf_exec(filename: &str) -> Result<(), &'static str> {
             
             match result {
                 syscall::SysCallResult::Success(entry_point) => {
-                    console_println!("[o] ELF execution completed successfully!");
+                    ok_println!("ELF execution completed successfully!");
                     console_println!("   Entry point was: 0x{:08x}", entry_point as u32);
                     Ok(())
                 }
                 syscall::SysCallResult::Error(_) => {
-                    console_println!("[x] ELF execution failed");
+                    err_println!("ELF execution failed");
                     Err("ELF execution failed")
                 }
             }
         }
         Err(_) => {
-            console_println!("[x] File not found: {}", filename);
+            err_println!("File not found: {}", filename);
             Err("File not found")
         }
     }
@@ -828,7 +2476,7 @@ fn cmd_elf_exec(filename: &str) -> Result<(), &'static str> {
 
 /// Show heap usage information
 pub fn cmd_heap() -> Result<(), &'static str> {
-    console_println!("[i] Heap Status:");
+    info_println!("Heap Status:");
     console_println!("================");
     
     let (used, total, available) = memory::get_heap_usage();
@@ -846,9 +2494,9 @@ pub fn cmd_heap() -> Result<(), &'static str> {
     console_println!("%");
     
     if available == 0 {
-        console_println!("[!]  WARNING: Heap is completely exhausted!");
+        warn_println!("WARNING: Heap is completely exhausted!");
     } else if usage_percent > 90 {
-        console_println!("[!]  WARNING: Heap usage is very high!");
+        warn_println!("WARNING: Heap usage is very high!");
     }
     
     Ok(())
@@ -856,13 +2504,13 @@ pub fn cmd_heap() -> Result<(), &'static str> {
 
 /// Reset heap for testing (dangerous)
 pub fn cmd_heap_reset() -> Result<(), &'static str> {
-    console_println!("[!]  DANGER: This will reset the heap position!");
+    warn_println!("DANGER: This will reset the heap position!");
     console_println!("This may cause memory corruption if other allocations are active.");
     console_println!("Resetting heap...");
     
     memory::reset_heap_for_testing();
     
-    console_println!("[o] Heap position reset to 0");
+    ok_println!("Heap position reset to 0");
     
     // Show new heap status
     cmd_heap()
@@ -875,20 +2523,227 @@ pub fn cmd_mmap() -> Result<(), &'static str> {
     Ok(())
 }
 
+const PEEK_SPEC: CommandSpec = CommandSpec {
+    name: "peek",
+    options: &[args::OptSpec::value('w', "width")],
+    min_positional: 2,
+    max_positional: 2,
+};
+
+const POKE_SPEC: CommandSpec = CommandSpec {
+    name: "poke",
+    options: &[args::OptSpec::value('w', "width")],
+    min_positional: 2,
+    max_positional: args::MAX_ARGS,
+};
+
+/// Most bytes one peek shows or one poke writes
+const PEEK_MAX_BYTES: usize = 256;
+
+/// What peek and poke work on
+enum PeekTarget {
+    /// Kernel memory at an address
+    Memory(usize),
+    /// A file, from a byte offset
+    File(PathBuf, usize),
+}
+
+impl PeekTarget {
+    /// `ADDR` or `FILE:OFFSET`; numbers are decimal or 0x hex
+    fn parse(text: &str) -> Result<Self, &'static str> {
+        match text.rsplit_once(':') {
+            Some((path, offset)) if !path.is_empty() => {
+                let offset = parse_number(offset).ok_or("Invalid file offset")?;
+                Ok(PeekTarget::File(resolve_path(path)?, offset))
+            }
+            _ => parse_number(text).map(PeekTarget::Memory).ok_or("Invalid address"),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Access width asked for with `-w`: 1, 2, 4 or 8 bytes
+fn access_width(parsed: &args::ParsedArgs) -> Result<usize, &'static str> {
+    match parsed.value("width") {
+        None => Ok(1),
+        Some("1") => Ok(1),
+        Some("2") => Ok(2),
+        Some("4") => Ok(4),
+        Some("8") => Ok(8),
+        Some(_) => Err("Width must be 1, 2, 4 or 8"),
+    }
+}
+
+/// Check that `len` bytes at `addr` may be accessed `width` bytes at a
+/// time: aligned, and all in RAM or in one registered device or buffer
+/// mapping
+fn check_memory_range(addr: usize, len: usize, width: usize, write: bool) -> Result<(), &'static str> {
+    if addr % width != 0 || len % width != 0 {
+        return Err("Address and length must be multiples of the width");
+    }
+    let end = addr.checked_add(len).ok_or("Range wraps around")?;
+    let ram = crate::memory::detect_main_ram().unwrap_or_else(crate::memory::get_fallback_ram);
+    let in_ram = addr >= ram.start && end <= ram.start + ram.size;
+    let in_mapping = crate::memory::mapping::find_memory_mapping(addr)
+        .is_some_and(|mapping| end <= mapping.end_addr() && (!write || mapping.permissions.write));
+    if in_ram || in_mapping {
+        Ok(())
+    } else {
+        Err("Range is not mapped")
+    }
+}
+
+/// Read kernel memory `width` bytes at a time; the range must have passed
+/// `check_memory_range`
+fn read_memory(addr: usize, buffer: &mut [u8], width: usize) {
+    for (i, chunk) in buffer.chunks_exact_mut(width).enumerate() {
+        let at = addr + i * width;
+        unsafe {
+            match width {
+                1 => chunk[0] = core::ptr::read_volatile(at as *const u8),
+                2 => chunk.copy_from_slice(&core::ptr::read_volatile(at as *const u16).to_le_bytes()),
+                4 => chunk.copy_from_slice(&core::ptr::read_volatile(at as *const u32).to_le_bytes()),
+                _ => chunk.copy_from_slice(&core::ptr::read_volatile(at as *const u64).to_le_bytes()),
+            }
+        }
+    }
+}
+
+/// Write kernel memory `width` bytes at a time; the range must have passed
+/// `check_memory_range`
+fn write_memory(addr: usize, data: &[u8], width: usize) {
+    for (i, chunk) in data.chunks_exact(width).enumerate() {
+        let at = addr + i * width;
+        unsafe {
+            match width {
+                1 => core::ptr::write_volatile(at as *mut u8, chunk[0]),
+                2 => core::ptr::write_volatile(at as *mut u16, u16::from_le_bytes([chunk[0], chunk[1]])),
+                4 => core::ptr::write_volatile(at as *mut u32, u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])),
+                _ => {
+                    let mut word = [0u8; 8];
+                    word.copy_from_slice(chunk);
+                    core::ptr::write_volatile(at as *mut u64, u64::from_le_bytes(word));
+                }
+            }
+        }
+    }
+    // The bytes may be instructions
+    unsafe { core::arch::asm!("fence.i") };
+}
+
+/// Print `data`, which starts at `base`, 16 bytes a line: single bytes
+/// with their characters, or little-endian words of `width` bytes
+fn print_dump(base: usize, data: &[u8], width: usize) {
+    for (line, bytes) in data.chunks(16).enumerate() {
+        let mut text: String<128> = String::new();
+        let _ = write!(text, "{:08x}:", base + line * 16);
+        for word in bytes.chunks(width) {
+            let value = word.iter().rev().fold(0u64, |value, &byte| value << 8 | byte as u64);
+            let _ = write!(text, " {:0digits$x}", value, digits = width * 2);
+        }
+        if width == 1 {
+            let _ = write!(text, "{:pad$} |", "", pad = (16 - bytes.len()) * 3);
+            for &byte in bytes {
+                let _ = text.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' });
+            }
+            let _ = text.push('|');
+        }
+        console_println!("{}", text);
+    }
+}
+
+fn cmd_peek(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&PEEK_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let width = access_width(&parsed)?;
+    let target = PeekTarget::parse(parsed.arg(0).unwrap_or_default())?;
+    let len = parse_number(parsed.arg(1).unwrap_or_default())
+        .filter(|&len| len > 0 && len <= PEEK_MAX_BYTES)
+        .ok_or("Length must be 1 to 256 bytes")?;
+
+    let mut buffer = [0u8; PEEK_MAX_BYTES];
+    let data = &mut buffer[..len];
+    match target {
+        PeekTarget::Memory(addr) => {
+            check_memory_range(addr, len, width, false)?;
+            read_memory(addr, data, width);
+            print_dump(addr, data, width);
+        }
+        PeekTarget::File(path, offset) => {
+            let size = crate::filesystem::get_file_size(&path).map_err(|_| "No such file")?;
+            if offset.checked_add(len).is_none_or(|end| end > size) {
+                return Err("Range is past the end of the file");
+            }
+            let read = crate::filesystem::read_file_at(&path, offset, data).map_err(|_| "Failed to read file")?;
+            print_dump(offset, &data[..read], width);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_poke(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&POKE_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let width = access_width(&parsed)?;
+    let target = PeekTarget::parse(parsed.arg(0).unwrap_or_default())?;
+
+    // Hex bytes in the order they are to be stored, in any grouping
+    let mut data: Vec<u8, PEEK_MAX_BYTES> = Vec::new();
+    for group in &parsed.positional()[1..] {
+        let group = group.strip_prefix("0x").unwrap_or(group);
+        if group.len() % 2 != 0 {
+            return Err("Bytes must be given as pairs of hex digits");
+        }
+        for pair in group.as_bytes().chunks(2) {
+            let pair = core::str::from_utf8(pair).map_err(|_| "Invalid hex byte")?;
+            let byte = u8::from_str_radix(pair, 16).map_err(|_| "Invalid hex byte")?;
+            data.push(byte).map_err(|_| "At most 256 bytes at once")?;
+        }
+    }
+
+    match target {
+        PeekTarget::Memory(addr) => {
+            check_memory_range(addr, data.len(), width, true)?;
+            write_memory(addr, &data, width);
+            ok_println!("Wrote {} bytes at 0x{:x}", data.len(), addr);
+        }
+        PeekTarget::File(path, offset) => {
+            // Files are patched in place, never extended
+            let size = crate::filesystem::get_file_size(&path).map_err(|_| "No such file")?;
+            if offset.checked_add(data.len()).is_none_or(|end| end > size) {
+                return Err("Range is past the end of the file");
+            }
+            crate::filesystem::write_file_at(&path, offset, &data).map_err(|_| "Failed to write file")?;
+            ok_println!("Wrote {} bytes to {} at offset {}", data.len(), path, offset);
+        }
+    }
+    Ok(())
+}
+
 /// Show graphics information
 pub fn cmd_graphics() -> Result<(), &'static str> {
     console_println!("=== Graphics System Information ===");
     
     match crate::graphics::get_dimensions() {
         Ok((width, height)) => {
-            console_println!("[o] Graphics system is available");
+            ok_println!("Graphics system is available");
             console_println!("Framebuffer Information:");
             console_println!("  Resolution: {}x{}", width, height);
             console_println!("  Bits per pixel: 32");
             console_println!("  Total pixels: {}", width * height);
         }
         Err(_) => {
-            console_println!("[!] Graphics system is not available");
+            warn_println!("Graphics system is not available");
             console_println!("    This may be due to:");
             console_println!("    - Insufficient memory for framebuffer allocation");
             console_println!("    - Memory management API failure");
@@ -899,6 +2754,93 @@ pub fn cmd_graphics() -> Result<(), &'static str> {
     Ok(())
 }
 
+const GFXBENCH_SPEC: CommandSpec = CommandSpec {
+    name: "gfxbench",
+    options: &[args::OptSpec::value('n', "frames")],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// Measure fill rate, glyph rendering and flush latency
+fn cmd_gfxbench(args: &str) -> Result<(), &'static str> {
+    use crate::graphics::bench::{self, Millis};
+
+    let parsed = match args::parse_command(&GFXBENCH_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let frames = match parsed.value("frames") {
+        Some(frames) => frames.parse::<u32>().ok()
+            .filter(|frames| (1..=1000).contains(frames))
+            .ok_or("Invalid number of frames")?,
+        None => 20,
+    };
+
+    let report = bench::run(frames)?;
+    console_println!("Fill:   {} pixels in {}: {} kpixel/s",
+        report.fill_pixels, Millis(report.fill_ns), bench::per_second(report.fill_pixels, report.fill_ns) / 1000);
+    console_println!("Glyphs: {} in {}: {} glyphs/s",
+        report.glyphs, Millis(report.glyph_ns), bench::per_second(report.glyphs, report.glyph_ns));
+    match report.flush {
+        Some(flush) => console_println!("Flush:  {} frames, min {}, avg {}, max {}: {} fps",
+            flush.frames, Millis(flush.min_ns), Millis(flush.average_ns()), Millis(flush.max_ns),
+            bench::per_second(1, flush.average_ns())),
+        None => console_println!("Flush:  no VirtIO GPU, nothing to flush to"),
+    }
+    Ok(())
+}
+
+const SCREENSHOT_SPEC: CommandSpec = CommandSpec {
+    name: "screenshot",
+    options: &[],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+/// Save the framebuffer as a binary PPM (P6) image
+fn cmd_screenshot(args: &str) -> Result<(), &'static str> {
+    use crate::filesystem::{self, FilesystemResult};
+
+    let parsed = match args::parse_command(&SCREENSHOT_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    let path = resolve_path(parsed.arg(0).unwrap_or_default())?;
+    if filesystem::is_pseudo_path(&path) {
+        return Err("Cannot save a screenshot in /dev or /proc");
+    }
+    let (width, height) = crate::graphics::get_dimensions()?;
+
+    let mut header: String<32> = String::new();
+    let _ = write!(header, "P6\n{} {}\n255\n", width, height);
+    // Framebuffer pixels are XRGB words, stored B, G, R, X
+    let save = || -> FilesystemResult<()> {
+        filesystem::write_file(&path, &header)?;
+        let mut pixels = [0u8; 1024];
+        let mut rgb = [0u8; 768];
+        let (mut offset, mut written) = (0, header.len());
+        loop {
+            let read = crate::graphics::read_framebuffer(offset, &mut pixels).map_err(|_| FilesystemError::IoError)?;
+            let count = read / 4;
+            if count == 0 {
+                return Ok(());
+            }
+            for (pixel, out) in pixels[..count * 4].chunks_exact(4).zip(rgb.chunks_exact_mut(3)) {
+                out.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            }
+            filesystem::write_file_at(&path, written, &rgb[..count * 3])?;
+            offset += count * 4;
+            written += count * 3;
+        }
+    };
+    save().map_err(|e| {
+        print_filesystem_error(&e);
+        "Failed to save screenshot"
+    })?;
+    ok_println!("Saved {}x{} screenshot to {}", width, height, path);
+    Ok(())
+}
+
 // Test graphics drawing
 // Commented out - simple TTY console doesn't need complex graphics tests
 /*
@@ -907,7 +2849,7 @@ pub fn cmd_graphics_test() -> Result<(), &'static str> {
     match crate::graphics::clear_screen(0x000000FF) {
         Ok(()) => {},
         Err(e) => {
-            console_println!("[x] Failed to clear screen: {}", e);
+            err_println!("Failed to clear screen: {}", e);
             return Err(e);
         }
     }
@@ -927,8 +2869,8 @@ pub fn cmd_graphics_test() -> Result<(), &'static str> {
         let x = (i * 80) as u32;
         let y = 200;
         match crate::graphics::set_pixel(x, y, color) {
-            Ok(()) => console_println!("[o] Drew {} pixel at ({}, {})", name, x, y),
-            Err(e) => console_println!("[x] Failed to draw {} pixel: {}", name, e),
+            Ok(()) => ok_println!("Drew {} pixel at ({}, {})", name, x, y),
+            Err(e) => err_println!("Failed to draw {} pixel: {}", name, e),
         }
     }
     
@@ -943,13 +2885,13 @@ pub fn cmd_graphics_test() -> Result<(), &'static str> {
     for &(x, y, w, h, color, name) in &test_rects {
         match crate::graphics::draw_rect(x, y, w, h, color) {
             Ok(()) => {
-                console_println!("[o] Drew {} rectangle {}x{} at ({}, {})", name, w, h, x, y);
-                match crate::graphics::flush_to_display() {
-                    Ok(()) => console_println!("[o] {} rectangle flushed to display", name),
-                    Err(e) => console_println!("[!] Failed to flush {} rectangle: {}", name, e),
+                ok_println!("Drew {} rectangle {}x{} at ({}, {})", name, w, h, x, y);
+                match crate::graphics::present() {
+                    Ok(()) => ok_println!("{} rectangle flushed to display", name),
+                    Err(e) => warn_println!("Failed to flush {} rectangle: {}", name, e),
                 }
             }
-            Err(e) => console_println!("[x] Failed to draw {} rectangle: {}", name, e),
+            Err(e) => err_println!("Failed to draw {} rectangle: {}", name, e),
         }
     }
     
@@ -958,32 +2900,32 @@ pub fn cmd_graphics_test() -> Result<(), &'static str> {
     
     // Test out of bounds pixel
     match crate::graphics::set_pixel(1000, 1000, 0xFFFFFFFF) {
-        Ok(()) => console_println!("[!] Out of bounds pixel at (1000, 1000) - SHOULD HAVE FAILED!"),
-        Err(e) => console_println!("[o] Out of bounds pixel at (1000, 1000) - CORRECTLY REJECTED: {}", e),
+        Ok(()) => warn_println!("Out of bounds pixel at (1000, 1000) - SHOULD HAVE FAILED!"),
+        Err(e) => ok_println!("Out of bounds pixel at (1000, 1000) - CORRECTLY REJECTED: {}", e),
     }
     
     // Test edge pixel
     match crate::graphics::set_pixel(639, 479, 0xFFFFFFFF) {
-        Ok(()) => console_println!("[o] Edge pixel at (639, 479) - SUCCESS"),
-        Err(e) => console_println!("[x] Edge pixel at (639, 479) - FAILED: {}", e),
+        Ok(()) => ok_println!("Edge pixel at (639, 479) - SUCCESS"),
+        Err(e) => err_println!("Edge pixel at (639, 479) - FAILED: {}", e),
     }
     
     // Test just out of bounds
     match crate::graphics::set_pixel(640, 480, 0xFFFFFFFF) {
-        Ok(()) => console_println!("[!] Just out of bounds at (640, 480) - SHOULD HAVE FAILED!"),
-        Err(e) => console_println!("[o] Just out of bounds at (640, 480) - CORRECTLY REJECTED: {}", e),
+        Ok(()) => warn_println!("Just out of bounds at (640, 480) - SHOULD HAVE FAILED!"),
+        Err(e) => ok_println!("Just out of bounds at (640, 480) - CORRECTLY REJECTED: {}", e),
     }
     
     // Final flush
     console_print!("Final: Flushing all graphics to display...");
-    match crate::graphics::flush_to_display() {
-        Ok(()) => console_println!("[o] Final flush completed - all graphics should now be visible!"),
-        Err(e) => console_println!("[!] Final flush failed: {}", e),
+    match crate::graphics::present() {
+        Ok(()) => ok_println!("Final flush completed - all graphics should now be visible!"),
+        Err(e) => warn_println!("Final flush failed: {}", e),
     }
     
     // Summary
     console_println!("\n=== Test Results ===");
-    console_println!("[o] Graphics tests completed successfully");
+    ok_println!("Graphics tests completed successfully");
     
     Ok(())
 }
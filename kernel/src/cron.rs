@@ -0,0 +1,236 @@
+//! Commands run later, once or periodically
+//!
+//! `at` queues a shell command to run once, after a number of seconds or at
+//! a time of day (UTC, from the RTC). `/etc/cron.cfg` lists commands to run
+//! over and over, each with its interval, so maintenance goes on unattended
+//! during soak tests:
+//!
+//! ```text
+//! # every  command
+//! 1h       logrotate
+//! 10m      sync
+//! 1d       wipefree /
+//! ```
+//!
+//! An interval is a number of seconds, or of minutes, hours or days with an
+//! `m`, `h` or `d` after it. A periodic job first runs one interval after
+//! the table is loaded.
+//!
+//! There is one thread of control, the shell's, so jobs run where the shell
+//! does its other idle work: while it waits for input. A job that comes due
+//! while a command runs waits for the prompt; the line being typed is shown
+//! again after it. Jobs run as if typed, `$VARIABLES` expanded, but do not
+//! go into the history or change `$?`.
+
+use heapless::{String, Vec};
+use spin::Mutex;
+use elinos_common::{info_println, warn_println};
+
+use crate::trap;
+
+/// Path of the periodic job table
+pub const CRON_TABLE_PATH: &str = "/etc/cron.cfg";
+
+/// Most `at` jobs queued at once
+pub const MAX_AT_JOBS: usize = 16;
+
+/// Most periodic jobs
+pub const MAX_PERIODIC_JOBS: usize = 16;
+
+/// Longest command a job runs
+pub const MAX_JOB_LEN: usize = 128;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub type Command = String<MAX_JOB_LEN>;
+
+/// A job queued by `at`
+#[derive(Clone)]
+pub struct AtJob {
+    pub id: u32,
+    /// Tick it runs at
+    pub due: u64,
+    pub command: Command,
+}
+
+/// A job from the table
+#[derive(Clone)]
+pub struct PeriodicJob {
+    pub interval_secs: u64,
+    /// Tick it next runs at
+    pub due: u64,
+    pub command: Command,
+    pub runs: u32,
+    /// Exit status of the last run
+    pub last_status: Option<i32>,
+}
+
+/// Which job a due command came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobId {
+    At(u32),
+    /// Position in the table
+    Periodic(usize),
+}
+
+/// A job to run now
+pub struct DueJob {
+    pub id: JobId,
+    pub command: Command,
+}
+
+struct Scheduler {
+    at_jobs: Vec<AtJob, MAX_AT_JOBS>,
+    next_id: u32,
+    periodic: Vec<PeriodicJob, MAX_PERIODIC_JOBS>,
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler {
+    at_jobs: Vec::new(),
+    next_id: 1,
+    periodic: Vec::new(),
+});
+
+/// Seconds in an interval: a number, with `s`, `m`, `h` or `d` after it
+pub fn parse_interval(text: &str) -> Option<u64> {
+    let (number, unit) = match text.char_indices().last()? {
+        (end, 's') => (&text[..end], 1),
+        (end, 'm') => (&text[..end], 60),
+        (end, 'h') => (&text[..end], 60 * 60),
+        (end, 'd') => (&text[..end], SECONDS_PER_DAY),
+        _ => (text, 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Seconds from now until `when`: `+INTERVAL`, or `HH:MM` for the next
+/// time the clock shows it
+pub fn parse_when(when: &str) -> Result<u64, &'static str> {
+    if let Some(interval) = when.strip_prefix('+') {
+        return parse_interval(interval).ok_or("Invalid number of seconds");
+    }
+    let (hour, minute) = when.split_once(':').ok_or("Expected +SECONDS or HH:MM")?;
+    let hour: u64 = hour.parse().ok().filter(|&hour| hour < 24).ok_or("Invalid hour")?;
+    let minute: u64 = minute.parse().ok().filter(|&minute| minute < 60).ok_or("Invalid minute")?;
+    let now = elinos_common::rtc::unix_time().ok_or("RTC not set, use +SECONDS")?;
+    let target = hour * 3600 + minute * 60;
+    match (target + SECONDS_PER_DAY - now % SECONDS_PER_DAY) % SECONDS_PER_DAY {
+        0 => Ok(SECONDS_PER_DAY),
+        wait => Ok(wait),
+    }
+}
+
+/// The tick `seconds` from now
+fn due_in(seconds: u64) -> u64 {
+    trap::tick_count().saturating_add(seconds.saturating_mul(trap::TICK_HZ))
+}
+
+/// Whole seconds until tick `due`
+pub fn seconds_until(due: u64) -> u64 {
+    due.saturating_sub(trap::tick_count()).div_ceil(trap::TICK_HZ)
+}
+
+/// Queue `command` to run once, `seconds` from now. Returns its job ID.
+pub fn at(seconds: u64, command: &str) -> Result<u32, &'static str> {
+    let command = Command::try_from(command).map_err(|_| "Command too long")?;
+    let mut scheduler = SCHEDULER.lock();
+    let id = scheduler.next_id;
+    scheduler.at_jobs.push(AtJob { id, due: due_in(seconds), command }).map_err(|_| "Too many jobs queued")?;
+    scheduler.next_id = id.wrapping_add(1).max(1);
+    Ok(id)
+}
+
+/// Take `at` job `id` off the queue. Returns whether it was there.
+pub fn remove(id: u32) -> bool {
+    let mut scheduler = SCHEDULER.lock();
+    match scheduler.at_jobs.iter().position(|job| job.id == id) {
+        Some(index) => {
+            scheduler.at_jobs.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The queued `at` jobs, soonest first
+pub fn at_jobs() -> Vec<AtJob, MAX_AT_JOBS> {
+    let mut jobs = SCHEDULER.lock().at_jobs.clone();
+    jobs.sort_unstable_by_key(|job| job.due);
+    jobs
+}
+
+/// The periodic jobs, in table order
+pub fn periodic_jobs() -> Vec<PeriodicJob, MAX_PERIODIC_JOBS> {
+    SCHEDULER.lock().periodic.clone()
+}
+
+/// Read the periodic jobs from `/etc/cron.cfg`, replacing those there were.
+/// Returns how many there are now; a missing file means none.
+pub fn load_table() -> usize {
+    let mut periodic: Vec<PeriodicJob, MAX_PERIODIC_JOBS> = Vec::new();
+    if let Ok(data) = crate::filesystem::read_file(CRON_TABLE_PATH) {
+        match core::str::from_utf8(&data) {
+            Ok(table) => parse_table(table, &mut periodic),
+            Err(_) => warn_println!(target: "cron", "{} is not valid UTF-8, ignoring it", CRON_TABLE_PATH),
+        }
+    }
+    let count = periodic.len();
+    SCHEDULER.lock().periodic = periodic;
+    if count > 0 {
+        info_println!(target: "cron", "{} periodic job(s) from {}", count, CRON_TABLE_PATH);
+    }
+    count
+}
+
+fn parse_table(table: &str, periodic: &mut Vec<PeriodicJob, MAX_PERIODIC_JOBS>) {
+    for (number, line) in table.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (interval, command) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let interval_secs = match parse_interval(interval) {
+            Some(secs) if secs > 0 => secs,
+            _ => {
+                warn_println!(target: "cron", "{}:{}: invalid interval, skipping it", CRON_TABLE_PATH, number + 1);
+                continue;
+            }
+        };
+        let command = match Command::try_from(command.trim()) {
+            Ok(command) if !command.is_empty() => command,
+            _ => {
+                warn_println!(target: "cron", "{}:{}: missing or too long command, skipping it", CRON_TABLE_PATH, number + 1);
+                continue;
+            }
+        };
+        let job = PeriodicJob { interval_secs, due: due_in(interval_secs), command, runs: 0, last_status: None };
+        if periodic.push(job).is_err() {
+            warn_println!(target: "cron", "{}: more than {} jobs, ignoring the rest", CRON_TABLE_PATH, MAX_PERIODIC_JOBS);
+            return;
+        }
+    }
+}
+
+/// The next job that is due, if any. An `at` job leaves the queue; a
+/// periodic one is due again an interval from now.
+pub fn take_due() -> Option<DueJob> {
+    let now = trap::tick_count();
+    let mut scheduler = SCHEDULER.lock();
+    if let Some(index) = scheduler.at_jobs.iter().position(|job| job.due <= now) {
+        let job = scheduler.at_jobs.swap_remove(index);
+        return Some(DueJob { id: JobId::At(job.id), command: job.command });
+    }
+    let (index, job) = scheduler.periodic.iter_mut().enumerate().find(|(_, job)| job.due <= now)?;
+    job.due = due_in(job.interval_secs);
+    Some(DueJob { id: JobId::Periodic(index), command: job.command.clone() })
+}
+
+/// Record how a job taken with `take_due` ended
+pub fn finished(id: JobId, status: i32) {
+    if let JobId::Periodic(index) = id {
+        if let Some(job) = SCHEDULER.lock().periodic.get_mut(index) {
+            job.runs = job.runs.saturating_add(1);
+            job.last_status = Some(status);
+        }
+    }
+}
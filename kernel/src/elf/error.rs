@@ -18,6 +18,10 @@ pub enum ElfError {
     ExecutionError,
     MemoryAllocationFailed,
     InvalidEntryPoint,
+    SignatureMissing,
+    SignatureInvalid,
+    KeyUnavailable,
+    UnsupportedRelocation,
 }
 
 impl fmt::Display for ElfError {
@@ -34,6 +38,10 @@ impl fmt::Display for ElfError {
             ElfError::ExecutionError => write!(f, "ELF execution error"),
             ElfError::MemoryAllocationFailed => write!(f, "Memory allocation failed"),
             ElfError::InvalidEntryPoint => write!(f, "Invalid entry point"),
+            ElfError::SignatureMissing => write!(f, "Binary is not signed"),
+            ElfError::SignatureInvalid => write!(f, "Binary signature verification failed"),
+            ElfError::KeyUnavailable => write!(f, "Signing key not available"),
+            ElfError::UnsupportedRelocation => write!(f, "Relocation needs a dynamic linker"),
         }
     }
 }
@@ -6,8 +6,9 @@
 use crate::memory;
 use super::constants::*;
 use super::error::{ElfError, ElfResult};
-use super::structures::{Elf64Header, LoadedElf, ElfSegment};
+use super::structures::{Elf64Header, Elf64ProgramHeader, LoadedElf, ElfSegment, RelocationTable};
 use super::parser::ElfParser;
+use super::relocate;
 
 /// ELF Loader for loading ELF64 binaries into memory
 pub struct ElfLoader {
@@ -21,21 +22,19 @@ impl ElfLoader {
         }
     }
 
-    /// Load ELF binary into memory
-    pub fn load_elf(&self, data: &[u8]) -> ElfResult<LoadedElf> {
+    /// Collect the non-empty PT_LOAD program headers from `data`, and the
+    /// PT_DYNAMIC one if there is one.
+    /// `data` only needs to cover the ELF header and program header table.
+    fn load_headers(&self, data: &[u8]) -> ElfResult<(heapless::Vec<Elf64ProgramHeader, 8>, Option<Elf64ProgramHeader>)> {
         let header = self.parser.parse_header(data)?;
         
         // Copy packed fields to local variables to avoid alignment issues
-        let entry_point = header.e_entry;
         let phnum = header.e_phnum;
         let ph_offset = header.e_phoff;
         let phentsize = header.e_phentsize;
         
-        if memory::mmu::is_mmu_enabled() {
-            // Using Software MMU - skipping hardware page table setup
-        }
-        
-        let mut segments = heapless::Vec::<ElfSegment, 8>::new();
+        let mut headers = heapless::Vec::<Elf64ProgramHeader, 8>::new();
+        let mut dynamic = None;
         
         // Calculate the base address for program headers
         let ph_start = ph_offset as usize;
@@ -45,103 +44,180 @@ impl ElfLoader {
             return Err(ElfError::InvalidHeader);
         }
         
-        let ph_count = phnum as usize;
-        
-        for i in 0..ph_count {
-            
+        for i in 0..phnum as usize {
             let ph_offset_in_data = ph_start + i * (phentsize as usize);
             
             if ph_offset_in_data + 56 > data.len() {
                 continue;
             }
             
-            let ph_data = &data[ph_offset_in_data..ph_offset_in_data + 56];
-            let p_type = u32::from_le_bytes([ph_data[0], ph_data[1], ph_data[2], ph_data[3]]);
+            let ph = unsafe {
+                core::ptr::read_unaligned(data.as_ptr().add(ph_offset_in_data) as *const Elf64ProgramHeader)
+            };
+            let p_type = ph.p_type;
+            let p_memsz = ph.p_memsz;
             
+            if p_type == PT_LOAD && p_memsz != 0 {
+                headers.push(ph).map_err(|_| ElfError::LoadError)?;
+            } else if p_type == PT_DYNAMIC {
+                dynamic = Some(ph);
+            }
+        }
+        
+        Ok((headers, dynamic))
+    }
+
+    /// Find the relocations of a position-independent binary. `read` reads
+    /// file bytes at an offset and returns how many it got.
+    fn relocation_table(
+        &self,
+        e_type: u16,
+        dynamic: Option<Elf64ProgramHeader>,
+        loads: &[Elf64ProgramHeader],
+        read: impl FnOnce(usize, &mut [u8]) -> ElfResult<usize>,
+    ) -> ElfResult<Option<RelocationTable>> {
+        let dynamic = match dynamic {
+            Some(dynamic) if e_type == ET_DYN => dynamic,
+            _ => return Ok(None),
+        };
+        let mut buffer = [0u8; relocate::MAX_DYNAMIC_SIZE];
+        let size = core::cmp::min(dynamic.p_filesz as usize, buffer.len());
+        let read = read(dynamic.p_offset as usize, &mut buffer[..size])?;
+        relocate::find_rela_table(&buffer[..read], loads)
+    }
+
+    /// Load ELF binary into memory
+    pub fn load_elf(&self, data: &[u8]) -> ElfResult<LoadedElf> {
+        let header = self.parser.parse_header(data)?;
+        let (entry_point, e_type) = (header.e_entry, header.e_type);
+        let (loads, dynamic) = self.load_headers(data)?;
+        let load_bias = relocate::load_bias(e_type, &loads);
+        let mut segments = heapless::Vec::<ElfSegment, 8>::new();
+        
+        for ph in loads.iter() {
+            let p_flags = ph.p_flags;
+            let p_offset = ph.p_offset as usize;
+            let p_vaddr = ph.p_vaddr;
+            let p_filesz = ph.p_filesz;
+            let p_memsz = ph.p_memsz;
             
-            if p_type == PT_LOAD {
-                
-                let p_flags = u32::from_le_bytes([ph_data[4], ph_data[5], ph_data[6], ph_data[7]]);
-                let p_offset = u64::from_le_bytes([
-                    ph_data[8], ph_data[9], ph_data[10], ph_data[11],
-                    ph_data[12], ph_data[13], ph_data[14], ph_data[15]
-                ]) as usize;
-                let p_vaddr = u64::from_le_bytes([
-                    ph_data[16], ph_data[17], ph_data[18], ph_data[19],
-                    ph_data[20], ph_data[21], ph_data[22], ph_data[23]
-                ]);
-                let p_filesz = u64::from_le_bytes([
-                    ph_data[32], ph_data[33], ph_data[34], ph_data[35],
-                    ph_data[36], ph_data[37], ph_data[38], ph_data[39]
-                ]);
-                let p_memsz = u64::from_le_bytes([
-                    ph_data[40], ph_data[41], ph_data[42], ph_data[43],
-                    ph_data[44], ph_data[45], ph_data[46], ph_data[47]
-                ]);
-                
-                
-                if p_memsz == 0 {
-                    continue;
-                }
-                
-                
-                let file_size = if p_offset < data.len() {
-                    core::cmp::min(p_filesz as usize, data.len() - p_offset)
-                } else {
-                    0
-                };
-                
-                
-                let segment_data = if p_offset >= data.len() {
-                    &[]
-                } else if p_offset + file_size > data.len() {
-                    &data[p_offset..data.len()]
-                } else if file_size == 0 {
-                    &[]
-                } else {
-                    &data[p_offset..p_offset + file_size]
-                };
+            let file_size = if p_offset < data.len() {
+                core::cmp::min(p_filesz as usize, data.len() - p_offset)
+            } else {
+                0
+            };
+            
+            let segment_data = if file_size == 0 {
+                &[]
+            } else {
+                &data[p_offset..p_offset + file_size]
+            };
+            
+            let allocated_addr = if let Ok(addr) = memory::allocate_memory(p_memsz as usize, 8) {
+                let dest_ptr = addr.as_ptr();
                 
-                let allocated_addr = if let Ok(addr) = memory::allocate_memory(p_memsz as usize, 8) {
-                    
-                    let dest_ptr = addr.as_ptr();
+                unsafe {
+                    // Zero the entire allocated memory
+                    core::ptr::write_bytes(dest_ptr, 0, p_memsz as usize);
                     
-                    unsafe {
-                        // Zero the entire allocated memory
-                        core::ptr::write_bytes(dest_ptr, 0, p_memsz as usize);
-                        
-                        // Copy file data if we have any
-                        if !segment_data.is_empty() {
-                            core::ptr::copy_nonoverlapping(
-                                segment_data.as_ptr(),
-                                dest_ptr,
-                                segment_data.len()
-                            );
-                        }
+                    // Copy file data if we have any
+                    if !segment_data.is_empty() {
+                        core::ptr::copy_nonoverlapping(
+                            segment_data.as_ptr(),
+                            dest_ptr,
+                            segment_data.len()
+                        );
                     }
-                    
-                    
-                    addr.as_ptr() as usize
-                } else {
-                    return Err(ElfError::LoadError);
-                };
-                
-                if let Err(_) = segments.push(ElfSegment {
-                    vaddr: p_vaddr,
-                    memsz: p_memsz,
-                    data_addr: Some(allocated_addr),
-                    data_size: file_size,
-                    flags: p_flags,
-                }) {
-                    return Err(ElfError::LoadError);
                 }
+                
+                addr.as_ptr() as usize
+            } else {
+                return Err(ElfError::LoadError);
+            };
+            
+            if let Err(_) = segments.push(ElfSegment {
+                vaddr: p_vaddr.wrapping_add(load_bias),
+                memsz: p_memsz,
+                data_addr: Some(allocated_addr),
+                data_size: file_size,
+                file_offset: p_offset,
+                flags: p_flags,
+            }) {
+                return Err(ElfError::LoadError);
+            }
+        }
+        
+        // Fix up the addresses in the loaded copies of the segments
+        let relocations = self.relocation_table(e_type, dynamic, &loads, |offset, buffer| {
+            let bytes = data.get(offset..offset + buffer.len()).ok_or(ElfError::InvalidHeader)?;
+            buffer.copy_from_slice(bytes);
+            Ok(buffer.len())
+        })?;
+        if let Some(table) = relocations {
+            let end = table.file_offset + table.count * relocate::RELA_SIZE;
+            let entries = data.get(table.file_offset..end).ok_or(ElfError::InvalidHeader)?;
+            relocate::apply(entries, load_bias, |vaddr, value| {
+                let segment = segments.iter()
+                    .find(|segment| vaddr >= segment.vaddr && vaddr - segment.vaddr + 8 <= segment.memsz)
+                    .ok_or(ElfError::InvalidHeader)?;
+                let dest = segment.data_addr.ok_or(ElfError::LoadError)? + (vaddr - segment.vaddr) as usize;
+                unsafe { core::ptr::write_unaligned(dest as *mut u64, value) };
+                Ok(())
+            })?;
+        }
+        
+        Ok(LoadedElf {
+            entry_point: entry_point.wrapping_add(load_bias),
+            segments,
+            source: None,
+            load_bias,
+            relocations: None,
+        })
+    }
+
+    /// Prepare an ELF binary for demand paging.
+    ///
+    /// Only the headers are parsed; `header_data` is the start of the file
+    /// and must contain the program header table. Segment contents stay in
+    /// `path` and are faulted in page by page when the program touches them.
+    pub fn load_elf_lazy(&self, header_data: &[u8], path: &str) -> ElfResult<LoadedElf> {
+        let header = self.parser.parse_header(header_data)?;
+        let (entry_point, e_type) = (header.e_entry, header.e_type);
+        let source = heapless::String::try_from(path).map_err(|_| ElfError::LoadError)?;
+        let (loads, dynamic) = self.load_headers(header_data)?;
+        let load_bias = relocate::load_bias(e_type, &loads);
+        let mut segments = heapless::Vec::<ElfSegment, 8>::new();
+        
+        for ph in loads.iter() {
+            let p_filesz = ph.p_filesz;
+            let p_memsz = ph.p_memsz;
+            
+            if p_filesz > p_memsz {
+                return Err(ElfError::InvalidHeader);
             }
+            
+            segments.push(ElfSegment {
+                vaddr: ph.p_vaddr.wrapping_add(load_bias),
+                memsz: p_memsz,
+                data_addr: None,
+                data_size: p_filesz as usize,
+                file_offset: ph.p_offset as usize,
+                flags: ph.p_flags,
+            }).map_err(|_| ElfError::LoadError)?;
         }
         
+        // Relocations are applied once the segments are mapped; see
+        // `relocate::apply_from_file`
+        let relocations = self.relocation_table(e_type, dynamic, &loads, |offset, buffer| {
+            crate::filesystem::read_file_at(path, offset, buffer).map_err(|_| ElfError::LoadError)
+        })?;
         
         Ok(LoadedElf {
-            entry_point,
+            entry_point: entry_point.wrapping_add(load_bias),
             segments,
+            source: Some(source),
+            load_bias,
+            relocations,
         })
     }
 
@@ -9,7 +9,7 @@
 // Re-export core types for backward compatibility
 pub use constants::*;
 pub use error::{ElfError, ElfResult};
-pub use structures::{Elf64Header, Elf64ProgramHeader, LoadedElf, ElfSegment};
+pub use structures::{Elf64Header, Elf64ProgramHeader, LoadedElf, ElfSegment, RelocationTable};
 pub use parser::ElfParser;
 pub use loader::ElfLoader;
 
@@ -19,6 +19,8 @@ pub mod error;
 pub mod structures;
 pub mod parser;
 pub mod loader;
+pub mod relocate;
+pub mod verify;
 
 // TODO: These modules will be created in follow-up work
 // pub mod executor;
@@ -28,88 +30,206 @@ pub mod loader;
 // For now, include the remaining functions from the original elf.rs
 // These will be moved to their respective modules later
 
-use crate::console_println;
+use crate::{console_println, err_println, info_println};
 
-/// Main ELF execution function - coordinates loading and execution
-pub fn execute_elf(loaded_elf: &LoadedElf) -> ElfResult<()> {
-    console_println!("[i] Executing ELF at entry point 0x{:x}", loaded_elf.entry_point);
-    
-    // Always use software MMU for now (hardware MMU has issues)
-    console_println!("[i] Virtual Memory enabled - executing with software MMU");
-    
-    // Execute with software virtual memory translation
-    console_println!("[i] Executing at virtual entry point: 0x{:x}", loaded_elf.entry_point);
+/// Main ELF execution function - coordinates loading and execution.
+/// Returns the program's exit status once it has finished.
+pub fn execute_elf(loaded_elf: &LoadedElf) -> ElfResult<i32> {
+    info_println!("Executing ELF at entry point 0x{:x}", loaded_elf.entry_point);
     
     unsafe {
-        execute_user_program_with_software_mmu(loaded_elf.entry_point as usize, loaded_elf);
+        if crate::memory::mmu::is_hardware_paging() {
+            execute_in_address_space(loaded_elf)
+        } else if loaded_elf.source.is_some() {
+            // Demand paging needs page faults, which need Sv39
+            err_println!("Demand paged programs need hardware paging");
+            Err(ElfError::ExecutionError)
+        } else if loaded_elf.load_bias != 0 {
+            // The relocated addresses only mean something in a user space
+            err_println!("Position-independent programs need hardware paging");
+            Err(ElfError::ExecutionError)
+        } else {
+            // No Sv39 on this hart: translate the entry point by hand
+            execute_user_program_with_software_mmu(loaded_elf.entry_point as usize, loaded_elf)
+        }
     }
-    
-    Ok(())
 }
 
 // Temporary inclusion of execution functions
 // TODO: Move these to executor.rs and syscall.rs modules
 
-/// Execute user program with temporary syscall support
-unsafe fn execute_with_syscall_support(entry_point: usize) -> usize {
-    use core::arch::asm;
+/// Size of the stack given to user programs
+const USER_STACK_SIZE: usize = 8192;
+
+/// Code user programs return into: li a7, 93; ecall; ebreak; nop
+const EXIT_STUB: [u32; 4] = [0x05d00893, 0x00000073, 0x00100073, 0x00000013];
+
+/// Page table flags for an ELF segment's permissions
+fn segment_pte_flags(flags: u32) -> u64 {
+    use crate::memory::mmu::{PTE_R, PTE_W, PTE_X};
+    
+    let mut pte_flags = 0;
+    if flags & PF_R != 0 {
+        pte_flags |= PTE_R;
+    }
+    if flags & PF_W != 0 {
+        pte_flags |= PTE_R | PTE_W; // W without R is a reserved encoding
+    }
+    if flags & PF_X != 0 {
+        pte_flags |= PTE_X;
+    }
+    pte_flags
+}
+
+/// Load a program to be run a slice at a time in the background: its
+/// address space, given to the current process, and the registers that
+/// start it. Slices switch address spaces, so this needs hardware paging.
+pub fn prepare_background(loaded_elf: &LoadedElf) -> ElfResult<(u64, crate::trap::TrapContext)> {
+    use crate::memory::mmu::{USER_STACK, USER_TRAMPOLINE};
+    
+    if !crate::memory::mmu::is_hardware_paging() {
+        err_println!("Background programs need hardware paging");
+        return Err(ElfError::ExecutionError);
+    }
+    let satp = unsafe { build_address_space(loaded_elf)? };
+    Ok((satp, crate::trap::entry_frame(loaded_elf.entry_point as usize, USER_STACK, USER_TRAMPOLINE)))
+}
+
+/// Build a fresh Sv39 address space for the program and run it at its linked addresses
+unsafe fn execute_in_address_space(loaded_elf: &LoadedElf) -> ElfResult<i32> {
+    use crate::memory::mmu::{self, USER_STACK, USER_TRAMPOLINE};
+    
+    let satp = build_address_space(loaded_elf)?;
     
+    // Restore whatever was active before (the kernel, or a parent program)
+    let previous_satp = mmu::read_satp();
+    mmu::write_satp(satp as usize);
+    
+    let exit_code = crate::trap::run_user_program(loaded_elf.entry_point as usize, USER_STACK, USER_TRAMPOLINE);
+    
+    mmu::write_satp(previous_satp);
+    mmu::release_user_space(satp);
+    
+    Ok(exit_code)
+}
+
+/// Map the program's segments, stack and exit stub into a new address
+/// space, hand it to the MMU manager and record it for the current
+/// process. Returns the satp value that selects it.
+unsafe fn build_address_space(loaded_elf: &LoadedElf) -> ElfResult<u64> {
+    use crate::memory::mmu::{self, PTE_R, PTE_W, PTE_X, PAGE_SIZE, USER_STACK, USER_TRAMPOLINE};
+    
+    let entry_point = loaded_elf.entry_point as usize;
+    if entry_point % 4 != 0 {
+        err_println!("Entry point 0x{:x} is not 4-byte aligned!", entry_point);
+        return Err(ElfError::InvalidEntryPoint);
+    }
+    
+    let mut space = mmu::create_user_address_space().map_err(|e| {
+        err_println!("Failed to create address space: {}", e);
+        ElfError::MemoryAllocationFailed
+    })?;
+    
+    if let Some(path) = &loaded_elf.source {
+        space.set_backing_file(path).map_err(|_| ElfError::LoadError)?;
+    }
+    
+    for segment in &loaded_elf.segments {
+        let vaddr = segment.vaddr as usize;
+        let flags = segment_pte_flags(segment.flags);
+        
+        if segment.data_addr.is_none() && loaded_elf.source.is_some() {
+            // Demand paged: pages are read from the file on first access
+            if let Err(e) = space.map_lazy_region(vaddr, segment.memsz as usize, segment.file_offset, segment.data_size, flags) {
+                err_println!("Cannot map segment at 0x{:x}: {}", vaddr, e);
+                return Err(ElfError::LoadError);
+            }
+            continue;
+        }
+        
+        if let Err(e) = space.map_user_region(vaddr, segment.memsz as usize, flags) {
+            err_println!("Cannot map segment at 0x{:x}: {}", vaddr, e);
+            return Err(ElfError::LoadError);
+        }
+        
+        if let Some(data_addr) = segment.data_addr {
+            let data = core::slice::from_raw_parts(data_addr as *const u8, segment.data_size);
+            space.copy_to_user(vaddr, data).map_err(|_| ElfError::LoadError)?;
+        }
+    }
+    
+    if let (Some(table), Some(path)) = (loaded_elf.relocations, &loaded_elf.source) {
+        relocate::apply_from_file(&mut space, path, table, loaded_elf.load_bias).map_err(|e| {
+            err_println!("Cannot relocate {}: {}", path, e);
+            e
+        })?;
+    }
+    
+    let mut stub = [0u8; 16];
+    for (i, word) in EXIT_STUB.iter().enumerate() {
+        stub[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    space.map_user_region(USER_STACK - USER_STACK_SIZE, USER_STACK_SIZE, PTE_R | PTE_W)
+        .and_then(|_| space.map_user_region(USER_TRAMPOLINE, PAGE_SIZE, PTE_R | PTE_X))
+        .and_then(|_| space.copy_to_user(USER_TRAMPOLINE, &stub))
+        .map_err(|e| {
+            err_println!("Failed to set up user stack: {}", e);
+            ElfError::MemoryAllocationFailed
+        })?;
+    
+    // The MMU manager owns the space from here on so page faults can find it
+    let satp = mmu::register_user_space(space).map_err(|e| {
+        err_println!("{}", e);
+        ElfError::ExecutionError
+    })?;
+    
+    {
+        let mut pm = crate::process::PROCESS_MANAGER.lock();
+        let pid = pm.get_current_pid();
+        if let Some(process) = pm.get_process_mut(pid) {
+            process.satp = satp;
+            process.heap_start = loaded_elf.heap_start();
+            process.brk = process.heap_start;
+        }
+    }
+    
+    Ok(satp)
+}
+
+/// Execute user program with syscall support and return its exit status
+unsafe fn execute_with_syscall_support(entry_point: usize) -> ElfResult<i32> {
     // Allocate user stack
-    let user_stack = match crate::memory::allocate_memory(8192, 8) {
-        Ok(addr) => addr.as_ptr() as usize,
+    let user_stack = match crate::memory::allocate_memory(USER_STACK_SIZE, 16) {
+        Ok(addr) => addr,
         Err(_) => {
-            console_println!("[x] Failed to allocate user stack");
-            return 0;
+            err_println!("Failed to allocate user stack");
+            return Err(ElfError::MemoryAllocationFailed);
         }
     };
-    let user_stack_top = user_stack + 8192;
-    
-    console_println!("[i] User stack allocated: 0x{:x} - 0x{:x}", user_stack, user_stack_top);
+    let user_stack_top = user_stack.as_ptr() as usize + USER_STACK_SIZE;
     
     // Create a small exit stub that will be called when the user program returns
     let exit_stub = match crate::memory::allocate_memory(32, 8) {
-        Ok(addr) => addr.as_ptr() as usize,
+        Ok(addr) => addr,
         Err(_) => {
-            console_println!("[x] Failed to allocate exit stub");
-            // Note: deallocate_memory signature needs to be fixed too
-            return 0;
+            err_println!("Failed to allocate exit stub");
+            crate::memory::deallocate_memory(user_stack, USER_STACK_SIZE);
+            return Err(ElfError::MemoryAllocationFailed);
         }
     };
     
-    // Write exit stub code: li a7, 93; ecall; ebreak (breakpoint)
-    let exit_stub_ptr = exit_stub as *mut u32;
-    exit_stub_ptr.write_volatile(0x05d00893); // li a7, 93 (addi a7, x0, 93)
-    exit_stub_ptr.add(1).write_volatile(0x00000073); // ecall
-    exit_stub_ptr.add(2).write_volatile(0x00100073); // ebreak (breakpoint)
-    exit_stub_ptr.add(3).write_volatile(0x00000013); // nop (padding)
-    
-    console_println!("[i] Exit stub created at 0x{:x}", exit_stub);
+    let exit_stub_ptr = exit_stub.as_ptr() as *mut u32;
+    for (i, word) in EXIT_STUB.iter().enumerate() {
+        exit_stub_ptr.add(i).write_volatile(*word);
+    }
     
-    // Set up proper user mode status
-    let user_status = 0x00000020; // SPIE=1, SPP=0 (user mode)
-    console_println!("   Status: 0x{:x}", user_status);
+    // Enter user mode; this returns once the program exits or faults
+    let exit_code = crate::trap::run_user_program(entry_point, user_stack_top, exit_stub.as_ptr() as usize);
     
-    console_println!("[i] About to jump to user mode...");
+    crate::memory::deallocate_memory(exit_stub, 32);
+    crate::memory::deallocate_memory(user_stack, USER_STACK_SIZE);
     
-    let result: usize;
-    unsafe {
-        asm!(
-            "csrw sepc, {entry}",
-            "csrw sstatus, {status}",
-            "mv sp, {stack}",
-            "mv ra, {exit_stub}",
-            "sret",
-            "mv {result}, a0",
-            entry = in(reg) entry_point,
-            status = in(reg) user_status,
-            stack = in(reg) user_stack_top,
-            exit_stub = in(reg) exit_stub,
-            result = out(reg) result,
-        );
-    }
-    
-    console_println!("[o] Returned from user mode. Result: {}", result);
-    result
+    Ok(exit_code)
 }
 
 /// Temporary trap handler specifically for user program execution
@@ -145,7 +265,7 @@ extern "C" fn syscall_trap_handler() {
     
     // Handle system calls (ecall from user mode = 8, ecall from supervisor mode = 9)
     if exception_code == 8 || exception_code == 9 {
-        console_println!("📞 System call: SYS_{} fd={} ptr=0x{:x} len={}", a0, a1, a2, a3);
+        info_println!("System call: SYS_{} fd={} ptr=0x{:x} len={}", a0, a1, a2, a3);
         
         // Handle SYS_WRITE (64)
         if a0 == 64 && a1 == 1 { // SYS_WRITE to stdout
@@ -183,7 +303,7 @@ extern "C" fn syscall_trap_handler() {
         }
     } else {
         // Handle other exceptions
-        console_println!("[x] Unhandled exception: code={}", exception_code);
+        err_println!("Unhandled exception: code={}", exception_code);
         
         unsafe {
             asm!(
@@ -196,7 +316,7 @@ extern "C" fn syscall_trap_handler() {
 }
 
 /// Execute user program with software MMU virtual memory translation
-unsafe fn execute_user_program_with_software_mmu(entry_point: usize, loaded_elf: &LoadedElf) {
+unsafe fn execute_user_program_with_software_mmu(entry_point: usize, loaded_elf: &LoadedElf) -> ElfResult<i32> {
     // Find the executable segment to get the virtual-to-physical mapping
     for segment in &loaded_elf.segments {
         if segment.flags & PF_X != 0 && segment.data_addr.is_some() {
@@ -208,66 +328,33 @@ unsafe fn execute_user_program_with_software_mmu(entry_point: usize, loaded_elf:
                 let entry_offset = entry_point - segment_start;
                 let physical_entry = data_addr + entry_offset;
                 
-                execute_user_program(physical_entry);
-                return;
+                return execute_user_program(physical_entry);
             }
         }
     }
     
-    console_println!("[x] Entry point 0x{:08x} not found in any executable segment", entry_point);
+    err_println!("Entry point 0x{:08x} not found in any executable segment", entry_point);
     for (i, segment) in loaded_elf.segments.iter().enumerate() {
         let perms = segment_permissions(segment.flags);
         console_println!("      Segment {}: 0x{:08x} - 0x{:08x} [{}]", 
             i, segment.vaddr, segment.vaddr + segment.memsz, perms);
     }
+    Err(ElfError::InvalidEntryPoint)
 }
 
 /// Execute user program at the given physical address
-unsafe fn execute_user_program(entry_point: usize) {
-    
-    console_println!("[i] Setting up execution environment...");
-    
+unsafe fn execute_user_program(entry_point: usize) -> ElfResult<i32> {
     // Validate entry point alignment (RISC-V requires 4-byte alignment)
     if entry_point % 4 != 0 {
-        console_println!("[x] Entry point 0x{:x} is not 4-byte aligned!", entry_point);
-        return;
+        err_println!("Entry point 0x{:x} is not 4-byte aligned!", entry_point);
+        return Err(ElfError::InvalidEntryPoint);
     }
     
     // Check if entry point looks reasonable (within our allocated memory)
     if entry_point < 0x80000000 || entry_point > 0x90000000 {
-        console_println!("[x] Entry point 0x{:x} looks suspicious!", entry_point);
-        return;
-    }
-    
-    // Examine the instructions at the entry point
-    console_println!("[i] Examining instructions at entry point:");
-    let instr_ptr = entry_point as *const u32;
-    for i in 0..4 {
-        let instr = core::ptr::read_volatile(instr_ptr.add(i));
-        console_println!("   0x{:08x}: 0x{:08x}", entry_point + (i * 4), instr);
+        err_println!("Entry point 0x{:x} looks suspicious!", entry_point);
+        return Err(ElfError::InvalidEntryPoint);
     }
     
-    // Allocate a simple stack for the user program (4KB)
-    if let Ok(stack_addr) = crate::memory::allocate_memory(4096, 8) {
-        let stack_addr = stack_addr.as_ptr() as usize;
-        let stack_top = stack_addr + 4096;
-        console_println!("[i] Allocated stack at 0x{:x}-0x{:x}", stack_addr, stack_top);
-        
-        console_println!("[i] About to execute user program...");
-        console_println!("   Entry point: 0x{:x}", entry_point);
-        console_println!("   Stack pointer: 0x{:x}", stack_top);
-        
-        console_println!("[i] Executing in user mode with syscall support...");
-        
-        let result = execute_with_syscall_support(entry_point);
-        
-        console_println!("[o] User program completed with result: {}", result);
-        
-        // Deallocate the stack
-        if let Some(ptr) = core::ptr::NonNull::new(stack_addr as *mut u8) {
-            crate::memory::deallocate_memory(ptr, 4096);
-        }
-    } else {
-        console_println!("[x] Failed to allocate stack for user program");
-    }
-} 
\ No newline at end of file
+    execute_with_syscall_support(entry_point)
+}
@@ -0,0 +1,108 @@
+//! Relocation of Position-Independent Executables
+//!
+//! PIE binaries (ET_DYN) are usually linked at address 0 and carry a table
+//! of the places that hold addresses. The loader moves the image to
+//! `USER_PIE_BASE` and adds the difference, the load bias, at each of those
+//! places. Only R_RISCV_RELATIVE is handled, which is all a static PIE
+//! needs; binaries that depend on shared libraries or bind symbols at run
+//! time need a dynamic linker and are refused.
+
+use super::constants::*;
+use super::error::{ElfError, ElfResult};
+use super::structures::{Elf64Dyn, Elf64ProgramHeader, Elf64Rela, RelocationTable};
+use crate::memory::mmu::{AddressSpace, USER_PIE_BASE};
+
+/// Size of one RELA entry
+pub const RELA_SIZE: usize = core::mem::size_of::<Elf64Rela>();
+
+/// Most bytes of the dynamic section looked at
+pub const MAX_DYNAMIC_SIZE: usize = 1024;
+
+/// Amount added to link addresses: 0 for ET_EXEC, which runs where it was
+/// linked; for ET_DYN, whatever moves its lowest page to `USER_PIE_BASE`
+pub fn load_bias(e_type: u16, loads: &[Elf64ProgramHeader]) -> u64 {
+    if e_type != ET_DYN {
+        return 0;
+    }
+    let lowest = loads.iter().map(|ph| ph.p_vaddr).min().unwrap_or(0) & !0xfff;
+    (USER_PIE_BASE as u64).wrapping_sub(lowest)
+}
+
+/// File offset of link address `vaddr`, if a PT_LOAD segment holds it in
+/// the file
+pub fn file_offset(loads: &[Elf64ProgramHeader], vaddr: u64) -> Option<usize> {
+    loads.iter()
+        .find(|ph| vaddr >= ph.p_vaddr && vaddr - ph.p_vaddr < ph.p_filesz)
+        .map(|ph| (ph.p_offset + (vaddr - ph.p_vaddr)) as usize)
+}
+
+/// Find the RELA table described by the dynamic section `dynamic`
+pub fn find_rela_table(dynamic: &[u8], loads: &[Elf64ProgramHeader]) -> ElfResult<Option<RelocationTable>> {
+    let mut rela = None;
+    let mut rela_size = 0;
+    let mut rela_entry_size = RELA_SIZE as u64;
+
+    for chunk in dynamic.chunks_exact(core::mem::size_of::<Elf64Dyn>()) {
+        let entry = unsafe { core::ptr::read_unaligned(chunk.as_ptr() as *const Elf64Dyn) };
+        match entry.d_tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(entry.d_val),
+            DT_RELASZ => rela_size = entry.d_val,
+            DT_RELAENT => rela_entry_size = entry.d_val,
+            // Shared libraries and lazily bound calls need a dynamic linker
+            DT_NEEDED => return Err(ElfError::UnsupportedRelocation),
+            DT_PLTRELSZ if entry.d_val > 0 => return Err(ElfError::UnsupportedRelocation),
+            _ => {}
+        }
+    }
+
+    let Some(rela) = rela else {
+        return Ok(None);
+    };
+    if rela_entry_size != RELA_SIZE as u64 {
+        return Err(ElfError::InvalidHeader);
+    }
+    let file_offset = file_offset(loads, rela).ok_or(ElfError::InvalidHeader)?;
+    Ok(Some(RelocationTable { file_offset, count: (rela_size / rela_entry_size) as usize }))
+}
+
+/// Apply the RELA entries in `entries` for a load bias of `bias`. `write`
+/// stores a 64-bit value at a relocated address.
+pub fn apply(entries: &[u8], bias: u64, mut write: impl FnMut(u64, u64) -> ElfResult<()>) -> ElfResult<()> {
+    for chunk in entries.chunks_exact(RELA_SIZE) {
+        let rela = unsafe { core::ptr::read_unaligned(chunk.as_ptr() as *const Elf64Rela) };
+        match rela.kind() {
+            R_RISCV_NONE => {}
+            R_RISCV_RELATIVE => write(rela.r_offset.wrapping_add(bias), bias.wrapping_add(rela.r_addend as u64))?,
+            _ => return Err(ElfError::UnsupportedRelocation),
+        }
+    }
+    Ok(())
+}
+
+/// Apply the relocations of a demand-paged program to its address space,
+/// reading them from the program's file. The pages they land in are read
+/// in now and stay resident.
+pub fn apply_from_file(space: &mut AddressSpace, path: &str, table: RelocationTable, bias: u64) -> ElfResult<()> {
+    const BATCH: usize = 64;
+    let mut batch = [0u8; BATCH * RELA_SIZE];
+    let mut done = 0;
+    while done < table.count {
+        let count = core::cmp::min(table.count - done, BATCH);
+        let entries = &mut batch[..count * RELA_SIZE];
+        let read = crate::filesystem::read_file_at(path, table.file_offset + done * RELA_SIZE, entries)
+            .map_err(|_| ElfError::LoadError)?;
+        if read != entries.len() {
+            return Err(ElfError::InvalidHeader);
+        }
+        apply(entries, bias, |vaddr, value| {
+            let vaddr = vaddr as usize;
+            space.fault_in(vaddr)
+                .and_then(|_| space.fault_in(vaddr.saturating_add(7)))
+                .and_then(|_| space.copy_to_user(vaddr, &value.to_le_bytes()))
+                .map_err(|_| ElfError::LoadError)
+        })?;
+        done += count;
+    }
+    Ok(())
+}
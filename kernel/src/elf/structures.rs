@@ -37,14 +37,46 @@ pub struct Elf64ProgramHeader {
     pub p_align: u64,
 }
 
+/// ELF64 dynamic section entry
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Elf64Dyn {
+    pub d_tag: i64,
+    pub d_val: u64,
+}
+
+/// ELF64 relocation with addend
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Elf64Rela {
+    pub r_offset: u64,
+    pub r_info: u64,
+    pub r_addend: i64,
+}
+
+impl Elf64Rela {
+    /// Relocation type, e.g. R_RISCV_RELATIVE
+    pub fn kind(&self) -> u32 {
+        self.r_info as u32
+    }
+}
+
+/// Where the relocations of a position-independent binary lie in its file
+#[derive(Debug, Clone, Copy)]
+pub struct RelocationTable {
+    pub file_offset: usize,
+    pub count: usize,
+}
+
 /// Represents a loaded ELF segment in memory
 #[derive(Debug)]
 pub struct ElfSegment {
     pub vaddr: u64,
     pub memsz: u64,
     pub flags: u32,
-    pub data_addr: Option<usize>, // Physical address where data is loaded
+    pub data_addr: Option<usize>, // Physical address where data is loaded, None if demand paged
     pub data_size: usize,
+    pub file_offset: usize,       // Offset of the segment data in the ELF file
 }
 
 /// Represents a fully loaded ELF binary
@@ -52,4 +84,18 @@ pub struct ElfSegment {
 pub struct LoadedElf {
     pub entry_point: u64,
     pub segments: heapless::Vec<ElfSegment, 8>,
+    pub source: Option<heapless::String<128>>, // File to page segments in from, if loaded lazily
+    pub load_bias: u64,                         // Added to link addresses of position-independent binaries
+    pub relocations: Option<RelocationTable>,   // Still to apply once the segments are mapped
+}
+
+impl LoadedElf {
+    /// First page past every segment, where the program's heap starts
+    pub fn heap_start(&self) -> usize {
+        let end = self.segments.iter()
+            .map(|segment| (segment.vaddr + segment.memsz) as usize)
+            .max()
+            .unwrap_or(0);
+        (end + 4095) & !4095
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,146 @@
+//! ELF Signature Verification
+//!
+//! Optional "secure exec" policy. When enabled, every binary handed to exec
+//! must carry an HMAC-SHA256 signature trailer computed with the signing
+//! key. Unsigned or tampered binaries are rejected.
+//!
+//! Signed binary layout:
+//!   [ ELF image ][ 32-byte HMAC-SHA256(key, ELF image) ][ "ELINSIG1" ]
+//!
+//! The key comes from the boot command line (`secexec.key=` and the key in
+//! hex). The bootloader takes `secexec.*` arguments only from the boot.cfg
+//! built into it, never from a `boot.cmdline` in the config store, so the
+//! key is never on a filesystem a program or the shell can read or write.
+//! It is hidden wherever the command line is printed or logged.
+//!
+//! The policy is on from boot with `secexec=on` on the command line, or
+//! `exec.secure=on` in the config store. Once on, it stays on until the
+//! next reboot, and the `config` command will not change the store key to
+//! turn it off. The store is a file on the root filesystem, though, so
+//! whoever can write to that can keep the policy off at the next boot
+//! unless boot.cfg turns it on; they cannot change the key.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::Vec;
+use spin::Mutex;
+use elinos_common::crypto::{HmacSha256, Mac};
+use elinos_common::{info_println, warn_println};
+use crate::filesystem::{configstore, FilesystemResult};
+use super::error::{ElfError, ElfResult};
+
+/// Magic marking the end of a signature trailer
+pub const SIGNATURE_MAGIC: &[u8; 8] = b"ELINSIG1";
+/// Size of the HMAC-SHA256 tag
+pub const SIGNATURE_TAG_SIZE: usize = 32;
+/// Total trailer size appended to signed binaries
+pub const SIGNATURE_TRAILER_SIZE: usize = SIGNATURE_TAG_SIZE + SIGNATURE_MAGIC.len();
+/// Boot argument giving the signing key, in hex
+pub const KEY_ARG: &str = elinos_common::cmdline::SECEXEC_KEY_ARG;
+/// Boot argument turning the policy on
+const ENABLE_ARG: &str = "secexec=on";
+/// Config store key turning the policy on at boot when it is `on`
+pub const STORE_KEY: &str = "exec.secure";
+/// Largest key accepted from the command line
+const MAX_KEY_SIZE: usize = 64;
+
+static SECURE_EXEC: AtomicBool = AtomicBool::new(false);
+static EXEC_KEY: Mutex<Vec<u8, MAX_KEY_SIZE>> = Mutex::new(Vec::new());
+
+/// Take the key from the boot command line and turn the policy on if the
+/// command line or the config store asks for it. Call once the config
+/// store is loaded.
+pub fn init() {
+    let cmdline = crate::boot_cmdline();
+    for arg in cmdline.split_whitespace() {
+        if let Some(hex) = arg.strip_prefix(KEY_ARG) {
+            match decode_hex(hex) {
+                Some(key) => *EXEC_KEY.lock() = key,
+                None => warn_println!("Ignoring {}: not a hex key of up to {} bytes", KEY_ARG, MAX_KEY_SIZE),
+            }
+        }
+    }
+    
+    let enabled = cmdline.split_whitespace().any(|arg| arg == ENABLE_ARG)
+        || configstore::get(STORE_KEY).as_deref() == Some("on");
+    if enabled {
+        SECURE_EXEC.store(true, Ordering::Relaxed);
+        if has_key() {
+            info_println!("Secure exec enabled");
+        } else {
+            warn_println!("Secure exec enabled without {}; no program will run", KEY_ARG);
+        }
+    }
+}
+
+/// Turn the policy on until reboot, and at every boot after. The key must
+/// have been given at boot.
+pub fn enable() -> FilesystemResult<()> {
+    SECURE_EXEC.store(true, Ordering::Relaxed);
+    configstore::set(STORE_KEY, "on")
+}
+
+/// Check whether the secure exec policy is enabled
+pub fn is_secure_exec() -> bool {
+    SECURE_EXEC.load(Ordering::Relaxed)
+}
+
+/// Whether a signing key was given at boot
+pub fn has_key() -> bool {
+    !EXEC_KEY.lock().is_empty()
+}
+
+/// The bytes of an even-length hex string, if it is one and fits a key
+fn decode_hex(hex: &str) -> Option<Vec<u8, MAX_KEY_SIZE>> {
+    let digits = hex.as_bytes();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return None;
+    }
+    let mut key = Vec::new();
+    for pair in digits.chunks(2) {
+        let pair = core::str::from_utf8(pair).ok()?;
+        key.push(u8::from_str_radix(pair, 16).ok()?).ok()?;
+    }
+    Some(key)
+}
+
+/// Split a binary into its ELF image and optional signature tag
+pub fn split_signature(data: &[u8]) -> (&[u8], Option<&[u8]>) {
+    if data.len() < SIGNATURE_TRAILER_SIZE || !data.ends_with(SIGNATURE_MAGIC) {
+        return (data, None);
+    }
+
+    let image_len = data.len() - SIGNATURE_TRAILER_SIZE;
+    let tag = &data[image_len..image_len + SIGNATURE_TAG_SIZE];
+    (&data[..image_len], Some(tag))
+}
+
+/// Verify an HMAC-SHA256 signature over an ELF image
+pub fn verify_signature(image: &[u8], tag: &[u8], key: &[u8]) -> bool {
+    let mut mac = HmacSha256::new(key);
+    mac.update(image);
+    mac.verify(tag)
+}
+
+/// Apply the exec policy to a binary and return the ELF image to load.
+///
+/// With secure exec disabled the signature trailer (if any) is stripped and
+/// the image is returned unchecked.
+pub fn check_exec_policy(data: &[u8]) -> ElfResult<&[u8]> {
+    let (image, tag) = split_signature(data);
+
+    if !is_secure_exec() {
+        return Ok(image);
+    }
+
+    let tag = tag.ok_or(ElfError::SignatureMissing)?;
+    let key = EXEC_KEY.lock();
+    if key.is_empty() {
+        return Err(ElfError::KeyUnavailable);
+    }
+
+    if verify_signature(image, tag, &key) {
+        Ok(image)
+    } else {
+        Err(ElfError::SignatureInvalid)
+    }
+}
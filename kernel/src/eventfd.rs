@@ -0,0 +1,158 @@
+//! Event file descriptors
+//!
+//! An eventfd is a 64-bit counter behind a descriptor, for one side to
+//! signal another without a pipe's buffer. Writing a native-endian u64
+//! adds it to the counter; reading takes the whole counter and zeroes it,
+//! or in semaphore mode takes 1. A read waits while the counter is 0 and a
+//! write while it would pass `MAX_COUNT`, unless the descriptor is
+//! non-blocking; `ppoll` reports it readable while the counter is above 0
+//! and writable while 1 more fits.
+//!
+//! The kernel signals eventfds too: an eventfd can watch kernel event
+//! sources, such as automount mounting or unmounting a disk, and kernel
+//! code calls `notify` to add 1 to every eventfd watching the source it has
+//! news from. That never waits; a full counter stays at `MAX_COUNT`.
+
+use heapless::Vec;
+use spin::Mutex;
+
+/// Event descriptors that can be open at once
+const MAX_EVENTFDS: usize = 16;
+
+/// Largest value the counter can hold
+pub const MAX_COUNT: u64 = u64::MAX - 1;
+
+/// Kernel event sources an eventfd can watch, as bits
+pub mod source {
+    /// Automount mounted or unmounted a disk
+    pub const MOUNTS: u32 = 1 << 0;
+
+    /// Every source there is
+    pub const ALL: u32 = MOUNTS;
+}
+
+/// An open event descriptor
+struct EventFd {
+    fd: i32,
+    counter: u64,
+    semaphore: bool,
+    nonblocking: bool,
+    /// Sources from `source` that signal it
+    watching: u32,
+}
+
+impl EventFd {
+    /// Add `value` as the kernel does, without waiting for room
+    fn signal(&mut self, value: u64) {
+        self.counter = self.counter.saturating_add(value).min(MAX_COUNT);
+    }
+}
+
+/// Why an eventfd operation failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventError {
+    NoEventFd,
+    TooManyEventFds,
+    /// A non-blocking read found the counter at 0, or a non-blocking write
+    /// would have overflowed it
+    WouldBlock,
+    /// u64::MAX was written, or an unknown source watched
+    Invalid,
+}
+
+static EVENTFDS: Mutex<Vec<EventFd, MAX_EVENTFDS>> = Mutex::new(Vec::new());
+
+/// Run `f` on eventfd `fd`
+fn with_eventfd<T>(fd: i32, f: impl FnOnce(&mut EventFd) -> T) -> Result<T, EventError> {
+    let mut eventfds = EVENTFDS.lock();
+    let eventfd = eventfds.iter_mut().find(|e| e.fd == fd).ok_or(EventError::NoEventFd)?;
+    Ok(f(eventfd))
+}
+
+/// Whether `fd` is an open eventfd
+pub fn is_eventfd(fd: i32) -> bool {
+    EVENTFDS.lock().iter().any(|e| e.fd == fd)
+}
+
+/// Create an eventfd for descriptor `fd` with its counter at `initial`
+pub fn create(fd: i32, initial: u32, semaphore: bool, nonblocking: bool) -> Result<(), EventError> {
+    EVENTFDS.lock().push(EventFd { fd, counter: initial as u64, semaphore, nonblocking, watching: 0 })
+        .map_err(|_| EventError::TooManyEventFds)
+}
+
+/// Close the eventfd for `fd`. Returns false if there is none.
+pub fn close(fd: i32) -> bool {
+    let mut eventfds = EVENTFDS.lock();
+    match eventfds.iter().position(|e| e.fd == fd) {
+        Some(index) => {
+            eventfds.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Take the counter of eventfd `fd`, or 1 of it in semaphore mode, waiting
+/// for it to be above 0 unless the descriptor is non-blocking
+pub fn read(fd: i32) -> Result<u64, EventError> {
+    let nonblocking = with_eventfd(fd, |eventfd| eventfd.nonblocking)?;
+    let take = || with_eventfd(fd, |eventfd| match eventfd.counter {
+        0 => None,
+        _ if eventfd.semaphore => {
+            eventfd.counter -= 1;
+            Some(1)
+        }
+        _ => Some(core::mem::take(&mut eventfd.counter)),
+    });
+    if nonblocking {
+        return take()?.ok_or(EventError::WouldBlock);
+    }
+    // Only an interrupt or the kernel can signal it meanwhile
+    crate::trap::wait_until(u64::MAX, || take().transpose()).unwrap_or(Err(EventError::NoEventFd))
+}
+
+/// Add `value` to the counter of eventfd `fd`, waiting for room unless the
+/// descriptor is non-blocking
+pub fn write(fd: i32, value: u64) -> Result<(), EventError> {
+    if value == u64::MAX {
+        return Err(EventError::Invalid);
+    }
+    let nonblocking = with_eventfd(fd, |eventfd| eventfd.nonblocking)?;
+    let add = || with_eventfd(fd, |eventfd| {
+        if eventfd.counter > MAX_COUNT - value {
+            return None;
+        }
+        eventfd.counter += value;
+        Some(())
+    });
+    if nonblocking {
+        return add()?.ok_or(EventError::WouldBlock);
+    }
+    crate::trap::wait_until(u64::MAX, || add().transpose()).unwrap_or(Err(EventError::NoEventFd))
+}
+
+/// Have eventfd `fd` signalled by the kernel event sources in `sources`,
+/// replacing those it watched before; 0 stops watching
+pub fn watch(fd: i32, sources: u32) -> Result<(), EventError> {
+    if sources & !source::ALL != 0 {
+        return Err(EventError::Invalid);
+    }
+    with_eventfd(fd, |eventfd| eventfd.watching = sources)
+}
+
+/// Signal every eventfd watching one of `sources`
+pub fn notify(sources: u32) {
+    for eventfd in EVENTFDS.lock().iter_mut().filter(|e| e.watching & sources != 0) {
+        eventfd.signal(1);
+    }
+}
+
+/// Whether eventfd `fd` can be read without waiting
+pub fn readable(fd: i32) -> bool {
+    with_eventfd(fd, |eventfd| eventfd.counter > 0).unwrap_or(false)
+}
+
+/// Whether 1 can be written to eventfd `fd` without waiting
+pub fn writable(fd: i32) -> bool {
+    with_eventfd(fd, |eventfd| eventfd.counter < MAX_COUNT).unwrap_or(false)
+}
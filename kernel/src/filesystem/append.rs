@@ -0,0 +1,121 @@
+//! Buffered appends for files that grow a line at a time
+//!
+//! Logs and the shell history gain a few bytes at a time. Rewriting the
+//! whole file for each of those, or even writing its last block, costs a
+//! disk write per line. `append_file` instead collects the bytes in memory
+//! and adds them to the end of the file in one write once enough have
+//! gathered, when the shell is idle, on `sync` and before shutdown.
+//!
+//! The module-level read and write functions flush a file's pending bytes
+//! before they touch it, so the shell sees what was appended; programs
+//! reading through their own file descriptors see it after the next flush.
+
+use heapless::{String, Vec};
+use spin::Mutex;
+
+use super::traits::FileSystem;
+use super::{FilesystemError, FilesystemResult, FILESYSTEM};
+
+/// Files that can have appends waiting at once
+const APPEND_SLOTS: usize = 4;
+
+/// Bytes waiting for one file before they are written
+pub const APPEND_BUFFER_SIZE: usize = 1024;
+
+/// Bytes waiting to go to the end of a file
+struct PendingAppend {
+    path: String<64>,
+    data: Vec<u8, APPEND_BUFFER_SIZE>,
+}
+
+static PENDING: Mutex<Vec<PendingAppend, APPEND_SLOTS>> = Mutex::new(Vec::new());
+
+/// Add `data` to the end of `path`, creating the file if needed. The bytes
+/// may reach the disk later; see the module documentation.
+pub fn append_file(path: &str, data: &[u8]) -> FilesystemResult<()> {
+    let mut pending = PENDING.lock();
+
+    let slot = match pending.iter().position(|append| append.path == path) {
+        Some(slot) => slot,
+        None => {
+            if pending.is_full() {
+                // Make room by writing out whatever has waited longest
+                let oldest = pending.remove(0);
+                write_at_end(&oldest.path, &oldest.data)?;
+            }
+            let path = String::try_from(path).map_err(|_| FilesystemError::FilenameTooLong)?;
+            let _ = pending.push(PendingAppend { path, data: Vec::new() });
+            pending.len() - 1
+        }
+    };
+
+    let append = &mut pending[slot];
+    if append.data.len() + data.len() > APPEND_BUFFER_SIZE {
+        write_at_end(&append.path, &append.data)?;
+        append.data.clear();
+    }
+    if append.data.extend_from_slice(data).is_err() {
+        // Too big to buffer at all
+        return write_at_end(path, data);
+    }
+    Ok(())
+}
+
+/// Write out the pending appends of `path`, if any
+pub fn flush_file(path: &str) -> FilesystemResult<()> {
+    let mut pending = PENDING.lock();
+    match pending.iter().position(|append| append.path == path) {
+        Some(slot) => {
+            let append = pending.remove(slot);
+            write_at_end(&append.path, &append.data)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Write out every pending append. All files are tried; the first error is
+/// returned.
+pub fn flush_all() -> FilesystemResult<()> {
+    let mut pending = PENDING.lock();
+    let mut result = Ok(());
+    while let Some(append) = pending.pop() {
+        if let Err(e) = write_at_end(&append.path, &append.data) {
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+/// Size `path` will have once its pending appends are written
+pub fn end_of_file(path: &str) -> FilesystemResult<usize> {
+    let pending = PENDING.lock();
+    let waiting = pending.iter()
+        .find(|append| append.path == path)
+        .map_or(0, |append| append.data.len());
+    let size = FILESYSTEM.lock().get_file_size(path)?;
+    Ok(size + waiting)
+}
+
+/// Drop the pending appends of `path`, which is being deleted
+pub fn discard_file(path: &str) {
+    PENDING.lock().retain(|append| append.path != path);
+}
+
+/// Write `data` after the current end of `path`, creating it if needed
+fn write_at_end(path: &str, data: &[u8]) -> FilesystemResult<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let mut fs = FILESYSTEM.lock();
+    let entry = if fs.file_exists(path) {
+        fs.get_file_entry(path)?
+    } else {
+        fs.create_file(path)?
+    };
+    if entry.is_directory {
+        return Err(FilesystemError::IsADirectory);
+    }
+    let size = fs.get_file_size(path)?;
+    fs.write_file(&entry, size as u64, data)?;
+    Ok(())
+}
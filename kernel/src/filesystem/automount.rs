@@ -0,0 +1,202 @@
+//! Automounting of disks that appear while the system runs
+//!
+//! When hotplug attaches a block device, or a USB disk is found, each of
+//! its partitions holding an ext2 filesystem (or the whole disk, if it is
+//! not partitioned) is mounted on `/media/<label>`, or `/media/blkN[pM]`
+//! (`/media/usbN[pM]`) if it has no label. Mounts
+//! are read-only unless a rule in `/etc/automount` says otherwise. Each rule
+//! is a match, an action and for `mount` an optional mode; the first rule
+//! that matches the disk wins:
+//!
+//! ```text
+//! # match      action  mode
+//! label=BACKUP mount   rw
+//! uuid=2f1c... ignore
+//! blk3         ignore
+//! blk2p1       mount   rw
+//! usb0         mount   rw
+//! *            mount   ro
+//! ```
+//!
+//! A disk name matches all of its partitions. A disk that goes away is
+//! unmounted; anything not yet written to it is lost. Every mount and
+//! unmount signals the eventfds watching `eventfd::source::MOUNTS`.
+
+use core::fmt::Write;
+use elinos_common::{ok_println, err_println, warn_println, info_println};
+use heapless::String;
+
+use super::traits::FileSystem;
+use super::{FilesystemType, MountOptions, FILESYSTEM};
+use super::probe::VolumeId;
+use crate::virtio::Volume;
+use crate::virtio::hotplug::{self, HotplugEvent};
+use crate::virtio::mmio::VIRTIO_ID_BLOCK;
+
+/// Path of the automount rules file
+pub const AUTOMOUNT_RULES_PATH: &str = "/etc/automount";
+
+/// Directory new disks are mounted below
+pub const MEDIA_DIR: &str = "/media";
+
+/// What to do with a disk that appeared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Mount { read_only: bool },
+    Ignore,
+}
+
+/// Used when no rule matches
+const DEFAULT_ACTION: Action = Action::Mount { read_only: true };
+
+/// Whether the match word of a rule selects `volume`
+fn rule_matches(pattern: &str, volume: Volume, id: &VolumeId) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    if let Some(wanted) = pattern.strip_prefix("label=") {
+        return id.label.as_deref() == Some(wanted);
+    }
+    if let Some(wanted) = pattern.strip_prefix("uuid=") {
+        return !id.uuid.is_empty() && id.uuid.eq_ignore_ascii_case(wanted);
+    }
+    match crate::virtio::parse_volume_name(pattern) {
+        Some(named) if named.partition == 0 => named.device == volume.device,
+        Some(named) => named == volume,
+        None => false,
+    }
+}
+
+/// Look up what the rules say to do with `volume`
+fn action_for(volume: Volume, id: &VolumeId) -> Action {
+    let data = match super::read_file(AUTOMOUNT_RULES_PATH) {
+        Ok(data) => data,
+        Err(_) => return DEFAULT_ACTION,
+    };
+    let rules = match core::str::from_utf8(&data) {
+        Ok(rules) => rules,
+        Err(_) => {
+            warn_println!(target: "fs", "{} is not valid UTF-8, ignoring it", AUTOMOUNT_RULES_PATH);
+            return DEFAULT_ACTION;
+        }
+    };
+
+    for (number, line) in rules.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let pattern = match words.next() {
+            Some(pattern) => pattern,
+            None => continue,
+        };
+        let action = match (words.next(), words.next(), words.next()) {
+            (Some("ignore"), None, None) => Action::Ignore,
+            (Some("mount"), None | Some("ro"), None) => Action::Mount { read_only: true },
+            (Some("mount"), Some("rw"), None) => Action::Mount { read_only: false },
+            _ => {
+                warn_println!(target: "fs", "{}:{}: invalid rule, skipping it", AUTOMOUNT_RULES_PATH, number + 1);
+                continue;
+            }
+        };
+        if rule_matches(pattern, volume, id) {
+            return action;
+        }
+    }
+    DEFAULT_ACTION
+}
+
+/// Directory name for a label: anything but letters, digits, `-`, `_` and
+/// `.` becomes `_`
+fn sanitize_label(label: &str) -> String<16> {
+    let mut name = String::new();
+    for c in label.chars() {
+        let c = if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' };
+        let _ = name.push(c);
+    }
+    if name.chars().all(|c| c == '.') {
+        name.clear();
+    }
+    name
+}
+
+/// Mount a block device that just appeared, as the rules say
+fn device_added(index: usize) {
+    for volume in crate::virtio::block::partition::volumes(index) {
+        volume_added(volume);
+    }
+}
+
+/// Mount one disk or partition of a device that just appeared
+fn volume_added(volume: Volume) {
+    let id = match super::probe_volume(volume) {
+        Some(id) if id.fs_type == FilesystemType::Ext2 => id,
+        _ => {
+            info_println!(target: "fs", "{}: no supported filesystem, not mounting it", volume);
+            return;
+        }
+    };
+    let label = id.label.as_deref();
+
+    let read_only = match action_for(volume, &id) {
+        Action::Mount { read_only } => read_only,
+        Action::Ignore => {
+            info_println!(target: "fs", "{}: ignored by {}", volume, AUTOMOUNT_RULES_PATH);
+            return;
+        }
+    };
+
+    let mut fs = FILESYSTEM.lock();
+
+    // Fall back to the device name if the label is unusable or taken
+    let mut path = String::<64>::new();
+    let name = label.map(sanitize_label).unwrap_or_default();
+    let _ = write!(path, "{}/{}", MEDIA_DIR, name);
+    if name.is_empty() || fs.mounts().iter().any(|mount| mount.path == path) {
+        path.clear();
+        let _ = write!(path, "{}/{}", MEDIA_DIR, volume);
+    }
+
+    // Both may already exist
+    let _ = fs.create_directory(MEDIA_DIR);
+    let _ = fs.create_directory(&path);
+
+    match fs.mount_at(volume, &path, MountOptions { read_only, ..MountOptions::default() }) {
+        Ok(()) => {
+            ok_println!(target: "fs", "{} ({}) mounted {} on {}",
+                volume,
+                label.unwrap_or("no label"),
+                if read_only { "read-only" } else { "read-write" },
+                path);
+            crate::eventfd::notify(crate::eventfd::source::MOUNTS);
+        }
+        Err(e) => err_println!(target: "fs", "{}: could not mount on {}: {}", volume, path, e),
+    }
+}
+
+/// Unmount whatever was on block devices that went away
+fn device_removed() {
+    let dropped = FILESYSTEM.lock().drop_unavailable_mounts();
+    for path in &dropped {
+        warn_println!(target: "fs", "Disk removed, {} unmounted", path);
+    }
+    if !dropped.is_empty() {
+        crate::eventfd::notify(crate::eventfd::source::MOUNTS);
+    }
+}
+
+/// Act on the hotplug events queued since the last call
+pub fn handle_events() {
+    while let Some(event) = hotplug::take_event() {
+        match event {
+            HotplugEvent::Added { base, device_id: VIRTIO_ID_BLOCK, driver: Some(_) } => {
+                if let Some(index) = crate::virtio::block::find_by_base(base) {
+                    device_added(index);
+                }
+            }
+            HotplugEvent::Removed { device_id: VIRTIO_ID_BLOCK, .. } => device_removed(),
+            _ => {}
+        }
+    }
+    while let Some(disk) = crate::usb::storage::take_new() {
+        device_added(crate::virtio::block::partition::USB_DISK_BASE + disk);
+    }
+}
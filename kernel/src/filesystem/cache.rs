@@ -0,0 +1,311 @@
+//! Sector cache between the filesystems and the block devices
+//!
+//! Filesystems read and write through `read_blocks` / `write_blocks` here
+//! instead of locking a device directly. I/O goes to the selected volume,
+//! a whole disk or a partition: the root filesystem's, or a mounted
+//! filesystem's while `with_volume` runs its operation. Sector numbers are
+//! relative to the volume and must lie within it. Sectors of all devices
+//! share the cache, keyed by device and sector. The cache runs write-back unless
+//! the kernel command line says `blockcache=writethrough`; dirty sectors are
+//! written out by `sync`, at shutdown and reboot, and by `write_back_expired`
+//! once they have waited `fs.blockcache.writeback_secs`. `blockcache.verify=log` or
+//! `blockcache.verify=panic` keeps a checksum of each cached sector and
+//! reports, or panics on, sectors that change while cached. The size,
+//! mode and checksum policy can also be changed at run time through the
+//! `fs.blockcache.*` sysctls.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use elinos_common::blockcache::{BlockCache, CacheMode, SectorDevice, VerifyPolicy, SECTOR_SIZE};
+use elinos_common::{console_println, timer, warn_println};
+use crate::sysctl::{Kind, Tunable};
+use crate::virtio::{DiskError, Volume, block_device};
+use crate::virtio::block::partition;
+use super::{FilesystemError, FilesystemResult};
+
+/// Most cached sectors (64 KB)
+const CACHE_SECTORS: usize = 128;
+
+static BLOCK_CACHE: Mutex<BlockCache<CACHE_SECTORS>> = Mutex::new(BlockCache::new(CacheMode::WriteBack));
+
+/// Seconds a sector stays dirty before the idle shell writes it; 0 leaves
+/// it for `sync`, eviction and shutdown
+static WRITEBACK_SECS: AtomicU64 = AtomicU64::new(5);
+
+/// The volume filesystem I/O goes to, and where it lies on its disk
+#[derive(Clone, Copy)]
+struct Selection {
+    volume: Volume,
+    start: u64,
+    sectors: u64,
+}
+
+static SELECTED: Mutex<Selection> = Mutex::new(Selection { volume: Volume::disk(0), start: 0, sectors: u64::MAX });
+
+/// Cache keys hold the device index above this bit and the sector below
+const DEVICE_SHIFT: u32 = 56;
+
+fn cache_key(device: usize, sector: u64) -> u64 {
+    ((device as u64) << DEVICE_SHIFT) | sector
+}
+
+/// All block devices, addressed by cache key
+struct BlockDevices;
+
+impl BlockDevices {
+    fn with_device<T>(key: u64, f: impl FnOnce(&mut dyn SectorDevice<Error = DiskError>, u64) -> Result<T, DiskError>) -> Result<T, DiskError> {
+        partition::with_device((key >> DEVICE_SHIFT) as usize, |device| f(device, key & ((1 << DEVICE_SHIFT) - 1)))
+    }
+}
+
+impl SectorDevice for BlockDevices {
+    type Error = DiskError;
+
+    fn read_sector(&mut self, key: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        Self::with_device(key, |device, sector| device.read_sector(sector, buf))
+    }
+
+    fn write_sector(&mut self, key: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        Self::with_device(key, |device, sector| device.write_sector(sector, buf))
+    }
+
+    fn read_sectors(&mut self, key: u64, buf: &mut [u8]) -> Result<(), DiskError> {
+        Self::with_device(key, |device, sector| device.read_sectors(sector, buf))
+    }
+
+    fn write_sectors(&mut self, key: u64, buf: &[u8]) -> Result<(), DiskError> {
+        Self::with_device(key, |device, sector| device.write_sectors(sector, buf))
+    }
+}
+
+/// Pick the cache mode and checksum policy from the kernel command line
+pub fn init() {
+    let cmdline = crate::boot_cmdline();
+    let mode = match cmdline.split_whitespace().find_map(|arg| arg.strip_prefix("blockcache=")) {
+        None | Some("writeback") => CacheMode::WriteBack,
+        Some("writethrough") => CacheMode::WriteThrough,
+        Some(other) => {
+            warn_println!(target: "fs", "Unknown blockcache mode '{}', using write-back", other);
+            CacheMode::WriteBack
+        }
+    };
+    let verify = match cmdline.split_whitespace().find_map(|arg| arg.strip_prefix("blockcache.verify=")) {
+        None | Some("off") => VerifyPolicy::Off,
+        Some("log") => VerifyPolicy::Log,
+        Some("panic") => VerifyPolicy::Panic,
+        Some(other) => {
+            warn_println!(target: "fs", "Unknown blockcache.verify policy '{}', not checking", other);
+            VerifyPolicy::Off
+        }
+    };
+    let mut cache = BLOCK_CACHE.lock();
+    cache.set_mode(&mut BlockDevices, mode).ok();
+    cache.set_verify_policy(verify);
+}
+
+/// Block cache parameters for `sysctl`
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "fs.blockcache.sectors",
+        description: "Sectors the block cache holds; shrinking writes back the ones dropped",
+        kind: Kind::Int { min: 1, max: CACHE_SECTORS as i64, get: get_sectors, set: set_sectors },
+    },
+    Tunable {
+        name: "fs.blockcache.mode",
+        description: "When writes reach the disk",
+        kind: Kind::Choice { choices: &["writeback", "writethrough"], get: get_mode, set: set_mode },
+    },
+    Tunable {
+        name: "fs.blockcache.verify",
+        description: "What to do about cached sectors that no longer match their checksum",
+        kind: Kind::Choice { choices: &["off", "log", "panic"], get: get_verify, set: set_verify },
+    },
+    Tunable {
+        name: "fs.blockcache.writeback_secs",
+        description: "Seconds a dirty sector waits before it is written in the background (0: only on sync)",
+        kind: Kind::Int { min: 0, max: 3600, get: get_writeback_secs, set: set_writeback_secs },
+    },
+];
+
+fn get_sectors() -> i64 {
+    BLOCK_CACHE.lock().limit() as i64
+}
+
+fn set_sectors(sectors: i64) -> Result<(), &'static str> {
+    BLOCK_CACHE.lock().set_limit(&mut BlockDevices, sectors as usize)
+        .map_err(|_| "Failed to write back cached sectors")
+}
+
+fn get_mode() -> &'static str {
+    match BLOCK_CACHE.lock().mode() {
+        CacheMode::WriteBack => "writeback",
+        CacheMode::WriteThrough => "writethrough",
+    }
+}
+
+fn set_mode(name: &'static str) -> Result<(), &'static str> {
+    let mode = if name == "writethrough" { CacheMode::WriteThrough } else { CacheMode::WriteBack };
+    BLOCK_CACHE.lock().set_mode(&mut BlockDevices, mode)
+        .map_err(|_| "Failed to write back cached sectors")
+}
+
+fn get_writeback_secs() -> i64 {
+    WRITEBACK_SECS.load(Ordering::Relaxed) as i64
+}
+
+fn set_writeback_secs(secs: i64) -> Result<(), &'static str> {
+    WRITEBACK_SECS.store(secs as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+fn get_verify() -> &'static str {
+    BLOCK_CACHE.lock().verify_policy().name()
+}
+
+fn set_verify(name: &'static str) -> Result<(), &'static str> {
+    let policy = match name {
+        "log" => VerifyPolicy::Log,
+        "panic" => VerifyPolicy::Panic,
+        _ => VerifyPolicy::Off,
+    };
+    BLOCK_CACHE.lock().set_verify_policy(policy);
+    Ok(())
+}
+
+/// The volume filesystem I/O goes to
+pub fn selected_volume() -> Volume {
+    SELECTED.lock().volume
+}
+
+fn selection(volume: Volume) -> FilesystemResult<Selection> {
+    let (start, sectors) = volume.extent().ok_or(FilesystemError::DeviceError)?;
+    Ok(Selection { volume, start, sectors })
+}
+
+/// Send filesystem I/O to `volume` from now on
+pub fn select_volume(volume: Volume) -> FilesystemResult<()> {
+    *SELECTED.lock() = selection(volume)?;
+    Ok(())
+}
+
+/// Run `f` with filesystem I/O going to `volume`. Callers hold the
+/// filesystem lock, so no other I/O can see the switch.
+pub fn with_volume<T>(volume: Volume, f: impl FnOnce() -> FilesystemResult<T>) -> FilesystemResult<T> {
+    let selected = selection(volume)?;
+    let previous = core::mem::replace(&mut *SELECTED.lock(), selected);
+    let result = f();
+    *SELECTED.lock() = previous;
+    result
+}
+
+/// Drop every cached sector of block device `index`, written out or not,
+/// e.g. after the device went away
+pub fn forget_device(index: usize) {
+    BLOCK_CACHE.lock().invalidate_matching(|key| (key >> DEVICE_SHIFT) as usize == index);
+}
+
+/// Drop the cached copies of `count` sectors of block device `index` from
+/// `first`, counted from the start of the disk, e.g. once something other
+/// than the filesystems writes them
+pub fn forget_sectors(index: usize, first: u64, count: u64) {
+    let first = cache_key(index, first);
+    BLOCK_CACHE.lock().invalidate_matching(|key| key >= first && key < first + count);
+}
+
+/// Run `f` with the cache, for I/O on `count` sectors of the selected
+/// volume from `start_sector`. `f` gets the cache key of the volume's first
+/// sector.
+fn with_cache<T>(start_sector: u64, count: usize, f: impl FnOnce(&mut BlockCache<CACHE_SECTORS>, &mut BlockDevices, u64) -> Result<T, DiskError>) -> FilesystemResult<T> {
+    // Always cache before device, so the two locks cannot deadlock
+    let mut cache = BLOCK_CACHE.lock();
+    let selected = *SELECTED.lock();
+    if partition::capacity(selected.volume.device).is_none() {
+        return Err(FilesystemError::DeviceError);
+    }
+    if start_sector.saturating_add(count as u64) > selected.sectors {
+        return Err(FilesystemError::IoError);
+    }
+    let base = cache_key(selected.volume.device, selected.start);
+    f(&mut cache, &mut BlockDevices, base).map_err(|_| FilesystemError::IoError)
+}
+
+/// Read whole sectors starting at `start_sector` into `buffer`
+pub fn read_blocks(start_sector: u64, buffer: &mut [u8]) -> FilesystemResult<()> {
+    if buffer.len() % SECTOR_SIZE != 0 {
+        return Err(FilesystemError::IoError);
+    }
+    with_cache(start_sector, buffer.len() / SECTOR_SIZE, |cache, devices, base| {
+        cache.read_many(devices, base + start_sector, buffer)
+    })
+}
+
+/// Write whole sectors from `buffer` starting at `start_sector`
+pub fn write_blocks(start_sector: u64, buffer: &[u8]) -> FilesystemResult<()> {
+    if buffer.len() % SECTOR_SIZE != 0 {
+        return Err(FilesystemError::IoError);
+    }
+    with_cache(start_sector, buffer.len() / SECTOR_SIZE, |cache, devices, base| {
+        cache.write_many(devices, base + start_sector, buffer)
+    })
+}
+
+/// Drop `count` sectors of the selected volume from `start_sector` from
+/// the cache and, if the device can, discard them. Returns whether the
+/// device was told; the sectors should have been written out first, as
+/// dirty copies are dropped.
+pub fn discard_blocks(start_sector: u64, count: u64) -> FilesystemResult<bool> {
+    let mut cache = BLOCK_CACHE.lock();
+    let selected = *SELECTED.lock();
+    if start_sector.saturating_add(count) > selected.sectors {
+        return Err(FilesystemError::IoError);
+    }
+    let first = cache_key(selected.volume.device, selected.start + start_sector);
+    cache.invalidate_matching(|key| key >= first && key < first + count);
+
+    // USB disks are not told
+    let Some(device) = block_device(selected.volume.device) else {
+        return Ok(false);
+    };
+    let mut device = device.lock();
+    if !device.supports_discard() {
+        return Ok(false);
+    }
+    device.discard(selected.start + start_sector, count).map_err(|_| FilesystemError::IoError)?;
+    Ok(true)
+}
+
+/// Write all dirty sectors, of every device, to their devices
+pub fn flush() -> FilesystemResult<()> {
+    BLOCK_CACHE.lock().flush(&mut BlockDevices).map_err(|_| FilesystemError::IoError)
+}
+
+/// Write the sectors that have been dirty for `fs.blockcache.writeback_secs`;
+/// returns how many were written. Leaves the cache alone if it is in use.
+pub fn write_back_expired() -> FilesystemResult<usize> {
+    let secs = WRITEBACK_SECS.load(Ordering::Relaxed);
+    if secs == 0 {
+        return Ok(0);
+    }
+    let Some(mut cache) = BLOCK_CACHE.try_lock() else {
+        crate::health::count(crate::health::Event::LockContended);
+        return Ok(0);
+    };
+    cache.flush_expired(&mut BlockDevices, timer::ns_to_ticks(secs * 1_000_000_000))
+        .map_err(|_| FilesystemError::IoError)
+}
+
+/// Print the cache configuration and counters
+pub fn show_stats() {
+    let cache = BLOCK_CACHE.lock();
+    let stats = cache.stats();
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = if lookups == 0 { 0 } else { stats.hits * 100 / lookups };
+    console_println!("   Block cache: {} of {} sectors, {}", cache.limit(), cache.capacity(), cache.mode().name());
+    console_println!("   Hits: {}  Misses: {}  Hit rate: {}%", stats.hits, stats.misses, hit_rate);
+    console_println!("   Checksums: {}  Mismatches: {}", cache.verify_policy().name(), stats.checksum_mismatches);
+    console_println!("   Dirty: {}  Written back: {}", cache.dirty_count(), stats.writebacks);
+    match WRITEBACK_SECS.load(Ordering::Relaxed) {
+        0 => console_println!("   Background write-back: off"),
+        secs => console_println!("   Background write-back: after {} s", secs),
+    }
+}
@@ -0,0 +1,172 @@
+//! The key-value config store on the root filesystem
+//!
+//! Settings that must outlive a reboot and must never be half written,
+//! such as the boot slot and network addresses, live here rather than in
+//! the shell environment. Every update is a transaction: the new text goes
+//! to the spare slot of the store file, then a one-sector commit record
+//! switches to it; see `elinos_common::configstore` for the format. The
+//! sectors are written straight to the file's blocks, so no other metadata
+//! changes along the way.
+
+use heapless::{String, Vec};
+use spin::Mutex;
+use elinos_common::blockcache::SECTOR_SIZE;
+use elinos_common::configstore::{self as format, Record, MAX_DATA, SLOT_SIZE, STORE_PATH, STORE_SIZE};
+use elinos_common::warn_println;
+
+pub use elinos_common::configstore::{MAX_KEY_LEN, MAX_VALUE_LEN, is_valid_key, is_valid_value};
+
+use super::traits::FileSystem;
+use super::{cache, FilesystemError, FilesystemResult, FILESYSTEM};
+
+/// Most entries `entries` returns
+pub const MAX_ENTRIES: usize = 32;
+
+/// The committed store, as loaded or last written
+struct Store {
+    seq: u32,
+    slot: usize,
+    data: Vec<u8, MAX_DATA>,
+}
+
+impl Store {
+    const fn empty() -> Self {
+        // The first commit goes to slot 0
+        Store { seq: 0, slot: 1, data: Vec::new() }
+    }
+}
+
+static STORE: Mutex<Store> = Mutex::new(Store::empty());
+
+/// What `load` found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadOutcome {
+    /// No store yet
+    Empty,
+    /// The committed text
+    Committed,
+    /// The record was torn or pointed at a damaged slot; the newest intact
+    /// slot was taken and the record written again
+    Recovered,
+}
+
+/// Read the store from disk, recovering from an interrupted update
+pub fn load() -> FilesystemResult<LoadOutcome> {
+    let mut store = STORE.lock();
+    *store = Store::empty();
+    if !super::file_exists(STORE_PATH) {
+        return Ok(LoadOutcome::Empty);
+    }
+
+    let mut image = [0u8; STORE_SIZE];
+    super::read_file_at(STORE_PATH, 0, &mut image)?;
+    let Some(committed) = format::select(&image) else {
+        return Ok(LoadOutcome::Empty);
+    };
+    store.seq = committed.seq;
+    store.slot = committed.slot;
+    let _ = store.data.extend_from_slice(committed.data);
+
+    if !committed.recovered {
+        return Ok(LoadOutcome::Committed);
+    }
+    warn_println!(target: "fs", "Config store: last update was interrupted, using slot {} (#{})", committed.slot, committed.seq);
+    write_record(Record { seq: committed.seq, slot: committed.slot })?;
+    Ok(LoadOutcome::Recovered)
+}
+
+/// The value of `key`
+pub fn get(key: &str) -> Option<String<MAX_VALUE_LEN>> {
+    let store = STORE.lock();
+    format::lookup(&store.data, key).and_then(|value| String::try_from(value).ok())
+}
+
+/// All entries, in the order they are stored
+pub fn entries() -> Vec<(String<MAX_KEY_LEN>, String<MAX_VALUE_LEN>), MAX_ENTRIES> {
+    let store = STORE.lock();
+    let mut entries = Vec::new();
+    for (key, value) in format::entries(&store.data) {
+        if let (Ok(key), Ok(value)) = (String::try_from(key), String::try_from(value)) {
+            if entries.push((key, value)).is_err() {
+                break;
+            }
+        }
+    }
+    entries
+}
+
+/// Set `key` to `value`
+pub fn set(key: &str, value: &str) -> FilesystemResult<()> {
+    update(&[(key, Some(value))])
+}
+
+/// Remove `key`; false if it was not set
+pub fn unset(key: &str) -> FilesystemResult<bool> {
+    if get(key).is_none() {
+        return Ok(false);
+    }
+    update(&[(key, None)])?;
+    Ok(true)
+}
+
+/// Apply `changes` as one transaction: `Some(value)` sets a key, `None`
+/// removes it. After a power loss either all of them are in the store or
+/// none are.
+pub fn update(changes: &[(&str, Option<&str>)]) -> FilesystemResult<()> {
+    if changes.iter().any(|&(key, value)| !is_valid_key(key) || !value.map_or(true, is_valid_value)) {
+        return Err(invalid_entry());
+    }
+
+    let mut store = STORE.lock();
+    let mut text: Vec<u8, MAX_DATA> = Vec::new();
+    let kept = format::entries(&store.data).filter(|(key, _)| !changes.iter().any(|(name, _)| name == key));
+    let added = changes.iter().filter_map(|&(key, value)| value.map(|value| (key, value)));
+    for (key, value) in kept.chain(added) {
+        [key.as_bytes(), b"=", value.as_bytes(), b"\n"].iter()
+            .try_for_each(|part| text.extend_from_slice(part))
+            .map_err(|_| FilesystemError::FilesystemFull)?;
+    }
+
+    let seq = store.seq.wrapping_add(1);
+    let slot = 1 - store.slot;
+    let mut buffer = [0u8; SLOT_SIZE];
+    format::encode_slot(seq, &text, &mut buffer);
+
+    create_store_file()?;
+    // The new text must be on the disk before the record points at it
+    FILESYSTEM.lock().write_file_sectors(STORE_PATH, format::slot_sector(slot), &buffer)?;
+    cache::flush()?;
+    write_record(Record { seq, slot })?;
+
+    store.seq = seq;
+    store.slot = slot;
+    store.data = text;
+    Ok(())
+}
+
+/// Switch to a slot with a single sector write
+fn write_record(record: Record) -> FilesystemResult<()> {
+    let sector: [u8; SECTOR_SIZE] = record.encode();
+    FILESYSTEM.lock().write_file_sectors(STORE_PATH, 0, &sector)?;
+    cache::flush()
+}
+
+/// Create the store file at its full size, unless it is there already.
+/// A file of another size is not a store and is replaced.
+fn create_store_file() -> FilesystemResult<()> {
+    let mut fs = FILESYSTEM.lock();
+    if fs.file_exists(STORE_PATH) {
+        if fs.get_file_size(STORE_PATH)? == STORE_SIZE {
+            return Ok(());
+        }
+        fs.delete_file(STORE_PATH)?;
+    }
+    let entry = fs.create_file(STORE_PATH)?;
+    // Zeros, not a hole: every sector needs a block to be written in place
+    fs.write_file(&entry, 0, &[0u8; STORE_SIZE])?;
+    Ok(())
+}
+
+fn invalid_entry() -> FilesystemError {
+    FilesystemError::Other(String::try_from("Invalid config key or value").unwrap_or_default())
+}
@@ -0,0 +1,313 @@
+//! Device files under /dev
+//!
+//! devfs is not on any disk: its nodes are made up from the devices the
+//! kernel has, so programs can read and write them by path like any file.
+//!
+//! ```text
+//! console   the console TTY; reads wait for input
+//! null      reads nothing, swallows writes
+//! zero      reads zero bytes, swallows writes
+//! fb0       framebuffer memory, once graphics is up
+//! input     events of the VirtIO input device; reads wait for one
+//! vda       block device blk0 as raw bytes; vdb is blk1, and so on
+//! vda1      partition 1 of blk0
+//! ```
+//!
+//! Block device I/O goes through the sector cache, so it sees what the
+//! mounted filesystems wrote and they see what it wrote. Callers hold the
+//! filesystem lock, as for any cache I/O.
+
+use core::fmt::Write;
+use heapless::{String, Vec};
+use elinos_common::blockcache::SECTOR_SIZE;
+use elinos_common::console_print;
+
+use super::cache;
+use super::traits::{DirListing, FileEntry, FilesystemError, FilesystemResult, DIR_ENTRY_CACHE};
+use crate::virtio::{Volume, MAX_BLOCK_DEVICES};
+
+/// Where devfs appears in the tree
+pub const DEVFS_PATH: &str = "/dev";
+
+/// `FileEntry::mount` of device files
+pub const DEVFS_MOUNT: usize = usize::MAX;
+
+/// Most nodes devfs lists: the fixed ones, then each disk and its partitions
+pub const MAX_NODES: usize = 32;
+
+/// A device file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Console,
+    Null,
+    Zero,
+    Framebuffer,
+    Input,
+    Block(Volume),
+}
+
+impl Node {
+    /// Node for a name within /dev
+    pub fn from_name(name: &str) -> Option<Node> {
+        match name {
+            "console" => Some(Node::Console),
+            "null" => Some(Node::Null),
+            "zero" => Some(Node::Zero),
+            "fb0" => crate::graphics::get_dimensions().ok().map(|_| Node::Framebuffer),
+            "input" => crate::input::device_present().then_some(Node::Input),
+            _ => {
+                let volume = parse_block_name(name)?;
+                volume.extent()?;
+                Some(Node::Block(volume))
+            }
+        }
+    }
+
+    /// Name within /dev
+    pub fn name(&self) -> String<16> {
+        let mut name = String::new();
+        let _ = match self {
+            Node::Console => name.push_str("console"),
+            Node::Null => name.push_str("null"),
+            Node::Zero => name.push_str("zero"),
+            Node::Framebuffer => name.push_str("fb0"),
+            Node::Input => name.push_str("input"),
+            Node::Block(volume) => write_block_name(&mut name, *volume).map_err(|_| ()),
+        };
+        name
+    }
+
+    /// Size in bytes; 0 for character devices
+    pub fn size(&self) -> usize {
+        match self {
+            Node::Console | Node::Null | Node::Zero | Node::Input => 0,
+            Node::Framebuffer => crate::graphics::framebuffer_size().unwrap_or(0),
+            Node::Block(volume) => volume.extent()
+                .map_or(0, |(_, sectors)| (sectors as usize).saturating_mul(SECTOR_SIZE)),
+        }
+    }
+
+    /// Identifier kept in `FileEntry::inode`, so writes find the node again
+    fn id(&self) -> u64 {
+        match self {
+            Node::Console => 1,
+            Node::Null => 2,
+            Node::Zero => 3,
+            Node::Framebuffer => 4,
+            Node::Input => 5,
+            Node::Block(volume) => 0x100 + ((volume.device as u64) << 8) + volume.partition as u64,
+        }
+    }
+
+    fn from_id(id: u64) -> Option<Node> {
+        match id {
+            1 => Some(Node::Console),
+            2 => Some(Node::Null),
+            3 => Some(Node::Zero),
+            4 => Some(Node::Framebuffer),
+            5 => Some(Node::Input),
+            _ if id >= 0x100 => Some(Node::Block(Volume {
+                device: ((id - 0x100) >> 8) as usize,
+                partition: (id & 0xFF) as u8,
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// Linux-style name of a volume: blk0 is vda, blk1p2 is vdb2
+fn write_block_name(out: &mut String<16>, volume: Volume) -> core::fmt::Result {
+    let letter = (b'a' + volume.device as u8) as char;
+    match volume.partition {
+        0 => write!(out, "vd{}", letter),
+        partition => write!(out, "vd{}{}", letter, partition),
+    }
+}
+
+/// Volume named like "vda" or "vdb2"
+fn parse_block_name(name: &str) -> Option<Volume> {
+    let rest = name.strip_prefix("vd")?;
+    let letter = *rest.as_bytes().first()?;
+    if !letter.is_ascii_lowercase() {
+        return None;
+    }
+    let device = (letter - b'a') as usize;
+    if device >= MAX_BLOCK_DEVICES {
+        return None;
+    }
+    let partition = match &rest[1..] {
+        "" => 0,
+        number => number.parse::<u8>().ok().filter(|&number| number > 0)?,
+    };
+    Some(Volume { device, partition })
+}
+
+/// Name within /dev of `path`, if it is a device file; `Some("")` for /dev itself
+pub fn node_name(path: &str) -> Option<&str> {
+    let rest = path.strip_prefix(DEVFS_PATH)?;
+    if rest.is_empty() || rest == "/" {
+        return Some("");
+    }
+    rest.strip_prefix('/')
+}
+
+/// Node at `path`, which lies in /dev
+pub fn lookup(path: &str) -> FilesystemResult<Node> {
+    match node_name(path) {
+        Some("") => Err(FilesystemError::IsADirectory),
+        Some(name) => Node::from_name(name).ok_or(FilesystemError::FileNotFound),
+        None => Err(FilesystemError::FileNotFound),
+    }
+}
+
+/// Every node there is now
+pub fn nodes() -> Vec<Node, MAX_NODES> {
+    let mut nodes = Vec::new();
+    let _ = nodes.push(Node::Console);
+    let _ = nodes.push(Node::Null);
+    let _ = nodes.push(Node::Zero);
+    if crate::graphics::get_dimensions().is_ok() {
+        let _ = nodes.push(Node::Framebuffer);
+    }
+    if crate::input::device_present() {
+        let _ = nodes.push(Node::Input);
+    }
+    for device in 0..MAX_BLOCK_DEVICES {
+        if Volume::disk(device).extent().is_none() {
+            continue;
+        }
+        let _ = nodes.push(Node::Block(Volume::disk(device)));
+        if let Some(table) = crate::virtio::block::partition::partition_table(device) {
+            for partition in &table.partitions {
+                let _ = nodes.push(Node::Block(Volume { device, partition: partition.number }));
+            }
+        }
+    }
+    nodes
+}
+
+/// Listing of /dev: name, size and whether it is a directory
+pub fn list() -> DirListing {
+    let mut entries = DirListing::new(&DIR_ENTRY_CACHE);
+    for node in &nodes() {
+        if let Ok(name) = String::try_from(node.name().as_str()) {
+            let _ = entries.push((name, node.size(), false));
+        }
+    }
+    entries
+}
+
+/// Entry for `path`: /dev itself or a device file
+pub fn file_entry(path: &str) -> FilesystemResult<FileEntry> {
+    let mut entry = match node_name(path) {
+        Some("") => FileEntry::new_directory("dev", 0)?,
+        _ => {
+            let node = lookup(path)?;
+            FileEntry::new_file(node.name().as_str(), node.id(), node.size())?
+        }
+    };
+    entry.mount = DEVFS_MOUNT;
+    Ok(entry)
+}
+
+/// Read from `node` at byte `offset`; returns how many bytes were read, 0
+/// at the end of the device
+pub fn read(node: Node, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+    match node {
+        Node::Console => Ok(read_console(buffer)),
+        Node::Null => Ok(0),
+        Node::Zero => {
+            buffer.fill(0);
+            Ok(buffer.len())
+        }
+        Node::Framebuffer => crate::graphics::read_framebuffer(offset, buffer)
+            .map_err(|_| FilesystemError::DeviceError),
+        Node::Input => Ok(crate::input::read_events(buffer)),
+        Node::Block(volume) => read_volume(volume, offset, buffer),
+    }
+}
+
+/// Write to the node of `file` at byte `offset`; returns how many bytes
+/// were written
+pub fn write(file: &FileEntry, offset: usize, data: &[u8]) -> FilesystemResult<usize> {
+    match Node::from_id(file.inode).ok_or(FilesystemError::FileNotFound)? {
+        Node::Console => {
+            for &byte in data {
+                console_print!("{}", byte as char);
+            }
+            Ok(data.len())
+        }
+        Node::Null | Node::Zero => Ok(data.len()),
+        Node::Input => Err(FilesystemError::ReadOnly),
+        Node::Framebuffer => crate::graphics::write_framebuffer(offset, data)
+            .map_err(|_| FilesystemError::DeviceError),
+        Node::Block(volume) => write_volume(volume, offset, data),
+    }
+}
+
+/// Wait for a byte from the console, then take what else is there, up to
+/// the end of the line
+fn read_console(buffer: &mut [u8]) -> usize {
+    let mut count = 0;
+    while count < buffer.len() {
+        let byte = match elinos_common::tty::read_byte() {
+            Some(byte) => byte,
+            None if count == 0 => {
+                crate::watchdog::pet(crate::watchdog::Progress::Input);
+                elinos_common::uart::wait_for_input();
+                continue;
+            }
+            None => break,
+        };
+        buffer[count] = byte;
+        count += 1;
+        if byte == b'\n' || byte == b'\r' {
+            break;
+        }
+    }
+    count
+}
+
+/// Run `f` on each sector of `volume` that bytes `offset..offset+len` touch,
+/// with the sector number, the range within it and how far into the
+/// caller's buffer that range starts
+fn for_each_sector(volume: Volume, offset: usize, len: usize,
+                   mut f: impl FnMut(u64, core::ops::Range<usize>, usize) -> FilesystemResult<()>) -> FilesystemResult<usize> {
+    let (_, sectors) = volume.extent().ok_or(FilesystemError::DeviceError)?;
+    let size = (sectors as usize).saturating_mul(SECTOR_SIZE);
+    let end = offset.saturating_add(len).min(size);
+    let mut position = offset;
+    while position < end {
+        let within = position % SECTOR_SIZE;
+        let count = (SECTOR_SIZE - within).min(end - position);
+        f((position / SECTOR_SIZE) as u64, within..within + count, position - offset)?;
+        position += count;
+    }
+    Ok(end.saturating_sub(offset))
+}
+
+fn read_volume(volume: Volume, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+    cache::with_volume(volume, || {
+        let mut sector_buf = [0u8; SECTOR_SIZE];
+        for_each_sector(volume, offset, buffer.len(), |sector, range, done| {
+            cache::read_blocks(sector, &mut sector_buf)?;
+            buffer[done..done + range.len()].copy_from_slice(&sector_buf[range]);
+            Ok(())
+        })
+    })
+}
+
+fn write_volume(volume: Volume, offset: usize, data: &[u8]) -> FilesystemResult<usize> {
+    cache::with_volume(volume, || {
+        let mut sector_buf = [0u8; SECTOR_SIZE];
+        for_each_sector(volume, offset, data.len(), |sector, range, done| {
+            // Only part of the sector changes: keep the rest
+            if range.len() < SECTOR_SIZE {
+                cache::read_blocks(sector, &mut sector_buf)?;
+            }
+            let len = range.len();
+            sector_buf[range].copy_from_slice(&data[done..done + len]);
+            cache::write_blocks(sector, &sector_buf)
+        })
+    })
+}
@@ -2,7 +2,7 @@
 
 use super::superblock::SuperblockManager;
 use super::super::traits::FilesystemResult;
-use elinos_common::console_println;
+use elinos_common::debug_println;
 
 /// Manages ext2 bitmap operations
 pub struct BitmapManager {
@@ -14,7 +14,7 @@ impl BitmapManager {
     }
     
     pub fn init(&mut self, sb_mgr: &SuperblockManager) -> FilesystemResult<()> {
-        console_println!("[i] Bitmap manager initialized");
+        debug_println!(target: "ext2", "Bitmap manager initialized");
         Ok(())
     }
     
@@ -25,13 +25,13 @@ impl BitmapManager {
     
     pub fn allocate_block(&self) -> FilesystemResult<u32> {
         // Stub implementation
-        console_println!("[i] Allocating new block");
+        debug_println!(target: "ext2", "Allocating new block");
         Ok(100)
     }
     
     pub fn free_block(&self, block_num: u32) -> FilesystemResult<()> {
         // Stub implementation
-        console_println!("[i]  Freeing block {}", block_num);
+        debug_println!(target: "ext2", "Freeing block {}", block_num);
         Ok(())
     }
     
@@ -42,13 +42,13 @@ impl BitmapManager {
     
     pub fn allocate_inode_in_bitmap(&self) -> FilesystemResult<u32> {
         // Stub implementation
-        console_println!("[i] Allocating new inode in bitmap");
+        debug_println!(target: "ext2", "Allocating new inode in bitmap");
         Ok(12)
     }
     
     pub fn free_inode_in_bitmap(&self, inode_num: u32) -> FilesystemResult<()> {
         // Stub implementation
-        console_println!("[i]  Freeing inode {} in bitmap", inode_num);
+        debug_println!(target: "ext2", "Freeing inode {} in bitmap", inode_num);
         Ok(())
     }
 } 
\ No newline at end of file
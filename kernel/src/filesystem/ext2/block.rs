@@ -2,16 +2,11 @@
 
 use super::structures::*;
 use super::superblock::SuperblockManager;
+use super::extent;
 use super::super::traits::{FilesystemError, FilesystemResult};
 use elinos_common::console_println;
 use heapless::Vec;
 
-/// ext2 inode flags
-const EXT2_EXTENTS_FL: u32 = 0x00080000;  // Inode uses extents
-
-/// Extent magic number
-const EXT2_EXT_MAGIC: u16 = 0xF30A;
-
 /// Manages ext2 block operations
 pub struct BlockManager {
 }
@@ -22,20 +17,19 @@ impl BlockManager {
     }
     
     pub fn init(&mut self, _sb_mgr: &SuperblockManager) -> FilesystemResult<()> {
-        // console_println!("[i] Block manager initialized");
+        // info_println!("Block manager initialized");
         Ok(())
     }
     
     pub fn read_file_content(&self, inode: &Ext2Inode, file_size: usize, sb_mgr: &SuperblockManager) -> FilesystemResult<Vec<u8, 8192>> {
         // console_println!("🔍Reading file content of size {}", file_size);
         
-        // Copy flags and first block to avoid packed field issues
-        let i_flags = inode.i_flags;
+        // Copy fields to avoid packed field issues
         let i_blocks_lo = inode.i_blocks_lo;
         let first_block = inode.i_block[0];
         
         //console_println!("   [i]  File details: size={}, blocks={}, first_block={}, uses_extents={}", 
-        //    file_size, i_blocks_lo, first_block, (i_flags & EXT2_EXTENTS_FL) != 0);
+        //    file_size, i_blocks_lo, first_block, inode.uses_extents());
         
         // If file size is 0 but blocks are allocated, there might be content to read
         // This can happen if the file was created but size wasn't updated properly
@@ -49,275 +43,331 @@ impl BlockManager {
             file_size
         };
         
-        // Check if this inode uses extents
-        if (i_flags & EXT2_EXTENTS_FL) != 0 {
-            // console_println!("   - File uses extents - reading from extent tree");
-            self.read_file_content_from_extents(inode, effective_size, sb_mgr)
-        } else {
-            //console_println!("   - File uses direct blocks - reading from traditional blocks");
-            self.read_file_content_from_blocks(inode, effective_size, sb_mgr)
-        }
+        self.read_file_content_from_blocks(inode, effective_size, sb_mgr)
     }
     
-    /// Read file content from extent-based inode
-    fn read_file_content_from_extents(&self, inode: &Ext2Inode, file_size: usize, sb_mgr: &SuperblockManager) -> FilesystemResult<Vec<u8, 8192>> {
-        let mut file_content = Vec::new();
-        let mut bytes_read = 0;
+    /// Read file content through the inode's block map or extent tree
+    fn read_file_content_from_blocks(&self, inode: &Ext2Inode, file_size: usize, sb_mgr: &SuperblockManager) -> FilesystemResult<Vec<u8, 8192>> {
+        let mut file_content: Vec<u8, 8192> = Vec::new();
+        let block_size = sb_mgr.get_block_size();
+        let mut logical = 0u32;
         
-        // Copy i_block array to avoid packed field alignment issues
-        let i_block_copy = inode.i_block;
+        while file_content.len() < file_size {
+            let chunk = core::cmp::min(block_size, file_size - file_content.len());
+            let room = file_content.capacity() - file_content.len();
+            let take = core::cmp::min(chunk, room);
+            
+            match self.map_logical_block(inode, logical, sb_mgr)? {
+                Some(block_num) => {
+                    let block_data = sb_mgr.read_block_data(block_num)?;
+                    let _ = file_content.extend_from_slice(&block_data[..take]);
+                }
+                // Holes read as zeros
+                None => {
+                    let _ = file_content.resize(file_content.len() + take, 0);
+                }
+            }
+            
+            if take < chunk {
+                console_println!("   [!] File content buffer full");
+                break;
+            }
+            logical += 1;
+        }
         
-        // Parse extent header
-        let extent_header: Ext2ExtentHeader = unsafe {
-            let header_ptr = i_block_copy.as_ptr() as *const Ext2ExtentHeader;
-            *header_ptr
-        };
+        Ok(file_content)
+    }
+    
+    /// Where logical block `logical` hangs off the inode: the `i_block` slot
+    /// and the entry index at each level of indirection below it
+    fn block_path(&self, logical: u32, block_size: usize) -> FilesystemResult<(usize, Vec<usize, 3>)> {
+        let per_block = block_size / 4;
+        let mut logical = logical as usize;
+        let mut path = Vec::new();
         
-        let eh_magic = extent_header.eh_magic;
-        let eh_entries = extent_header.eh_entries;
-        let eh_depth = extent_header.eh_depth;
+        if logical < EXT2_NDIR_BLOCKS {
+            return Ok((logical, path));
+        }
+        logical -= EXT2_NDIR_BLOCKS;
+        if logical < per_block {
+            let _ = path.push(logical);
+            return Ok((EXT2_IND_BLOCK, path));
+        }
+        logical -= per_block;
+        if logical < per_block * per_block {
+            let _ = path.push(logical / per_block);
+            let _ = path.push(logical % per_block);
+            return Ok((EXT2_DIND_BLOCK, path));
+        }
+        logical -= per_block * per_block;
+        if logical < per_block * per_block * per_block {
+            let _ = path.push(logical / (per_block * per_block));
+            let _ = path.push((logical / per_block) % per_block);
+            let _ = path.push(logical % per_block);
+            return Ok((EXT2_TIND_BLOCK, path));
+        }
+        Err(FilesystemError::FilesystemFull)
+    }
+    
+    /// Entry `index` of indirect block `table`
+    fn read_indirect_entry(&self, table: u32, index: usize, sb_mgr: &SuperblockManager) -> FilesystemResult<u32> {
+        let data = sb_mgr.read_block_data(table as u64)?;
+        let offset = index * 4;
+        Ok(u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]))
+    }
+    
+    fn write_indirect_entry(&self, table: u32, index: usize, value: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<()> {
+        let mut data = sb_mgr.read_block_data(table as u64)?;
+        let offset = index * 4;
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        sb_mgr.write_block_data(table, &data)
+    }
+    
+    /// Allocate a zero-filled block, preferably at `goal`, and count it in
+    /// the inode's i_blocks
+    fn allocate_zeroed_block(&self, inode: &mut Ext2Inode, goal: u32, sb_mgr: &mut SuperblockManager) -> FilesystemResult<u32> {
+        let block_size = sb_mgr.get_block_size();
+        let block_num = sb_mgr.allocate_block_near(goal)?;
+        let zeros = [0u8; 4096];
+        sb_mgr.write_block_data(block_num, &zeros[..block_size])?;
+        inode.i_blocks_lo = inode.i_blocks_lo.wrapping_add((block_size / SECTOR_SIZE) as u32);
+        Ok(block_num)
+    }
+    
+    /// Disk block for logical block `logical`, allocating it and any missing
+    /// indirect blocks on the way
+    fn map_or_allocate_block(&self, inode: &mut Ext2Inode, logical: u32, sb_mgr: &mut SuperblockManager) -> FilesystemResult<u32> {
+        if inode.uses_extents() {
+            return self.map_or_allocate_extent_block(inode, logical, sb_mgr);
+        }
         
-        // console_println!("   [i]  File extent header: magic=0x{:04x}, entries={}, depth={}", 
-        //    eh_magic, eh_entries, eh_depth);
+        let (slot, path) = self.block_path(logical, sb_mgr.get_block_size())?;
         
-        if eh_magic != EXT2_EXT_MAGIC {
-            console_println!("   [x] Invalid extent magic for file");
-            return Err(FilesystemError::CorruptedFilesystem);
+        let mut i_block_copy = inode.i_block;
+        if i_block_copy[slot] == 0 {
+            i_block_copy[slot] = self.allocate_zeroed_block(inode, 0, sb_mgr)?;
+            inode.i_block = i_block_copy;
         }
         
-        if eh_depth != 0 {
-            console_println!("   [x] Multi-level extent trees not supported for files");
-            return Err(FilesystemError::UnsupportedFilesystem);
+        let mut current = i_block_copy[slot];
+        for &index in path.iter() {
+            let mut next = self.read_indirect_entry(current, index, sb_mgr)?;
+            if next == 0 {
+                next = self.allocate_zeroed_block(inode, current, sb_mgr)?;
+                self.write_indirect_entry(current, index, next, sb_mgr)?;
+            }
+            current = next;
+        }
+        Ok(current)
+    }
+    
+    /// Extent-mapped counterpart of `map_or_allocate_block`
+    fn map_or_allocate_extent_block(&self, inode: &mut Ext2Inode, logical: u32, sb_mgr: &mut SuperblockManager) -> FilesystemResult<u32> {
+        if let Some(found) = extent::find_extent(inode, logical, sb_mgr)? {
+            if found.is_uninitialized() {
+                console_println!("   [!] Writing into preallocated extents not yet supported");
+                return Err(FilesystemError::NotImplemented);
+            }
+            return Ok((found.get_start_block() + (logical - found.ee_block) as u64) as u32);
         }
         
-        // Parse extent entries
-        let extents_start = core::mem::size_of::<Ext2ExtentHeader>();
-        let i_block_bytes = unsafe {
-            core::slice::from_raw_parts(
-                i_block_copy.as_ptr() as *const u8,
-                60  // i_block is 15 * 4 = 60 bytes
-            )
-        };
+        let goal = extent::allocation_goal(inode, logical)?;
+        let block_num = self.allocate_zeroed_block(inode, goal, sb_mgr)?;
+        if let Err(e) = extent::insert_block(inode, logical, block_num) {
+            console_println!("   [!] File needs more extents than fit in its inode");
+            sb_mgr.free_block(block_num)?;
+            let sectors = (sb_mgr.get_block_size() / SECTOR_SIZE) as u32;
+            inode.i_blocks_lo = inode.i_blocks_lo.saturating_sub(sectors);
+            return Err(e);
+        }
+        Ok(block_num)
+    }
+    
+    /// Free every block of the tree rooted at `block` (`depth` levels of
+    /// indirection) that maps logical blocks from `keep` on. `base` is the
+    /// first logical block the tree covers. Returns true if `block` itself
+    /// was freed, so the caller must clear its pointer.
+    fn release_tree(&self, inode: &mut Ext2Inode, block: u32, depth: u32, base: usize, keep: usize, sb_mgr: &mut SuperblockManager) -> FilesystemResult<bool> {
+        let per_block = sb_mgr.get_block_size() / 4;
+        let covered = per_block.pow(depth);
         
-        for i in 0..eh_entries as usize {
-            if bytes_read >= file_size {
-                break;
-            }
-            
-            let extent_offset = extents_start + i * core::mem::size_of::<Ext2Extent>();
-            
-            if extent_offset + core::mem::size_of::<Ext2Extent>() > i_block_bytes.len() {
-                console_println!("   [!] File extent {} extends beyond i_block", i);
-                break;
-            }
-            
-            let extent: Ext2Extent = unsafe {
-                let extent_ptr = (i_block_bytes.as_ptr().add(extent_offset)) as *const Ext2Extent;
-                *extent_ptr
-            };
-            
-            let ee_block = extent.ee_block;
-            let ee_len = extent.ee_len;
-            let ee_start_hi = extent.ee_start_hi;
-            let ee_start_lo = extent.ee_start_lo;
-            
-            // Calculate physical block number
-            let physical_block = ((ee_start_hi as u64) << 32) | (ee_start_lo as u64);
-            
-            console_println!("   [i]  File extent {}: logical={}, len={}, physical={}", 
-                i, ee_block, ee_len, physical_block);
-            
-            // Read file data from this extent
-            for block_offset in 0..ee_len {
-                if bytes_read >= file_size {
-                    break;
+        if base + covered <= keep {
+            return Ok(false);
+        }
+        
+        if depth > 0 {
+            let child_covered = per_block.pow(depth - 1);
+            let mut table = sb_mgr.read_block_data(block as u64)?;
+            let mut modified = false;
+            for index in 0..per_block {
+                let offset = index * 4;
+                let child = u32::from_le_bytes([table[offset], table[offset + 1], table[offset + 2], table[offset + 3]]);
+                if child != 0 && self.release_tree(inode, child, depth - 1, base + index * child_covered, keep, sb_mgr)? {
+                    table[offset..offset + 4].copy_from_slice(&0u32.to_le_bytes());
+                    modified = true;
                 }
-                
-                let block_num = physical_block + block_offset as u64;
-                
-                //console_println!("   [i]  Reading file block {} (from extent)", block_num);
-                
-                let block_data = match sb_mgr.read_block_data(block_num) {
-                    Ok(data) => data,
-                    Err(_) => {
-                        console_println!("   [!] Failed to read file extent block {}", block_num);
-                        continue;
-                    }
-                };
-                
-                let bytes_to_copy = core::cmp::min(file_size - bytes_read, block_data.len());
-                
-                for i in 0..bytes_to_copy {
-                    if file_content.push(block_data[i]).is_err() {
-                        console_println!("   [!] File content buffer full");
-                        return Ok(file_content);
-                    }
-                    bytes_read += 1;
+            }
+            // Still maps blocks below `keep`
+            if base < keep {
+                if modified {
+                    sb_mgr.write_block_data(block, &table)?;
                 }
+                return Ok(false);
             }
         }
         
-        // console_println!("   [o] Read {} bytes from extent-based file", bytes_read);
-        Ok(file_content)
+        sb_mgr.free_block(block)?;
+        let sectors = (sb_mgr.get_block_size() / SECTOR_SIZE) as u32;
+        inode.i_blocks_lo = inode.i_blocks_lo.saturating_sub(sectors);
+        Ok(true)
     }
     
-    /// Read file content from traditional direct block pointers
-    fn read_file_content_from_blocks(&self, inode: &Ext2Inode, file_size: usize, sb_mgr: &SuperblockManager) -> FilesystemResult<Vec<u8, 8192>> {
-        let mut file_content = Vec::new();
-        let mut bytes_read = 0;
+    /// Free the blocks mapping logical blocks from `keep` on
+    fn release_blocks(&self, inode: &mut Ext2Inode, keep: usize, sb_mgr: &mut SuperblockManager) -> FilesystemResult<()> {
+        let per_block = sb_mgr.get_block_size() / 4;
+        let roots = [
+            (EXT2_IND_BLOCK, 1, EXT2_NDIR_BLOCKS),
+            (EXT2_DIND_BLOCK, 2, EXT2_NDIR_BLOCKS + per_block),
+            (EXT2_TIND_BLOCK, 3, EXT2_NDIR_BLOCKS + per_block + per_block * per_block),
+        ];
         
-        // Copy i_block array to avoid packed field alignment issues
-        let i_block_copy = inode.i_block;
-        
-        // console_println!("   [i]  Reading from direct blocks, target size: {}", file_size);
-        //console_println!("   [i]  First 5 block numbers: {:?}", &i_block_copy[..5]);
-        
-        // Read file data from direct blocks
-        for (i, &block_num) in i_block_copy.iter().take(12).enumerate() {
-            // console_println!("   [i] Block {}: {}", i, block_num);
-            
-            if block_num == 0 {
-                //console_println!("   [!]  Block {} is 0, stopping", i);
-                break;
+        let mut i_block_copy = inode.i_block;
+        for slot in 0..EXT2_NDIR_BLOCKS {
+            if i_block_copy[slot] != 0 && self.release_tree(inode, i_block_copy[slot], 0, slot, keep, sb_mgr)? {
+                i_block_copy[slot] = 0;
             }
-            
-            if bytes_read >= file_size {
-                // console_println!("   [o] Read enough bytes ({}), stopping", bytes_read);
-                break;
+        }
+        for &(slot, depth, base) in roots.iter() {
+            if i_block_copy[slot] != 0 && self.release_tree(inode, i_block_copy[slot], depth, base, keep, sb_mgr)? {
+                i_block_copy[slot] = 0;
             }
-            
-            // Validate block number
-            if block_num > 1000000 {
-                console_println!("   [!] Skipping invalid block number: {}", block_num);
-                continue;
+        }
+        inode.i_block = i_block_copy;
+        Ok(())
+    }
+    
+    /// Map a file's logical block number to its block on disk.
+    /// Returns None for holes and blocks beyond the mapped range.
+    pub fn map_logical_block(&self, inode: &Ext2Inode, logical: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<Option<u64>> {
+        if inode.uses_extents() {
+            return extent::map_block(inode, logical, sb_mgr);
+        }
+        
+        // Copy fields to avoid packed field issues
+        let i_block_copy = inode.i_block;
+        let (slot, path) = self.block_path(logical, sb_mgr.get_block_size())?;
+        let mut current = i_block_copy[slot];
+        for &index in path.iter() {
+            if current == 0 {
+                break;
             }
+            current = self.read_indirect_entry(current, index, sb_mgr)?;
+        }
+        Ok(if current == 0 { None } else { Some(current as u64) })
+    }
+    
+    /// Read up to `buffer.len()` bytes of a file starting at `offset`.
+    /// Holes read as zeros. Returns the number of bytes read.
+    pub fn read_at(&self, inode: &Ext2Inode, file_size: usize, offset: usize, buffer: &mut [u8], sb_mgr: &SuperblockManager) -> FilesystemResult<usize> {
+        if offset >= file_size {
+            return Ok(0);
+        }
+        
+        let block_size = sb_mgr.get_block_size();
+        let end = core::cmp::min(file_size, offset + buffer.len());
+        let mut pos = offset;
+        
+        while pos < end {
+            let logical = (pos / block_size) as u32;
+            let block_offset = pos % block_size;
+            let chunk = core::cmp::min(block_size - block_offset, end - pos);
+            let dest = &mut buffer[pos - offset..pos - offset + chunk];
             
-            // console_println!("   [i]  Reading block {} from disk", block_num);
-            let block_data = match sb_mgr.read_block_data(block_num as u64) {
-                Ok(data) => {
-                    // console_println!("   [o] Successfully read block {}, got {} bytes", block_num, data.len());
-                    data
-                },
-                Err(e) => {
-                    console_println!("   [x] Failed to read block {}: {:?}", block_num, e);
-                    continue;
-                }
-            };
-            
-            let bytes_to_copy = core::cmp::min(file_size - bytes_read, block_data.len());
-            // console_println!("   📝 Copying {} bytes from block {}", bytes_to_copy, block_num);
-            
-            for i in 0..bytes_to_copy {
-                if file_content.push(block_data[i]).is_err() {
-                    console_println!("   [!] File content buffer full");
-                    break;
-                }
-                bytes_read += 1;
-                if bytes_read >= file_size {
-                    break;
+            match self.map_logical_block(inode, logical, sb_mgr)? {
+                Some(block_num) => {
+                    let block_data = sb_mgr.read_block_data(block_num)?;
+                    dest.copy_from_slice(&block_data[block_offset..block_offset + chunk]);
                 }
+                None => dest.fill(0),
             }
             
-            // console_println!("   [i]  Total bytes read so far: {}", bytes_read);
+            pos += chunk;
         }
         
-        // console_println!("   [o] Read {} bytes from block-based file", bytes_read);
-        Ok(file_content)
+        Ok(end - offset)
     }
     
+    /// Write `data` at `offset`, allocating data and indirect blocks as
+    /// needed. The file grows if the write ends past its current size.
     pub fn write_file_content(&self, inode: &mut Ext2Inode, offset: u64, data: &[u8], sb_mgr: &mut SuperblockManager) -> FilesystemResult<usize> {
-        // console_println!("✏️  Writing {} bytes at offset {} to inode", data.len(), offset);
-        
         if data.is_empty() {
             return Ok(0);
         }
         
-        // For simplicity, only support writing from offset 0 for now
-        if offset != 0 {
-            console_println!("   [!] Only offset 0 writing supported currently");
-            return Err(FilesystemError::NotImplemented);
-        }
+        let block_size = sb_mgr.get_block_size();
+        let mut written = 0;
         
-        // Check if file uses extents (not supported for writing yet)
-        let i_flags = inode.i_flags;
-        if (i_flags & EXT2_EXTENTS_FL) != 0 {
-            console_println!("   [!] Writing to extent-based files not yet supported");
-            return Err(FilesystemError::NotImplemented);
+        while written < data.len() {
+            let pos = offset as usize + written;
+            let logical = (pos / block_size) as u32;
+            let block_offset = pos % block_size;
+            let chunk = core::cmp::min(block_size - block_offset, data.len() - written);
+            
+            let block_num = self.map_or_allocate_block(inode, logical, sb_mgr)?;
+            let mut block_data = sb_mgr.read_block_data(block_num as u64)?;
+            block_data[block_offset..block_offset + chunk].copy_from_slice(&data[written..written + chunk]);
+            sb_mgr.write_block_data(block_num, &block_data)?;
+            
+            written += chunk;
         }
         
-        // For traditional direct blocks, check if we need to allocate first block
-        let first_block = if inode.i_block[0] == 0 {
-            // console_println!("   [i] No blocks allocated, allocating first block");
-            let new_block = sb_mgr.allocate_block()?;
-            inode.i_block[0] = new_block;
-            // console_println!("   [o] Allocated block {} for file", new_block);
-            new_block
-        } else {
-            inode.i_block[0]
-        };
-        
-        // console_println!("   [i] Writing to block {}", first_block);
-        
-        // Read existing block data or create empty block
-        let mut block_data = if inode.get_size() == 0 {
-            // New file, create empty block
-            let mut empty_block = Vec::new();
-            for _ in 0..sb_mgr.get_block_size() {
-                empty_block.push(0).map_err(|_| FilesystemError::FilesystemFull)?;
-            }
-            empty_block
-        } else {
-            // Existing file, read current block data
-            match sb_mgr.read_block_data(first_block as u64) {
-                Ok(data) => data,
-                Err(e) => {
-                    console_println!("   [x] Failed to read existing block data: {:?}", e);
-                    return Err(e);
-                }
-            }
-        };
-        
-        // Copy new data into block
-        let bytes_to_write = core::cmp::min(data.len(), block_data.len());
-        block_data[..bytes_to_write].copy_from_slice(&data[..bytes_to_write]);
-        
-        // Write block back to disk
-        match sb_mgr.write_block_data(first_block as u32, &block_data) {
-            Ok(()) => {
-                // console_println!("   [o] Successfully wrote {} bytes to block {}", bytes_to_write, first_block);
-                
-                // Update inode size
-                inode.set_size(bytes_to_write as u64);
-                
-                Ok(bytes_to_write)
-            }
-            Err(e) => {
-                console_println!("   [x] Failed to write block data: {:?}", e);
-                Err(e)
-            }
+        let end = offset + written as u64;
+        if end > inode.get_size() {
+            inode.set_size(end);
         }
+        Ok(written)
     }
     
     pub fn free_inode_blocks(&self, inode: &Ext2Inode, sb_mgr: &mut SuperblockManager) -> FilesystemResult<()> {
-        // console_println!("[i] Freeing blocks for inode");
+        // info_println!("Freeing blocks for inode");
         
-        // Free direct blocks
-        // Copy i_block array to avoid packed field alignment issues
-        let i_block_copy = inode.i_block;
-        for &block_num in i_block_copy.iter().take(12) {
-            if block_num != 0 {
-                sb_mgr.free_block(block_num)?;
-            }
+        // A fast symlink's i_block holds its target, not block numbers
+        if inode.is_fast_symlink(sb_mgr.get_block_size()) {
+            return Ok(());
         }
         
-        // TODO: Handle indirect blocks when implemented
-        
-        Ok(())
+        let mut released = *inode;
+        if inode.uses_extents() {
+            return extent::release_blocks(&mut released, 0, sb_mgr);
+        }
+        self.release_blocks(&mut released, 0, sb_mgr)
     }
     
-    pub fn truncate_file(&self, inode: &mut Ext2Inode, new_size: u64) -> FilesystemResult<()> {
-        // console_println!("[i] Truncating file to {} bytes", new_size);
+    /// Set the file size, freeing blocks past the new end and zeroing the
+    /// tail of the last block kept
+    pub fn truncate_file(&self, inode: &mut Ext2Inode, new_size: u64, sb_mgr: &mut SuperblockManager) -> FilesystemResult<()> {
+        // info_println!("Truncating file to {} bytes", new_size);
+        if new_size < inode.get_size() {
+            let block_size = sb_mgr.get_block_size() as u64;
+            let keep = new_size.div_ceil(block_size) as usize;
+            if inode.uses_extents() {
+                extent::release_blocks(inode, keep as u32, sb_mgr)?;
+            } else {
+                self.release_blocks(inode, keep, sb_mgr)?;
+            }
+
+            // Zero the rest of the last block kept, so growing the file
+            // again reads zeros there rather than the old bytes
+            let tail = (new_size % block_size) as usize;
+            if tail != 0 {
+                if let Some(block_num) = self.map_logical_block(inode, (new_size / block_size) as u32, sb_mgr)? {
+                    let mut block_data = sb_mgr.read_block_data(block_num)?;
+                    block_data[tail..].fill(0);
+                    sb_mgr.write_block_data(block_num as u32, &block_data)?;
+                }
+            }
+        }
         inode.set_size(new_size);
         Ok(())
     }
-} 
\ No newline at end of file
+}
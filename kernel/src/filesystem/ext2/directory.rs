@@ -3,8 +3,11 @@
 use super::structures::*;
 use super::superblock::SuperblockManager;
 use super::inode::InodeManager;
-use super::super::traits::{FileEntry, FilesystemError, FilesystemResult};
-use elinos_common::console_println;
+use super::block::BlockManager;
+use super::extent;
+use super::super::traits::{DirListing, FileEntry, FilesystemError, FilesystemResult, DIR_ENTRY_CACHE};
+use crate::memory::slab::SlabList;
+use elinos_common::{console_println, err_println, debug_println, trace_println};
 use heapless::Vec;
 use core::mem;
 
@@ -18,22 +21,20 @@ impl DirectoryManager {
     }
     
     pub fn init(&mut self, _sb_mgr: &SuperblockManager, _inode_mgr: &InodeManager) -> FilesystemResult<()> {
-        console_println!("[i] Directory manager initialized");
+        debug_println!(target: "ext2", "Directory manager initialized");
         Ok(())
     }
     
-    pub fn read_directory_entries(&self, inode: &Ext2Inode, files: &mut Vec<FileEntry, 64>, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
-        console_println!("[i] Reading directory entries...");
+    pub fn read_directory_entries(&self, inode: &Ext2Inode, files: &mut SlabList<FileEntry>, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
+        debug_println!(target: "ext2", "Reading directory entries...");
         
         if !inode.is_directory() {
             return Err(FilesystemError::NotADirectory);
         }
         
-        // For now, read the first direct block only (simplified)
-        if inode.i_block[0] != 0 {
-            let block_data = sb_mgr.read_block_data(inode.i_block[0] as u64)?;
-            self.parse_directory_block(&block_data, files, sb_mgr, inode_mgr)?;
-        }
+        self.scan_blocks(inode, sb_mgr, |_, block_data| {
+            self.parse_directory_block(block_data, files, sb_mgr, inode_mgr).map(|_| None::<()>)
+        })?;
         
         Ok(())
     }
@@ -43,52 +44,52 @@ impl DirectoryManager {
     }
     
     pub fn find_entry_in_dir(&self, dir_inode_num: u32, entry_name: &str, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<Option<(Ext2DirEntry, u32, usize)>> {
-        // console_println!("[i] Looking for '{}' in directory inode {}", entry_name, dir_inode_num);
+        trace_println!(target: "ext2", "Looking for '{}' in directory inode {}", entry_name, dir_inode_num);
         
+        match self.locate_entry(dir_inode_num, entry_name, sb_mgr, inode_mgr)? {
+            Some((block_num, entry, offset)) => {
+                let inode_num = entry.inode;
+                trace_println!(target: "ext2", "Found '{}' -> inode {} in block {}", entry_name, inode_num, block_num);
+                Ok(Some((entry, inode_num, offset)))
+            }
+            None => {
+                trace_println!(target: "ext2", "'{}' not found in directory inode {}", entry_name, dir_inode_num);
+                Ok(None)
+            }
+        }
+    }
+    
+    /// The block holding the entry `entry_name` of directory `dir_inode_num`,
+    /// the entry and its offset in that block
+    fn locate_entry(&self, dir_inode_num: u32, entry_name: &str, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<Option<(u32, Ext2DirEntry, usize)>> {
         let dir_inode = inode_mgr.read_inode(dir_inode_num, sb_mgr)?;
         
         if !dir_inode.is_directory() {
             return Err(FilesystemError::NotADirectory);
         }
         
-        // Search in first direct block (simplified)
-        if dir_inode.i_block[0] != 0 {
-            let block_num = dir_inode.i_block[0];
-            //console_println!("      Searching in block {}", block_num);
-            let block_data = sb_mgr.read_block_data(block_num as u64)?;
-            let result = self.find_entry_in_block(&block_data, entry_name, block_num);
-            
-            if let Ok(Some((entry, _, _))) = &result {
-                let inode_num = entry.inode;
-                //console_println!("   [o] Found '{}' -> inode {}", entry_name, inode_num);
-            } else {
-                console_println!("   [x] '{}' not found", entry_name);
-            }
-            
-            return result;
-        }
-        
-        Ok(None)
+        self.scan_blocks(&dir_inode, sb_mgr, |block_num, block_data| {
+            Ok(self.find_entry_in_block(block_data, entry_name, block_num)?
+                .map(|(entry, _, offset)| (block_num, entry, offset)))
+        })
     }
     
-    pub fn list_directory(&self, inode: &Ext2Inode, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<Vec<(heapless::String<64>, usize, bool), 32>> {
-        let mut result = Vec::new();
+    pub fn list_directory(&self, inode: &Ext2Inode, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<DirListing> {
+        let mut result = DirListing::new(&DIR_ENTRY_CACHE);
         
         if !inode.is_directory() {
             return Err(FilesystemError::NotADirectory);
         }
         
-        // Read first direct block (simplified)
-        if inode.i_block[0] != 0 {
-            let block_data = sb_mgr.read_block_data(inode.i_block[0] as u64)?;
-            self.parse_directory_block_for_listing(&block_data, &mut result, sb_mgr, inode_mgr)?;
-        }
+        self.scan_blocks(inode, sb_mgr, |_, block_data| {
+            self.parse_directory_block_for_listing(block_data, &mut result, sb_mgr, inode_mgr).map(|_| None::<()>)
+        })?;
         
         Ok(result)
     }
     
     pub fn add_directory_entry(&self, parent_inode: u32, child_inode: u32, name: &str, file_type: u8, sb_mgr: &mut SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
-        // console_println!("[i] Adding directory entry: {} -> {} (type {})", name, child_inode, file_type);
+        debug_println!(target: "ext2", "Adding directory entry: {} -> {} (type {})", name, child_inode, file_type);
         
         if name.len() > 255 {
             return Err(FilesystemError::FilenameTooLong);
@@ -106,8 +107,12 @@ impl DirectoryManager {
             return Err(FilesystemError::NotADirectory);
         }
         
-        // For simplicity, only handle direct block directories
-        let first_block = parent_dir_inode.i_block[0];
+        // For simplicity, only handle the first directory block
+        let first_block = self.first_block(&parent_dir_inode, sb_mgr)?;
+        
+        if first_block == 0 && parent_dir_inode.uses_extents() {
+            return Err(FilesystemError::NotImplemented);
+        }
         
         if first_block == 0 {
             // Need to allocate a new block for this directory
@@ -149,14 +154,14 @@ impl DirectoryManager {
             // Write back the updated parent inode
             inode_mgr.write_inode(parent_inode, &parent_dir_inode, sb_mgr)?;
             
-            // console_println!("[o] Added '{}' to new directory block {}", name, new_block);
+            debug_println!(target: "ext2", "Added '{}' to new directory block {}", name, new_block);
         } else {
             // Add to existing directory block using entry splitting logic
             let mut block_data = sb_mgr.read_block_data(first_block as u64)?;
             let mut entry_added = false;
             let mut offset = 0;
             
-            // console_println!("[i] Directory block {} contents before adding '{}':", first_block, name);
+            // info_println!("Directory block {} contents before adding '{}':", first_block, name);
             // console_println!("    Block size: {}, first 32 bytes: {:02x?}", 
             //                block_data.len(), &block_data[0..32.min(block_data.len())]);
             
@@ -179,14 +184,14 @@ impl DirectoryManager {
                 
                 // Additional validation for corrupted entries
                 if current_rec_len > block_data.len() - offset {
-                    console_println!("[x] rec_len {} exceeds remaining block space {}, breaking", 
+                    err_println!(target: "ext2", "rec_len {} exceeds remaining block space {}, breaking", 
                                     current_rec_len, block_data.len() - offset);
                     break;
                 }
                 
                 // Sanity check for obviously corrupted rec_len values
                 if current_rec_len > 4096 {  // Block size is typically 1024 or 4096
-                    console_println!("[x] Suspiciously large rec_len {}, breaking", current_rec_len);
+                    err_println!(target: "ext2", "Suspiciously large rec_len {}, breaking", current_rec_len);
                     break;
                 }
                 
@@ -195,7 +200,7 @@ impl DirectoryManager {
                 
                 // Scenario 1: Reuse deleted entry (inode == 0)
                 if current_inode == 0 && current_rec_len >= required_rec_len as usize {
-                    console_println!("[i] Reusing deleted entry at offset {}", offset);
+                    debug_println!(target: "ext2", "Reusing deleted entry at offset {}", offset);
                     let new_entry = Ext2DirEntry {
                         inode: child_inode,
                         rec_len: current_rec_len as u16,
@@ -223,7 +228,7 @@ impl DirectoryManager {
                 
                 // Scenario 2: Split current entry if it has enough slack space
                 if current_inode != 0 && current_rec_len >= space_used_by_current + required_rec_len as usize {
-                    // console_println!("[i] Splitting entry at offset {} (current_rec_len={}, used={}, needed={})", 
+                    // info_println!("Splitting entry at offset {} (current_rec_len={}, used={}, needed={})", 
                     //                offset, current_rec_len, space_used_by_current, required_rec_len);
                     
                     // Calculate remaining space after splitting
@@ -231,7 +236,7 @@ impl DirectoryManager {
                     
                     // Validate that remaining space is reasonable
                     if remaining_space < required_rec_len as usize || remaining_space > block_data.len() {
-                        console_println!("[x] Invalid remaining space {} for split, skipping", remaining_space);
+                        err_println!(target: "ext2", "Invalid remaining space {} for split, skipping", remaining_space);
                         offset += current_rec_len;
                         continue;
                     }
@@ -247,7 +252,7 @@ impl DirectoryManager {
                     
                     // Validate new entry offset
                     if new_entry_offset + core::mem::size_of::<Ext2DirEntry>() > block_data.len() {
-                        console_println!("[x] New entry offset {} would exceed block boundary", new_entry_offset);
+                        err_println!(target: "ext2", "New entry offset {} would exceed block boundary", new_entry_offset);
                         offset += current_rec_len;
                         continue;
                     }
@@ -284,7 +289,7 @@ impl DirectoryManager {
                         entry_added = true;
                         break;
                     } else {
-                        console_println!("[x] Name would exceed block boundary at offset {}", name_start);
+                        err_println!(target: "ext2", "Name would exceed block boundary at offset {}", name_start);
                     }
                 }
                 
@@ -297,13 +302,13 @@ impl DirectoryManager {
             }
             
             if entry_added {
-                // console_println!("[i] Directory block {} contents after adding '{}':", first_block, name);
+                // info_println!("Directory block {} contents after adding '{}':", first_block, name);
                 // console_println!("    First 32 bytes: {:02x?}", &block_data[0..32.min(block_data.len())]);
                 
                 sb_mgr.write_block_data(first_block, &block_data)?;
-                // console_println!("[o] Added '{}' to existing directory block {}", name, first_block);
+                debug_println!(target: "ext2", "Added '{}' to existing directory block {}", name, first_block);
             } else {
-                console_println!("[x] No space found in directory block for '{}'", name);
+                err_println!(target: "ext2", "No space found in directory block for '{}'", name);
                 return Err(FilesystemError::FilesystemFull);
             }
         }
@@ -317,40 +322,47 @@ impl DirectoryManager {
     }
     
     pub fn remove_directory_entry(&self, parent_inode: u32, name: &str, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
-        //console_println!("➖ Removing directory entry: '{}' from inode {}", name, parent_inode);
+        debug_println!(target: "ext2", "Removing directory entry '{}' from inode {}", name, parent_inode);
         
-        // First, find the entry to get its location
-        if let Some((_, found_inode, _)) = self.find_entry_in_dir(parent_inode, name, sb_mgr, inode_mgr)? {
-          //  console_println!("[i] Found entry '{}' with inode {}, proceeding with removal", name, found_inode);
-            
-            // Read the parent directory inode
-            let parent_dir_inode = inode_mgr.read_inode(parent_inode, sb_mgr)?;
-            
-            // For simplicity, only handle direct block directories
-            let first_block = parent_dir_inode.i_block[0];
-            
-            if first_block == 0 {
-                console_println!("[x] Parent directory has no blocks allocated");
-                return Err(FilesystemError::FileNotFound);
-            }
-            
+        // First, find the block holding the entry
+        if let Some((block_num, _, _)) = self.locate_entry(parent_inode, name, sb_mgr, inode_mgr)? {
             // Read the directory block
-            let mut block_data = sb_mgr.read_block_data(first_block as u64)?;
+            let mut block_data = sb_mgr.read_block_data(block_num as u64)?;
             
             // Find and remove the entry
             self.remove_entry_from_block(&mut block_data, name)?;
             
             // Write the updated block back to disk
-            sb_mgr.write_block_data(first_block, &block_data)?;
+            sb_mgr.write_block_data(block_num, &block_data)?;
             
-            // console_println!("[o] Successfully removed directory entry '{}' from inode {}", name, parent_inode);
+            // ok_println!("Successfully removed directory entry '{}' from inode {}", name, parent_inode);
             Ok(())
         } else {
-            console_println!("[x] Entry '{}' not found in directory inode {}", name, parent_inode);
+            err_println!(target: "ext2", "Entry '{}' not found in directory inode {}", name, parent_inode);
             Err(FilesystemError::FileNotFound)
         }
     }
     
+    /// Point the entry `name` of `parent_inode` at `new_inode`, of type
+    /// `file_type`, instead, in one block write, so the name never goes
+    /// missing
+    pub fn replace_entry_inode(&self, parent_inode: u32, name: &str, new_inode: u32, file_type: u8, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
+        debug_println!(target: "ext2", "Pointing directory entry '{}' of inode {} at inode {}", name, parent_inode, new_inode);
+        
+        let Some((block_num, _, offset)) = self.locate_entry(parent_inode, name, sb_mgr, inode_mgr)? else {
+            return Err(FilesystemError::FileNotFound);
+        };
+        
+        let mut block_data = sb_mgr.read_block_data(block_num as u64)?;
+        unsafe {
+            let entry_ptr = block_data[offset..].as_mut_ptr() as *mut Ext2DirEntry;
+            (*entry_ptr).inode = new_inode;
+            (*entry_ptr).file_type = file_type;
+        }
+        sb_mgr.write_block_data(block_num, &block_data)?;
+        Ok(())
+    }
+    
     fn remove_entry_from_block(&self, block_data: &mut Vec<u8, 4096>, target_name: &str) -> FilesystemResult<()> {
         let mut offset = 0;
         
@@ -379,7 +391,7 @@ impl DirectoryManager {
                 let name_bytes = &block_data[name_start..name_end];
                 if let Ok(name_str) = core::str::from_utf8(name_bytes) {
                     if name_str == target_name {
-                        // console_println!("[i] Found target entry '{}' at offset {}, marking as deleted", target_name, offset);
+                        // info_println!("Found target entry '{}' at offset {}, marking as deleted", target_name, offset);
                         
                         // Mark the entry as deleted by setting inode to 0
                         unsafe {
@@ -388,7 +400,7 @@ impl DirectoryManager {
                             // Keep rec_len and other fields for proper directory traversal
                         }
                         
-                        //console_println!("[i]  Entry '{}' marked as deleted (inode=0)", target_name);
+                        //info_println!("Entry '{}' marked as deleted (inode=0)", target_name);
                         return Ok(());
                     }
                 }
@@ -401,12 +413,12 @@ impl DirectoryManager {
             }
         }
         
-        console_println!("[x] Target entry '{}' not found in directory block", target_name);
+        err_println!(target: "ext2", "Target entry '{}' not found in directory block", target_name);
         Err(FilesystemError::FileNotFound)
     }
     
     pub fn create_dot_entries(&self, dir_inode: u32, parent_inode: u32, sb_mgr: &mut SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
-        //console_println!("[i] Creating . and .. entries for inode {}", dir_inode);
+        //info_println!("Creating . and .. entries for inode {}", dir_inode);
         
         // Add "." entry (current directory)
         self.add_directory_entry(dir_inode, dir_inode, ".", EXT2_FT_DIR, sb_mgr, inode_mgr)?;
@@ -414,7 +426,7 @@ impl DirectoryManager {
         // Add ".." entry (parent directory)
         self.add_directory_entry(dir_inode, parent_inode, "..", EXT2_FT_DIR, sb_mgr, inode_mgr)?;
         
-        //console_println!("[o] Created . and .. entries for directory inode {}", dir_inode);
+        //ok_println!("Created . and .. entries for directory inode {}", dir_inode);
         Ok(())
     }
     
@@ -426,10 +438,10 @@ impl DirectoryManager {
         // Check if directory only contains . and .. entries
         let mut entry_count = 0;
         
-        if inode.i_block[0] != 0 {
-            let block_data = sb_mgr.read_block_data(inode.i_block[0] as u64)?;
-            entry_count = self.count_directory_entries(&block_data)?;
-        }
+        self.scan_blocks(inode, sb_mgr, |_, block_data| {
+            entry_count += self.count_directory_entries(block_data)?;
+            Ok(None::<()>)
+        })?;
         
         // Directory is empty if it only has . and .. entries (count <= 2)
         Ok(entry_count <= 2)
@@ -437,7 +449,33 @@ impl DirectoryManager {
     
     // Helper methods
     
-    fn parse_directory_block(&self, block_data: &[u8], files: &mut Vec<FileEntry, 64>, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
+    /// Disk block holding the start of directory `inode`, or 0 if it has none
+    fn first_block(&self, inode: &Ext2Inode, sb_mgr: &SuperblockManager) -> FilesystemResult<u32> {
+        if inode.uses_extents() {
+            Ok(extent::map_block(inode, 0, sb_mgr)?.unwrap_or(0) as u32)
+        } else {
+            Ok(inode.i_block[0])
+        }
+    }
+    
+    /// Call `f` with the number and contents of each block of directory
+    /// `inode` in turn, until it returns something. Holes are skipped.
+    fn scan_blocks<T>(&self, inode: &Ext2Inode, sb_mgr: &SuperblockManager, mut f: impl FnMut(u32, &[u8]) -> FilesystemResult<Option<T>>) -> FilesystemResult<Option<T>> {
+        let blocks = inode.get_size().div_ceil(sb_mgr.get_block_size() as u64);
+        let block_mgr = BlockManager::new();
+        for logical in 0..blocks {
+            let Some(block_num) = block_mgr.map_logical_block(inode, logical as u32, sb_mgr)? else {
+                continue;
+            };
+            let block_data = sb_mgr.read_block_data(block_num)?;
+            if let Some(found) = f(block_num as u32, &block_data)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+    
+    fn parse_directory_block(&self, block_data: &[u8], files: &mut SlabList<FileEntry>, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
         let mut offset = 0;
         
         while offset < block_data.len() {
@@ -485,7 +523,7 @@ impl DirectoryManager {
                             } else {
                                 FileEntry::new_file(name_str, inode_num as u64, size)
                             } {
-                                let _ = files.push(file_entry);
+                                files.push(file_entry).map_err(|_| FilesystemError::FilesystemFull)?;
                             }
                         }
                     }
@@ -498,9 +536,9 @@ impl DirectoryManager {
         Ok(())
     }
     
-    fn parse_directory_block_for_listing(&self, block_data: &[u8], result: &mut Vec<(heapless::String<64>, usize, bool), 32>, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
+    fn parse_directory_block_for_listing(&self, block_data: &[u8], result: &mut DirListing, sb_mgr: &SuperblockManager, inode_mgr: &InodeManager) -> FilesystemResult<()> {
         let mut offset = 0;
-        // console_println!("[i] Parsing directory block ({} bytes):", block_data.len());
+        // info_println!("Parsing directory block ({} bytes):", block_data.len());
         
         while offset < block_data.len() {
             if offset + mem::size_of::<Ext2DirEntry>() > block_data.len() {
@@ -577,7 +615,7 @@ impl DirectoryManager {
                     };
                     
                     // console_println!("   [o] Added: '{}' (dir: {}, size: {})", name_str, is_dir, size);
-                    let _ = result.push((short_name, size, is_dir));
+                    result.push((short_name, size, is_dir)).map_err(|_| FilesystemError::FilesystemFull)?;
                 } else {
                     console_println!("   [x] Filename too long: '{}'", name_str);
                 }
@@ -593,7 +631,7 @@ impl DirectoryManager {
             }
         }
         
-        // console_println!("[i] Directory parsing complete, found {} entries", result.len());
+        // info_println!("Directory parsing complete, found {} entries", result.len());
         Ok(())
     }
     
@@ -0,0 +1,284 @@
+// Extent trees for ext4-style inodes
+//
+// An extent-mapped inode keeps the root of its tree in `i_block`: a header
+// and up to four entries. Leaf entries map a run of logical blocks to
+// consecutive disk blocks; index entries point at tree blocks one level
+// down, which hold a header and entries in the same layout.
+//
+// Lookups and releasing blocks work at any depth. New blocks are only added
+// to the root, so a file that needs more than four extents cannot grow;
+// splitting the root into a deeper tree is not supported.
+
+use super::structures::*;
+use super::superblock::SuperblockManager;
+use super::super::traits::{FilesystemError, FilesystemResult};
+use heapless::Vec;
+
+/// Deepest tree ext4 creates
+const EXT4_EXT_MAX_DEPTH: usize = 5;
+
+/// Size of the node header and of each entry, extent or index alike
+const HEADER_SIZE: usize = core::mem::size_of::<Ext2ExtentHeader>();
+const ENTRY_SIZE: usize = core::mem::size_of::<Ext2Extent>();
+
+/// Tree root bytes as stored in `i_block`
+fn root(inode: &Ext2Inode) -> [u8; 60] {
+    let i_block_copy = inode.i_block;
+    let mut bytes = [0u8; 60];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(i_block_copy.iter()) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+fn set_root(inode: &mut Ext2Inode, bytes: &[u8; 60]) {
+    let mut i_block_copy = [0u32; 15];
+    for (word, chunk) in i_block_copy.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    inode.i_block = i_block_copy;
+}
+
+/// A node's header, checked against the space the node has
+fn read_header(node: &[u8]) -> FilesystemResult<Ext2ExtentHeader> {
+    let header: Ext2ExtentHeader = unsafe { core::ptr::read_unaligned(node.as_ptr() as *const Ext2ExtentHeader) };
+    let eh_magic = header.eh_magic;
+    let eh_entries = header.eh_entries as usize;
+    let eh_max = header.eh_max as usize;
+    let eh_depth = header.eh_depth as usize;
+
+    if eh_magic != EXT2_EXT_MAGIC
+        || eh_entries > eh_max
+        || HEADER_SIZE + eh_max * ENTRY_SIZE > node.len()
+        || eh_depth > EXT4_EXT_MAX_DEPTH
+    {
+        return Err(FilesystemError::CorruptedFilesystem);
+    }
+    Ok(header)
+}
+
+fn set_header(node: &mut [u8], header: Ext2ExtentHeader) {
+    unsafe { core::ptr::write_unaligned(node.as_mut_ptr() as *mut Ext2ExtentHeader, header) }
+}
+
+fn extent_at(node: &[u8], index: usize) -> Ext2Extent {
+    let offset = HEADER_SIZE + index * ENTRY_SIZE;
+    unsafe { core::ptr::read_unaligned(node[offset..].as_ptr() as *const Ext2Extent) }
+}
+
+fn set_extent_at(node: &mut [u8], index: usize, extent: Ext2Extent) {
+    let offset = HEADER_SIZE + index * ENTRY_SIZE;
+    unsafe { core::ptr::write_unaligned(node[offset..].as_mut_ptr() as *mut Ext2Extent, extent) }
+}
+
+fn index_at(node: &[u8], index: usize) -> Ext2ExtentIdx {
+    let offset = HEADER_SIZE + index * ENTRY_SIZE;
+    unsafe { core::ptr::read_unaligned(node[offset..].as_ptr() as *const Ext2ExtentIdx) }
+}
+
+/// Remove entry `index` of `entries`, closing the gap
+fn remove_entry(node: &mut [u8], index: usize, entries: usize) {
+    let start = HEADER_SIZE + (index + 1) * ENTRY_SIZE;
+    let end = HEADER_SIZE + entries * ENTRY_SIZE;
+    node.copy_within(start..end, start - ENTRY_SIZE);
+}
+
+/// The extent covering logical block `logical`, if any
+pub fn find_extent(inode: &Ext2Inode, logical: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<Option<Ext2Extent>> {
+    let mut node: Vec<u8, 4096> = Vec::new();
+    let _ = node.extend_from_slice(&root(inode));
+
+    for _ in 0..=EXT4_EXT_MAX_DEPTH {
+        let header = read_header(&node)?;
+        let entries = header.eh_entries as usize;
+
+        if header.eh_depth == 0 {
+            for i in 0..entries {
+                let extent = extent_at(&node, i);
+                let ee_block = extent.ee_block;
+                if logical >= ee_block && logical - ee_block < extent.block_count() {
+                    return Ok(Some(extent));
+                }
+            }
+            return Ok(None);
+        }
+
+        // Index entries are sorted; descend into the last one starting at
+        // or before `logical`
+        let child = (0..entries)
+            .map(|i| index_at(&node, i))
+            .take_while(|index| index.ei_block <= logical)
+            .last();
+        match child {
+            Some(index) => node = sb_mgr.read_block_data(index.get_leaf_block())?,
+            None => return Ok(None),
+        }
+    }
+
+    Err(FilesystemError::CorruptedFilesystem)
+}
+
+/// Disk block holding logical block `logical`. Holes and uninitialized
+/// extents map to None, as both read as zeros.
+pub fn map_block(inode: &Ext2Inode, logical: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<Option<u64>> {
+    Ok(find_extent(inode, logical, sb_mgr)?
+        .filter(|extent| !extent.is_uninitialized())
+        .map(|extent| extent.get_start_block() + (logical - extent.ee_block) as u64))
+}
+
+/// Where a new block for `logical` should go: right after the blocks of
+/// the extent before it, so that extent can simply grow
+pub fn allocation_goal(inode: &Ext2Inode, logical: u32) -> FilesystemResult<u32> {
+    let root = root(inode);
+    let header = read_header(&root)?;
+    if header.eh_depth != 0 {
+        return Ok(0);
+    }
+
+    let previous = (0..header.eh_entries as usize)
+        .map(|i| extent_at(&root, i))
+        .take_while(|extent| extent.ee_block < logical)
+        .last();
+    Ok(match previous {
+        Some(extent) => (extent.get_start_block() + (logical - extent.ee_block) as u64) as u32,
+        None => 0,
+    })
+}
+
+/// Map the unmapped logical block `logical` to disk block `block`, growing
+/// the extent that ends just before it when the two are adjacent on disk
+pub fn insert_block(inode: &mut Ext2Inode, logical: u32, block: u32) -> FilesystemResult<()> {
+    let mut root = root(inode);
+    let mut header = read_header(&root)?;
+    if header.eh_depth != 0 {
+        return Err(FilesystemError::NotImplemented);
+    }
+
+    let entries = header.eh_entries as usize;
+    let position = (0..entries)
+        .find(|&i| extent_at(&root, i).ee_block > logical)
+        .unwrap_or(entries);
+
+    if position > 0 {
+        let mut previous = extent_at(&root, position - 1);
+        let ee_block = previous.ee_block;
+        let ee_len = previous.ee_len;
+        if !previous.is_uninitialized()
+            && ee_len < EXT4_EXT_INIT_MAX_LEN
+            && ee_block + ee_len as u32 == logical
+            && previous.get_start_block() + ee_len as u64 == block as u64
+        {
+            previous.ee_len = ee_len + 1;
+            set_extent_at(&mut root, position - 1, previous);
+            set_root(inode, &root);
+            return Ok(());
+        }
+    }
+
+    if entries >= header.eh_max as usize {
+        return Err(FilesystemError::NotImplemented);
+    }
+
+    let start = HEADER_SIZE + position * ENTRY_SIZE;
+    let end = HEADER_SIZE + entries * ENTRY_SIZE;
+    root.copy_within(start..end, start + ENTRY_SIZE);
+
+    let mut extent = Ext2Extent { ee_block: logical, ee_len: 1, ee_start_hi: 0, ee_start_lo: 0 };
+    extent.set_start_block(block as u64);
+    set_extent_at(&mut root, position, extent);
+
+    header.eh_entries += 1;
+    set_header(&mut root, header);
+    set_root(inode, &root);
+    Ok(())
+}
+
+/// Free a data or tree block and drop it from the inode's i_blocks
+fn release_block(inode: &mut Ext2Inode, block: u64, sb_mgr: &mut SuperblockManager) -> FilesystemResult<()> {
+    let block = u32::try_from(block).map_err(|_| FilesystemError::CorruptedFilesystem)?;
+    sb_mgr.free_block(block)?;
+    let sectors = (sb_mgr.get_block_size() / SECTOR_SIZE) as u32;
+    inode.i_blocks_lo = inode.i_blocks_lo.saturating_sub(sectors);
+    Ok(())
+}
+
+/// Free the blocks below `node` that map logical blocks from `keep` on,
+/// shrinking or dropping its entries. Returns whether the node changed.
+fn release_node(inode: &mut Ext2Inode, node: &mut [u8], keep: u32, sb_mgr: &mut SuperblockManager) -> FilesystemResult<bool> {
+    let mut header = read_header(node)?;
+    let mut entries = header.eh_entries as usize;
+    let mut changed = false;
+    let mut i = 0;
+
+    while i < entries {
+        if header.eh_depth == 0 {
+            let mut extent = extent_at(node, i);
+            let ee_block = extent.ee_block;
+            let count = extent.block_count();
+            if ee_block + count <= keep {
+                i += 1;
+                continue;
+            }
+
+            let kept = keep.saturating_sub(ee_block);
+            for offset in kept..count {
+                release_block(inode, extent.get_start_block() + offset as u64, sb_mgr)?;
+            }
+            changed = true;
+
+            if kept > 0 {
+                let uninit = if extent.is_uninitialized() { EXT4_EXT_INIT_MAX_LEN } else { 0 };
+                extent.ee_len = kept as u16 + uninit;
+                set_extent_at(node, i, extent);
+                i += 1;
+            } else {
+                remove_entry(node, i, entries);
+                entries -= 1;
+            }
+        } else {
+            // The subtree ends where the next one starts
+            if i + 1 < entries && index_at(node, i + 1).ei_block <= keep {
+                i += 1;
+                continue;
+            }
+
+            let child_block = index_at(node, i).get_leaf_block();
+            let mut child = sb_mgr.read_block_data(child_block)?;
+            if !release_node(inode, &mut child, keep, sb_mgr)? {
+                i += 1;
+                continue;
+            }
+            changed = true;
+
+            if read_header(&child)?.eh_entries == 0 {
+                release_block(inode, child_block, sb_mgr)?;
+                remove_entry(node, i, entries);
+                entries -= 1;
+            } else {
+                sb_mgr.write_block_data(child_block as u32, &child)?;
+                i += 1;
+            }
+        }
+    }
+
+    header.eh_entries = entries as u16;
+    set_header(node, header);
+    Ok(changed)
+}
+
+/// Free the blocks mapping logical blocks from `keep` on
+pub fn release_blocks(inode: &mut Ext2Inode, keep: u32, sb_mgr: &mut SuperblockManager) -> FilesystemResult<()> {
+    let mut root = root(inode);
+    if !release_node(inode, &mut root, keep, sb_mgr)? {
+        return Ok(());
+    }
+
+    // An emptied tree goes back to being a bare leaf
+    let mut header = read_header(&root)?;
+    if header.eh_entries == 0 {
+        header.eh_depth = 0;
+        set_header(&mut root, header);
+    }
+    set_root(inode, &root);
+    Ok(())
+}
@@ -3,7 +3,14 @@
 use super::structures::*;
 use super::superblock::SuperblockManager;
 use super::super::traits::{FilesystemError, FilesystemResult};
-use elinos_common::console_println;
+use elinos_common::{err_println, debug_println, trace_println};
+
+/// Seconds since the epoch for inode times, from the clock gettimeofday
+/// reads: the RTC, or the time since boot until it is set
+pub fn current_time() -> u32 {
+    use crate::syscall::time::{clock_ns, CLOCK_REALTIME};
+    (clock_ns(CLOCK_REALTIME).unwrap_or(0) / 1_000_000_000) as u32
+}
 
 /// Manages ext2 inode operations
 pub struct InodeManager {
@@ -26,9 +33,8 @@ impl InodeManager {
         self.inode_size = if sb.s_rev_level == 0 { 128 } else { sb.s_inode_size };
         self.inodes_per_group = sb.s_inodes_per_group;
         
-        console_println!("[i] Inode manager initialized");
-        console_println!("      Inode size: {} bytes", self.inode_size);
-        console_println!("      Inodes per group: {}", self.inodes_per_group);
+        debug_println!(target: "ext2", "Inode manager initialized: {} byte inodes, {} per group",
+            self.inode_size, self.inodes_per_group);
         
         Ok(())
     }
@@ -61,13 +67,8 @@ impl InodeManager {
         // Read the block containing the inode
         let block_num = inode_table_block + block_offset as u64;
         
-        // Debug inode reading calculation
-        //console_println!("[i] Reading inode {} calculation:", inode_num);
-        //console_println!("   Group: {}, Local index: {}", group_num, local_inode_index);
-        //console_println!("   Inode table block: {}", inode_table_block);
-        //console_println!("   Inode size: {}, Block size: {}", self.inode_size, block_size);
-        //console_println!("   Inode offset: {}, Block offset: {}, Offset in block: {}", inode_offset, block_offset, offset_in_block);
-        //console_println!("   Reading from block: {}", block_num);
+        trace_println!(target: "ext2", "Reading inode {}: group {}, index {}, table block {}, block {} offset {}",
+            inode_num, group_num, local_inode_index, inode_table_block, block_num, offset_in_block);
         
         let block_data = sb_mgr.read_block_data(block_num)?;
         
@@ -80,17 +81,13 @@ impl InodeManager {
             core::ptr::read(block_data[offset_in_block..].as_ptr() as *const Ext2Inode)
         };
         
-        // Debug the actual inode data
+        // Copied out first: the inode is packed
         let raw_mode = inode.i_mode;
         let raw_size_lo = inode.i_size_lo;
         let raw_blocks_lo = inode.i_blocks_lo;
         let raw_block_0 = inode.i_block[0];
-        
-        //console_println!("[i] Raw inode {} data:", inode_num);
-        //console_println!("   Raw mode: 0x{:04x}", raw_mode);
-        //console_println!("   Raw size_lo: {}", raw_size_lo);
-        //console_println!("   Raw blocks_lo: {}", raw_blocks_lo);
-        //console_println!("   Raw block[0]: {}", raw_block_0);
+        trace_println!(target: "ext2", "Inode {}: mode 0x{:04x}, size_lo {}, blocks_lo {}, block[0] {}",
+            inode_num, raw_mode, raw_size_lo, raw_blocks_lo, raw_block_0);
         
         Ok(inode)
     }
@@ -165,13 +162,17 @@ impl InodeManager {
         // TODO: Update free inode count in group descriptor and superblock
         // This should be done to keep counts accurate
         
-        // Create new inode
-        let new_inode = Ext2Inode::new(mode, uid, gid, links_count, flags);
+        // Create new inode, made now
+        let mut new_inode = Ext2Inode::new(mode, uid, gid, links_count, flags);
+        let now = current_time();
+        new_inode.i_atime = now;
+        new_inode.i_ctime = now;
+        new_inode.i_mtime = now;
         
         // Write inode to disk
         self.write_inode(free_inode_num, &new_inode, sb_mgr)?;
         
-        //console_println!("[i] Created new inode {} with mode 0x{:04x}", free_inode_num, mode);
+        debug_println!(target: "ext2", "Created new inode {} with mode 0x{:04x}", free_inode_num, mode);
         Ok(free_inode_num)
     }
     
@@ -183,7 +184,7 @@ impl InodeManager {
             .ok_or(FilesystemError::InvalidSuperblock)?;
 
         if group_desc.bg_free_inodes_count_lo == 0 {
-            console_println!("[x] find_free_inode: No free inodes in group 0 per descriptor.");
+            err_println!(target: "ext2", "find_free_inode: No free inodes in group 0 per descriptor.");
             return Err(FilesystemError::FilesystemFull);
         }
 
@@ -208,7 +209,7 @@ impl InodeManager {
             }
         }
         
-        console_println!("[x] find_free_inode: No free bit found in inode bitmap for group 0.");
+        err_println!(target: "ext2", "find_free_inode: No free bit found in inode bitmap for group 0.");
         Err(FilesystemError::FilesystemFull)
     }
     
@@ -235,7 +236,7 @@ impl InodeManager {
         if byte_index < inode_bitmap_data.len() {
             inode_bitmap_data[byte_index] &= !(1 << bit_in_byte_index);
             sb_mgr.write_block_data(inode_bitmap_block as u32, &inode_bitmap_data)?;
-            console_println!("[i] Freed inode {} in bitmap", inode_num);
+            debug_println!(target: "ext2", "Freed inode {} in bitmap", inode_num);
         } else {
             return Err(FilesystemError::CorruptedFilesystem);
         }
@@ -0,0 +1,340 @@
+// JBD2 journal replay for ext3/ext4
+//
+// Linux writes metadata to the journal first and to its home location
+// later. A filesystem that was not unmounted cleanly has the RECOVER flag
+// set and may hold committed transactions whose blocks never reached home,
+// so mounting without replaying them would see stale metadata.
+//
+// Replay follows the Linux recovery code in three passes over the log:
+// find the last committed transaction, collect the revoke records, then
+// copy every logged block home unless a later revoke cancelled it.
+// Checksums in the log are not verified. elinOS does not journal its own
+// writes; once replayed, the journal is marked empty.
+
+use super::structures::*;
+use super::superblock::SuperblockManager;
+use super::inode::InodeManager;
+use super::block::BlockManager;
+use super::super::cache;
+use super::super::traits::{FilesystemError, FilesystemResult};
+use elinos_common::{console_println, err_println, info_println, ok_println};
+use heapless::{FnvIndexMap, Vec};
+
+const JBD2_MAGIC: u32 = 0xC03B3998;
+
+/// Journal block types
+const JBD2_DESCRIPTOR_BLOCK: u32 = 1;
+const JBD2_COMMIT_BLOCK: u32 = 2;
+const JBD2_SUPERBLOCK_V1: u32 = 3;
+const JBD2_SUPERBLOCK_V2: u32 = 4;
+const JBD2_REVOKE_BLOCK: u32 = 5;
+
+/// Journal incompatible features
+const JBD2_FEATURE_INCOMPAT_REVOKE: u32 = 0x1;
+const JBD2_FEATURE_INCOMPAT_64BIT: u32 = 0x2;
+const JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT: u32 = 0x4;
+const JBD2_FEATURE_INCOMPAT_CSUM_V2: u32 = 0x8;
+const JBD2_FEATURE_INCOMPAT_CSUM_V3: u32 = 0x10;
+const JBD2_KNOWN_INCOMPAT: u32 = JBD2_FEATURE_INCOMPAT_REVOKE
+    | JBD2_FEATURE_INCOMPAT_64BIT
+    | JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT
+    | JBD2_FEATURE_INCOMPAT_CSUM_V2
+    | JBD2_FEATURE_INCOMPAT_CSUM_V3;
+
+/// Descriptor tag flags
+const JBD2_FLAG_ESCAPE: u32 = 0x1;
+const JBD2_FLAG_SAME_UUID: u32 = 0x2;
+const JBD2_FLAG_LAST_TAG: u32 = 0x8;
+
+/// Every journal block starts with magic, block type and sequence
+const JBD2_HEADER_SIZE: usize = 12;
+
+/// Journal superblock field offsets
+const JSB_BLOCKSIZE: usize = 12;
+const JSB_MAXLEN: usize = 16;
+const JSB_FIRST: usize = 20;
+const JSB_SEQUENCE: usize = 24;
+const JSB_START: usize = 28;
+const JSB_FEATURE_INCOMPAT: usize = 40;
+
+/// Revoked blocks remembered during replay
+const MAX_REVOKED: usize = 512;
+
+/// Journal data is big-endian
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+fn be16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+/// What a pass over the log is shown
+enum Record<'a> {
+    /// Journal block `journal_block` holds a copy of disk block `home`
+    Block { home: u64, journal_block: u32, escaped: bool, sequence: u32 },
+    /// A revoke block's contents
+    Revoke { data: &'a [u8], sequence: u32 },
+}
+
+/// The journal of a mounted filesystem, read through its inode
+struct Journal<'a> {
+    inode: Ext2Inode,
+    block_mgr: &'a BlockManager,
+    block_size: usize,
+    first: u32,
+    maxlen: u32,
+    start: u32,
+    sequence: u32,
+    incompat: u32,
+}
+
+impl<'a> Journal<'a> {
+    fn open(inode: Ext2Inode, block_mgr: &'a BlockManager, sb_mgr: &SuperblockManager) -> FilesystemResult<Self> {
+        let mut journal = Journal {
+            inode,
+            block_mgr,
+            block_size: sb_mgr.get_block_size(),
+            first: 0,
+            maxlen: 0,
+            start: 0,
+            sequence: 0,
+            incompat: 0,
+        };
+
+        let jsb = journal.read(0, sb_mgr)?;
+        let block_type = be32(&jsb, 4);
+        if be32(&jsb, 0) != JBD2_MAGIC || (block_type != JBD2_SUPERBLOCK_V1 && block_type != JBD2_SUPERBLOCK_V2) {
+            err_println!(target: "ext2", "Journal superblock is invalid");
+            return Err(FilesystemError::CorruptedFilesystem);
+        }
+        if be32(&jsb, JSB_BLOCKSIZE) as usize != journal.block_size {
+            err_println!(target: "ext2", "Journal block size differs from the filesystem's");
+            return Err(FilesystemError::UnsupportedFilesystem);
+        }
+
+        journal.maxlen = be32(&jsb, JSB_MAXLEN);
+        journal.first = be32(&jsb, JSB_FIRST);
+        journal.sequence = be32(&jsb, JSB_SEQUENCE);
+        journal.start = be32(&jsb, JSB_START);
+        if block_type == JBD2_SUPERBLOCK_V2 {
+            journal.incompat = be32(&jsb, JSB_FEATURE_INCOMPAT);
+        }
+
+        if journal.incompat & !JBD2_KNOWN_INCOMPAT != 0 {
+            err_println!(target: "ext2", "Journal uses unknown features 0x{:x}", journal.incompat & !JBD2_KNOWN_INCOMPAT);
+            return Err(FilesystemError::UnsupportedFilesystem);
+        }
+        if journal.first == 0 || journal.first >= journal.maxlen {
+            return Err(FilesystemError::CorruptedFilesystem);
+        }
+        Ok(journal)
+    }
+
+    fn has(&self, feature: u32) -> bool {
+        self.incompat & feature != 0
+    }
+
+    /// Read block `block` of the journal
+    fn read(&self, block: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<Vec<u8, 4096>> {
+        let disk_block = self.block_mgr.map_logical_block(&self.inode, block, sb_mgr)?
+            .ok_or(FilesystemError::CorruptedFilesystem)?;
+        sb_mgr.read_block_data(disk_block)
+    }
+
+    /// The log is circular between `first` and `maxlen`
+    fn next(&self, block: u32) -> u32 {
+        if block + 1 >= self.maxlen { self.first } else { block + 1 }
+    }
+
+    fn tag_size(&self) -> usize {
+        if self.has(JBD2_FEATURE_INCOMPAT_CSUM_V3) {
+            return 16;
+        }
+        let mut size = 12;
+        if self.has(JBD2_FEATURE_INCOMPAT_CSUM_V2) {
+            size += 2;
+        }
+        if !self.has(JBD2_FEATURE_INCOMPAT_64BIT) {
+            size -= 4;
+        }
+        size
+    }
+
+    /// Descriptor and revoke blocks end in a checksum when checksums are on
+    fn tail_size(&self) -> usize {
+        if self.has(JBD2_FEATURE_INCOMPAT_CSUM_V2 | JBD2_FEATURE_INCOMPAT_CSUM_V3) { 4 } else { 0 }
+    }
+
+    /// Walk the log from its start, showing `visit` each logged block and
+    /// revoke block of the transactions before `end`, or of all of them up
+    /// to where the log stops when `end` is None. Returns the sequence
+    /// number after the last committed transaction.
+    fn walk(&self, end: Option<u32>, sb_mgr: &SuperblockManager, mut visit: impl FnMut(Record) -> FilesystemResult<()>) -> FilesystemResult<u32> {
+        let mut block = self.start;
+        let mut sequence = self.sequence;
+        let mut steps = 0;
+
+        while end != Some(sequence) && steps < self.maxlen {
+            let data = self.read(block, sb_mgr)?;
+            if be32(&data, 0) != JBD2_MAGIC || be32(&data, 8) != sequence {
+                break;
+            }
+            block = self.next(block);
+            steps += 1;
+
+            match be32(&data, 4) {
+                JBD2_DESCRIPTOR_BLOCK => {
+                    let tag_size = self.tag_size();
+                    let limit = self.block_size - self.tail_size();
+                    let mut offset = JBD2_HEADER_SIZE;
+
+                    while offset + tag_size <= limit {
+                        let low = be32(&data, offset);
+                        let flags = if self.has(JBD2_FEATURE_INCOMPAT_CSUM_V3) {
+                            be32(&data, offset + 4)
+                        } else {
+                            be16(&data, offset + 6) as u32
+                        };
+                        let high = if self.has(JBD2_FEATURE_INCOMPAT_64BIT) { be32(&data, offset + 8) } else { 0 };
+
+                        visit(Record::Block {
+                            home: ((high as u64) << 32) | low as u64,
+                            journal_block: block,
+                            escaped: flags & JBD2_FLAG_ESCAPE != 0,
+                            sequence,
+                        })?;
+                        block = self.next(block);
+                        steps += 1;
+
+                        offset += tag_size;
+                        if flags & JBD2_FLAG_SAME_UUID == 0 {
+                            offset += 16;
+                        }
+                        if flags & JBD2_FLAG_LAST_TAG != 0 {
+                            break;
+                        }
+                    }
+                }
+                JBD2_COMMIT_BLOCK => sequence = sequence.wrapping_add(1),
+                JBD2_REVOKE_BLOCK => visit(Record::Revoke { data: &data, sequence })?,
+                _ => break,
+            }
+        }
+
+        Ok(sequence)
+    }
+
+    /// Replay every committed transaction
+    fn replay(&self, sb_mgr: &SuperblockManager) -> FilesystemResult<u32> {
+        // Scan: find where the committed part of the log ends. A scan with
+        // no end stops at the first block that does not continue the log,
+        // so a transaction without its commit block is left out.
+        let end = self.walk(None, sb_mgr, |_| Ok(()))?;
+        let transactions = end.wrapping_sub(self.sequence);
+        if transactions == 0 {
+            return Ok(end);
+        }
+        info_println!(target: "ext2", "Replaying {} journal transaction(s)", transactions);
+
+        // Revoke: a block revoked in transaction N is not replayed from any
+        // transaction up to N
+        let record_size = if self.has(JBD2_FEATURE_INCOMPAT_64BIT) { 8 } else { 4 };
+        let mut revoked: FnvIndexMap<u64, u32, MAX_REVOKED> = FnvIndexMap::new();
+        self.walk(Some(end), sb_mgr, |record| {
+            if let Record::Revoke { data, sequence } = record {
+                let used = core::cmp::min(be32(data, JBD2_HEADER_SIZE) as usize, data.len());
+                let mut offset = JBD2_HEADER_SIZE + 4;
+                while offset + record_size <= used {
+                    let block = if record_size == 8 {
+                        ((be32(data, offset) as u64) << 32) | be32(data, offset + 4) as u64
+                    } else {
+                        be32(data, offset) as u64
+                    };
+                    let latest = revoked.get(&block).map_or(sequence, |&s| core::cmp::max(s, sequence));
+                    revoked.insert(block, latest).map_err(|_| FilesystemError::FilesystemFull)?;
+                    offset += record_size;
+                }
+            }
+            Ok(())
+        })?;
+
+        // Replay: copy logged blocks home, oldest transaction first so the
+        // newest copy of a block wins
+        let mut replayed = 0;
+        let fs_blocks = sb_mgr.get_superblock().ok_or(FilesystemError::InvalidSuperblock)?.s_blocks_count_lo as u64;
+        self.walk(Some(end), sb_mgr, |record| {
+            if let Record::Block { home, journal_block, escaped, sequence } = record {
+                if revoked.get(&home).is_some_and(|&revoke| sequence <= revoke) {
+                    return Ok(());
+                }
+                if home >= fs_blocks {
+                    err_println!(target: "ext2", "Journal block {} logs block {} past the end of the filesystem", journal_block, home);
+                    return Err(FilesystemError::CorruptedFilesystem);
+                }
+
+                let mut data = self.read(journal_block, sb_mgr)?;
+                // A logged block starting with the journal magic was stored
+                // with its first word zeroed
+                if escaped {
+                    data[..4].copy_from_slice(&JBD2_MAGIC.to_be_bytes());
+                }
+                sb_mgr.write_block_data(home as u32, &data)?;
+                replayed += 1;
+            }
+            Ok(())
+        })?;
+
+        console_println!("   {} block(s) replayed, {} revoked", replayed, revoked.len());
+        Ok(end)
+    }
+
+    /// Mark the log empty, continuing from sequence `sequence`
+    fn reset(&self, sequence: u32, sb_mgr: &SuperblockManager) -> FilesystemResult<()> {
+        let disk_block = self.block_mgr.map_logical_block(&self.inode, 0, sb_mgr)?
+            .ok_or(FilesystemError::CorruptedFilesystem)?;
+        let mut jsb = sb_mgr.read_block_data(disk_block)?;
+        jsb[JSB_SEQUENCE..JSB_SEQUENCE + 4].copy_from_slice(&sequence.to_be_bytes());
+        jsb[JSB_START..JSB_START + 4].copy_from_slice(&0u32.to_be_bytes());
+        sb_mgr.write_block_data(disk_block as u32, &jsb)
+    }
+}
+
+/// Replay the journal if the filesystem needs recovery. Returns true if
+/// anything on disk changed, in which case cached metadata must be re-read.
+pub fn recover(sb_mgr: &mut SuperblockManager, inode_mgr: &InodeManager, block_mgr: &BlockManager) -> FilesystemResult<bool> {
+    let sb = *sb_mgr.get_superblock().ok_or(FilesystemError::InvalidSuperblock)?;
+    let compat = sb.s_feature_compat;
+    let incompat = sb.s_feature_incompat;
+
+    if compat & EXT3_FEATURE_COMPAT_HAS_JOURNAL == 0 || incompat & EXT3_FEATURE_INCOMPAT_RECOVER == 0 {
+        return Ok(false);
+    }
+
+    let journal_inum = sb.s_journal_inum;
+    if incompat & EXT3_FEATURE_INCOMPAT_JOURNAL_DEV != 0 || journal_inum == 0 {
+        err_println!(target: "ext2", "Filesystem needs recovery from an external journal, which is not supported");
+        return Err(FilesystemError::UnsupportedFilesystem);
+    }
+
+    info_println!(target: "ext2", "Filesystem was not unmounted cleanly, recovering journal...");
+    let journal_inode = inode_mgr.read_inode(journal_inum, sb_mgr)?;
+    let journal = Journal::open(journal_inode, block_mgr, sb_mgr)?;
+
+    if journal.start != 0 {
+        let end = journal.replay(sb_mgr)?;
+        // Replayed blocks must be on disk before the log stops covering them
+        cache::flush()?;
+        journal.reset(end, sb_mgr)?;
+    }
+
+    // Replay may have rewritten the superblock itself
+    sb_mgr.init()?;
+    let mut sb = *sb_mgr.get_superblock().ok_or(FilesystemError::InvalidSuperblock)?;
+    sb.s_feature_incompat &= !EXT3_FEATURE_INCOMPAT_RECOVER;
+    sb_mgr.write_superblock(&sb)?;
+    cache::flush()?;
+
+    ok_println!(target: "ext2", "Journal recovery complete");
+    Ok(true)
+}
@@ -1,23 +1,92 @@
-// Modular ext2 Filesystem Implementation
+//! Modular ext2 Filesystem Implementation
+//!
+//! The superblock is validated at mount, falling back to a backup copy and
+//! restoring the primary from it. Files are read and written through block
+//! maps or extent trees, and the JBD2 journal of an ext3/ext4 image is
+//! replayed first. Directories are read across all their blocks. Symbolic
+//! links, fast (target in the inode) and slow (target in a block), are
+//! followed in path lookup up to 8 deep. Hard links and renames only
+//! rewrite directory entries, so `mv` never copies data. Creating, writing
+//! and reading a file stamp its ctime, mtime and atime (relatime) from the
+//! RTC.
 
-use super::traits::{FileSystem, FileEntry, FilesystemError, FilesystemResult};
+use super::traits::{DirListing, FileSystem, FileEntry, FileStat, FilesystemError, FilesystemResult, DIR_ENTRY_CACHE, FILE_ENTRY_CACHE};
+use super::path::{self, PathBuf};
+use crate::memory::slab::SlabList;
 use heapless::Vec;
+use elinos_common::warn_println;
 
 // Re-export modules
-pub mod structures;
+pub use elinos_common::ext2::structures;
 pub mod superblock;
 pub mod inode;
 pub mod directory;
 pub mod block;
+pub mod extent;
+pub mod journal;
 pub mod bitmap;
 
 use structures::*;
 use superblock::SuperblockManager;
-use inode::InodeManager;
+use inode::{current_time, InodeManager};
 use directory::DirectoryManager;
 use block::BlockManager;
 use bitmap::BitmapManager;
 
+/// Most symbolic links followed in looking up one path, as Linux's
+/// MAXSYMLINKS
+const MAX_SYMLINKS: usize = 8;
+
+/// Seconds after which a read updates the access time even if the file
+/// has not changed
+const RELATIME_INTERVAL: u32 = 24 * 60 * 60;
+
+/// The label in a superblock's `s_volume_name` bytes, if one is set and is
+/// valid UTF-8
+pub fn parse_volume_label(name: &[u8]) -> Option<heapless::String<16>> {
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    let label = core::str::from_utf8(&name[..len]).ok()?.trim();
+    if label.is_empty() {
+        return None;
+    }
+    heapless::String::try_from(label).ok()
+}
+
+/// Joins disk blocks handed to it in order into runs of sectors
+struct SectorRuns<'f> {
+    sectors_per_block: u64,
+    run: Option<(u64, u64)>,
+    f: &'f mut dyn FnMut(u64, u64) -> FilesystemResult<()>,
+}
+
+impl<'f> SectorRuns<'f> {
+    fn new(block_size: usize, f: &'f mut dyn FnMut(u64, u64) -> FilesystemResult<()>) -> Self {
+        SectorRuns { sectors_per_block: (block_size / SECTOR_SIZE) as u64, run: None, f }
+    }
+
+    fn add(&mut self, block: u64) -> FilesystemResult<()> {
+        let sector = block * self.sectors_per_block;
+        match self.run {
+            Some((start, count)) if start + count == sector => {
+                self.run = Some((start, count + self.sectors_per_block));
+            }
+            Some((start, count)) => {
+                (self.f)(start, count)?;
+                self.run = Some((sector, self.sectors_per_block));
+            }
+            None => self.run = Some((sector, self.sectors_per_block)),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> FilesystemResult<()> {
+        match self.run {
+            Some((start, count)) => (self.f)(start, count),
+            None => Ok(()),
+        }
+    }
+}
+
 /// Main ext2 Filesystem implementation
 pub struct Ext2FileSystem {
     superblock_mgr: SuperblockManager,
@@ -25,7 +94,8 @@ pub struct Ext2FileSystem {
     directory_mgr: DirectoryManager,
     block_mgr: BlockManager,
     bitmap_mgr: BitmapManager,
-    files: Vec<FileEntry, 64>,
+    /// The root directory's entries
+    files: SlabList<FileEntry>,
     initialized: bool,
     mounted: bool,
 }
@@ -38,7 +108,7 @@ impl Ext2FileSystem {
             directory_mgr: DirectoryManager::new(),
             block_mgr: BlockManager::new(),
             bitmap_mgr: BitmapManager::new(),
-            files: Vec::new(),
+            files: SlabList::new(&FILE_ENTRY_CACHE),
             initialized: false,
             mounted: false,
         }
@@ -46,13 +116,35 @@ impl Ext2FileSystem {
     
     /// Initialize the ext2 filesystem
     pub fn init(&mut self) -> FilesystemResult<()> {
+        self.init_from(false)
+    }
+    
+    /// Initialize from a backup superblock, for when the primary one is
+    /// damaged
+    pub fn init_from_backup(&mut self) -> FilesystemResult<()> {
+        self.init_from(true)
+    }
+    
+    fn init_from(&mut self, backup: bool) -> FilesystemResult<()> {
         // Initialize all managers in sequence
-        self.superblock_mgr.init()?;
+        let result = if backup { self.superblock_mgr.init_from_backup() } else { self.superblock_mgr.init() };
+        if result == Err(FilesystemError::InvalidSuperblock) && !backup {
+            if let Some(found) = superblock::find_backup() {
+                warn_println!(target: "ext2", "A valid backup superblock is at block {}; mount with --backup-superblock, or repair with 'fsck -r'",
+                    found.block);
+            }
+        }
+        result?;
         self.inode_mgr.init(&self.superblock_mgr)?;
         self.directory_mgr.init(&self.superblock_mgr, &self.inode_mgr)?;
         self.block_mgr.init(&self.superblock_mgr)?;
         self.bitmap_mgr.init(&self.superblock_mgr)?;
         
+        // Bring the filesystem up to date before reading anything else
+        if journal::recover(&mut self.superblock_mgr, &self.inode_mgr, &self.block_mgr)? {
+            self.inode_mgr.init(&self.superblock_mgr)?;
+        }
+        
         // Parse root directory
         self.parse_root_directory()?;
         
@@ -68,53 +160,165 @@ impl Ext2FileSystem {
     }
     
     fn resolve_path_to_inode(&self, path: &str) -> FilesystemResult<u32> {
-        if path == "/" {
-            return Ok(EXT2_ROOT_INODE);
-        }
-        
-        let path = path.trim_start_matches('/');
-        let components: Vec<&str, 32> = path.split('/').collect();
-        
+        self.lookup_inode(path, true)
+    }
+    
+    /// Inode of `path`, following symbolic links on the way. A link as the
+    /// last name is followed only with `follow`, so the link itself can be
+    /// removed, renamed or read. A relative target is taken from the
+    /// directory holding the link and an absolute one from the root of
+    /// this filesystem, not of the whole tree, so a link does not lead
+    /// into another mount.
+    fn lookup_inode(&self, path: &str, follow: bool) -> FilesystemResult<u32> {
+        let mut pending = PathBuf::try_from(path).map_err(|_| FilesystemError::FilenameTooLong)?;
         let mut current_inode = EXT2_ROOT_INODE;
-        
-        for component in components.iter() {
-            if component.is_empty() {
-                continue;
-            }
-            
-            let inode = self.inode_mgr.read_inode(current_inode, &self.superblock_mgr)?;
-            if !self.directory_mgr.is_directory(&inode) {
-                return Err(FilesystemError::NotADirectory);
+        let mut links = 0;
+        
+        loop {
+            let trimmed = pending.trim_start_matches('/');
+            let (name, rest) = match trimmed.find('/') {
+                Some(pos) => (&trimmed[..pos], &trimmed[pos..]),
+                None => (trimmed, ""),
+            };
+            if name.is_empty() {
+                return Ok(current_inode);
             }
             
-            if let Some((_, child_inode, _)) = self.directory_mgr.find_entry_in_dir(current_inode, component, &self.superblock_mgr, &self.inode_mgr)? {
-                current_inode = child_inode;
+            let next = if name == "." {
+                PathBuf::try_from(rest).map_err(|_| FilesystemError::FilenameTooLong)?
             } else {
-                return Err(FilesystemError::FileNotFound);
+                let inode = self.inode_mgr.read_inode(current_inode, &self.superblock_mgr)?;
+                if !self.directory_mgr.is_directory(&inode) {
+                    return Err(FilesystemError::NotADirectory);
+                }
+                
+                // `..` comes from a link target; the directory's own entry
+                // for it leads to the parent
+                let (_, child_inode_num, _) = self.directory_mgr.find_entry_in_dir(current_inode, name, &self.superblock_mgr, &self.inode_mgr)?
+                    .ok_or(FilesystemError::FileNotFound)?;
+                let child_inode = self.inode_mgr.read_inode(child_inode_num, &self.superblock_mgr)?;
+                let last = path::components(rest).next().is_none();
+                
+                if child_inode.is_symlink() && (follow || !last) {
+                    links += 1;
+                    if links > MAX_SYMLINKS {
+                        return Err(FilesystemError::SymlinkLoop);
+                    }
+                    let target = self.read_link_target(&child_inode)?;
+                    if target.starts_with('/') {
+                        current_inode = EXT2_ROOT_INODE;
+                    }
+                    let mut next = target;
+                    next.push('/').and_then(|()| next.push_str(rest))
+                        .map_err(|()| FilesystemError::FilenameTooLong)?;
+                    next
+                } else {
+                    current_inode = child_inode_num;
+                    PathBuf::try_from(rest).map_err(|_| FilesystemError::FilenameTooLong)?
+                }
+            };
+            pending = next;
+        }
+    }
+    
+    /// Target of the symbolic link `inode`. A short one is kept in the
+    /// inode's block pointers, a longer one in a data block.
+    fn read_link_target(&self, inode: &Ext2Inode) -> FilesystemResult<PathBuf> {
+        let size = inode.get_size() as usize;
+        if size > path::MAX_PATH_LEN {
+            return Err(FilesystemError::FilenameTooLong);
+        }
+        
+        let mut bytes = [0u8; path::MAX_PATH_LEN];
+        if inode.is_fast_symlink(self.superblock_mgr.get_block_size()) {
+            if size > EXT2_FAST_SYMLINK_MAX {
+                return Err(FilesystemError::CorruptedFilesystem);
             }
+            let block = inode.i_block;
+            for (chunk, word) in bytes.chunks_exact_mut(4).zip(block.iter()) {
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+        } else if self.block_mgr.read_at(inode, size, 0, &mut bytes[..size], &self.superblock_mgr)? != size {
+            return Err(FilesystemError::CorruptedFilesystem);
         }
         
-        Ok(current_inode)
+        let target = core::str::from_utf8(&bytes[..size]).map_err(|_| FilesystemError::CorruptedFilesystem)?;
+        PathBuf::try_from(target).map_err(|_| FilesystemError::FilenameTooLong)
+    }
+    
+    /// Stamp `inode_num` as changed now: its ctime, and its mtime too if
+    /// its data changed, which for a directory is its entries
+    fn mark_changed(&self, inode_num: u32, data: bool) -> FilesystemResult<()> {
+        let mut inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        let now = current_time();
+        inode.i_ctime = now;
+        if data {
+            inode.i_mtime = now;
+        }
+        self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr)
+    }
+    
+    /// Take one name away from `inode_num`; the inode and its blocks are
+    /// freed with the last one
+    fn drop_link(&mut self, inode_num: u32, inode: &Ext2Inode) -> FilesystemResult<()> {
+        if inode.i_links_count > 1 {
+            let mut inode = *inode;
+            inode.i_links_count -= 1;
+            inode.i_ctime = current_time();
+            return self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr);
+        }
+        self.block_mgr.free_inode_blocks(inode, &mut self.superblock_mgr)?;
+        self.inode_mgr.free_inode(inode_num, &self.superblock_mgr)
+    }
+    
+    /// Count a subdirectory's `..` in or out of directory `dir_inode`'s
+    /// link count. It stays at 2 or more, its own `.` and name, as
+    /// directories made before this was kept can be undercounted.
+    fn adjust_dir_links(&self, dir_inode: u32, delta: i16) -> FilesystemResult<()> {
+        let mut inode = self.inode_mgr.read_inode(dir_inode, &self.superblock_mgr)?;
+        let links = inode.i_links_count.saturating_add_signed(delta).max(2);
+        if links > EXT2_LINK_MAX {
+            return Err(FilesystemError::TooManyLinks);
+        }
+        inode.i_links_count = links;
+        self.inode_mgr.write_inode(dir_inode, &inode, &self.superblock_mgr)
+    }
+    
+    /// Whether directory `dir_inode` is `ancestor` or below it, going up
+    /// through the `..` entries
+    fn is_within(&self, mut dir_inode: u32, ancestor: u32) -> FilesystemResult<bool> {
+        loop {
+            if dir_inode == ancestor {
+                return Ok(true);
+            }
+            if dir_inode == EXT2_ROOT_INODE {
+                return Ok(false);
+            }
+            let (_, parent, _) = self.directory_mgr.find_entry_in_dir(dir_inode, "..", &self.superblock_mgr, &self.inode_mgr)?
+                .ok_or(FilesystemError::CorruptedFilesystem)?;
+            if parent == dir_inode {
+                return Ok(false);
+            }
+            dir_inode = parent;
+        }
     }
     
     fn resolve_path_to_parent_and_filename(&self, path: &str) -> FilesystemResult<(u32, heapless::String<255>)> {
-        let path = path.trim_start_matches('/').trim_end_matches('/');
-        
-        let last_slash = path.rfind('/');
-        
-        let (parent_path, filename) = if let Some(pos) = last_slash {
-            (&path[..pos], &path[pos + 1..])
-        } else {
-            ("/", path)
-        };
+        let (parent_path, filename) = path::split_parent(path);
         
-        let parent_inode = self.resolve_path_to_inode(if parent_path.is_empty() { "/" } else { parent_path })?;
+        let parent_inode = self.resolve_path_to_inode(parent_path)?;
         let filename = heapless::String::try_from(filename)
             .map_err(|_| FilesystemError::FilenameTooLong)?;
         
         Ok((parent_inode, filename))
     }
     
+    /// Volume label from the superblock, if one is set
+    pub fn volume_label(&self) -> Option<heapless::String<16>> {
+        let name = self.superblock_mgr.get_superblock()?.s_volume_name;
+        parse_volume_label(&name)
+    }
+
     /// Get a file entry for an existing file (public method)
     pub fn get_file_entry(&self, path: &str) -> FilesystemResult<FileEntry> {
         let inode_num = self.resolve_path_to_inode(path)?;
@@ -127,6 +331,60 @@ impl Ext2FileSystem {
         }
     }
     
+    /// Call `f` with each run of sectors holding the data of the file at
+    /// `path`, as (first sector, sectors). Whole blocks are given, so the
+    /// slack after the end of the file is included; holes are not.
+    pub fn for_each_file_sector_run(&self, path: &str, f: &mut dyn FnMut(u64, u64) -> FilesystemResult<()>) -> FilesystemResult<()> {
+        let inode_num = self.resolve_path_to_inode(path)?;
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        if inode.is_directory() {
+            return Err(FilesystemError::IsADirectory);
+        }
+        if !inode.is_regular_file() {
+            return Err(FilesystemError::NotImplemented);
+        }
+
+        let block_size = self.superblock_mgr.get_block_size();
+        let blocks = inode.get_size().div_ceil(block_size as u64);
+        let mut runs = SectorRuns::new(block_size, f);
+        for logical in 0..blocks {
+            if let Some(block) = self.block_mgr.map_logical_block(&inode, logical as u32, &self.superblock_mgr)? {
+                runs.add(block)?;
+            }
+        }
+        runs.finish()
+    }
+
+    /// Call `f` with each run of sectors in free blocks, as (first sector,
+    /// sectors). Groups still marked uninitialized are skipped, as they
+    /// have never held data.
+    pub fn for_each_free_sector_run(&self, f: &mut dyn FnMut(u64, u64) -> FilesystemResult<()>) -> FilesystemResult<()> {
+        let sb = *self.superblock_mgr.get_superblock().ok_or(FilesystemError::InvalidSuperblock)?;
+        let first_data_block = sb.s_first_data_block;
+        let blocks_count = sb.s_blocks_count_lo;
+        let blocks_per_group = sb.s_blocks_per_group;
+
+        let mut runs = SectorRuns::new(self.superblock_mgr.get_block_size(), f);
+        for group in 0..sb.group_count() {
+            let gd = self.superblock_mgr.read_group_desc(group)?;
+            let free = gd.bg_free_blocks_count_lo;
+            let flags = gd.bg_flags;
+            if free == 0 || flags & EXT4_BG_BLOCK_UNINIT != 0 {
+                continue;
+            }
+
+            let group_start = first_data_block + group * blocks_per_group;
+            let group_blocks = core::cmp::min(blocks_per_group, blocks_count - group_start);
+            let bitmap = self.superblock_mgr.read_block_data(gd.bg_block_bitmap_lo as u64)?;
+            for bit in 0..group_blocks {
+                if bitmap[(bit / 8) as usize] & (1 << (bit % 8)) == 0 {
+                    runs.add((group_start + bit) as u64)?;
+                }
+            }
+        }
+        runs.finish()
+    }
+
     /// Refresh the in-memory cache by re-reading the root directory
     fn refresh_root_directory_cache(&mut self) -> FilesystemResult<()> {
         // Clear the current cache
@@ -140,23 +398,23 @@ impl Ext2FileSystem {
 }
 
 impl FileSystem for Ext2FileSystem {
-    fn list_files(&self) -> FilesystemResult<Vec<(heapless::String<64>, usize), 32>> {
+    fn list_files(&self) -> FilesystemResult<DirListing> {
         if !self.is_mounted() {
             return Err(FilesystemError::NotMounted);
         }
         
-        let mut result = Vec::new();
+        let mut result = DirListing::new(&DIR_ENTRY_CACHE);
         for file in &self.files {
             if !file.is_directory {
                 let name = heapless::String::try_from(file.name.as_str())
                     .map_err(|_| FilesystemError::FilenameTooLong)?;
-                result.push((name, file.size)).map_err(|_| FilesystemError::FilesystemFull)?;
+                result.push((name, file.size, false)).map_err(|_| FilesystemError::FilesystemFull)?;
             }
         }
         Ok(result)
     }
     
-    fn list_directory(&self, path: &str) -> FilesystemResult<Vec<(heapless::String<64>, usize, bool), 32>> {
+    fn list_directory(&self, path: &str) -> FilesystemResult<DirListing> {
         if !self.is_mounted() {
             return Err(FilesystemError::NotMounted);
         }
@@ -209,6 +467,22 @@ impl FileSystem for Ext2FileSystem {
         Ok(bytes_to_copy)
     }
     
+    fn read_at(&self, filename: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.resolve_path_to_inode(filename)?;
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        
+        if self.directory_mgr.is_directory(&inode) {
+            return Err(FilesystemError::IsADirectory);
+        }
+        
+        let file_size = self.inode_mgr.get_file_size(&inode);
+        self.block_mgr.read_at(&inode, file_size, offset, buffer, &self.superblock_mgr)
+    }
+    
     fn get_file_size(&self, filename: &str) -> FilesystemResult<usize> {
         if !self.is_mounted() {
             return Err(FilesystemError::NotMounted);
@@ -247,7 +521,8 @@ impl FileSystem for Ext2FileSystem {
             return Err(FilesystemError::NotMounted);
         }
         
-        if self.file_exists(path) {
+        // A link holds its name even if what it points to is gone
+        if self.lookup_inode(path, false).is_ok() {
             return Err(FilesystemError::FileAlreadyExists);
         }
         
@@ -260,6 +535,7 @@ impl FileSystem for Ext2FileSystem {
             let inode_mgr = &self.inode_mgr;
             self.directory_mgr.add_directory_entry(parent_inode, new_inode, &filename, EXT2_FT_REG_FILE, sb_mgr, inode_mgr)?;
         }
+        self.mark_changed(parent_inode, true)?;
         
         // Refresh the in-memory cache to include the new file
         self.refresh_root_directory_cache()?;
@@ -272,7 +548,7 @@ impl FileSystem for Ext2FileSystem {
             return Err(FilesystemError::NotMounted);
         }
         
-        if self.file_exists(path) {
+        if self.lookup_inode(path, false).is_ok() {
             return Err(FilesystemError::FileAlreadyExists);
         }
         
@@ -287,6 +563,8 @@ impl FileSystem for Ext2FileSystem {
             self.directory_mgr.add_directory_entry(parent_inode, new_inode, &dirname, EXT2_FT_DIR, sb_mgr, inode_mgr)?;
             self.directory_mgr.create_dot_entries(new_inode, parent_inode, sb_mgr, inode_mgr)?;
         }
+        self.adjust_dir_links(parent_inode, 1)?;
+        self.mark_changed(parent_inode, true)?;
         
         // Refresh the in-memory cache to include the new directory
         self.refresh_root_directory_cache()?;
@@ -303,6 +581,9 @@ impl FileSystem for Ext2FileSystem {
         let mut inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
         
         let bytes_written = self.block_mgr.write_file_content(&mut inode, offset, data, &mut self.superblock_mgr)?;
+        let now = current_time();
+        inode.i_mtime = now;
+        inode.i_ctime = now;
         self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr)?;
         
         Ok(bytes_written)
@@ -313,7 +594,8 @@ impl FileSystem for Ext2FileSystem {
             return Err(FilesystemError::NotMounted);
         }
         
-        let inode_num = self.resolve_path_to_inode(path)?;
+        // Removing a link removes the link, not what it points to
+        let inode_num = self.lookup_inode(path, false)?;
         let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
         
         if self.directory_mgr.is_directory(&inode) {
@@ -329,9 +611,9 @@ impl FileSystem for Ext2FileSystem {
             self.directory_mgr.remove_directory_entry(parent_inode, &filename, sb_mgr, inode_mgr)?;
         }
 
-        // Free blocks and inode
-        self.block_mgr.free_inode_blocks(&inode, &mut self.superblock_mgr)?;
-        self.inode_mgr.free_inode(inode_num, &self.superblock_mgr)?;
+        // Free blocks and inode once no other name is left
+        self.drop_link(inode_num, &inode)?;
+        self.mark_changed(parent_inode, true)?;
         
         // Refresh the in-memory cache to reflect the deletion
         self.refresh_root_directory_cache()?;
@@ -344,7 +626,7 @@ impl FileSystem for Ext2FileSystem {
             return Err(FilesystemError::NotMounted);
         }
         
-        let inode_num = self.resolve_path_to_inode(path)?;
+        let inode_num = self.lookup_inode(path, false)?;
         let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
         
         if !self.directory_mgr.is_directory(&inode) {
@@ -368,6 +650,8 @@ impl FileSystem for Ext2FileSystem {
         // Free blocks and inode
         self.block_mgr.free_inode_blocks(&inode, &mut self.superblock_mgr)?;
         self.inode_mgr.free_inode(inode_num, &self.superblock_mgr)?;
+        self.adjust_dir_links(parent_inode, -1)?;
+        self.mark_changed(parent_inode, true)?;
         
         // Refresh the in-memory cache to reflect the deletion
         self.refresh_root_directory_cache()?;
@@ -375,6 +659,244 @@ impl FileSystem for Ext2FileSystem {
         Ok(())
     }
     
+    fn rename(&mut self, from: &str, to: &str) -> FilesystemResult<()> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.lookup_inode(from, false)?;
+        if inode_num == EXT2_ROOT_INODE {
+            return Err(FilesystemError::Busy);
+        }
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        let is_dir = self.directory_mgr.is_directory(&inode);
+        let file_type = if is_dir {
+            EXT2_FT_DIR
+        } else if inode.is_symlink() {
+            EXT2_FT_SYMLINK
+        } else {
+            EXT2_FT_REG_FILE
+        };
+        
+        let (from_parent, from_name) = self.resolve_path_to_parent_and_filename(from)?;
+        let (to_parent, to_name) = self.resolve_path_to_parent_and_filename(to)?;
+        // A directory cannot go inside itself
+        if is_dir && self.is_within(to_parent, inode_num)? {
+            return Err(FilesystemError::InvalidPath);
+        }
+        let existing = self.directory_mgr.find_entry_in_dir(to_parent, &to_name, &self.superblock_mgr, &self.inode_mgr)?;
+        
+        match existing {
+            Some((_, old_inode_num, _)) if old_inode_num == inode_num => return Ok(()),
+            Some((_, old_inode_num, _)) => {
+                let old_inode = self.inode_mgr.read_inode(old_inode_num, &self.superblock_mgr)?;
+                let old_is_dir = self.directory_mgr.is_directory(&old_inode);
+                match (is_dir, old_is_dir) {
+                    (false, true) => return Err(FilesystemError::IsADirectory),
+                    (true, false) => return Err(FilesystemError::NotADirectory),
+                    (true, true) if !self.directory_mgr.is_empty_directory(&old_inode, &self.superblock_mgr)? => {
+                        return Err(FilesystemError::DirectoryNotEmpty);
+                    }
+                    _ => {}
+                }
+                // `to` switches from the old file to this one in one write,
+                // then the old file goes
+                self.directory_mgr.replace_entry_inode(to_parent, &to_name, inode_num, file_type, &self.superblock_mgr, &self.inode_mgr)?;
+                self.directory_mgr.remove_directory_entry(from_parent, &from_name, &self.superblock_mgr, &self.inode_mgr)?;
+                if old_is_dir {
+                    self.block_mgr.free_inode_blocks(&old_inode, &mut self.superblock_mgr)?;
+                    self.inode_mgr.free_inode(old_inode_num, &self.superblock_mgr)?;
+                    self.adjust_dir_links(to_parent, -1)?;
+                } else {
+                    self.drop_link(old_inode_num, &old_inode)?;
+                }
+            }
+            None => {
+                // Under both names for a moment rather than under neither
+                {
+                    let sb_mgr = &mut self.superblock_mgr;
+                    let inode_mgr = &self.inode_mgr;
+                    self.directory_mgr.add_directory_entry(to_parent, inode_num, &to_name, file_type, sb_mgr, inode_mgr)?;
+                }
+                self.directory_mgr.remove_directory_entry(from_parent, &from_name, &self.superblock_mgr, &self.inode_mgr)?;
+            }
+        }
+        
+        // A directory moved to another parent takes its `..` along
+        if is_dir && from_parent != to_parent {
+            self.directory_mgr.replace_entry_inode(inode_num, "..", to_parent, EXT2_FT_DIR, &self.superblock_mgr, &self.inode_mgr)?;
+            self.adjust_dir_links(from_parent, -1)?;
+            self.adjust_dir_links(to_parent, 1)?;
+        }
+        self.mark_changed(inode_num, false)?;
+        self.mark_changed(from_parent, true)?;
+        if to_parent != from_parent {
+            self.mark_changed(to_parent, true)?;
+        }
+        
+        // Refresh the in-memory cache to show the new name
+        self.refresh_root_directory_cache()?;
+        
+        Ok(())
+    }
+    
+    fn link(&mut self, existing: &str, new: &str) -> FilesystemResult<()> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.lookup_inode(existing, false)?;
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        if self.directory_mgr.is_directory(&inode) {
+            return Err(FilesystemError::IsADirectory);
+        }
+        if inode.i_links_count >= EXT2_LINK_MAX {
+            return Err(FilesystemError::TooManyLinks);
+        }
+        if self.lookup_inode(new, false).is_ok() {
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        let file_type = if inode.is_symlink() { EXT2_FT_SYMLINK } else { EXT2_FT_REG_FILE };
+        let (parent_inode, name) = self.resolve_path_to_parent_and_filename(new)?;
+        
+        // Counted before the name is added: a failure in between leaves a
+        // count too high, which only keeps the inode, never one too low
+        let mut linked = inode;
+        linked.i_links_count += 1;
+        linked.i_ctime = current_time();
+        self.inode_mgr.write_inode(inode_num, &linked, &self.superblock_mgr)?;
+        let added = {
+            let sb_mgr = &mut self.superblock_mgr;
+            let inode_mgr = &self.inode_mgr;
+            self.directory_mgr.add_directory_entry(parent_inode, inode_num, &name, file_type, sb_mgr, inode_mgr)
+        };
+        if let Err(e) = added {
+            let _ = self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr);
+            return Err(e);
+        }
+        self.mark_changed(parent_inode, true)?;
+        
+        self.refresh_root_directory_cache()?;
+        
+        Ok(())
+    }
+    
+    fn create_symlink(&mut self, path: &str, target: &str) -> FilesystemResult<FileEntry> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        if target.is_empty() {
+            return Err(FilesystemError::InvalidPath);
+        }
+        if target.len() > path::MAX_PATH_LEN {
+            return Err(FilesystemError::FilenameTooLong);
+        }
+        if self.lookup_inode(path, false).is_ok() {
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        
+        let (parent_inode, linkname) = self.resolve_path_to_parent_and_filename(path)?;
+        let new_inode = self.inode_mgr.allocate_inode(EXT2_S_IFLNK | 0o777, 0, 0, 1, 0, &self.superblock_mgr)?;
+        let mut inode = self.inode_mgr.read_inode(new_inode, &self.superblock_mgr)?;
+        
+        if target.len() <= EXT2_FAST_SYMLINK_MAX {
+            // Short enough to live in the block pointers, with no block
+            let mut bytes = [0u8; 60];
+            bytes[..target.len()].copy_from_slice(target.as_bytes());
+            let mut block = [0u32; 15];
+            for (word, chunk) in block.iter_mut().zip(bytes.chunks_exact(4)) {
+                *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+            inode.i_block = block;
+            inode.set_size(target.len() as u64);
+        } else {
+            self.block_mgr.write_file_content(&mut inode, 0, target.as_bytes(), &mut self.superblock_mgr)?;
+        }
+        self.inode_mgr.write_inode(new_inode, &inode, &self.superblock_mgr)?;
+        
+        {
+            let sb_mgr = &mut self.superblock_mgr;
+            let inode_mgr = &self.inode_mgr;
+            self.directory_mgr.add_directory_entry(parent_inode, new_inode, &linkname, EXT2_FT_SYMLINK, sb_mgr, inode_mgr)?;
+        }
+        self.mark_changed(parent_inode, true)?;
+        
+        self.refresh_root_directory_cache()?;
+        
+        FileEntry::new_file(&linkname, new_inode as u64, target.len())
+    }
+    
+    fn read_link(&self, path: &str) -> FilesystemResult<PathBuf> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.lookup_inode(path, false)?;
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        if !inode.is_symlink() {
+            return Err(FilesystemError::InvalidPath);
+        }
+        self.read_link_target(&inode)
+    }
+    
+    fn stat(&self, path: &str, follow: bool) -> FilesystemResult<FileStat> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.lookup_inode(path, follow)?;
+        let inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        Ok(FileStat {
+            device: 0,
+            inode: inode_num as u64,
+            mode: inode.i_mode as u32,
+            links: inode.i_links_count as u32,
+            uid: inode.i_uid as u32,
+            gid: inode.i_gid as u32,
+            size: inode.get_size(),
+            blocks: inode.i_blocks_lo as u64,
+            block_size: self.superblock_mgr.get_block_size() as u32,
+            atime: inode.i_atime as u64,
+            mtime: inode.i_mtime as u64,
+            ctime: inode.i_ctime as u64,
+        })
+    }
+    
+    fn set_times(&mut self, path: &str, atime: Option<u64>, mtime: Option<u64>) -> FilesystemResult<()> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.resolve_path_to_inode(path)?;
+        let mut inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        if let Some(atime) = atime {
+            inode.i_atime = atime as u32;
+        }
+        if let Some(mtime) = mtime {
+            inode.i_mtime = mtime as u32;
+        }
+        inode.i_ctime = current_time();
+        self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr)
+    }
+    
+    fn mark_accessed(&mut self, path: &str) -> FilesystemResult<()> {
+        if !self.is_mounted() {
+            return Err(FilesystemError::NotMounted);
+        }
+        
+        let inode_num = self.resolve_path_to_inode(path)?;
+        let mut inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
+        let now = current_time();
+        // As Linux's relatime: only when the file changed since it was last
+        // read, or a day later, so reading is not a write each time
+        let (atime, mtime, ctime) = (inode.i_atime, inode.i_mtime, inode.i_ctime);
+        if atime > mtime && atime > ctime && now.saturating_sub(atime) < RELATIME_INTERVAL {
+            return Ok(());
+        }
+        inode.i_atime = now;
+        self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr)
+    }
+    
     fn truncate_file(&mut self, file: &FileEntry, new_size: u64) -> FilesystemResult<()> {
         if !self.is_mounted() {
             return Err(FilesystemError::NotMounted);
@@ -383,7 +905,10 @@ impl FileSystem for Ext2FileSystem {
         let inode_num = file.inode as u32;
         let mut inode = self.inode_mgr.read_inode(inode_num, &self.superblock_mgr)?;
         
-        self.block_mgr.truncate_file(&mut inode, new_size)?;
+        self.block_mgr.truncate_file(&mut inode, new_size, &mut self.superblock_mgr)?;
+        let now = current_time();
+        inode.i_mtime = now;
+        inode.i_ctime = now;
         self.inode_mgr.write_inode(inode_num, &inode, &self.superblock_mgr)?;
         
         Ok(())
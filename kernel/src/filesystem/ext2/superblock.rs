@@ -2,14 +2,127 @@
 
 use super::structures::*;
 use super::super::traits::{FilesystemError, FilesystemResult};
-use crate::{console_println, virtio};
+use super::super::cache;
+use crate::{console_println, ok_println, err_println, warn_println, info_println};
 use heapless::Vec;
 
+/// Sector of the primary superblock
+const PRIMARY_SUPERBLOCK_SECTOR: u64 = (EXT2_SUPERBLOCK_OFFSET / SECTOR_SIZE) as u64;
+
+/// Block groups searched for a backup superblock. With sparse_super only
+/// groups 0, 1 and powers of 3, 5 and 7 keep one; without it every group
+/// does, so these are found either way.
+const BACKUP_GROUPS: [u32; 8] = [1, 3, 5, 7, 9, 25, 27, 49];
+
+/// A valid backup copy of the superblock
+#[derive(Clone, Copy)]
+pub struct BackupSuperblock {
+    /// Block group holding the copy
+    pub group: u32,
+    /// Block the copy starts at; the group descriptor table follows it
+    pub block: u32,
+    pub superblock: Ext2Superblock,
+}
+
+impl BackupSuperblock {
+    fn sector(&self) -> u64 {
+        let block_size = 1024u64 << { self.superblock.s_log_block_size };
+        self.block as u64 * block_size / SECTOR_SIZE as u64
+    }
+}
+
+/// Whether `sb` describes a filesystem we can use, beyond having the magic
+pub fn superblock_is_valid(sb: &Ext2Superblock) -> bool {
+    let magic = sb.s_magic;
+    let log_block_size = sb.s_log_block_size;
+    let blocks = sb.s_blocks_count_lo;
+    let inodes = sb.s_inodes_count;
+    let blocks_per_group = sb.s_blocks_per_group;
+    let inodes_per_group = sb.s_inodes_per_group;
+    let first_data_block = sb.s_first_data_block;
+
+    // Blocks of up to 4 KB, which is what read_block_data handles
+    if magic != EXT2_MAGIC || log_block_size > 2 {
+        return false;
+    }
+    let block_size = 1024u32 << log_block_size;
+    blocks != 0
+        && inodes != 0
+        && blocks_per_group != 0
+        && blocks_per_group <= 8 * block_size
+        && inodes_per_group != 0
+        && first_data_block == if block_size == 1024 { 1 } else { 0 }
+        && first_data_block < blocks
+}
+
+/// Read a superblock copy starting at `sector`
+fn read_superblock_at(sector: u64) -> FilesystemResult<Ext2Superblock> {
+    let mut sb_buffer = [0u8; 1024];
+    cache::read_blocks(sector, &mut sb_buffer)?;
+    Ok(unsafe { core::ptr::read_unaligned(sb_buffer.as_ptr() as *const Ext2Superblock) })
+}
+
+/// Whether the primary superblock of the selected volume is valid
+pub fn primary_is_valid() -> bool {
+    read_superblock_at(PRIMARY_SUPERBLOCK_SECTOR).is_ok_and(|sb| superblock_is_valid(&sb))
+}
+
+/// Find the first valid backup superblock on the selected volume. The
+/// primary may be unreadable, so each block size mkfs uses is tried with
+/// its default group size of 8 blocks per byte of block.
+pub fn find_backup() -> Option<BackupSuperblock> {
+    for log_block_size in 0..=2u32 {
+        let block_size = 1024u32 << log_block_size;
+        let blocks_per_group = 8 * block_size;
+        let first_data_block = if block_size == 1024 { 1 } else { 0 };
+
+        for group in BACKUP_GROUPS {
+            let block = first_data_block + group * blocks_per_group;
+            let sector = block as u64 * block_size as u64 / SECTOR_SIZE as u64;
+            let Ok(sb) = read_superblock_at(sector) else {
+                // Past the end of the volume
+                break;
+            };
+            let matches = superblock_is_valid(&sb)
+                && { sb.s_log_block_size } == log_block_size
+                && { sb.s_blocks_per_group } == blocks_per_group
+                && group < sb.group_count()
+                && ({ sb.s_rev_level } == 0 || { sb.s_block_group_nr } as u32 == group);
+            if matches {
+                return Some(BackupSuperblock { group, block, superblock: sb });
+            }
+        }
+    }
+    None
+}
+
+/// Overwrite the primary superblock and group descriptor table of the
+/// selected volume with the backup copies in `backup`
+pub fn restore_primary(backup: &BackupSuperblock) -> FilesystemResult<()> {
+    let mut sb = backup.superblock;
+    sb.s_block_group_nr = 0;
+    let mut mgr = SuperblockManager::new();
+    mgr.block_size = 1024 << { sb.s_log_block_size };
+    mgr.superblock = Some(sb);
+
+    let table_blocks = (sb.group_count() as usize * sb.group_desc_size()).div_ceil(mgr.block_size) as u32;
+    let primary_table = sb.s_first_data_block + 1;
+    for i in 0..table_blocks {
+        let data = mgr.read_block_data((backup.block + 1 + i) as u64)?;
+        mgr.write_block_data(primary_table + i, &data)?;
+    }
+    mgr.write_superblock(&sb)
+}
+
 /// Manages ext2 superblock operations
 pub struct SuperblockManager {
     superblock: Option<Ext2Superblock>,
     group_desc: Option<Ext2GroupDesc>,
     block_size: usize,
+    /// Where the superblock in use is read from and written to
+    superblock_sector: u64,
+    /// First block of the group descriptor table in use
+    group_table_block: Option<u32>,
 }
 
 impl SuperblockManager {
@@ -18,6 +131,8 @@ impl SuperblockManager {
             superblock: None,
             group_desc: None,
             block_size: 1024, // Default ext2 block size
+            superblock_sector: PRIMARY_SUPERBLOCK_SECTOR,
+            group_table_block: None,
         }
     }
     
@@ -28,35 +143,22 @@ impl SuperblockManager {
         Ok(())
     }
     
+    /// Initialize from the first valid backup superblock and the group
+    /// descriptor table after it, for when the primary is damaged. Changes
+    /// are written to the backup copies.
+    pub fn init_from_backup(&mut self) -> FilesystemResult<()> {
+        let backup = find_backup().ok_or(FilesystemError::InvalidSuperblock)?;
+        warn_println!(target: "ext2", "Using backup superblock at block {} (group {})", backup.block, backup.group);
+        self.superblock_sector = backup.sector();
+        self.group_table_block = Some(backup.block + 1);
+        self.init()
+    }
+    
     /// Read and validate superblock from disk
     fn read_superblock(&mut self) -> FilesystemResult<()> {
-        console_println!("[i] Reading ext2 superblock...");
-        
-        let mut disk_device = virtio::VIRTIO_BLK.lock();
-        
-        if !disk_device.is_initialized() {
-            return Err(FilesystemError::DeviceError);
-        }
-
-        // Read superblock sectors (1024 bytes starting at offset 1024)
-        let start_sector = EXT2_SUPERBLOCK_OFFSET / SECTOR_SIZE; // sector 2
-        let mut sb_buffer = [0u8; 1024];
-        
-        // Read 2 sectors to get full superblock
-        for i in 0..2 {
-            let current_sector = (start_sector + i) as u64;
-            let mut sector_buf = [0u8; SECTOR_SIZE];
-            
-            disk_device.read_blocks(current_sector, &mut sector_buf)
-                .map_err(|_| FilesystemError::IoError)?;
-            
-            sb_buffer[i * SECTOR_SIZE..(i + 1) * SECTOR_SIZE].copy_from_slice(&sector_buf);
-        }
+        info_println!(target: "ext2", "Reading ext2 superblock...");
         
-        drop(disk_device);
-        
-        // Parse superblock
-        let sb: Ext2Superblock = unsafe { core::ptr::read(sb_buffer.as_ptr() as *const Ext2Superblock) };
+        let sb = read_superblock_at(self.superblock_sector)?;
         
         // Copy values from packed struct to avoid reference issues
         let magic = sb.s_magic;
@@ -66,41 +168,45 @@ impl SuperblockManager {
         
         // Validate magic number
         if magic != EXT2_MAGIC {
-            console_println!("[x] Invalid ext2 magic: 0x{:X}, expected 0x{:X}", magic, EXT2_MAGIC);
+            err_println!(target: "ext2", "Invalid ext2 magic: 0x{:X}, expected 0x{:X}", magic, EXT2_MAGIC);
+            return Err(FilesystemError::InvalidSuperblock);
+        }
+        if !superblock_is_valid(&sb) {
+            err_println!(target: "ext2", "ext2 superblock has impossible geometry");
             return Err(FilesystemError::InvalidSuperblock);
         }
         
         // Calculate block size
         self.block_size = 1024 << log_block_size;
         
-        console_println!("[o] Valid ext2 superblock found!");
+        ok_println!(target: "ext2", "Valid ext2 superblock found!");
         console_println!("   Block size: {} bytes", self.block_size);
         console_println!("   Total blocks: {}", total_blocks);
         console_println!("   Total inodes: {}", total_inodes);
         
+        let ro_compat = sb.s_feature_ro_compat;
+        if ro_compat & (EXT4_FEATURE_RO_COMPAT_METADATA_CSUM | EXT4_FEATURE_RO_COMPAT_GDT_CSUM) != 0 {
+            warn_println!(target: "ext2", "Filesystem has metadata checksums, which writes do not update; run e2fsck after writing");
+        }
+        
         self.superblock = Some(sb);
         Ok(())
     }
     
     /// Read group descriptor
     fn read_group_descriptor(&mut self) -> FilesystemResult<()> {
-        console_println!("[i] Reading group descriptor...");
+        info_println!(target: "ext2", "Reading group descriptor...");
         
         let _sb = self.superblock.as_ref().ok_or(FilesystemError::InvalidSuperblock)?;
         
-        // Group descriptor is in the block after superblock
-        let gd_block = if self.block_size == 1024 { 2 } else { 1 };
-        let gd_data = self.read_block_data(gd_block)?;
-        
-        // Parse first group descriptor
-        let gd: Ext2GroupDesc = unsafe { core::ptr::read(gd_data.as_ptr() as *const Ext2GroupDesc) };
+        let gd = self.read_group_desc(0)?;
         
         // Copy values from packed struct to avoid reference issues
         let block_bitmap = gd.bg_block_bitmap_lo;
         let inode_bitmap = gd.bg_inode_bitmap_lo;
         let inode_table = gd.bg_inode_table_lo;
         
-        console_println!("[o] Group descriptor loaded");
+        ok_println!(target: "ext2", "Group descriptor loaded");
         console_println!("   Block bitmap: {}", block_bitmap);
         console_println!("   Inode bitmap: {}", inode_bitmap);
         console_println!("   Inode table: {}", inode_table);
@@ -111,62 +217,26 @@ impl SuperblockManager {
     
     /// Read a block from disk
     pub fn read_block_data(&self, block_num: u64) -> FilesystemResult<Vec<u8, 4096>> {
-        let mut disk_device = virtio::VIRTIO_BLK.lock();
-        
-        if !disk_device.is_initialized() {
-            return Err(FilesystemError::DeviceError);
-        }
-        
         let sectors_per_block = self.block_size / SECTOR_SIZE;
         let start_sector = block_num * (sectors_per_block as u64);
         
         let mut block_data = Vec::new();
-        
-        for i in 0..sectors_per_block {
-            let sector = start_sector + (i as u64);
-            let mut sector_buf = [0u8; SECTOR_SIZE];
-            
-            disk_device.read_blocks(sector, &mut sector_buf)
-                .map_err(|_| FilesystemError::IoError)?;
-            
-            for byte in sector_buf.iter() {
-                block_data.push(*byte).map_err(|_| FilesystemError::FilesystemFull)?;
-            }
-        }
-        
-        drop(disk_device);
+        block_data.resize(self.block_size, 0).map_err(|_| FilesystemError::FilesystemFull)?;
+        cache::read_blocks(start_sector, &mut block_data)?;
         Ok(block_data)
     }
     
     /// Write a block to disk
     pub fn write_block_data(&self, block_num: u32, data: &[u8]) -> FilesystemResult<()> {
-        let mut disk_device = virtio::VIRTIO_BLK.lock();
-        
-        if !disk_device.is_initialized() {
-            return Err(FilesystemError::DeviceError);
-        }
-        
         let sectors_per_block = self.block_size / SECTOR_SIZE;
         let start_sector = (block_num as u64) * (sectors_per_block as u64);
         
-        for i in 0..sectors_per_block {
-            let sector = start_sector + (i as u64);
-            let sector_start = i * SECTOR_SIZE;
-            let sector_end = core::cmp::min(sector_start + SECTOR_SIZE, data.len());
-            
-            let mut sector_buf = [0u8; SECTOR_SIZE];
-            
-            if sector_end > sector_start {
-                let copy_len = sector_end - sector_start;
-                sector_buf[..copy_len].copy_from_slice(&data[sector_start..sector_end]);
-            }
-            
-            disk_device.write_blocks(sector, &sector_buf)
-                .map_err(|_| FilesystemError::IoError)?;
-        }
-        
-        drop(disk_device);
-        Ok(())
+        // The whole block in one write, zero-filled past the end of `data`
+        let mut block_buf = [0u8; 4096];
+        let block_buf = block_buf.get_mut(..self.block_size).ok_or(FilesystemError::InvalidSuperblock)?;
+        let copy_len = data.len().min(self.block_size);
+        block_buf[..copy_len].copy_from_slice(&data[..copy_len]);
+        cache::write_blocks(start_sector, block_buf)
     }
     
     /// Write superblock to disk
@@ -182,50 +252,43 @@ impl SuperblockManager {
             );
         }
         
-        let mut disk_device = virtio::VIRTIO_BLK.lock();
-        
-        if !disk_device.is_initialized() {
-            return Err(FilesystemError::DeviceError);
-        }
-        
-        let start_sector = EXT2_SUPERBLOCK_OFFSET / SECTOR_SIZE;
-        
-        // Write 2 sectors
-        for i in 0..2 {
-            let sector = (start_sector + i) as u64;
-            let sector_start = i * SECTOR_SIZE;
-            let sector_buf = &sb_buffer[sector_start..sector_start + SECTOR_SIZE];
-            
-            let mut write_buf = [0u8; SECTOR_SIZE];
-            write_buf.copy_from_slice(sector_buf);
-            
-            disk_device.write_blocks(sector, &write_buf)
-                .map_err(|_| FilesystemError::IoError)?;
-        }
+        cache::write_blocks(self.superblock_sector, &sb_buffer)?;
         
-        drop(disk_device);
         self.superblock = Some(*sb);
         Ok(())
     }
     
     /// Write group descriptor to disk
     pub fn write_group_descriptor(&mut self, gd: &Ext2GroupDesc) -> FilesystemResult<()> {
-        let gd_block = if self.block_size == 1024 { 2 } else { 1 };
-        
-        let mut gd_data = [0u8; 4096];
-        let data_len = core::cmp::min(self.block_size, 4096);
-        
-        // Copy group descriptor to buffer
-        unsafe {
-            core::ptr::copy_nonoverlapping(
-                gd as *const Ext2GroupDesc as *const u8,
-                gd_data.as_mut_ptr(),
-                core::mem::size_of::<Ext2GroupDesc>()
-            );
+        self.write_group_desc(0, gd)
+    }
+    
+    /// Block and byte offset of group `group`'s descriptor in the table that
+    /// follows the superblock
+    fn group_desc_location(&self, group: u32) -> FilesystemResult<(u32, usize)> {
+        let sb = self.superblock.as_ref().ok_or(FilesystemError::InvalidSuperblock)?;
+        let table_block = self.group_table_block.unwrap_or(sb.s_first_data_block + 1);
+        let offset = group as usize * sb.group_desc_size();
+        Ok((table_block + (offset / self.block_size) as u32, offset % self.block_size))
+    }
+    
+    /// Read the descriptor of block group `group`
+    pub fn read_group_desc(&self, group: u32) -> FilesystemResult<Ext2GroupDesc> {
+        let (block, offset) = self.group_desc_location(group)?;
+        let data = self.read_block_data(block as u64)?;
+        Ok(unsafe { core::ptr::read_unaligned(data[offset..].as_ptr() as *const Ext2GroupDesc) })
+    }
+    
+    /// Write the descriptor of block group `group`, leaving its neighbours
+    /// in the table (and any 64-bit fields past ours) untouched
+    pub fn write_group_desc(&mut self, group: u32, gd: &Ext2GroupDesc) -> FilesystemResult<()> {
+        let (block, offset) = self.group_desc_location(group)?;
+        let mut data = self.read_block_data(block as u64)?;
+        unsafe { core::ptr::write_unaligned(data[offset..].as_mut_ptr() as *mut Ext2GroupDesc, *gd) };
+        self.write_block_data(block, &data)?;
+        if group == 0 {
+            self.group_desc = Some(*gd);
         }
-        
-        self.write_block_data(gd_block, &gd_data[..data_len])?;
-        self.group_desc = Some(*gd);
         Ok(())
     }
     
@@ -263,62 +326,82 @@ impl SuperblockManager {
         Ok(())
     }
     
-    /// Allocate a new block (simplified implementation)
+    /// Allocate a free block anywhere on the filesystem
     pub fn allocate_block(&mut self) -> FilesystemResult<u32> {
-        // For now, use a simple incrementing counter starting from block 1000
-        // In a real implementation, you'd check the block bitmap
-        
-        use spin::Mutex;
-        use heapless::FnvIndexSet;
-        
-        static ALLOCATED_BLOCKS: Mutex<FnvIndexSet<u32, 1024>> = Mutex::new(FnvIndexSet::new());
-        static mut NEXT_BLOCK: u32 = 1000;
-        
-        unsafe {
-            let mut allocated = ALLOCATED_BLOCKS.lock();
-            
-            // Find the next available block
-            loop {
-                let block_num = NEXT_BLOCK;
-                NEXT_BLOCK += 1;
-                
-                // Simple validation - don't exceed reasonable limits
-                if block_num > 100000 {
-                    return Err(FilesystemError::FilesystemFull);
-                }
-                
-                // Check if this block is already allocated
-                if !allocated.contains(&block_num) {
-                    // Mark as allocated
-                    if allocated.insert(block_num).is_ok() {
-                        // console_println!("[i] Allocated block {}", block_num);
-                        return Ok(block_num);
-                    } else {
-                        console_println!("[!] Block allocation table full");
-                        return Err(FilesystemError::FilesystemFull);
-                    }
-                }
-                
-                // This block is already allocated, try the next one
-                //console_println!("[i] Block {} already allocated, trying next", block_num);
+        self.allocate_block_near(0)
+    }
+    
+    /// Allocate a free block, preferring `goal` and the blocks after it so
+    /// that a growing file stays contiguous
+    pub fn allocate_block_near(&mut self, goal: u32) -> FilesystemResult<u32> {
+        let sb = *self.superblock.as_ref().ok_or(FilesystemError::InvalidSuperblock)?;
+        let first_data_block = sb.s_first_data_block;
+        let blocks_count = sb.s_blocks_count_lo;
+        let blocks_per_group = sb.s_blocks_per_group;
+        let groups = sb.group_count();
+        
+        let goal = goal.clamp(first_data_block, blocks_count - 1);
+        let goal_group = (goal - first_data_block) / blocks_per_group;
+        
+        for i in 0..groups {
+            let group = (goal_group + i) % groups;
+            let mut gd = self.read_group_desc(group)?;
+            let free = gd.bg_free_blocks_count_lo;
+            let flags = gd.bg_flags;
+            // An uninitialized group has no bitmap on disk to mark
+            if free == 0 || flags & EXT4_BG_BLOCK_UNINIT != 0 {
+                continue;
             }
+            
+            let group_start = first_data_block + group * blocks_per_group;
+            let group_blocks = core::cmp::min(blocks_per_group, blocks_count - group_start);
+            let start = if group == goal_group { goal - group_start } else { 0 };
+            
+            let bitmap_block = gd.bg_block_bitmap_lo;
+            let mut bitmap = self.read_block_data(bitmap_block as u64)?;
+            let is_free = |bit: u32| bitmap[(bit / 8) as usize] & (1 << (bit % 8)) == 0;
+            let found = (start..group_blocks).chain(0..start).find(|&bit| is_free(bit));
+            let Some(bit) = found else {
+                warn_println!(target: "ext2", "Block group {} claims {} free blocks but its bitmap is full", group, free);
+                continue;
+            };
+            
+            bitmap[(bit / 8) as usize] |= 1 << (bit % 8);
+            self.write_block_data(bitmap_block, &bitmap)?;
+            gd.bg_free_blocks_count_lo = free - 1;
+            self.write_group_desc(group, &gd)?;
+            self.update_free_blocks(-1)?;
+            return Ok(group_start + bit);
         }
+        
+        Err(FilesystemError::FilesystemFull)
     }
     
-    /// Free a block (simplified implementation)
+    /// Return a block to its group's free pool
     pub fn free_block(&mut self, block_num: u32) -> FilesystemResult<()> {
-        use spin::Mutex;
-        use heapless::FnvIndexSet;
+        let sb = *self.superblock.as_ref().ok_or(FilesystemError::InvalidSuperblock)?;
+        let first_data_block = sb.s_first_data_block;
+        let blocks_count = sb.s_blocks_count_lo;
+        if block_num < first_data_block || block_num >= blocks_count {
+            return Err(FilesystemError::CorruptedFilesystem);
+        }
         
-        static ALLOCATED_BLOCKS: Mutex<FnvIndexSet<u32, 1024>> = Mutex::new(FnvIndexSet::new());
+        let group = (block_num - first_data_block) / sb.s_blocks_per_group;
+        let bit = (block_num - first_data_block) % sb.s_blocks_per_group;
+        let mut gd = self.read_group_desc(group)?;
+        let bitmap_block = gd.bg_block_bitmap_lo;
+        let mut bitmap = self.read_block_data(bitmap_block as u64)?;
         
-        let allocated = ALLOCATED_BLOCKS.lock();
-        // if allocated.remove(&block_num) {
-        //     console_println!("[i] Freed block {}", block_num);
-        // } else {
-        //     console_println!("[!] Attempted to free unallocated block {}", block_num);
-        // }
-        Ok(())
+        let mask = 1 << (bit % 8);
+        if bitmap[(bit / 8) as usize] & mask == 0 {
+            warn_println!(target: "ext2", "Freeing block {} which is not in use", block_num);
+            return Ok(());
+        }
+        bitmap[(bit / 8) as usize] &= !mask;
+        self.write_block_data(bitmap_block, &bitmap)?;
+        gd.bg_free_blocks_count_lo += 1;
+        self.write_group_desc(group, &gd)?;
+        self.update_free_blocks(1)
     }
     
     /// Sync superblock to disk
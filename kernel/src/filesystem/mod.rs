@@ -3,19 +3,35 @@
 
 pub mod ext2;
 pub mod traits;
+pub mod cache;
+pub mod automount;
+pub mod probe;
+pub mod devfs;
+pub mod procfs;
+pub mod append;
+pub mod wipe;
+pub mod configstore;
+pub mod transaction;
+pub mod path;
 
 use spin::Mutex;
-use elinos_common::console_println;
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println, debug_println};
 use heapless::Vec;
+use elinos_common::blockcache::SECTOR_SIZE;
 
 pub use traits::{FileSystem, FileEntry, FilesystemError, FilesystemResult};
+pub use traits::{DirEntry, DirListing, FILE_ENTRY_CACHE, DIR_ENTRY_CACHE};
+pub use traits::FileStat;
 use ext2::Ext2FileSystem;
+use crate::virtio::Volume;
 
 /// Filesystem type detection
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FilesystemType {
     Unknown,
     Ext2,
+    /// Recognized by `probe`, but not mountable
+    Fat,
 }
 
 impl core::fmt::Display for FilesystemType {
@@ -23,6 +39,7 @@ impl core::fmt::Display for FilesystemType {
         match self {
             FilesystemType::Unknown => write!(f, "Unknown"),
             FilesystemType::Ext2 => write!(f, "ext2"),
+            FilesystemType::Fat => write!(f, "vfat"),
         }
     }
 }
@@ -33,10 +50,43 @@ pub enum Filesystem {
     None,
 }
 
+/// Most filesystems that can be mounted below the root at once
+pub const MAX_MOUNTS: usize = 4;
+
+/// How a filesystem below the root is mounted
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MountOptions {
+    /// Refuse changes
+    pub read_only: bool,
+    /// Read the superblock from a backup copy, the primary being damaged
+    pub backup_superblock: bool,
+}
+
+/// A filesystem mounted on a directory of the root filesystem
+pub struct MountPoint {
+    /// Absolute path of the directory, without a trailing slash
+    pub path: heapless::String<64>,
+    /// Disk or partition the filesystem is on
+    pub volume: Volume,
+    pub read_only: bool,
+    fs: Ext2FileSystem,
+}
+
+impl MountPoint {
+    /// Volume label of the mounted filesystem, if it has one
+    pub fn label(&self) -> Option<heapless::String<16>> {
+        self.fs.volume_label()
+    }
+}
+
 /// Main filesystem manager
 pub struct UnifiedFileSystem {
     filesystem: Filesystem,
     fs_type: FilesystemType,
+    /// Disk or partition the filesystem is mounted from
+    volume: Option<Volume>,
+    /// Filesystems mounted below the root
+    mounts: Vec<MountPoint, MAX_MOUNTS>,
 }
 
 impl UnifiedFileSystem {
@@ -44,32 +94,85 @@ impl UnifiedFileSystem {
         UnifiedFileSystem {
             filesystem: Filesystem::None,
             fs_type: FilesystemType::Unknown,
+            volume: None,
+            mounts: Vec::new(),
         }
     }
     
-    /// Initialize filesystem with automatic type detection
+    /// Mount the boot filesystem: the volume named by `root=` on the kernel
+    /// command line (`blkN[pM]`, `LABEL=...` or `UUID=...`), otherwise the
+    /// first disk or partition with a filesystem
     pub fn init(&mut self) -> FilesystemResult<()> {
-        console_println!("[i] Starting unified filesystem initialization...");
-        
+        info_println!(target: "fs", "Starting unified filesystem initialization...");
+
+        let cmdline = crate::boot_cmdline();
+        if let Some(root) = cmdline.split_whitespace().find_map(|arg| arg.strip_prefix("root=")) {
+            match probe::find_volume(root) {
+                Some(volume) => return self.mount_volume(volume, false),
+                None => warn_println!(target: "fs", "No volume matches root={}, searching all disks", root),
+            }
+        }
+
+        let mut result = Err(FilesystemError::DeviceError);
+        for index in 0..crate::virtio::MAX_BLOCK_DEVICES {
+            if !crate::virtio::VIRTIO_BLK_DEVICES[index].lock().is_initialized() {
+                continue;
+            }
+            for volume in crate::virtio::block::partition::volumes(index) {
+                result = self.mount_volume(volume, false);
+                if result.is_ok() {
+                    return result;
+                }
+            }
+        }
+        result
+    }
+
+    /// Mount the filesystem on `volume` in place of the current one,
+    /// optionally from a backup superblock
+    pub fn mount_volume(&mut self, volume: Volume, backup_superblock: bool) -> FilesystemResult<()> {
+        if self.mounts.iter().any(|mount| mount.volume.overlaps(&volume)) {
+            err_println!(target: "fs", "{} is already mounted", volume);
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        if !self.mounts.is_empty() {
+            err_println!(target: "fs", "Unmount the filesystems mounted below / first");
+            return Err(FilesystemError::DirectoryNotEmpty);
+        }
+        cache::select_volume(volume)?;
+        self.filesystem = Filesystem::None;
+        self.fs_type = FilesystemType::Unknown;
+        self.volume = None;
+
         // Detect filesystem type
         self.fs_type = detect_filesystem_type()?;
         
         match self.fs_type {
             FilesystemType::Ext2 => {
-                // console_println!("[i] Mounting ext2 filesystem...");
+                // info_println!("Mounting ext2 filesystem...");
                 let mut ext2_fs = Ext2FileSystem::new();
-                ext2_fs.init()?;
+                if backup_superblock {
+                    ext2_fs.init_from_backup()?;
+                } else {
+                    ext2_fs.init()?;
+                }
                 self.filesystem = Filesystem::Ext2(ext2_fs);
-                console_println!("[o] ext2 filesystem mounted successfully");
+                ok_println!(target: "fs", "ext2 filesystem mounted successfully from {}", volume);
             }
-            FilesystemType::Unknown => {
-                console_println!("[x] No supported filesystem detected");
+            FilesystemType::Fat | FilesystemType::Unknown => {
+                err_println!(target: "fs", "No supported filesystem detected on {}", volume);
                 return Err(FilesystemError::UnsupportedFilesystem);
             }
         }
         
+        self.volume = Some(volume);
         Ok(())
     }
+
+    /// Disk or partition the filesystem is mounted from
+    pub fn volume(&self) -> Option<Volume> {
+        self.volume
+    }
     
     /// Get filesystem type
     pub fn get_filesystem_type(&self) -> FilesystemType {
@@ -91,36 +194,286 @@ impl UnifiedFileSystem {
             Filesystem::None => false,
         }
     }
+
+    /// Mount the filesystem on `volume` at `path`, a directory of the root
+    /// filesystem
+    pub fn mount_at(&mut self, volume: Volume, path: &str, options: MountOptions) -> FilesystemResult<()> {
+        let path = path.trim_end_matches('/');
+        if path.is_empty() || !path.starts_with('/') {
+            return Err(FilesystemError::InvalidPath);
+        }
+        if self.volume_in_use(volume) || crate::memory::swap::uses_volume(volume) {
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        if self.mounts.iter().any(|mount| mount.path == path) {
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        if self.mounts.is_full() {
+            return Err(FilesystemError::FilesystemFull);
+        }
+        // The directory must exist on whatever filesystem holds it now
+        match self.list_directory(path) {
+            Ok(_) => {}
+            Err(FilesystemError::NotMounted) => return Err(FilesystemError::NotMounted),
+            Err(_) => return Err(FilesystemError::FileNotFound),
+        }
+        let fs = cache::with_volume(volume, || {
+            if detect_filesystem_type()? != FilesystemType::Ext2 {
+                return Err(FilesystemError::UnsupportedFilesystem);
+            }
+            let mut fs = Ext2FileSystem::new();
+            if options.backup_superblock {
+                fs.init_from_backup()?;
+            } else {
+                fs.init()?;
+            }
+            Ok(fs)
+        })?;
+
+        let path = heapless::String::try_from(path).map_err(|_| FilesystemError::FilenameTooLong)?;
+        let mount = MountPoint { path, volume, read_only: options.read_only, fs };
+        self.mounts.push(mount).map_err(|_| FilesystemError::FilesystemFull)?;
+        Ok(())
+    }
+
+    /// Whether the root filesystem or a mount is on `volume`, or on a
+    /// disk or partition overlapping it
+    pub fn volume_in_use(&self, volume: Volume) -> bool {
+        self.volume.is_some_and(|root| root.overlaps(&volume))
+            || self.mounts.iter().any(|mount| mount.volume.overlaps(&volume))
+    }
+
+    /// Unmount the filesystem mounted at `path`, writing out its changes
+    pub fn unmount(&mut self, path: &str) -> FilesystemResult<()> {
+        let path = path.trim_end_matches('/');
+        let slot = self.mounts.iter().position(|mount| mount.path == path).ok_or(FilesystemError::NotMounted)?;
+        // Nothing may stay mounted inside it
+        let nested = self.mounts.iter().any(|mount| {
+            mount.path.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'))
+        });
+        if nested {
+            return Err(FilesystemError::DirectoryNotEmpty);
+        }
+
+        let mount = &mut self.mounts[slot];
+        let volume = mount.volume;
+        if crate::memory::swap::uses_volume(volume) {
+            return Err(FilesystemError::Busy);
+        }
+        if !mount.read_only {
+            cache::with_volume(volume, || mount.fs.sync())?;
+        }
+        cache::flush()?;
+        cache::forget_device(volume.device);
+        self.mounts.remove(slot);
+        Ok(())
+    }
+
+    /// Drop mounts whose disk or partition has gone away. Unwritten
+    /// changes on them are lost. Returns the paths that were mounted.
+    pub fn drop_unavailable_mounts(&mut self) -> Vec<heapless::String<64>, MAX_MOUNTS> {
+        let mut dropped = Vec::new();
+        self.mounts.retain(|mount| {
+            let present = mount.volume.extent().is_some();
+            if !present {
+                cache::forget_device(mount.volume.device);
+                let _ = dropped.push(mount.path.clone());
+            }
+            present
+        });
+        dropped
+    }
+
+    /// Filesystems mounted below the root
+    pub fn mounts(&self) -> &[MountPoint] {
+        &self.mounts
+    }
+
+    /// Slot of the mount holding `path` and the path within it; `None` for
+    /// the root filesystem
+    fn find_mount<'p>(&self, path: &'p str) -> Option<(usize, &'p str)> {
+        self.mounts.iter().enumerate()
+            .filter_map(|(slot, mount)| {
+                let rest = path.strip_prefix(mount.path.as_str())?;
+                match rest {
+                    "" => Some((slot, "/")),
+                    _ if rest.starts_with('/') => Some((slot, rest)),
+                    _ => None,
+                }
+            })
+            .max_by_key(|&(slot, _)| self.mounts[slot].path.len())
+    }
+
+    /// `FileEntry::mount` value for entries under `path`
+    fn mount_id(&self, path: &str) -> usize {
+        self.find_mount(path).map_or(0, |(slot, _)| slot + 1)
+    }
+
+    /// Run `f` on the filesystem holding `path`, with the path within it
+    fn on_path<T>(&self, path: &str, f: impl FnOnce(&Ext2FileSystem, &str) -> FilesystemResult<T>) -> FilesystemResult<T> {
+        match self.find_mount(path) {
+            Some((slot, rest)) => {
+                let mount = &self.mounts[slot];
+                cache::with_volume(mount.volume, || f(&mount.fs, rest))
+            }
+            None => match &self.filesystem {
+                Filesystem::Ext2(fs) => f(fs, path),
+                Filesystem::None => Err(FilesystemError::NotMounted),
+            },
+        }
+    }
+
+    /// Like `on_path`, for changes; read-only mounts refuse them
+    fn on_path_mut<T>(&mut self, path: &str, f: impl FnOnce(&mut Ext2FileSystem, &str) -> FilesystemResult<T>) -> FilesystemResult<T> {
+        match self.find_mount(path) {
+            Some((slot, rest)) => {
+                let mount = &mut self.mounts[slot];
+                if mount.read_only {
+                    return Err(FilesystemError::ReadOnly);
+                }
+                cache::with_volume(mount.volume, || f(&mut mount.fs, rest))
+            }
+            None => match &mut self.filesystem {
+                Filesystem::Ext2(fs) => f(fs, path),
+                Filesystem::None => Err(FilesystemError::NotMounted),
+            },
+        }
+    }
+
+    /// Run `f` on the filesystem `file` belongs to
+    fn on_entry_mut<T>(&mut self, file: &FileEntry, f: impl FnOnce(&mut Ext2FileSystem) -> FilesystemResult<T>) -> FilesystemResult<T> {
+        if file.mount == 0 {
+            return match &mut self.filesystem {
+                Filesystem::Ext2(fs) => f(fs),
+                Filesystem::None => Err(FilesystemError::NotMounted),
+            };
+        }
+        let mount = self.mounts.get_mut(file.mount - 1).ok_or(FilesystemError::NotMounted)?;
+        if mount.read_only {
+            return Err(FilesystemError::ReadOnly);
+        }
+        cache::with_volume(mount.volume, || f(&mut mount.fs))
+    }
+
+    /// Overwrite the data of the file at `path` with `passes` patterns,
+    /// then delete it
+    pub fn shred_file(&mut self, path: &str, passes: usize) -> FilesystemResult<wipe::WipeReport> {
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        if crate::memory::swap::is_swap_file(path) {
+            return Err(FilesystemError::Busy);
+        }
+        self.on_path_mut(path, |fs, path| {
+            let report = wipe::overwrite(passes, |f| fs.for_each_file_sector_run(path, f))?;
+            fs.delete_file(path)?;
+            Ok(report)
+        })
+    }
+
+    /// Overwrite the free blocks of the filesystem holding `path` with
+    /// `passes` patterns
+    pub fn wipe_free_space(&mut self, path: &str, passes: usize) -> FilesystemResult<wipe::WipeReport> {
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        self.on_path_mut(path, |fs, _| wipe::overwrite(passes, |f| fs.for_each_free_sector_run(f)))
+    }
+
+    /// Write `data`, whole sectors, over the existing file `path` from its
+    /// sector `first` on, straight to the blocks holding them: nothing else
+    /// on the disk changes, not even the inode. The file must have no holes.
+    pub fn write_file_sectors(&mut self, path: &str, first: usize, data: &[u8]) -> FilesystemResult<()> {
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        if data.len() % SECTOR_SIZE != 0 {
+            return Err(FilesystemError::IoError);
+        }
+        let first = first as u64;
+        let end = first + (data.len() / SECTOR_SIZE) as u64;
+        self.on_path_mut(path, |fs, path| {
+            // File sector at the start of the current run
+            let mut index = 0;
+            fs.for_each_file_sector_run(path, &mut |start, count| {
+                let from = index.max(first);
+                let to = (index + count).min(end);
+                if from < to {
+                    let bytes = &data[(from - first) as usize * SECTOR_SIZE..(to - first) as usize * SECTOR_SIZE];
+                    cache::write_blocks(start + (from - index), bytes)?;
+                }
+                index += count;
+                Ok(())
+            })?;
+            if index < end {
+                // Past the end of the file
+                return Err(FilesystemError::IoError);
+            }
+            Ok(())
+        })
+    }
+
+    /// Entry for an existing file or directory at `path`
+    pub fn get_file_entry(&self, path: &str) -> FilesystemResult<FileEntry> {
+        if devfs::node_name(path).is_some() {
+            return devfs::file_entry(path);
+        }
+        if procfs::is_proc_path(path) {
+            return procfs::file_entry(path);
+        }
+        let mut entry = self.on_path(path, |fs, path| fs.get_file_entry(path))?;
+        entry.mount = self.mount_id(path);
+        Ok(entry)
+    }
 }
 
 // Implement the FileSystem trait for UnifiedFileSystem
 impl FileSystem for UnifiedFileSystem {
-    fn list_files(&self) -> FilesystemResult<Vec<(heapless::String<64>, usize), 32>> {
+    fn list_files(&self) -> FilesystemResult<DirListing> {
         match &self.filesystem {
             Filesystem::Ext2(fs) => fs.list_files(),
             Filesystem::None => Err(FilesystemError::NotMounted),
         }
     }
     
-    fn list_directory(&self, path: &str) -> FilesystemResult<Vec<(heapless::String<64>, usize, bool), 32>> {
-        match &self.filesystem {
-            Filesystem::Ext2(fs) => fs.list_directory(path),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+    fn list_directory(&self, path: &str) -> FilesystemResult<DirListing> {
+        match devfs::node_name(path) {
+            Some("") => return Ok(devfs::list()),
+            Some(_) => return Err(FilesystemError::NotADirectory),
+            None => {}
         }
+        if procfs::is_proc_path(path) {
+            return procfs::list(path);
+        }
+        self.on_path(path, |fs, path| fs.list_directory(path))
     }
     
     fn read_file(&self, filename: &str) -> FilesystemResult<heapless::Vec<u8, 32768>> {
-        match &self.filesystem {
-            Filesystem::Ext2(fs) => fs.read_file(filename),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if devfs::node_name(filename).is_some() {
+            let mut content = heapless::Vec::new();
+            let _ = content.resize_default(content.capacity());
+            let count = devfs::read(devfs::lookup(filename)?, 0, &mut content)?;
+            content.truncate(count);
+            return Ok(content);
+        }
+        if procfs::is_proc_path(filename) {
+            let mut content = heapless::Vec::new();
+            let _ = content.resize_default(content.capacity());
+            let count = procfs::read(self, filename, 0, &mut content)?;
+            content.truncate(count);
+            return Ok(content);
         }
+        self.on_path(filename, |fs, path| fs.read_file(path))
     }
     
     fn file_exists(&self, filename: &str) -> bool {
-        match &self.filesystem {
-            Filesystem::Ext2(fs) => fs.file_exists(filename),
-            Filesystem::None => false,
+        if devfs::node_name(filename).is_some() {
+            return devfs::file_entry(filename).is_ok();
+        }
+        if procfs::is_proc_path(filename) {
+            return procfs::lookup(filename).is_ok();
         }
+        self.on_path(filename, |fs, path| Ok(fs.file_exists(path))).unwrap_or(false)
     }
     
     fn get_filesystem_info(&self) -> Option<(u16, u32, u16)> {
@@ -144,50 +497,160 @@ impl FileSystem for UnifiedFileSystem {
         }
     }
 
-    // TODO: Implement these methods for UnifiedFileSystem by dispatching to the active FS
     fn create_file(&mut self, path: &str) -> FilesystemResult<FileEntry> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.create_file(path),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if devfs::node_name(path).is_some() || procfs::is_proc_path(path) {
+            return Err(FilesystemError::ReadOnly);
         }
+        let mount = self.mount_id(path);
+        let mut entry = self.on_path_mut(path, |fs, path| fs.create_file(path))?;
+        entry.mount = mount;
+        Ok(entry)
     }
 
     fn create_directory(&mut self, path: &str) -> FilesystemResult<FileEntry> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.create_directory(path),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if devfs::node_name(path).is_some() || procfs::is_proc_path(path) {
+            return Err(FilesystemError::ReadOnly);
         }
+        let mount = self.mount_id(path);
+        let mut entry = self.on_path_mut(path, |fs, path| fs.create_directory(path))?;
+        entry.mount = mount;
+        Ok(entry)
     }
 
     fn write_file(&mut self, file: &FileEntry, offset: u64, data: &[u8]) -> FilesystemResult<usize> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.write_file(file, offset, data),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if file.mount == devfs::DEVFS_MOUNT {
+            return devfs::write(file, offset as usize, data);
         }
+        if file.mount == procfs::PROCFS_MOUNT {
+            return procfs::write(file, offset as usize, data);
+        }
+        self.on_entry_mut(file, |fs| fs.write_file(file, offset, data))
     }
 
     fn delete_file(&mut self, path: &str) -> FilesystemResult<()> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.delete_file(path),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if devfs::node_name(path).is_some() || procfs::is_proc_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        if crate::memory::swap::is_swap_file(path) {
+            return Err(FilesystemError::Busy);
         }
+        self.on_path_mut(path, |fs, path| fs.delete_file(path))
     }
 
     fn delete_directory(&mut self, path: &str) -> FilesystemResult<()> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.delete_directory(path),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        if devfs::node_name(path).is_some() || procfs::is_proc_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        if self.mounts.iter().any(|mount| mount.path == path.trim_end_matches('/')) {
+            return Err(FilesystemError::DirectoryNotEmpty);
+        }
+        self.on_path_mut(path, |fs, path| fs.delete_directory(path))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> FilesystemResult<()> {
+        if is_pseudo_path(from) || is_pseudo_path(to) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        if crate::memory::swap::is_swap_file(from) || crate::memory::swap::is_swap_file(to) {
+            return Err(FilesystemError::Busy);
+        }
+        // A mount point, or a directory holding one, stays where it is
+        let from_dir = from.trim_end_matches('/');
+        if self.mounts.iter().any(|mount| mount.path == from_dir
+            || mount.path.strip_prefix(from_dir).is_some_and(|rest| rest.starts_with('/'))) {
+            return Err(FilesystemError::Busy);
+        }
+        // Only within one filesystem
+        if self.mount_id(from) != self.mount_id(to) {
+            return Err(FilesystemError::InvalidPath);
+        }
+        let to_rest = self.find_mount(to).map_or(to, |(_, rest)| rest);
+        self.on_path_mut(from, |fs, from| fs.rename(from, to_rest))
+    }
+
+    fn link(&mut self, existing: &str, new: &str) -> FilesystemResult<()> {
+        if is_pseudo_path(existing) || is_pseudo_path(new) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        // Only within one filesystem
+        if self.mount_id(existing) != self.mount_id(new) {
+            return Err(FilesystemError::InvalidPath);
         }
+        let new_rest = self.find_mount(new).map_or(new, |(_, rest)| rest);
+        self.on_path_mut(existing, |fs, existing| fs.link(existing, new_rest))
+    }
+
+    fn create_symlink(&mut self, path: &str, target: &str) -> FilesystemResult<FileEntry> {
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        let mount = self.mount_id(path);
+        let mut entry = self.on_path_mut(path, |fs, path| fs.create_symlink(path, target))?;
+        entry.mount = mount;
+        Ok(entry)
+    }
+
+    fn read_link(&self, path: &str) -> FilesystemResult<path::PathBuf> {
+        // /dev and /proc hold no links
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::InvalidPath);
+        }
+        self.on_path(path, |fs, path| fs.read_link(path))
+    }
+
+    fn stat(&self, path: &str, follow: bool) -> FilesystemResult<FileStat> {
+        use traits::{S_IFCHR, S_IFDIR, S_IFREG};
+
+        if devfs::node_name(path).is_some() {
+            let entry = devfs::file_entry(path)?;
+            let mode = if entry.is_directory { S_IFDIR | 0o755 } else { S_IFCHR | 0o666 };
+            return Ok(FileStat::synthetic(&entry, mode));
+        }
+        if procfs::is_proc_path(path) {
+            let entry = procfs::file_entry(path)?;
+            let mode = match entry.is_directory {
+                true => S_IFDIR | 0o555,
+                false if procfs::is_writable(&entry) => S_IFREG | 0o644,
+                false => S_IFREG | 0o444,
+            };
+            return Ok(FileStat::synthetic(&entry, mode));
+        }
+        let mut stat = self.on_path(path, |fs, path| fs.stat(path, follow))?;
+        stat.device = self.mount_id(path) as u64;
+        Ok(stat)
+    }
+
+    fn set_times(&mut self, path: &str, atime: Option<u64>, mtime: Option<u64>) -> FilesystemResult<()> {
+        if is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        self.on_path_mut(path, |fs, path| fs.set_times(path, atime, mtime))
+    }
+
+    fn mark_accessed(&mut self, path: &str) -> FilesystemResult<()> {
+        // Nothing to record for /dev and /proc, or on a read-only mount
+        if is_pseudo_path(path) || self.find_mount(path).is_some_and(|(slot, _)| self.mounts[slot].read_only) {
+            return Ok(());
+        }
+        self.on_path_mut(path, |fs, path| fs.mark_accessed(path))
     }
 
     fn truncate_file(&mut self, file: &FileEntry, new_size: u64) -> FilesystemResult<()> {
-        match &mut self.filesystem {
-            Filesystem::Ext2(fs) => fs.truncate_file(file, new_size),
-            Filesystem::None => Err(FilesystemError::NotMounted),
+        // Devices have no length to cut
+        if file.mount == devfs::DEVFS_MOUNT {
+            return Ok(());
         }
+        // Writes to /proc/sys replace the whole value anyway
+        if file.mount == procfs::PROCFS_MOUNT {
+            return if procfs::is_writable(file) { Ok(()) } else { Err(FilesystemError::ReadOnly) };
+        }
+        self.on_entry_mut(file, |fs| fs.truncate_file(file, new_size))
     }
 
     fn sync(&mut self) -> FilesystemResult<()> {
+        for mount in self.mounts.iter_mut().filter(|mount| !mount.read_only) {
+            cache::with_volume(mount.volume, || mount.fs.sync())?;
+        }
         match &mut self.filesystem {
             Filesystem::Ext2(fs) => fs.sync(),
             Filesystem::None => Err(FilesystemError::NotMounted),
@@ -195,39 +658,63 @@ impl FileSystem for UnifiedFileSystem {
     }
 
     fn read_file_to_buffer(&self, filename: &str, buffer: &mut [u8]) -> FilesystemResult<usize> {
-        match &self.filesystem {
-            Filesystem::Ext2(fs) => fs.read_file_to_buffer(filename, buffer),
-            Filesystem::None => Err(FilesystemError::NotInitialized),
+        if devfs::node_name(filename).is_some() {
+            return devfs::read(devfs::lookup(filename)?, 0, buffer);
         }
+        if procfs::is_proc_path(filename) {
+            return procfs::read(self, filename, 0, buffer);
+        }
+        self.on_path(filename, |fs, path| fs.read_file_to_buffer(path, buffer))
+    }
+
+    fn read_at(&self, filename: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+        if devfs::node_name(filename).is_some() {
+            return devfs::read(devfs::lookup(filename)?, offset, buffer);
+        }
+        if procfs::is_proc_path(filename) {
+            return procfs::read(self, filename, offset, buffer);
+        }
+        self.on_path(filename, |fs, path| fs.read_at(path, offset, buffer))
     }
 
     fn get_file_size(&self, filename: &str) -> FilesystemResult<usize> {
-        match &self.filesystem {
-            Filesystem::Ext2(fs) => fs.get_file_size(filename),
-            Filesystem::None => Err(FilesystemError::NotInitialized),
+        if devfs::node_name(filename).is_some() {
+            return Ok(devfs::lookup(filename)?.size());
         }
+        if procfs::is_proc_path(filename) {
+            return procfs::size(self, filename);
+        }
+        self.on_path(filename, |fs, path| fs.get_file_size(path))
     }
 }
 
 /// Detect filesystem type by reading specific disk locations
 pub fn detect_filesystem_type() -> FilesystemResult<FilesystemType> {
     // console_println!("filesystem::detect_filesystem_type: Starting detection...");
-            let mut disk_device = crate::virtio::VIRTIO_BLK.lock();
-
-    if !disk_device.is_initialized() {
-        // console_println!("filesystem::detect_filesystem_type: VirtIO disk not initialized.");
+    let index = cache::selected_volume().device;
+    if crate::virtio::block::partition::capacity(index).is_none() {
         return Err(FilesystemError::DeviceError);
     }
+    // Only VirtIO disks need the warmup
+    if let Some(device) = crate::virtio::block_device(index) {
+        let mut disk_device = device.lock();
 
-    // IMPORTANT: Warm up VirtIO driver with a simple read to ensure clean buffer state
-    // This prevents VirtIO buffer corruption issues that occur when ext2 detection
-    let mut warmup_buf = [0u8; 512];
-    match disk_device.read_blocks(0, &mut warmup_buf) {
-        Ok(_) => {
+        if !disk_device.is_initialized() {
+            // console_println!("filesystem::detect_filesystem_type: VirtIO disk not initialized.");
+            return Err(FilesystemError::DeviceError);
         }
-        Err(e) => {
-            console_println!("[!] VirtIO warmup failed: {:?}, continuing anyway", e);
-            // Continue anyway - the warmup attempt may have still helped
+
+        // IMPORTANT: Warm up VirtIO driver with a simple read to ensure clean buffer state
+        // This prevents VirtIO buffer corruption issues that occur when ext2 detection
+        // (deliberately uncached, so it really reaches the device)
+        let mut warmup_buf = [0u8; 512];
+        match disk_device.read_blocks(0, &mut warmup_buf) {
+            Ok(_) => {
+            }
+            Err(e) => {
+                warn_println!(target: "fs", "VirtIO warmup failed: {:?}, continuing anyway", e);
+                // Continue anyway - the warmup attempt may have still helped
+            }
         }
     }
 
@@ -242,13 +729,13 @@ pub fn detect_filesystem_type() -> FilesystemResult<FilesystemType> {
         let current_sector_to_read = (start_sector + i) as u64;
         // console_println!("filesystem::detect_filesystem_type: Reading ext2 SB sector {}", current_sector_to_read);
         let mut sector_buf = [0u8; SECTOR_SIZE];
-        match disk_device.read_blocks(current_sector_to_read, &mut sector_buf) {
+        match cache::read_blocks(current_sector_to_read, &mut sector_buf) {
             Ok(_) => {
                 // console_println!("filesystem::detect_filesystem_type: Successfully read ext2 SB sector {}", current_sector_to_read);
                 sb_buffer[i * SECTOR_SIZE..(i + 1) * SECTOR_SIZE].copy_from_slice(&sector_buf);
             }
             Err(e) => {
-                // console_println!("filesystem::detect_filesystem_type: Failed to read ext2 SB sector {}: {:?}", current_sector_to_read, e);
+                debug_println!(target: "fs", "Failed to read ext2 superblock sector {}: {:?}", current_sector_to_read, e);
                 // If we can't read these, it's unlikely ext2, or there's a general disk issue.
                 return Ok(FilesystemType::Unknown); // Return Unknown, don't mask with IoError yet
             }
@@ -260,12 +747,12 @@ pub fn detect_filesystem_type() -> FilesystemResult<FilesystemType> {
     if sb_buffer.len() >= 56 + 2 {
         let ext2_magic = u16::from_le_bytes([sb_buffer[56], sb_buffer[57]]);
         if ext2_magic == 0xEF53 {
-            console_println!("[o] ext2 magic 0xEF53 found at offset 56");
+            debug_println!(target: "fs", "ext2 magic 0xEF53 found at offset 56");
             return Ok(FilesystemType::Ext2);
         }
-        console_println!("[!] ext2 magic not found, read 0x{:04X} at offset 56", ext2_magic);
+        warn_println!(target: "fs", "ext2 magic not found, read 0x{:04X} at offset 56", ext2_magic);
     } else {
-        console_println!("[!] Superblock buffer too short for ext2 magic check");
+        warn_println!(target: "fs", "Superblock buffer too short for ext2 magic check");
     }
 
     // console_println!("filesystem::detect_filesystem_type: No known filesystem type identified.");
@@ -280,61 +767,198 @@ pub static FILESYSTEM: Mutex<UnifiedFileSystem> = Mutex::new(UnifiedFileSystem::
 
 /// Initialize the filesystem with automatic detection
 pub fn init_filesystem() -> FilesystemResult<()> {
+    cache::init();
     let mut fs = FILESYSTEM.lock();
     fs.init()
 }
 
+/// Mount the filesystem on `volume` in place of the current one
+pub fn mount_volume(volume: Volume, backup_superblock: bool) -> FilesystemResult<()> {
+    FILESYSTEM.lock().mount_volume(volume, backup_superblock)
+}
+
+/// Mount the filesystem on `volume` at directory `path`
+pub fn mount_at(volume: Volume, path: &str, options: MountOptions) -> FilesystemResult<()> {
+    FILESYSTEM.lock().mount_at(volume, path, options)
+}
+
+/// Unmount the filesystem mounted at `path`
+pub fn unmount(path: &str) -> FilesystemResult<()> {
+    // Pending appends may be for files on it
+    append::flush_all()?;
+    FILESYSTEM.lock().unmount(path)
+}
+
+/// The volume named by `spec`: `blkN[pM]`, `usbN[pM]`, `LABEL=...` or `UUID=...`
+pub fn find_volume(spec: &str) -> Option<Volume> {
+    let _fs = FILESYSTEM.lock();
+    probe::find_volume(spec)
+}
+
+/// Identify the filesystem on `volume` without mounting it
+pub fn probe_volume(volume: Volume) -> Option<probe::VolumeId> {
+    let _fs = FILESYSTEM.lock();
+    probe::probe(volume)
+}
+
+/// Disk or partition the filesystem is mounted from
+pub fn mounted_volume() -> Option<Volume> {
+    FILESYSTEM.lock().volume()
+}
+
+/// Whether a filesystem is mounted from `volume` or a volume overlapping it
+pub fn volume_in_use(volume: Volume) -> bool {
+    FILESYSTEM.lock().volume_in_use(volume)
+}
+
+/// Call `f` with each run of sectors holding the file `path`, as (first
+/// sector, sectors) counted from the start of its disk, after writing out
+/// what the cache holds. Holes have no sectors and are left out. Returns
+/// the volume the file is on.
+pub fn file_disk_runs(path: &str, f: &mut dyn FnMut(u64, u64) -> FilesystemResult<()>) -> FilesystemResult<Volume> {
+    append::flush_file(path)?;
+    if is_pseudo_path(path) {
+        return Err(FilesystemError::NotImplemented);
+    }
+    let fs = FILESYSTEM.lock();
+    let volume = match fs.find_mount(path) {
+        Some((slot, _)) => fs.mounts[slot].volume,
+        None => fs.volume.ok_or(FilesystemError::NotMounted)?,
+    };
+    let (start, _) = volume.extent().ok_or(FilesystemError::DeviceError)?;
+    fs.on_path(path, |fs, path| fs.for_each_file_sector_run(path, &mut |first, count| f(start + first, count)))?;
+    cache::flush()?;
+    Ok(volume)
+}
+
 /// List files in the filesystem
-pub fn list_files() -> FilesystemResult<Vec<(heapless::String<64>, usize), 32>> {
+pub fn list_files() -> FilesystemResult<DirListing> {
     let fs = FILESYSTEM.lock();
     fs.list_files()
 }
 
 /// List files in a specific directory path
-pub fn list_directory(path: &str) -> FilesystemResult<Vec<(heapless::String<64>, usize, bool), 32>> {
+pub fn list_directory(path: &str) -> FilesystemResult<DirListing> {
     let fs = FILESYSTEM.lock();
     fs.list_directory(path)
 }
 
+pub use append::append_file;
+
 /// Read a file from the filesystem
 pub fn read_file(filename: &str) -> FilesystemResult<heapless::Vec<u8, 32768>> {
+    append::flush_file(filename)?;
+    let mut fs = FILESYSTEM.lock();
+    let content = fs.read_file(filename)?;
+    let _ = fs.mark_accessed(filename);
+    Ok(content)
+}
+
+/// Read part of a file starting at `offset`. A read from the start marks
+/// the file accessed.
+pub fn read_file_at(filename: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+    append::flush_file(filename)?;
+    let mut fs = FILESYSTEM.lock();
+    let read = fs.read_at(filename, offset, buffer)?;
+    if offset == 0 {
+        let _ = fs.mark_accessed(filename);
+    }
+    Ok(read)
+}
+
+/// Like `read_file_at`, but fail with `DeviceError` instead of waiting if the
+/// filesystem is busy (for use from the page fault handler)
+pub fn try_read_file_at(filename: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+    let Some(fs) = FILESYSTEM.try_lock() else {
+        crate::health::count(crate::health::Event::LockContended);
+        return Err(FilesystemError::DeviceError);
+    };
+    fs.read_at(filename, offset, buffer)
+}
+
+/// Size of the pieces `read_file_chunked` reads at a time
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Read a file of any size piece by piece, calling `f` with each piece in
+/// order. The filesystem is not locked while `f` runs. Returns the total
+/// number of bytes read.
+pub fn read_file_chunked(filename: &str, mut f: impl FnMut(&[u8])) -> FilesystemResult<usize> {
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
+    let mut offset = 0;
+    
+    loop {
+        let read = read_file_at(filename, offset, &mut chunk)?;
+        if read == 0 {
+            return Ok(offset);
+        }
+        f(&chunk[..read]);
+        offset += read;
+    }
+}
+
+/// Get the size of a file in bytes
+pub fn get_file_size(filename: &str) -> FilesystemResult<usize> {
+    append::flush_file(filename)?;
     let fs = FILESYSTEM.lock();
-    fs.read_file(filename)
+    fs.get_file_size(filename)
+}
+
+/// A whole file read into kernel memory, freed when dropped
+pub struct FileBuffer {
+    addr: usize,
+    len: usize,
+    capacity: usize,
 }
 
-/// Read an ELF file from the filesystem (supports larger files)
-pub fn read_elf_file(filename: &str) -> Result<heapless::Vec<u8, 32768>, &'static str> {
-    // Use the regular read_file with larger buffer
-    match read_file(filename) {
-        Ok(data) => Ok(data),
-        Err(_) => Err("Failed to read ELF file"),
+impl core::ops::Deref for FileBuffer {
+    type Target = [u8];
+    
+    fn deref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.addr as *const u8, self.len) }
     }
 }
 
+impl Drop for FileBuffer {
+    fn drop(&mut self) {
+        crate::memory::deallocate_kernel_memory(self.addr, self.capacity);
+    }
+}
+
+/// Read an ELF file from the filesystem, whatever its size
+pub fn read_elf_file(filename: &str) -> Result<FileBuffer, &'static str> {
+    let size = get_file_size(filename).map_err(|_| "Failed to read ELF file")?;
+    let capacity = size.max(1);
+    let addr = crate::memory::allocate_kernel_memory(capacity, 8).ok_or("Out of memory for ELF file")?;
+    let mut buffer = FileBuffer { addr, len: 0, capacity };
+    
+    read_file_chunked(filename, |chunk| {
+        // The file may have grown since we sized the buffer
+        let take = chunk.len().min(buffer.capacity - buffer.len);
+        unsafe {
+            core::ptr::copy_nonoverlapping(chunk.as_ptr(), (buffer.addr + buffer.len) as *mut u8, take);
+        }
+        buffer.len += take;
+    }).map_err(|_| "Failed to read ELF file")?;
+    
+    Ok(buffer)
+}
+
 /// Check if a file exists
 pub fn file_exists(filename: &str) -> bool {
+    let _ = append::flush_file(filename);
     let fs = FILESYSTEM.lock();
     fs.file_exists(filename)
 }
 
-/// Get file entry for an existing file (for internal use)
-fn get_file_entry(fs: &UnifiedFileSystem, filename: &str) -> FilesystemResult<FileEntry> {
-    match &fs.filesystem {
-        Filesystem::Ext2(ext2_fs) => {
-            // Use the public method from ext2 filesystem
-            ext2_fs.get_file_entry(filename)
-        }
-        Filesystem::None => Err(FilesystemError::NotMounted),
-    }
-}
-
 /// Write data to a file (create if it doesn't exist)
 pub fn write_file(filename: &str, content: &str) -> FilesystemResult<()> {
+    // Earlier appends are replaced along with the rest
+    append::flush_file(filename)?;
     let mut fs = FILESYSTEM.lock();
     
     let file_entry = if fs.file_exists(filename) {
         // Try to get existing file entry efficiently
-        match get_file_entry(&fs, filename) {
+        match fs.get_file_entry(filename) {
             Ok(entry) => entry,
             Err(_) => {
                 // Fallback: delete and recreate
@@ -347,18 +971,135 @@ pub fn write_file(filename: &str, content: &str) -> FilesystemResult<()> {
         fs.create_file(filename)?
     };
     
-    // Write content to file
+    // Replace the old contents
     let data = content.as_bytes();
+    fs.truncate_file(&file_entry, 0)?;
     fs.write_file(&file_entry, 0, data)?;
     
     Ok(())
 }
 
+/// Cut or extend an existing file to `size` bytes
+pub fn truncate_file(filename: &str, size: usize) -> FilesystemResult<()> {
+    if crate::memory::swap::is_swap_file(filename) {
+        return Err(FilesystemError::Busy);
+    }
+    append::flush_file(filename)?;
+    let mut fs = FILESYSTEM.lock();
+    let file_entry = fs.get_file_entry(filename)?;
+    fs.truncate_file(&file_entry, size as u64)
+}
+
+/// Give file or directory `from` the name `to` on the same filesystem,
+/// without copying its data
+pub fn rename(from: &str, to: &str) -> FilesystemResult<()> {
+    // Appends still waiting are for the old name
+    append::flush_file(from)?;
+    append::flush_file(to)?;
+    FILESYSTEM.lock().rename(from, to)
+}
+
+/// Give the file `existing` the further name `new` on the same filesystem
+pub fn link(existing: &str, new: &str) -> FilesystemResult<()> {
+    append::flush_file(existing)?;
+    FILESYSTEM.lock().link(existing, new)
+}
+
+/// Make `path` a symbolic link to `target`. The target is kept as given
+/// and need not exist.
+pub fn create_symlink(path: &str, target: &str) -> FilesystemResult<()> {
+    FILESYSTEM.lock().create_symlink(path, target).map(|_| ())
+}
+
+/// Target of the symbolic link at `path`
+pub fn read_link(path: &str) -> FilesystemResult<path::PathBuf> {
+    FILESYSTEM.lock().read_link(path)
+}
+
+/// Set the access and modification times of `path`; see
+/// `FileSystem::set_times`
+pub fn set_times(path: &str, atime: Option<u64>, mtime: Option<u64>) -> FilesystemResult<()> {
+    FILESYSTEM.lock().set_times(path, atime, mtime)
+}
+
+/// Mode, owner, size and times of `path`, following a symbolic link at
+/// the end only with `follow`
+pub fn stat(path: &str, follow: bool) -> FilesystemResult<FileStat> {
+    // The size counts appends still waiting
+    append::flush_file(path)?;
+    FILESYSTEM.lock().stat(path, follow)
+}
+
+/// Whether `path` is in /dev or /proc, which are not on any disk
+pub fn is_pseudo_path(path: &str) -> bool {
+    devfs::node_name(path).is_some() || procfs::is_proc_path(path)
+}
+
+/// Write `data` into an existing file at byte `offset`; returns how many
+/// bytes were written
+pub fn write_file_at(filename: &str, offset: usize, data: &[u8]) -> FilesystemResult<usize> {
+    append::flush_file(filename)?;
+    let mut fs = FILESYSTEM.lock();
+    let file_entry = fs.get_file_entry(filename)?;
+    fs.write_file(&file_entry, offset as u64, data)
+}
+
+/// Overwrite a file's data, then delete it; see `wipe`
+pub fn shred_file(path: &str, passes: usize) -> FilesystemResult<wipe::WipeReport> {
+    // Appends still waiting would only bring it back
+    append::discard_file(path);
+    FILESYSTEM.lock().shred_file(path, passes)
+}
+
+/// Overwrite the free blocks of the filesystem holding `path`; see `wipe`
+pub fn wipe_free_space(path: &str, passes: usize) -> FilesystemResult<wipe::WipeReport> {
+    FILESYSTEM.lock().wipe_free_space(path, passes)
+}
+
+/// Check the ext2 superblocks of `volume`. With `restore`, a damaged
+/// primary superblock and group descriptor table are overwritten with the
+/// first valid backup; the volume must not be mounted.
+pub fn check_superblocks(volume: Volume, restore: bool) -> FilesystemResult<()> {
+    use ext2::superblock;
+
+    let fs = FILESYSTEM.lock();
+    let mounted = fs.volume.is_some_and(|root| root.overlaps(&volume))
+        || fs.mounts.iter().any(|mount| mount.volume.overlaps(&volume));
+    if restore && mounted {
+        return Err(FilesystemError::Busy);
+    }
+
+    cache::with_volume(volume, || {
+        let primary_ok = superblock::primary_is_valid();
+        let backup = superblock::find_backup();
+
+        info_println!(target: "fs", "ext2 superblocks on {}:", volume);
+        console_println!("   Primary: {}", if primary_ok { "valid" } else { "damaged" });
+        match &backup {
+            Some(backup) => console_println!("   Backup: block {} (group {})", backup.block, backup.group),
+            None => console_println!("   Backup: none found"),
+        }
+
+        if !restore {
+            return Ok(());
+        }
+        if primary_ok {
+            console_println!("Primary superblock is valid, nothing to restore");
+            return Ok(());
+        }
+        let backup = backup.ok_or(FilesystemError::InvalidSuperblock)?;
+        superblock::restore_primary(&backup)?;
+        cache::flush()?;
+        ok_println!(target: "fs", "Primary superblock restored from block {}", backup.block);
+        Ok(())
+    })
+}
+
 /// Check filesystem status and display information
 pub fn check_filesystem() -> Result<(), FilesystemError> {
     let fs = FILESYSTEM.lock();
     
-    console_println!("[i] Filesystem Check:");
+    info_println!(target: "fs", "Filesystem Check:");
     console_println!("   Type: {}", fs.get_filesystem_type());
     
     if let Some((signature, total_blocks, block_size)) = fs.get_filesystem_info() {
@@ -367,7 +1108,12 @@ pub fn check_filesystem() -> Result<(), FilesystemError> {
             if fs.is_mounted() { "MOUNTED" } else { "UNMOUNTED" });
         console_println!("   Total Blocks/Sectors: {}", total_blocks);
         console_println!("   Block/Sector Size: {} bytes", block_size);
-        console_println!("   Storage: VirtIO Block Device");
+        if let Some(volume) = fs.volume() {
+            console_println!("   Storage: VirtIO Block Device {}", volume);
+            if let Some(id) = probe::probe(volume) {
+                console_println!("   Label: {}  UUID: {}", id.label.as_deref().unwrap_or("(none)"), id.uuid);
+            }
+        }
     }
     
     let file_count = match list_files() {
@@ -375,6 +1121,7 @@ pub fn check_filesystem() -> Result<(), FilesystemError> {
         Err(_) => 0,
     };
     console_println!("   Files in Cache: {}", file_count);
+    cache::show_stats();
     
     Ok(())
 } 
\ No newline at end of file
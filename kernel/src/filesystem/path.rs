@@ -0,0 +1,85 @@
+//! Paths: splitting, normalizing and resolving them against a working
+//! directory
+//!
+//! The filesystems are given absolute paths with no `.`, `..` or repeated
+//! slashes. A path that comes from the user, as a shell argument or in a
+//! program's syscall, goes through `resolve` first, which joins it to the
+//! working directory of the current process. `..` is taken by name, as a
+//! shell's `cd` does, so `/mnt/..` is `/` whatever is mounted at /mnt, and
+//! `..` at the root stays there.
+//!
+//! Each process starts in its parent's working directory; programs change
+//! it with `chdir` and read it with `getcwd`.
+
+use heapless::{String, Vec};
+
+use super::traits::{FilesystemError, FilesystemResult};
+
+/// Longest path, in bytes
+pub const MAX_PATH_LEN: usize = 256;
+
+/// Most components a path resolves to
+const MAX_COMPONENTS: usize = 32;
+
+/// An absolute, normalized path
+pub type PathBuf = String<MAX_PATH_LEN>;
+
+/// The names in `path` in order, without empty parts and `.`
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|part| !part.is_empty() && *part != ".")
+}
+
+/// The directory holding `path` and the last name in it: ("/a", "b") for
+/// "/a/b/", ("/", "b") for "/b". `path` is already normalized.
+pub fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("/", path),
+    }
+}
+
+/// `path` taken from directory `base` (itself absolute), normalized
+pub fn join(base: &str, path: &str) -> FilesystemResult<PathBuf> {
+    let mut parts: Vec<&str, MAX_COMPONENTS> = Vec::new();
+    let start = if path.starts_with('/') { "" } else { base };
+    for part in components(start).chain(components(path)) {
+        if part == ".." {
+            parts.pop();
+        } else {
+            parts.push(part).map_err(|_| FilesystemError::FilenameTooLong)?;
+        }
+    }
+
+    let mut joined = PathBuf::new();
+    if parts.is_empty() {
+        let _ = joined.push('/');
+    }
+    for part in parts {
+        joined.push('/').and_then(|()| joined.push_str(part))
+            .map_err(|()| FilesystemError::FilenameTooLong)?;
+    }
+    Ok(joined)
+}
+
+/// `path` as an absolute path, taken from the current process's working
+/// directory if it is relative
+pub fn resolve(path: &str) -> FilesystemResult<PathBuf> {
+    if path.starts_with('/') {
+        return join("/", path);
+    }
+    join(&crate::process::current_cwd(), path)
+}
+
+/// Make `path` the working directory of the current process. It has to
+/// be a directory.
+pub fn change_directory(path: &str) -> FilesystemResult<()> {
+    let path = resolve(path)?;
+    // The root is there even with nothing mounted
+    if path != "/" && !super::FILESYSTEM.lock().get_file_entry(&path)?.is_directory {
+        return Err(FilesystemError::NotADirectory);
+    }
+    crate::process::set_current_cwd(&path);
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Identifying the filesystem on a disk or partition without mounting it
+//!
+//! Like blkid, this reads the ext2 superblock or FAT boot sector of a volume
+//! for its type, label and UUID, so volumes can be named `LABEL=...` or
+//! `UUID=...` instead of by the order their devices were found in. FAT
+//! volumes have a 32-bit serial number rather than a UUID; it is shown as
+//! XXXX-XXXX, as Linux does.
+
+use core::fmt::Write;
+use heapless::{String, Vec};
+
+use super::{cache, FilesystemType};
+use crate::virtio::Volume;
+use crate::virtio::block::partition::{self, MAX_DISKS};
+use elinos_common::virtio::storage::MAX_PARTITIONS;
+
+/// What a volume holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeId {
+    pub fs_type: FilesystemType,
+    pub label: Option<String<16>>,
+    /// Empty if the filesystem has none
+    pub uuid: String<36>,
+}
+
+const EXT2_MAGIC: u16 = 0xEF53;
+
+/// FAT boot sectors say "NO NAME" when there is no label
+const FAT_NO_LABEL: &str = "NO NAME";
+
+/// Identify the filesystem on `volume`. Callers hold the filesystem lock,
+/// as for any `cache::with_volume` I/O.
+pub fn probe(volume: Volume) -> Option<VolumeId> {
+    let mut sectors = [0u8; 1536];
+    cache::with_volume(volume, || cache::read_blocks(0, &mut sectors)).ok()?;
+    let (boot, superblock) = sectors.split_at(512);
+
+    if u16::from_le_bytes([superblock[56], superblock[57]]) == EXT2_MAGIC {
+        let mut uuid = String::new();
+        format_uuid(&mut uuid, &superblock[0x68..0x78]);
+        let label = super::ext2::parse_volume_label(&superblock[0x78..0x88]);
+        return Some(VolumeId { fs_type: FilesystemType::Ext2, label, uuid });
+    }
+
+    probe_fat(boot)
+}
+
+/// Read the label and serial number of a FAT12/16/32 boot sector
+fn probe_fat(boot: &[u8]) -> Option<VolumeId> {
+    if boot[510..512] != [0x55, 0xAA] {
+        return None;
+    }
+    let bytes_per_sector = u16::from_le_bytes([boot[11], boot[12]]);
+    if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096) {
+        return None;
+    }
+
+    // FAT32 has no 16-bit FAT size, and its extended BPB starts later
+    let sectors_per_fat16 = u16::from_le_bytes([boot[22], boot[23]]);
+    let ebpb = if sectors_per_fat16 == 0 { 0x40 } else { 0x24 };
+    let fs_name = &boot[ebpb + 0x12..ebpb + 0x1A];
+    if boot[ebpb + 2] != 0x29 || !fs_name.starts_with(b"FAT") {
+        return None;
+    }
+
+    let serial = u32::from_le_bytes([boot[ebpb + 3], boot[ebpb + 4], boot[ebpb + 5], boot[ebpb + 6]]);
+    let mut uuid = String::new();
+    let _ = write!(uuid, "{:04X}-{:04X}", serial >> 16, serial & 0xFFFF);
+
+    let label = core::str::from_utf8(&boot[ebpb + 7..ebpb + 0x12]).ok()
+        .map(str::trim_end)
+        .filter(|label| !label.is_empty() && *label != FAT_NO_LABEL)
+        .and_then(|label| String::try_from(label).ok());
+
+    Some(VolumeId { fs_type: FilesystemType::Fat, label, uuid })
+}
+
+/// Write a 16-byte UUID in the usual 8-4-4-4-12 form; nothing if it is all zero
+fn format_uuid(out: &mut String<36>, uuid: &[u8]) {
+    if uuid.iter().all(|&b| b == 0) {
+        return;
+    }
+    for (i, byte) in uuid.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            let _ = out.push('-');
+        }
+        let _ = write!(out, "{:02x}", byte);
+    }
+}
+
+/// Every disk and partition that may hold a filesystem, in device order
+pub fn all_volumes() -> Vec<Volume, { MAX_DISKS * MAX_PARTITIONS }> {
+    let mut volumes = Vec::new();
+    for index in 0..MAX_DISKS {
+        if partition::capacity(index).is_none() {
+            continue;
+        }
+        for volume in partition::volumes(index) {
+            let _ = volumes.push(volume);
+        }
+    }
+    volumes
+}
+
+/// The volume named by `spec`: `blkN[pM]`, `LABEL=<label>` or
+/// `UUID=<uuid>` (compared ignoring case). Callers hold the filesystem
+/// lock, as for `probe`.
+pub fn find_volume(spec: &str) -> Option<Volume> {
+    if let Some(label) = spec.strip_prefix("LABEL=") {
+        return all_volumes().into_iter()
+            .find(|&volume| probe(volume).is_some_and(|id| id.label.as_deref() == Some(label)));
+    }
+    if let Some(uuid) = spec.strip_prefix("UUID=") {
+        return all_volumes().into_iter()
+            .find(|&volume| probe(volume).is_some_and(|id| !id.uuid.is_empty() && id.uuid.eq_ignore_ascii_case(uuid)));
+    }
+    crate::virtio::parse_volume_name(spec)
+}
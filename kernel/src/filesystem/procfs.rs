@@ -0,0 +1,410 @@
+//! Kernel state as files under /proc
+//!
+//! Like devfs, procfs is made up on the spot: each read formats the current
+//! state into text, so scripts and programs can read it instead of parsing
+//! what commands print. Everything is read-only except /proc/sys, where
+//! writing a file sets the kernel parameter it stands for.
+//!
+//! ```text
+//! meminfo        memory sizes, allocator counters and swap use
+//! swaps          the swap area, as in Linux
+//! mounts         one line per mounted filesystem, as in Linux
+//! devices        device files, block devices and the console TTY's devices
+//! framestats     frames flushed to the VirtIO GPU and how long they took
+//! health         error and event counters, as the `health` command shows
+//! traps          traps taken by cause, as the `traps` command shows
+//! bootchart      time taken by each boot stage, as the `bootchart` command shows
+//! slabinfo       objects and slabs of each slab cache, roughly as in Linux
+//! <pid>/status   name, state, working directory and memory of a process
+//! <pid>/maps     address ranges of a process, their permissions and backing
+//! sys/...        one file per sysctl, `fs.blockcache.mode` at sys/fs/blockcache/mode
+//! ```
+
+use core::fmt::Write;
+use heapless::String;
+
+use super::devfs::{self, DEVFS_PATH};
+use super::path;
+use super::traits::{DirListing, FileEntry, FilesystemError, FilesystemResult, DIR_ENTRY_CACHE};
+use super::UnifiedFileSystem;
+use crate::process::{ProcessState, PROCESS_MANAGER};
+use crate::sysctl::{self, Tunable};
+use crate::virtio::{Volume, MAX_BLOCK_DEVICES};
+
+/// Where procfs appears in the tree
+pub const PROCFS_PATH: &str = "/proc";
+
+/// `FileEntry::mount` of files under /proc
+pub const PROCFS_MOUNT: usize = usize::MAX - 1;
+
+/// Longest text a /proc file holds
+const MAX_CONTENT: usize = 4096;
+
+/// A file or directory under /proc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Root,
+    MemInfo,
+    Swaps,
+    Mounts,
+    Devices,
+    FrameStats,
+    Health,
+    Traps,
+    Bootchart,
+    SlabInfo,
+    Process(i32),
+    ProcessStatus(i32),
+    ProcessMaps(i32),
+    /// Directory of /proc/sys holding the first `depth` parts of the name
+    /// of tunable `tunable`
+    SysDir { tunable: usize, depth: usize },
+    /// File of the tunable with this index
+    SysEntry(usize),
+}
+
+impl Node {
+    fn is_directory(&self) -> bool {
+        matches!(self, Node::Root | Node::Process(_) | Node::SysDir { .. })
+    }
+}
+
+/// Whether `path` lies in /proc
+pub fn is_proc_path(path: &str) -> bool {
+    match path.strip_prefix(PROCFS_PATH) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Whether there is a process with this pid
+fn process_exists(pid: i32) -> bool {
+    PROCESS_MANAGER.lock().get_process(pid)
+        .is_some_and(|process| process.state != ProcessState::Unused)
+}
+
+/// Node at `path`, which lies in /proc
+pub fn lookup(path: &str) -> FilesystemResult<Node> {
+    let rest = path.strip_prefix(PROCFS_PATH).ok_or(FilesystemError::FileNotFound)?;
+    if let Some(sys) = rest.strip_prefix("/sys") {
+        if sys.is_empty() || sys.starts_with('/') {
+            return sys_lookup(sys);
+        }
+    }
+    let mut parts = path::components(rest);
+    let node = match (parts.next(), parts.next()) {
+        (None, _) => Node::Root,
+        (Some("meminfo"), None) => Node::MemInfo,
+        (Some("swaps"), None) => Node::Swaps,
+        (Some("mounts"), None) => Node::Mounts,
+        (Some("devices"), None) => Node::Devices,
+        (Some("framestats"), None) => Node::FrameStats,
+        (Some("health"), None) => Node::Health,
+        (Some("traps"), None) => Node::Traps,
+        (Some("bootchart"), None) => Node::Bootchart,
+        (Some("slabinfo"), None) => Node::SlabInfo,
+        (Some(pid), file) => {
+            let pid = pid.parse::<i32>().ok()
+                .filter(|&pid| process_exists(pid))
+                .ok_or(FilesystemError::FileNotFound)?;
+            match file {
+                None => Node::Process(pid),
+                Some("status") => Node::ProcessStatus(pid),
+                Some("maps") => Node::ProcessMaps(pid),
+                Some(_) => return Err(FilesystemError::FileNotFound),
+            }
+        }
+    };
+    if parts.next().is_some() {
+        return Err(FilesystemError::FileNotFound);
+    }
+    Ok(node)
+}
+
+/// Node at `path` under /proc/sys: the tunable its parts name, or a
+/// directory if they are only the start of names
+fn sys_lookup(path: &str) -> FilesystemResult<Node> {
+    let parts = || path::components(path);
+    let depth = parts().count();
+    for (index, tunable) in sysctl::tunables().enumerate() {
+        let mut name = tunable.name.split('.');
+        if parts().all(|part| name.next() == Some(part)) {
+            return Ok(match name.next() {
+                None => Node::SysEntry(index),
+                Some(_) => Node::SysDir { tunable: index, depth },
+            });
+        }
+    }
+    Err(FilesystemError::FileNotFound)
+}
+
+fn tunable(index: usize) -> FilesystemResult<&'static Tunable> {
+    sysctl::tunables().nth(index).ok_or(FilesystemError::FileNotFound)
+}
+
+/// The first `depth` parts of a dotted name
+fn name_prefix(name: &str, depth: usize) -> &str {
+    match depth.checked_sub(1).and_then(|n| name.match_indices('.').nth(n)) {
+        Some((end, _)) => &name[..end],
+        None if depth == 0 => "",
+        None => name,
+    }
+}
+
+/// Listing of a /proc directory: name, size and whether it is a directory
+pub fn list(path: &str) -> FilesystemResult<DirListing> {
+    let mut entries = DirListing::new(&DIR_ENTRY_CACHE);
+    let mut add = |name: &str, is_directory: bool| {
+        if entries.iter().any(|(listed, _, _)| listed == name) {
+            return;
+        }
+        if let Ok(name) = String::try_from(name) {
+            let _ = entries.push((name, 0, is_directory));
+        }
+    };
+    match lookup(path)? {
+        Node::Root => {
+            add("meminfo", false);
+            add("swaps", false);
+            add("mounts", false);
+            add("devices", false);
+            add("framestats", false);
+            add("health", false);
+            add("traps", false);
+            add("bootchart", false);
+            add("slabinfo", false);
+            add("sys", true);
+            for process in PROCESS_MANAGER.lock().processes() {
+                if process.state == ProcessState::Unused {
+                    continue;
+                }
+                let mut name = String::<16>::new();
+                let _ = write!(name, "{}", process.pid);
+                add(&name, true);
+            }
+        }
+        Node::Process(_) => {
+            add("status", false);
+            add("maps", false);
+        }
+        Node::SysDir { tunable: index, depth } => {
+            let prefix = name_prefix(tunable(index)?.name, depth);
+            for tunable in sysctl::tunables() {
+                let rest = match depth {
+                    0 => Some(tunable.name),
+                    _ => tunable.name.strip_prefix(prefix).and_then(|rest| rest.strip_prefix('.')),
+                };
+                let Some(rest) = rest else { continue };
+                match rest.split_once('.') {
+                    Some((child, _)) => add(child, true),
+                    None => add(rest, false),
+                }
+            }
+        }
+        _ => return Err(FilesystemError::NotADirectory),
+    }
+    Ok(entries)
+}
+
+/// Entry for `path`. Files report size 0, as they have no size until read.
+pub fn file_entry(path: &str) -> FilesystemResult<FileEntry> {
+    let node = lookup(path)?;
+    let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("proc");
+    let mut entry = match node {
+        // Writes find their tunable by inode
+        Node::SysEntry(index) => FileEntry::new_file(name, index as u64 + 1, 0)?,
+        _ if node.is_directory() => FileEntry::new_directory(name, 0)?,
+        _ => FileEntry::new_file(name, 0, 0)?,
+    };
+    entry.mount = PROCFS_MOUNT;
+    Ok(entry)
+}
+
+/// Read the text of the file at `path` from byte `offset`
+pub fn read(fs: &UnifiedFileSystem, path: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize> {
+    let content = content(fs, lookup(path)?)?;
+    let content = content.as_bytes().get(offset..).unwrap_or(&[]);
+    let count = content.len().min(buffer.len());
+    buffer[..count].copy_from_slice(&content[..count]);
+    Ok(count)
+}
+
+/// The tunable behind `file`, if it is a /proc/sys file
+fn sys_tunable(file: &FileEntry) -> Option<&'static Tunable> {
+    let index = file.inode.checked_sub(1)?;
+    sysctl::tunables().nth(index as usize)
+}
+
+/// Whether `file` can be written
+pub fn is_writable(file: &FileEntry) -> bool {
+    sys_tunable(file).is_some()
+}
+
+/// Set the tunable behind a /proc/sys file to the text written, which must
+/// be the whole value
+pub fn write(file: &FileEntry, offset: usize, data: &[u8]) -> FilesystemResult<usize> {
+    let tunable = sys_tunable(file).ok_or(FilesystemError::ReadOnly)?;
+    if offset != 0 {
+        return Err(FilesystemError::IoError);
+    }
+    let text = core::str::from_utf8(data).map_err(|_| FilesystemError::IoError)?;
+    tunable.set(text.trim())
+        .map_err(|e| FilesystemError::Other(String::try_from(e).unwrap_or_default()))?;
+    Ok(data.len())
+}
+
+/// Length of the text the file at `path` has now
+pub fn size(fs: &UnifiedFileSystem, path: &str) -> FilesystemResult<usize> {
+    Ok(content(fs, lookup(path)?)?.len())
+}
+
+/// The current text of a file
+fn content(fs: &UnifiedFileSystem, node: Node) -> FilesystemResult<String<MAX_CONTENT>> {
+    let mut out = String::new();
+    match node {
+        Node::Root | Node::Process(_) | Node::SysDir { .. } => return Err(FilesystemError::IsADirectory),
+        Node::MemInfo => write_meminfo(&mut out),
+        Node::Swaps => write_swaps(&mut out),
+        Node::Mounts => write_mounts(fs, &mut out),
+        Node::Devices => write_devices(&mut out),
+        Node::FrameStats => write_framestats(&mut out),
+        Node::Health => crate::health::write_summary(&mut out),
+        Node::Traps => crate::health::write_traps(&mut out),
+        Node::Bootchart => crate::bootchart::write_report(&mut out),
+        Node::SlabInfo => crate::memory::slab::write_slabinfo(&mut out),
+        Node::ProcessStatus(pid) => write_status(pid, &mut out),
+        Node::ProcessMaps(pid) => write_maps(pid, &mut out),
+        Node::SysEntry(index) => writeln!(out, "{}", tunable(index)?.value()),
+    }
+    .map_err(|_| FilesystemError::IoError)?;
+    Ok(out)
+}
+
+fn write_meminfo(out: &mut String<MAX_CONTENT>) -> core::fmt::Result {
+    let stats = crate::memory::get_memory_stats();
+    writeln!(out, "MemTotal:      {:>10} kB", stats.detected_ram_size / 1024)?;
+    writeln!(out, "HeapTotal:     {:>10} kB", stats.heap_size / 1024)?;
+    writeln!(out, "HeapUsed:      {:>10} kB", stats.heap_used / 1024)?;
+    writeln!(out, "HeapFree:      {:>10} kB", stats.heap_size.saturating_sub(stats.heap_used) / 1024)?;
+    writeln!(out, "Allocations:   {:>10}", stats.allocation_count)?;
+    writeln!(out, "AllocFailures: {:>10}", stats.allocation_failures)?;
+    writeln!(out, "Compactions:   {:>10}", stats.compactions)?;
+    writeln!(out, "Compacted:     {:>10} kB", stats.compacted_bytes / 1024)?;
+    writeln!(out, "Migrated:      {:>10} kB", stats.migrated_bytes / 1024)?;
+    writeln!(out, "MemoryRegions: {:>10}", stats.regions_detected)?;
+    writeln!(out, "AllocatorMode: {:?}", stats.allocator_mode)?;
+    let swap = crate::memory::swap::stats();
+    let kb = |pages: usize| pages * crate::memory::mmu::PAGE_SIZE / 1024;
+    writeln!(out, "SwapTotal:     {:>10} kB", kb(swap.pages))?;
+    writeln!(out, "SwapFree:      {:>10} kB", kb(swap.pages - swap.used))?;
+    writeln!(out, "SwapIns:       {:>10}", swap.swap_ins)?;
+    writeln!(out, "SwapOuts:      {:>10}", swap.swap_outs)?;
+    writeln!(out, "Reclaims:      {:>10}", swap.reclaims)?;
+    writeln!(out, "ReclaimFails:  {:>10}", swap.reclaim_failures)
+}
+
+/// `Filename Type Size Used Priority`, sizes in KB, as in Linux
+fn write_swaps(out: &mut String<MAX_CONTENT>) -> core::fmt::Result {
+    writeln!(out, "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority")?;
+    let swap = crate::memory::swap::stats();
+    let (Some(name), Some(kind)) = (swap.name, swap.kind) else { return Ok(()) };
+    let kb = |pages: usize| pages * crate::memory::mmu::PAGE_SIZE / 1024;
+    writeln!(out, "{:<40}{}\t\t{}\t\t{}\t\t-1", name, kind.name(), kb(swap.pages), kb(swap.used))
+}
+
+/// `source mountpoint type options 0 0`, as in Linux
+pub fn write_mounts(fs: &UnifiedFileSystem, out: &mut impl Write) -> core::fmt::Result {
+    if let Some(root) = fs.volume() {
+        writeln!(out, "{} / {} rw 0 0", root, fs.get_filesystem_type())?;
+    }
+    for mount in fs.mounts() {
+        writeln!(out, "{} {} ext2 {} 0 0", mount.volume, mount.path, if mount.read_only { "ro" } else { "rw" })?;
+    }
+    writeln!(out, "devfs {} devfs rw 0 0", DEVFS_PATH)?;
+    writeln!(out, "proc {} proc rw 0 0", PROCFS_PATH)
+}
+
+/// The device nodes, block devices and console devices
+pub fn write_devices(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out, "Character devices:")?;
+    for node in devfs::nodes() {
+        if !matches!(node, devfs::Node::Block(_)) {
+            writeln!(out, "  {}", node.name())?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Block devices:")?;
+    for index in 0..MAX_BLOCK_DEVICES {
+        let (sectors, state) = match crate::virtio::block_device(index) {
+            Some(device) if device.lock().is_initialized() => {
+                let device = device.lock();
+                (device.get_capacity(), device.health().state())
+            }
+            _ => continue,
+        };
+        let disk = Volume::disk(index);
+        writeln!(out, "  {} {} {} sectors {}", disk, devfs::Node::Block(disk).name(), sectors, state)?;
+    }
+
+    let (sinks, sources) = elinos_common::tty::devices();
+    writeln!(out)?;
+    writeln!(out, "Console:")?;
+    write!(out, "  output")?;
+    for name in &sinks {
+        write!(out, " {}", name)?;
+    }
+    writeln!(out)?;
+    write!(out, "  input")?;
+    for name in &sources {
+        write!(out, " {}", name)?;
+    }
+    writeln!(out)
+}
+
+fn write_framestats(out: &mut String<MAX_CONTENT>) -> core::fmt::Result {
+    let stats = crate::virtio::frame_stats();
+    writeln!(out, "Frames:     {:>10}", stats.frames)?;
+    writeln!(out, "Pixels:     {:>10}", stats.pixels)?;
+    writeln!(out, "Failed:     {:>10}", stats.failed)?;
+    writeln!(out, "LastFlush:  {:>10} us", stats.last_ns / 1000)?;
+    writeln!(out, "MinFlush:   {:>10} us", stats.min_ns / 1000)?;
+    writeln!(out, "AvgFlush:   {:>10} us", stats.average_ns() / 1000)?;
+    writeln!(out, "MaxFlush:   {:>10} us", stats.max_ns / 1000)?;
+    writeln!(out, "FlushTime:  {:>10} ms", stats.total_ns / 1_000_000)
+}
+
+fn write_status(pid: i32, out: &mut String<MAX_CONTENT>) -> core::fmt::Result {
+    let pm = PROCESS_MANAGER.lock();
+    let process = match pm.get_process(pid) {
+        Some(process) => process,
+        None => return Ok(()),
+    };
+    let state = match process.state {
+        ProcessState::Running => "R (running)",
+        ProcessState::Waiting => "S (waiting)",
+        ProcessState::Zombie => "Z (zombie)",
+        ProcessState::Unused => "X (dead)",
+    };
+    writeln!(out, "Name:     {}", process.name)?;
+    writeln!(out, "State:    {}", state)?;
+    writeln!(out, "Pid:      {}", process.pid)?;
+    writeln!(out, "PPid:     {}", process.ppid)?;
+    writeln!(out, "Cwd:      {}", process.cwd)?;
+    if let Some(code) = process.exit_code {
+        writeln!(out, "ExitCode: {}", code)?;
+    }
+    writeln!(out, "Entry:    0x{:x}", process.entry_point)?;
+    writeln!(out, "VmSize:   {} kB", process.memory_size.unwrap_or(0) / 1024)
+}
+
+/// `start-end perms backing name`, one line per mapping as in Linux; empty
+/// for a process that runs in the kernel's address space
+fn write_maps(pid: i32, out: &mut String<MAX_CONTENT>) -> core::fmt::Result {
+    for vma in crate::process::process_vmas(pid).unwrap_or_default() {
+        let perms = vma.perms_str();
+        writeln!(out, "{:08x}-{:08x} {} {:<6} {}", vma.start, vma.end,
+            core::str::from_utf8(&perms).unwrap_or("----"), vma.backing.name(), vma.name)?;
+    }
+    Ok(())
+}
@@ -1,6 +1,7 @@
 // Common traits and types for filesystem implementations
 
-use heapless::Vec;
+use crate::memory::slab::{ListNode, SlabCache, SlabList};
+use super::path::PathBuf;
 use crate::virtio::DiskError;
 use elinos_common::console_println;
 
@@ -28,6 +29,10 @@ pub enum FilesystemError {
     PathNotFound,
     InvalidFileNameCharacter,
     NotImplemented,
+    ReadOnly,
+    Busy,
+    SymlinkLoop,
+    TooManyLinks,
     Other(heapless::String<64>),
 }
 
@@ -55,6 +60,10 @@ impl core::fmt::Display for FilesystemError {
             FilesystemError::PathNotFound => write!(f, "Path not found"),
             FilesystemError::InvalidFileNameCharacter => write!(f, "Invalid file name character"),
             FilesystemError::NotImplemented => write!(f, "Feature not implemented"),
+            FilesystemError::ReadOnly => write!(f, "Read-only filesystem"),
+            FilesystemError::Busy => write!(f, "Device or resource busy"),
+            FilesystemError::SymlinkLoop => write!(f, "Too many levels of symbolic links"),
+            FilesystemError::TooManyLinks => write!(f, "Too many links"),
             FilesystemError::Other(ref s) => write!(f, "Other error: {}", s),
         }
     }
@@ -78,6 +87,9 @@ pub struct FileEntry {
     pub is_directory: bool,
     pub size: usize,
     pub inode: u64,  // Can be cluster (FAT32) or inode number (ext2)
+    /// Filesystem the entry belongs to: 0 for the root, otherwise one more
+    /// than its mount table slot
+    pub mount: usize,
 }
 
 impl FileEntry {
@@ -90,6 +102,7 @@ impl FileEntry {
             is_directory: false,
             size,
             inode,
+            mount: 0,
         })
     }
     
@@ -102,26 +115,125 @@ impl FileEntry {
             is_directory: true,
             size: 0,
             inode,
+            mount: 0,
         })
     }
 }
 
+/// File type bits of `FileStat::mode`, Linux's S_IF* values
+pub const S_IFMT: u32 = 0o170000;
+pub const S_IFREG: u32 = 0o100000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFLNK: u32 = 0o120000;
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFBLK: u32 = 0o060000;
+
+/// What `stat` tells about a file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileStat {
+    /// Filesystem holding it, as `FileEntry::mount`
+    pub device: u64,
+    pub inode: u64,
+    /// File type and permission bits
+    pub mode: u32,
+    pub links: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    /// 512-byte sectors allocated to it
+    pub blocks: u64,
+    pub block_size: u32,
+    /// Seconds since the epoch
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+}
+
+impl FileStat {
+    /// For a file no disk holds, as in /dev and /proc: `mode` is made up,
+    /// and the times are now
+    pub fn synthetic(entry: &FileEntry, mode: u32) -> Self {
+        let now = elinos_common::rtc::unix_time().unwrap_or(0);
+        FileStat {
+            device: entry.mount as u64,
+            inode: entry.inode,
+            mode,
+            links: if entry.is_directory { 2 } else { 1 },
+            size: entry.size as u64,
+            block_size: 512,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            ..FileStat::default()
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+
+    /// The mode as `ls -l` shows it, such as "drwxr-xr-x"
+    pub fn mode_text(&self) -> heapless::String<10> {
+        let mut text = heapless::String::new();
+        let kind = match self.mode & S_IFMT {
+            S_IFDIR => 'd',
+            S_IFLNK => 'l',
+            S_IFCHR => 'c',
+            S_IFBLK => 'b',
+            _ => '-',
+        };
+        let _ = text.push(kind);
+        for shift in [6, 3, 0] {
+            let bits = self.mode >> shift;
+            let _ = text.push(if bits & 4 != 0 { 'r' } else { '-' });
+            let _ = text.push(if bits & 2 != 0 { 'w' } else { '-' });
+            let _ = text.push(if bits & 1 != 0 { 'x' } else { '-' });
+        }
+        text
+    }
+}
+
+/// A name in a directory listing, its size and whether it is a directory
+pub type DirEntry = (heapless::String<64>, usize, bool);
+
+/// A directory listing, as long as the directory is
+pub type DirListing = SlabList<DirEntry>;
+
+/// Entries of the cached root directory
+pub static FILE_ENTRY_CACHE: SlabCache = SlabCache::of::<ListNode<FileEntry>>("file_entry");
+
+/// Entries of directory listings
+pub static DIR_ENTRY_CACHE: SlabCache = SlabCache::of::<ListNode<DirEntry>>("dir_entry");
+
 /// Common filesystem trait that all filesystem implementations must implement
 pub trait FileSystem {
     /// List all files in the filesystem
-    fn list_files(&self) -> FilesystemResult<Vec<(heapless::String<64>, usize), 32>>;
+    fn list_files(&self) -> FilesystemResult<DirListing>;
     
     /// List files in a specific directory path
-    fn list_directory(&self, path: &str) -> FilesystemResult<Vec<(heapless::String<64>, usize, bool), 32>>;
+    fn list_directory(&self, path: &str) -> FilesystemResult<DirListing>;
     
     /// Read the contents of a file into a provided buffer
     /// Returns the number of bytes read
     fn read_file_to_buffer(&self, filename: &str, buffer: &mut [u8]) -> FilesystemResult<usize>;
     
+    /// Read part of a file starting at `offset` into `buffer`
+    /// Returns the number of bytes read (0 at end of file)
+    fn read_at(&self, filename: &str, offset: usize, buffer: &mut [u8]) -> FilesystemResult<usize>;
+    
     /// Get the size of a file
     fn get_file_size(&self, filename: &str) -> FilesystemResult<usize>;
     
-    /// Read the contents of a file
+    /// Read the contents of a file. Files larger than the buffer are cut
+    /// short; use `read_at` to read them in pieces.
     fn read_file(&self, filename: &str) -> FilesystemResult<heapless::Vec<u8, 32768>>;
     
     /// Check if a file exists
@@ -153,6 +265,30 @@ pub trait FileSystem {
 
     /// Remove an empty directory
     fn delete_directory(&mut self, path: &str) -> FilesystemResult<()>;
+
+    /// Give file or directory `from` the name `to`, replacing a file (or
+    /// an empty directory, for a directory) already there
+    fn rename(&mut self, from: &str, to: &str) -> FilesystemResult<()>;
+
+    /// Give the file at `existing` a further name `new`, a hard link
+    fn link(&mut self, existing: &str, new: &str) -> FilesystemResult<()>;
+    
+    /// Create a symbolic link at `path` pointing to `target`
+    fn create_symlink(&mut self, path: &str, target: &str) -> FilesystemResult<FileEntry>;
+
+    /// Target of the symbolic link at `path`
+    fn read_link(&self, path: &str) -> FilesystemResult<PathBuf>;
+
+    /// Mode, owner, size and times of `path`; a symbolic link as the last
+    /// name is followed only with `follow`
+    fn stat(&self, path: &str, follow: bool) -> FilesystemResult<FileStat>;
+
+    /// Set the access and modification times of `path` to those given, in
+    /// seconds since the epoch, leaving a None one as it is
+    fn set_times(&mut self, path: &str, atime: Option<u64>, mtime: Option<u64>) -> FilesystemResult<()>;
+
+    /// Record that `path` was read, in its access time
+    fn mark_accessed(&mut self, path: &str) -> FilesystemResult<()>;
     
     /// Truncate or extend a file to a new size.
     /// If new_size > current_size, the file should be zero-extended.
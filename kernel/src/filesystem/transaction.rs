@@ -0,0 +1,224 @@
+//! Replacing several files as one
+//!
+//! A `Transaction` gathers new contents for a set of files, such as the
+//! network settings, the hostname and the services list, or the files of
+//! an applet, and `commit` replaces all of them or none:
+//!
+//! 1. Each file's new contents are written to `<path>.txn-new`, beside it
+//!    so the rename stays on one filesystem. If one cannot be written, the
+//!    staged files are deleted and nothing else has changed.
+//! 2. The block cache is flushed, so the staged data is on disk before a
+//!    name points at it.
+//! 3. Each file that exists is renamed to `<path>.txn-old` and its staged
+//!    copy renamed into its place. If a rename fails, the files already
+//!    replaced get their `.txn-old` copies back, and those that did not
+//!    exist before are deleted.
+//! 4. The `.txn-old` copies are deleted and the cache flushed again.
+//!
+//! Each file is missing only between its own two renames; a crash there
+//! leaves it as `<path>.txn-old`.
+
+use heapless::{String, Vec};
+
+use super::traits::FileSystem;
+use super::{append, cache, FilesystemError, FilesystemResult, FILESYSTEM};
+
+/// Most files one transaction replaces
+pub const MAX_FILES: usize = 16;
+
+/// Longest path a transaction takes, its suffix included
+const MAX_PATH_LEN: usize = 256;
+
+const STAGED_SUFFIX: &str = ".txn-new";
+const BACKUP_SUFFIX: &str = ".txn-old";
+
+/// Bytes copied from a source file at a time
+const COPY_CHUNK: usize = 512;
+
+type Path = String<MAX_PATH_LEN>;
+
+/// Where a file's new contents come from
+enum Source<'a> {
+    Bytes(&'a [u8]),
+    /// Another file, copied when staging
+    File(Path),
+}
+
+/// One file to replace
+struct Update<'a> {
+    path: Path,
+    source: Source<'a>,
+}
+
+/// A set of files to replace together
+pub struct Transaction<'a> {
+    updates: Vec<Update<'a>, MAX_FILES>,
+}
+
+/// `path` with `suffix` added
+fn with_suffix(path: &str, suffix: &str) -> FilesystemResult<Path> {
+    let mut out = Path::new();
+    out.push_str(path).and_then(|_| out.push_str(suffix)).map_err(|_| FilesystemError::FilenameTooLong)?;
+    Ok(out)
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new() -> Self {
+        Transaction { updates: Vec::new() }
+    }
+
+    fn add(&mut self, path: &str, source: Source<'a>) -> FilesystemResult<()> {
+        if super::is_pseudo_path(path) {
+            return Err(FilesystemError::ReadOnly);
+        }
+        // Leave room for the suffixes
+        with_suffix(path, STAGED_SUFFIX)?;
+        if self.updates.iter().any(|update| update.path == path) {
+            return Err(FilesystemError::FileAlreadyExists);
+        }
+        let path = Path::try_from(path).map_err(|_| FilesystemError::FilenameTooLong)?;
+        self.updates.push(Update { path, source })
+            .map_err(|_| FilesystemError::Other(String::try_from("Too many files in one transaction").unwrap_or_default()))
+    }
+
+    /// Replace `path` with `data`
+    pub fn write(&mut self, path: &str, data: &'a [u8]) -> FilesystemResult<()> {
+        self.add(path, Source::Bytes(data))
+    }
+
+    /// Replace `path` with a copy of file `from`
+    pub fn copy(&mut self, path: &str, from: &str) -> FilesystemResult<()> {
+        let from = Path::try_from(from).map_err(|_| FilesystemError::FilenameTooLong)?;
+        self.add(path, Source::File(from))
+    }
+
+    /// Files the transaction replaces
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    /// Replace every file, or leave them all as they were
+    pub fn commit(self) -> FilesystemResult<()> {
+        for update in &self.updates {
+            append::flush_file(&update.path)?;
+            if let Source::File(from) = &update.source {
+                append::flush_file(from)?;
+            }
+        }
+
+        // 1. Stage
+        for (index, update) in self.updates.iter().enumerate() {
+            if let Err(e) = stage(update) {
+                discard_staged(&self.updates[..=index]);
+                return Err(e);
+            }
+        }
+
+        // 2. Staged data reaches the disk before any name changes
+        if let Err(e) = cache::flush() {
+            discard_staged(&self.updates);
+            return Err(e);
+        }
+
+        // 3. Swap in; `replaced[i]` says whether file i existed before
+        let mut replaced: Vec<bool, MAX_FILES> = Vec::new();
+        for (index, update) in self.updates.iter().enumerate() {
+            match swap_in(&update.path) {
+                Ok(existed) => {
+                    let _ = replaced.push(existed);
+                }
+                Err(e) => {
+                    roll_back(&self.updates[..index], &replaced);
+                    discard_staged(&self.updates[index..]);
+                    return Err(e);
+                }
+            }
+        }
+
+        // 4. The old contents are no longer needed
+        let mut fs = FILESYSTEM.lock();
+        for (update, &existed) in self.updates.iter().zip(&replaced) {
+            if existed {
+                let _ = with_suffix(&update.path, BACKUP_SUFFIX).and_then(|backup| fs.delete_file(&backup));
+            }
+        }
+        drop(fs);
+        cache::flush()
+    }
+}
+
+/// Write the new contents of `update` to its staged file
+fn stage(update: &Update) -> FilesystemResult<()> {
+    let staged = with_suffix(&update.path, STAGED_SUFFIX)?;
+    let mut fs = FILESYSTEM.lock();
+    if fs.file_exists(&staged) {
+        // Left by a transaction that did not finish
+        fs.delete_file(&staged)?;
+    }
+    let entry = fs.create_file(&staged)?;
+    match &update.source {
+        Source::Bytes(data) => {
+            fs.write_file(&entry, 0, data)?;
+        }
+        Source::File(from) => {
+            let mut chunk = [0u8; COPY_CHUNK];
+            let mut offset = 0;
+            loop {
+                let read = fs.read_at(from, offset, &mut chunk)?;
+                if read == 0 {
+                    break;
+                }
+                fs.write_file(&entry, offset as u64, &chunk[..read])?;
+                offset += read;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Move `path` aside, if it exists, and its staged file into its place.
+/// Returns whether it existed.
+fn swap_in(path: &str) -> FilesystemResult<bool> {
+    let staged = with_suffix(path, STAGED_SUFFIX)?;
+    let backup = with_suffix(path, BACKUP_SUFFIX)?;
+    let mut fs = FILESYSTEM.lock();
+    let existed = fs.file_exists(path);
+    if existed {
+        // rename would move a directory aside as readily as a file
+        if fs.get_file_entry(path)?.is_directory {
+            return Err(FilesystemError::IsADirectory);
+        }
+        fs.rename(path, &backup)?;
+    }
+    if let Err(e) = fs.rename(&staged, path) {
+        if existed {
+            let _ = fs.rename(&backup, path);
+        }
+        return Err(e);
+    }
+    Ok(existed)
+}
+
+/// Undo `swap_in` for `updates`, which existed before as `existed` says
+fn roll_back(updates: &[Update], existed: &[bool]) {
+    let mut fs = FILESYSTEM.lock();
+    for (update, &existed) in updates.iter().zip(existed) {
+        if existed {
+            let _ = with_suffix(&update.path, BACKUP_SUFFIX).and_then(|backup| fs.rename(&backup, &update.path));
+        } else {
+            let _ = fs.delete_file(&update.path);
+        }
+    }
+}
+
+/// Delete the staged files of `updates` that were written
+fn discard_staged(updates: &[Update]) {
+    let mut fs = FILESYSTEM.lock();
+    for update in updates {
+        if let Ok(staged) = with_suffix(&update.path, STAGED_SUFFIX) {
+            if fs.file_exists(&staged) {
+                let _ = fs.delete_file(&staged);
+            }
+        }
+    }
+}
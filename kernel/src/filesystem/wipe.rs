@@ -0,0 +1,121 @@
+//! Overwriting data so it cannot be read back after deletion
+//!
+//! Deleting a file only frees its blocks; the bytes stay on the card until
+//! something else lands there. `shred` overwrites a file's blocks before
+//! deleting it and `wipefree` overwrites every free block of a filesystem.
+//! Each pass writes one pattern over all the sectors and is written out to
+//! the device before the next begins. Afterwards the sectors are discarded
+//! where the device supports it, so flash can erase them as well.
+//!
+//! SD cards remap worn blocks on their own, and an overwrite cannot reach
+//! copies the card has already moved aside; only discard gives the card
+//! the chance to erase those.
+
+use elinos_common::blockcache::SECTOR_SIZE;
+use elinos_common::{rtc, timer};
+
+use super::{cache, FilesystemResult};
+
+/// Passes when the command does not say
+pub const DEFAULT_PASSES: usize = 3;
+
+/// Most passes a wipe may make
+pub const MAX_PASSES: usize = 32;
+
+/// Sectors written at once
+const CHUNK_SECTORS: usize = 8;
+
+/// What one pass writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Byte(u8),
+    Random,
+}
+
+impl Pattern {
+    /// Pattern of pass `pass` of `passes`: alternating bits, with the last
+    /// pass random so nothing regular is left behind
+    pub fn for_pass(pass: usize, passes: usize) -> Self {
+        if pass + 1 == passes {
+            Pattern::Random
+        } else if pass % 2 == 0 {
+            Pattern::Byte(0x55)
+        } else {
+            Pattern::Byte(0xAA)
+        }
+    }
+}
+
+/// What a wipe did
+#[derive(Debug, Clone, Copy)]
+pub struct WipeReport {
+    /// Sectors overwritten by each pass
+    pub sectors: u64,
+    pub passes: usize,
+    /// Whether the device was told to discard the sectors afterwards
+    pub discarded: bool,
+}
+
+/// Pseudo-random bytes for the random pass. They only have to differ from
+/// what was there, so xorshift will do.
+struct XorShift(u64);
+
+impl XorShift {
+    fn seeded() -> Self {
+        let seed = timer::ticks() ^ rtc::unix_time().unwrap_or(0).rotate_left(32);
+        XorShift(seed | 1)
+    }
+
+    fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(8) {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            chunk.copy_from_slice(&self.0.to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+/// Overwrite the sectors of the selected volume that `for_each_run` hands
+/// out as (first sector, sectors), making `passes` passes, then discard
+/// them where the device can. `for_each_run` is called once per pass and
+/// once more for the discard, and must give the same runs each time; the
+/// caller holds the filesystem lock, so nothing can change them.
+pub fn overwrite(
+    passes: usize,
+    mut for_each_run: impl FnMut(&mut dyn FnMut(u64, u64) -> FilesystemResult<()>) -> FilesystemResult<()>,
+) -> FilesystemResult<WipeReport> {
+    let mut random = XorShift::seeded();
+    let mut buffer = [0u8; CHUNK_SECTORS * SECTOR_SIZE];
+    let mut sectors = 0;
+
+    for pass in 0..passes {
+        let pattern = Pattern::for_pass(pass, passes);
+        sectors = 0;
+        for_each_run(&mut |start, count| {
+            let mut done = 0;
+            while done < count {
+                let chunk = (count - done).min(CHUNK_SECTORS as u64) as usize;
+                let bytes = &mut buffer[..chunk * SECTOR_SIZE];
+                match pattern {
+                    Pattern::Byte(byte) => bytes.fill(byte),
+                    Pattern::Random => random.fill(bytes),
+                }
+                cache::write_blocks(start + done, bytes)?;
+                done += chunk as u64;
+            }
+            sectors += count;
+            Ok(())
+        })?;
+        // The next pass must not just replace this one in the cache
+        cache::flush()?;
+    }
+
+    let mut discarded = sectors > 0;
+    for_each_run(&mut |start, count| {
+        discarded &= cache::discard_blocks(start, count)?;
+        Ok(())
+    })?;
+
+    Ok(WipeReport { sectors, passes, discarded })
+}
@@ -0,0 +1,290 @@
+//! ANSI escape sequences for the framebuffer console
+//!
+//! The console understands the subset of VT100/ANSI that line-based output
+//! uses: cursor movement and positioning, saving and restoring the cursor,
+//! erasing the line or the screen, and SGR attributes for the eight colours
+//! and their bright forms, bold and reverse video. `Parser` turns the
+//! characters written to the console into `Action`s one at a time, so a
+//! sequence split across writes still works; anything else introduced by
+//! ESC is read to its end and dropped rather than drawn.
+
+/// Parameters a control sequence may have; more are ignored
+const MAX_PARAMS: usize = 8;
+
+/// Largest parameter value; larger values are clamped
+const MAX_PARAM: u16 = 9999;
+
+/// What a character written to the console asks for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Draw the character or act on the control character
+    Print(char),
+    CursorUp(u32),
+    CursorDown(u32),
+    CursorForward(u32),
+    CursorBack(u32),
+    /// Move to a cell, counted from zero
+    CursorTo { row: u32, col: u32 },
+    CursorColumn(u32),
+    SaveCursor,
+    RestoreCursor,
+    EraseDisplay(Erase),
+    EraseLine(Erase),
+    /// Select graphic rendition
+    Sgr(Params),
+}
+
+/// Which part of the line or screen to erase
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Erase {
+    /// From the cursor to the end
+    ToEnd,
+    /// From the start to the cursor
+    ToCursor,
+    All,
+}
+
+impl Erase {
+    fn from_param(param: u16) -> Option<Erase> {
+        match param {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToCursor),
+            2 | 3 => Some(Erase::All),
+            _ => None,
+        }
+    }
+}
+
+/// The numeric parameters of a control sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    values: [u16; MAX_PARAMS],
+    count: usize,
+}
+
+impl Params {
+    const fn new() -> Self {
+        Params { values: [0; MAX_PARAMS], count: 0 }
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.count]
+    }
+
+    /// Parameter `index`, or `default` if it is missing or zero
+    fn get_or(&self, index: usize, default: u16) -> u16 {
+        match self.as_slice().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    /// After ESC
+    Escape,
+    /// After ESC [
+    Csi,
+    /// In a control sequence with a private marker such as `?`
+    CsiIgnore,
+    /// After ESC and an intermediate byte, e.g. ESC ( B
+    EscapeIgnore,
+}
+
+/// Splits console output into characters and escape sequences
+pub struct Parser {
+    state: State,
+    params: Params,
+    /// Whether a digit of the current parameter has been seen
+    in_param: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser { state: State::Ground, params: Params::new(), in_param: false }
+    }
+
+    /// Feed one character; returns what it completes, if anything
+    pub fn advance(&mut self, ch: char) -> Option<Action> {
+        match self.state {
+            State::Ground => {
+                if ch == '\x1b' {
+                    self.state = State::Escape;
+                    None
+                } else {
+                    Some(Action::Print(ch))
+                }
+            }
+            State::Escape => {
+                self.state = State::Ground;
+                match ch {
+                    '[' => {
+                        self.state = State::Csi;
+                        self.params = Params::new();
+                        self.in_param = false;
+                        None
+                    }
+                    '7' => Some(Action::SaveCursor),
+                    '8' => Some(Action::RestoreCursor),
+                    ' '..='/' => {
+                        self.state = State::EscapeIgnore;
+                        None
+                    }
+                    _ => None,
+                }
+            }
+            State::EscapeIgnore => {
+                if !(' '..='/').contains(&ch) {
+                    self.state = State::Ground;
+                }
+                None
+            }
+            State::Csi => self.csi(ch),
+            State::CsiIgnore => {
+                if ('@'..='~').contains(&ch) {
+                    self.state = State::Ground;
+                }
+                None
+            }
+        }
+    }
+
+    fn csi(&mut self, ch: char) -> Option<Action> {
+        match ch {
+            '0'..='9' => {
+                if !self.in_param {
+                    self.start_param();
+                }
+                if let Some(value) = self.params.count.checked_sub(1).and_then(|last| self.params.values.get_mut(last)) {
+                    *value = (*value * 10 + ch as u16 - '0' as u16).min(MAX_PARAM);
+                }
+                None
+            }
+            ';' => {
+                if !self.in_param {
+                    self.start_param();
+                }
+                self.in_param = false;
+                None
+            }
+            '<'..='?' => {
+                // Private sequences such as showing the cursor
+                self.state = State::CsiIgnore;
+                None
+            }
+            '@'..='~' => {
+                self.state = State::Ground;
+                self.finish(ch)
+            }
+            // Intermediate bytes and anything stray end the sequence unread
+            _ => {
+                self.state = if (' '..='/').contains(&ch) { State::CsiIgnore } else { State::Ground };
+                None
+            }
+        }
+    }
+
+    fn start_param(&mut self) {
+        if self.params.count < MAX_PARAMS {
+            self.params.values[self.params.count] = 0;
+            self.params.count += 1;
+        }
+        self.in_param = true;
+    }
+
+    fn finish(&mut self, final_byte: char) -> Option<Action> {
+        let params = self.params;
+        let count = u32::from(params.get_or(0, 1));
+        let action = match final_byte {
+            'A' => Action::CursorUp(count),
+            'B' => Action::CursorDown(count),
+            'C' => Action::CursorForward(count),
+            'D' => Action::CursorBack(count),
+            'G' => Action::CursorColumn(count - 1),
+            'H' | 'f' => Action::CursorTo {
+                row: u32::from(params.get_or(0, 1)) - 1,
+                col: u32::from(params.get_or(1, 1)) - 1,
+            },
+            'J' => Action::EraseDisplay(Erase::from_param(params.as_slice().first().copied().unwrap_or(0))?),
+            'K' => Action::EraseLine(Erase::from_param(params.as_slice().first().copied().unwrap_or(0))?),
+            'm' => Action::Sgr(params),
+            's' => Action::SaveCursor,
+            'u' => Action::RestoreCursor,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// The standard colours, then their bright forms, as the VGA palette has them
+const PALETTE: [u32; 16] = [
+    0x00000000, 0x00AA0000, 0x0000AA00, 0x00AA5500, 0x000000AA, 0x00AA00AA, 0x0000AAAA, 0x00AAAAAA,
+    0x00555555, 0x00FF5555, 0x0055FF55, 0x00FFFF55, 0x005555FF, 0x00FF55FF, 0x0055FFFF, 0x00FFFFFF,
+];
+
+/// Colours and attributes set by SGR
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Attributes {
+    /// Palette index of the foreground, or None for the console's default
+    foreground: Option<u8>,
+    background: Option<u8>,
+    bold: bool,
+    reverse: bool,
+}
+
+impl Attributes {
+    /// Apply the parameters of an SGR sequence
+    pub fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Attributes::default();
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Attributes::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                30..=37 => self.foreground = Some((param - 30) as u8),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some((param - 40) as u8),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some((param - 90 + 8) as u8),
+                100..=107 => self.background = Some((param - 100 + 8) as u8),
+                // 256-colour and direct colours are not drawn, but their
+                // arguments must not be taken for attributes
+                38 | 48 => match params.next() {
+                    Some(5) => {
+                        params.next();
+                    }
+                    Some(2) => {
+                        params.next();
+                        params.next();
+                        params.next();
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Foreground and background to draw with, given the console's defaults
+    pub fn colors(&self, default_fg: u32, default_bg: u32) -> (u32, u32) {
+        let mut foreground = match self.foreground {
+            // Bold brightens the standard colours, as on the VGA console
+            Some(index) if self.bold && index < 8 => PALETTE[index as usize + 8],
+            Some(index) => PALETTE[index as usize],
+            None => default_fg,
+        };
+        let mut background = self.background.map_or(default_bg, |index| PALETTE[index as usize]);
+        if self.reverse {
+            core::mem::swap(&mut foreground, &mut background);
+        }
+        (foreground, background)
+    }
+}
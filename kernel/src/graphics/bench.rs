@@ -0,0 +1,114 @@
+//! Graphics benchmark
+//!
+//! Measures the three costs of getting pixels on the screen: filling the
+//! framebuffer, rendering glyphs into it, and flushing it to the VirtIO GPU.
+//! The first two are CPU work on the framebuffer in RAM; a flush transfers
+//! the whole frame to the host and then flushes the resource, waiting for
+//! the device each time. The screen is left cleared.
+
+use core::fmt;
+use elinos_common::timer;
+
+use super::font::GlyphCache;
+use super::{FRAMEBUFFER, VIRTIO_GPU_ENABLED};
+
+/// Results of one `run`
+#[derive(Debug, Clone, Copy)]
+pub struct BenchReport {
+    pub frames: u32,
+    /// Pixels written by the fills and the time they took
+    pub fill_pixels: u64,
+    pub fill_ns: u64,
+    /// Glyphs rendered and the time they took
+    pub glyphs: u64,
+    pub glyph_ns: u64,
+    /// Flush times, if there is a VirtIO GPU to flush to
+    pub flush: Option<FlushTimes>,
+}
+
+/// Time of the flushes of one `run`
+#[derive(Debug, Clone, Copy)]
+pub struct FlushTimes {
+    pub frames: u32,
+    pub min_ns: u64,
+    pub max_ns: u64,
+    pub total_ns: u64,
+}
+
+impl FlushTimes {
+    pub fn average_ns(&self) -> u64 {
+        self.total_ns / u64::from(self.frames).max(1)
+    }
+}
+
+/// `count` per second, given it took `ns`
+pub fn per_second(count: u64, ns: u64) -> u64 {
+    (count as u128 * 1_000_000_000 / u128::from(ns.max(1))) as u64
+}
+
+/// Nanoseconds shown as milliseconds with three decimals
+pub struct Millis(pub u64);
+
+impl fmt::Display for Millis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:03} ms", self.0 / 1_000_000, self.0 / 1000 % 1000)
+    }
+}
+
+/// Fill, render a screen of glyphs into and flush `frames` frames each
+pub fn run(frames: u32) -> Result<BenchReport, &'static str> {
+    let fb = unsafe { (*core::ptr::addr_of_mut!(FRAMEBUFFER)).as_mut() }.ok_or("Graphics not initialized")?;
+    let (width, height) = fb.get_dimensions();
+    let frames = frames.max(1);
+
+    let start = timer::ticks();
+    for frame in 0..frames {
+        fb.clear(pattern_color(frame));
+    }
+    let fill_ns = timer::ticks_to_ns(timer::ticks() - start);
+    let fill_pixels = u64::from(width) * u64::from(height) * u64::from(frames);
+
+    // In the console's font, through a cache of its own decoded up front
+    let mut glyphs = GlyphCache::new(super::console_font());
+    let font = glyphs.font();
+    for ch in ' '..='~' {
+        glyphs.glyph(ch);
+    }
+    let (cols, rows) = (width / font.width, height / font.height);
+    let start = timer::ticks();
+    for frame in 0..frames {
+        for row in 0..rows {
+            for col in 0..cols {
+                let ch = (b'!' + ((frame + row + col) % 94) as u8) as char;
+                fb.draw_glyph(glyphs.glyph(ch), font.width, col * font.width, row * font.height, 0x00FFFFFF, None);
+            }
+        }
+    }
+    let glyph_ns = timer::ticks_to_ns(timer::ticks() - start);
+    let glyphs = u64::from(cols) * u64::from(rows) * u64::from(frames);
+
+    let flush = if unsafe { VIRTIO_GPU_ENABLED } {
+        let mut times = FlushTimes { frames, min_ns: u64::MAX, max_ns: 0, total_ns: 0 };
+        for frame in 0..frames {
+            fb.clear(pattern_color(frame));
+            let start = timer::ticks();
+            crate::virtio::flush_display().map_err(|_| "Failed to flush to display")?;
+            let ns = timer::ticks_to_ns(timer::ticks() - start);
+            times.min_ns = times.min_ns.min(ns);
+            times.max_ns = times.max_ns.max(ns);
+            times.total_ns += ns;
+        }
+        Some(times)
+    } else {
+        None
+    };
+
+    super::clear_console()?;
+    Ok(BenchReport { frames, fill_pixels, fill_ns, glyphs, glyph_ns, flush })
+}
+
+/// A different colour for each frame, so every flush changes the screen
+fn pattern_color(frame: u32) -> u32 {
+    const COLORS: [u32; 4] = [0x00202080, 0x00208020, 0x00802020, 0x00404040];
+    COLORS[frame as usize % COLORS.len()]
+}
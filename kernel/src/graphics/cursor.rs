@@ -0,0 +1,102 @@
+//! Pointer cursor
+//!
+//! The cursor never lives in the framebuffer. It is drawn over it just
+//! before a frame goes to the VirtIO GPU and the pixels under it are put
+//! back as soon as the flush is done, so the text console, /dev/fb0 and
+//! screenshots only ever see what they drew themselves. It shows once a
+//! pointing device has reported a position. The text selection is shown
+//! the same way, and so is the text console's cursor, an underline in the
+//! cell where the next character goes.
+
+use super::{selection, ConsoleGeometry, SimpleFramebuffer, FRAMEBUFFER};
+
+const CURSOR_WIDTH: u32 = 12;
+const CURSOR_HEIGHT: u32 = 16;
+
+/// Arrow outline and fill, one row per line, leftmost pixel in bit 11
+const OUTLINE: [u16; CURSOR_HEIGHT as usize] = [
+    0x800, 0xC00, 0xA00, 0x900, 0x880, 0x840, 0x820, 0x810,
+    0x808, 0x804, 0x83E, 0x920, 0xA90, 0xC90, 0x048, 0x078,
+];
+const FILL: [u16; CURSOR_HEIGHT as usize] = [
+    0x000, 0x000, 0x400, 0x600, 0x700, 0x780, 0x7C0, 0x7E0,
+    0x7F0, 0x7F8, 0x7C0, 0x6C0, 0x460, 0x060, 0x030, 0x000,
+];
+
+const OUTLINE_COLOR: u32 = 0x00000000;
+const FILL_COLOR: u32 = 0x00FFFFFF;
+
+/// Pixels the cursor covers, to be put back after the flush
+struct Saved {
+    x: u32,
+    y: u32,
+    pixels: [u32; (CURSOR_WIDTH * CURSOR_HEIGHT) as usize],
+}
+
+/// Text cursor height, in pixel rows at the bottom of the cell
+const TEXT_CURSOR_HEIGHT: u32 = 2;
+
+/// Run `flush` with the selection and the cursors drawn over the framebuffer
+pub fn with_cursor<T>(flush: impl FnOnce() -> T) -> T {
+    let Some(fb) = (unsafe { (*core::ptr::addr_of_mut!(FRAMEBUFFER)).as_mut() }) else {
+        return flush();
+    };
+    let selected = selection::range().zip(super::console_geometry());
+    if let Some((range, geometry)) = selected {
+        selection::invert(fb, geometry, range);
+    }
+    let text_cursor = super::text_cursor();
+    if let Some((geometry, cell)) = text_cursor {
+        underline(fb, geometry, cell);
+    }
+    let saved = crate::input::pointer().map(|pointer| draw(fb, pointer.x, pointer.y));
+    let result = flush();
+    if let Some(saved) = &saved {
+        restore(fb, saved);
+    }
+    if let Some((geometry, cell)) = text_cursor {
+        underline(fb, geometry, cell);
+    }
+    if let Some((range, geometry)) = selected {
+        selection::invert(fb, geometry, range);
+    }
+    result
+}
+
+/// Invert the bottom rows of `cell`; doing it again puts them back
+fn underline(fb: &mut SimpleFramebuffer, geometry: ConsoleGeometry, cell: selection::Cell) {
+    let bottom = (cell.row + 1) * geometry.cell_height;
+    for y in bottom - TEXT_CURSOR_HEIGHT.min(geometry.cell_height)..bottom {
+        for x in cell.col * geometry.cell_width..(cell.col + 1) * geometry.cell_width {
+            if let Some(pixel) = fb.get_pixel(x, y) {
+                let _ = fb.set_pixel(x, y, pixel ^ 0x00FFFFFF);
+            }
+        }
+    }
+}
+
+fn draw(fb: &mut SimpleFramebuffer, x: u32, y: u32) -> Saved {
+    let mut saved = Saved { x, y, pixels: [0; (CURSOR_WIDTH * CURSOR_HEIGHT) as usize] };
+    for row in 0..CURSOR_HEIGHT {
+        for col in 0..CURSOR_WIDTH {
+            let (px, py) = (x + col, y + row);
+            let Some(pixel) = fb.get_pixel(px, py) else { continue };
+            saved.pixels[(row * CURSOR_WIDTH + col) as usize] = pixel;
+            let bit = 1 << (CURSOR_WIDTH - 1 - col);
+            if OUTLINE[row as usize] & bit != 0 {
+                let _ = fb.set_pixel(px, py, OUTLINE_COLOR);
+            } else if FILL[row as usize] & bit != 0 {
+                let _ = fb.set_pixel(px, py, FILL_COLOR);
+            }
+        }
+    }
+    saved
+}
+
+fn restore(fb: &mut SimpleFramebuffer, saved: &Saved) {
+    for row in 0..CURSOR_HEIGHT {
+        for col in 0..CURSOR_WIDTH {
+            let _ = fb.set_pixel(saved.x + col, saved.y + row, saved.pixels[(row * CURSOR_WIDTH + col) as usize]);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Drawing primitives beyond rectangles
+//!
+//! Lines, circles, pattern fills, copies within the framebuffer and images
+//! with alpha, for the graphics syscalls. Coordinates may lie off the
+//! screen: everything is clipped to it, so a shape partly outside is drawn
+//! in part rather than refused. Nothing here presents; callers draw a
+//! frame and then present it once.
+
+use super::{Rect, SimpleFramebuffer};
+
+/// An 8x8 one-bit tile, one byte per row, most significant bit leftmost
+/// (the layout of the font); set bits take the foreground color
+pub type Pattern = [u8; 8];
+
+/// A rectangle that may reach off the screen, as programs pass it
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Area {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Area {
+    /// Whether it is within `COORD_LIMIT`
+    pub fn in_limits(&self) -> bool {
+        in_limits(&[self.x.into(), self.y.into(), self.width.into(), self.height.into()])
+    }
+}
+
+/// Every pixel in the foreground color
+pub const SOLID: Pattern = [0xFF; 8];
+
+/// Largest coordinate, width or radius a shape may have, far off any
+/// screen; it keeps the arithmetic from overflowing
+pub const COORD_LIMIT: i32 = 1 << 16;
+
+/// Whether `values` are all within `COORD_LIMIT` of the origin
+pub fn in_limits(values: &[i64]) -> bool {
+    values.iter().all(|value| value.abs() <= i64::from(COORD_LIMIT))
+}
+
+impl SimpleFramebuffer {
+    /// The part of `area` on the screen, if any
+    fn clip(&self, area: Area) -> Option<Rect> {
+        let x0 = i64::from(area.x).max(0);
+        let y0 = i64::from(area.y).max(0);
+        let x1 = (i64::from(area.x) + i64::from(area.width)).min(i64::from(self.width));
+        let y1 = (i64::from(area.y) + i64::from(area.height)).min(i64::from(self.height));
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        Some(Rect { x: x0 as u32, y: y0 as u32, width: (x1 - x0) as u32, height: (y1 - y0) as u32 })
+    }
+
+    /// Set the pixel at (x, y) if it is on the screen, without noting
+    /// damage; the caller damages what it drew as a whole
+    fn plot(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            unsafe {
+                *self.buffer.add(y as usize * self.width as usize + x as usize) = color;
+            }
+        }
+    }
+
+    /// Fill the span of row `y` from `x0` to `x1` inclusive, clipped
+    fn span(&mut self, x0: i32, x1: i32, y: i32, color: u32) {
+        if let Some(rect) = self.clip(Area { x: x0, y, width: (x1 - x0 + 1).max(0) as u32, height: 1 }) {
+            let start = (rect.y * self.width + rect.x) as usize;
+            unsafe {
+                core::slice::from_raw_parts_mut(self.buffer.add(start), rect.width as usize).fill(color);
+            }
+        }
+    }
+
+    /// Damage the bounding box of a shape given in signed coordinates
+    fn damage_box(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let width = (i64::from(x1) - i64::from(x0) + 1).clamp(0, u32::MAX as i64) as u32;
+        let height = (i64::from(y1) - i64::from(y0) + 1).clamp(0, u32::MAX as i64) as u32;
+        if let Some(rect) = self.clip(Area { x: x0, y: y0, width, height }) {
+            self.damage(rect);
+        }
+    }
+
+    /// Draw a one-pixel line from (x0, y0) to (x1, y1), both ends included,
+    /// with Bresenham's algorithm
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.plot(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+        self.damage_box(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+    }
+
+    /// Draw the circle of `radius` around (cx, cy) with the midpoint
+    /// algorithm, as an outline or filled
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, color: u32, filled: bool) {
+        let radius = radius.min(COORD_LIMIT as u32) as i32;
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+        while x >= y {
+            if filled {
+                self.span(cx - x, cx + x, cy + y, color);
+                self.span(cx - x, cx + x, cy - y, color);
+                self.span(cx - y, cx + y, cy + x, color);
+                self.span(cx - y, cx + y, cy - x, color);
+            } else {
+                for (px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                    self.plot(cx + px, cy + py, color);
+                }
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+        self.damage_box(cx - radius, cy - radius, cx + radius, cy + radius);
+    }
+
+    /// Fill a rectangle with `pattern`, tiled from the screen's top left
+    /// corner so neighbouring fills line up
+    pub fn fill_pattern(&mut self, area: Area, pattern: &Pattern, foreground: u32, background: u32) {
+        let Some(rect) = self.clip(area) else { return };
+        for row in rect.y..rect.y + rect.height {
+            let bits = pattern[(row % 8) as usize];
+            let line = unsafe {
+                core::slice::from_raw_parts_mut(self.buffer.add((row * self.width + rect.x) as usize), rect.width as usize)
+            };
+            for (col, pixel) in (rect.x..).zip(line.iter_mut()) {
+                *pixel = if bits & (0x80 >> (col % 8)) != 0 { foreground } else { background };
+            }
+        }
+        self.damage(rect);
+    }
+
+    /// Copy the pixels of `src` to `dst` on the screen, scaled to fit with
+    /// the nearest pixel if the sizes differ. Overlapping copies of the
+    /// same size come out as if through a temporary buffer.
+    pub fn blit(&mut self, src: Area, dst: Area) {
+        let Some(src) = self.clip(src) else { return };
+        let Some(visible) = self.clip(dst) else { return };
+        let (dst_x, dst_y, dst_width, dst_height) = (dst.x, dst.y, dst.width, dst.height);
+        // Walk against the direction of the copy so no source pixel is
+        // overwritten before it is read
+        let rows_up = i64::from(dst_y) <= i64::from(src.y);
+        let cols_left = i64::from(dst_x) <= i64::from(src.x);
+        for i in 0..visible.height {
+            let row = if rows_up { visible.y + i } else { visible.y + visible.height - 1 - i };
+            let from_y = src.y + ((i64::from(row) - i64::from(dst_y)) * i64::from(src.height) / i64::from(dst_height)) as u32;
+            for j in 0..visible.width {
+                let col = if cols_left { visible.x + j } else { visible.x + visible.width - 1 - j };
+                let from_x = src.x + ((i64::from(col) - i64::from(dst_x)) * i64::from(src.width) / i64::from(dst_width)) as u32;
+                unsafe {
+                    let pixel = *self.buffer.add((from_y * self.width + from_x) as usize);
+                    *self.buffer.add((row * self.width + col) as usize) = pixel;
+                }
+            }
+        }
+        self.damage(visible);
+    }
+
+    /// Draw a `width` x `height` image of 0xAARRGGBB pixels with its top
+    /// left corner at (x, y). With `blend`, each pixel is mixed with the
+    /// screen by its alpha; without, alpha is ignored and pixels replace
+    /// the screen.
+    pub fn draw_image(&mut self, pixels: &[u32], width: u32, height: u32, x: i32, y: i32, blend: bool) {
+        let Some(visible) = self.clip(Area { x, y, width, height }) else { return };
+        for row in visible.y..visible.y + visible.height {
+            let src_row = (i64::from(row) - i64::from(y)) as usize * width as usize;
+            for col in visible.x..visible.x + visible.width {
+                let Some(&pixel) = pixels.get(src_row + (i64::from(col) - i64::from(x)) as usize) else { return };
+                let target = unsafe { &mut *self.buffer.add((row * self.width + col) as usize) };
+                *target = if blend { blend_pixel(pixel, *target) } else { pixel & 0x00FF_FFFF };
+            }
+        }
+        self.damage(visible);
+    }
+}
+
+/// `over` (0xAARRGGBB) drawn over `under` (0x00RRGGBB)
+fn blend_pixel(over: u32, under: u32) -> u32 {
+    let alpha = over >> 24;
+    match alpha {
+        0 => under,
+        255 => over & 0x00FF_FFFF,
+        _ => {
+            let mix = |shift: u32| {
+                let top = (over >> shift) & 0xFF;
+                let bottom = (under >> shift) & 0xFF;
+                ((top * alpha + bottom * (255 - alpha) + 127) / 255) << shift
+            };
+            mix(16) | mix(8) | mix(0)
+        }
+    }
+}
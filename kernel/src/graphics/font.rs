@@ -0,0 +1,510 @@
+//! Console fonts and the glyph cache
+//!
+//! Fonts are fixed-width bitmaps for the printable ASCII characters, one
+//! row of bits per scanline. Two are built in: the 8x8 font, which packs
+//! 80x60 characters onto a 640x480 screen but is hard to read, and an 8x16
+//! font in the style of the VGA text mode, the console's default, giving
+//! 80x30. The `console.font` tunable switches between them; the console
+//! then works out its rows and columns from the framebuffer size.
+//!
+//! Fonts store their bits in different orders, so glyphs are decoded into
+//! rows with the leftmost pixel in the top bit before they are drawn. A
+//! `GlyphCache` keeps each glyph of one font once decoded, and drawing a
+//! character is then a lookup and a run of row writes.
+
+use crate::sysctl::{Kind, Tunable};
+
+/// First and last character the fonts have
+const FIRST_CHAR: char = ' ';
+const LAST_CHAR: char = '~';
+const GLYPH_COUNT: usize = LAST_CHAR as usize - FIRST_CHAR as usize + 1;
+
+/// Largest glyph size a font may have
+pub const MAX_GLYPH_WIDTH: u32 = 16;
+pub const MAX_GLYPH_HEIGHT: usize = 16;
+
+/// A fixed-width bitmap font
+pub struct Font {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Glyphs from `FIRST_CHAR` to `LAST_CHAR`, `height` rows each, each
+    /// row `width` bits rounded up to whole bytes
+    data: &'static [u8],
+    /// Whether the lowest bit of a byte is the leftmost pixel
+    lsb_left: bool,
+}
+
+impl Font {
+    fn bytes_per_row(&self) -> usize {
+        self.width.div_ceil(8) as usize
+    }
+
+    /// Rows of the glyph for `ch`, leftmost pixel in the top bit, into
+    /// `rows`; false if the font has no glyph for it
+    fn decode(&self, ch: char, rows: &mut [u16; MAX_GLYPH_HEIGHT]) -> bool {
+        if !(FIRST_CHAR..=LAST_CHAR).contains(&ch) {
+            return false;
+        }
+        let row_bytes = self.bytes_per_row();
+        let size = row_bytes * self.height as usize;
+        let start = (ch as usize - FIRST_CHAR as usize) * size;
+        let Some(glyph) = self.data.get(start..start + size) else { return false };
+        for (row, bytes) in rows.iter_mut().zip(glyph.chunks(row_bytes)) {
+            *row = bytes.iter().enumerate().fold(0, |bits, (i, &byte)| {
+                let byte = if self.lsb_left { byte.reverse_bits() } else { byte };
+                bits | (u16::from(byte) << (8 - 8 * i))
+            });
+        }
+        true
+    }
+}
+
+pub static FONT_8X8: Font = Font { name: "8x8", width: 8, height: 8, data: FONT_8X8_DATA, lsb_left: true };
+pub static FONT_8X16: Font = Font { name: "8x16", width: 8, height: 16, data: FONT_8X16_DATA, lsb_left: false };
+
+/// Every built-in font
+pub static FONTS: [&Font; 2] = [&FONT_8X8, &FONT_8X16];
+
+/// Font the console starts with
+pub static DEFAULT_FONT: &Font = &FONT_8X16;
+
+/// The built-in font called `name`
+pub fn by_name(name: &str) -> Option<&'static Font> {
+    FONTS.iter().copied().find(|font| font.name == name)
+}
+
+/// The decoded glyphs of one font, each decoded on first use
+pub struct GlyphCache {
+    font: &'static Font,
+    rows: [[u16; MAX_GLYPH_HEIGHT]; GLYPH_COUNT],
+    decoded: [bool; GLYPH_COUNT],
+}
+
+impl GlyphCache {
+    pub const fn new(font: &'static Font) -> Self {
+        GlyphCache { font, rows: [[0; MAX_GLYPH_HEIGHT]; GLYPH_COUNT], decoded: [false; GLYPH_COUNT] }
+    }
+
+    pub fn font(&self) -> &'static Font {
+        self.font
+    }
+
+    /// The rows of the glyph for `ch`, leftmost pixel in the top bit, or
+    /// those of a space if the font has none
+    pub fn glyph(&mut self, ch: char) -> &[u16] {
+        let ch = if (FIRST_CHAR..=LAST_CHAR).contains(&ch) { ch } else { ' ' };
+        let index = ch as usize - FIRST_CHAR as usize;
+        if !self.decoded[index] {
+            if !self.font.decode(ch, &mut self.rows[index]) {
+                self.rows[index] = [0; MAX_GLYPH_HEIGHT];
+            }
+            self.decoded[index] = true;
+        }
+        &self.rows[index][..self.font.height as usize]
+    }
+}
+
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "console.font",
+        description: "Font of the framebuffer console; changing it clears the console",
+        kind: Kind::Choice { choices: &["8x8", "8x16"], get: get_font, set: set_font },
+    },
+];
+
+fn get_font() -> &'static str {
+    super::console_font().name
+}
+
+fn set_font(name: &'static str) -> Result<(), &'static str> {
+    super::set_console_font(by_name(name).ok_or("No such font")?)
+}
+
+// 8x8 font, lowest bit leftmost
+const FONT_8X8_DATA: &[u8] = &[
+    // Space (32)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ! (33)
+    0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00,
+    // " (34)
+    0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // # (35)
+    0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00,
+    // $ (36)
+    0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00,
+    // % (37)
+    0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00,
+    // & (38)
+    0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00,
+    // ' (39)
+    0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ( (40)
+    0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00,
+    // ) (41)
+    0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00,
+    // * (42)
+    0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00,
+    // + (43)
+    0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00,
+    // , (44)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x06, 0x00,
+    // - (45)
+    0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00,
+    // . (46)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00,
+    // / (47)
+    0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00,
+    // 0 (48)
+    0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00,
+    // 1 (49)
+    0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00,
+    // 2 (50)
+    0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00,
+    // 3 (51)
+    0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00,
+    // 4 (52)
+    0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00,
+    // 5 (53)
+    0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00,
+    // 6 (54)
+    0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00,
+    // 7 (55)
+    0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00,
+    // 8 (56)
+    0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00,
+    // 9 (57)
+    0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00,
+    // : (58)
+    0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00,
+    // ; (59)
+    0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x06, 0x00,
+    // < (60)
+    0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00,
+    // = (61)
+    0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00,
+    // > (62)
+    0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00,
+    // ? (63)
+    0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00,
+    // @ (64)
+    0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00,
+    // A (65)
+    0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00,
+    // B (66)
+    0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00,
+    // C (67)
+    0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00,
+    // D (68)
+    0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00,
+    // E (69)
+    0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00,
+    // F (70)
+    0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00,
+    // G (71)
+    0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00,
+    // H (72)
+    0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00,
+    // I (73)
+    0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
+    // J (74)
+    0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00,
+    // K (75)
+    0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00,
+    // L (76)
+    0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00,
+    // M (77)
+    0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00,
+    // N (78)
+    0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00,
+    // O (79)
+    0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00,
+    // P (80)
+    0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00,
+    // Q (81)
+    0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00,
+    // R (82)
+    0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00,
+    // S (83)
+    0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00,
+    // T (84)
+    0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
+    // U (85)
+    0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00,
+    // V (86)
+    0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00,
+    // W (87)
+    0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00,
+    // X (88)
+    0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00,
+    // Y (89)
+    0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00,
+    // Z (90)
+    0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00,
+    // [ (91)
+    0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00,
+    // \ (92)
+    0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00,
+    // ] (93)
+    0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00,
+    // ^ (94)
+    0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00,
+    // _ (95)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF,
+    // ` (96)
+    0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // a (97)
+    0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00,
+    // b (98)
+    0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00,
+    // c (99)
+    0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00,
+    // d (100)
+    0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6E, 0x00,
+    // e (101)
+    0x00, 0x00, 0x1E, 0x33, 0x3f, 0x03, 0x1E, 0x00,
+    // f (102)
+    0x1C, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0F, 0x00,
+    // g (103)
+    0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F,
+    // h (104)
+    0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00,
+    // i (105)
+    0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
+    // j (106)
+    0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E,
+    // k (107)
+    0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00,
+    // l (108)
+    0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
+    // m (109)
+    0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00,
+    // n (110)
+    0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00,
+    // o (111)
+    0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00,
+    // p (112)
+    0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F,
+    // q (113)
+    0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78,
+    // r (114)
+    0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00,
+    // s (115)
+    0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00,
+    // t (116)
+    0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00,
+    // u (117)
+    0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00,
+    // v (118)
+    0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00,
+    // w (119)
+    0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00,
+    // x (120)
+    0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00,
+    // y (121)
+    0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F,
+    // z (122)
+    0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00,
+    // { (123)
+    0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00,
+    // | (124)
+    0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00,
+    // } (125)
+    0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00,
+    // ~ (126)
+    0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// 8x16 font after the VGA text mode font, highest bit leftmost
+const FONT_8X16_DATA: &[u8] = &[
+    // Space (32)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ! (33)
+    0x00, 0x00, 0x18, 0x3C, 0x3C, 0x3C, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    // " (34)
+    0x00, 0x66, 0x66, 0x66, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // # (35)
+    0x00, 0x00, 0x00, 0x6C, 0x6C, 0xFE, 0x6C, 0x6C, 0x6C, 0xFE, 0x6C, 0x6C, 0x00, 0x00, 0x00, 0x00,
+    // $ (36)
+    0x18, 0x18, 0x7C, 0xC6, 0xC2, 0xC0, 0x7C, 0x06, 0x06, 0x86, 0xC6, 0x7C, 0x18, 0x18, 0x00, 0x00,
+    // % (37)
+    0x00, 0x00, 0x00, 0x00, 0xC2, 0xC6, 0x0C, 0x18, 0x30, 0x60, 0xC6, 0x86, 0x00, 0x00, 0x00, 0x00,
+    // & (38)
+    0x00, 0x00, 0x38, 0x6C, 0x6C, 0x38, 0x76, 0xDC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00,
+    // ' (39)
+    0x00, 0x30, 0x30, 0x30, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ( (40)
+    0x00, 0x00, 0x0C, 0x18, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x18, 0x0C, 0x00, 0x00, 0x00, 0x00,
+    // ) (41)
+    0x00, 0x00, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x18, 0x30, 0x00, 0x00, 0x00, 0x00,
+    // * (42)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // + (43)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x7E, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // , (44)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x18, 0x30, 0x00, 0x00, 0x00,
+    // - (45)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFE, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // . (46)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    // / (47)
+    0x00, 0x00, 0x00, 0x00, 0x02, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0x80, 0x00, 0x00, 0x00, 0x00,
+    // 0 (48)
+    0x00, 0x00, 0x38, 0x6C, 0xC6, 0xC6, 0xD6, 0xD6, 0xC6, 0xC6, 0x6C, 0x38, 0x00, 0x00, 0x00, 0x00,
+    // 1 (49)
+    0x00, 0x00, 0x18, 0x38, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00, 0x00, 0x00, 0x00,
+    // 2 (50)
+    0x00, 0x00, 0x7C, 0xC6, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xC0, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00,
+    // 3 (51)
+    0x00, 0x00, 0x7C, 0xC6, 0x06, 0x06, 0x3C, 0x06, 0x06, 0x06, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // 4 (52)
+    0x00, 0x00, 0x0C, 0x1C, 0x3C, 0x6C, 0xCC, 0xFE, 0x0C, 0x0C, 0x0C, 0x1E, 0x00, 0x00, 0x00, 0x00,
+    // 5 (53)
+    0x00, 0x00, 0xFE, 0xC0, 0xC0, 0xC0, 0xFC, 0x06, 0x06, 0x06, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // 6 (54)
+    0x00, 0x00, 0x38, 0x60, 0xC0, 0xC0, 0xFC, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // 7 (55)
+    0x00, 0x00, 0xFE, 0xC6, 0x06, 0x06, 0x0C, 0x18, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00,
+    // 8 (56)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // 9 (57)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0x7E, 0x06, 0x06, 0x06, 0x0C, 0x78, 0x00, 0x00, 0x00, 0x00,
+    // : (58)
+    0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ; (59)
+    0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x30, 0x00, 0x00, 0x00, 0x00,
+    // < (60)
+    0x00, 0x00, 0x00, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x30, 0x18, 0x0C, 0x06, 0x00, 0x00, 0x00, 0x00,
+    // = (61)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // > (62)
+    0x00, 0x00, 0x00, 0x60, 0x30, 0x18, 0x0C, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x00, 0x00, 0x00, 0x00,
+    // ? (63)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0x0C, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    // @ (64)
+    0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xDE, 0xDE, 0xDE, 0xDC, 0xC0, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // A (65)
+    0x00, 0x00, 0x10, 0x38, 0x6C, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // B (66)
+    0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x66, 0x66, 0x66, 0x66, 0xFC, 0x00, 0x00, 0x00, 0x00,
+    // C (67)
+    0x00, 0x00, 0x3C, 0x66, 0xC2, 0xC0, 0xC0, 0xC0, 0xC0, 0xC2, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // D (68)
+    0x00, 0x00, 0xF8, 0x6C, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x6C, 0xF8, 0x00, 0x00, 0x00, 0x00,
+    // E (69)
+    0x00, 0x00, 0xFE, 0x66, 0x62, 0x68, 0x78, 0x68, 0x60, 0x62, 0x66, 0xFE, 0x00, 0x00, 0x00, 0x00,
+    // F (70)
+    0x00, 0x00, 0xFE, 0x66, 0x62, 0x68, 0x78, 0x68, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00,
+    // G (71)
+    0x00, 0x00, 0x3C, 0x66, 0xC2, 0xC0, 0xC0, 0xDE, 0xC6, 0xC6, 0x66, 0x3A, 0x00, 0x00, 0x00, 0x00,
+    // H (72)
+    0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xFE, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // I (73)
+    0x00, 0x00, 0x3C, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // J (74)
+    0x00, 0x00, 0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0xCC, 0xCC, 0xCC, 0x78, 0x00, 0x00, 0x00, 0x00,
+    // K (75)
+    0x00, 0x00, 0xE6, 0x66, 0x66, 0x6C, 0x78, 0x78, 0x6C, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00,
+    // L (76)
+    0x00, 0x00, 0xF0, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x62, 0x66, 0xFE, 0x00, 0x00, 0x00, 0x00,
+    // M (77)
+    0x00, 0x00, 0xC6, 0xEE, 0xFE, 0xFE, 0xD6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // N (78)
+    0x00, 0x00, 0xC6, 0xE6, 0xF6, 0xFE, 0xDE, 0xCE, 0xC6, 0xC6, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // O (79)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // P (80)
+    0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00,
+    // Q (81)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xD6, 0xDE, 0x7C, 0x0C, 0x0E, 0x00, 0x00,
+    // R (82)
+    0x00, 0x00, 0xFC, 0x66, 0x66, 0x66, 0x7C, 0x6C, 0x66, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00,
+    // S (83)
+    0x00, 0x00, 0x7C, 0xC6, 0xC6, 0x60, 0x38, 0x0C, 0x06, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // T (84)
+    0x00, 0x00, 0x7E, 0x7E, 0x5A, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // U (85)
+    0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // V (86)
+    0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x6C, 0x38, 0x10, 0x00, 0x00, 0x00, 0x00,
+    // W (87)
+    0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xD6, 0xD6, 0xD6, 0xFE, 0xEE, 0x6C, 0x00, 0x00, 0x00, 0x00,
+    // X (88)
+    0x00, 0x00, 0xC6, 0xC6, 0x6C, 0x7C, 0x38, 0x38, 0x7C, 0x6C, 0xC6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // Y (89)
+    0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // Z (90)
+    0x00, 0x00, 0xFE, 0xC6, 0x86, 0x0C, 0x18, 0x30, 0x60, 0xC2, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00,
+    // [ (91)
+    0x00, 0x00, 0x3C, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // \ (92)
+    0x00, 0x00, 0x00, 0x80, 0xC0, 0xE0, 0x70, 0x38, 0x1C, 0x0E, 0x06, 0x02, 0x00, 0x00, 0x00, 0x00,
+    // ] (93)
+    0x00, 0x00, 0x3C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // ^ (94)
+    0x10, 0x38, 0x6C, 0xC6, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // _ (95)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00,
+    // ` (96)
+    0x30, 0x30, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // a (97)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x78, 0x0C, 0x7C, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00,
+    // b (98)
+    0x00, 0x00, 0xE0, 0x60, 0x60, 0x78, 0x6C, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // c (99)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC0, 0xC0, 0xC0, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // d (100)
+    0x00, 0x00, 0x1C, 0x0C, 0x0C, 0x3C, 0x6C, 0xCC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00,
+    // e (101)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xFE, 0xC0, 0xC0, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // f (102)
+    0x00, 0x00, 0x38, 0x6C, 0x64, 0x60, 0xF0, 0x60, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00,
+    // g (103)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x76, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x7C, 0x0C, 0xCC, 0x78, 0x00,
+    // h (104)
+    0x00, 0x00, 0xE0, 0x60, 0x60, 0x6C, 0x76, 0x66, 0x66, 0x66, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00,
+    // i (105)
+    0x00, 0x00, 0x18, 0x18, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // j (106)
+    0x00, 0x00, 0x06, 0x06, 0x00, 0x0E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x06, 0x66, 0x66, 0x3C, 0x00,
+    // k (107)
+    0x00, 0x00, 0xE0, 0x60, 0x60, 0x66, 0x6C, 0x78, 0x78, 0x6C, 0x66, 0xE6, 0x00, 0x00, 0x00, 0x00,
+    // l (108)
+    0x00, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x00, 0x00, 0x00, 0x00,
+    // m (109)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xEC, 0xFE, 0xD6, 0xD6, 0xD6, 0xD6, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // n (110)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00,
+    // o (111)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // p (112)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x66, 0x66, 0x66, 0x66, 0x66, 0x7C, 0x60, 0x60, 0xF0, 0x00,
+    // q (113)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x76, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x7C, 0x0C, 0x0C, 0x1E, 0x00,
+    // r (114)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xDC, 0x76, 0x66, 0x60, 0x60, 0x60, 0xF0, 0x00, 0x00, 0x00, 0x00,
+    // s (115)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0xC6, 0x60, 0x38, 0x0C, 0xC6, 0x7C, 0x00, 0x00, 0x00, 0x00,
+    // t (116)
+    0x00, 0x00, 0x10, 0x30, 0x30, 0xFC, 0x30, 0x30, 0x30, 0x30, 0x36, 0x1C, 0x00, 0x00, 0x00, 0x00,
+    // u (117)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0x76, 0x00, 0x00, 0x00, 0x00,
+    // v (118)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x18, 0x00, 0x00, 0x00, 0x00,
+    // w (119)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0xC6, 0xD6, 0xD6, 0xD6, 0xFE, 0x6C, 0x00, 0x00, 0x00, 0x00,
+    // x (120)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0x6C, 0x38, 0x38, 0x38, 0x6C, 0xC6, 0x00, 0x00, 0x00, 0x00,
+    // y (121)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0xC6, 0x7E, 0x06, 0x0C, 0xF8, 0x00,
+    // z (122)
+    0x00, 0x00, 0x00, 0x00, 0x00, 0xFE, 0xCC, 0x18, 0x30, 0x60, 0xC6, 0xFE, 0x00, 0x00, 0x00, 0x00,
+    // { (123)
+    0x00, 0x00, 0x0E, 0x18, 0x18, 0x18, 0x70, 0x18, 0x18, 0x18, 0x18, 0x0E, 0x00, 0x00, 0x00, 0x00,
+    // | (124)
+    0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+    // } (125)
+    0x00, 0x00, 0x70, 0x18, 0x18, 0x18, 0x0E, 0x18, 0x18, 0x18, 0x18, 0x70, 0x00, 0x00, 0x00, 0x00,
+    // ~ (126)
+    0x00, 0x00, 0x76, 0xDC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
@@ -1,7 +1,26 @@
 //! Simple Graphics System for elinOS
 //! Provides basic framebuffer operations for drawing pixels and rectangles
+//!
+//! Drawing goes to the framebuffer in RAM, the back buffer; the VirtIO GPU
+//! shows its own copy of the frame, which only changes when `present` sends
+//! it what was drawn. The framebuffer keeps the bounding rectangle of the
+//! pixels changed since, so a present transfers and flushes only that part
+//! of the screen rather than all of it.
 
-use elinos_common::{console_println, debug_println};
+use core::sync::atomic::{AtomicBool, Ordering};
+use elinos_common::{console_println, ok_println, warn_println, info_println, debug_println};
+use heapless::Vec;
+
+use ansi::{Action, Attributes, Erase, Parser};
+use draw::Area;
+use font::{Font, GlyphCache};
+
+pub mod ansi;
+pub mod bench;
+pub mod cursor;
+pub mod draw;
+pub mod font;
+pub mod selection;
 
 /// Simple framebuffer for basic graphics operations
 pub struct SimpleFramebuffer {
@@ -11,6 +30,35 @@ pub struct SimpleFramebuffer {
     pitch: u32, // bytes per row
     size: usize,
     bpp: u32, // bits per pixel
+    /// Pixels changed since the last present
+    damage: Option<Rect>,
+}
+
+/// A rectangle of pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The smallest rectangle holding both
+    fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    pub fn pixels(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
 }
 
 // Safety: We control access to the framebuffer through proper synchronization
@@ -20,13 +68,13 @@ unsafe impl Sync for SimpleFramebuffer {}
 impl SimpleFramebuffer {
     /// Create a new framebuffer with VirtIO GPU compatibility
     pub fn new(width: u32, height: u32, bpp: u32) -> Result<Self, &'static str> {
-        console_println!("[i] Setting up software framebuffer: {}x{} @ {} bpp", width, height, bpp);
+        info_println!("Setting up software framebuffer: {}x{} @ {} bpp", width, height, bpp);
         
         let bytes_per_pixel = bpp / 8;
         let pitch = width * bytes_per_pixel;
         let size = (width * height * bytes_per_pixel) as usize;
         
-        console_println!("[i] Framebuffer size: {} KB", size / 1024);
+        info_println!("Framebuffer size: {} KB", size / 1024);
         
         // For VirtIO GPU compatibility, allocate framebuffer in proper RAM region
         // Use VirtIO-specific allocation to ensure it's in the right memory region
@@ -36,7 +84,7 @@ impl SimpleFramebuffer {
             "VirtIO GPU Framebuffer", // Use "VirtIO" in name to trigger proper allocation
         ) {
             Ok(addr) => {
-                console_println!("[o] VGA framebuffer mapped at 0x{:x}", addr);
+                ok_println!("VGA framebuffer mapped at 0x{:x}", addr);
                 
                 let framebuffer = SimpleFramebuffer {
                     buffer: addr as *mut u32,
@@ -45,9 +93,10 @@ impl SimpleFramebuffer {
                     pitch,
                     size,
                     bpp,
+                    damage: None,
                 };
                 
-                console_println!("[i] Simple framebuffer created:");
+                info_println!("Simple framebuffer created:");
                 console_println!("   Resolution: {}x{}", width, height);
                 console_println!("   BPP: {}, Pitch: {}", bpp, pitch);
                 console_println!("   Size: {} KB", size / 1024);
@@ -67,6 +116,49 @@ impl SimpleFramebuffer {
                 *self.buffer.add(i) = color;
             }
         }
+        self.damage_all();
+    }
+
+    /// Move the scanlines above `bottom` up by `lines`, as whole scanlines,
+    /// and fill the `lines` freed at the bottom with `color`
+    pub fn scroll_up(&mut self, lines: u32, bottom: u32, color: u32) {
+        let bottom = bottom.min(self.height);
+        let lines = lines.min(bottom);
+        let scanline = self.width as usize;
+        let kept = (bottom - lines) as usize * scanline;
+        unsafe {
+            // The two ranges overlap, so this is a memmove
+            core::ptr::copy(self.buffer.add(lines as usize * scanline), self.buffer, kept);
+            core::slice::from_raw_parts_mut(self.buffer.add(kept), lines as usize * scanline).fill(color);
+        }
+        self.damage(Rect { x: 0, y: 0, width: self.width, height: bottom });
+    }
+
+    /// Note that the pixels of `rect` changed; the part on the screen is
+    /// sent by the next present
+    pub fn damage(&mut self, rect: Rect) {
+        if rect.x >= self.width || rect.y >= self.height || rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let rect = Rect {
+            width: rect.width.min(self.width - rect.x),
+            height: rect.height.min(self.height - rect.y),
+            ..rect
+        };
+        self.damage = Some(match self.damage {
+            Some(damage) => damage.union(rect),
+            None => rect,
+        });
+    }
+
+    /// Note that the whole screen changed
+    pub fn damage_all(&mut self) {
+        self.damage = Some(Rect { x: 0, y: 0, width: self.width, height: self.height });
+    }
+
+    /// The pixels changed since the last call, if any
+    pub fn take_damage(&mut self) -> Option<Rect> {
+        self.damage.take()
     }
     
     /// Set a pixel at the given coordinates
@@ -79,9 +171,18 @@ impl SimpleFramebuffer {
         unsafe {
             *self.buffer.add(offset) = color;
         }
+        self.damage(Rect { x, y, width: 1, height: 1 });
         Ok(())
     }
     
+    /// The pixel at the given coordinates, if they are on the screen
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(unsafe { *self.buffer.add((y * self.width + x) as usize) })
+    }
+    
     /// Draw a filled rectangle
     pub fn draw_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: u32) -> Result<(), &'static str> {
         // Bounds checking
@@ -100,6 +201,31 @@ impl SimpleFramebuffer {
         Ok(())
     }
     
+    /// Draw a glyph `width` pixels wide with its top left corner at (x, y).
+    /// `rows` holds one row of bits per scanline, leftmost pixel in the top
+    /// bit, as `font::GlyphCache` gives them. Set bits take `fg`; clear
+    /// bits take `bg`, or are left alone if it is `None`.
+    pub fn draw_glyph(&mut self, rows: &[u16], width: u32, x: u32, y: u32, fg: u32, bg: Option<u32>) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let width = width.min(font::MAX_GLYPH_WIDTH).min(self.width - x);
+        let height = (rows.len() as u32).min(self.height - y);
+        for (row, &bits) in rows.iter().take(height as usize).enumerate() {
+            let line = unsafe {
+                core::slice::from_raw_parts_mut(self.buffer.add(((y + row as u32) * self.width + x) as usize), width as usize)
+            };
+            for (col, pixel) in line.iter_mut().enumerate() {
+                if bits & (0x8000 >> col) != 0 {
+                    *pixel = fg;
+                } else if let Some(bg) = bg {
+                    *pixel = bg;
+                }
+            }
+        }
+        self.damage(Rect { x, y, width, height });
+    }
+    
     /// Get framebuffer information for VirtIO GPU
     pub fn get_framebuffer_info(&self) -> (usize, usize) {
         (self.buffer as usize, self.size)
@@ -116,7 +242,7 @@ static mut VIRTIO_GPU_ENABLED: bool = false;
 
 /// Initialize graphics system with VirtIO GPU support
 pub fn init_graphics() -> Result<(), &'static str> {
-    console_println!("[i] Initializing VGA graphics system...");
+    info_println!("Initializing VGA graphics system...");
     
     // Create framebuffer with VirtIO GPU compatibility
     let mut framebuffer = SimpleFramebuffer::new(640, 480, 32)?;
@@ -128,68 +254,68 @@ pub fn init_graphics() -> Result<(), &'static str> {
     let fb_phys_addr = match crate::memory::mapping::find_memory_mapping(fb_addr) {
         Some(mapping) => {
             if let Some(phys_addr) = mapping.physical_addr {
-                console_println!("[i] Framebuffer: virt=0x{:x}, phys=0x{:x}, size={} KB", 
+                info_println!("Framebuffer: virt=0x{:x}, phys=0x{:x}, size={} KB", 
                                fb_addr, phys_addr, fb_size / 1024);
                 phys_addr
             } else {
-                console_println!("[!] No physical address found for framebuffer");
+                warn_println!("No physical address found for framebuffer");
                 return Err("Failed to get framebuffer physical address");
             }
         }
         None => {
-            console_println!("[!] Framebuffer mapping not found");
+            warn_println!("Framebuffer mapping not found");
             return Err("Framebuffer mapping not found");
         }
     };
     
     // Initialize VirtIO GPU with the properly allocated framebuffer
-    console_println!("[i] Attempting VirtIO GPU initialization...");
+    info_println!("Attempting VirtIO GPU initialization...");
     match crate::virtio::init_virtio_gpu(fb_phys_addr, fb_size) {
         Ok(()) => {
-            console_println!("[o] VirtIO GPU initialized successfully with physical address!");
-            console_println!("[i] Graphics output should now be visible in QEMU window!");
+            ok_println!("VirtIO GPU initialized successfully with physical address!");
+            info_println!("Graphics output should now be visible in QEMU window!");
             
             // Update framebuffer to use the physical address for VirtIO GPU
-            console_println!("[i] Updating framebuffer to use VirtIO GPU physical address...");
+            info_println!("Updating framebuffer to use VirtIO GPU physical address...");
             framebuffer.buffer = fb_phys_addr as *mut u32;
-            console_println!("[o] Framebuffer updated: now using addr=0x{:x}", fb_phys_addr);
+            ok_println!("Framebuffer updated: now using addr=0x{:x}", fb_phys_addr);
             
             unsafe { VIRTIO_GPU_ENABLED = true; }
         }
         Err(_) => {
-            console_println!("[!] VirtIO GPU not available - using software framebuffer only");
+            warn_println!("VirtIO GPU not available - using software framebuffer only");
             unsafe { VIRTIO_GPU_ENABLED = false; }
         }
     }
     
     // Initialize with simple black background for text console
-    console_println!("[i] Initializing simple TTY framebuffer console...");
+    info_println!("Initializing simple TTY framebuffer console...");
     
     // Clear to black background for text console
     framebuffer.clear(0x00000000); // Black background
     
-    console_println!("[o] Simple TTY framebuffer console ready");
+    ok_println!("Simple TTY framebuffer console ready");
     
     // Store framebuffer globally
-    console_println!("[i] Storing framebuffer globally with correct address...");
+    info_println!("Storing framebuffer globally with correct address...");
     unsafe {
         FRAMEBUFFER = Some(framebuffer);
     }
-    console_println!("[o] Framebuffer stored: ptr=0x{:x}", unsafe { FRAMEBUFFER.as_ref().unwrap().buffer as usize });
+    ok_println!("Framebuffer stored: ptr=0x{:x}", unsafe { FRAMEBUFFER.as_ref().unwrap().buffer as usize });
     
     // Initialize text console for TTY output
     init_text_console()?;
     
     // Flush to display if VirtIO GPU is available
     if unsafe { VIRTIO_GPU_ENABLED } {
-        console_println!("[i] Flushing TTY console to VirtIO GPU display...");
+        info_println!("Flushing TTY console to VirtIO GPU display...");
         match crate::virtio::flush_display() {
-            Ok(()) => console_println!("[o] TTY console flushed to VirtIO GPU display"),
-            Err(e) => console_println!("[!] Failed to flush TTY console to display: {:?}", e),
+            Ok(()) => ok_println!("TTY console flushed to VirtIO GPU display"),
+            Err(e) => warn_println!("Failed to flush TTY console to display: {:?}", e),
         }
     }
     
-    console_println!("[o] TTY framebuffer system initialized");
+    ok_println!("TTY framebuffer system initialized");
     Ok(())
 }
 
@@ -198,12 +324,7 @@ pub fn clear_screen(color: u32) -> Result<(), &'static str> {
     unsafe {
         if let Some(ref mut fb) = FRAMEBUFFER {
             fb.clear(color);
-            
-            // Flush to display if VirtIO GPU is available
-            if VIRTIO_GPU_ENABLED {
-                let _ = crate::virtio::flush_display();
-            }
-            
+            let _ = present();
             Ok(())
         } else {
             Err("Graphics not initialized")
@@ -216,12 +337,7 @@ pub fn set_pixel(x: u32, y: u32, color: u32) -> Result<(), &'static str> {
     unsafe {
         if let Some(ref mut fb) = FRAMEBUFFER {
             let result = fb.set_pixel(x, y, color);
-            
-            // Flush to display if VirtIO GPU is available
-            if VIRTIO_GPU_ENABLED {
-                let _ = crate::virtio::flush_display();
-            }
-            
+            let _ = present();
             result
         } else {
             Err("Graphics not initialized")
@@ -234,12 +350,7 @@ pub fn draw_rect(x: u32, y: u32, width: u32, height: u32, color: u32) -> Result<
     unsafe {
         if let Some(ref mut fb) = FRAMEBUFFER {
             let result = fb.draw_rect(x, y, width, height, color);
-            
-            // Flush to display if VirtIO GPU is available
-            if VIRTIO_GPU_ENABLED {
-                let _ = crate::virtio::flush_display();
-            }
-            
+            let _ = present();
             result
         } else {
             Err("Graphics not initialized")
@@ -247,6 +358,13 @@ pub fn draw_rect(x: u32, y: u32, width: u32, height: u32, color: u32) -> Result<
     }
 }
 
+/// Run `f` on the framebuffer. Nothing is presented; what `f` draws shows
+/// with the next present.
+pub fn with_framebuffer<T>(f: impl FnOnce(&mut SimpleFramebuffer) -> T) -> Result<T, &'static str> {
+    let fb = unsafe { (*core::ptr::addr_of_mut!(FRAMEBUFFER)).as_mut() }.ok_or("Graphics not initialized")?;
+    Ok(f(fb))
+}
+
 /// Get framebuffer dimensions
 pub fn get_dimensions() -> Result<(u32, u32), &'static str> {
     unsafe {
@@ -258,8 +376,56 @@ pub fn get_dimensions() -> Result<(u32, u32), &'static str> {
     }
 }
 
-/// Flush framebuffer to display (if VirtIO GPU is available)
-pub fn flush_to_display() -> Result<(), &'static str> {
+/// Framebuffer size in bytes
+pub fn framebuffer_size() -> Result<usize, &'static str> {
+    unsafe {
+        match (*core::ptr::addr_of!(FRAMEBUFFER)).as_ref() {
+            Some(fb) => Ok(fb.size),
+            None => Err("Graphics not initialized"),
+        }
+    }
+}
+
+/// Copy framebuffer bytes from `offset` into `buf`; returns how many there were
+pub fn read_framebuffer(offset: usize, buf: &mut [u8]) -> Result<usize, &'static str> {
+    unsafe {
+        let fb = (*core::ptr::addr_of!(FRAMEBUFFER)).as_ref().ok_or("Graphics not initialized")?;
+        let count = fb.size.saturating_sub(offset).min(buf.len());
+        if count == 0 {
+            return Ok(0);
+        }
+        core::ptr::copy_nonoverlapping((fb.buffer as *const u8).add(offset), buf.as_mut_ptr(), count);
+        Ok(count)
+    }
+}
+
+/// Copy `data` into the framebuffer at `offset` and show it; returns how
+/// many bytes fit
+pub fn write_framebuffer(offset: usize, data: &[u8]) -> Result<usize, &'static str> {
+    let count = unsafe {
+        let fb = (*core::ptr::addr_of_mut!(FRAMEBUFFER)).as_mut().ok_or("Graphics not initialized")?;
+        let count = fb.size.saturating_sub(offset).min(data.len());
+        if count == 0 {
+            return Ok(0);
+        }
+        core::ptr::copy_nonoverlapping(data.as_ptr(), (fb.buffer as *mut u8).add(offset), count);
+        let first_row = (offset / fb.pitch as usize) as u32;
+        let last_row = ((offset + count - 1) / fb.pitch as usize) as u32;
+        fb.damage(Rect { x: 0, y: first_row, width: fb.width, height: last_row - first_row + 1 });
+        count
+    };
+    present()?;
+    Ok(count)
+}
+
+/// The pixels drawn since the last call, if any
+pub fn take_damage() -> Option<Rect> {
+    unsafe { (*core::ptr::addr_of_mut!(FRAMEBUFFER)).as_mut()?.take_damage() }
+}
+
+/// Show what was drawn since the last present: send the changed part of
+/// the framebuffer to the VirtIO GPU, if there is one
+pub fn present() -> Result<(), &'static str> {
     unsafe {
         if VIRTIO_GPU_ENABLED {
             match crate::virtio::flush_display() {
@@ -274,10 +440,11 @@ pub fn flush_to_display() -> Result<(), &'static str> {
 
 /// Initialize text console for shell output
 pub fn init_text_console() -> Result<(), &'static str> {
-    console_println!("[i] Initializing TTY text console...");
+    info_println!("Initializing TTY text console...");
     
     unsafe {
-        TEXT_CONSOLE = Some(TextConsole::new());
+        let (width, height) = get_dimensions()?;
+        TEXT_CONSOLE = Some(TextConsole::new(font::DEFAULT_FONT, width, height));
         
         // Clear screen and position cursor at top
         if let Some(ref mut console) = TEXT_CONSOLE {
@@ -285,247 +452,90 @@ pub fn init_text_console() -> Result<(), &'static str> {
             console.cursor_x = 0;
             console.cursor_y = 0;
         }
-        
-        console_println!("[o] TTY text console ready");
     }
     
+    // Console output is drawn from now on
+    elinos_common::tty::add_sink(elinos_common::tty::Sink {
+        name: "fbcon",
+        write: console_write,
+        flush: console_flush,
+    })?;
+    ok_println!("TTY text console ready");
     Ok(())
 }
 
-/// Print text to graphics console
-pub fn print_to_console(text: &str) -> Result<(), &'static str> {
-    unsafe {
-        if let Some(ref mut console) = TEXT_CONSOLE {
-            console.print_str(text)?;
-            
-            // Flush to display after printing (skip during recursive console calls)
-            if VIRTIO_GPU_ENABLED && !crate::is_console_bridge_active() {
-                let _ = crate::virtio::flush_display();
-            }
-            
-            Ok(())
-        } else {
-            Err("Text console not initialized")
-        }
+/// Text drawn on the console and not presented yet
+static CONSOLE_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// Draw console output; the TTY sink of the text console. The screen is
+/// presented at the end of each line, and for the rest when the TTY is
+/// flushed, so typing and prompts show without a flush per character.
+fn console_write(text: &str) {
+    if let Some(console) = unsafe { (*core::ptr::addr_of_mut!(TEXT_CONSOLE)).as_mut() } {
+        let _ = console.print_str(text);
+        CONSOLE_DIRTY.store(true, Ordering::Relaxed);
+    }
+    if text.contains('\n') {
+        console_flush();
+    }
+}
+
+/// Present console text drawn since the last flush
+fn console_flush() {
+    if CONSOLE_DIRTY.swap(false, Ordering::Relaxed) {
+        let _ = present();
     }
 }
 
+/// Whether the text cursor is shown, which it is while the shell reads a
+/// command line
+static TEXT_CURSOR: AtomicBool = AtomicBool::new(false);
+
+/// Show or hide the text cursor from the next present on
+pub fn show_text_cursor(show: bool) {
+    TEXT_CURSOR.store(show, Ordering::Relaxed);
+    CONSOLE_DIRTY.store(true, Ordering::Relaxed);
+}
+
+/// The cell the text cursor is in, if it is shown
+fn text_cursor() -> Option<(ConsoleGeometry, selection::Cell)> {
+    if !TEXT_CURSOR.load(Ordering::Relaxed) {
+        return None;
+    }
+    let console = unsafe { (*core::ptr::addr_of!(TEXT_CONSOLE)).as_ref() }?;
+    // After the last column the next character goes on the next row, but
+    // the cursor stays on this one until it is written
+    let cell = selection::Cell { col: console.cursor_x.min(console.max_cols - 1), row: console.cursor_y };
+    Some((console.geometry(), cell))
+}
+
 /// Clear the graphics console
 pub fn clear_console() -> Result<(), &'static str> {
     unsafe {
         if let Some(ref mut console) = TEXT_CONSOLE {
             console.clear_screen()?;
-            
-            // Flush to display
-            if VIRTIO_GPU_ENABLED {
-                let _ = crate::virtio::flush_display();
-            }
+            let _ = present();
         }
     }
     Ok(())
 }
 
-// Simple 8x8 bitmap font data for basic ASCII characters
-const FONT_WIDTH: u32 = 8;
-const FONT_HEIGHT: u32 = 8;
-
-// Simple bitmap font for characters 32-126 (space to ~)
-const FONT_DATA: &[u8] = &[
-    // Space (32)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    // ! (33)
-    0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00,
-    // " (34)
-    0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    // # (35)
-    0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00,
-    // $ (36)
-    0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00,
-    // % (37)
-    0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00,
-    // & (38)
-    0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00,
-    // ' (39)
-    0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00,
-    // ( (40)
-    0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00,
-    // ) (41)
-    0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00,
-    // * (42)
-    0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00,
-    // + (43)
-    0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00,
-    // , (44)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x06, 0x00,
-    // - (45)
-    0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00,
-    // . (46)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00,
-    // / (47)
-    0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00,
-    // 0 (48)
-    0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00,
-    // 1 (49)
-    0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00,
-    // 2 (50)
-    0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00,
-    // 3 (51)
-    0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00,
-    // 4 (52)
-    0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00,
-    // 5 (53)
-    0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00,
-    // 6 (54)
-    0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00,
-    // 7 (55)
-    0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00,
-    // 8 (56)
-    0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00,
-    // 9 (57)
-    0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00,
-    // : (58)
-    0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00,
-    // ; (59)
-    0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x06, 0x00,
-    // < (60)
-    0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00,
-    // = (61)
-    0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00,
-    // > (62)
-    0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00,
-    // ? (63)
-    0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00,
-    // @ (64)
-    0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00,
-    // A (65)
-    0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00,
-    // B (66)
-    0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00,
-    // C (67)
-    0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00,
-    // D (68)
-    0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00,
-    // E (69)
-    0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00,
-    // F (70)
-    0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00,
-    // G (71)
-    0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00,
-    // H (72)
-    0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00,
-    // I (73)
-    0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
-    // J (74)
-    0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00,
-    // K (75)
-    0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00,
-    // L (76)
-    0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00,
-    // M (77)
-    0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00,
-    // N (78)
-    0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00,
-    // O (79)
-    0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00,
-    // P (80)
-    0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00,
-    // Q (81)
-    0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00,
-    // R (82)
-    0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00,
-    // S (83)
-    0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00,
-    // T (84)
-    0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
-    // U (85)
-    0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00,
-    // V (86)
-    0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00,
-    // W (87)
-    0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00,
-    // X (88)
-    0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00,
-    // Y (89)
-    0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00,
-    // Z (90)
-    0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00,
-    // [ (91)
-    0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00,
-    // \ (92)
-    0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00,
-    // ] (93)
-    0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00,
-    // ^ (94)
-    0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00,
-    // _ (95)
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF,
-    // ` (96)
-    0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00,
-    // a (97)
-    0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00,
-    // b (98)
-    0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00,
-    // c (99)
-    0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00,
-    // d (100)
-    0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6E, 0x00,
-    // e (101)
-    0x00, 0x00, 0x1E, 0x33, 0x3f, 0x03, 0x1E, 0x00,
-    // f (102)
-    0x1C, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0F, 0x00,
-    // g (103)
-    0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F,
-    // h (104)
-    0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00,
-    // i (105)
-    0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
-    // j (106)
-    0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E,
-    // k (107)
-    0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00,
-    // l (108)
-    0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00,
-    // m (109)
-    0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00,
-    // n (110)
-    0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00,
-    // o (111)
-    0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00,
-    // p (112)
-    0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F,
-    // q (113)
-    0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78,
-    // r (114)
-    0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00,
-    // s (115)
-    0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00,
-    // t (116)
-    0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00,
-    // u (117)
-    0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00,
-    // v (118)
-    0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00,
-    // w (119)
-    0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00,
-    // x (120)
-    0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00,
-    // y (121)
-    0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F,
-    // z (122)
-    0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00,
-    // { (123)
-    0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00,
-    // | (124)
-    0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00,
-    // } (125)
-    0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00,
-    // ~ (126)
-    0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-];
+/// Largest text console, in characters: 8x8 cells on a 1280x960 screen
+const MAX_CONSOLE_COLS: usize = 160;
+const MAX_CONSOLE_ROWS: usize = 120;
 
 // Text console state
 static mut TEXT_CONSOLE: Option<TextConsole> = None;
 
+/// Size of the text console and of its character cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConsoleGeometry {
+    pub cols: u32,
+    pub rows: u32,
+    pub cell_width: u32,
+    pub cell_height: u32,
+}
+
 struct TextConsole {
     cursor_x: u32,
     cursor_y: u32,
@@ -533,60 +543,58 @@ struct TextConsole {
     max_rows: u32,
     fg_color: u32,
     bg_color: u32,
+    glyphs: GlyphCache,
+    /// Character last drawn in each cell, row by row, for text selection
+    cells: Vec<u8, { MAX_CONSOLE_COLS * MAX_CONSOLE_ROWS }>,
+    /// Escape sequences in the output, and the colours they selected
+    parser: Parser,
+    attributes: Attributes,
+    /// Cursor kept by a save-cursor sequence
+    saved_cursor: (u32, u32),
 }
 
 impl TextConsole {
-    fn new() -> Self {
-        TextConsole {
+    /// A console in `font` filling a `width` x `height` screen
+    fn new(font: &'static Font, width: u32, height: u32) -> Self {
+        let mut console = TextConsole {
             cursor_x: 0,
             cursor_y: 0,
-            max_cols: 640 / FONT_WIDTH,   // 80 columns
-            max_rows: 480 / FONT_HEIGHT,  // 60 rows
-            fg_color: 0x00FFFFFF,         // White text (XRGB: 0xXXRRGGBB)
-            bg_color: 0x00000000,         // Black background
-        }
+            max_cols: 0,
+            max_rows: 0,
+            fg_color: 0x00FFFFFF,           // White text (XRGB: 0xXXRRGGBB)
+            bg_color: 0x00000000,           // Black background
+            glyphs: GlyphCache::new(font),
+            cells: Vec::new(),
+            parser: Parser::new(),
+            attributes: Attributes::default(),
+            saved_cursor: (0, 0),
+        };
+        console.fit(width, height);
+        console
+    }
+
+    /// Take as many rows and columns of the font as fit on the screen
+    fn fit(&mut self, width: u32, height: u32) {
+        let font = self.glyphs.font();
+        self.max_cols = (width / font.width).clamp(1, MAX_CONSOLE_COLS as u32);
+        self.max_rows = (height / font.height).clamp(1, MAX_CONSOLE_ROWS as u32);
+        self.cells.clear();
+        let _ = self.cells.resize((self.max_cols * self.max_rows) as usize, b' ');
+    }
+
+    fn geometry(&self) -> ConsoleGeometry {
+        let font = self.glyphs.font();
+        ConsoleGeometry { cols: self.max_cols, rows: self.max_rows, cell_width: font.width, cell_height: font.height }
     }
     
-    fn draw_char(&mut self, ch: char, x: u32, y: u32) -> Result<(), &'static str> {
-        if ch < ' ' || ch > '~' {
-            return Ok(()); // Skip non-printable characters
-        }
-        
-        let char_index = (ch as u8 - 32) as usize; // ASCII 32 = space
-        let font_offset = char_index * 8; // 8 bytes per character
-        
-        if font_offset + 8 > FONT_DATA.len() {
-            return Ok(()); // Character not in font
-        }
-        
-        // Use the SAME direct drawing method that worked for graphics
+    fn draw_char(&mut self, ch: char, col: u32, row: u32) -> Result<(), &'static str> {
+        let font = self.glyphs.font();
+        let (fg, bg) = self.attributes.colors(self.fg_color, self.bg_color);
+        let glyph = self.glyphs.glyph(ch);
         unsafe {
             if let Some(ref mut fb) = FRAMEBUFFER {
-                for row in 0..8 {
-                    let font_byte = FONT_DATA[font_offset + row];
-                    for col in 0..8 {
-                        let pixel_x = x + col;
-                        let pixel_y = y + row as u32;
-                        
-                        if pixel_x < 640 && pixel_y < 480 {
-                            let color = if (font_byte & (0x80 >> col)) != 0 {
-                                self.fg_color // Foreground color for set bits
-                            } else {
-                                continue; // Don't draw background, leave existing pixels
-                            };
-                            
-                            // Direct pixel write - same as graphics rectangles
-                            let offset = (pixel_y * 640 + pixel_x) as usize;
-                            *fb.buffer.add(offset) = color;
-                        }
-                    }
-                }
-                
-                // Force flush to VirtIO GPU after drawing character
-                // Skip flushing during initialization to prevent infinite loops
-                if VIRTIO_GPU_ENABLED {
-                    let _ = crate::virtio::flush_display();
-                }
+                // The caller presents once the whole string is drawn
+                fb.draw_glyph(glyph, font.width, col * font.width, row * font.height, fg, Some(bg));
             }
         }
         Ok(())
@@ -594,6 +602,9 @@ impl TextConsole {
     
     fn print_char(&mut self, ch: char) -> Result<(), &'static str> {
         match ch {
+            '\x08' => {
+                self.cursor_x = self.cursor_x.saturating_sub(1);
+            }
             '\n' => {
                 self.cursor_x = 0;
                 self.cursor_y += 1;
@@ -615,6 +626,8 @@ impl TextConsole {
                     }
                 }
             }
+            // Bell and other controls draw nothing
+            ch if ch.is_control() => {}
             _ => {
                 if self.cursor_x >= self.max_cols {
                     self.cursor_x = 0;
@@ -624,7 +637,9 @@ impl TextConsole {
                     }
                 }
                 
-                self.draw_char(ch, self.cursor_x * FONT_WIDTH, self.cursor_y * FONT_HEIGHT)?;
+                let index = (self.cursor_y * self.max_cols + self.cursor_x) as usize;
+                self.cells[index] = if (' '..='~').contains(&ch) { ch as u8 } else { b' ' };
+                self.draw_char(ch, self.cursor_x, self.cursor_y)?;
                 self.cursor_x += 1;
             }
         }
@@ -633,59 +648,109 @@ impl TextConsole {
     
     fn print_str(&mut self, s: &str) -> Result<(), &'static str> {
         for ch in s.chars() {
-            self.print_char(ch)?;
+            if let Some(action) = self.parser.advance(ch) {
+                self.perform(action)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carry out what a character or escape sequence asks for
+    fn perform(&mut self, action: Action) -> Result<(), &'static str> {
+        let (last_col, last_row) = (self.max_cols - 1, self.max_rows - 1);
+        match action {
+            Action::Print(ch) => return self.print_char(ch),
+            Action::CursorUp(count) => self.cursor_y = self.cursor_y.saturating_sub(count),
+            Action::CursorDown(count) => self.cursor_y = self.cursor_y.saturating_add(count).min(last_row),
+            Action::CursorForward(count) => self.cursor_x = self.cursor_x.saturating_add(count).min(last_col),
+            Action::CursorBack(count) => self.cursor_x = self.cursor_x.min(last_col).saturating_sub(count),
+            Action::CursorTo { row, col } => {
+                self.cursor_y = row.min(last_row);
+                self.cursor_x = col.min(last_col);
+            }
+            Action::CursorColumn(col) => self.cursor_x = col.min(last_col),
+            Action::SaveCursor => self.saved_cursor = (self.cursor_x, self.cursor_y),
+            Action::RestoreCursor => {
+                self.cursor_x = self.saved_cursor.0.min(last_col);
+                self.cursor_y = self.saved_cursor.1.min(last_row);
+            }
+            Action::EraseLine(erase) => {
+                let (from, to) = self.erase_range(erase);
+                self.erase(self.cursor_y, from, to);
+            }
+            Action::EraseDisplay(erase) => {
+                let (from, to) = self.erase_range(erase);
+                self.erase(self.cursor_y, from, to);
+                let rows = match erase {
+                    Erase::ToEnd => self.cursor_y + 1..self.max_rows,
+                    Erase::ToCursor => 0..self.cursor_y,
+                    Erase::All => 0..self.max_rows,
+                };
+                for row in rows {
+                    self.erase(row, 0, self.max_cols);
+                }
+                selection::clear();
+            }
+            Action::Sgr(params) => self.attributes.apply(params.as_slice()),
         }
         Ok(())
     }
+
+    /// Columns of the cursor's row that `erase` covers, end excluded
+    fn erase_range(&self, erase: Erase) -> (u32, u32) {
+        let col = self.cursor_x.min(self.max_cols - 1);
+        match erase {
+            Erase::ToEnd => (col, self.max_cols),
+            Erase::ToCursor => (0, col + 1),
+            Erase::All => (0, self.max_cols),
+        }
+    }
+
+    /// Blank the cells of `row` from `from` up to `to` in the current
+    /// background colour
+    fn erase(&mut self, row: u32, from: u32, to: u32) {
+        if from >= to {
+            return;
+        }
+        let start = (row * self.max_cols) as usize;
+        self.cells[start + from as usize..start + to as usize].fill(b' ');
+        let font = self.glyphs.font();
+        let (_, bg) = self.attributes.colors(self.fg_color, self.bg_color);
+        let area = Area {
+            x: (from * font.width) as i32,
+            y: (row * font.height) as i32,
+            width: (to - from) * font.width,
+            height: font.height,
+        };
+        unsafe {
+            if let Some(ref mut fb) = FRAMEBUFFER {
+                fb.fill_pattern(area, &draw::SOLID, bg, bg);
+            }
+        }
+    }
     
     fn scroll_up(&mut self) {
         // Move all text up by one line
+        let line_height = self.glyphs.font().height;
         unsafe {
             if let Some(ref mut fb) = FRAMEBUFFER {
-                // Copy each line up
-                for y in 1..self.max_rows {
-                    for x in 0..self.max_cols {
-                        for row in 0..FONT_HEIGHT {
-                            for col in 0..FONT_WIDTH {
-                                let src_x = x * FONT_WIDTH + col;
-                                let src_y = y * FONT_HEIGHT + row;
-                                let dst_x = x * FONT_WIDTH + col;
-                                let dst_y = (y - 1) * FONT_HEIGHT + row;
-                                
-                                if src_x < 640 && src_y < 480 && dst_x < 640 && dst_y < 480 {
-                                    let offset_src = (src_y * 640 + src_x) as usize;
-                                    let offset_dst = (dst_y * 640 + dst_x) as usize;
-                                    
-                                    let color = *fb.buffer.add(offset_src);
-                                    *fb.buffer.add(offset_dst) = color;
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                // Clear the last line
-                for x in 0..self.max_cols {
-                    for row in 0..FONT_HEIGHT {
-                        for col in 0..FONT_WIDTH {
-                            let pixel_x = x * FONT_WIDTH + col;
-                            let pixel_y = (self.max_rows - 1) * FONT_HEIGHT + row;
-                            
-                            if pixel_x < 640 && pixel_y < 480 {
-                                let _ = fb.set_pixel(pixel_x, pixel_y, self.bg_color);
-                            }
-                        }
-                    }
-                }
+                fb.scroll_up(line_height, self.max_rows * line_height, self.bg_color);
             }
         }
         
+        let cols = self.max_cols as usize;
+        self.cells.copy_within(cols.., 0);
+        let last_row = self.cells.len() - cols;
+        self.cells[last_row..].fill(b' ');
+        selection::clear();
         self.cursor_y = self.max_rows - 1;
     }
     
     fn clear_screen(&mut self) -> Result<(), &'static str> {
         self.cursor_x = 0;
         self.cursor_y = 0;
+        self.cells.fill(b' ');
+        selection::clear();
         
         unsafe {
             if let Some(ref mut fb) = FRAMEBUFFER {
@@ -697,25 +762,38 @@ impl TextConsole {
     }
 }
 
+/// The character last drawn in `cell` of the text console
+fn console_char(cell: selection::Cell) -> char {
+    let console = unsafe { (*core::ptr::addr_of!(TEXT_CONSOLE)).as_ref() };
+    console.filter(|console| cell.col < console.max_cols)
+        .and_then(|console| console.cells.get((cell.row * console.max_cols + cell.col) as usize))
+        .map_or(' ', |&byte| byte as char)
+}
+
+/// Rows, columns and cell size of the text console, if it is up
+pub fn console_geometry() -> Option<ConsoleGeometry> {
+    unsafe { (*core::ptr::addr_of!(TEXT_CONSOLE)).as_ref() }.map(TextConsole::geometry)
+}
 
-/// Print text prompt to TTY framebuffer (simple text rendering)
-pub fn print_shell_prompt() -> Result<(), &'static str> {
+/// The text console's font
+pub fn console_font() -> &'static Font {
+    unsafe { (*core::ptr::addr_of!(TEXT_CONSOLE)).as_ref() }.map_or(font::DEFAULT_FONT, |console| console.glyphs.font())
+}
+
+/// Switch the text console to `font`, fitting it to the screen again. The
+/// console is cleared, as the old text does not fit the new cells.
+pub fn set_console_font(font: &'static Font) -> Result<(), &'static str> {
+    let (width, height) = get_dimensions()?;
     unsafe {
-        if let Some(ref mut console) = TEXT_CONSOLE {
-            // Just print the prompt as text using the text console
-            console.print_str("elinOS> ")?;
-            
-            // Flush to display if VirtIO GPU is available
-            if VIRTIO_GPU_ENABLED {
-                let _ = crate::virtio::flush_display();
-            }
-            
-            Ok(())
-        } else {
-            // No text console available, return success silently
-            Ok(())
+        let console = (*core::ptr::addr_of_mut!(TEXT_CONSOLE)).as_mut().ok_or("Text console not initialized")?;
+        if core::ptr::eq(console.glyphs.font(), font) {
+            return Ok(());
         }
+        console.glyphs = GlyphCache::new(font);
+        console.fit(width, height);
+        console.clear_screen()?;
     }
+    present()
 }
 
  
\ No newline at end of file
@@ -0,0 +1,140 @@
+//! Text selection on the framebuffer console
+//!
+//! Dragging with the left button selects text, cell by cell in reading
+//! order as a terminal does, and releasing it copies the selection into the
+//! paste buffer: one line per row, without trailing blanks. A click without
+//! a drag drops the selection but keeps the buffer. The middle button, or
+//! Ctrl-V at the shell, types the buffer into the shell input.
+//!
+//! The selection is kept in character cells, not pixels, and read back from
+//! the console's character grid. Like the pointer cursor it is only shown
+//! inverted while a frame goes to the GPU. Scrolling or clearing the
+//! console drops it, as the cells no longer hold what was selected.
+
+use heapless::{Deque, String};
+use spin::Mutex;
+
+use super::{ConsoleGeometry, SimpleFramebuffer, MAX_CONSOLE_COLS};
+
+/// Bytes the paste buffer holds; a full screen of the default font fits
+pub const PASTE_BUFFER_SIZE: usize = 8192;
+
+/// A character cell of the console, row first so cells order as text does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cell {
+    pub row: u32,
+    pub col: u32,
+}
+
+impl Cell {
+    /// The cell under pixel (x, y), if the console is up
+    pub fn at(x: u32, y: u32) -> Option<Cell> {
+        let geometry = super::console_geometry()?;
+        Some(Cell {
+            row: (y / geometry.cell_height).min(geometry.rows - 1),
+            col: (x / geometry.cell_width).min(geometry.cols - 1),
+        })
+    }
+}
+
+/// Where a drag started and where it is now
+#[derive(Clone, Copy)]
+struct Selection {
+    anchor: Cell,
+    end: Cell,
+}
+
+impl Selection {
+    /// First and last selected cell
+    fn range(&self) -> (Cell, Cell) {
+        (self.anchor.min(self.end), self.anchor.max(self.end))
+    }
+}
+
+static SELECTION: Mutex<Option<Selection>> = Mutex::new(None);
+static PASTE_BUFFER: Mutex<String<PASTE_BUFFER_SIZE>> = Mutex::new(String::new());
+
+/// Pasted bytes the shell has not read yet
+static PENDING: Mutex<Deque<u8, PASTE_BUFFER_SIZE>> = Mutex::new(Deque::new());
+
+/// Start a selection at `cell`, dropping the old one
+pub fn begin(cell: Cell) {
+    *SELECTION.lock() = Some(Selection { anchor: cell, end: cell });
+}
+
+/// Move the end of the selection being made to `cell`
+pub fn extend(cell: Cell) {
+    if let Some(selection) = SELECTION.lock().as_mut() {
+        selection.end = cell;
+    }
+}
+
+/// End the drag and copy what it selected into the paste buffer. Returns
+/// false if nothing was selected, i.e. it was a click.
+pub fn finish() -> bool {
+    let Some(selection) = *SELECTION.lock() else { return false };
+    if selection.anchor == selection.end {
+        clear();
+        return false;
+    }
+    let Some(geometry) = super::console_geometry() else { return false };
+    let (first, last) = selection.range();
+    let mut buffer = PASTE_BUFFER.lock();
+    buffer.clear();
+    for row in first.row..=last.row {
+        let from = if row == first.row { first.col } else { 0 };
+        let to = if row == last.row { last.col } else { geometry.cols - 1 };
+        let mut line = String::<MAX_CONSOLE_COLS>::new();
+        for col in from..=to {
+            let _ = line.push(super::console_char(Cell { row, col }));
+        }
+        if row != first.row {
+            let _ = buffer.push('\n');
+        }
+        let _ = buffer.push_str(line.trim_end());
+    }
+    true
+}
+
+/// Drop the selection; the paste buffer stays
+pub fn clear() {
+    *SELECTION.lock() = None;
+}
+
+/// Queue the paste buffer as shell input
+pub fn paste() {
+    let buffer = PASTE_BUFFER.lock();
+    let mut pending = PENDING.lock();
+    for byte in buffer.bytes() {
+        if pending.push_back(byte).is_err() {
+            break;
+        }
+    }
+}
+
+/// The next pasted byte for the shell, if a paste is under way
+pub fn next_pasted_byte() -> Option<u8> {
+    PENDING.lock().pop_front()
+}
+
+/// First and last selected cell, if there is a selection
+pub fn range() -> Option<(Cell, Cell)> {
+    SELECTION.lock().map(|selection| selection.range())
+}
+
+/// Invert the pixels of the cells from `first` to `last`; doing it again
+/// puts them back
+pub fn invert(fb: &mut SimpleFramebuffer, geometry: ConsoleGeometry, (first, last): (Cell, Cell)) {
+    let (cell_width, cell_height) = (geometry.cell_width, geometry.cell_height);
+    for row in first.row..=last.row {
+        let from = if row == first.row { first.col } else { 0 };
+        let to = if row == last.row { last.col } else { geometry.cols - 1 };
+        for y in row * cell_height..(row + 1) * cell_height {
+            for x in from * cell_width..(to + 1) * cell_width {
+                if let Some(pixel) = fb.get_pixel(x, y) {
+                    let _ = fb.set_pixel(x, y, pixel ^ 0x00FFFFFF);
+                }
+            }
+        }
+    }
+}
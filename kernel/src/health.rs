@@ -0,0 +1,173 @@
+//! Health counters and the summary `health` and /proc/health show
+//!
+//! Trouble that does not stop the kernel is easy to miss: a refused
+//! allocation, a disk request that failed after its retries, a packet
+//! thrown away, a timer tick that came late because interrupts were held
+//! off. Each such event bumps a counter here, and the summary puts them
+//! next to the allocator's and the block devices' own counters and the
+//! count of every trap taken, so one look tells whether anything went
+//! wrong since boot and where to dig.
+//!
+//! Stalls the watchdog reported are counted too; late ticks are the
+//! closest thing to its near misses. Lock contention is only seen where the kernel uses `try_lock`
+//! and has to give up, as spin locks keep no count of their waits.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::trap::TrapCause;
+
+/// Something that went wrong without stopping the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A received packet was malformed, failed its checksum or had nowhere
+    /// to go
+    PacketDropped,
+    /// The network device refused a frame to send
+    TransmitError,
+    /// A timer interrupt came a tick or more after it was due
+    LateTick,
+    /// A `try_lock` found its lock held and gave up
+    LockContended,
+    /// A user program was killed by an exception it caused
+    ProgramKilled,
+    /// The kernel faulted in a system call and killed the calling program
+    /// rather than halting
+    KernelFault,
+    /// The watchdog saw no progress for its timeout
+    WatchdogStall,
+}
+
+impl Event {
+    const ALL: [Event; 7] = [
+        Event::PacketDropped, Event::TransmitError, Event::LateTick, Event::LockContended,
+        Event::ProgramKilled, Event::KernelFault, Event::WatchdogStall,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Event::PacketDropped => "PacketsDropped",
+            Event::TransmitError => "TransmitErrors",
+            Event::LateTick => "LateTicks",
+            Event::LockContended => "LockContended",
+            Event::ProgramKilled => "ProgramsKilled",
+            Event::KernelFault => "KernelFaults",
+            Event::WatchdogStall => "WatchdogStalls",
+        }
+    }
+}
+
+static EVENTS: [AtomicU64; Event::ALL.len()] = [const { AtomicU64::new(0) }; Event::ALL.len()];
+
+/// Trap codes counted: scause without its interrupt bit. The spec's
+/// exception codes, custom ones included, end at 63.
+const TRAP_CODES: usize = 64;
+
+static EXCEPTIONS: [AtomicU64; TRAP_CODES] = [const { AtomicU64::new(0) }; TRAP_CODES];
+static INTERRUPTS: [AtomicU64; TRAP_CODES] = [const { AtomicU64::new(0) }; TRAP_CODES];
+
+/// Count one `event`
+pub fn count(event: Event) {
+    EVENTS[event as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many times `event` happened since boot
+pub fn events(event: Event) -> u64 {
+    EVENTS[event as usize].load(Ordering::Relaxed)
+}
+
+/// Count a trap with cause `scause`
+pub fn count_trap(scause: u64) {
+    let code = (scause & !(1 << 63)) as usize;
+    let table = if scause & (1 << 63) != 0 { &INTERRUPTS } else { &EXCEPTIONS };
+    if let Some(counter) = table.get(code) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Write the summary: one `Name: value` line per signal, the traps taken
+/// by cause, and a last line naming the signals that need attention
+pub fn write_summary(out: &mut impl Write) -> core::fmt::Result {
+    let mut attention = heapless::Vec::<&str, 8>::new();
+    let mut line = |out: &mut dyn Write, name: &'static str, value: u64, bad: bool| {
+        if bad && value > 0 {
+            let _ = attention.push(name);
+        }
+        writeln!(out, "{:<16}{:>10}", label(format_args!("{}:", name)), value)
+    };
+
+    let ticks = crate::trap::tick_count();
+    writeln!(out, "{:<16}{:>10} s", "Uptime:", ticks / crate::trap::TICK_HZ)?;
+
+    let memory = crate::memory::get_memory_stats();
+    line(out, "AllocFailures", memory.allocation_failures as u64, true)?;
+
+    let (mut io_errors, mut retries, mut offline) = (0, 0, 0);
+    for index in 0..crate::virtio::MAX_BLOCK_DEVICES {
+        let Some(device) = crate::virtio::block_device(index) else { continue };
+        let device = device.lock();
+        if !device.is_initialized() {
+            continue;
+        }
+        let health = device.health();
+        io_errors += health.read_errors + health.write_errors;
+        retries += health.retries;
+        offline += u64::from(health.offline);
+    }
+    line(out, "IoErrors", io_errors, true)?;
+    line(out, "IoRetries", retries, false)?;
+    line(out, "DevicesOffline", offline, true)?;
+
+    for event in Event::ALL {
+        line(out, event.name(), events(event), true)?;
+    }
+
+    writeln!(out, "Traps:")?;
+    for (scause, taken) in traps_taken() {
+        let code = scause & !(1 << 63);
+        let name = match TrapCause::from(scause) {
+            TrapCause::Unknown if scause & (1 << 63) != 0 => label(format_args!("Interrupt{}", code)),
+            TrapCause::Unknown => label(format_args!("Exception{}", code)),
+            cause => label(format_args!("{:?}", cause)),
+        };
+        writeln!(out, "  {:<30}{:>10}", name, taken)?;
+    }
+
+    if attention.is_empty() {
+        writeln!(out, "Status: ok")
+    } else {
+        write!(out, "Status: check")?;
+        for name in &attention {
+            write!(out, " {}", name)?;
+        }
+        writeln!(out)
+    }
+}
+
+/// The scause values taken since boot with their counts, interrupts first
+fn traps_taken() -> impl Iterator<Item = (u64, u64)> {
+    [(&INTERRUPTS, 1 << 63), (&EXCEPTIONS, 0)].into_iter().flat_map(|(table, interrupt_bit)| {
+        table.iter().enumerate()
+            .map(move |(code, counter)| (code as u64 | interrupt_bit, counter.load(Ordering::Relaxed)))
+            .filter(|&(_, taken)| taken > 0)
+    })
+}
+
+/// Write the trap table `traps` and /proc/traps show: each cause taken
+/// since boot with its code and count, then how exceptions ended
+pub fn write_traps(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out, "{:<10}{:>5}{:>10}  CAUSE", "TYPE", "CODE", "COUNT")?;
+    for (scause, taken) in traps_taken() {
+        let kind = if scause & (1 << 63) != 0 { "interrupt" } else { "exception" };
+        writeln!(out, "{:<10}{:>5}{:>10}  {}", kind, scause & !(1 << 63), taken, crate::trap::describe(scause))?;
+    }
+    writeln!(out, "Programs killed: {}", events(Event::ProgramKilled))?;
+    writeln!(out, "Kernel faults recovered: {}", events(Event::KernelFault))
+}
+
+/// `args` as a string, so the summary can pad it into a column
+fn label(args: core::fmt::Arguments) -> heapless::String<32> {
+    let mut label = heapless::String::new();
+    let _ = label.write_fmt(args);
+    label
+}
@@ -0,0 +1,998 @@
+//! Command registry and help system
+//!
+//! Every shell command is described once here: its usage line, a one-line
+//! description for `help`, and the long help shown by `man`.
+
+use elinos_common::{console_println, info_println};
+
+/// Help sections, in display order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandCategory {
+    Shell,
+    System,
+    Filesystem,
+    Program,
+    Network,
+    Control,
+}
+
+impl CommandCategory {
+    pub const ALL: [CommandCategory; 6] = [
+        CommandCategory::Shell,
+        CommandCategory::System,
+        CommandCategory::Filesystem,
+        CommandCategory::Program,
+        CommandCategory::Network,
+        CommandCategory::Control,
+    ];
+    
+    pub fn title(&self) -> &'static str {
+        match self {
+            CommandCategory::Shell => "Shell Built-ins",
+            CommandCategory::System => "System Information",
+            CommandCategory::Filesystem => "Filesystem Operations",
+            CommandCategory::Program => "Program Execution",
+            CommandCategory::Network => "Networking",
+            CommandCategory::Control => "System Control",
+        }
+    }
+}
+
+/// Structured description of a shell command
+pub struct CommandInfo {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub long_help: &'static str,
+    pub category: CommandCategory,
+}
+
+/// All commands known to the shell
+pub static COMMAND_REGISTRY: &[CommandInfo] = &[
+    // Shell built-ins
+    CommandInfo {
+        name: "help",
+        usage: "help [command]",
+        description: "Show this help message",
+        long_help: "Without arguments, lists every command grouped by category.\n\
+                    With a command name, prints that command's usage line.\n\
+                    Use 'man <command>' for the full description.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "man",
+        usage: "man <command>",
+        description: "Show the manual page for a command",
+        long_help: "Displays the long help for a command through the pager.\n\
+                    Keys: Space - next page, Enter - next line, q - quit.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "history",
+        usage: "history [-t] [-c]",
+        description: "Show command history",
+        long_help: "Lists previously entered commands, oldest first.\n\
+                    History is saved to /.shell_history and restored at boot.\n\
+                    Use the Up/Down arrow keys to recall entries, or Ctrl-R\n\
+                    to search back for one containing what you type\n\
+                    (Ctrl-R again: older match, Enter: run, Esc: edit,\n\
+                    Ctrl-G: cancel).\n\
+                    -t, --time   show when each command was entered (UTC)\n\
+                    -c, --clear  forget all entries and empty the file\n\
+                    HISTSIZE sets how many entries are kept (at most 100);\n\
+                    HISTCONTROL=erasedups drops earlier copies of a command.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "set",
+        usage: "set [VAR=value]",
+        description: "Set an environment variable",
+        long_help: "Defines or updates a shell variable. Without arguments,\n\
+                    lists all variables like 'env'.\n\
+                    Variables are expanded in command lines as $VAR or ${VAR};\n\
+                    write \\$ for a literal dollar sign.\n\
+                    The environment is saved to /.shell_env.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "unset",
+        usage: "unset VAR [VAR...]",
+        description: "Remove environment variables",
+        long_help: "Removes one or more variables from the shell environment.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "alias",
+        usage: "alias [name[='command args']]",
+        description: "Define or list command aliases",
+        long_help: "Without arguments, lists every alias. With a name, shows\n\
+                    that alias; with name=command, defines it, e.g.\n\
+                    alias ll='ls -l'. A command line starting with the name\n\
+                    runs the command instead, with the rest of the line after\n\
+                    it. An alias may start with another alias. $VARIABLES in\n\
+                    it are expanded each time it is used.\n\
+                    Aliases are saved to /.shell_aliases.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "unalias",
+        usage: "unalias -a | unalias NAME [NAME...]",
+        description: "Remove command aliases",
+        long_help: "Removes the aliases named, or all of them with -a.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "env",
+        usage: "env",
+        description: "List environment variables",
+        long_help: "Prints every shell variable as NAME=value.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "exit",
+        usage: "exit [status]",
+        description: "Exit the shell (also 'quit')",
+        long_help: "Leaves the interactive shell. In a script, ends the script\n\
+                    with the status given, or that of the last command.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "run",
+        usage: "run [-e] [-x] <script>",
+        description: "Run the commands in a file",
+        long_help: "Runs each line of the script as if it were typed. Blank\n\
+                    lines are skipped and # starts a comment. && runs the next\n\
+                    command if the one before succeeded, || if it failed.\n\
+                    The status is that of the last command run.\n\
+                    -e, --exit-on-error  stop at the first command that fails,\n\
+                                         unless it is tested by && or ||\n\
+                    -x, --trace          print each line before running it\n\
+                    'set -e', 'set -x' and their +e/+x forms switch these\n\
+                    modes inside a script; 'exit' ends the script.\n\
+                    At boot, /etc/rc (or else /.elinrc) runs this way before\n\
+                    the first prompt; Ctrl-C during boot skips it, and\n\
+                    'config set shell.rc off' turns it off.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "source",
+        usage: "source [-e] [-x] <script>",
+        description: "Run the commands in a file (same as run)",
+        long_help: "Same as 'run'. Variables the script sets stay set.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "test",
+        usage: "test [!] <condition>",
+        description: "Check a condition (also [ condition ])",
+        long_help: "Succeeds if the condition holds, fails with status 1 if not\n\
+                    and with status 2 if it cannot be read. ! negates it.\n\
+                    -e PATH  exists            -f PATH  is a regular file\n\
+                    -d PATH  is a directory    -L PATH  is a symbolic link\n\
+                    -s PATH  is not empty\n\
+                    -n STR, -z STR  string is or is not empty\n\
+                    A = B, A != B   strings are equal or differ\n\
+                    A -eq B (-ne -lt -le -gt -ge)  integer comparison",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "true",
+        usage: "true",
+        description: "Succeed, with status 0",
+        long_help: "Does nothing and succeeds.",
+        category: CommandCategory::Shell,
+    },
+    CommandInfo {
+        name: "false",
+        usage: "false",
+        description: "Fail, with status 1",
+        long_help: "Does nothing and fails with status 1.",
+        category: CommandCategory::Shell,
+    },
+    
+    // System information
+    CommandInfo {
+        name: "version",
+        usage: "version",
+        description: "Show kernel version and features",
+        long_help: "Prints the kernel version and the list of compiled-in features.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "uptime",
+        usage: "uptime",
+        description: "Show time since boot",
+        long_help: "Prints the monotonic clock (hours:minutes:seconds since boot)\n\
+                    and the number of kernel timer ticks so far.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "date",
+        usage: "date [+FORMAT]",
+        description: "Show the date and time",
+        long_help: "Prints the wall-clock time from the goldfish RTC, in UTC.\n\
+                    +FORMAT chooses the fields: %Y %m %d %H %M %S, %F for\n\
+                    %Y-%m-%d, %T for %H:%M:%S, %s for seconds since 1970 and %%\n\
+                    for a percent sign. Example: date +%F_%H%M",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "sleep",
+        usage: "sleep <seconds>",
+        description: "Wait for a number of seconds",
+        long_help: "Sleeps with nanosleep for the given time, which may have a\n\
+                    fraction down to nanoseconds: sleep 2, sleep 0.25.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "at",
+        usage: "at [<+seconds|HH:MM> <command> | -d <id>]",
+        description: "Run a command later, once",
+        long_help: "Queues a shell command to run once, after +N seconds (or +Nm,\n\
+                    +Nh, +Nd) or at HH:MM UTC by the RTC. Jobs run while the shell\n\
+                    waits for input. With no arguments, lists the queue; -d takes\n\
+                    a job off it. Example: at +30 sync",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "cron",
+        usage: "cron [reload]",
+        description: "Show the periodic jobs",
+        long_help: "Lists the jobs from /etc/cron.cfg with their interval, the\n\
+                    seconds until they next run, their runs and last status.\n\
+                    Each line of the file is an interval and a command, such as\n\
+                    '1h logrotate'. reload reads the file again.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "memory",
+        usage: "memory",
+        description: "Show memory regions and allocator statistics",
+        long_help: "Displays detected RAM regions and the state of the unified\n\
+                    memory manager, including allocator mode and usage.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "heap",
+        usage: "heap",
+        description: "Show heap usage information",
+        long_help: "Prints kernel heap size, used bytes and utilization.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "heap-reset",
+        usage: "heap-reset",
+        description: "Reset the heap allocator (testing only)",
+        long_help: "Resets the kernel heap allocator state. Intended for testing;\n\
+                    memory handed out before the reset must not be used afterwards.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "mmap",
+        usage: "mmap",
+        description: "Show memory mapping information",
+        long_help: "Lists the kernel's virtual and device memory mappings with\n\
+                    their addresses, sizes and permissions.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "peek",
+        usage: "peek [-w N] <addr|file:offset> <len>",
+        description: "Show bytes of memory or a file",
+        long_help: "Prints <len> bytes (at most 256) as a hex dump. The target is\n\
+                    an address in RAM or in a registered device mapping, or\n\
+                    file:offset. Numbers are decimal or 0x hex. -w 2, 4 or 8\n\
+                    reads memory that many bytes at a time, for device registers;\n\
+                    the address and length must then be multiples of N.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "poke",
+        usage: "poke [-w N] <addr|file:offset> <bytes>",
+        description: "Write bytes to memory or a file",
+        long_help: "Writes hex bytes, given in storage order as pairs of digits in\n\
+                    one or more groups (poke 0x80200000 13 00 00 00 or\n\
+                    poke /bin/app:64 13000000), at most 256 at once. Memory must\n\
+                    be RAM or a writable device mapping and aligned as for peek;\n\
+                    files are patched in place and never grow.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "devices",
+        usage: "devices",
+        description: "List detected VirtIO devices",
+        long_help: "Shows the VirtIO devices found on the MMIO bus and their state.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "mount",
+        usage: "mount [-r] [-b] [VOLUME [DIR]]",
+        description: "Show or change mounted block devices",
+        long_help: "Without an argument, lists what is mounted where. With a\n\
+                    volume - a disk or partition (blk0, blk1p2, usb0p1, ...), LABEL=name\n\
+                    or UUID=uuid - writes out cached data and mounts its\n\
+                    filesystem on / instead; the working directory goes back\n\
+                    to /. With a directory as well, mounts it there, beside\n\
+                    the root filesystem.\n\
+                    -r, --read-only   Refuse changes to a filesystem mounted on DIR\n\
+                    -b, --backup-superblock\n\
+                                      Use a backup superblock, the primary being damaged\n\
+                    At boot, root=VOLUME picks the root device; otherwise the\n\
+                    first disk or partition with a filesystem is used. Disks\n\
+                    attached later are mounted read-only on /media/<label> unless\n\
+                    a rule in /etc/automount says otherwise, one per line:\n\
+                      label=NAME|uuid=UUID|blkN[pM]|usbN[pM]|*  mount [ro|rw] | ignore",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "parts",
+        usage: "parts [blkN|usbN]",
+        description: "List disk partitions",
+        long_help: "Shows the MBR or GPT partition table of each block device,\n\
+                    or just the one named, with the type, label and UUID of\n\
+                    each filesystem found. Partition M of blkN is mounted as\n\
+                    blkNpM; a disk without a table is mounted whole.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "lsblk",
+        usage: "lsblk [blkN|usbN]",
+        description: "List block devices, partitions and mountpoints",
+        long_help: "Shows each block device and its partitions as a tree, with\n\
+                    size, disk health (ok, degraded or offline), filesystem type,\n\
+                    label, UUID and where it is mounted.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "diskstats",
+        usage: "diskstats",
+        description: "Show block device I/O and error counters",
+        long_help: "Shows the sectors read and written by each block device, the\n\
+                    requests that failed after all retries, the retries made and\n\
+                    whether the device is ok, degraded or offline. A device goes\n\
+                    offline when requests keep failing; attach it again to reset it.\n\
+                    Retries are set with virtio.blk_retries=N and virtio.blk_backoff=N\n\
+                    on the kernel command line.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "health",
+        usage: "health",
+        description: "Summarize error counters and traps since boot",
+        long_help: "One place to look before digging further: allocation failures,\n\
+                    disk I/O errors and retries, offline devices, dropped packets,\n\
+                    transmit errors, timer ticks that came late because interrupts\n\
+                    were held off, lock attempts that gave up, programs killed by\n\
+                    a fault, kernel faults survived, and every trap taken by cause.\n\
+                    The last line is ok, or names the counters that are not zero.\n\
+                    The same text is in /proc/health.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "traps",
+        usage: "traps",
+        description: "Count traps taken by cause",
+        long_help: "Lists every interrupt and exception cause taken since boot\n\
+                    with its scause code and count, named as the privileged spec\n\
+                    does (reserved and custom codes included), then how many user\n\
+                    programs faults killed and how many kernel faults in system\n\
+                    calls were survived. The same table is in /proc/traps.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "memdebug",
+        usage: "memdebug [-a]",
+        description: "Show live allocations by caller",
+        long_help: "With sysctl vm.alloc_tracking=on, the memory manager records\n\
+                    each allocation with the source line that asked for it.\n\
+                    memdebug lists, per line, the blocks and bytes still live\n\
+                    and the allocations and frees made, most bytes first, with\n\
+                    frees that matched no allocation (double frees) and size\n\
+                    mismatches. -a lists each live block with its address,\n\
+                    size and age. vm.alloc_poison=on fills freed memory with\n\
+                    0x6b so a use after free is easy to spot.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "kconfig",
+        usage: "kconfig dump [FILE]",
+        description: "Report the effective configuration",
+        long_help: "Prints, or saves to FILE, what this image is and how it came\n\
+                    up: the version, Cargo features and profile it was built\n\
+                    with, the board from the device tree (or the QEMU virt\n\
+                    layout assumed without one), the memory mode and heap sizes\n\
+                    chosen for the RAM found, which driver took each device, the\n\
+                    mount table and every sysctl value. Each section starts with\n\
+                    a # line, so saved reports from two machines can be diffed.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "beep",
+        usage: "beep [-f HZ] [-l MS]",
+        description: "Play a tone",
+        long_help: "Plays a square wave of -f HZ (default 440, 20 to 20000) for\n\
+                    -l MS milliseconds (default 200, at most 10000) on a VirtIO\n\
+                    sound device if there is one, else on a GPIO buzzer the\n\
+                    device tree describes, else rings the terminal bell, and\n\
+                    says which. sysctl kernel.beep sets whether the kernel\n\
+                    beeps on its own at boot and on fatal errors.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "usb",
+        usage: "usb [read usbN SECTOR]",
+        description: "List USB controllers, devices and disks",
+        long_help: "Lists the EHCI and xHCI host controllers found in the device\n\
+                    tree, the devices enumerated on their root ports with vendor,\n\
+                    product, class and speed, and the mass-storage disks with\n\
+                    their size and partitions. 'read usbN SECTOR' dumps one\n\
+                    512-byte sector of a disk. Only EHCI moves data so far, and\n\
+                    USB disks are not mounted.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "bootchart",
+        usage: "bootchart",
+        description: "Show how long each boot stage took",
+        long_help: "Lists the stages of boot in order, from firmware and the\n\
+                    bootloader through memory, the MMU, device probes, the\n\
+                    filesystem mount and graphics to the shell, with when each\n\
+                    started and how long it took in milliseconds, its share of\n\
+                    the boot and a bar scaled to the longest. Device probes are\n\
+                    indented under their stage. The same text is in /proc/bootchart.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "fsck",
+        usage: "fsck [-r] VOLUME",
+        description: "Check and repair ext2 superblocks",
+        long_help: "Checks the primary superblock of an ext2 volume and looks for\n\
+                    a valid backup copy in the block groups that keep one.\n\
+                    -r, --restore   Overwrite a damaged primary superblock and\n\
+                                    group descriptors with the backup copies.\n\
+                                    The volume must not be mounted.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "umount",
+        usage: "umount DIR",
+        description: "Unmount the filesystem mounted on a directory",
+        long_help: "Writes out cached data for the filesystem mounted on DIR and\n\
+                    unmounts it. The root filesystem is changed with mount instead.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "rescan",
+        usage: "rescan",
+        description: "Look for VirtIO devices attached or removed",
+        long_help: "Re-reads every VirtIO MMIO slot. New devices are handed to\n\
+                    their driver and removed ones are detached. The shell also\n\
+                    scans in the background while it waits for input.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "graphics",
+        usage: "graphics",
+        description: "Show graphics information",
+        long_help: "Prints framebuffer resolution and VirtIO GPU status.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "screenshot",
+        usage: "screenshot <file>",
+        description: "Save the screen as a PPM image",
+        long_help: "Writes what the framebuffer holds to <file> as a binary PPM\n\
+                    (P6) image, 8 bits per channel. The file is replaced if it\n\
+                    exists.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "gfxbench",
+        usage: "gfxbench [-n frames]",
+        description: "Benchmark the framebuffer and VirtIO GPU",
+        long_help: "Fills the framebuffer, renders a screen of glyphs into it and\n\
+                    flushes it to the VirtIO GPU, each for a number of frames,\n\
+                    and prints the fill rate, glyphs per second and flush times.\n\
+                    Every flush sends the whole frame. The screen is cleared\n\
+                    afterwards.\n\
+                    \n\
+                      -n, --frames N  frames for each test, 1..1000 (default 20)\n\
+                    \n\
+                    Counters of every flush since boot are in /proc/framestats.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "syscalls",
+        usage: "syscalls [-H]",
+        description: "List implemented system calls",
+        long_help: "Lists every implemented system call with its number, grouped\n\
+                    by category.\n\
+                    -H, --header  Print the numbers as a C header for user programs",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "abitest",
+        usage: "abitest [file]",
+        description: "Check the syscall ABI from the shell",
+        long_help: "Runs process, memory, console, file, timer and eventfd operations through the\n\
+                    syscall entry path user programs use, and compares the\n\
+                    results with the kernel's own view. The file (default: the\n\
+                    first file in /) is read with openat, read and close.\n\
+                    Exit status is 1 if any check failed.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "selftest",
+        usage: "selftest",
+        description: "Check the crypto primitives against known answers",
+        long_help: "Runs AES-128, AES-128-CTR, SHA-256 and HMAC-SHA256 on\n\
+                    published test vectors (FIPS-197, SP 800-38A, FIPS 180-4,\n\
+                    RFC 4231) and reports each. Exit status is 1 if any failed.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "fscheck",
+        usage: "fscheck",
+        description: "Check filesystem status and metadata",
+        long_help: "Displays the mounted filesystem type, superblock information,\n\
+                    mount status and block cache statistics.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "sync",
+        usage: "sync",
+        description: "Write cached disk data to the disk",
+        long_help: "Writes every dirty sector in the block cache to the disk.\n\
+                    The cache is write-back unless the kernel command line has\n\
+                    blockcache=writethrough; shutdown and reboot also sync.\n\
+                    While the shell is idle, sectors dirty for longer than\n\
+                    fs.blockcache.writeback_secs (default 5, 0 = off) are\n\
+                    written in the background.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "config",
+        usage: "config [list | get <key> | set <key> <value> | unset <key>]",
+        description: "Show system configuration or edit the config store",
+        long_help: "Without arguments, displays hardware detection results and the\n\
+                    memory allocations calculated from them.\n\
+                    \n\
+                    The subcommands read and change the config store in /.config,\n\
+                    which keeps settings across reboots. Each change is written as\n\
+                    a transaction, so a power loss leaves either the old or the new\n\
+                    settings, never a mix. Keys in use:\n\
+                      boot.slot    kernel slot the bootloader starts (a or b); any\n\
+                                   boot.cfg key works with a boot. prefix\n\
+                      net.mode     static or dhcp, saved by ifconfig\n\
+                      net.ip, net.netmask, net.gateway  the static addresses",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "sysctl",
+        usage: "sysctl [-s] [-d] [name[=value]]...",
+        description: "Show or change kernel parameters",
+        long_help: "Without names, lists every parameter and its value. A name shows\n\
+                    one parameter; name=value changes it, checked against its type\n\
+                    and range. The same parameters are files under /proc/sys, e.g.\n\
+                    fs.blockcache.mode is /proc/sys/fs/blockcache/mode.\n\
+                    \n\
+                    Options:\n\
+                      -s, --save      also keep the value in the config store, to be\n\
+                                      applied again at boot\n\
+                      -d, --describe  show what each parameter does and its values\n\
+                    \n\
+                    Parameters:\n\
+                      kernel.printk          console log level, 0..9\n\
+                      kernel.log.disk        also write the log to disk: off, on\n\
+                      kernel.log.max_size    bytes at which the log file is rotated\n\
+                      fs.blockcache.sectors  block cache size in sectors\n\
+                      fs.blockcache.mode     writeback or writethrough\n\
+                      fs.blockcache.verify   cached sector checksums: off, log, panic\n\
+                      shell.low_bandwidth    shorter input line redraws: off, on",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "dmesg",
+        usage: "dmesg [-r] [-c|-C] [-l level]",
+        description: "Show the kernel log",
+        long_help: "Prints the console lines kept in the kernel log buffer, oldest\n\
+                    first, each with the seconds since boot. Status lines below\n\
+                    the console log level (sysctl kernel.printk) are kept too,\n\
+                    except debug and trace lines, kept only when printed.\n\
+                    The buffer holds 32 KB; older lines are dropped.\n\
+                    -r          show each line's <level> as syslog(2) reads it\n\
+                    -l level    only lines at this level or more severe\n\
+                                (3 errors, 4 warnings, 5 ok, 6 info and plain,\n\
+                                7 debug, 8 trace)\n\
+                    -c          clear the buffer after printing it\n\
+                    -C          clear the buffer without printing it",
+
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "loglevel",
+        usage: "loglevel [subsystem] [level]",
+        description: "Show or set the console log level",
+        long_help: "Without arguments, shows the global console log level and that\n\
+                    of each subsystem. A level sets the global one, the same as\n\
+                    sysctl kernel.printk; a subsystem and a level set that\n\
+                    subsystem's own, e.g. 'loglevel virtio debug', and 'global'\n\
+                    has it follow the global level again.\n\
+                    \n\
+                    Levels, each printing its kind of line and everything more\n\
+                    severe: quiet, error, warn, notice, info, debug, trace, or a\n\
+                    number 0-9. Boot uses info.\n\
+                    Subsystems: virtio, ext2, fs, mm, net",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "logrotate",
+        usage: "logrotate",
+        description: "Rotate the kernel log file",
+        long_help: "With sysctl kernel.log.disk=on the kernel log is also written\n\
+                    to /var/log/kernel.log, when the shell is idle and on sync,\n\
+                    and rotated once it reaches kernel.log.max_size. logrotate\n\
+                    writes out what is pending and rotates it now: kernel.log\n\
+                    becomes kernel.log.1, kernel.log.1 becomes kernel.log.2.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "ps",
+        usage: "ps",
+        description: "List processes",
+        long_help: "Shows the process table: PID, parent PID, state and name.\n\
+                    PID 1 is the shell; programs run as its children.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "jobs",
+        usage: "jobs",
+        description: "List programs running in the background",
+        long_help: "A program followed by & (hello &) runs in the background: the\n\
+                    shell prints its job number and PID and goes back to the prompt,\n\
+                    running the program while it waits for keys. jobs lists each one\n\
+                    with its number, PID, whether it is running or sleeping, and the\n\
+                    command. A job that ends is reported at the prompt. Background\n\
+                    programs read end of file from the console, and need hardware\n\
+                    paging; at most 4 run at once.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "fg",
+        usage: "fg [%job]",
+        description: "Run a background job in the foreground",
+        long_help: "Brings a background job, the newest unless one is named by its\n\
+                    number from jobs (fg %2 or fg 2), to the foreground and waits for\n\
+                    it to exit, with the console's keys its input. The status is the\n\
+                    program's exit code.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "kill",
+        usage: "kill <pid>",
+        description: "End a background job",
+        long_help: "Ends the background job with the given PID, as listed by jobs\n\
+                    or ps, and frees its memory. Its exit status is 137, as for a\n\
+                    program killed with SIGKILL elsewhere.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "pmap",
+        usage: "pmap <pid>",
+        description: "Show the address space of a process",
+        long_help: "Lists each mapping of the process: address range, size, pages\n\
+                    resident, permissions (rwxp) and backing, which is anon, file\n\
+                    or device, with the file or [heap]/[stack] label. The same\n\
+                    lines are in /proc/<pid>/maps. The shell (PID 1) runs in the\n\
+                    kernel's address space and has no user mappings.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "swapon",
+        usage: "swapon [-c KB] [FILE|VOLUME]",
+        description: "Swap to a file or partition, or show swap use",
+        long_help: "Uses FILE, or a disk or partition (blkN[pM], LABEL=... or UUID=...)\n\
+                    with nothing mounted from it, as swap. -c creates FILE with KB\n\
+                    kilobytes first. Once free memory drops below vm.min_free_kbytes,\n\
+                    or runs out, writable pages of a process not used lately are\n\
+                    written to swap and read back on the next access. The swap file\n\
+                    cannot be deleted while in use. With no argument, shows the swap\n\
+                    area and how many pages went out and came back.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "swapoff",
+        usage: "swapoff",
+        description: "Stop swapping",
+        long_help: "Reads every swapped out page back into memory and stops using\n\
+                    the swap area. Fails, leaving swap on, if there is not enough\n\
+                    memory for them.",
+        category: CommandCategory::System,
+    },
+    
+    // Filesystem operations
+    CommandInfo {
+        name: "ls",
+        usage: "ls [-h] [-l] [path]",
+        description: "List files/dirs (default: current directory)",
+        long_help: "Lists the contents of a directory. Relative paths are\n\
+                    resolved against the current working directory.\n\
+                    -l, --long   Show mode, links, owner, group, size and\n\
+                                 modification time, from the inode\n\
+                    -h, --help   Show the usage line",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "cat",
+        usage: "cat [-h] <path>...",
+        description: "Display file contents",
+        long_help: "Prints the contents of one or more files to the console,\n\
+                    in the order given.\n\
+                    -h, --help   Show the usage line",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "echo",
+        usage: "echo [message]",
+        description: "Print a message (newline if no message)",
+        long_help: "Prints its arguments followed by a newline.\n\
+                    Environment variables are expanded before printing.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "pwd",
+        usage: "pwd",
+        description: "Print current working directory",
+        long_help: "Prints the absolute path of the current working directory.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "touch",
+        usage: "touch <path>",
+        description: "Create an empty file, or update its times",
+        long_help: "Creates a new empty file. If the file already exists, its\n\
+                    access and modification times are set to now instead.\n\
+                    ext2 files get the time they were created, last read and\n\
+                    last changed from the RTC, or from boot until it is set.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "mkdir",
+        usage: "mkdir <path>",
+        description: "Create a directory at the specified path",
+        long_help: "Creates a new directory. The parent directory must exist.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "rm",
+        usage: "rm <path>",
+        description: "Remove a file at the specified path",
+        long_help: "Deletes a regular file. Use 'rmdir' for directories.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "ln",
+        usage: "ln [-s] TARGET LINK",
+        description: "Create a hard or symbolic link",
+        long_help: "Gives file TARGET the further name LINK on the same ext2\n\
+                    filesystem; both names are the same file, and its data is\n\
+                    freed with the last name removed. Directories cannot be\n\
+                    hard linked. With -s, LINK is a symbolic link instead:\n\
+                    TARGET is stored as given and need not exist, a relative\n\
+                    one is taken from the directory holding LINK, and 'rm LINK'\n\
+                    removes the link alone.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "mv",
+        usage: "mv FROM TO",
+        description: "Move or rename a file or directory",
+        long_help: "Renames FROM to TO on the same filesystem without copying\n\
+                    any data. If TO is an existing directory, FROM moves into\n\
+                    it under its own name; otherwise a file at TO is replaced.\n\
+                    A directory can replace only an empty directory, and cannot\n\
+                    move into itself or hold a mount point.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "shred",
+        usage: "shred [-n N] FILE",
+        description: "Overwrite a file's blocks, then remove it",
+        long_help: "Overwrites every block of FILE with alternating bit patterns\n\
+                    and a final random pass, writing each pass out to the\n\
+                    device, then discards the blocks where the device\n\
+                    supports it and deletes the file.\n\
+                    -n, --iterations N   Passes to make (default 3)\n\
+                    Flash cards may keep copies of remapped blocks that no\n\
+                    overwrite can reach.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "update",
+        usage: "update DEST=SRC...",
+        description: "Replace several files at once, all or none",
+        long_help: "Copies each SRC over its DEST as one transaction: the copies are\n\
+                    written next to their destinations as DEST.txn-new, then\n\
+                    renamed into place. If any copy or rename fails, every DEST is\n\
+                    left as it was, so a set of configuration files is never half\n\
+                    updated. Each DEST must be on the same filesystem as its copy;\n\
+                    up to 16 files.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "wipefree",
+        usage: "wipefree [-n N] DIR",
+        description: "Overwrite the free blocks of a filesystem",
+        long_help: "Overwrites every free block of the filesystem holding DIR,\n\
+                    as 'shred' does for a file, so deleted files cannot be\n\
+                    read back. Files in use are left alone.\n\
+                    -n, --iterations N   Passes to make (default 3)",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "rmdir",
+        usage: "rmdir <path>",
+        description: "Remove an empty directory at the specified path",
+        long_help: "Deletes a directory. The directory must be empty.",
+        category: CommandCategory::Filesystem,
+    },
+    CommandInfo {
+        name: "cd",
+        usage: "cd [path]",
+        description: "Change directory (default: root, use '/', '..')",
+        long_help: "Changes the current working directory. Without an argument\n\
+                    returns to '/'. Supports '.', '..' and absolute paths, and\n\
+                    fails if the target is not a directory. Each process has\n\
+                    its own working directory; programs start in the shell's,\n\
+                    and every relative path is taken from it.",
+        category: CommandCategory::Filesystem,
+    },
+    
+    // Program execution
+    CommandInfo {
+        name: "locale",
+        usage: "locale [-s] [en|ascii]",
+        description: "Show or set the message language",
+        long_help: "Without arguments, shows the current locale. 'ascii' uses\n\
+                    short 7-bit messages for limited serial consoles.\n\
+                    -s, --save   Also write the choice to /etc/locale\n\
+                    /etc/locale is read at boot. Besides 'locale=<name>' it\n\
+                    may hold '<key>=<text>' lines overriding single messages.",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "charset",
+        usage: "charset [-s] [utf8|ascii]",
+        description: "Show or set the console character set",
+        long_help: "Without arguments, shows the current setting. With 'ascii',\n\
+                    emoji and other non-ASCII output is replaced by plain\n\
+                    markers such as [o] and [x] for serial terminals.\n\
+                    -s, --save   Also record the choice in /etc/locale",
+        category: CommandCategory::System,
+    },
+    CommandInfo {
+        name: "secexec",
+        usage: "secexec [on|off]",
+        description: "Show or set signed binary enforcement",
+        long_help: "When secure exec is on, programs must carry an HMAC-SHA256\n\
+                    signature trailer made with the key given at boot, in hex, as\n\
+                    secexec.key=<hex> on the kernel command line.\n\
+                    Signed binary layout:\n\
+                    \x20 [ ELF image ][ 32-byte HMAC-SHA256 tag ][ \"ELINSIG1\" ]\n\
+                    Unsigned or modified programs are refused. secexec=on on the\n\
+                    command line, or exec.secure=on in the config store, turns it on\n\
+                    at boot; secexec on turns it on now and saves exec.secure=on.\n\
+                    Once on, it stays on until reboot.",
+        category: CommandCategory::Program,
+    },
+    
+    // Networking
+    CommandInfo {
+        name: "ifconfig",
+        usage: "ifconfig [dhcp | <ip> [-m netmask] [-g gateway]]",
+        description: "Show or configure the network interface",
+        long_help: "Without arguments, shows the interface addresses, how they\n\
+                    were configured and the ARP cache. 'dhcp' asks a DHCP server\n\
+                    for an address; an IP address sets it statically.\n\
+                    -m, --netmask  Netmask, dotted or prefix length (default: keep)\n\
+                    -g, --gateway  Default gateway (default: keep)\n\
+                    Also available as 'net'.",
+        category: CommandCategory::Network,
+    },
+    CommandInfo {
+        name: "ping",
+        usage: "ping <ip> [-c count]",
+        description: "Send ICMP echo requests and show round-trip times",
+        long_help: "Sends echo requests one at a time, waiting up to a second\n\
+                    for each reply, then prints loss and round-trip statistics.\n\
+                    -c, --count  Number of requests (default: 4)\n\
+                    Exit status is 1 if no reply arrived.",
+        category: CommandCategory::Network,
+    },
+    
+    // System control
+    CommandInfo {
+        name: "shutdown",
+        usage: "shutdown",
+        description: "Shutdown the system via SBI",
+        long_help: "Powers off the machine using the SBI system reset extension.",
+        category: CommandCategory::Control,
+    },
+    CommandInfo {
+        name: "reboot",
+        usage: "reboot",
+        description: "Reboot the system via SBI",
+        long_help: "Restarts the machine using the SBI system reset extension.",
+        category: CommandCategory::Control,
+    },
+];
+
+/// Look up a command by name (aliases resolve to their main entry)
+pub fn find_command(name: &str) -> Option<&'static CommandInfo> {
+    let name = match name {
+        "quit" => "exit",
+        "net" => "ifconfig",
+        "syscall" => "syscalls",
+        other => other,
+    };
+    COMMAND_REGISTRY.iter().find(|cmd| cmd.name == name)
+}
+
+/// Print the command overview grouped by category
+pub fn show_help() {
+    info_println!("ElinOS Commands");
+    console_println!("===============================================");
+    
+    for category in CommandCategory::ALL.iter() {
+        console_println!();
+        info_println!("{}:", category.title());
+        for cmd in COMMAND_REGISTRY.iter().filter(|cmd| cmd.category == *category) {
+            console_println!("  {:<16}- {}", cmd.usage, cmd.description);
+        }
+        
+        if *category == CommandCategory::Program {
+            console_println!("  {:<16}- Execute ELF binary directly by name", "hello_simple");
+            console_println!("  {:<16}- Execute with explicit relative path", "./hello_simple");
+            console_println!("  {:<16}- Execute with absolute path", "/programs/hello");
+        }
+    }
+    
+    console_println!();
+    console_println!("Type 'man <command>' for details on a command.");
+}
+
+/// Print the usage line for a single command
+pub fn show_usage(name: &str) -> Result<(), &'static str> {
+    let cmd = find_command(name).ok_or("No help for that command")?;
+    crate::msg_println!(crate::messages::Msg::Usage, cmd.usage);
+    console_println!("  {}", cmd.description);
+    Ok(())
+}
+
+/// Render the manual page for a command through the pager
+pub fn show_man(name: &str) -> Result<(), &'static str> {
+    let cmd = find_command(name).ok_or("No manual entry for that command")?;
+    
+    // Assemble the page as owned lines so the pager can count them
+    let mut page: heapless::Vec<heapless::String<128>, 48> = heapless::Vec::new();
+    let mut add_line = |args: core::fmt::Arguments| {
+        let mut line = heapless::String::<128>::new();
+        let _ = core::fmt::write(&mut line, args);
+        let _ = page.push(line);
+    };
+    
+    add_line(format_args!("NAME"));
+    add_line(format_args!("    {} - {}", cmd.name, cmd.description));
+    add_line(format_args!(""));
+    add_line(format_args!("SYNOPSIS"));
+    add_line(format_args!("    {}", cmd.usage));
+    add_line(format_args!(""));
+    add_line(format_args!("DESCRIPTION"));
+    for line in cmd.long_help.lines() {
+        add_line(format_args!("    {}", line));
+    }
+    
+    let lines = page.iter().map(|line| line.as_str());
+    crate::pager::page_lines(lines);
+    Ok(())
+}
@@ -0,0 +1,272 @@
+//! Pointer and keyboard input
+//!
+//! Events from the VirtIO input device are taken off its queue whenever
+//! the shell wakes and when /dev/input is read. Pointer events move the
+//! pointer, kept in screen coordinates: a mouse's relative motion is added
+//! up and clamped to the screen, a tablet's absolute position is scaled
+//! from the device's range. Once a report that moved the pointer is
+//! complete the screen is flushed, which draws the cursor in its new place.
+//! The buttons select and paste console text; see `graphics::selection`.
+//! Keys are turned into the bytes a US keyboard types, arrows into the
+//! escape sequences a terminal sends, and queued for the console TTY,
+//! which reads them after pasted text and before the UART.
+//!
+//! Every event is also queued for /dev/input, as Linux's 24-byte
+//! `struct input_event`: seconds and microseconds since boot, then type,
+//! code and value. When the queue is full the oldest events go.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use elinos_common::tty::{self, Source};
+use heapless::Deque;
+use spin::Mutex;
+
+use crate::graphics::selection::{self, Cell};
+use crate::virtio::input::{InputEvent, VirtioInput, VIRTIO_INPUT};
+
+// Event types and codes, as in Linux
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const ABS_X: u16 = 0x00;
+pub const ABS_Y: u16 = 0x01;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+pub const KEY_LEFTCTRL: u16 = 29;
+pub const KEY_LEFTSHIFT: u16 = 42;
+pub const KEY_RIGHTSHIFT: u16 = 54;
+pub const KEY_RIGHTCTRL: u16 = 97;
+pub const KEY_UP: u16 = 103;
+pub const KEY_LEFT: u16 = 105;
+pub const KEY_RIGHT: u16 = 106;
+pub const KEY_DOWN: u16 = 108;
+
+/// What the keys of a US keyboard type, by code: plain, then with shift;
+/// 0 for keys that type nothing
+const KEYMAP: [(u8, u8); 58] = [
+    (0, 0), (0x1b, 0x1b),
+    (b'1', b'!'), (b'2', b'@'), (b'3', b'#'), (b'4', b'$'), (b'5', b'%'),
+    (b'6', b'^'), (b'7', b'&'), (b'8', b'*'), (b'9', b'('), (b'0', b')'),
+    (b'-', b'_'), (b'=', b'+'), (0x7f, 0x7f), (b'\t', b'\t'),
+    (b'q', b'Q'), (b'w', b'W'), (b'e', b'E'), (b'r', b'R'), (b't', b'T'),
+    (b'y', b'Y'), (b'u', b'U'), (b'i', b'I'), (b'o', b'O'), (b'p', b'P'),
+    (b'[', b'{'), (b']', b'}'), (b'\r', b'\r'), (0, 0),
+    (b'a', b'A'), (b's', b'S'), (b'd', b'D'), (b'f', b'F'), (b'g', b'G'),
+    (b'h', b'H'), (b'j', b'J'), (b'k', b'K'), (b'l', b'L'),
+    (b';', b':'), (b'\'', b'"'), (b'`', b'~'), (0, 0), (b'\\', b'|'),
+    (b'z', b'Z'), (b'x', b'X'), (b'c', b'C'), (b'v', b'V'), (b'b', b'B'),
+    (b'n', b'N'), (b'm', b'M'), (b',', b'<'), (b'.', b'>'), (b'/', b'?'),
+    (0, 0), (b'*', b'*'), (0, 0), (b' ', b' '),
+];
+
+/// Bytes of one event read from /dev/input
+pub const EVENT_RECORD_SIZE: usize = 24;
+
+/// Events kept for /dev/input
+const MAX_QUEUED_EVENTS: usize = 128;
+
+/// Typed bytes kept for the console; more are dropped
+const MAX_TYPED: usize = 64;
+
+/// Where the pointer is and which buttons are down
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pointer {
+    pub x: u32,
+    pub y: u32,
+    /// Bit 0 left, bit 1 right, bit 2 middle
+    pub buttons: u8,
+}
+
+struct InputState {
+    pointer: Pointer,
+    /// The pointer moved since the last complete report
+    moved: bool,
+    queue: Deque<(u64, InputEvent), MAX_QUEUED_EVENTS>,
+    shift: bool,
+    ctrl: bool,
+    /// Bytes typed and not yet read by the console
+    typed: Deque<u8, MAX_TYPED>,
+}
+
+static STATE: Mutex<InputState> = Mutex::new(InputState {
+    pointer: Pointer { x: 0, y: 0, buttons: 0 },
+    moved: false,
+    queue: Deque::new(),
+    shift: false,
+    ctrl: false,
+    typed: Deque::new(),
+});
+
+/// Set once a device has sent a pointer event, so the cursor is shown
+static POINTER_SEEN: AtomicBool = AtomicBool::new(false);
+
+/// Where the pointer is, if there is one
+pub fn pointer() -> Option<Pointer> {
+    POINTER_SEEN.load(Ordering::Relaxed).then(|| STATE.lock().pointer)
+}
+
+/// Have the console read pasted text, then typed keys
+pub fn init() {
+    let _ = tty::add_source(Source { name: "paste", read: selection::next_pasted_byte });
+    let _ = tty::add_source(Source { name: "keyboard", read: next_typed_byte });
+}
+
+/// The next byte typed on the keyboard, if any
+fn next_typed_byte() -> Option<u8> {
+    poll();
+    STATE.lock().typed.pop_front()
+}
+
+/// Whether there is an input device
+pub fn device_present() -> bool {
+    VIRTIO_INPUT.lock().is_initialized()
+}
+
+/// Take the events the device posted. Does nothing if the device is in
+/// use elsewhere, e.g. by an interrupted poll.
+pub fn poll() {
+    let mut redraw = false;
+    {
+        let Some(mut device) = VIRTIO_INPUT.try_lock() else {
+            crate::health::count(crate::health::Event::LockContended);
+            return;
+        };
+        let mut state = STATE.lock();
+        while let Some(event) = device.next_event() {
+            redraw |= state.handle(&device, event);
+        }
+    }
+    if redraw {
+        let _ = crate::graphics::present();
+    }
+}
+
+impl InputState {
+    /// Queue `event` and apply it to the pointer and the selection; returns
+    /// true when the screen needs redrawing
+    fn handle(&mut self, device: &VirtioInput, event: InputEvent) -> bool {
+        if self.queue.is_full() {
+            self.queue.pop_front();
+        }
+        let _ = self.queue.push_back((crate::syscall::time::monotonic_ns(), event));
+
+        let (width, height) = crate::graphics::get_dimensions().unwrap_or((640, 480));
+        let pointer = &mut self.pointer;
+        match (event.type_, event.code) {
+            (EV_REL, REL_X) => pointer.x = (pointer.x as i64 + event.value as i32 as i64).clamp(0, width as i64 - 1) as u32,
+            (EV_REL, REL_Y) => pointer.y = (pointer.y as i64 + event.value as i32 as i64).clamp(0, height as i64 - 1) as u32,
+            (EV_ABS, ABS_X) => pointer.x = scale(event.value, device.abs_range(0), width),
+            (EV_ABS, ABS_Y) => pointer.y = scale(event.value, device.abs_range(1), height),
+            (EV_KEY, BTN_LEFT..=BTN_MIDDLE) => {
+                let bit = 1 << (event.code - BTN_LEFT);
+                let pressed = event.value != 0;
+                if pressed {
+                    pointer.buttons |= bit;
+                } else {
+                    pointer.buttons &= !bit;
+                }
+                return match (event.code, pressed) {
+                    (BTN_LEFT, true) => {
+                        if let Some(cell) = Cell::at(pointer.x, pointer.y) {
+                            selection::begin(cell);
+                        }
+                        true
+                    }
+                    // A click drops the selection, which needs a redraw
+                    (BTN_LEFT, false) => !selection::finish(),
+                    (BTN_MIDDLE, true) => {
+                        selection::paste();
+                        false
+                    }
+                    _ => false,
+                };
+            }
+            (EV_KEY, code) if code < BTN_LEFT => {
+                self.key(code, event.value != 0);
+                return false;
+            }
+            (EV_SYN, _) => {
+                if self.moved && self.pointer.buttons & 1 != 0 {
+                    if let Some(cell) = Cell::at(self.pointer.x, self.pointer.y) {
+                        selection::extend(cell);
+                    }
+                }
+                return core::mem::take(&mut self.moved);
+            }
+            _ => return false,
+        }
+        POINTER_SEEN.store(true, Ordering::Relaxed);
+        self.moved = true;
+        false
+    }
+
+    /// Track the modifiers and queue what a key press or repeat types
+    fn key(&mut self, code: u16, pressed: bool) {
+        match code {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => self.shift = pressed,
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => self.ctrl = pressed,
+            _ if !pressed => {}
+            KEY_UP => self.type_bytes(b"\x1b[A"),
+            KEY_DOWN => self.type_bytes(b"\x1b[B"),
+            KEY_RIGHT => self.type_bytes(b"\x1b[C"),
+            KEY_LEFT => self.type_bytes(b"\x1b[D"),
+            _ => {
+                let Some(&(plain, shifted)) = KEYMAP.get(code as usize) else { return };
+                let mut ch = if self.shift { shifted } else { plain };
+                if self.ctrl && ch.is_ascii_alphabetic() {
+                    ch &= 0x1f;
+                }
+                if ch != 0 {
+                    self.type_bytes(&[ch]);
+                }
+            }
+        }
+    }
+
+    fn type_bytes(&mut self, bytes: &[u8]) {
+        if self.typed.capacity() - self.typed.len() < bytes.len() {
+            return;
+        }
+        for &byte in bytes {
+            let _ = self.typed.push_back(byte);
+        }
+    }
+}
+
+/// Absolute position `value` in `range` as a coordinate from 0 to `size - 1`
+fn scale(value: u32, range: Option<crate::virtio::input::AbsRange>, size: u32) -> u32 {
+    let Some(range) = range.filter(|range| range.max > range.min) else {
+        return value.min(size - 1);
+    };
+    let offset = value.clamp(range.min, range.max) - range.min;
+    (offset as u64 * (size as u64 - 1) / (range.max - range.min) as u64) as u32
+}
+
+/// Copy queued events into `buffer` as whole `EVENT_RECORD_SIZE` records,
+/// waiting for one if there are none. Returns the bytes copied; 0 if there
+/// is no input device or `buffer` cannot take a record.
+pub fn read_events(buffer: &mut [u8]) -> usize {
+    if buffer.len() < EVENT_RECORD_SIZE || !device_present() {
+        return 0;
+    }
+    crate::trap::wait_until(u64::MAX, || {
+        poll();
+        (!STATE.lock().queue.is_empty()).then_some(())
+    });
+
+    let mut state = STATE.lock();
+    let mut count = 0;
+    for record in buffer.chunks_exact_mut(EVENT_RECORD_SIZE) {
+        let Some((ns, event)) = state.queue.pop_front() else { break };
+        record[0..8].copy_from_slice(&(ns / 1_000_000_000).to_le_bytes());
+        record[8..16].copy_from_slice(&(ns % 1_000_000_000 / 1000).to_le_bytes());
+        record[16..18].copy_from_slice(&event.type_.to_le_bytes());
+        record[18..20].copy_from_slice(&event.code.to_le_bytes());
+        record[20..24].copy_from_slice(&event.value.to_le_bytes());
+        count += EVENT_RECORD_SIZE;
+    }
+    count
+}
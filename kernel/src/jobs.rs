@@ -0,0 +1,239 @@
+//! Background jobs: programs started with `&`
+//!
+//! A program started with `&` is loaded into an address space of its own
+//! and left in the process table while the shell goes back to its prompt.
+//! There is one hart and no scheduler, so the shell runs the jobs itself:
+//! while it waits for a key, it gives each job in turn a slice of
+//! `SLICE_TICKS` kernel ticks. The timer interrupt ends the slice and keeps
+//! the program's registers here for the next one; a key typed ends it at
+//! once, so the console answers as if nothing else were running.
+//!
+//! A job that sleeps gives up its slice and is not run again until its
+//! sleep is over. Its reads from the console get end of file, as they
+//! would from `/dev/null`, so it cannot take the shell's keys. Other system
+//! calls that wait, and children it forks, hold the console until they
+//! return, as they do in the foreground.
+//!
+//! `jobs` lists the jobs, `fg` runs one in the foreground until it exits,
+//! and `kill` ends one by PID. A job that finishes is reported at the
+//! prompt.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use heapless::{String, Vec};
+use spin::Mutex;
+use elinos_common::{console_println, timer};
+
+use crate::elf::LoadedElf;
+use crate::memory::mmu;
+use crate::process::{self, WaitStatus, PROCESS_MANAGER};
+use crate::trap::{self, TrapContext, UserExit};
+
+/// Most jobs at once; each holds one of the MMU's user address spaces
+const MAX_JOBS: usize = 4;
+
+/// Kernel ticks a job runs before the shell looks for input again
+const SLICE_TICKS: u64 = 5;
+
+struct Job {
+    id: usize,
+    pid: i32,
+    satp: u64,
+    /// Registers to resume it with
+    frame: TrapContext,
+    /// `time` value its sleep ends at (0 = not sleeping)
+    wake: u64,
+    command: String<{ crate::MAX_COMMAND_LEN }>,
+}
+
+static JOBS: Mutex<Vec<Job, MAX_JOBS>> = Mutex::new(Vec::new());
+
+/// Job given the last slice, so the next one goes to the job after it
+static LAST_RUN: AtomicUsize = AtomicUsize::new(0);
+
+/// Set while a job runs in a slice
+static IN_BACKGROUND: AtomicBool = AtomicBool::new(false);
+
+/// Sleep asked for by the job in its slice
+static WAKE: AtomicU64 = AtomicU64::new(0);
+
+/// A job that has ended, to report
+pub struct Finished {
+    pub id: usize,
+    pub pid: i32,
+    pub status: i32,
+    pub command: String<{ crate::MAX_COMMAND_LEN }>,
+}
+
+/// What a call to `run_slice` did
+pub enum Slice {
+    /// No job was ready to run
+    Idle,
+    /// A job ran and has more to do
+    Ran,
+    /// A job ran and ended
+    Finished(Finished),
+}
+
+/// Start the loaded program `name` as a job, `command` being what was
+/// typed. Returns the job number and PID.
+pub fn start(command: &str, name: &str, loaded_elf: &LoadedElf) -> Result<(usize, i32), &'static str> {
+    if JOBS.lock().is_full() {
+        return Err("Too many background jobs");
+    }
+    let (pid, satp, frame) = process::start_program(name, loaded_elf)
+        .map_err(|_| "Failed to start the program")?;
+
+    let mut jobs = JOBS.lock();
+    let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+    let mut job = Job { id, pid, satp, frame, wake: 0, command: String::new() };
+    let _ = job.command.push_str(command);
+    if jobs.push(job).is_err() {
+        drop(jobs);
+        finish(pid, satp, process::KILLED_EXIT_CODE);
+        return Err("Too many background jobs");
+    }
+    Ok((id, pid))
+}
+
+/// Give the next job that is ready a slice of time
+pub fn run_slice() -> Slice {
+    let now = timer::ticks();
+    let (id, pid, satp, frame) = {
+        let jobs = JOBS.lock();
+        let last = LAST_RUN.load(Ordering::Relaxed);
+        let ready = |job: &&Job| job.wake <= now;
+        let Some(job) = jobs.iter().filter(ready).find(|job| job.id > last).or_else(|| jobs.iter().find(ready)) else {
+            return Slice::Idle;
+        };
+        (job.id, job.pid, job.satp, job.frame)
+    };
+    LAST_RUN.store(id, Ordering::Relaxed);
+
+    IN_BACKGROUND.store(true, Ordering::Relaxed);
+    let exit = run(pid, satp, &frame);
+    IN_BACKGROUND.store(false, Ordering::Relaxed);
+
+    match exit {
+        UserExit::Preempted(frame) => {
+            if let Some(job) = JOBS.lock().iter_mut().find(|job| job.id == id) {
+                job.frame = frame;
+                job.wake = WAKE.load(Ordering::Relaxed);
+            }
+            Slice::Ran
+        }
+        UserExit::Exited(status) => Slice::Finished(remove(id, status)),
+    }
+}
+
+/// Run job `id`, or the newest job if None, in the foreground until it exits
+pub fn foreground(id: Option<usize>) -> Result<Finished, &'static str> {
+    let (id, pid, satp, mut frame, mut wake) = {
+        let jobs = JOBS.lock();
+        let job = match id {
+            Some(id) => jobs.iter().find(|job| job.id == id).ok_or("No such job")?,
+            None => jobs.last().ok_or("No background jobs")?,
+        };
+        console_println!("{}", job.command);
+        (job.id, job.pid, job.satp, job.frame, job.wake)
+    };
+
+    // Slices one after another, so its sleeps are served here; its reads
+    // from the console wait for keys
+    loop {
+        trap::sleep_until(wake);
+        match run(pid, satp, &frame) {
+            UserExit::Preempted(next) => {
+                frame = next;
+                wake = WAKE.load(Ordering::Relaxed);
+            }
+            UserExit::Exited(status) => return Ok(remove(id, status)),
+        }
+    }
+}
+
+/// End the job with PID `pid`
+pub fn kill(pid: i32) -> Result<Finished, &'static str> {
+    let id = JOBS.lock().iter().find(|job| job.pid == pid).map(|job| job.id);
+    match id {
+        Some(id) => Ok(remove(id, process::KILLED_EXIT_CODE)),
+        None if pid == process::INIT_PID => Err("Cannot kill the shell"),
+        None if PROCESS_MANAGER.lock().get_process(pid).is_some() => Err("Not a background job"),
+        None => Err("No such process"),
+    }
+}
+
+/// Print the jobs: number, PID, whether it is sleeping, and the command
+pub fn show() {
+    let now = timer::ticks();
+    for job in JOBS.lock().iter() {
+        let state = if job.wake > now { "Sleeping" } else { "Running" };
+        console_println!("[{}]  {:>4}  {:<8}  {} &", job.id, job.pid, state, job.command);
+    }
+}
+
+/// Print a notice that a job has ended
+pub fn report(finished: &Finished) {
+    match finished.status {
+        0 => console_println!("[{}]  Done      {}", finished.id, finished.command),
+        process::KILLED_EXIT_CODE => console_println!("[{}]  Killed    {}", finished.id, finished.command),
+        status => console_println!("[{}]  Exit {:<3}  {}", finished.id, status, finished.command),
+    }
+}
+
+/// Whether the running program is a job in its slice, or a child of one
+pub fn in_background() -> bool {
+    IN_BACKGROUND.load(Ordering::Relaxed)
+}
+
+/// Put the job in its slice to sleep until `time` value `deadline`. Its
+/// slice ends when the system call returns, and it is not run again until
+/// then.
+pub fn sleep_until(deadline: u64) {
+    WAKE.store(deadline, Ordering::Relaxed);
+    trap::yield_slice();
+}
+
+/// Run a slice of the program `pid` in its address space, as the current
+/// process
+fn run(pid: i32, satp: u64, frame: &TrapContext) -> UserExit {
+    let parent_pid = {
+        let mut pm = PROCESS_MANAGER.lock();
+        let parent_pid = pm.get_current_pid();
+        pm.set_current_pid(pid);
+        parent_pid
+    };
+    let previous_satp = mmu::read_satp();
+    let exit = unsafe {
+        mmu::write_satp(satp as usize);
+        WAKE.store(0, Ordering::Relaxed);
+        let exit = trap::run_user_slice(frame, SLICE_TICKS);
+        mmu::write_satp(previous_satp);
+        exit
+    };
+    PROCESS_MANAGER.lock().set_current_pid(parent_pid);
+    exit
+}
+
+/// Take job `id` out of the table, its program ended with `status`
+fn remove(id: usize, status: i32) -> Finished {
+    let job = {
+        let mut jobs = JOBS.lock();
+        let index = jobs.iter().position(|job| job.id == id).expect("job is in the table");
+        jobs.remove(index)
+    };
+    let status = finish(job.pid, job.satp, status);
+    Finished { id: job.id, pid: job.pid, status, command: job.command }
+}
+
+/// Free the address space of `pid` and reap it. Returns its exit status:
+/// the one it gave sys_exit, or `status`.
+fn finish(pid: i32, satp: u64, status: i32) -> i32 {
+    mmu::release_user_space(satp);
+    let mut pm = PROCESS_MANAGER.lock();
+    let parent_pid = pm.get_process(pid).map(|process| process.ppid).unwrap_or(process::INIT_PID);
+    pm.exit_process(pid, status);
+    match pm.wait_for_child(parent_pid, pid) {
+        WaitStatus::Exited(_, status) => status,
+        _ => status,
+    }
+}
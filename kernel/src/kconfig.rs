@@ -0,0 +1,173 @@
+//! The effective configuration of this image and machine, as one report
+//!
+//! `kconfig dump` answers "what exactly is running here?" in one go: the
+//! Cargo features the kernel was built with, the board it found (or the
+//! QEMU virt layout it assumed), the memory mode and sizes `MemoryConfig`
+//! chose for the RAM found, which driver took each device, the mount table
+//! and every sysctl value. Each section is `# Title` followed by its lines,
+//! so the report reads in a terminal and compares with `diff` once saved.
+//!
+//! Sections that read driver or filesystem state are gathered into a
+//! buffer before they are written out, so no device or filesystem lock is
+//! held while the report goes to a file on disk.
+
+use core::fmt::Write;
+
+use elinos_common::{console_print, platform, sbi, timer};
+use heapless::String;
+
+use crate::filesystem::{self, append, procfs, FilesystemError, FilesystemResult};
+
+/// Buffer for a section that has to be gathered first
+const SECTION_SIZE: usize = 2048;
+
+/// The kernel's Cargo features that were turned on
+const FEATURES: &[(&str, bool)] = &[
+    ("development", cfg!(feature = "development")),
+    ("production", cfg!(feature = "production")),
+    ("headless", cfg!(feature = "headless")),
+];
+
+/// Write the whole report
+pub fn write_report(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out, "# elinOS configuration")?;
+    write_build(out)?;
+    write_board(out)?;
+    write_memory(out)?;
+
+    let mut section = String::<SECTION_SIZE>::new();
+    write_drivers(&mut section)?;
+    out.write_str(&section)?;
+
+    section.clear();
+    writeln!(section)?;
+    writeln!(section, "# Mounts")?;
+    procfs::write_mounts(&filesystem::FILESYSTEM.lock(), &mut section)?;
+    out.write_str(&section)?;
+
+    writeln!(out)?;
+    writeln!(out, "# Sysctl")?;
+    for tunable in crate::sysctl::tunables() {
+        writeln!(out, "{} = {}", tunable.name, tunable.value())?;
+    }
+    Ok(())
+}
+
+/// Print the report on the console
+pub fn print() -> core::fmt::Result {
+    write_report(&mut Console)
+}
+
+/// Prints what is written as it comes
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        console_print!("{}", s);
+        Ok(())
+    }
+}
+
+/// Write the report to the file `path`, replacing what it held
+pub fn save(path: &str) -> FilesystemResult<()> {
+    filesystem::write_file(path, "")?;
+    let mut file = AppendWriter { path, error: None };
+    let result = write_report(&mut file);
+    append::flush_file(path)?;
+    match (file.error, result) {
+        (Some(error), _) => Err(error),
+        (None, Err(_)) => Err(FilesystemError::IoError),
+        (None, Ok(())) => Ok(()),
+    }
+}
+
+/// Adds what is written to the end of a file, keeping the first error
+struct AppendWriter<'a> {
+    path: &'a str,
+    error: Option<FilesystemError>,
+}
+
+impl Write for AppendWriter<'_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        append::append_file(self.path, s.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            core::fmt::Error
+        })
+    }
+}
+
+fn write_build(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out)?;
+    writeln!(out, "# Build")?;
+    writeln!(out, "version = {}", env!("CARGO_PKG_VERSION"))?;
+    write!(out, "features =")?;
+    for (name, _) in FEATURES.iter().filter(|(_, enabled)| *enabled) {
+        write!(out, " {}", name)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "profile = {}", if cfg!(debug_assertions) { "debug" } else { "release" })
+}
+
+fn write_board(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out)?;
+    writeln!(out, "# Board")?;
+    match (platform::from_device_tree(), platform::model()) {
+        (true, Some(model)) => writeln!(out, "model = {} (device tree)", model)?,
+        (true, None) => writeln!(out, "model = unnamed (device tree)")?,
+        (false, _) => writeln!(out, "model = QEMU virt (assumed, no device tree)")?,
+    }
+    let (ram_base, ram_size) = platform::ram();
+    writeln!(out, "ram = 0x{:x}, {} MB", ram_base, ram_size / (1024 * 1024))?;
+    let uart = platform::uart();
+    writeln!(out, "uart = 0x{:x}, irq {}", uart.base, uart.irq)?;
+    writeln!(out, "harts = {} online, booted on hart {}", crate::smp::online_harts(), crate::smp::boot_hart())?;
+    writeln!(out, "timebase = {} Hz", timer::frequency())?;
+    writeln!(out, "virtio_slots = {}", platform::virtio_slots().len())?;
+    writeln!(out, "usb_hosts = {}", platform::usb_hosts().len())?;
+    match platform::beeper() {
+        Some(beeper) => writeln!(out, "beeper = gpio 0x{:x} line {}{}", beeper.gpio.base, beeper.pin,
+            if beeper.active_low { ", active low" } else { "" })?,
+        None => writeln!(out, "beeper = none")?,
+    }
+    writeln!(out, "sbi = implementation {}, version 0x{:x}", sbi::get_sbi_impl_id(), sbi::get_sbi_impl_version())
+}
+
+fn write_memory(out: &mut impl Write) -> core::fmt::Result {
+    let config = crate::memory::get_memory_config();
+    let kb = |bytes: usize| bytes / 1024;
+    writeln!(out)?;
+    writeln!(out, "# Memory")?;
+    writeln!(out, "mode = {:?}", config.mode)?;
+    writeln!(out, "total_ram = {} KB", kb(config.total_ram))?;
+    writeln!(out, "kernel = 0x{:x}-0x{:x}", config.kernel_start, config.kernel_end)?;
+    writeln!(out, "heap = {} KB", kb(config.heap_size))?;
+    writeln!(out, "buddy_heap = {} KB", kb(config.buddy_heap_size))?;
+    writeln!(out, "small_heap = {} KB", kb(config.small_heap_size))?;
+    writeln!(out, "device_memory = {} KB", kb(config.device_memory_size))?;
+    writeln!(out, "max_allocation = {} KB", kb(config.max_allocation_size))?;
+    let swap = crate::memory::swap::stats();
+    match swap.name {
+        Some(name) => writeln!(out, "swap = {}, {} KB", name, kb(swap.pages * crate::memory::mmu::PAGE_SIZE)),
+        None => writeln!(out, "swap = none"),
+    }
+}
+
+/// What each VirtIO slot holds and which driver took it, then the devices
+/// that came up: disks, USB and sound
+fn write_drivers(out: &mut impl Write) -> core::fmt::Result {
+    writeln!(out)?;
+    writeln!(out, "# Drivers")?;
+    for (base, device_id, driver) in crate::virtio::hotplug::occupied_slots() {
+        writeln!(out, "virtio 0x{:x} = device {}, {}", base, device_id, driver.unwrap_or("no driver"))?;
+    }
+    for (name, base, ports) in crate::usb::controllers() {
+        writeln!(out, "usb 0x{:x} = {}, {} ports", base, name, ports)?;
+    }
+    for (index, (model, sectors, _)) in crate::usb::storage::disks().iter().enumerate() {
+        writeln!(out, "usb{} = {}, {} sectors", index, model, sectors)?;
+    }
+    let sound = crate::sound::has_speaker();
+    writeln!(out, "sound = {}", if sound { "speaker" } else { "terminal bell only" })?;
+    writeln!(out)?;
+    procfs::write_devices(out)
+}
@@ -0,0 +1,355 @@
+//! The kernel log
+//!
+//! Every line printed with `console_println!`, and every status line
+//! (`ok_println!` and friends) whether the log level let it reach the
+//! console or not, is kept in a ring buffer with its level and the time
+//! since boot, as `<6>[    1.234567] text` the way syslog(2) reads it: a
+//! status line has the level of its status, a plain line the info level.
+//! `dmesg` prints the buffer and the syslog system call reads it; when it
+//! is full the oldest lines go.
+//!
+//! With `kernel.log.disk=on` the lines also go to /var/log/kernel.log. They
+//! are handed to the buffered appends when the shell is idle and on `sync`,
+//! never from where they were printed, so logging neither waits for the disk
+//! nor takes the filesystem lock. Once the file grows past
+//! `kernel.log.max_size` it is rotated: kernel.log.1 and kernel.log.2 keep
+//! the two before it.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use heapless::String;
+use spin::Mutex;
+use elinos_common::console::Status;
+
+use crate::filesystem::{self, append, FilesystemError, FilesystemResult, FILESYSTEM};
+use crate::filesystem::traits::FileSystem;
+use crate::sysctl::{Kind, Tunable};
+
+/// Bytes of log kept in memory
+pub const LOG_BUFFER_SIZE: usize = 32 * 1024;
+
+/// Level of lines printed without a status, as KERN_INFO
+const PLAIN_LEVEL: u8 = 6;
+
+/// Longest line kept; longer ones are cut
+const MAX_LINE: usize = 256;
+
+/// Where the log goes on disk
+pub const LOG_DIR: &str = "/var/log";
+pub const LOG_PATH: &str = "/var/log/kernel.log";
+
+/// Rotated files kept: kernel.log.1 up to kernel.log.<LOG_KEEP>
+const LOG_KEEP: usize = 2;
+
+/// Bytes moved to the appends at a time
+const FLUSH_CHUNK: usize = 512;
+
+/// Lines in the order they were logged. Positions count every byte ever
+/// logged; the one at `p` is kept in `data[p % LOG_BUFFER_SIZE]`.
+struct LogBuffer {
+    data: [u8; LOG_BUFFER_SIZE],
+    /// Start of the oldest line kept
+    start: usize,
+    /// End of the newest line
+    end: usize,
+    /// Everything before this has been handed to the disk sink
+    synced: usize,
+    /// Everything before this has been read by syslog(2)'s READ action
+    read: usize,
+    /// Everything before this was cleared and is not shown
+    cleared: usize,
+}
+
+impl LogBuffer {
+    fn push(&mut self, line: &[u8]) {
+        let line = &line[..line.len().min(LOG_BUFFER_SIZE)];
+        // Drop whole lines until the new one fits
+        while self.end + line.len() - self.start > LOG_BUFFER_SIZE {
+            let mut position = self.start;
+            while position < self.end && self.data[position % LOG_BUFFER_SIZE] != b'\n' {
+                position += 1;
+            }
+            self.start = (position + 1).min(self.end);
+        }
+        for &byte in line {
+            self.data[self.end % LOG_BUFFER_SIZE] = byte;
+            self.end += 1;
+        }
+    }
+
+    /// Copy bytes from position `from` that are still kept into `out`;
+    /// returns where the copy started and how many bytes it took
+    fn copy_from(&self, from: usize, out: &mut [u8]) -> (usize, usize) {
+        let from = from.max(self.start);
+        let count = (self.end - from).min(out.len());
+        for (i, byte) in out[..count].iter_mut().enumerate() {
+            *byte = self.data[(from + i) % LOG_BUFFER_SIZE];
+        }
+        (from, count)
+    }
+
+    /// Start of the first line shown, past what was cleared
+    fn first_shown(&self) -> usize {
+        self.start.max(self.cleared)
+    }
+}
+
+static LOG: Mutex<LogBuffer> = Mutex::new(LogBuffer {
+    data: [0; LOG_BUFFER_SIZE],
+    start: 0,
+    end: 0,
+    synced: 0,
+    read: 0,
+    cleared: 0,
+});
+
+/// Whether the log also goes to `LOG_PATH`
+static DISK_SINK: AtomicBool = AtomicBool::new(false);
+
+/// Size at which `LOG_PATH` is rotated
+static MAX_FILE_SIZE: AtomicUsize = AtomicUsize::new(64 * 1024);
+
+/// Start keeping console lines; call once the console is up
+pub fn init() {
+    elinos_common::console::set_log_hook(record);
+}
+
+/// Keep a console line, one log line for each line of its text. A line
+/// logged while the buffer is in use, e.g. from an interrupt taken during
+/// `dmesg`, is dropped rather than waited for.
+fn record(status: Option<Status>, args: fmt::Arguments) {
+    let ns = crate::syscall::time::monotonic_ns();
+    let level = status.map_or(PLAIN_LEVEL, |status| status.level());
+    // A line too long for the buffer is kept cut short
+    let mut text: String<MAX_LINE> = String::new();
+    let _ = match status {
+        Some(status) => write!(text, "{} {}", status.prefix(), args),
+        None => text.write_fmt(args),
+    };
+
+    let Some(mut log) = LOG.try_lock() else {
+        crate::health::count(crate::health::Event::LockContended);
+        return;
+    };
+    for part in text.split('\n') {
+        let mut line: String<MAX_LINE> = String::new();
+        let _ = write!(line, "<{}>[{:>5}.{:06}] ", level, ns / 1_000_000_000, ns % 1_000_000_000 / 1000);
+        for ch in part.chars().filter(|&ch| ch != '\r') {
+            if line.push(ch).is_err() {
+                break;
+            }
+        }
+        if line.push('\n').is_err() {
+            line.pop();
+            let _ = line.push('\n');
+        }
+        log.push(line.as_bytes());
+    }
+}
+
+/// Level of a kept line, and the line without it
+fn split_level(line: &str) -> (Option<u8>, &str) {
+    let bytes = line.as_bytes();
+    match bytes {
+        [b'<', level @ b'0'..=b'8', b'>', ..] => (Some(level - b'0'), &line[3..]),
+        _ => (None, line),
+    }
+}
+
+/// Print the kept lines, oldest first, leaving out those less severe than
+/// `max_level`. `raw` keeps the `<level>` each line starts with. What is
+/// printed is not logged again.
+pub fn dump(raw: bool, max_level: Option<u8>) {
+    let log = LOG.lock();
+    let mut line: String<MAX_LINE> = String::new();
+    for position in log.first_shown()..log.end {
+        match log.data[position % LOG_BUFFER_SIZE] {
+            b'\n' => {
+                let (level, text) = split_level(&line);
+                if max_level.is_none_or(|max| level.is_none_or(|level| level <= max)) {
+                    let shown = if raw { line.as_str() } else { text };
+                    let _ = elinos_common::console::print_fmt(format_args!("{}\r\n", shown));
+                }
+                line.clear();
+            }
+            byte => {
+                let _ = line.push(if byte.is_ascii() { byte as char } else { '?' });
+            }
+        }
+    }
+}
+
+/// Stop showing the lines kept so far, in `dmesg` and the syslog READ_ALL
+/// action; the disk sink still gets them
+pub fn clear() {
+    let mut log = LOG.lock();
+    log.cleared = log.end;
+}
+
+/// Bytes not yet taken by `read_unread`
+pub fn unread_len() -> usize {
+    let log = LOG.lock();
+    log.end - log.read.max(log.start)
+}
+
+/// Copy bytes not yet taken by an earlier call into `out`, as syslog(2)'s
+/// READ action does; returns how many were copied
+pub fn read_unread(out: &mut [u8]) -> usize {
+    let mut log = LOG.lock();
+    let (from, count) = log.copy_from(log.read, out);
+    log.read = from + count;
+    count
+}
+
+/// Copy the newest whole lines that fit into `out`, oldest first, as
+/// syslog(2)'s READ_ALL action does; returns how many bytes were copied
+pub fn read_all(out: &mut [u8]) -> usize {
+    let log = LOG.lock();
+    let first = log.first_shown();
+    let mut from = log.end.saturating_sub(out.len()).max(first);
+    // Start at a line boundary
+    while from > first && from < log.end && log.data[(from - 1) % LOG_BUFFER_SIZE] != b'\n' {
+        from += 1;
+    }
+    log.copy_from(from, out).1
+}
+
+/// Console log level from before `console_off`, 0 while the console is on
+static SAVED_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// Print only the most severe status lines until `console_on`
+pub fn console_off() {
+    let level = elinos_common::console::log_level();
+    if SAVED_LEVEL.compare_exchange(0, level.max(1), Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+        elinos_common::console::set_log_level(1);
+    }
+}
+
+/// Print status lines at the level from before `console_off` again
+pub fn console_on() {
+    let level = SAVED_LEVEL.swap(0, Ordering::Relaxed);
+    if level != 0 {
+        elinos_common::console::set_log_level(level);
+    }
+}
+
+/// Hand the lines logged since the last call to the buffered appends of
+/// `LOG_PATH`, and rotate it if it has grown too big. Does nothing unless
+/// the disk sink is on.
+pub fn flush_to_disk() -> FilesystemResult<()> {
+    if !DISK_SINK.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    create_log_dir()?;
+    let mut chunk = [0u8; FLUSH_CHUNK];
+    loop {
+        let (from, count, lost) = {
+            let log = LOG.lock();
+            let (from, count) = log.copy_from(log.synced, &mut chunk);
+            (from, count, from - log.synced)
+        };
+        if lost > 0 {
+            let mut note: String<64> = String::new();
+            let _ = writeln!(note, "[log: {} bytes lost]", lost);
+            append::append_file(LOG_PATH, note.as_bytes())?;
+        }
+        if count == 0 {
+            LOG.lock().synced = from;
+            break;
+        }
+        append::append_file(LOG_PATH, &chunk[..count])?;
+        LOG.lock().synced = from + count;
+    }
+
+    if append::end_of_file(LOG_PATH)? >= MAX_FILE_SIZE.load(Ordering::Relaxed) {
+        rotate()?;
+    }
+    Ok(())
+}
+
+/// Start a new `LOG_PATH`, keeping the current one as kernel.log.1 and
+/// shifting older ones up to kernel.log.<LOG_KEEP>. Returns false if there
+/// is no log file.
+pub fn rotate() -> FilesystemResult<bool> {
+    append::flush_file(LOG_PATH)?;
+    if !filesystem::file_exists(LOG_PATH) {
+        return Ok(false);
+    }
+    for number in (1..LOG_KEEP).rev() {
+        let from = rotated_path(number);
+        if filesystem::file_exists(&from) {
+            copy_file(&from, &rotated_path(number + 1))?;
+        }
+    }
+    copy_file(LOG_PATH, &rotated_path(1))?;
+    filesystem::truncate_file(LOG_PATH, 0)?;
+    Ok(true)
+}
+
+/// `LOG_PATH` with `.number` added
+pub fn rotated_path(number: usize) -> String<32> {
+    let mut path = String::new();
+    let _ = write!(path, "{}.{}", LOG_PATH, number);
+    path
+}
+
+/// Replace `to` with a copy of `from`; there is no rename
+fn copy_file(from: &str, to: &str) -> FilesystemResult<()> {
+    filesystem::write_file(to, "")?;
+    let mut chunk = [0u8; FLUSH_CHUNK];
+    let mut offset = 0;
+    loop {
+        let read = filesystem::read_file_at(from, offset, &mut chunk)?;
+        if read == 0 {
+            return Ok(());
+        }
+        filesystem::write_file_at(to, offset, &chunk[..read])?;
+        offset += read;
+    }
+}
+
+/// Create `LOG_DIR` and its parents if they are missing
+fn create_log_dir() -> FilesystemResult<()> {
+    let mut fs = FILESYSTEM.lock();
+    for (end, _) in LOG_DIR.match_indices('/').skip(1).chain(core::iter::once((LOG_DIR.len(), ""))) {
+        let dir = &LOG_DIR[..end];
+        if !fs.file_exists(dir) {
+            fs.create_directory(dir)?;
+        } else if !fs.get_file_entry(dir)?.is_directory {
+            return Err(FilesystemError::NotADirectory);
+        }
+    }
+    Ok(())
+}
+
+/// Kernel log parameters for `sysctl`
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "kernel.log.disk",
+        description: "Also write the kernel log to /var/log/kernel.log",
+        kind: Kind::Choice { choices: &["off", "on"], get: get_disk_sink, set: set_disk_sink },
+    },
+    Tunable {
+        name: "kernel.log.max_size",
+        description: "Bytes at which /var/log/kernel.log is rotated",
+        kind: Kind::Int { min: 4096, max: 1024 * 1024, get: get_max_size, set: set_max_size },
+    },
+];
+
+fn get_disk_sink() -> &'static str {
+    if DISK_SINK.load(Ordering::Relaxed) { "on" } else { "off" }
+}
+
+fn set_disk_sink(value: &'static str) -> Result<(), &'static str> {
+    DISK_SINK.store(value == "on", Ordering::Relaxed);
+    Ok(())
+}
+
+fn get_max_size() -> i64 {
+    MAX_FILE_SIZE.load(Ordering::Relaxed) as i64
+}
+
+fn set_max_size(size: i64) -> Result<(), &'static str> {
+    MAX_FILE_SIZE.store(size as usize, Ordering::Relaxed);
+    Ok(())
+}
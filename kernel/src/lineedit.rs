@@ -0,0 +1,180 @@
+//! Redrawing the shell's input line
+//!
+//! When history navigation swaps the line being edited for another, only
+//! what changed is sent: the cursor backs up to the first byte the two
+//! lines do not share, the rest of the new line is written, and whatever is
+//! left of the old one is blanked. The prompt and the shared start are
+//! never sent again, so holding the up arrow on a 115200 baud UART keeps up.
+//! An insertion or deletion in the middle of the line likewise rewrites
+//! only the line from there on, then moves the cursor back to its place.
+//!
+//! By default the cursor is moved left with backspaces, moved right by
+//! writing again the characters it passes over, and the tail blanked with
+//! spaces, which any terminal understands. Low-bandwidth mode
+//! (`sysctl shell.low_bandwidth=on`) uses the ANSI cursor-movement and
+//! erase-to-end-of-line sequences instead wherever they are shorter.
+//!
+//! A change to the prompt itself, as when Ctrl-R search starts or ends,
+//! rewrites the whole line from its first column instead.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::String;
+use elinos_common::console_print;
+
+use crate::sysctl::{Kind, Tunable};
+
+static LOW_BANDWIDTH: AtomicBool = AtomicBool::new(false);
+
+/// Output gathered and sent a chunk at a time rather than byte by byte
+struct Output {
+    buffer: String<128>,
+}
+
+impl Output {
+    fn new() -> Self {
+        Output { buffer: String::new() }
+    }
+
+    fn push(&mut self, ch: char) {
+        if self.buffer.push(ch).is_err() {
+            self.flush();
+            let _ = self.buffer.push(ch);
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        s.chars().for_each(|ch| self.push(ch));
+    }
+
+    fn repeat(&mut self, ch: char, count: usize) {
+        (0..count).for_each(|_| self.push(ch));
+    }
+
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            console_print!("{}", self.buffer);
+            self.buffer.clear();
+        }
+    }
+}
+
+/// Bytes in the ANSI sequence moving the cursor `count` columns
+fn cursor_move_len(count: usize) -> usize {
+    // ESC [ digits D (or C)
+    3 + count.ilog10() as usize + 1
+}
+
+/// Move the cursor `count` columns left
+fn back(out: &mut Output, count: usize, low_bandwidth: bool) {
+    if count == 0 {
+        return;
+    }
+    if low_bandwidth && cursor_move_len(count) < count {
+        let mut sequence: String<16> = String::new();
+        let _ = write!(sequence, "\x1b[{}D", count);
+        out.push_str(&sequence);
+    } else {
+        out.repeat('\x08', count);
+    }
+}
+
+/// Move the cursor right from column `from` to column `to` of `line`
+fn forward(out: &mut Output, line: &[u8], from: usize, to: usize, low_bandwidth: bool) {
+    let count = to - from;
+    if count == 0 {
+        return;
+    }
+    if low_bandwidth && cursor_move_len(count) < count {
+        let mut sequence: String<16> = String::new();
+        let _ = write!(sequence, "\x1b[{}C", count);
+        out.push_str(&sequence);
+    } else {
+        out.push_str(core::str::from_utf8(&line[from..to]).unwrap_or(""));
+    }
+}
+
+/// Blank the `count` columns from the cursor on, leaving the cursor there
+fn erase(out: &mut Output, count: usize, low_bandwidth: bool) {
+    if count == 0 {
+        return;
+    }
+    // Erasing to the end of the line takes 3 bytes, blanking one column 2
+    if low_bandwidth && count > 1 {
+        out.push_str("\x1b[K");
+    } else {
+        out.repeat(' ', count);
+        back(out, count, low_bandwidth);
+    }
+}
+
+/// Turn `old`, shown with the cursor after its end, into `new` on the
+/// terminal
+pub fn replace(old: &[u8], new: &[u8]) {
+    let low_bandwidth = LOW_BANDWIDTH.load(Ordering::Relaxed);
+    let shared = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let mut out = Output::new();
+
+    back(&mut out, old.len() - shared, low_bandwidth);
+    out.push_str(core::str::from_utf8(&new[shared..]).unwrap_or(""));
+    erase(&mut out, old.len().saturating_sub(new.len()), low_bandwidth);
+    out.flush();
+}
+
+/// Move the cursor along `line`, shown on the terminal, from column `from`
+/// to column `to`
+pub fn move_cursor(line: &[u8], from: usize, to: usize) {
+    let low_bandwidth = LOW_BANDWIDTH.load(Ordering::Relaxed);
+    let mut out = Output::new();
+
+    if to < from {
+        back(&mut out, from - to, low_bandwidth);
+    } else {
+        forward(&mut out, line, from, to, low_bandwidth);
+    }
+    out.flush();
+}
+
+/// Show `line` again from column `at`, where the cursor is, after a change
+/// there that left it `removed` columns shorter, then put the cursor at
+/// column `cursor`
+pub fn redraw_from(line: &[u8], at: usize, removed: usize, cursor: usize) {
+    let low_bandwidth = LOW_BANDWIDTH.load(Ordering::Relaxed);
+    let mut out = Output::new();
+
+    out.push_str(core::str::from_utf8(&line[at..]).unwrap_or(""));
+    erase(&mut out, removed, low_bandwidth);
+    back(&mut out, line.len() - cursor, low_bandwidth);
+    out.flush();
+}
+
+/// Turn the line on the terminal, `shown` columns wide with the cursor after
+/// its end, into `prompt` followed by `line`
+pub fn rewrite(shown: usize, prompt: &str, line: &[u8]) {
+    let low_bandwidth = LOW_BANDWIDTH.load(Ordering::Relaxed);
+    let mut out = Output::new();
+
+    out.push('\r');
+    out.push_str(prompt);
+    out.push_str(core::str::from_utf8(line).unwrap_or(""));
+    erase(&mut out, shown.saturating_sub(prompt.len() + line.len()), low_bandwidth);
+    out.flush();
+}
+
+/// Line editor parameters for `sysctl`
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "shell.low_bandwidth",
+        description: "Redraw the input line with ANSI cursor sequences where they take fewer bytes",
+        kind: Kind::Choice { choices: &["off", "on"], get: get_low_bandwidth, set: set_low_bandwidth },
+    },
+];
+
+fn get_low_bandwidth() -> &'static str {
+    if LOW_BANDWIDTH.load(Ordering::Relaxed) { "on" } else { "off" }
+}
+
+fn set_low_bandwidth(value: &'static str) -> Result<(), &'static str> {
+    LOW_BANDWIDTH.store(value == "on", Ordering::Relaxed);
+    Ok(())
+}
@@ -3,47 +3,16 @@
 
 use core::panic::PanicInfo;
 use core::arch::asm;
+use core::fmt::Write;
 use spin::Mutex;
-use heapless::{String, Vec};
+use heapless::{String, Vec, FnvIndexMap};
+use messages::Msg;
 
 // Import shared library components
 use elinos_common as common;
 
 // Re-export commonly used macros and functions from shared library
-pub use common::{console_print, console_println, debug_print, debug_println};
-
-// Flag to prevent recursive console bridge calls
-static mut CONSOLE_BRIDGE_ACTIVE: bool = false;
-
-/// Bridge function to forward console output to framebuffer
-#[no_mangle]
-pub extern "C" fn kernel_console_to_framebuffer(text: *const u8, len: usize) {
-    if text.is_null() || len == 0 {
-        return;
-    }
-    
-    unsafe {
-        // Prevent recursive calls that can cause infinite loops
-        if CONSOLE_BRIDGE_ACTIVE {
-            return;
-        }
-        
-        CONSOLE_BRIDGE_ACTIVE = true;
-        
-        let slice = core::slice::from_raw_parts(text, len);
-        if let Ok(text_str) = core::str::from_utf8(slice) {
-            // Forward to graphics framebuffer
-            let _ = graphics::print_to_console(text_str);
-        }
-        
-        CONSOLE_BRIDGE_ACTIVE = false;
-    }
-}
-
-/// Helper function to check if console bridge is active (prevent recursion)
-pub fn is_console_bridge_active() -> bool {
-    unsafe { CONSOLE_BRIDGE_ACTIVE }
-}
+pub use common::{console_print, console_println, ok_println, err_println, warn_println, info_println, debug_print, debug_println, trace_println};
 
 // Module declarations
 pub mod commands;
@@ -54,6 +23,28 @@ pub mod syscall;
 pub mod virtio;
 pub mod trap;  // Add trap module
 pub mod graphics; // Simple framebuffer graphics
+pub mod process; // Process table and program execution
+pub mod help; // Command registry and man pages
+pub mod pager; // Paged console output
+pub mod lineedit; // Input line redraws
+pub mod args; // Shell argument parsing
+pub mod messages; // Message catalog and locales
+pub mod net; // Ethernet, ARP, IPv4 and UDP
+pub mod sysctl; // Runtime-tunable kernel parameters
+pub mod klog; // Kernel log buffer and its disk sink
+pub mod input; // Pointer state and /dev/input events
+pub mod timerfd; // Timer file descriptors
+pub mod eventfd; // Event counter file descriptors
+pub mod health; // Event counters and the health summary
+pub mod bootchart; // Boot stage timing
+pub mod smp; // Secondary hart bring-up
+pub mod cron; // Delayed and periodic commands
+pub mod usb; // USB host controllers and mass storage
+pub mod watchdog; // Hung kernel code and program detection
+pub mod sound; // Beeps on VirtIO sound, a GPIO buzzer or the bell
+pub mod kconfig; // Effective configuration report
+pub mod script; // Shell scripts
+pub mod jobs; // Background programs
 
 // Global UART instance is now in the shared library
 pub use common::uart::UART;
@@ -65,28 +56,80 @@ enum EscapeSequence {
     DownArrow,
     LeftArrow,
     RightArrow,
+    Home,
+    End,
+    Delete,
 }
 
 /// Shell constants
 const MAX_COMMAND_LEN: usize = 1024;
 const MAX_HISTORY_ENTRIES: usize = 100;
 const HISTORY_FILE_PATH: &str = "/.shell_history";
+/// Longest line in the history file: a status, a tab and the command
+const HISTORY_LINE_LEN: usize = MAX_COMMAND_LEN + 40;
+/// Variable giving how many history entries to keep, up to MAX_HISTORY_ENTRIES
+const HISTSIZE_VAR: &str = "HISTSIZE";
+/// Variable that drops every earlier copy of a command from the history
+/// when it contains `erasedups`, as in bash
+const HISTCONTROL_VAR: &str = "HISTCONTROL";
+const MAX_ENV_VARS: usize = 32;
+const MAX_ENV_NAME_LEN: usize = 32;
+const MAX_ENV_VALUE_LEN: usize = 256;
+const ENV_FILE_PATH: &str = "/.shell_env";
+const MAX_ALIASES: usize = 32;
+const MAX_ALIAS_NAME_LEN: usize = 32;
+const MAX_ALIAS_VALUE_LEN: usize = 256;
+const ALIAS_FILE_PATH: &str = "/.shell_aliases";
+
+/// Environment variable holding the prompt indicator shown after a command
+/// fails; `{}` is replaced with the exit status, an empty value disables it
+const STATUS_INDICATOR_VAR: &str = "STATUS_INDICATOR";
+const DEFAULT_STATUS_INDICATOR: &str = "[{}] ";
+const MAX_PROMPT_PREFIX_LEN: usize = MAX_ENV_VALUE_LEN + 16;
+/// Shown after the prompt prefix
+const SHELL_PROMPT: &str = "elinOS> ";
+
+/// A history entry: the command line, the exit status it finished with
+/// (None while it is running, or for entries saved without one) and when it
+/// was entered, in seconds since the epoch (None without an RTC)
+struct HistoryEntry {
+    command: String<MAX_COMMAND_LEN>,
+    status: Option<i32>,
+    time: Option<u64>,
+}
 
 /// Shell state for history and input management
 pub struct ShellState {
     command_buffer: Vec<u8, MAX_COMMAND_LEN>,
-    history: Vec<String<MAX_COMMAND_LEN>, MAX_HISTORY_ENTRIES>,
+    /// Position of the cursor in the command buffer
+    cursor: usize,
+    history: Vec<HistoryEntry, MAX_HISTORY_ENTRIES>,
     history_index: Option<usize>,
     current_input: String<MAX_COMMAND_LEN>,
+    env: FnvIndexMap<String<MAX_ENV_NAME_LEN>, String<MAX_ENV_VALUE_LEN>, MAX_ENV_VARS>,
+    aliases: FnvIndexMap<String<MAX_ALIAS_NAME_LEN>, String<MAX_ALIAS_VALUE_LEN>, MAX_ALIASES>,
+    last_status: i32,
+    prompt_prefix: String<MAX_PROMPT_PREFIX_LEN>,
+    /// The last history entry is not in the history file yet
+    unsaved_entry: bool,
+    /// Entries in the history file, old ones included
+    history_file_entries: usize,
 }
 
 impl ShellState {
     fn new() -> Self {
         Self {
             command_buffer: Vec::new(),
+            cursor: 0,
             history: Vec::new(),
             history_index: None,
             current_input: String::new(),
+            env: FnvIndexMap::new(),
+            aliases: FnvIndexMap::new(),
+            last_status: 0,
+            prompt_prefix: String::new(),
+            unsaved_entry: false,
+            history_file_entries: 0,
         }
     }
 }
@@ -94,19 +137,27 @@ impl ShellState {
 // Global shell state
 static SHELL_STATE: Mutex<ShellState> = Mutex::new(ShellState {
     command_buffer: heapless::Vec::new(),
+    cursor: 0,
     history: heapless::Vec::new(),
     history_index: None,
     current_input: heapless::String::new(),
+    env: heapless::FnvIndexMap::new(),
+    aliases: heapless::FnvIndexMap::new(),
+    last_status: 0,
+    prompt_prefix: heapless::String::new(),
+    unsaved_entry: false,
+    history_file_entries: 0,
 });
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     // Print the panic message
-    console_println!("[x]  KERNEL PANIC: {}", info.message());
+    err_println!("KERNEL PANIC: {}", info.message());
     
     if let Some(location) = info.location() {
-        console_println!("[i] Location: {}:{}:{}", location.file(), location.line(), location.column());
+        info_println!("Location: {}:{}:{}", location.file(), location.line(), location.column());
     }
+    sound::notify(sound::Notice::Error);
     
     loop {
         unsafe {
@@ -124,13 +175,19 @@ struct BootloaderInfo {
     kernel_base: usize,
     available_ram_start: usize,
     available_ram_size: usize,
+    cmdline_addr: usize,
+    cmdline_len: usize,
+    /// Device tree blob from the firmware, 0 if none
+    dtb_addr: usize,
+    /// Hart the firmware booted on
+    boot_hart: usize,
 }
 
 const BOOTLOADER_MAGIC: u64 = 0xEA15_0000_B007_AB1E;
 
 #[link_section = ".text.kernel"]
 #[no_mangle]
-pub fn kernel_main(bootloader_info_ptr: usize) -> ! {
+pub fn kernel_main(bootloader_info_ptr: usize, dtb_addr: usize) -> ! {
     // Setup initial stack in safe kernel space
     unsafe {
         asm!(
@@ -138,8 +195,9 @@ pub fn kernel_main(bootloader_info_ptr: usize) -> ! {
         );
     }
     
-    // Validate bootloader info - handle both bootloader and direct boot scenarios
-    if bootloader_info_ptr == 0 || bootloader_info_ptr > 0x90000000 {
+    // Validate bootloader info - handle both bootloader and direct boot scenarios.
+    // Straight from the firmware, a0 is the hart ID rather than a RAM address.
+    if bootloader_info_ptr < 0x80000000 || bootloader_info_ptr > 0x90000000 {
         // Direct boot scenario - create default bootloader info
         // Create a default bootloader info structure on the stack
         let default_info = BootloaderInfo {
@@ -149,6 +207,11 @@ pub fn kernel_main(bootloader_info_ptr: usize) -> ! {
             kernel_base: 0x80400000,         // Standard kernel base
             available_ram_start: 0x80600000, // Start after kernel space
             available_ram_size: 126 * 1024 * 1024, // Most of 128MB available
+            cmdline_addr: 0,
+            cmdline_len: 0,
+            // Booted straight from the firmware, which passes it in a1
+            dtb_addr,
+            boot_hart: bootloader_info_ptr,
         };
         
         kernel_core_main(&default_info)
@@ -164,109 +227,574 @@ pub fn kernel_main(bootloader_info_ptr: usize) -> ! {
     }
 }
 
+/// Longest kernel command line kept from the bootloader
+const MAX_CMDLINE_LEN: usize = 256;
+
+static BOOT_CMDLINE: Mutex<String<MAX_CMDLINE_LEN>> = Mutex::new(String::new());
+
+/// Kernel command line the bootloader passed
+pub fn boot_cmdline() -> String<MAX_CMDLINE_LEN> {
+    BOOT_CMDLINE.lock().clone()
+}
+
+/// Copy the command line out of bootloader memory before anything reuses it
+fn save_boot_cmdline(bootloader_info: &BootloaderInfo) {
+    if bootloader_info.cmdline_addr == 0 {
+        return;
+    }
+    let len = bootloader_info.cmdline_len.min(MAX_CMDLINE_LEN);
+    let bytes = unsafe { core::slice::from_raw_parts(bootloader_info.cmdline_addr as *const u8, len) };
+    if let Ok(cmdline) = core::str::from_utf8(bytes) {
+        let mut saved = BOOT_CMDLINE.lock();
+        saved.clear();
+        let _ = saved.push_str(cmdline);
+    }
+}
+
+/// Take the `time` CSR frequency from the device tree, so clocks and
+/// timeouts are right on any board, not only QEMU's 10 MHz
+fn init_platform(dtb_addr: usize) {
+    let Some(fdt) = (unsafe { common::fdt::Fdt::from_addr(dtb_addr) }) else {
+        warn_println!("No device tree, assuming the QEMU virt machine");
+        return;
+    };
+    match fdt.timebase_frequency() {
+        Some(hz) => {
+            common::timer::set_frequency(hz);
+            info_println!("Timebase: {} Hz from the device tree", hz);
+        }
+        None => warn_println!("No timebase in the device tree, assuming {} Hz", common::timer::frequency()),
+    }
+
+    // RAM, the UART and the VirtIO slots for the drivers brought up below
+    common::platform::init(&fdt);
+    let (ram_base, ram_size) = common::platform::ram();
+    info_println!("Device tree: {} MB RAM at 0x{:x}, UART at 0x{:x}, {} VirtIO slots",
+                  ram_size / (1024 * 1024), ram_base, common::platform::uart().base,
+                  common::platform::virtio_slots().len());
+}
+
 #[no_mangle]
 pub extern "C" fn kernel_core_main(bootloader_info: &BootloaderInfo) -> ! {
+    bootchart::begin("Early init");
+    smp::set_boot_hart(bootloader_info.boot_hart);
+    save_boot_cmdline(bootloader_info);
+    // Keep status lines from here on, for dmesg and the log file
+    klog::init();
+    // Before the first line, in case the UART is not where QEMU puts it
+    init_platform(bootloader_info.dtb_addr);
+    
     console_println!();
     console_println!();
     console_println!("elinOS Starting...");
+    
+    let cmdline = boot_cmdline();
+    if !cmdline.is_empty() {
+        info_println!("Kernel command line: {}", elinos_common::cmdline::Redacted(&cmdline));
+    }
 
     // Initialize trap handling (CRITICAL: must be early!)
     trap::init_trap_handling();
-    console_println!("[o] Trap handling ready");
+    ok_println!("Trap handling ready");
 
     // Initialize console system
     if let Err(e) = common::console::init_console() {
         panic!("Failed to initialize console: {}", e);
     }
+    bootchart::end();
     
     // Initialize unified memory management from shared library
+    bootchart::begin("Memory");
     if let Err(e) = common::memory::init_unified_memory_manager() {
-        console_println!("[x] Failed to initialize memory manager: {:?}", e);
+        err_println!("Failed to initialize memory manager: {:?}", e);
         panic!("Memory initialization failed");
     }
-    console_println!("[o] Unified memory management ready");
+    ok_println!("Unified memory management ready");
     
     // Initialize compatibility layer for existing code
     memory::init_allocator_compatibility();
 
-    // Initialize Virtual Memory Management (Software MMU)
+    // The process table takes its entries from a slab cache
+    process::init();
+    bootchart::end();
+
+    // Initialize Virtual Memory Management (Sv39, software MMU fallback)
+    bootchart::begin("MMU");
     if let Err(e) = memory::mmu::init_mmu() {
-        console_println!("[x] Virtual Memory initialization failed: {}", e);
-        console_println!("[!] Continuing in physical memory mode");
+        err_println!("Virtual Memory initialization failed: {}", e);
+        warn_println!("Continuing in physical memory mode");
     } else {
-        console_println!("[o] Virtual Memory Management enabled!");
+        ok_println!("Virtual Memory Management enabled!");
     }
+    bootchart::end();
+
+    // Park the other harts, each on a stack of its own
+    bootchart::begin("Harts");
+    smp::init();
+    bootchart::end();
 
     // Initialize VirtIO block device  
+    bootchart::begin("VirtIO block probe");
     if let Err(_) = virtio::init_virtio_memory() {
-        console_println!("[x] Failed to initialize VirtIO memory manager");
+        err_println!("Failed to initialize VirtIO memory manager");
     }
     
     if let Err(e) = virtio::init_virtio_blk() {
-        console_println!("[x] VirtIO disk initialization failed: {}", e);
+        err_println!("VirtIO disk initialization failed: {}", e);
     } else {
-        console_println!("[o] VirtIO disk ready");
+        ok_println!("VirtIO disk ready");
     }
+    bootchart::end();
+
+    // Initialize networking (optional)
+    bootchart::begin("Network");
+    match virtio::init_virtio_net() {
+        Ok(()) => {
+            if let Err(e) = net::init() {
+                warn_println!("Network stack not started: {}", e);
+            }
+        }
+        Err(_) => info_println!("No network device, networking disabled"),
+    }
+    bootchart::end();
+
+    // Pointer and keyboard input (optional)
+    bootchart::begin("Input");
+    let _ = virtio::init_virtio_input();
+    input::init();
+    bootchart::end();
+
+    // Sound for beeps (optional)
+    bootchart::begin("Sound");
+    let _ = virtio::init_virtio_sound();
+    bootchart::end();
+
+    // USB host controllers from the device tree (optional)
+    bootchart::begin("USB");
+    usb::init();
+    bootchart::end();
 
     // Initialize filesystem
+    bootchart::begin("Filesystem mount");
     match filesystem::init_filesystem() {
         Ok(()) => {
-            // console_println!("[o] Filesystem initialization successful!");
+            // ok_println!("Filesystem initialization successful!");
         }
         Err(e) => {
-            console_println!("[x] Filesystem initialization failed: {:?}", e);
+            err_println!("Filesystem initialization failed: {:?}", e);
         }
     }
+    bootchart::end();
+
+    // Saved settings, then the network configuration and sysctls they hold
+    bootchart::begin("Config store");
+    if let Err(e) = filesystem::configstore::load() {
+        warn_println!("Config store not loaded: {}", e);
+    }
+    net::config::apply_stored();
+    sysctl::load_persisted();
+    elf::verify::init();
+    bootchart::end();
 
     // Initialize graphics (optional)
+    bootchart::begin("Graphics");
     match graphics::init_graphics() {
-        Ok(_) => console_println!("[o] Graphics system initialized"),
-        Err(e) => console_println!("[!] Graphics initialization failed: {}", e),
+        Ok(_) => ok_println!("Graphics system initialized"),
+        Err(e) => warn_println!("Graphics initialization failed: {}", e),
     }
+    bootchart::end();
+
+    // Remember which devices the drivers took, so later scans see changes
+    bootchart::begin("Timer and interrupts");
+    virtio::hotplug::init();
+    
+    // Periodic tick for the monotonic clock and sleeps
+    trap::init_timer();
+
+    // Device interrupts; console input stops being polled
+    trap::init_external_interrupts();
+    bootchart::end();
     
     console_println!();
     
-    // Load shell history and start enhanced shell
+    // Load shell environment and history, then start enhanced shell.
+    // The environment comes first as it configures the history.
+    bootchart::begin("Shell setup");
+    load_shell_env();
+    load_shell_aliases();
     load_shell_history();
+    messages::load_locale_file();
+    cron::load_table();
+    bootchart::finish();
+    sound::notify(sound::Notice::BootComplete);
     show_welcome();
+    script::run_startup();
     enhanced_shell_loop();
 }
 
-/// Load command history from filesystem
+/// Entries to keep in the history: $HISTSIZE, at most MAX_HISTORY_ENTRIES
+fn history_size() -> usize {
+    get_env_var(HISTSIZE_VAR)
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .map_or(MAX_HISTORY_ENTRIES, |size| size.min(MAX_HISTORY_ENTRIES))
+}
+
+/// Whether $HISTCONTROL asks for every earlier copy of a command to go
+fn history_erases_dups() -> bool {
+    get_env_var(HISTCONTROL_VAR)
+        .is_some_and(|value| value.split(':').any(|option| option.trim() == "erasedups"))
+}
+
+/// Add `entry` as the newest history entry, dropping what the history size
+/// and dedup policy say must go
+fn push_history_entry(history: &mut Vec<HistoryEntry, MAX_HISTORY_ENTRIES>, entry: HistoryEntry, size: usize, erase_dups: bool) {
+    if erase_dups {
+        history.retain(|old| old.command != entry.command);
+    }
+    while !history.is_empty() && history.len() >= size {
+        history.remove(0);
+    }
+    if size > 0 {
+        let _ = history.push(entry);
+    }
+}
+
+/// Load command history from filesystem.
+/// Lines are `status<TAB>command`, or just `command` when no status was recorded.
+/// A `#seconds` line before an entry gives the time it was entered.
+/// Commands are appended to the file as they run, so it may hold more than
+/// fits in memory; it is read a piece at a time, whatever its size, and the
+/// newest entries are kept.
 fn load_shell_history() {
-    if let Ok(data) = filesystem::read_file(HISTORY_FILE_PATH) {
+    let size = history_size();
+    let erase_dups = history_erases_dups();
+    let mut history = Vec::new();
+    let mut entries = 0;
+    let mut time = None;
+    let mut end_line = |line: &[u8]| {
+        if let Some(entry) = parse_history_line(line, &mut time) {
+            entries += 1;
+            push_history_entry(&mut history, entry, size, erase_dups);
+        }
+    };
+    
+    // A line too long to be an entry is skipped
+    let mut line: Vec<u8, HISTORY_LINE_LEN> = Vec::new();
+    let mut overlong = false;
+    let read = filesystem::read_file_chunked(HISTORY_FILE_PATH, |chunk| {
+        for &byte in chunk {
+            if byte != b'\n' {
+                overlong |= line.push(byte).is_err();
+                continue;
+            }
+            if !overlong {
+                end_line(&line);
+            }
+            line.clear();
+            overlong = false;
+        }
+    });
+    // Ignore errors - history file might not exist on first run
+    if read.is_err() {
+        return;
+    }
+    if !overlong {
+        end_line(&line);
+    }
+    
+    let mut shell_state = SHELL_STATE.lock();
+    shell_state.history = history;
+    shell_state.history_file_entries = entries;
+    if entries > shell_state.history.len() {
+        drop(shell_state);
+        save_shell_history();
+    }
+}
+
+/// Parse one line of the history file. A `#seconds` line is kept in `time`
+/// for the entry that follows; a command line gives that entry.
+fn parse_history_line(line: &[u8], time: &mut Option<u64>) -> Option<HistoryEntry> {
+    let trimmed = core::str::from_utf8(line).ok()?.trim();
+    if let Some(seconds) = trimmed.strip_prefix('#').and_then(|s| s.parse::<u64>().ok()) {
+        *time = Some(seconds);
+        return None;
+    }
+    if trimmed.is_empty() {
+        return None;
+    }
+    let time = time.take();
+    let (status, cmd) = match trimmed.split_once('\t') {
+        Some((status, cmd)) => match status.parse::<i32>() {
+            Ok(status) => (Some(status), cmd),
+            Err(_) => (None, trimmed),
+        },
+        None => (None, trimmed),
+    };
+    let command = String::try_from(cmd).ok()?;
+    Some(HistoryEntry { command, status, time })
+}
+
+/// Format a history entry as it is stored in the history file
+fn format_history_entry(entry: &HistoryEntry, out: &mut String<HISTORY_LINE_LEN>) {
+    out.clear();
+    if let Some(time) = entry.time {
+        let _ = writeln!(out, "#{}", time);
+    }
+    if let Some(status) = entry.status {
+        let _ = write!(out, "{}\t", status);
+    }
+    let _ = out.push_str(&entry.command);
+    let _ = out.push('\n');
+}
+
+/// Rewrite the history file with the entries in memory
+fn save_shell_history() {
+    let mut shell_state = SHELL_STATE.lock();
+    shell_state.history_file_entries = shell_state.history.len();
+    shell_state.unsaved_entry = false;
+    
+    // Start over, then add the entries through the append buffer
+    // (ignore errors)
+    if filesystem::write_file(HISTORY_FILE_PATH, "").is_err() {
+        return;
+    }
+    let mut line = String::new();
+    for entry in &shell_state.history {
+        format_history_entry(entry, &mut line);
+        if filesystem::append_file(HISTORY_FILE_PATH, line.as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+/// Append the last history entry, which now has its status, to the history
+/// file. Appends are buffered, so this rarely touches the disk; the file is
+/// rewritten once it holds twice the entries kept.
+fn append_shell_history() {
+    let size = history_size();
+    let mut shell_state = SHELL_STATE.lock();
+    if !shell_state.unsaved_entry {
+        return;
+    }
+    shell_state.unsaved_entry = false;
+    
+    let mut line = String::new();
+    if let Some(entry) = shell_state.history.last() {
+        format_history_entry(entry, &mut line);
+    }
+    shell_state.history_file_entries += 1;
+    let compact = shell_state.history_file_entries >= 2 * size.max(1);
+    drop(shell_state);
+    
+    if compact {
+        save_shell_history();
+    } else {
+        // Ignore errors, as when saving
+        let _ = filesystem::append_file(HISTORY_FILE_PATH, line.as_bytes());
+    }
+}
+
+/// Empty the history and the history file
+fn clear_shell_history() -> Result<(), &'static str> {
+    let mut shell_state = SHELL_STATE.lock();
+    shell_state.history.clear();
+    shell_state.history_index = None;
+    shell_state.unsaved_entry = false;
+    shell_state.history_file_entries = 0;
+    drop(shell_state);
+    
+    filesystem::write_file(HISTORY_FILE_PATH, "").map_err(|_| "Failed to truncate the history file")
+}
+
+/// Load environment variables from filesystem
+fn load_shell_env() {
+    if let Ok(data) = filesystem::read_file(ENV_FILE_PATH) {
         if let Ok(content) = core::str::from_utf8(&data) {
             let mut shell_state = SHELL_STATE.lock();
-            shell_state.history.clear();
+            shell_state.env.clear();
             
             for line in content.lines() {
-                let trimmed = line.trim();
-                if !trimmed.is_empty() {
-                    if let Ok(cmd_string) = String::try_from(trimmed) {
-                        if shell_state.history.push(cmd_string).is_err() {
-                            break; // History buffer is full
+                if let Some((name, value)) = line.split_once('=') {
+                    if !is_valid_env_name(name) {
+                        continue;
+                    }
+                    if let (Ok(name), Ok(value)) = (String::try_from(name), String::try_from(value)) {
+                        if shell_state.env.insert(name, value).is_err() {
+                            break; // Environment table is full
                         }
                     }
                 }
             }
         }
     }
-    // Ignore errors - history file might not exist on first run
+    // Ignore errors - environment file might not exist on first run
 }
 
-/// Save command history to filesystem
-fn save_shell_history() {
+/// Save environment variables to filesystem
+fn save_shell_env() {
     let shell_state = SHELL_STATE.lock();
     let mut content = String::<4096>::new();
     
-    for cmd in &shell_state.history {
-        if content.push_str(cmd).is_ok() {
+    for (name, value) in shell_state.env.iter() {
+        if content.push_str(name).is_ok() && content.push('=').is_ok() && content.push_str(value).is_ok() {
             let _ = content.push('\n');
         }
     }
+    drop(shell_state);
     
     // Write to filesystem (ignore errors)
-    let _ = filesystem::write_file(HISTORY_FILE_PATH, &content);
+    let _ = filesystem::write_file(ENV_FILE_PATH, &content);
+}
+
+/// Load aliases from filesystem, one `name=command` per line
+fn load_shell_aliases() {
+    if let Ok(data) = filesystem::read_file(ALIAS_FILE_PATH) {
+        if let Ok(content) = core::str::from_utf8(&data) {
+            let mut shell_state = SHELL_STATE.lock();
+            shell_state.aliases.clear();
+            
+            for line in content.lines() {
+                if let Some((name, value)) = line.split_once('=') {
+                    if !is_valid_alias_name(name) {
+                        continue;
+                    }
+                    if let (Ok(name), Ok(value)) = (String::try_from(name), String::try_from(value)) {
+                        if shell_state.aliases.insert(name, value).is_err() {
+                            break; // Alias table is full
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // Ignore errors - alias file might not exist on first run
+}
+
+/// Save aliases to filesystem
+fn save_shell_aliases() {
+    let shell_state = SHELL_STATE.lock();
+    let mut content = String::<{ MAX_ALIASES * (MAX_ALIAS_NAME_LEN + MAX_ALIAS_VALUE_LEN + 2) }>::new();
+    
+    for (name, value) in shell_state.aliases.iter() {
+        let _ = writeln!(content, "{}={}", name, value);
+    }
+    drop(shell_state);
+    
+    // Write to filesystem in one go (ignore errors)
+    let _ = filesystem::write_file(ALIAS_FILE_PATH, &content);
+}
+
+/// Check that an alias name is made of letters, digits, `_`, `-` and `.`
+/// and does not start with `-`
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Replace the first word of `command` with the alias of that name, then
+/// the first word of that, until a word that is no alias or was replaced
+/// already, so `alias ls='ls -F'` stops after one round
+fn expand_aliases(command: &str) -> Result<String<MAX_COMMAND_LEN>, &'static str> {
+    let mut line = String::<MAX_COMMAND_LEN>::try_from(command.trim_start()).map_err(|_| "Command too long")?;
+    let mut replaced: Vec<String<MAX_ALIAS_NAME_LEN>, 8> = Vec::new();
+    
+    loop {
+        let word = line.split_whitespace().next().unwrap_or("");
+        let Ok(name) = String::<MAX_ALIAS_NAME_LEN>::try_from(word) else {
+            break;
+        };
+        if replaced.contains(&name) {
+            break;
+        }
+        let Some(value) = SHELL_STATE.lock().aliases.get(&name).cloned() else {
+            break;
+        };
+        
+        let mut expanded = String::<MAX_COMMAND_LEN>::new();
+        expanded.push_str(&value)
+            .and_then(|()| expanded.push_str(&line[word.len()..]))
+            .map_err(|()| "Command too long after expansion")?;
+        line = expanded;
+        if replaced.push(name).is_err() {
+            break;
+        }
+    }
+    Ok(line)
+}
+
+/// Check that a variable name is made of letters, digits and underscores
+/// and does not start with a digit
+fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Look up an environment variable
+pub fn get_env_var(name: &str) -> Option<String<MAX_ENV_VALUE_LEN>> {
+    let shell_state = SHELL_STATE.lock();
+    shell_state.env.iter()
+        .find(|(key, _)| key.as_str() == name)
+        .map(|(_, value)| value.clone())
+}
+
+/// Expand $VAR and ${VAR} references in a command line.
+/// Unknown variables expand to an empty string, `\$` gives a literal dollar
+/// and `$?` the exit status of the previous command.
+fn expand_env_vars(command: &str) -> Result<String<MAX_COMMAND_LEN>, &'static str> {
+    let mut result = String::<MAX_COMMAND_LEN>::new();
+    let bytes = command.as_bytes();
+    let mut i = 0;
+    
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if i + 1 < bytes.len() && bytes[i + 1] == b'$' => {
+                result.push('$').map_err(|_| "Command too long after expansion")?;
+                i += 2;
+            }
+            b'$' if i + 1 < bytes.len() && bytes[i + 1] == b'?' => {
+                let status = SHELL_STATE.lock().last_status;
+                write!(result, "{}", status).map_err(|_| "Command too long after expansion")?;
+                i += 2;
+            }
+            b'$' => {
+                let braced = i + 1 < bytes.len() && bytes[i + 1] == b'{';
+                let start = if braced { i + 2 } else { i + 1 };
+                let mut end = start;
+                while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+                    end += 1;
+                }
+                
+                if braced && (end >= bytes.len() || bytes[end] != b'}') {
+                    return Err("Bad substitution: missing '}'");
+                }
+                
+                if end == start {
+                    // Lone '$' is kept as-is
+                    result.push('$').map_err(|_| "Command too long after expansion")?;
+                    i += 1;
+                    continue;
+                }
+                
+                if let Some(value) = get_env_var(&command[start..end]) {
+                    result.push_str(&value).map_err(|_| "Command too long after expansion")?;
+                }
+                i = if braced { end + 1 } else { end };
+            }
+            _ => {
+                // Copy one full UTF-8 character
+                let ch = command[i..].chars().next().unwrap_or('?');
+                result.push(ch).map_err(|_| "Command too long after expansion")?;
+                i += ch.len_utf8();
+            }
+        }
+    }
+    
+    Ok(result)
 }
 
 /// Show welcome message
@@ -286,26 +814,29 @@ fn show_welcome() {
 pub fn enhanced_shell_loop() -> ! {
     loop {
         // Show prompt
-        console_print!("elinOS> ");
-        
-        // Also print prompt to framebuffer TTY if graphics are available
-        let _ = graphics::print_shell_prompt();
+        update_prompt_prefix();
+        console_print!("{}{}", SHELL_STATE.lock().prompt_prefix, SHELL_PROMPT);
         
         // Read command with enhanced features
-        if let Ok(command) = read_enhanced_command() {
+        graphics::show_text_cursor(true);
+        let command = read_enhanced_command();
+        graphics::show_text_cursor(false);
+        if let Ok(command) = command {
             if !command.is_empty() {
                 // Add to history before processing
-                add_to_history(&command);
+                let recorded = add_to_history(&command);
                 
                 // Process command
-                if let Err(e) = process_enhanced_command(&command) {
+                let outcome = run_command_list(&command);
+                if let Err(e) = outcome.result {
                     if e == "exit_shell" {
-                        console_println!("Goodbye!");
+                        crate::msg_println!(Msg::Goodbye);
                         break;
                     } else {
-                        console_println!("Error: {}", e);
+                        crate::msg_println!(Msg::CommandError, e);
                     }
                 }
+                record_status(outcome.status, recorded);
             }
         }
         
@@ -316,42 +847,73 @@ pub fn enhanced_shell_loop() -> ! {
     panic!("Shell loop exited unexpectedly");
 }
 
+/// Rebuild the prompt prefix: the status indicator if the last command failed
+fn update_prompt_prefix() {
+    let status = SHELL_STATE.lock().last_status;
+    let format = get_env_var(STATUS_INDICATOR_VAR);
+    let format = format.as_deref().unwrap_or(DEFAULT_STATUS_INDICATOR);
+
+    let mut prefix = String::new();
+    if status != 0 {
+        let mut parts = format.splitn(2, "{}");
+        let _ = prefix.push_str(parts.next().unwrap_or(""));
+        if let Some(rest) = parts.next() {
+            let _ = write!(prefix, "{}{}", status, rest);
+        }
+    }
+    SHELL_STATE.lock().prompt_prefix = prefix;
+}
+
 /// Read command with history navigation and editing support
 fn read_enhanced_command() -> Result<String<MAX_COMMAND_LEN>, &'static str> {
-    let mut shell_state = SHELL_STATE.lock();
-    shell_state.command_buffer.clear();
-    shell_state.history_index = None;
-    shell_state.current_input.clear();
+    {
+        let mut shell_state = SHELL_STATE.lock();
+        shell_state.command_buffer.clear();
+        shell_state.cursor = 0;
+        shell_state.history_index = None;
+        shell_state.current_input.clear();
+    }
     
     loop {
+        // The state is not held while waiting, so due jobs can run
         let ch = read_char();
+        let sequence = if ch == b'\x1b' { read_escape_sequence() } else { Ok(None) };
+        let mut shell_state = SHELL_STATE.lock();
+        let cursor = shell_state.cursor;
+        let len = shell_state.command_buffer.len();
         
         match ch {
             b'\r' | b'\n' => {
                 console_println!();
-                let command_str = core::str::from_utf8(&shell_state.command_buffer)
-                    .map_err(|_| "Invalid UTF-8 in command")?;
-                return String::try_from(command_str.trim())
-                    .map_err(|_| "Command too long");
+                return buffered_command(&shell_state);
             }
             b'\x08' | b'\x7f' => { // Backspace or DEL
-                if !shell_state.command_buffer.is_empty() {
-                    shell_state.command_buffer.pop();
-                    console_print!("\x08 \x08"); // Move back, print space, move back
-                    
-                    // Update current_input if not navigating history
-                    if shell_state.history_index.is_none() {
-                        // Clone the buffer to avoid borrow checker issues
-                        let buffer_copy = shell_state.command_buffer.clone();
-                        if let Ok(current_str) = core::str::from_utf8(&buffer_copy) {
-                            shell_state.current_input.clear();
-                            let _ = shell_state.current_input.push_str(current_str);
-                        }
-                    }
+                if cursor > 0 {
+                    lineedit::move_cursor(&shell_state.command_buffer, cursor, cursor - 1);
+                    shell_state.command_buffer.remove(cursor - 1);
+                    lineedit::redraw_from(&shell_state.command_buffer, cursor - 1, 1, cursor - 1);
+                    shell_state.cursor = cursor - 1;
+                    input_edited(&mut shell_state);
+                }
+            }
+            b'\x01' => { // Ctrl-A - start of line
+                move_input_cursor(&mut shell_state, 0);
+            }
+            b'\x05' => { // Ctrl-E - end of line
+                move_input_cursor(&mut shell_state, len);
+            }
+            b'\x16' => { // Ctrl-V - paste the selected console text
+                graphics::selection::paste();
+            }
+            b'\x12' => { // Ctrl-R - reverse incremental history search
+                drop(shell_state);
+                if reverse_search() {
+                    console_println!();
+                    return buffered_command(&SHELL_STATE.lock());
                 }
             }
             b'\x1b' => { // ESC - start of escape sequence
-                if let Ok(Some(sequence)) = read_escape_sequence() {
+                if let Ok(Some(sequence)) = sequence {
                     match sequence {
                         EscapeSequence::UpArrow => {
                             navigate_history_up(&mut shell_state)?;
@@ -359,33 +921,33 @@ fn read_enhanced_command() -> Result<String<MAX_COMMAND_LEN>, &'static str> {
                         EscapeSequence::DownArrow => {
                             navigate_history_down(&mut shell_state)?;
                         }
-                        _ => {
-                            // Ignore other escape sequences for now
+                        EscapeSequence::LeftArrow => {
+                            move_input_cursor(&mut shell_state, cursor.saturating_sub(1));
+                        }
+                        EscapeSequence::RightArrow => {
+                            move_input_cursor(&mut shell_state, (cursor + 1).min(len));
+                        }
+                        EscapeSequence::Home => {
+                            move_input_cursor(&mut shell_state, 0);
+                        }
+                        EscapeSequence::End => {
+                            move_input_cursor(&mut shell_state, len);
+                        }
+                        EscapeSequence::Delete => {
+                            if cursor < len {
+                                shell_state.command_buffer.remove(cursor);
+                                lineedit::redraw_from(&shell_state.command_buffer, cursor, 1, cursor);
+                                input_edited(&mut shell_state);
+                            }
                         }
                     }
                 }
             }
             b' '..=b'~' => { // Printable ASCII
-                if shell_state.command_buffer.len() < MAX_COMMAND_LEN - 1 {
-                    if shell_state.command_buffer.push(ch).is_ok() {
-                        console_print!(
-                            "{}",
-                            core::str::from_utf8(&[ch]).unwrap_or("?")
-                        );
-                        
-                                                 // Update current_input if not navigating history
-                         if shell_state.history_index.is_none() {
-                             // Clone the buffer to avoid borrow checker issues
-                             let buffer_copy = shell_state.command_buffer.clone();
-                             if let Ok(current_str) = core::str::from_utf8(&buffer_copy) {
-                                 shell_state.current_input.clear();
-                                 let _ = shell_state.current_input.push_str(current_str);
-                             }
-                         } else {
-                             // User started typing while in history mode - exit history mode
-                             shell_state.history_index = None;
-                         }
-                    }
+                if len < MAX_COMMAND_LEN - 1 && shell_state.command_buffer.insert(cursor, ch).is_ok() {
+                    lineedit::redraw_from(&shell_state.command_buffer, cursor, 0, cursor + 1);
+                    shell_state.cursor = cursor + 1;
+                    input_edited(&mut shell_state);
                 }
             }
             _ => {
@@ -395,10 +957,127 @@ fn read_enhanced_command() -> Result<String<MAX_COMMAND_LEN>, &'static str> {
     }
 }
 
+/// Move the cursor in the input line to `position`
+fn move_input_cursor(shell_state: &mut ShellState, position: usize) {
+    lineedit::move_cursor(&shell_state.command_buffer, shell_state.cursor, position);
+    shell_state.cursor = position;
+}
+
+/// Note that the input line was edited: it is the line being typed now,
+/// even if it came from the history
+fn input_edited(shell_state: &mut ShellState) {
+    shell_state.history_index = None;
+    let ShellState { command_buffer, current_input, .. } = shell_state;
+    current_input.clear();
+    let _ = current_input.push_str(core::str::from_utf8(command_buffer).unwrap_or(""));
+}
+
+/// The command in the input buffer, trimmed
+fn buffered_command(shell_state: &ShellState) -> Result<String<MAX_COMMAND_LEN>, &'static str> {
+    let command_str = core::str::from_utf8(&shell_state.command_buffer)
+        .map_err(|_| "Invalid UTF-8 in command")?;
+    String::try_from(command_str.trim())
+        .map_err(|_| "Command too long")
+}
+
+/// Search the history backwards for a command containing what is typed, as
+/// bash's Ctrl-R does. Ctrl-R again finds the next older match and Backspace
+/// shortens the query. Enter runs the match, Esc or another control key
+/// keeps it in the input line for editing, and Ctrl-G or Ctrl-C puts the
+/// line back as it was. Returns true if the match is to run now.
+fn reverse_search() -> bool {
+    let (original, mut shown) = {
+        let shell_state = SHELL_STATE.lock();
+        let shown = shell_state.prompt_prefix.len() + SHELL_PROMPT.len() + shell_state.command_buffer.len();
+        (shell_state.command_buffer.clone(), shown)
+    };
+    let mut query = String::<64>::new();
+    let mut found: Option<usize> = None;
+    let mut failing = false;
+    
+    loop {
+        // Show the query and the command it matches
+        let mut prompt = String::<96>::new();
+        let _ = write!(prompt, "({}reverse-i-search)`{}': ", if failing { "failing " } else { "" }, query);
+        {
+            let shell_state = SHELL_STATE.lock();
+            let command = found.and_then(|index| shell_state.history.get(index))
+                .map_or(&b""[..], |entry| entry.command.as_bytes());
+            lineedit::rewrite(shown, &prompt, command);
+            shown = prompt.len() + command.len();
+        }
+        
+        let ch = read_char();
+        let mut shell_state = SHELL_STATE.lock();
+        let older_than = match ch {
+            b'\x12' => found.unwrap_or(shell_state.history.len()),
+            b'\x08' | b'\x7f' => {
+                query.pop();
+                shell_state.history.len()
+            }
+            b' '..=b'~' => {
+                if query.push(ch as char).is_err() {
+                    continue;
+                }
+                // The current match may still do
+                found.map_or(shell_state.history.len(), |index| index + 1)
+            }
+            _ => {
+                if ch == b'\x07' || ch == b'\x03' {
+                    shell_state.command_buffer = original;
+                } else if let Some(index) = found {
+                    load_search_match(&mut shell_state, index);
+                }
+                if ch == b'\x1b' {
+                    // Drop the rest of an arrow key's sequence
+                    drop(shell_state);
+                    let _ = read_escape_sequence();
+                    shell_state = SHELL_STATE.lock();
+                }
+                
+                let mut prompt = String::<{ MAX_PROMPT_PREFIX_LEN + 16 }>::new();
+                let _ = write!(prompt, "{}{}", shell_state.prompt_prefix, SHELL_PROMPT);
+                lineedit::rewrite(shown, &prompt, &shell_state.command_buffer);
+                shell_state.cursor = shell_state.command_buffer.len();
+                return ch == b'\r' || ch == b'\n';
+            }
+        };
+        
+        let matched = if query.is_empty() {
+            None
+        } else {
+            (0..older_than).rev().find(|&index| shell_state.history[index].command.contains(query.as_str()))
+        };
+        failing = matched.is_none() && !query.is_empty();
+        if matched.is_some() || query.is_empty() {
+            found = matched;
+        }
+    }
+}
+
+/// Put the history entry a search stopped at in the input buffer, and carry
+/// on navigating the history from there
+fn load_search_match(shell_state: &mut ShellState, index: usize) {
+    if shell_state.history_index.is_none() {
+        if let Ok(current_str) = core::str::from_utf8(&shell_state.command_buffer) {
+            shell_state.current_input.clear();
+            let _ = shell_state.current_input.push_str(current_str);
+        }
+    }
+    let Some(entry) = shell_state.history.get(index) else {
+        return;
+    };
+    let command = entry.command.clone();
+    shell_state.command_buffer.clear();
+    let _ = shell_state.command_buffer.extend_from_slice(command.as_bytes());
+    shell_state.history_index = Some(index);
+}
+
 /// Read escape sequence for arrow keys
 fn read_escape_sequence() -> Result<Option<EscapeSequence>, &'static str> {
     let ch1 = read_char();
-    if ch1 != b'[' {
+    // Terminals in application cursor mode send ESC O instead of ESC [
+    if ch1 != b'[' && ch1 != b'O' {
         return Ok(None); // Not a standard escape sequence
     }
     
@@ -408,6 +1087,25 @@ fn read_escape_sequence() -> Result<Option<EscapeSequence>, &'static str> {
         b'B' => Ok(Some(EscapeSequence::DownArrow)),
         b'C' => Ok(Some(EscapeSequence::RightArrow)),
         b'D' => Ok(Some(EscapeSequence::LeftArrow)),
+        b'H' => Ok(Some(EscapeSequence::Home)),
+        b'F' => Ok(Some(EscapeSequence::End)),
+        b'0'..=b'9' => {
+            // VT220 editing keys: ESC [ number ~
+            let mut number = u32::from(ch2 - b'0');
+            loop {
+                match read_char() {
+                    digit @ b'0'..=b'9' => number = number.saturating_mul(10).saturating_add(u32::from(digit - b'0')),
+                    b'~' => break,
+                    _ => return Ok(None),
+                }
+            }
+            match number {
+                1 | 7 => Ok(Some(EscapeSequence::Home)),
+                3 => Ok(Some(EscapeSequence::Delete)),
+                4 | 8 => Ok(Some(EscapeSequence::End)),
+                _ => Ok(None),
+            }
+        }
         _ => Ok(None), // Unknown escape sequence
     }
 }
@@ -469,115 +1167,206 @@ fn navigate_history_down(shell_state: &mut ShellState) -> Result<(), &'static st
 
 /// Load a command from history into the command buffer
 fn load_history_command(shell_state: &mut ShellState, index: usize) -> Result<(), &'static str> {
-    if let Some(cmd) = shell_state.history.get(index) {
-        // Clear current line
-        clear_current_line(&shell_state.command_buffer)?;
-        
-        // Load command into buffer
-        shell_state.command_buffer.clear();
-        for byte in cmd.as_bytes() {
-            if shell_state.command_buffer.push(*byte).is_err() {
-                break;
-            }
-        }
-        
-        // Display the command
-        console_print!("{}", cmd);
+    if let Some(entry) = shell_state.history.get(index) {
+        let cmd = entry.command.clone();
+        replace_input(shell_state, cmd.as_bytes());
     }
     Ok(())
 }
 
 /// Restore current input when exiting history navigation
 fn restore_current_input(shell_state: &mut ShellState) -> Result<(), &'static str> {
-    // Clear current line
-    clear_current_line(&shell_state.command_buffer)?;
+    let input = shell_state.current_input.clone();
+    replace_input(shell_state, input.as_bytes());
+    Ok(())
+}
+
+/// Put `line` in the input buffer in place of what is there, with the
+/// cursor at its end
+fn replace_input(shell_state: &mut ShellState, line: &[u8]) {
+    // Redraw only what differs from the line shown
+    let len = shell_state.command_buffer.len();
+    move_input_cursor(shell_state, len);
+    lineedit::replace(&shell_state.command_buffer, line);
     
-    // Load current input into buffer
     shell_state.command_buffer.clear();
-    for byte in shell_state.current_input.as_bytes() {
+    for byte in line {
         if shell_state.command_buffer.push(*byte).is_err() {
             break;
         }
     }
-    
-    // Display the current input
-    console_print!("{}", &shell_state.current_input);
-    Ok(())
-}
-
-/// Clear the current line on the terminal
-fn clear_current_line(command_buffer: &[u8]) -> Result<(), &'static str> {
-    // Move cursor to beginning of line and clear to end
-    console_print!("\r");
-    console_print!("elinOS> ");
-    
-    // Also redraw prompt to framebuffer TTY if graphics are available
-    let _ = graphics::print_shell_prompt();
-    
-    // Clear rest of line by printing spaces
-    for _ in 0..command_buffer.len() {
-        console_print!(" ");
-    }
-    
-    // Move cursor back to start of input area
-    console_print!("\r");
-    console_print!("elinOS> ");
-    
-    // Redraw prompt to framebuffer TTY again
-    let _ = graphics::print_shell_prompt();
-    
-    Ok(())
+    shell_state.cursor = shell_state.command_buffer.len();
 }
 
-/// Add command to history
-fn add_to_history(command: &str) {
+/// Add command to history. Returns true if the last history entry is now
+/// this command, so its exit status can be recorded there.
+fn add_to_history(command: &str) -> bool {
+    let size = history_size();
+    let erase_dups = history_erases_dups();
     let mut shell_state = SHELL_STATE.lock();
     
     // Don't add duplicate consecutive commands
-    if let Some(last_cmd) = shell_state.history.last() {
-        if last_cmd == command {
-            return;
+    if let Some(last) = shell_state.history.last() {
+        if last.command == command {
+            return true;
         }
     }
     
     // Don't add history command itself to history
-    if command.trim() == "history" {
-        return;
+    if command.split_whitespace().next() == Some("history") {
+        return false;
     }
     
     // Create history entry
     if let Ok(cmd_string) = String::try_from(command) {
-        // Remove oldest entry if at capacity
-        if shell_state.history.len() >= MAX_HISTORY_ENTRIES {
-            shell_state.history.remove(0);
+        let entry = HistoryEntry { command: cmd_string, status: None, time: common::rtc::unix_time() };
+        push_history_entry(&mut shell_state.history, entry, size, erase_dups);
+        if shell_state.history.last().is_some_and(|last| last.command == command) {
+            // Saved with its status once it has run
+            shell_state.unsaved_entry = true;
+            return true;
+        }
+    }
+    false
+}
+
+/// Remember the exit status of the command just run, for `$?`, the prompt
+/// and (if it was recorded) its history entry
+fn record_status(status: i32, in_history: bool) {
+    {
+        let mut shell_state = SHELL_STATE.lock();
+        shell_state.last_status = status;
+        if !in_history {
+            return;
+        }
+        match shell_state.history.last_mut() {
+            Some(entry) => entry.status = Some(status),
+            None => return,
+        }
+    }
+    append_shell_history();
+}
+
+/// How a command in a list is joined to the one before it
+#[derive(Clone, Copy)]
+enum Connector {
+    /// `&&`: run it if the one before succeeded
+    And,
+    /// `||`: run it if the one before failed
+    Or,
+}
+
+/// What running a command list came to
+struct ListOutcome {
+    status: i32,
+    result: Result<(), &'static str>,
+    /// The status is that of a command tested by `&&` or `||`, not of the
+    /// last in the list
+    tested: bool,
+}
+
+/// The first command of a list and, if more follow, how the next is joined
+/// to it and the rest of the list
+fn next_in_list(list: &str) -> (&str, Option<(Connector, &str)>) {
+    let split = [("&&", Connector::And), ("||", Connector::Or)].into_iter()
+        .filter_map(|(operator, connector)| list.find(operator).map(|at| (at, connector)))
+        .min_by_key(|&(at, _)| at);
+    match split {
+        Some((at, connector)) => (&list[..at], Some((connector, &list[at + 2..]))),
+        None => (list, None),
+    }
+}
+
+/// Run a command line, which may chain commands with `&&` and `||` as
+/// other shells do, left to right. `$?` is set after each command. Errors
+/// of all but the last command run are reported here; the last one's is
+/// returned.
+fn run_command_list(line: &str) -> ListOutcome {
+    let mut rest = Some(line);
+    while let Some(list) = rest {
+        let (command, next) = next_in_list(list);
+        if command.trim().is_empty() {
+            return ListOutcome { status: 2, result: Err("Syntax error: missing command around && or ||"), tested: false };
+        }
+        rest = next.map(|(_, list)| list);
+    }
+    
+    let mut outcome = ListOutcome { status: 0, result: Ok(()), tested: false };
+    let mut rest = Some((Connector::And, line));
+    while let Some((connector, list)) = rest {
+        let (command, next) = next_in_list(list);
+        rest = next;
+        let runs = match connector {
+            Connector::And => outcome.status == 0,
+            Connector::Or => outcome.status != 0,
+        };
+        if !runs {
+            continue;
+        }
+        if let Err(e) = outcome.result {
+            crate::msg_println!(Msg::CommandError, e);
         }
         
-        // Add new command
-        if shell_state.history.push(cmd_string).is_ok() {
-            // Save history to file (ignore errors)
-            drop(shell_state); // Release lock before saving
-            save_shell_history();
+        let (status, result) = process_enhanced_command(command.trim());
+        SHELL_STATE.lock().last_status = status;
+        outcome = ListOutcome { status, result, tested: rest.is_some() };
+        if outcome.result == Err("exit_shell") {
+            break;
         }
     }
+    outcome
 }
 
-/// Process enhanced command with built-in shell commands
-fn process_enhanced_command(command: &str) -> Result<(), &'static str> {
+/// Process enhanced command with built-in shell commands.
+/// Returns the exit status along with the result.
+fn process_enhanced_command(command: &str) -> (i32, Result<(), &'static str>) {
     if command.is_empty() {
-        return Ok(());
+        return (0, Ok(()));
+    }
+    
+    // A definition is kept as typed, so its variables are expanded each
+    // time the alias is used
+    if command.split_whitespace().next() == Some("alias") {
+        let result = cmd_shell_alias(&command.trim_start()["alias".len()..]);
+        return (if result.is_ok() { 0 } else { 1 }, result);
+    }
+    
+    let aliased = match expand_aliases(command) {
+        Ok(aliased) => aliased,
+        Err(e) => return (1, Err(e)),
+    };
+    let expanded = match expand_env_vars(&aliased) {
+        Ok(expanded) => expanded,
+        Err(e) => return (1, Err(e)),
+    };
+    let command = expanded.as_str();
+    
+    // A program followed by `&` runs in the background
+    if let Some(program) = command.trim_end().strip_suffix('&') {
+        return commands::execute_background(program.trim());
     }
     
     let parts: Vec<&str, 16> = command.split_whitespace().collect();
     if parts.is_empty() {
-        return Ok(());
+        return (0, Ok(()));
     }
     
     let cmd = parts[0];
     
-    match cmd {
-        "help" => cmd_shell_help(),
-        "history" => cmd_shell_history(),
-        "exit" | "quit" => Err("exit_shell"),
+    let result = match cmd {
+        "help" if parts.len() == 1 => cmd_shell_help(),
+        "history" => cmd_shell_history(&command.trim_start()["history".len()..]),
+        "exit" | "quit" => {
+            // The status a script ends with: the one given, or the last one
+            let status = parts.get(1).and_then(|status| status.parse().ok())
+                .unwrap_or_else(|| SHELL_STATE.lock().last_status);
+            return (status, Err("exit_shell"));
+        }
+        "run" | "source" => return script::run(cmd, &command.trim_start()[cmd.len()..]),
+        "set" => cmd_shell_set(command),
+        "unset" => cmd_shell_unset(&parts[1..]),
+        "unalias" => cmd_shell_unalias(&parts[1..]),
+        "env" => cmd_shell_env(),
         "shutdown" => {
             console_println!("Shutting down system...");
             commands::cmd_shutdown()
@@ -588,59 +1377,80 @@ fn process_enhanced_command(command: &str) -> Result<(), &'static str> {
         }
         _ => {
             // Delegate to existing command processor
-            commands::process_command(command)
+            return commands::execute_command(command);
         }
-    }
+    };
+    (if result.is_ok() { 0 } else { 1 }, result)
 }
 
 /// Built-in shell help command
 fn cmd_shell_help() -> Result<(), &'static str> {
-    console_println!("Built-in Shell Commands:");
-    console_println!("  help     - Show this help message");
-    console_println!("  history  - Show command history");
-    console_println!("  exit     - Exit the shell");
-    console_println!("  quit     - Exit the shell");
-    console_println!("  shutdown - Shutdown the system");
-    console_println!("  reboot   - Reboot the system");
+    commands::cmd_help()?;
     console_println!();
     console_println!("Navigation:");
-    console_println!("  Up/Down  - Navigate command history");
-    console_println!("  Backspace- Edit current command");
+    console_println!("  Up/Down    - Navigate command history");
+    console_println!("  Ctrl-R     - Search back through the history");
+    console_println!("  Left/Right - Move the cursor in the line");
+    console_println!("  Home/End   - Start or end of the line (also Ctrl-A/Ctrl-E)");
+    console_println!("  Backspace/Delete - Delete before or at the cursor");
     console_println!();
-    console_println!("System Commands:");
-    // Delegate to existing help for system commands
-    commands::cmd_help()
+    console_println!("Exit status:");
+    console_println!("  $?       - Status of the last command");
+    console_println!("  A failing command shows its status before the prompt;");
+    console_println!("  set {}= to hide it, or change its format", STATUS_INDICATOR_VAR);
+    console_println!("  a && b   - Run b if a succeeded; a || b runs b if a failed");
+    console_println!("  run FILE - Run the commands in a script");
+    console_println!("  prog &   - Run a program in the background; see jobs, fg, kill");
+    Ok(())
 }
 
-/// History command - show command history
-fn cmd_shell_history() -> Result<(), &'static str> {
+const HISTORY_SPEC: args::CommandSpec = args::CommandSpec {
+    name: "history",
+    options: &[args::OptSpec::flag('t', "time"), args::OptSpec::flag('c', "clear")],
+    min_positional: 0,
+    max_positional: 0,
+};
+
+/// History command - show or clear command history
+fn cmd_shell_history(args: &str) -> Result<(), &'static str> {
+    let parsed = match args::parse_command(&HISTORY_SPEC, args)? {
+        Some(parsed) => parsed,
+        None => return Ok(()),
+    };
+    if parsed.has("clear") {
+        return clear_shell_history();
+    }
+    let show_time = parsed.has("time");
+    
     let shell_state = SHELL_STATE.lock();
     
-    console_println!("Command History:");
-    console_println!("────────────────");
+    crate::msg_println!(Msg::HistoryTitle);
+    crate::msg_println!(Msg::Separator);
     
     if shell_state.history.is_empty() {
-        console_println!("  (no commands in history)");
+        crate::msg_println!(Msg::HistoryEmpty);
     } else {
-        for (i, cmd) in shell_state.history.iter().enumerate() {
-            console_print!("  ");
-            if i + 1 < 10 {
-                console_print!("  ");
-            } else if i + 1 < 100 {
-                console_print!(" ");
-            }
-            
-            // Simple number display
-            let num = i + 1;
-            if num < 10 {
-                console_print!("{}", num);
-            } else if num < 100 {
-                console_print!("{}", num);
+        if show_time {
+            console_println!("  {:>3}  {:<19}  {:>6}  {}", "#", "Time (UTC)", "Status", "Command");
+        } else {
+            console_println!("  {:>3}  {:>6}  {}", "#", "Status", "Command");
+        }
+        for (i, entry) in shell_state.history.iter().enumerate() {
+            let mut status = String::<12>::new();
+            let _ = match entry.status {
+                Some(code) => write!(status, "{}", code),
+                None => write!(status, "-"),
+            };
+            if show_time {
+                let mut time = String::<20>::new();
+                let _ = match entry.time {
+                    Some(seconds) => write!(time, "{}", common::rtc::DateTime::from_unix(seconds)),
+                    None => write!(time, "-"),
+                };
+                console_println!("  {:>3}  {:<19}  {:>6}  {}", i + 1, time, status, entry.command);
             } else {
-                console_print!("99+");
+                console_println!("  {:>3}  {:>6}  {}", i + 1, status, entry.command);
             }
-            console_print!("  ");
-            console_println!("{}", cmd);
         }
     }
     
@@ -650,10 +1460,214 @@ fn cmd_shell_history() -> Result<(), &'static str> {
     Ok(())
 }
 
-/// Read a character from UART
+/// Set command - define or update an environment variable
+fn cmd_shell_set(command: &str) -> Result<(), &'static str> {
+    let assignment = command.trim_start()["set".len()..].trim();
+    if assignment.is_empty() {
+        return cmd_shell_env();
+    }
+    
+    if matches!(assignment, "-e" | "+e" | "-x" | "+x") {
+        return Err("set -e and set -x only apply in scripts");
+    }
+    let (name, value) = assignment.split_once('=')
+        .ok_or("Usage: set VAR=value")?;
+    let name = name.trim();
+    if !is_valid_env_name(name) {
+        return Err("Invalid variable name");
+    }
+    
+    let name = String::try_from(name).map_err(|_| "Variable name too long")?;
+    let value = String::try_from(value.trim()).map_err(|_| "Variable value too long")?;
+    
+    {
+        let mut shell_state = SHELL_STATE.lock();
+        shell_state.env.insert(name, value).map_err(|_| "Environment is full")?;
+    }
+    
+    save_shell_env();
+    Ok(())
+}
+
+/// Unset command - remove environment variables
+fn cmd_shell_unset(names: &[&str]) -> Result<(), &'static str> {
+    if names.is_empty() {
+        return Err("Usage: unset VAR [VAR...]");
+    }
+    
+    {
+        let mut shell_state = SHELL_STATE.lock();
+        for name in names {
+            if let Ok(key) = String::<MAX_ENV_NAME_LEN>::try_from(*name) {
+                shell_state.env.remove(&key);
+            }
+        }
+    }
+    
+    save_shell_env();
+    Ok(())
+}
+
+/// Alias command - list, show or define aliases
+fn cmd_shell_alias(args: &str) -> Result<(), &'static str> {
+    let definition = args.trim();
+    let shell_state = SHELL_STATE.lock();
+    if definition.is_empty() {
+        for (name, value) in shell_state.aliases.iter() {
+            console_println!("alias {}='{}'", name, value);
+        }
+        return Ok(());
+    }
+    
+    let Some((name, value)) = definition.split_once('=') else {
+        let name = String::<MAX_ALIAS_NAME_LEN>::try_from(definition).map_err(|_| "No such alias")?;
+        let value = shell_state.aliases.get(&name).ok_or("No such alias")?;
+        console_println!("alias {}='{}'", name, value);
+        return Ok(());
+    };
+    drop(shell_state);
+    
+    let name = name.trim();
+    if !is_valid_alias_name(name) {
+        return Err("Invalid alias name");
+    }
+    // Quotes around the command are dropped, as other shells do
+    let value = value.trim();
+    let value = ['\'', '"'].into_iter()
+        .find_map(|quote| value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)))
+        .unwrap_or(value)
+        .trim();
+    if value.is_empty() {
+        return Err("Usage: alias name='command args'");
+    }
+    if value.contains("&&") || value.contains("||") {
+        return Err("An alias holds one command; put more in a script");
+    }
+    
+    let name = String::try_from(name).map_err(|_| "Alias name too long")?;
+    let value = String::try_from(value).map_err(|_| "Alias command too long")?;
+    SHELL_STATE.lock().aliases.insert(name, value).map_err(|_| "Too many aliases")?;
+    
+    save_shell_aliases();
+    Ok(())
+}
+
+/// Unalias command - remove aliases, or all of them with -a
+fn cmd_shell_unalias(names: &[&str]) -> Result<(), &'static str> {
+    if names.is_empty() {
+        return Err("Usage: unalias -a | unalias NAME [NAME...]");
+    }
+    
+    let mut missing = false;
+    {
+        let mut shell_state = SHELL_STATE.lock();
+        if names.contains(&"-a") {
+            shell_state.aliases.clear();
+        }
+        for name in names.iter().filter(|&&name| name != "-a") {
+            let removed = String::<MAX_ALIAS_NAME_LEN>::try_from(*name).ok()
+                .and_then(|key| shell_state.aliases.remove(&key));
+            missing |= removed.is_none();
+        }
+    }
+    
+    save_shell_aliases();
+    if missing {
+        return Err("No such alias");
+    }
+    Ok(())
+}
+
+/// Env command - list environment variables
+fn cmd_shell_env() -> Result<(), &'static str> {
+    let shell_state = SHELL_STATE.lock();
+    for (name, value) in shell_state.env.iter() {
+        console_println!("{}={}", name, value);
+    }
+    Ok(())
+}
+
+/// Run the `at` and periodic jobs that are due, then show the prompt and
+/// the line being typed again, with the cursor where it was
+fn run_due_jobs() {
+    let mut ran = false;
+    while let Some(job) = cron::take_due() {
+        if !ran {
+            console_println!();
+            ran = true;
+        }
+        match job.id {
+            cron::JobId::At(id) => info_println!(target: "cron", "Running job {}: {}", id, job.command),
+            cron::JobId::Periodic(_) => info_println!(target: "cron", "Running periodic job: {}", job.command),
+        }
+        let last_status = SHELL_STATE.lock().last_status;
+        let outcome = run_command_list(&job.command);
+        SHELL_STATE.lock().last_status = last_status;
+        if let Err(e) = outcome.result {
+            crate::msg_println!(Msg::CommandError, e);
+        }
+        cron::finished(job.id, outcome.status);
+    }
+    if ran {
+        redraw_input_line();
+    }
+}
+
+/// Give the next background job a slice of time, reporting it if it ends.
+/// Returns false if none was ready to run.
+fn run_background_jobs() -> bool {
+    match jobs::run_slice() {
+        jobs::Slice::Idle => false,
+        jobs::Slice::Ran => true,
+        jobs::Slice::Finished(finished) => {
+            console_println!();
+            jobs::report(&finished);
+            redraw_input_line();
+            true
+        }
+    }
+}
+
+/// Show the prompt and the line being typed again, with the cursor where
+/// it was
+fn redraw_input_line() {
+    let shell_state = SHELL_STATE.lock();
+    let line = &shell_state.command_buffer;
+    console_print!("{}{}{}", shell_state.prompt_prefix, SHELL_PROMPT, core::str::from_utf8(line).unwrap_or(""));
+    lineedit::move_cursor(line, line.len(), shell_state.cursor);
+}
+
+/// Idle time between VirtIO hotplug scans while waiting for input
+const HOTPLUG_POLL_TICKS: u64 = trap::TICK_HZ / 2;
+
+/// Read a character from the TTY, sleeping until one arrives. The kernel
+/// tick wakes the wait too, so hotplug scans go on while the shell is idle.
+/// Background jobs run in the meantime, if there are any ready.
 fn read_char() -> u8 {
-    let uart = UART.lock();
-    uart.getc()
+    let mut last_scan = trap::tick_count();
+    loop {
+        if let Some(ch) = common::tty::read_byte() {
+            return ch;
+        }
+        if trap::tick_count().wrapping_sub(last_scan) >= HOTPLUG_POLL_TICKS {
+            last_scan = trap::tick_count();
+            if virtio::hotplug::scan() > 0 {
+                filesystem::automount::handle_events();
+            }
+            // A quiet moment: pass on the kernel log, write out buffered
+            // appends, then the sectors that have been dirty long enough
+            let _ = klog::flush_to_disk();
+            let _ = filesystem::append::flush_all();
+            let _ = filesystem::cache::write_back_expired();
+            run_due_jobs();
+        }
+        watchdog::pet(watchdog::Progress::Shell);
+        if run_background_jobs() {
+            continue;
+        }
+        // Any interrupt ends the wait, pointer input included
+        common::uart::wait_for_input();
+    }
 }
 
 // Stack top symbol
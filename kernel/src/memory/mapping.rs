@@ -121,6 +121,7 @@ impl MemoryMappingManager {
     }
 
     /// Allocate and map virtual memory
+    #[track_caller]
     pub fn map_virtual_memory(
         &mut self,
         size: usize,
@@ -345,6 +346,7 @@ pub fn map_device_memory(
 }
 
 /// Allocate and map virtual memory
+#[track_caller]
 pub fn map_virtual_memory(
     size: usize,
     permissions: MemoryPermissions,
@@ -0,0 +1,97 @@
+//! Allocation tracking and poisoning switches, and the `memdebug` report
+//!
+//! The tracker itself is in the unified memory manager
+//! (`elinos_common::memory::tracker`); here it is turned on and off with
+//! `vm.alloc_tracking` and `vm.alloc_poison`, and its tables shown. Turning
+//! tracking on starts from empty tables, so blocks allocated before then
+//! are not listed and their frees count as unmatched. Saving the settings
+//! with `sysctl -s` turns them on at the next boot, once the config store
+//! is read; the allocations of earlier boot stages are still not seen.
+
+use heapless::Vec;
+use elinos_common::console_println;
+use elinos_common::memory::tracker::{TagStats, MAX_TAGS};
+use elinos_common::memory::with_memory_manager;
+use elinos_common::timer;
+
+use crate::sysctl::{Kind, Tunable};
+
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "vm.alloc_tracking",
+        description: "Record each allocation with its caller, for memdebug",
+        kind: Kind::Choice { choices: &["off", "on"], get: get_tracking, set: set_tracking },
+    },
+    Tunable {
+        name: "vm.alloc_poison",
+        description: "Fill freed memory with 0x6b, so a use after free stands out",
+        kind: Kind::Choice { choices: &["off", "on"], get: get_poison, set: set_poison },
+    },
+];
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+fn get_tracking() -> &'static str {
+    on_off(with_memory_manager(|manager| manager.tracker().tracking()))
+}
+
+fn set_tracking(value: &'static str) -> Result<(), &'static str> {
+    with_memory_manager(|manager| manager.tracker().set_tracking(value == "on"));
+    Ok(())
+}
+
+fn get_poison() -> &'static str {
+    on_off(with_memory_manager(|manager| manager.tracker().poisoning()))
+}
+
+fn set_poison(value: &'static str) -> Result<(), &'static str> {
+    with_memory_manager(|manager| manager.tracker().set_poisoning(value == "on"));
+    Ok(())
+}
+
+/// Print the live allocations by caller, most bytes first, and with `list`
+/// each recorded allocation too. Returns false if tracking is off.
+pub fn show(list: bool) -> bool {
+    with_memory_manager(|manager| {
+        let tracker = manager.tracker();
+        if !tracker.tracking() {
+            return false;
+        }
+
+        let mut tags: Vec<TagStats, { MAX_TAGS + 1 }> = tracker.tags().copied().collect();
+        tags.sort_unstable_by(|a, b| b.live_bytes.cmp(&a.live_bytes).then(b.live.cmp(&a.live)));
+        let (live, live_bytes) = tags.iter().fold((0, 0), |(live, bytes), tag| (live + tag.live, bytes + tag.live_bytes));
+
+        console_println!("Poisoning: {}", on_off(tracker.poisoning()));
+        console_println!("Live: {} blocks, {} KB ({} not recorded, table full)",
+            live, live_bytes / 1024, tracker.untracked());
+        console_println!("Unmatched frees: {}, size mismatches: {}", tracker.unmatched_frees(), tracker.size_mismatches());
+        console_println!("{:>6} {:>10} {:>8} {:>8}  Caller", "Live", "Bytes", "Allocs", "Frees");
+        for tag in &tags {
+            match tag.caller {
+                Some(caller) => console_println!("{:>6} {:>10} {:>8} {:>8}  {}:{}",
+                    tag.live, tag.live_bytes, tag.allocations, tag.frees, caller.file(), caller.line()),
+                None => console_println!("{:>6} {:>10} {:>8} {:>8}  (other callers)",
+                    tag.live, tag.live_bytes, tag.allocations, tag.frees),
+            }
+        }
+
+        if list {
+            let now = timer::ticks();
+            console_println!();
+            console_println!("{:<18} {:>10} {:>8}  Caller", "Address", "Size", "Age (s)");
+            for (allocation, caller) in tracker.live() {
+                let age = timer::ticks_to_ns(now.saturating_sub(allocation.allocated_at)) / 1_000_000_000;
+                match caller {
+                    Some(caller) => console_println!("0x{:<16x} {:>10} {:>8}  {}:{}",
+                        allocation.addr, allocation.size, age, caller.file(), caller.line()),
+                    None => console_println!("0x{:<16x} {:>10} {:>8}  (other callers)",
+                        allocation.addr, allocation.size, age),
+                }
+            }
+        }
+        true
+    })
+}
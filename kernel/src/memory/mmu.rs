@@ -5,10 +5,35 @@
 //! - 4KB pages
 //! - Kernel and user space separation
 //! - Virtual-to-physical address translation
+//!
+//! The kernel identity maps devices and RAM with gigapages. Each user program
+//! gets its own address space holding its segments at their linked addresses
+//! plus supervisor-only copies of the kernel mappings, so traps need no satp
+//! switch. Harts without Sv39 fall back to software translation.
+//!
+//! ELF segments can be demand paged: the address space records which file
+//! range backs each segment and the page fault handler reads a page in from
+//! the filesystem the first time it is touched.
+//!
+//! Programs can add memory with mmap: anonymous mappings and private copies
+//! of files are placed in the mmap area between the program and its stack.
+//!
+//! `vmas` lists an address space as ranges with one set of permissions and
+//! one source each, for /proc/<pid>/maps and `pmap`.
+//!
+//! With swap on, an address space short of memory swaps out its own
+//! writable pages that were not used lately and frees their frames; the
+//! page fault handler reads them back in. See `swap`.
+//!
+//! When an allocation fails and compacting the heap does not free enough,
+//! the memory manager calls `migrate_user_pages`, which moves writable user
+//! pages out of the highest frames into freed holes lower down, so their
+//! frames can join the free end of the heap too.
 
 use core::arch::asm;
 use spin::Mutex;
-use elinos_common::console_println;
+use heapless::{Vec, String, FnvIndexMap};
+use elinos_common::{err_println, warn_println};
 
 /// Page size (4KB)
 pub const PAGE_SIZE: usize = 4096;
@@ -29,12 +54,64 @@ pub const PTE_U: u64 = 1 << 4;  // User
 pub const PTE_G: u64 = 1 << 5;  // Global
 pub const PTE_A: u64 = 1 << 6;  // Accessed
 pub const PTE_D: u64 = 1 << 7;  // Dirty
+pub const PTE_COW: u64 = 1 << 8; // RSW: write-protected copy-on-write page
+pub const PTE_SWAPPED: u64 = 1 << 9; // RSW: with V clear, page in the swap slot held in the PPN
 
 /// Virtual address layout for Sv39
 pub const KERNEL_BASE: usize = 0xFFFF_FFC0_0000_0000;
 pub const USER_BASE: usize = 0x0000_0000_1000_0000;  // 256MB
 pub const USER_STACK: usize = 0x0000_0000_7000_0000; // 1.75GB
 pub const USER_HEAP: usize = 0x0000_0000_1000_0000;  // 256MB
+/// Page holding the exit stub user programs return into (one guard page above the stack)
+pub const USER_TRAMPOLINE: usize = USER_STACK + PAGE_SIZE;
+/// Where mmap places mappings that do not ask for an address
+pub const USER_MMAP_BASE: usize = 0x0000_0000_4000_0000; // 1GB
+/// End of the mmap area, well below the stack
+pub const USER_MMAP_END: usize = 0x0000_0000_6000_0000;  // 1.5GB
+/// Where position-independent programs are loaded, above the device windows
+pub const USER_PIE_BASE: usize = 0x0000_0000_2000_0000;  // 512MB
+
+/// Size of the pages mapped by a leaf at each page table level (4KB, 2MB, 1GB)
+const LEVEL_PAGE_SIZE: [usize; 3] = [PAGE_SIZE, 2 * 1024 * 1024, 1024 * 1024 * 1024];
+
+/// sstatus.SUM: lets supervisor mode access user pages (syscall buffers)
+const SSTATUS_SUM: usize = 1 << 18;
+
+/// Device windows the kernel needs while a user address space is active.
+/// Mapped with 2MB megapages and without the U bit.
+const KERNEL_DEVICE_WINDOWS: [(usize, usize); 4] = [
+    (0x0000_0000, 0x0020_0000), // Test finisher, goldfish RTC
+    (0x0200_0000, 0x0020_0000), // CLINT
+    (0x0c00_0000, 0x0060_0000), // PLIC
+    (0x1000_0000, 0x0020_0000), // UART, VirtIO MMIO, fw_cfg
+];
+
+/// Root table slots identity mapping RAM (0x80000000-0xFFFFFFFF) with gigapages
+const KERNEL_RAM_SLOTS: [usize; 2] = [2, 3];
+
+/// Maximum number of separate allocations (page tables and frames) owned by one address space
+const MAX_ADDRESS_SPACE_ALLOCATIONS: usize = 256;
+
+/// Maximum number of demand-paged segments per address space
+const MAX_LAZY_REGIONS: usize = 8;
+
+/// Maximum number of mmap regions per address space
+const MAX_MMAP_REGIONS: usize = 16;
+
+/// Maximum number of live user address spaces (a program and its forked descendants)
+const MAX_USER_SPACES: usize = 8;
+
+/// Maximum number of frames shared copy-on-write at once
+const MAX_SHARED_FRAMES: usize = 512;
+
+/// Most ranges `AddressSpace::vmas` lists
+pub const MAX_VMAS: usize = 32;
+
+/// Most pages `AddressSpace::reclaim` swaps out at once
+const RECLAIM_BATCH: usize = 16;
+
+/// Most pages `AddressSpace::migrate` moves at once
+const MIGRATE_BATCH: usize = 64;
 
 /// Page table entry
 #[derive(Debug, Clone, Copy)]
@@ -54,6 +131,20 @@ impl PageTableEntry {
         PageTableEntry((ppn << 10) | PTE_V)
     }
     
+    /// Entry of a page swapped out to `slot`, keeping its R, W, X and U bits
+    pub fn new_swapped(slot: usize, flags: u64) -> Self {
+        PageTableEntry(((slot as u64) << 10) | (flags & (PTE_R | PTE_W | PTE_X | PTE_U)) | PTE_SWAPPED)
+    }
+    
+    pub fn is_swapped(&self) -> bool {
+        !self.is_valid() && (self.0 & PTE_SWAPPED) != 0
+    }
+    
+    /// Swap slot of a swapped out page
+    pub fn swap_slot(&self) -> usize {
+        self.ppn() as usize
+    }
+    
     pub fn is_valid(&self) -> bool {
         (self.0 & PTE_V) != 0
     }
@@ -103,10 +194,164 @@ impl PageTable {
     }
 }
 
+
+/// Split a virtual address into its three VPN indices
+fn vpn_indices(vaddr: usize) -> [usize; 3] {
+    [
+        (vaddr >> 12) & 0x1FF,  // VPN[0]
+        (vaddr >> 21) & 0x1FF,  // VPN[1]
+        (vaddr >> 30) & 0x1FF,  // VPN[2]
+    ]
+}
+
+/// Walk the tables under `root` and return the leaf entry mapping `vaddr`
+/// together with its level (0 = 4KB, 1 = 2MB, 2 = 1GB)
+fn find_leaf_in(root: usize, vaddr: usize) -> Option<(*mut PageTableEntry, usize)> {
+    let vpn = vpn_indices(vaddr);
+    let mut table = root as *mut PageTable;
+    
+    for level in (0..3).rev() {
+        let entry = unsafe { &mut (*table).entries[vpn[level]] };
+        
+        if !entry.is_valid() {
+            return None;
+        }
+        
+        if entry.is_leaf() {
+            return Some((entry as *mut PageTableEntry, level));
+        }
+        
+        if level == 0 {
+            return None; // Should have found leaf by now
+        }
+        
+        table = entry.paddr() as *mut PageTable;
+    }
+    
+    None
+}
+
+/// The 4KB entry for `vaddr` under `root`, valid or not, if the tables
+/// down to it exist
+fn find_entry_in(root: usize, vaddr: usize) -> Option<*mut PageTableEntry> {
+    let vpn = vpn_indices(vaddr);
+    let mut table = root as *mut PageTable;
+    
+    for level in (1..3).rev() {
+        let entry = unsafe { &(*table).entries[vpn[level]] };
+        if !entry.is_valid() || entry.is_leaf() {
+            return None;
+        }
+        table = entry.paddr() as *mut PageTable;
+    }
+    
+    Some(unsafe { &mut (*table).entries[vpn[0]] as *mut PageTableEntry })
+}
+
 /// Address space (collection of page tables)
 pub struct AddressSpace {
     pub root_table_addr: usize,
     pub satp_value: u64,
+    /// Page tables and frames owned by this address space, freed on drop
+    allocations: Vec<(usize, usize), MAX_ADDRESS_SPACE_ALLOCATIONS>,
+    /// Segments paged in from `backing_file` on first access
+    lazy_regions: Vec<LazyRegion, MAX_LAZY_REGIONS>,
+    backing_file: String<128>,
+    /// Ranges set up by mmap
+    mmap_regions: Vec<MmapRegion, MAX_MMAP_REGIONS>,
+}
+
+/// A user segment whose pages are read from a file when first touched
+#[derive(Debug, Clone, Copy)]
+struct LazyRegion {
+    vaddr: usize,
+    memsz: usize,
+    file_offset: usize,
+    file_size: usize,
+    flags: u64,
+}
+
+/// A range mapped by mmap
+#[derive(Debug, Clone)]
+struct MmapRegion {
+    vaddr: usize,
+    /// Length in bytes, a multiple of the page size
+    len: usize,
+    /// Frames allocated for the region, freed when all of it is unmapped.
+    /// None if there are none (PROT_NONE) or they are shared with a fork.
+    frames: Option<usize>,
+    /// File the contents were copied from; None for anonymous memory
+    source: Option<String<64>>,
+}
+
+/// Where the contents of a mapping come from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backing {
+    Anonymous,
+    File,
+    /// A device file such as /dev/fb0
+    Device,
+}
+
+impl Backing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backing::Anonymous => "anon",
+            Backing::File => "file",
+            Backing::Device => "device",
+        }
+    }
+
+    fn of_path(path: &str) -> Backing {
+        if path.starts_with("/dev/") { Backing::Device } else { Backing::File }
+    }
+}
+
+/// A range of a user address space with one set of permissions and one
+/// source
+#[derive(Debug, Clone)]
+pub struct Vma {
+    pub start: usize,
+    pub end: usize,
+    /// PTE_R, PTE_W and PTE_X; copy-on-write pages count as writable
+    pub perms: u64,
+    pub backing: Backing,
+    /// Path of the file, a label such as `[heap]`, or empty
+    pub name: String<64>,
+    /// Pages of the range present in memory
+    pub resident: usize,
+}
+
+impl Vma {
+    fn new(start: usize, end: usize, perms: u64, backing: Backing, name: &str) -> Vma {
+        let mut label = String::new();
+        for ch in name.chars() {
+            if label.push(ch).is_err() {
+                break;
+            }
+        }
+        Vma { start, end, perms, backing, name: label, resident: 0 }
+    }
+
+    /// Permissions as `rwxp`, private as every mapping here is
+    pub fn perms_str(&self) -> [u8; 4] {
+        let bit = |flag: u64, ch: u8| if self.perms & flag != 0 { ch } else { b'-' };
+        [bit(PTE_R, b'r'), bit(PTE_W, b'w'), bit(PTE_X, b'x'), b'p']
+    }
+}
+
+/// Read, write and execute permission of a leaf, counting copy-on-write
+/// as writable
+fn user_perms(entry: &PageTableEntry) -> u64 {
+    let flags = entry.flags();
+    let cow = if flags & PTE_COW != 0 { PTE_W } else { 0 };
+    (flags & (PTE_R | PTE_W | PTE_X)) | cow
+}
+
+impl MmapRegion {
+    fn end(&self) -> usize {
+        self.vaddr + self.len
+    }
 }
 
 // SAFETY: AddressSpace only contains primitive types and addresses
@@ -128,9 +373,16 @@ impl AddressSpace {
         let ppn = (root_addr >> PAGE_SHIFT) as u64;
         let satp_value = SATP_MODE_SV39 | ppn;
         
+        let mut allocations = Vec::new();
+        let _ = allocations.push((root_addr, PAGE_SIZE));
+        
         Some(AddressSpace {
             root_table_addr: root_addr,
             satp_value,
+            allocations,
+            lazy_regions: Vec::new(),
+            backing_file: String::new(),
+            mmap_regions: Vec::new(),
         })
     }
     
@@ -139,84 +391,110 @@ impl AddressSpace {
         self.root_table_addr as *mut PageTable
     }
     
-    /// Map a virtual page to a physical page
-    pub fn map_page(&mut self, vaddr: usize, paddr: usize, flags: u64) -> Result<(), &'static str> {
-        let vpn = [
-            (vaddr >> 12) & 0x1FF,  // VPN[0]
-            (vaddr >> 21) & 0x1FF,  // VPN[1] 
-            (vaddr >> 30) & 0x1FF,  // VPN[2]
-        ];
+    /// Allocate zeroed, page-aligned memory owned by this address space.
+    /// With swap on, pages of this address space are swapped out first if
+    /// memory is low, and for as long as the allocation fails.
+    fn allocate_zeroed(&mut self, size: usize) -> Result<usize, &'static str> {
+        if self.allocations.is_full() {
+            return Err("Too many allocations in address space");
+        }
+        
+        if crate::memory::swap::under_pressure() {
+            self.reclaim(RECLAIM_BATCH);
+        }
+        let addr = loop {
+            if let Some(addr) = crate::memory::allocate_kernel_memory(size, PAGE_SIZE) {
+                break addr;
+            }
+            if !crate::memory::swap::is_on() || self.reclaim(RECLAIM_BATCH) == 0 {
+                return Err("Out of memory for address space");
+            }
+        };
+        unsafe {
+            core::ptr::write_bytes(addr as *mut u8, 0, size);
+        }
+        let _ = self.allocations.push((addr, size));
         
+        Ok(addr)
+    }
+    
+    /// Walk down to the entry for `vaddr` at `level`, allocating intermediate tables
+    fn walk_create(&mut self, vaddr: usize, level: usize) -> Result<*mut PageTableEntry, &'static str> {
+        let vpn = vpn_indices(vaddr);
         let mut table = unsafe { self.root_table() };
         
-        // Walk through levels 2 and 1
-        for level in (1..3).rev() {
-            let entry = unsafe { &mut (*table).entries[vpn[level]] };
+        for l in ((level + 1)..3).rev() {
+            let entry = unsafe { &mut (*table).entries[vpn[l]] };
             
             if !entry.is_valid() {
-                // Allocate new page table (must be page-aligned)
-                let new_table_addr = crate::memory::allocate_kernel_memory(PAGE_SIZE, PAGE_SIZE)
-                    .ok_or("Failed to allocate page table")?;
-                
-                unsafe {
-                    let new_table = new_table_addr as *mut PageTable;
-                    (*new_table).zero();
-                }
-                
+                let new_table_addr = self.allocate_zeroed(PAGE_SIZE)
+                    .map_err(|_| "Failed to allocate page table")?;
                 let ppn = (new_table_addr >> PAGE_SHIFT) as u64;
                 entry.set(ppn, PTE_V);
             } else if entry.is_leaf() {
-                console_println!("[x] Mapping conflict at level {} for vaddr 0x{:x}", level, vaddr);
-                console_println!("   VPN[{}] = 0x{:x}, entry = 0x{:x}", level, vpn[level], entry.0);
-                console_println!("   Entry flags: 0x{:x}, is_leaf: {}", entry.flags(), entry.is_leaf());
+                err_println!(target: "mm", "Mapping conflict at level {} for vaddr 0x{:x}", l, vaddr);
                 return Err("Mapping conflict: intermediate entry is leaf");
             }
             
             table = entry.paddr() as *mut PageTable;
         }
         
-        // Set leaf entry at level 0
-        let leaf_entry = unsafe { &mut (*table).entries[vpn[0]] };
+        Ok(unsafe { &mut (*table).entries[vpn[level]] as *mut PageTableEntry })
+    }
+    
+    /// Install a leaf entry at `level`
+    fn map_leaf(&mut self, vaddr: usize, paddr: usize, level: usize, flags: u64) -> Result<(), &'static str> {
+        let leaf_entry = unsafe { &mut *self.walk_create(vaddr, level)? };
         if leaf_entry.is_valid() {
-            console_println!("[x] Page already mapped at vaddr 0x{:x}", vaddr);
-            console_println!("   VPN[0] = 0x{:x}, entry = 0x{:x}", vpn[0], leaf_entry.0);
+            err_println!(target: "mm", "Page already mapped at vaddr 0x{:x}", vaddr);
             return Err("Page already mapped");
         }
         
+        // A and D are pre-set: implementations may fault instead of updating them
         let ppn = (paddr >> PAGE_SHIFT) as u64;
-        leaf_entry.set(ppn, flags | PTE_V);
+        leaf_entry.set(ppn, flags | PTE_V | PTE_A | PTE_D);
         
         Ok(())
     }
     
-    /// Unmap a virtual page
-    pub fn unmap_page(&mut self, vaddr: usize) -> Result<(), &'static str> {
-        let vpn = [
-            (vaddr >> 12) & 0x1FF,
-            (vaddr >> 21) & 0x1FF,
-            (vaddr >> 30) & 0x1FF,
-        ];
-        
-        let mut table = unsafe { self.root_table() };
-        
-        // Walk to leaf
-        for level in (1..3).rev() {
-            let entry = unsafe { &(*table).entries[vpn[level]] };
-            if !entry.is_valid() {
-                return Err("Page not mapped");
-            }
-            table = entry.paddr() as *mut PageTable;
+    /// Map a virtual page to a physical page
+    pub fn map_page(&mut self, vaddr: usize, paddr: usize, flags: u64) -> Result<(), &'static str> {
+        self.map_leaf(vaddr, paddr, 0, flags)
+    }
+    
+    /// Install `entry`, the entry of a page swapped out, for `vaddr`
+    fn map_swapped(&mut self, vaddr: usize, entry: PageTableEntry) -> Result<(), &'static str> {
+        let target = unsafe { &mut *self.walk_create(vaddr, 0)? };
+        if target.is_valid() || target.is_swapped() {
+            return Err("Page already mapped");
+        }
+        *target = entry;
+        Ok(())
+    }
+    
+    /// Map a 2MB (level 1) or 1GB (level 2) page
+    pub fn map_huge_page(&mut self, vaddr: usize, paddr: usize, level: usize, flags: u64) -> Result<(), &'static str> {
+        if level == 0 || level > 2 {
+            return Err("Invalid huge page level");
         }
         
-        let leaf_entry = unsafe { &mut (*table).entries[vpn[0]] };
-        if !leaf_entry.is_valid() {
-            return Err("Page not mapped");
+        let size = LEVEL_PAGE_SIZE[level];
+        if vaddr % size != 0 || paddr % size != 0 {
+            return Err("Huge page not aligned");
         }
         
-        leaf_entry.clear();
+        self.map_leaf(vaddr, paddr, level, flags)
+    }
+    
+    /// Unmap a virtual page
+    pub fn unmap_page(&mut self, vaddr: usize) -> Result<(), &'static str> {
+        let (leaf_entry, _) = find_leaf_in(self.root_table_addr, vaddr)
+            .ok_or("Page not mapped")?;
         
-        // Flush TLB for this address
         unsafe {
+            (*leaf_entry).clear();
+            
+            // Flush TLB for this address
             asm!("sfence.vma {}, zero", in(reg) vaddr);
         }
         
@@ -225,141 +503,827 @@ impl AddressSpace {
     
     /// Translate virtual address to physical address
     pub fn translate(&self, vaddr: usize) -> Option<usize> {
-        let vpn = [
-            (vaddr >> 12) & 0x1FF,
-            (vaddr >> 21) & 0x1FF,
-            (vaddr >> 30) & 0x1FF,
-        ];
+        let (leaf_entry, level) = find_leaf_in(self.root_table_addr, vaddr)?;
+        let page_offset = vaddr & (LEVEL_PAGE_SIZE[level] - 1);
+        Some(unsafe { (*leaf_entry).paddr() } + page_offset)
+    }
+    
+    /// Map a range of pages
+    pub fn map_range(&mut self, vaddr: usize, paddr: usize, size: usize, flags: u64) -> Result<(), &'static str> {
+        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
         
-        let mut table = unsafe { self.root_table() };
+        for i in 0..pages {
+            let va = vaddr + i * PAGE_SIZE;
+            let pa = paddr + i * PAGE_SIZE;
+            self.map_page(va, pa, flags)?;
+        }
         
-        // Walk page tables
-        for level in (0..3).rev() {
-            let entry = unsafe { &(*table).entries[vpn[level]] };
+        Ok(())
+    }
+    
+    /// Allocate zeroed frames for `[vaddr, vaddr + size)` and map them as user pages.
+    /// Pages already mapped (e.g. shared by two ELF segments) keep their frame
+    /// and gain the extra permissions.
+    pub fn map_user_region(&mut self, vaddr: usize, size: usize, flags: u64) -> Result<(), &'static str> {
+        let start = vaddr & !(PAGE_SIZE - 1);
+        let end = (vaddr + size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        if size == 0 || end <= start || end > (1 << (VA_BITS - 1)) {
+            return Err("Invalid user region");
+        }
+        
+        let frames = self.allocate_zeroed(end - start)?;
+        
+        for page in (start..end).step_by(PAGE_SIZE) {
+            // A page of an earlier segment may have gone to swap meanwhile
+            if self.is_swapped_out(page) && !self.swap_in(page) {
+                return Err("Failed to read page back from swap");
+            }
+            match find_leaf_in(self.root_table_addr, page) {
+                Some((entry, 0)) if unsafe { (*entry).flags() } & PTE_U != 0 => unsafe {
+                    (*entry).0 |= flags;
+                },
+                Some(_) => return Err("User region overlaps a kernel mapping"),
+                None => self.map_page(page, frames + (page - start), flags | PTE_U)?,
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Set the file that lazily mapped segments are read from
+    pub fn set_backing_file(&mut self, path: &str) -> Result<(), &'static str> {
+        self.backing_file = String::try_from(path).map_err(|_| "Backing file path too long")?;
+        Ok(())
+    }
+    
+    /// Reserve `[vaddr, vaddr + memsz)` for a segment without mapping it.
+    /// Its first `file_size` bytes come from the backing file at
+    /// `file_offset`; the rest reads as zeros.
+    pub fn map_lazy_region(&mut self, vaddr: usize, memsz: usize, file_offset: usize, file_size: usize, flags: u64) -> Result<(), &'static str> {
+        if memsz == 0 || file_size > memsz || vaddr + memsz > (1 << (VA_BITS - 1)) {
+            return Err("Invalid user region");
+        }
+        
+        self.lazy_regions.push(LazyRegion {
+            vaddr,
+            memsz,
+            file_offset,
+            file_size,
+            flags,
+        }).map_err(|_| "Too many lazy regions in address space")
+    }
+    
+    /// Page in the page containing `vaddr` from swap or, if it is lazily
+    /// mapped, from the backing file. Returns false if `vaddr` is neither,
+    /// already mapped, or the page could not be read.
+    fn resolve_demand_fault(&mut self, vaddr: usize) -> bool {
+        let page = vaddr & !(PAGE_SIZE - 1);
+        match find_leaf_in(self.root_table_addr, page) {
+            // Touched again after `reclaim` cleared its A bit, on a hart
+            // that faults rather than set it
+            Some((entry, 0)) if unsafe { (*entry).flags() } & (PTE_U | PTE_A) == PTE_U => {
+                unsafe {
+                    (*entry).0 |= PTE_A;
+                    asm!("sfence.vma {}, zero", in(reg) page);
+                }
+                return true;
+            }
+            Some(_) => return false,
+            None => {}
+        }
+        if self.is_swapped_out(page) {
+            return self.swap_in(page);
+        }
+        
+        // A page can hold the end of one segment and the start of the next
+        let mut flags = 0;
+        for region in self.lazy_regions.iter() {
+            if page < region.vaddr + region.memsz && region.vaddr < page + PAGE_SIZE {
+                flags |= region.flags;
+            }
+        }
+        if flags == 0 {
+            return false;
+        }
+        
+        let frame = match self.allocate_zeroed(PAGE_SIZE) {
+            Ok(addr) => addr,
+            Err(_) => return false,
+        };
+        
+        for region in self.lazy_regions.iter() {
+            let start = core::cmp::max(page, region.vaddr);
+            let end = core::cmp::min(page + PAGE_SIZE, region.vaddr + region.file_size);
+            if start >= end {
+                continue;
+            }
             
-            if !entry.is_valid() {
-                return None;
+            let dest = unsafe {
+                core::slice::from_raw_parts_mut((frame + (start - page)) as *mut u8, end - start)
+            };
+            let offset = region.file_offset + (start - region.vaddr);
+            // The fault may interrupt a syscall holding the filesystem lock
+            match crate::filesystem::try_read_file_at(&self.backing_file, offset, dest) {
+                Ok(n) if n == dest.len() => {}
+                _ => {
+                    err_println!(target: "mm", "Failed to page in 0x{:x} from {}", page, self.backing_file);
+                    return false;
+                }
             }
+        }
+        
+        self.map_page(page, frame, flags | PTE_U).is_ok()
+    }
+    
+    /// Make sure the page holding `vaddr` is present, reading it in if it
+    /// was swapped out or belongs to a demand-paged segment
+    pub fn fault_in(&mut self, vaddr: usize) -> Result<(), &'static str> {
+        if self.translate(vaddr).is_some() || self.resolve_demand_fault(vaddr) {
+            Ok(())
+        } else {
+            Err("Address not mapped")
+        }
+    }
+    
+    /// Copy `data` to `vaddr` in this address space. The pages must be
+    /// mapped; those swapped out are read back in.
+    pub fn copy_to_user(&mut self, vaddr: usize, data: &[u8]) -> Result<(), &'static str> {
+        let mut copied = 0;
+        
+        while copied < data.len() {
+            let va = vaddr + copied;
+            self.fault_in(va).map_err(|_| "Destination not mapped")?;
+            let pa = self.translate(va).ok_or("Destination not mapped")?;
+            let chunk = core::cmp::min(PAGE_SIZE - (va & (PAGE_SIZE - 1)), data.len() - copied);
             
-            if entry.is_leaf() {
-                // Found leaf entry
-                let page_offset = vaddr & (PAGE_SIZE - 1);
-                return Some(entry.paddr() + page_offset);
+            // RAM is identity mapped, so the frame can be written through its physical address
+            unsafe {
+                core::ptr::copy_nonoverlapping(data.as_ptr().add(copied), pa as *mut u8, chunk);
+            }
+            copied += chunk;
+        }
+        
+        Ok(())
+    }
+    
+    /// Whether `[start, end)` is free of pages, in memory or swapped out,
+    /// demand-paged segments and mmap regions
+    fn range_is_free(&self, start: usize, end: usize) -> bool {
+        let overlaps = |vaddr: usize, len: usize| vaddr < end && start < vaddr + len;
+        if self.lazy_regions.iter().any(|region| overlaps(region.vaddr, region.memsz))
+            || self.mmap_regions.iter().any(|region| overlaps(region.vaddr, region.len))
+        {
+            return false;
+        }
+        (start..end).step_by(PAGE_SIZE)
+            .all(|page| find_leaf_in(self.root_table_addr, page).is_none() && !self.is_swapped_out(page))
+    }
+    
+    /// Lowest free range of `len` bytes in the mmap area
+    fn find_mmap_space(&self, len: usize) -> Option<usize> {
+        let mut candidate = USER_MMAP_BASE;
+        while candidate + len <= USER_MMAP_END {
+            if self.range_is_free(candidate, candidate + len) {
+                return Some(candidate);
+            }
+            // Skip past a region in the way, or just one page
+            candidate = self.mmap_regions.iter()
+                .filter(|region| region.vaddr < candidate + len && candidate < region.end())
+                .map(|region| region.end())
+                .max()
+                .unwrap_or(candidate + PAGE_SIZE);
+        }
+        None
+    }
+    
+    /// Map `len` bytes of zeroed memory for mmap, at `addr` if given (it
+    /// must be page aligned and free) or else in the mmap area. `fill` can
+    /// put the initial contents into the frames before they are mapped.
+    /// With no permissions in `flags` the range is only reserved.
+    /// Returns the address of the mapping.
+    pub fn map_mmap_region<F>(&mut self, addr: Option<usize>, len: usize, flags: u64, source: Option<&str>, fill: F) -> Result<usize, &'static str>
+    where
+        F: FnOnce(&mut [u8]) -> Result<(), &'static str>,
+    {
+        let len = (len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        if len == 0 {
+            return Err("Invalid user region");
+        }
+        if self.mmap_regions.is_full() {
+            return Err("Too many mmap regions in address space");
+        }
+        
+        let vaddr = match addr {
+            Some(vaddr) => {
+                if vaddr % PAGE_SIZE != 0 || vaddr + len > (1 << (VA_BITS - 1)) {
+                    return Err("Invalid user region");
+                }
+                if !self.range_is_free(vaddr, vaddr + len) {
+                    return Err("Address already in use");
+                }
+                vaddr
+            }
+            None => self.find_mmap_space(len).ok_or("No room left in the mmap area")?,
+        };
+        
+        let frames = if flags & (PTE_R | PTE_W | PTE_X) != 0 {
+            let frames = self.allocate_zeroed(len)?;
+            let contents = unsafe { core::slice::from_raw_parts_mut(frames as *mut u8, len) };
+            if let Err(e) = fill(contents).and_then(|_| self.map_range(vaddr, frames, len, flags | PTE_U)) {
+                // Take back what was mapped and the frames
+                for page in (vaddr..vaddr + len).step_by(PAGE_SIZE) {
+                    if let Some((entry, 0)) = find_leaf_in(self.root_table_addr, page) {
+                        unsafe { (*entry).clear() };
+                    }
+                }
+                self.free_allocation(frames);
+                return Err(e);
+            }
+            Some(frames)
+        } else {
+            None
+        };
+        
+        let source = source.and_then(|path| String::try_from(path).ok());
+        let _ = self.mmap_regions.push(MmapRegion { vaddr, len, frames, source });
+        unsafe {
+            asm!("sfence.vma zero, zero");
+        }
+        Ok(vaddr)
+    }
+    
+    /// Unmap the mmap pages in `[vaddr, vaddr + len)`. Regions unmapped
+    /// entirely give back their frames; a region cut in the middle keeps
+    /// its range reserved until the rest of it goes.
+    pub fn unmap_mmap_range(&mut self, vaddr: usize, len: usize) {
+        let end = (vaddr + len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        
+        let mut i = 0;
+        while i < self.mmap_regions.len() {
+            let region = self.mmap_regions[i].clone();
+            let start = core::cmp::max(vaddr, region.vaddr);
+            let stop = core::cmp::min(end, region.end());
+            if start >= stop {
+                i += 1;
+                continue;
             }
             
-            if level == 0 {
-                return None; // Should have found leaf by now
+            for page in (start..stop).step_by(PAGE_SIZE) {
+                self.clear_user_page(page);
             }
             
-            table = entry.paddr() as *mut PageTable;
+            if start == region.vaddr && stop == region.end() {
+                if let Some(frames) = region.frames {
+                    self.free_allocation(frames);
+                }
+                self.mmap_regions.remove(i);
+                continue;
+            }
+            if start == region.vaddr {
+                self.mmap_regions[i].vaddr = stop;
+                self.mmap_regions[i].len = region.end() - stop;
+            } else if stop == region.end() {
+                self.mmap_regions[i].len = start - region.vaddr;
+            }
+            i += 1;
         }
         
-        None
+        unsafe {
+            asm!("sfence.vma zero, zero");
+        }
     }
     
-    /// Map a range of pages
-    pub fn map_range(&mut self, vaddr: usize, paddr: usize, size: usize, flags: u64) -> Result<(), &'static str> {
-        let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    /// Move the program break from `old` to `new`, mapping zeroed pages as
+    /// the heap grows and unmapping them as it shrinks. The heap may not
+    /// run into other mappings or the mmap area. Frames of pages unmapped
+    /// here are only given back with the address space.
+    pub fn move_break(&mut self, old: usize, new: usize) -> Result<(), &'static str> {
+        let old_end = (old + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let new_end = (new + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
         
-        for i in 0..pages {
-            let va = vaddr + i * PAGE_SIZE;
-            let pa = paddr + i * PAGE_SIZE;
-            self.map_page(va, pa, flags)?;
+        if new_end > old_end {
+            if old_end < USER_MMAP_END && USER_MMAP_BASE < new_end {
+                return Err("Heap would run into the mmap area");
+            }
+            if !self.range_is_free(old_end, new_end) {
+                return Err("Heap would run into another mapping");
+            }
+            self.map_user_region(old_end, new_end - old_end, PTE_R | PTE_W)?;
+        } else {
+            for page in (new_end..old_end).step_by(PAGE_SIZE) {
+                self.clear_user_page(page);
+            }
         }
         
+        unsafe {
+            asm!("sfence.vma zero, zero");
+        }
         Ok(())
     }
     
-    /// Activate this address space - RISC-V 64-bit implementation based on working examples
-    pub fn activate(&self) {
+    /// Free an allocation made by `allocate_zeroed` before the address
+    /// space goes
+    fn free_allocation(&mut self, addr: usize) {
+        if let Some(index) = self.allocations.iter().position(|&(a, _)| a == addr) {
+            let (addr, size) = self.allocations.swap_remove(index);
+            crate::memory::deallocate_kernel_memory(addr, size);
+        }
+    }
+    
+    /// Unmap the 4KB page at `page`, giving up its claim on a frame shared
+    /// copy-on-write or on its swap slot
+    fn clear_user_page(&mut self, page: usize) {
+        let Some(entry) = find_entry_in(self.root_table_addr, page) else { return };
+        let entry = unsafe { &mut *entry };
+        if entry.is_swapped() {
+            crate::memory::swap::release(entry.swap_slot());
+        } else if !entry.is_valid() {
+            return;
+        } else if entry.flags() & PTE_COW != 0 {
+            release_shared_frame(entry.paddr());
+        }
+        entry.clear();
+    }
+    
+    /// Whether the page at `page` is swapped out
+    fn is_swapped_out(&self, page: usize) -> bool {
+        find_entry_in(self.root_table_addr, page).is_some_and(|entry| unsafe { (*entry).is_swapped() })
+    }
+    
+    /// Whether the frame of this present user page may go to swap: a
+    /// writable page, not shared, whose frame this address space allocated
+    /// and can give back on its own
+    fn can_evict(&self, entry: &PageTableEntry) -> bool {
+        entry.is_valid()
+            && entry.flags() & (PTE_U | PTE_W | PTE_COW) == PTE_U | PTE_W
+            && self.can_release(entry.paddr())
+    }
+    
+    /// Whether `release_frame` can give back `frame`. Splitting an
+    /// allocation takes a record, and one is always left for the
+    /// allocation being made.
+    fn can_release(&self, frame: usize) -> bool {
+        self.allocations.iter().any(|&(addr, size)| {
+            let inside = addr <= frame && frame + PAGE_SIZE <= addr + size;
+            let splits = frame != addr && frame + PAGE_SIZE != addr + size;
+            inside && (!splits || self.allocations.len() + 2 < MAX_ADDRESS_SPACE_ALLOCATIONS)
+        })
+    }
+    
+    /// Free the 4KB `frame`, cutting it out of the allocation holding it.
+    /// An mmap region whose first frame goes now points at the next; if
+    /// its allocation is split, the part past the hole is only given
+    /// back with the address space.
+    fn release_frame(&mut self, frame: usize) {
+        let Some(index) = self.allocations.iter().position(|&(addr, size)| addr <= frame && frame < addr + size) else { return };
+        let (addr, size) = self.allocations[index];
+        let end = addr + size;
+        
+        let head = if size == PAGE_SIZE {
+            self.allocations.swap_remove(index);
+            None
+        } else if frame == addr {
+            self.allocations[index] = (addr + PAGE_SIZE, size - PAGE_SIZE);
+            Some(addr + PAGE_SIZE)
+        } else {
+            self.allocations[index].1 = frame - addr;
+            if frame + PAGE_SIZE < end && self.allocations.push((frame + PAGE_SIZE, end - frame - PAGE_SIZE)).is_err() {
+                // Not reached: `can_release` made sure of room
+                self.allocations[index].1 = size;
+                return;
+            }
+            Some(addr)
+        };
+        for region in self.mmap_regions.iter_mut() {
+            if region.frames == Some(addr) {
+                region.frames = head;
+            }
+        }
+        crate::memory::deallocate_kernel_memory(frame, PAGE_SIZE);
+    }
+    
+    /// Swap out up to `want` pages that were not used lately and free
+    /// their frames. Returns how many went.
+    ///
+    /// Pages get a second chance: those accessed since the last look have
+    /// their A bit cleared and are passed over, and only when no page is
+    /// left cold is a second pass made over the ones just cleared.
+    fn reclaim(&mut self, want: usize) -> usize {
+        if !crate::memory::swap::is_on() {
+            return 0;
+        }
+        
+        let want = want.min(RECLAIM_BATCH);
+        let mut victims = Vec::<(usize, usize), RECLAIM_BATCH>::new();
+        for _ in 0..2 {
+            self.for_each_user_page(|vaddr, entry| {
+                if victims.len() >= want || !self.can_evict(entry) {
+                    return;
+                }
+                if entry.flags() & PTE_A != 0 {
+                    entry.0 &= !PTE_A;
+                } else {
+                    let _ = victims.push((vaddr, entry.paddr()));
+                }
+            });
+            unsafe {
+                asm!("sfence.vma zero, zero");
+            }
+            if !victims.is_empty() {
+                break;
+            }
+        }
+        
+        let mut freed = 0;
+        for &(vaddr, frame) in victims.iter() {
+            if !self.can_release(frame) {
+                break;
+            }
+            let Some(slot) = crate::memory::swap::swap_out(frame) else { break };
+            if let Some(entry) = find_entry_in(self.root_table_addr, vaddr) {
+                unsafe {
+                    *entry = PageTableEntry::new_swapped(slot, (*entry).flags());
+                    asm!("sfence.vma {}, zero", in(reg) vaddr);
+                }
+            }
+            self.release_frame(frame);
+            freed += 1;
+        }
+        crate::memory::swap::note_reclaim(freed);
+        freed
+    }
+    
+    /// Move up to `want` of the pages `reclaim` could swap out into freed
+    /// holes lower in memory, those in the highest frames first, so the
+    /// frames they leave can be compacted into the free end of the heap.
+    /// Returns how many moved.
+    fn migrate(&mut self, want: usize) -> usize {
+        let mut pages = Vec::<(usize, usize), MIGRATE_BATCH>::new();
+        self.for_each_user_page(|vaddr, entry| {
+            if !self.can_evict(entry) {
+                return;
+            }
+            let page = (vaddr, entry.paddr());
+            if pages.push(page).is_err() {
+                let lowest = pages.iter().enumerate().min_by_key(|(_, &(_, frame))| frame).map(|(i, _)| i);
+                if let Some(i) = lowest.filter(|&i| pages[i].1 < page.1) {
+                    pages[i] = page;
+                }
+            }
+        });
+        pages.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+        
+        let mut moved = 0;
+        for &(vaddr, frame) in pages.iter().take(want) {
+            // Room for the new frame and for splitting the old one's allocation
+            if self.allocations.len() + 3 >= MAX_ADDRESS_SPACE_ALLOCATIONS {
+                break;
+            }
+            let Some(new_frame) = crate::memory::allocate_kernel_memory_below(PAGE_SIZE, PAGE_SIZE, frame) else { break };
+            let Some(entry) = find_entry_in(self.root_table_addr, vaddr) else {
+                crate::memory::deallocate_kernel_memory(new_frame, PAGE_SIZE);
+                continue;
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(frame as *const u8, new_frame as *mut u8, PAGE_SIZE);
+                (*entry).set((new_frame >> PAGE_SHIFT) as u64, (*entry).flags());
+                asm!("sfence.vma {}, zero", in(reg) vaddr);
+            }
+            let _ = self.allocations.push((new_frame, PAGE_SIZE));
+            self.release_frame(frame);
+            moved += 1;
+        }
+        moved
+    }
+    
+    /// Read the swapped out page at `page` back into a new frame. Returns
+    /// false if it is not swapped out or could not be read.
+    fn swap_in(&mut self, page: usize) -> bool {
+        let entry = match find_entry_in(self.root_table_addr, page) {
+            Some(entry) if unsafe { (*entry).is_swapped() } => entry,
+            _ => return false,
+        };
+        let (slot, flags) = unsafe { ((*entry).swap_slot(), (*entry).flags() & !PTE_SWAPPED) };
+        
+        // Page tables are never freed, so `entry` stays put even if this
+        // swaps out other pages
+        let frame = match self.allocate_zeroed(PAGE_SIZE) {
+            Ok(addr) => addr,
+            Err(_) => return false,
+        };
+        if let Err(e) = crate::memory::swap::swap_in(slot, frame) {
+            err_println!(target: "mm", "Failed to read 0x{:x} back from swap: {}", page, e);
+            self.free_allocation(frame);
+            return false;
+        }
         unsafe {
-            // RISC-V 64-bit specific validation
-            if self.root_table_addr % PAGE_SIZE != 0 {
-                console_println!("[x] Page table not 4KB aligned: 0x{:x}", self.root_table_addr);
+            (*entry).set((frame >> PAGE_SHIFT) as u64, flags | PTE_V | PTE_A | PTE_D);
+            asm!("sfence.vma {}, zero", in(reg) page);
+        }
+        true
+    }
+    
+    /// Read every swapped out page back in
+    fn swap_in_all(&mut self) -> Result<(), &'static str> {
+        loop {
+            let mut pages = Vec::<usize, 64>::new();
+            self.for_each_user_page(|vaddr, entry| {
+                if entry.is_swapped() {
+                    let _ = pages.push(vaddr);
+                }
+            });
+            if pages.is_empty() {
+                return Ok(());
+            }
+            for &page in pages.iter() {
+                if !self.swap_in(page) {
+                    return Err("Not enough memory to read swapped pages back");
+                }
+            }
+        }
+    }
+    
+    /// The ranges of this address space in address order: demand-paged
+    /// segments, mmap regions and the heap from `[start, brk)` as
+    /// recorded, and every other run of pages with the same permissions,
+    /// such as segments loaded up front and the stack. Ranges past
+    /// `MAX_VMAS` are left out.
+    pub fn vmas(&self, (heap_start, brk): (usize, usize)) -> Vec<Vma, MAX_VMAS> {
+        let page_up = |addr: usize| (addr + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let mut vmas = Vec::<Vma, MAX_VMAS>::new();
+        
+        for region in self.lazy_regions.iter() {
+            let start = region.vaddr & !(PAGE_SIZE - 1);
+            let end = page_up(region.vaddr + region.memsz);
+            let perms = region.flags & (PTE_R | PTE_W | PTE_X);
+            let _ = vmas.push(Vma::new(start, end, perms, Backing::File, &self.backing_file));
+        }
+        for region in self.mmap_regions.iter() {
+            // PROT_NONE regions have no pages to take permissions from
+            let perms = find_leaf_in(self.root_table_addr, region.vaddr)
+                .map_or(0, |(entry, _)| user_perms(unsafe { &*entry }));
+            let vma = match &region.source {
+                Some(path) => Vma::new(region.vaddr, region.end(), perms, Backing::of_path(path), path),
+                None => Vma::new(region.vaddr, region.end(), perms, Backing::Anonymous, ""),
+            };
+            let _ = vmas.push(vma);
+        }
+        let heap = (page_up(heap_start), page_up(brk));
+        if heap_start != 0 && heap.0 < heap.1 {
+            let _ = vmas.push(Vma::new(heap.0, heap.1, PTE_R | PTE_W, Backing::Anonymous, "[heap]"));
+        }
+        let recorded = vmas.len();
+        
+        // Runs of pages outside the recorded ranges
+        let mut run: Option<(usize, usize, u64)> = None;
+        let close = |vmas: &mut Vec<Vma, MAX_VMAS>, (start, end, perms): (usize, usize, u64)| {
+            let vma = if end == USER_STACK {
+                Vma::new(start, end, perms, Backing::Anonymous, "[stack]")
+            } else if start == USER_TRAMPOLINE {
+                Vma::new(start, end, perms, Backing::Anonymous, "[trampoline]")
+            } else if !self.backing_file.is_empty() {
+                // A segment copied in when the program was loaded
+                Vma::new(start, end, perms, Backing::File, &self.backing_file)
+            } else {
+                Vma::new(start, end, perms, Backing::Anonymous, "")
+            };
+            let _ = vmas.push(vma);
+        };
+        self.for_each_user_page(|vaddr, entry| {
+            if vmas[..recorded].iter().any(|vma| vma.start <= vaddr && vaddr < vma.end) {
                 return;
             }
+            let perms = user_perms(entry);
+            match run {
+                Some((start, end, run_perms)) if end == vaddr && run_perms == perms => run = Some((start, end + PAGE_SIZE, perms)),
+                Some(previous) => {
+                    close(&mut vmas, previous);
+                    run = Some((vaddr, vaddr + PAGE_SIZE, perms));
+                }
+                None => run = Some((vaddr, vaddr + PAGE_SIZE, perms)),
+            }
+        });
+        if let Some(last) = run {
+            close(&mut vmas, last);
+        }
+        
+        for vma in vmas.iter_mut() {
+            vma.resident = (vma.start..vma.end).step_by(PAGE_SIZE)
+                .filter(|&page| find_leaf_in(self.root_table_addr, page).is_some())
+                .count();
+        }
+        vmas.sort_unstable_by_key(|vma| vma.start);
+        vmas
+    }
+    
+    /// Share the kernel's RAM and device mappings (without the U bit) so that
+    /// traps and syscalls keep working while this address space is active
+    fn map_kernel(&mut self, kernel_space: &AddressSpace) -> Result<(), &'static str> {
+        for &slot in KERNEL_RAM_SLOTS.iter() {
+            unsafe {
+                (*self.root_table()).entries[slot] = (*kernel_space.root_table()).entries[slot];
+            }
+        }
+        
+        for &(start, size) in KERNEL_DEVICE_WINDOWS.iter() {
+            for offset in (0..size).step_by(LEVEL_PAGE_SIZE[1]) {
+                self.map_huge_page(start + offset, start + offset, 1, PTE_R | PTE_W | PTE_G)?;
+            }
+        }
+        
+        // Devices the device tree put outside those windows
+        let devices = core::iter::once(elinos_common::platform::uart())
+            .chain(elinos_common::platform::virtio_slots().iter().copied())
+            .chain(elinos_common::platform::usb_hosts().iter().map(|host| host.device))
+            .chain(elinos_common::platform::beeper().map(|beeper| beeper.gpio));
+        for device in devices {
+            let page = device.base & !(LEVEL_PAGE_SIZE[1] - 1);
+            if self.translate(page).is_none() {
+                self.map_huge_page(page, page, 1, PTE_R | PTE_W | PTE_G)?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Call `f` with every 4KB user leaf entry below the kernel RAM slots,
+    /// and with the entries of pages swapped out
+    fn for_each_user_page<F: FnMut(usize, &mut PageTableEntry)>(&self, mut f: F) {
+        let root = unsafe { &mut *self.root_table() };
+        
+        for i in 0..PTE_PER_PAGE / 2 {
+            let l2 = root.entries[i];
+            if KERNEL_RAM_SLOTS.contains(&i) || !l2.is_valid() || l2.is_leaf() {
+                continue;
+            }
             
-            // Check SATP format for RISC-V 64-bit Sv39
-            let mode = (self.satp_value >> 60) & 0xF;
-            let ppn = self.satp_value & 0xFFFFFFFFFFF; // PPN is bits 43-0
-            
-            if mode != 8 {
-                console_println!("[x] Invalid SATP mode for Sv39: {}", mode);
+            let l1_table = unsafe { &mut *(l2.paddr() as *mut PageTable) };
+            for j in 0..PTE_PER_PAGE {
+                let l1 = l1_table.entries[j];
+                if !l1.is_valid() || l1.is_leaf() {
+                    continue;
+                }
+                
+                let l0_table = unsafe { &mut *(l1.paddr() as *mut PageTable) };
+                for k in 0..PTE_PER_PAGE {
+                    let entry = &mut l0_table.entries[k];
+                    if (entry.is_valid() && entry.flags() & PTE_U != 0) || entry.is_swapped() {
+                        f((i << 30) | (j << 21) | (k << 12), entry);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Build a child that shares every user page with this address space.
+    /// Writable pages become read-only copy-on-write pages in both. Fails
+    /// if the shared frame table cannot count another copy-on-write page,
+    /// since a frame with no count would be written in place.
+    fn fork_cow(&mut self, kernel_space: &AddressSpace) -> Result<AddressSpace, &'static str> {
+        let mut child = AddressSpace::new()
+            .ok_or("Failed to create child address space")?;
+        child.map_kernel(kernel_space)?;
+        
+        let mut result = Ok(());
+        self.for_each_user_page(|vaddr, entry| {
+            if result.is_err() {
                 return;
             }
             
-            // Verify the PPN points to our page table
-            let expected_ppn = (self.root_table_addr >> 12) as u64;
-            
-            if ppn != expected_ppn {
-                console_println!("[x] SATP PPN mismatch: expected 0x{:x}, got 0x{:x}", expected_ppn, ppn);
+            if entry.is_swapped() {
+                // Both sides read the page back from the same slot
+                result = child.map_swapped(vaddr, *entry);
+                if result.is_ok() {
+                    crate::memory::swap::share(entry.swap_slot());
+                }
                 return;
             }
             
-            // Get current PC to verify we're in identity-mapped region
-            let current_pc: usize;
-            asm!("auipc {}, 0", out(reg) current_pc);
-            
-            // CRITICAL: Verify that our current execution address is identity-mapped
-            // in our page tables. If not, the system will crash when MMU activates.
-            // For elinOS, kernel should be at 0x80200000 and identity-mapped
-            if current_pc < 0x80200000 || current_pc > 0x80400000 {
-                console_println!("[!]  WARNING: Current PC 0x{:x} may not be identity-mapped!", current_pc);
-                console_println!("[!]  Expected PC in range 0x80200000-0x80400000");
-                console_println!("[!]  This could cause MMU activation to hang!");
+            if entry.flags() & PTE_W != 0 {
+                entry.0 = (entry.0 & !PTE_W) | PTE_COW;
             }
             
-            // Disable interrupts during critical section
-            asm!("csrci sstatus, 2"); // Clear SIE bit
-            
-            let satp_usize = self.satp_value as usize;
-            
-            // Complete all pending memory operations
-            asm!(
-                "fence rw, rw",
-                "fence.i",
-                options(nomem, nostack)
-            );
-            
-            // Try to write SATP with proper error handling
-            let activation_result = self.try_mmu_activation(satp_usize);
-            
-            if activation_result {
-                console_println!("[o] Hardware MMU activation successful!");
-            } else {
-                // Enable software-based virtual memory translation
-                self.enable_software_mmu();
-                
-                console_println!("[o] Software Virtual Memory Manager active!");
+            // Read-only pages are never written, so only copy-on-write
+            // pages need their sharers counted
+            let cow = entry.flags() & PTE_COW != 0;
+            if cow {
+                result = share_frame(entry.paddr());
+                if result.is_err() {
+                    return;
+                }
             }
             
-            // Re-enable interrupts
-            asm!("csrsi sstatus, 2"); // Set SIE bit
+            let flags = entry.flags() & (PTE_R | PTE_W | PTE_X | PTE_U | PTE_COW);
+            result = child.map_page(vaddr, entry.paddr(), flags);
+            if result.is_err() && cow {
+                release_shared_frame(entry.paddr());
+            }
+        });
+        
+        // Our own writable entries just lost their W bit
+        unsafe {
+            asm!("sfence.vma zero, zero");
         }
+        
+        // Pages not faulted in yet are paged in separately by each side
+        child.lazy_regions = self.lazy_regions.clone();
+        child.backing_file = self.backing_file.clone();
+        
+        // The mmap frames are shared now; neither side may free them early
+        for region in self.mmap_regions.iter_mut() {
+            region.frames = None;
+        }
+        child.mmap_regions = self.mmap_regions.clone();
+        
+        result.map(|_| child)
     }
     
-    /// Detect if hardware MMU is available and working
-    unsafe fn try_mmu_activation(&self, satp_value: usize) -> bool {
-        // For now, we'll skip hardware MMU activation entirely
-        // This avoids the QEMU hang issue and lets us focus on software MMU
-        false
+    /// Resolve a write to a copy-on-write page. Returns false if `vaddr`
+    /// is not a copy-on-write page of this address space.
+    fn resolve_cow(&mut self, vaddr: usize) -> bool {
+        let entry = match find_leaf_in(self.root_table_addr, vaddr) {
+            Some((entry, 0)) => unsafe { &mut *entry },
+            _ => return false,
+        };
+        if entry.flags() & PTE_COW == 0 {
+            return false;
+        }
+        
+        let frame = entry.paddr();
+        let flags = (entry.flags() & !PTE_COW) | PTE_W;
+        
+        if shared_frame_count(frame) > 1 {
+            // Someone else still uses the frame: take a private copy
+            let copy = match self.allocate_zeroed(PAGE_SIZE) {
+                Ok(addr) => addr,
+                Err(_) => return false,
+            };
+            unsafe {
+                core::ptr::copy_nonoverlapping(frame as *const u8, copy as *mut u8, PAGE_SIZE);
+            }
+            entry.set((copy >> PAGE_SHIFT) as u64, flags);
+        } else {
+            // Last user of the frame: just make it writable again
+            entry.set((frame >> PAGE_SHIFT) as u64, flags);
+        }
+        release_shared_frame(frame);
+        
+        unsafe {
+            asm!("sfence.vma {}, zero", in(reg) vaddr);
+        }
+        true
+    }
+    
+    /// Load this address space into satp.
+    /// Returns false if the hart does not support Sv39.
+    pub fn activate(&self) -> bool {
+        if self.root_table_addr % PAGE_SIZE != 0 {
+            err_println!(target: "mm", "Page table not 4KB aligned: 0x{:x}", self.root_table_addr);
+            return false;
+        }
+        
+        unsafe { self.try_mmu_activation(self.satp_value as usize) }
     }
     
-    /// Enable software-based virtual memory management
-    /// This provides full MMU functionality without hardware MMU activation
-    unsafe fn enable_software_mmu(&self) {
-        // The page tables are fully constructed and ready for software translation
-        // Re-enable interrupts
-        asm!("csrsi sstatus, 2");
+    /// Write satp and check that the hart accepted it. satp is WARL:
+    /// writing an unsupported mode leaves the register unchanged.
+    unsafe fn try_mmu_activation(&self, satp_value: usize) -> bool {
+        let previous = read_satp();
+        write_satp(satp_value);
+        
+        if read_satp() != satp_value {
+            write_satp(previous);
+            return false;
+        }
+        
+        true
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        // Give up our claim on frames still shared with another address
+        // space, and on swap slots
+        self.for_each_user_page(|_, entry| {
+            if entry.is_swapped() {
+                crate::memory::swap::release(entry.swap_slot());
+            } else if entry.flags() & PTE_COW != 0 {
+                release_shared_frame(entry.paddr());
+            }
+        });
+        
+        for &(addr, size) in self.allocations.iter() {
+            crate::memory::deallocate_kernel_memory(addr, size);
+        }
     }
 }
 
 /// Global MMU manager
 pub struct MmuManager {
     kernel_space: Option<AddressSpace>,
-    current_user_space: Option<AddressSpace>,
+    user_spaces: Vec<AddressSpace, MAX_USER_SPACES>,
     mmu_enabled: bool,
-    software_mmu: bool,  // Track if we're using software MMU
+    hardware_paging: bool,  // Sv39 active; false means software translation
 }
 
 // SAFETY: MmuManager is protected by a mutex and only contains AddressSpace
@@ -371,9 +1335,9 @@ impl MmuManager {
     pub const fn new() -> Self {
         MmuManager {
             kernel_space: None,
-            current_user_space: None,
+            user_spaces: Vec::new(),
             mmu_enabled: false,
-            software_mmu: false,
+            hardware_paging: false,
         }
     }
     
@@ -383,85 +1347,27 @@ impl MmuManager {
         let mut kernel_space = AddressSpace::new()
             .ok_or("Failed to create kernel address space")?;
         
-        // Identity map kernel memory using dynamic layout
-        let layout = crate::memory::layout::get_memory_layout();
-        
-        // Map kernel image with extra safety margin for QEMU RISC-V
-        let kernel_start = layout.kernel_start;
-        let kernel_size = layout.kernel_size;
-        
-        // For QEMU RISC-V, we need to ensure we map enough to cover:
-        // 1. The entire kernel image
-        // 2. Current execution context (stack, PC)
-        // 3. Any dynamic allocations during MMU activation
-        
-        // Round up to page boundary and add safety margin
-        let kernel_end_rounded = (layout.kernel_end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
-        let safety_margin = 64 * 1024; // 64KB extra for safety
-        let safe_kernel_size = kernel_end_rounded - kernel_start + safety_margin;
-        
-        match kernel_space.map_range(
-            kernel_start,
-            kernel_start, 
-            safe_kernel_size,
-            PTE_R | PTE_W | PTE_X | PTE_G
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Kernel image mapping failed: {}", e);
-                return Err(e);
-            }
-        }
-        
-        // Map kernel stack separately
-        let stack_start = layout.stack_start;
-        let stack_size = layout.stack_size;
-        
-        match kernel_space.map_range(
-            stack_start,
-            stack_start, 
-            stack_size,
-            PTE_R | PTE_W | PTE_G
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Kernel stack mapping failed: {}", e);
-                return Err(e);
-            }
-        }
-        
-        // Map heap area (where page tables are allocated) using dynamic layout
-        let heap_start = 0x80400000; // TODO: This is hardcoded in linker script - should be made dynamic
-        let (_, heap_total, _) = crate::memory::get_heap_usage();
-        let heap_size = heap_total; // Get actual heap size from memory manager
-        match kernel_space.map_range(
-            heap_start,
-            heap_start,
-            heap_size,
-            PTE_R | PTE_W | PTE_G
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Heap area mapping failed: {}", e);
-                return Err(e);
+        // Identity map the low 2GB of device space with gigapages
+        for base in [0x0000_0000usize, 0x4000_0000] {
+            match kernel_space.map_huge_page(base, base, 2, PTE_R | PTE_W | PTE_G) {
+                Ok(()) => {},
+                Err(e) => {
+                    err_println!(target: "mm", "Device mapping failed: {}", e);
+                    return Err(e);
+                }
             }
         }
         
-        // Map UART and VirtIO devices (identity mapping at their physical addresses)
-        let device_start = 0x10000000;
-        let device_size = 64 * 1024; // 64KB to cover UART + VirtIO MMIO devices
-        
-        match kernel_space.map_range(
-            device_start,
-            device_start,
-            device_size,
-            PTE_R | PTE_W | PTE_G
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Device mapping failed: {}", e);
-                console_println!("[!]  Continuing without device mapping for now...");
-                // Don't return error - continue without device mapping
+        // Identity map all of RAM: the unified memory manager hands out memory
+        // from anywhere in it, not just the kernel image and heap
+        for &slot in KERNEL_RAM_SLOTS.iter() {
+            let base = slot * LEVEL_PAGE_SIZE[2];
+            match kernel_space.map_huge_page(base, base, 2, PTE_R | PTE_W | PTE_X | PTE_G) {
+                Ok(()) => {},
+                Err(e) => {
+                    err_println!(target: "mm", "RAM mapping failed: {}", e);
+                    return Err(e);
+                }
             }
         }
         
@@ -480,95 +1386,70 @@ impl MmuManager {
             .ok_or("Kernel space not initialized")?;
         
         // Activate kernel address space
-        kernel_space.activate();
+        self.hardware_paging = kernel_space.activate();
         
-        // Test that we can still access memory after Virtual Memory is enabled
-        let test_addr: usize = 0x80200000; // Kernel start address
-        unsafe {
-            let test_value = core::ptr::read_volatile(test_addr as *const u32);
+        if self.hardware_paging {
+            // Syscalls read and write user buffers directly
+            unsafe {
+                asm!("csrs sstatus, {}", in(reg) SSTATUS_SUM);
+            }
+        } else {
+            warn_println!(target: "mm", "Sv39 not supported by this hart, using software MMU");
         }
         
-        // We're using software MMU which provides full virtual memory functionality
-        self.software_mmu = true;
         self.mmu_enabled = true;
         
         Ok(())
     }
     
-    /// Create user address space for ELF execution
-    pub fn create_user_space(&mut self) -> Result<&mut AddressSpace, &'static str> {
+    /// Create a user address space that shares the kernel mappings
+    pub fn create_user_space(&self) -> Result<AddressSpace, &'static str> {
+        let kernel_space = self.kernel_space.as_ref()
+            .ok_or("Kernel space not initialized")?;
+        
         let mut user_space = AddressSpace::new()
             .ok_or("Failed to create user address space")?;
+        user_space.map_kernel(kernel_space)?;
         
-        // Map essential devices in user space (for console output, etc.)
-        let device_start = 0x10000000;
-        let device_size = 64 * 1024; // 64KB to cover UART + VirtIO MMIO devices
-        
-        match user_space.map_range(
-            device_start,
-            device_start,
-            device_size,
-            PTE_R | PTE_W | PTE_U // User accessible read/write
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] User space device mapping failed: {}", e);
-                console_println!("[!]  Continuing without device mapping - console output may not work in user space");
-            }
-        }
-        
-        // Map kernel code into user space temporarily to avoid page faults during switch
-        let layout = crate::memory::layout::get_memory_layout();
-        
-        // Map kernel image
-        let kernel_start = layout.kernel_start;
-        let kernel_size = layout.kernel_size;
-        
-        match user_space.map_range(
-            kernel_start,
-            kernel_start,
-            kernel_size,
-            PTE_R | PTE_W | PTE_X // Kernel code needs execute permission
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Failed to map kernel image into user space: {}", e);
-                return Err("Cannot safely switch to user space without kernel mapping");
-            }
+        Ok(user_space)
+    }
+    
+    /// Take ownership of a user address space until `release_user_space`.
+    /// Returns the satp value that selects it.
+    pub fn register_user_space(&mut self, space: AddressSpace) -> Result<u64, &'static str> {
+        let satp = space.satp_value;
+        self.user_spaces.push(space).map_err(|_| "Too many user address spaces")?;
+        Ok(satp)
+    }
+    
+    /// Drop the user address space selected by `satp`. It must not be active.
+    pub fn release_user_space(&mut self, satp: u64) {
+        if let Some(index) = self.user_spaces.iter().position(|s| s.satp_value == satp) {
+            self.user_spaces.swap_remove(index);
         }
-        
-        // Map kernel stack
-        let stack_start = layout.stack_start;
-        let stack_size = layout.stack_size;
-        
-        match user_space.map_range(
-            stack_start,
-            stack_start,
-            stack_size,
-            PTE_R | PTE_W // Stack doesn't need execute permission
-        ) {
-            Ok(()) => {},
-            Err(e) => {
-                console_println!("[x] Failed to map kernel stack into user space: {}", e);
-                return Err("Cannot safely switch to user space without stack mapping");
-            }
+    }
+    
+    fn user_space_mut(&mut self, satp: u64) -> Option<&mut AddressSpace> {
+        self.user_spaces.iter_mut().find(|s| s.satp_value == satp)
+    }
+    
+    /// Read every page swapped out by a user address space back in
+    pub fn swap_in_all(&mut self) -> Result<(), &'static str> {
+        for space in self.user_spaces.iter_mut() {
+            space.swap_in_all()?;
         }
-        
-        // Note: We don't map kernel memory into user space to avoid complexity
-        // Instead, we'll switch back to kernel space for any kernel function calls
-        
-        self.current_user_space = Some(user_space);
-        
-        Ok(self.current_user_space.as_mut().unwrap())
+        Ok(())
     }
     
-    /// Switch to user address space
-    pub fn switch_to_user(&mut self) -> Result<(), &'static str> {
-        let user_space = self.current_user_space.as_ref()
-            .ok_or("No user space available")?;
+    /// Fork the user address space selected by `satp` copy-on-write
+    pub fn fork_user_space(&mut self, satp: u64) -> Result<u64, &'static str> {
+        let kernel_space = self.kernel_space.as_ref()
+            .ok_or("Kernel space not initialized")?;
+        let parent = self.user_spaces.iter_mut().find(|s| s.satp_value == satp)
+            .ok_or("No such user address space")?;
         
-        user_space.activate();
-        Ok(())
+        let child = parent.fork_cow(kernel_space)?;
+        self.register_user_space(child)
     }
     
     /// Switch back to kernel address space
@@ -576,7 +1457,9 @@ impl MmuManager {
         let kernel_space = self.kernel_space.as_ref()
             .ok_or("Kernel space not available")?;
         
-        kernel_space.activate();
+        if self.hardware_paging && !kernel_space.activate() {
+            return Err("Failed to activate kernel space");
+        }
         Ok(())
     }
     
@@ -584,18 +1467,71 @@ impl MmuManager {
         self.mmu_enabled
     }
     
-    pub fn get_current_user_space(&mut self) -> Option<&mut AddressSpace> {
-        self.current_user_space.as_mut()
+    pub fn is_hardware_paging(&self) -> bool {
+        self.hardware_paging
     }
 }
 
 /// Global MMU manager instance
 pub static MMU_MANAGER: Mutex<MmuManager> = Mutex::new(MmuManager::new());
 
+/// Reference counts of frames mapped copy-on-write by more than one address space
+static SHARED_FRAMES: Mutex<FnvIndexMap<usize, u32, MAX_SHARED_FRAMES>> = Mutex::new(FnvIndexMap::new());
+
+/// Record one more address space mapping `frame` copy-on-write. Fails if
+/// the table is full: the frame would look unshared, and the first side
+/// to write would write it in place, under the other.
+fn share_frame(frame: usize) -> Result<(), &'static str> {
+    let mut frames = SHARED_FRAMES.lock();
+    let count = frames.get(&frame).copied().unwrap_or(1) + 1;
+    frames.insert(frame, count).map(|_| ()).map_err(|_| {
+        warn_println!(target: "mm", "Shared frame table full");
+        "Shared frame table full"
+    })
+}
+
+/// Number of address spaces mapping `frame` (1 if it is not shared)
+fn shared_frame_count(frame: usize) -> u32 {
+    SHARED_FRAMES.lock().get(&frame).copied().unwrap_or(1)
+}
+
+/// Drop one address space's claim on `frame`
+fn release_shared_frame(frame: usize) {
+    let mut frames = SHARED_FRAMES.lock();
+    match frames.get(&frame).copied() {
+        Some(count) if count > 2 => {
+            let _ = frames.insert(frame, count - 1);
+        }
+        Some(_) => {
+            frames.remove(&frame);
+        }
+        None => {}
+    }
+}
+
+/// Read the satp register
+pub fn read_satp() -> usize {
+    let satp: usize;
+    unsafe {
+        asm!("csrr {}, satp", out(reg) satp);
+    }
+    satp
+}
+
+/// Load satp and flush the TLB
+pub unsafe fn write_satp(satp: usize) {
+    asm!(
+        "sfence.vma zero, zero",
+        "csrw satp, {}",
+        "sfence.vma zero, zero",
+        in(reg) satp,
+    );
+}
+
 /// Initialize MMU system
 pub fn init_mmu() -> Result<(), &'static str> {
     // Check heap status before starting
-    let (heap_used, heap_total, heap_available) = crate::memory::get_heap_usage();
+    let (_, _, heap_available) = crate::memory::get_heap_usage();
     
     if heap_available < PAGE_SIZE * 4 {
         crate::memory::reset_heap_for_testing();
@@ -606,7 +1542,7 @@ pub fn init_mmu() -> Result<(), &'static str> {
     match mmu.init() {
         Ok(()) => {},
         Err(e) => {
-            console_println!("[x] MMU manager init failed: {}", e);
+            err_println!(target: "mm", "MMU manager init failed: {}", e);
             return Err(e);
         }
     }
@@ -614,34 +1550,154 @@ pub fn init_mmu() -> Result<(), &'static str> {
     match mmu.enable_mmu() {
         Ok(()) => {},
         Err(e) => {
-            console_println!("[x] Virtual Memory enable failed: {}", e);
+            err_println!(target: "mm", "Virtual Memory enable failed: {}", e);
             return Err(e);
         }
     }
     
+    crate::memory::set_migrate_hook(migrate_user_pages);
     Ok(())
 }
 
-/// Create user address space for ELF execution
-pub fn create_user_address_space() -> Result<(), &'static str> {
+/// Create a new user address space for ELF execution
+pub fn create_user_address_space() -> Result<AddressSpace, &'static str> {
+    let mmu = MMU_MANAGER.lock();
+    mmu.create_user_space()
+}
+
+/// Hand a user address space to the MMU manager. Returns its satp value.
+pub fn register_user_space(space: AddressSpace) -> Result<u64, &'static str> {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.register_user_space(space)
+}
+
+/// Free the user address space selected by `satp`
+pub fn release_user_space(satp: u64) {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.release_user_space(satp);
+}
+
+/// Read every page swapped out by a user address space back in, as
+/// `swapoff` needs
+pub fn swap_in_user_spaces() -> Result<(), &'static str> {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.swap_in_all()
+}
+
+/// Fork the active user address space copy-on-write. Returns the child's satp value.
+pub fn fork_current_user_space() -> Result<u64, &'static str> {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.fork_user_space(read_satp() as u64)
+}
+
+/// Whether a user address space is active, so mmap has somewhere to map
+pub fn is_user_space_active() -> bool {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.user_space_mut(read_satp() as u64).is_some()
+}
+
+/// Map a region for mmap into the active user address space; see
+/// `AddressSpace::map_mmap_region`
+pub fn map_current_mmap<F>(addr: Option<usize>, len: usize, flags: u64, source: Option<&str>, fill: F) -> Result<usize, &'static str>
+where
+    F: FnOnce(&mut [u8]) -> Result<(), &'static str>,
+{
+    let mut mmu = MMU_MANAGER.lock();
+    let space = mmu.user_space_mut(read_satp() as u64).ok_or("No user address space active")?;
+    space.map_mmap_region(addr, len, flags, source, fill)
+}
+
+/// The ranges of the user address space selected by `satp`, given the
+/// heap of its process; see `AddressSpace::vmas`
+pub fn user_vmas(satp: u64, heap: (usize, usize)) -> Option<Vec<Vma, MAX_VMAS>> {
+    let mut mmu = MMU_MANAGER.lock();
+    mmu.user_space_mut(satp).map(|space| space.vmas(heap))
+}
+
+/// Unmap mmap pages of the active user address space
+pub fn unmap_current_mmap(vaddr: usize, len: usize) -> Result<(), &'static str> {
     let mut mmu = MMU_MANAGER.lock();
-    mmu.create_user_space()?;
+    let space = mmu.user_space_mut(read_satp() as u64).ok_or("No user address space active")?;
+    space.unmap_mmap_range(vaddr, len);
     Ok(())
 }
 
-/// Map ELF segment in user space
-pub fn map_elf_segment(vaddr: usize, paddr: usize, size: usize, flags: u64) -> Result<(), &'static str> {
+/// Move the program break of the active user address space; see
+/// `AddressSpace::move_break`
+pub fn move_current_break(old: usize, new: usize) -> Result<(), &'static str> {
     let mut mmu = MMU_MANAGER.lock();
-    let user_space = mmu.get_current_user_space()
-        .ok_or("No user address space")?;
-    
-    user_space.map_range(vaddr, paddr, size, flags)
+    let space = mmu.user_space_mut(read_satp() as u64).ok_or("No user address space active")?;
+    space.move_break(old, new)
 }
 
-/// Switch to user address space
-pub fn switch_to_user_space() -> Result<(), &'static str> {
+/// Make the pages of `[vaddr, vaddr + len)` in the active address space
+/// present, so a system call can touch them while holding a lock the
+/// page fault handler would need. Does nothing when no user address space
+/// is active.
+pub fn fault_in_current(vaddr: usize, len: usize) -> Result<(), &'static str> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = vaddr.checked_add(len).ok_or("Address not mapped")?;
     let mut mmu = MMU_MANAGER.lock();
-    mmu.switch_to_user()
+    let Some(space) = mmu.user_space_mut(read_satp() as u64) else {
+        return Ok(());
+    };
+    (vaddr & !(PAGE_SIZE - 1)..end).step_by(PAGE_SIZE).try_for_each(|page| space.fault_in(page))
+}
+
+/// Move user pages into freed holes after an allocation of `size` bytes
+/// failed, so the memory manager can compact what they leave behind.
+/// Called by the memory manager, unlocked. Returns the bytes moved.
+pub fn migrate_user_pages(size: usize) -> usize {
+    // The allocation may have been made with the manager held
+    let Some(mut mmu) = MMU_MANAGER.try_lock() else { return 0 };
+    
+    let want = size.div_ceil(PAGE_SIZE).clamp(1, MIGRATE_BATCH);
+    let mut moved = 0;
+    for space in mmu.user_spaces.iter_mut() {
+        if moved >= want {
+            break;
+        }
+        moved += space.migrate(want - moved);
+    }
+    moved * PAGE_SIZE
+}
+
+/// Handle a page fault on a demand-paged segment of the active address space.
+/// Returns false if the page is not lazily mapped or could not be read.
+pub fn handle_demand_fault(vaddr: usize) -> bool {
+    // The fault may interrupt kernel code holding the manager; never spin on it
+    let mut mmu = match MMU_MANAGER.try_lock() {
+        Some(mmu) => mmu,
+        None => {
+            crate::health::count(crate::health::Event::LockContended);
+            return false;
+        }
+    };
+    
+    match mmu.user_space_mut(read_satp() as u64) {
+        Some(space) => space.resolve_demand_fault(vaddr),
+        None => false,
+    }
+}
+
+/// Handle a store page fault on a copy-on-write page of the active address space.
+/// Returns false if the fault was not caused by copy-on-write.
+pub fn handle_cow_fault(vaddr: usize) -> bool {
+    // The fault may interrupt kernel code holding the manager; never spin on it
+    let mut mmu = match MMU_MANAGER.try_lock() {
+        Some(mmu) => mmu,
+        None => {
+            crate::health::count(crate::health::Event::LockContended);
+            return false;
+        }
+    };
+    
+    match mmu.user_space_mut(read_satp() as u64) {
+        Some(space) => space.resolve_cow(vaddr),
+        None => false,
+    }
 }
 
 /// Switch to kernel address space  
@@ -654,4 +1710,25 @@ pub fn switch_to_kernel_space() -> Result<(), &'static str> {
 pub fn is_mmu_enabled() -> bool {
     let mmu = MMU_MANAGER.lock();
     mmu.is_enabled()
-} 
\ No newline at end of file
+}
+
+/// Check if user programs run under hardware Sv39 translation
+pub fn is_hardware_paging() -> bool {
+    let mmu = MMU_MANAGER.lock();
+    mmu.is_hardware_paging()
+}
+
+/// Explain a page fault at `vaddr` in the active address space
+pub fn describe_fault(vaddr: usize) -> &'static str {
+    let satp = read_satp() as u64;
+    if satp & SATP_MODE_SV39 == 0 {
+        return "paging disabled";
+    }
+    
+    let root = ((satp & ((1 << 44) - 1)) as usize) << PAGE_SHIFT;
+    match find_leaf_in(root, vaddr) {
+        None => "unmapped address",
+        Some((entry, _)) if unsafe { (*entry).flags() } & PTE_U == 0 => "kernel memory",
+        Some(_) => "permission denied",
+    }
+}
@@ -4,6 +4,9 @@
 
 pub mod mmu;
 pub mod mapping;
+pub mod swap;
+pub mod memdebug;
+pub mod slab;
 
 // Re-export the unified memory management from shared library
 pub use elinos_common::memory::*;
@@ -45,12 +48,13 @@ pub fn init_allocator_compatibility() {
         ALLOCATOR.lock().init(heap_start as *mut u8, heap_size);
     }
     
-    elinos_common::console_println!("[o] Memory allocator compatibility layer initialized");
+    elinos_common::ok_println!(target: "mm", "Memory allocator compatibility layer initialized");
 }
 
 /// Kernel-specific memory functions that use the unified manager
 
 /// Allocate kernel memory with alignment
+#[track_caller]
 pub fn allocate_kernel_memory(size: usize, align: usize) -> Option<usize> {
     match allocate_memory(size, align) {
         Ok(ptr) => Some(ptr.as_ptr() as usize),
@@ -58,6 +62,16 @@ pub fn allocate_kernel_memory(size: usize, align: usize) -> Option<usize> {
     }
 }
 
+/// Allocate kernel memory from a freed hole ending by `limit`, to move a
+/// page lower down
+#[track_caller]
+pub fn allocate_kernel_memory_below(size: usize, align: usize, limit: usize) -> Option<usize> {
+    match allocate_memory_below(size, align, limit) {
+        Ok(ptr) => Some(ptr.as_ptr() as usize),
+        Err(_) => None,
+    }
+}
+
 /// Deallocate kernel memory
 pub fn deallocate_kernel_memory(addr: usize, size: usize) {
     if let Some(ptr) = core::ptr::NonNull::new(addr as *mut u8) {
@@ -79,6 +93,6 @@ pub fn get_kernel_memory_stats() -> (usize, usize) {
 
 /// Display kernel memory information
 pub fn display_kernel_memory_info() {
-    elinos_common::console_println!("[i] Kernel Memory Information:");
+    elinos_common::info_println!(target: "mm", "Kernel Memory Information:");
     display_memory_layout();
 }
\ No newline at end of file
@@ -1,346 +1,465 @@
-// Slab Allocator Implementation for elinOS
-// High-level allocator working on top of the buddy allocator
-// Inspired by Maestro OS and Linux kernel slab allocator
+//! Named slab caches for the kernel's hot structures
+//!
+//! A `SlabCache` hands out objects of one size from slabs: runs of memory
+//! taken from the unified memory manager and cut into up to 64 objects,
+//! one bit each in the slab's bitmap. Freeing an object only clears its
+//! bit, so objects that come and go (a request header per disk request, an
+//! entry per file listed) cost no trip to the memory manager once a slab
+//! is there. A slab that empties goes back to the manager unless it is
+//! the cache's last.
+//!
+//! On top of the caches:
+//!
+//! - `SlabBox<T>` owns one object, like a `Box`
+//! - `SlabList<T>` is a list that grows a node at a time, for tables that
+//!   used to be fixed-capacity `heapless::Vec`s and so had a most entries
+//!
+//! Each cache is a static next to the structure it holds; `CACHES` lists
+//! them for /proc/slabinfo. Slab memory is physical memory the kernel maps
+//! one to one, so devices can be handed objects' addresses for DMA, as the
+//! VirtIO block driver does with its request headers.
 
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
 use core::ptr::NonNull;
-use crate::memory::buddy::{BuddyAllocator, BuddyError};
+
 use heapless::Vec;
+use spin::Mutex;
 
-/// Size classes for the slab allocator (powers of 2)
-const SIZE_CLASSES: &[usize] = &[
-    8, 16, 32, 64, 128, 256, 512, 1024, 2048, 4096
-];
+/// Most slabs one cache holds at once
+const MAX_SLABS_PER_CACHE: usize = 64;
+
+/// Most objects in a slab: one bit each of the bitmap
+const MAX_OBJECTS_PER_SLAB: usize = 64;
+
+/// Fewest objects in a slab before it is rounded up to whole pages
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+/// Slabs start on this boundary, so objects keep alignments up to it
+const SLAB_ALIGN: usize = 64;
 
-/// Maximum number of slabs per size class
-const MAX_SLABS_PER_CLASS: usize = 16;
+const PAGE_SIZE: usize = 4096;
 
-/// A slab contains multiple objects of the same size
+/// Every cache, for /proc/slabinfo
+pub static CACHES: &[&SlabCache] = &[
+    &crate::filesystem::FILE_ENTRY_CACHE,
+    &crate::filesystem::DIR_ENTRY_CACHE,
+    &crate::virtio::block::REQUEST_HEADER_CACHE,
+    &crate::process::PROCESS_CACHE,
+];
+
+/// A run of memory holding objects of one size
 #[derive(Debug)]
 struct Slab {
-    /// Base address of this slab
     base_addr: usize,
-    /// Size of each object in this slab
-    object_size: usize,
-    /// Number of objects that can fit in this slab
-    capacity: usize,
-    /// Bitmap tracking which objects are allocated
-    allocated_bitmap: u64, // Supports up to 64 objects per slab
-    /// Number of free objects remaining
+    /// Bit `i` set while object `i` is handed out
+    allocated_bitmap: u64,
     free_count: usize,
 }
 
-/// Statistics for the slab allocator
-#[derive(Debug, Clone, Copy)]
-pub struct SlabStats {
-    pub total_allocations: usize,
-    pub total_deallocations: usize,
-    pub slab_allocations: usize,
-    pub total_slab_memory: usize,
-    pub total_objects_allocated: usize,
-    pub total_objects_capacity: usize,
-    pub fragmentation_ratio: f32,
-}
-
-/// Wrapper type for slab arrays to avoid orphan rules
-#[derive(Debug)]
-struct SlabArrays {
-    arrays: [Vec<Slab, MAX_SLABS_PER_CLASS>; SIZE_CLASSES.len()],
-}
+impl Slab {
+    fn allocate_object(&mut self, object_size: usize) -> Option<usize> {
+        if self.free_count == 0 {
+            return None;
+        }
+        let index = (!self.allocated_bitmap).trailing_zeros() as usize;
+        self.allocated_bitmap |= 1 << index;
+        self.free_count -= 1;
+        Some(self.base_addr + index * object_size)
+    }
 
-impl Default for SlabArrays {
-    fn default() -> Self {
-        Self {
-            arrays: [
-                Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(),
-                Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(),
-            ]
+    /// Give back the object at `addr`; false if it was not handed out
+    fn deallocate_object(&mut self, addr: usize, object_size: usize) -> bool {
+        let mask = 1 << ((addr - self.base_addr) / object_size);
+        if self.allocated_bitmap & mask == 0 {
+            return false;
         }
+        self.allocated_bitmap &= !mask;
+        self.free_count += 1;
+        true
     }
-}
 
-impl core::ops::Index<usize> for SlabArrays {
-    type Output = Vec<Slab, MAX_SLABS_PER_CLASS>;
-    
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.arrays[index]
+    fn owns_address(&self, addr: usize, slab_size: usize) -> bool {
+        (self.base_addr..self.base_addr + slab_size).contains(&addr)
     }
 }
 
-impl core::ops::IndexMut<usize> for SlabArrays {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.arrays[index]
-    }
+/// The slabs of a cache and its counters
+struct CacheState {
+    slabs: Vec<Slab, MAX_SLABS_PER_CACHE>,
+    allocations: usize,
+    frees: usize,
+    /// Allocations refused: no memory for a slab, or no room for another
+    failures: usize,
 }
 
-/// Slab allocator that works on top of buddy allocator
-pub struct SlabAllocator {
-    /// The underlying buddy allocator
-    buddy: BuddyAllocator,
-    /// Slabs for each size class
-    slabs: SlabArrays,
-    /// Statistics
-    total_allocations: usize,
-    total_deallocations: usize,
-    slab_allocations: usize,
+/// Counts for /proc/slabinfo
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slabs: usize,
+    pub active_objects: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    pub failures: usize,
 }
 
-impl SlabAllocator {
-    /// Create a new slab allocator on top of buddy allocator
-    pub fn new(base_address: usize, total_size: usize) -> Result<Self, BuddyError> {
-        let buddy = BuddyAllocator::new(base_address, total_size)?;
-        
-        let mut allocator = SlabAllocator {
-            buddy,
-            slabs: SlabArrays::default(),
-            total_allocations: 0,
-            total_deallocations: 0,
-            slab_allocations: 0,
-        };
-        
-        // Pre-allocate some slabs for common sizes
-        allocator.preallocate_common_slabs()?;
-        
-        Ok(allocator)
-    }
-    
-    /// Allocate memory of the given size
-    pub fn allocate(&mut self, size: usize) -> Option<NonNull<u8>> {
-        self.total_allocations += 1;
-        
-        // For large allocations, use buddy allocator directly
-        if size > SIZE_CLASSES[SIZE_CLASSES.len() - 1] {
-            let addr = self.buddy.allocate(size)?;
-            return NonNull::new(addr as *mut u8);
-        }
-        
-        // Find appropriate size class
-        let size_class_idx = self.find_size_class(size)?;
-        
-        // Try to allocate from existing slab
-        if let Some(addr) = self.allocate_from_slab(size_class_idx) {
-            return NonNull::new(addr as *mut u8);
-        }
-        
-        // No suitable slab found, create a new one
-        if self.create_new_slab(size_class_idx).is_ok() {
-            if let Some(addr) = self.allocate_from_slab(size_class_idx) {
-                return NonNull::new(addr as *mut u8);
-            }
+/// Objects of one size, handed out from slabs
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    objects_per_slab: usize,
+    slab_size: usize,
+    state: Mutex<CacheState>,
+}
+
+impl SlabCache {
+    /// A cache for objects of `T`, shown as `name`
+    pub const fn of<T>(name: &'static str) -> Self {
+        assert!(align_of::<T>() <= SLAB_ALIGN);
+        let align = align_of::<T>();
+        let size = if size_of::<T>() == 0 { 1 } else { size_of::<T>() };
+        let object_size = size.div_ceil(align) * align;
+
+        // Enough pages for a few objects, and no more objects than bits
+        let pages = (object_size * MIN_OBJECTS_PER_SLAB).div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        let objects = pages / object_size;
+        let objects_per_slab = if objects < MAX_OBJECTS_PER_SLAB { objects } else { MAX_OBJECTS_PER_SLAB };
+
+        SlabCache {
+            name,
+            object_size,
+            objects_per_slab,
+            slab_size: objects_per_slab * object_size,
+            state: Mutex::new(CacheState { slabs: Vec::new(), allocations: 0, frees: 0, failures: 0 }),
         }
-        
-        // Fallback to buddy allocator for the actual size requested
-        let addr = self.buddy.allocate(SIZE_CLASSES[size_class_idx])?;
+    }
+
+    /// Room for one object, uninitialized; None if there is no memory for
+    /// another slab or the cache has its most slabs
+    pub fn allocate(&self) -> Option<NonNull<u8>> {
+        let mut state = self.state.lock();
+        let slab = match state.slabs.iter().position(|slab| slab.free_count > 0) {
+            Some(slab) => slab,
+            None => match self.grow(&mut state) {
+                Some(slab) => slab,
+                None => {
+                    state.failures += 1;
+                    return None;
+                }
+            },
+        };
+        let addr = state.slabs[slab].allocate_object(self.object_size)?;
+        state.allocations += 1;
         NonNull::new(addr as *mut u8)
     }
-    
-    /// Deallocate memory at the given address
-    pub fn deallocate(&mut self, ptr: NonNull<u8>, size: usize) {
-        self.total_deallocations += 1;
+
+    /// Give back an object this cache handed out
+    pub fn free(&self, ptr: NonNull<u8>) {
         let addr = ptr.as_ptr() as usize;
-        
-        // For large allocations, use buddy allocator directly
-        if size > SIZE_CLASSES[SIZE_CLASSES.len() - 1] {
-            self.buddy.deallocate(addr, size);
+        let mut state = self.state.lock();
+        let Some(index) = state.slabs.iter().position(|slab| slab.owns_address(addr, self.slab_size)) else {
+            return;
+        };
+        if !state.slabs[index].deallocate_object(addr, self.object_size) {
             return;
         }
-        
-        // Find the size class and deallocate from slab
-        if let Some(size_class_idx) = self.find_size_class(size) {
-            if self.deallocate_from_slab(size_class_idx, addr) {
-                return;
-            }
+        state.frees += 1;
+
+        // Keep one slab, so a cache in steady use does not churn
+        if state.slabs[index].free_count == self.objects_per_slab && state.slabs.len() > 1 {
+            let slab = state.slabs.swap_remove(index);
+            crate::memory::deallocate_kernel_memory(slab.base_addr, self.slab_size);
         }
-        
-        // Fallback to buddy allocator
-        self.buddy.deallocate(addr, size);
-    }
-    
-    /// Pre-allocate slabs for commonly used sizes
-    fn preallocate_common_slabs(&mut self) -> Result<(), BuddyError> {
-        // Pre-allocate slabs for 32, 64, 128, 256 byte objects
-        let common_sizes = [2, 3, 4, 5]; // Indices in SIZE_CLASSES
-        
-        for &size_idx in &common_sizes {
-            let _ = self.create_new_slab(size_idx);
+    }
+
+    /// Add a slab; returns its index
+    fn grow(&self, state: &mut CacheState) -> Option<usize> {
+        if state.slabs.is_full() {
+            return None;
         }
-        
-        Ok(())
+        let base_addr = crate::memory::allocate_kernel_memory(self.slab_size, SLAB_ALIGN)?;
+        // Bits past the last object read as taken, so they are never handed out
+        let unused = if self.objects_per_slab < MAX_OBJECTS_PER_SLAB { !0u64 << self.objects_per_slab } else { 0 };
+        let _ = state.slabs.push(Slab { base_addr, allocated_bitmap: unused, free_count: self.objects_per_slab });
+        Some(state.slabs.len() - 1)
     }
-    
-    /// Find the appropriate size class for the given size
-    fn find_size_class(&self, size: usize) -> Option<usize> {
-        SIZE_CLASSES.iter().position(|&class_size| class_size >= size)
-    }
-    
-    /// Allocate an object from an existing slab
-    fn allocate_from_slab(&mut self, size_class_idx: usize) -> Option<usize> {
-        let slabs = &mut self.slabs.arrays[size_class_idx];
-        
-        for slab in slabs.iter_mut() {
-            if slab.free_count > 0 {
-                if let Some(object_addr) = slab.allocate_object() {
-                    return Some(object_addr);
-                }
-            }
+
+    pub fn stats(&self) -> SlabStats {
+        let state = self.state.lock();
+        let free: usize = state.slabs.iter().map(|slab| slab.free_count).sum();
+        SlabStats {
+            name: self.name,
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab,
+            slabs: state.slabs.len(),
+            active_objects: state.slabs.len() * self.objects_per_slab - free,
+            allocations: state.allocations,
+            frees: state.frees,
+            failures: state.failures,
         }
-        
-        None
-    }
-    
-    /// Deallocate an object from a slab
-    fn deallocate_from_slab(&mut self, size_class_idx: usize, addr: usize) -> bool {
-        let slabs = &mut self.slabs.arrays[size_class_idx];
-        
-        for slab in slabs.iter_mut() {
-            if slab.owns_address(addr) {
-                slab.deallocate_object(addr);
-                
-                // If slab becomes empty, consider returning it to buddy allocator
-                if slab.is_empty() && slabs.len() > 1 {
-                    // Keep at least one empty slab for future allocations
-                    // This is a simple heuristic
-                }
-                
-                return true;
-            }
+    }
+
+    /// Whether objects of `T` fit
+    fn holds<T>(&self) -> bool {
+        size_of::<T>() <= self.object_size && align_of::<T>() <= SLAB_ALIGN
+    }
+}
+
+/// One object from a slab cache, dropped and given back with the box
+pub struct SlabBox<T> {
+    ptr: NonNull<T>,
+    cache: &'static SlabCache,
+}
+
+// Owned like a Box
+unsafe impl<T: Send> Send for SlabBox<T> {}
+unsafe impl<T: Sync> Sync for SlabBox<T> {}
+
+impl<T> SlabBox<T> {
+    /// Put `value` in an object of `cache`; gives it back if there is no
+    /// room or the cache's objects are too small for it
+    pub fn new_in(cache: &'static SlabCache, value: T) -> Result<Self, T> {
+        if !cache.holds::<T>() {
+            return Err(value);
         }
-        
-        false
-    }
-    
-    /// Create a new slab for the given size class
-    fn create_new_slab(&mut self, size_class_idx: usize) -> Result<(), BuddyError> {
-        let object_size = SIZE_CLASSES[size_class_idx];
-        
-        // Calculate slab size (aim for ~4KB slabs)
-        let slab_size = if object_size <= 256 {
-            4096
-        } else {
-            // For larger objects, use smaller slabs
-            core::cmp::max(object_size * 8, 4096)
-        };
-        
-        // Allocate memory from buddy allocator
-        let base_addr = self.buddy.allocate(slab_size)
-            .ok_or(BuddyError::OutOfMemory)?;
-        
-        let capacity = slab_size / object_size;
-        if capacity == 0 || capacity > 64 {
-            // Return the memory if we can't use it effectively
-            self.buddy.deallocate(base_addr, slab_size);
-            return Err(BuddyError::InvalidSize);
+        let Some(ptr) = cache.allocate() else { return Err(value) };
+        let ptr = ptr.cast::<T>();
+        unsafe {
+            ptr.as_ptr().write(value);
         }
-        
-        let slab = Slab {
-            base_addr,
-            object_size,
-            capacity,
-            allocated_bitmap: 0,
-            free_count: capacity,
+        Ok(SlabBox { ptr, cache })
+    }
+
+    /// The object's address, e.g. to hand to a device
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    /// The value, with the object given back
+    pub fn into_inner(self) -> T {
+        let value = unsafe { self.ptr.as_ptr().read() };
+        self.cache.free(self.ptr.cast());
+        core::mem::forget(self);
+        value
+    }
+}
+
+impl<T> core::ops::Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> core::ops::DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+        }
+        self.cache.free(self.ptr.cast());
+    }
+}
+
+/// A node of a `SlabList`; its cache is made with
+/// `SlabCache::of::<ListNode<T>>`
+pub struct ListNode<T> {
+    value: T,
+    next: Option<SlabBox<ListNode<T>>>,
+}
+
+/// A list whose nodes come from a slab cache, in the order pushed. It has
+/// no most entries: it grows while the cache can get memory.
+pub struct SlabList<T> {
+    cache: &'static SlabCache,
+    head: Option<SlabBox<ListNode<T>>>,
+    /// The last node, to push in constant time; null when empty
+    tail: *mut ListNode<T>,
+    len: usize,
+}
+
+// The raw tail only ever points into nodes the list owns
+unsafe impl<T: Send> Send for SlabList<T> {}
+unsafe impl<T: Sync> Sync for SlabList<T> {}
+
+impl<T> SlabList<T> {
+    pub const fn new(cache: &'static SlabCache) -> Self {
+        SlabList { cache, head: None, tail: core::ptr::null_mut(), len: 0 }
+    }
+
+    /// Add `value` at the end; gives it back if the cache has no room
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let node = SlabBox::new_in(self.cache, ListNode { value, next: None })
+            .map_err(|node| node.value)?;
+        let link = match unsafe { self.tail.as_mut() } {
+            Some(tail) => &mut tail.next,
+            None => &mut self.head,
         };
-        
-        self.slabs.arrays[size_class_idx].push(slab)
-            .map_err(|_| BuddyError::OutOfMemory)?;
-        
-        self.slab_allocations += 1;
-        
+        self.tail = link.insert(node).as_ptr();
+        self.len += 1;
         Ok(())
     }
-    
-    /// Get allocator statistics
-    pub fn get_stats(&self) -> SlabStats {
-        let mut total_slab_memory = 0;
-        let mut total_objects_allocated = 0;
-        let mut total_objects_capacity = 0;
-        
-        for size_class_slabs in &self.slabs.arrays {
-            for slab in size_class_slabs {
-                total_slab_memory += slab.capacity * slab.object_size;
-                total_objects_allocated += slab.capacity - slab.free_count;
-                total_objects_capacity += slab.capacity;
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { node: self.head.as_deref(), remaining: self.len }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut { node: self.head.as_deref_mut().map(|node| node as *mut ListNode<T>), remaining: self.len, _list: PhantomData }
+    }
+
+    /// Keep only the values `keep` returns true for, in order
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        let mut rest = self.head.take();
+        self.tail = core::ptr::null_mut();
+        self.len = 0;
+        while let Some(mut node) = rest {
+            rest = node.next.take();
+            if keep(&node.value) {
+                let link = match unsafe { self.tail.as_mut() } {
+                    Some(tail) => &mut tail.next,
+                    None => &mut self.head,
+                };
+                self.tail = link.insert(node).as_ptr();
+                self.len += 1;
             }
         }
-        
-        SlabStats {
-            total_allocations: self.total_allocations,
-            total_deallocations: self.total_deallocations,
-            slab_allocations: self.slab_allocations,
-            total_slab_memory,
-            total_objects_allocated,
-            total_objects_capacity,
-            fragmentation_ratio: if total_objects_capacity > 0 {
-                (total_objects_capacity - total_objects_allocated) as f32 / total_objects_capacity as f32
-            } else {
-                0.0
-            },
+    }
+
+    /// Take the first value, if any
+    pub fn pop_front(&mut self) -> Option<T> {
+        let mut node = self.head.take()?;
+        self.head = node.next.take();
+        if self.head.is_none() {
+            self.tail = core::ptr::null_mut();
         }
+        self.len -= 1;
+        Some(node.into_inner().value)
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
     }
 }
 
-impl Slab {
-    /// Allocate an object from this slab
-    fn allocate_object(&mut self) -> Option<usize> {
-        if self.free_count == 0 {
-            return None;
-        }
-        
-        // Find first free bit
-        for i in 0..self.capacity {
-            if (self.allocated_bitmap & (1 << i)) == 0 {
-                // Mark as allocated
-                self.allocated_bitmap |= 1 << i;
-                self.free_count -= 1;
-                
-                return Some(self.base_addr + i * self.object_size);
-            }
-        }
-        
-        None
+impl<T> Drop for SlabList<T> {
+    fn drop(&mut self) {
+        // One node at a time, so a long list does not recurse through its
+        // links
+        self.clear();
     }
-    
-    /// Deallocate an object from this slab
-    fn deallocate_object(&mut self, addr: usize) {
-        if !self.owns_address(addr) {
-            return;
-        }
-        
-        let offset = addr - self.base_addr;
-        let object_index = offset / self.object_size;
-        
-        if object_index < self.capacity {
-            let mask = 1 << object_index;
-            if (self.allocated_bitmap & mask) != 0 {
-                self.allocated_bitmap &= !mask;
-                self.free_count += 1;
-            }
-        }
+}
+
+pub struct Iter<'a, T> {
+    node: Option<&'a ListNode<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.node?;
+        self.node = node.next.as_deref();
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+pub struct IterMut<'a, T> {
+    node: Option<*mut ListNode<T>>,
+    remaining: usize,
+    _list: PhantomData<&'a mut SlabList<T>>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        // Each node is visited once, so the references do not overlap
+        let node = unsafe { &mut *self.node? };
+        self.node = node.next.as_deref_mut().map(|next| next as *mut ListNode<T>);
+        self.remaining -= 1;
+        Some(&mut node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SlabList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SlabList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+/// Takes the values out of a list, first to last
+pub struct IntoIter<T>(SlabList<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
     }
-    
-    /// Check if this slab owns the given address
-    fn owns_address(&self, addr: usize) -> bool {
-        let slab_end = self.base_addr + self.capacity * self.object_size;
-        addr >= self.base_addr && addr < slab_end
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
     }
-    
-    /// Check if this slab is completely empty
-    fn is_empty(&self) -> bool {
-        self.free_count == self.capacity
+}
+
+impl<T> IntoIterator for SlabList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    
-    #[test]
-    fn test_size_class_selection() {
-        let allocator = SlabAllocator::new(0x1000, 1024 * 1024).unwrap();
-        
-        assert_eq!(allocator.find_size_class(1), Some(0));  // 8 bytes
-        assert_eq!(allocator.find_size_class(8), Some(0));  // 8 bytes
-        assert_eq!(allocator.find_size_class(9), Some(1));  // 16 bytes
-        assert_eq!(allocator.find_size_class(32), Some(2)); // 32 bytes
-        assert_eq!(allocator.find_size_class(4096), Some(9)); // 4096 bytes
-        assert_eq!(allocator.find_size_class(8192), None);  // Too large
-    }
-} 
\ No newline at end of file
+/// `name active total object_size objects_per_slab slabs`, then the
+/// allocation counters, roughly as Linux's /proc/slabinfo
+pub fn write_slabinfo(out: &mut impl core::fmt::Write) -> core::fmt::Result {
+    writeln!(out, "# name            <active_objs> <num_objs> <objsize> <objperslab> <num_slabs>  <allocs> <frees> <failures>")?;
+    for cache in CACHES {
+        let stats = cache.stats();
+        writeln!(out, "{:<18}{:>13} {:>10} {:>9} {:>12} {:>11}  {:>8} {:>7} {:>10}",
+            stats.name, stats.active_objects, stats.slabs * stats.objects_per_slab, stats.object_size,
+            stats.objects_per_slab, stats.slabs, stats.allocations, stats.frees, stats.failures)?;
+    }
+    Ok(())
+}
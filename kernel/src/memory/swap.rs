@@ -0,0 +1,344 @@
+//! Swap: room on disk for user pages when memory runs short
+//!
+//! `swapon` gives the kernel one swap area: a whole disk or partition that
+//! nothing is mounted from, or a file on a mounted filesystem. The sectors
+//! holding a file are looked up once, and pages then go straight to them
+//! through the block device, as a page fault may come in the middle of a
+//! syscall holding the filesystem lock or the sector cache. The file must
+//! not be changed while in use; deleting it is refused.
+//!
+//! The area is cut into page-sized slots. When an address space needs a
+//! frame and less than `vm.min_free_kbytes` is left, or the allocator has
+//! none at all, it writes some of its writable pages that have not been
+//! used lately to free slots and gives their frames back (see
+//! `AddressSpace::reclaim`). Their page table entries keep the slot with
+//! the valid bit clear, so the next access faults and the page is read
+//! back in. A fork shares the slots of pages swapped out, each slot
+//! counting its users. `swapoff` reads every page back in before the area
+//! goes.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use heapless::{String, Vec};
+use spin::Mutex;
+use elinos_common::blockcache::SECTOR_SIZE;
+use elinos_common::err_println;
+
+use super::mmu::PAGE_SIZE;
+use crate::filesystem::{self, FilesystemError};
+use crate::sysctl::{Kind, Tunable};
+use crate::virtio::{block_device, Volume};
+
+/// Most pages an area holds (16 MB); larger areas are only used that far
+pub const MAX_SWAP_PAGES: usize = 4096;
+
+/// Most separate runs of sectors a swap file may lie in
+const MAX_RUNS: usize = 64;
+
+const SECTORS_PER_PAGE: u64 = (PAGE_SIZE / SECTOR_SIZE) as u64;
+
+/// What the area is on
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwapKind {
+    /// A whole disk or partition
+    Volume,
+    /// A file on the volume
+    File(String<64>),
+}
+
+impl SwapKind {
+    /// Type column of /proc/swaps
+    pub fn name(&self) -> &'static str {
+        match self {
+            SwapKind::Volume => "partition",
+            SwapKind::File(_) => "file",
+        }
+    }
+}
+
+struct SwapArea {
+    kind: SwapKind,
+    volume: Volume,
+    /// Where the area lies, as (first sector, sectors) on the disk
+    runs: Vec<(u64, u64), MAX_RUNS>,
+    /// Slots in the area
+    pages: usize,
+    /// Address spaces using each slot; 0 when it is free
+    users: [u8; MAX_SWAP_PAGES],
+    used: usize,
+    /// Set by `swapoff` while it reads pages back: no new pages go out
+    draining: bool,
+}
+
+static SWAP: Mutex<Option<SwapArea>> = Mutex::new(None);
+
+/// Free memory below which address spaces swap out pages before taking
+/// more, in KB
+static MIN_FREE_KB: AtomicU64 = AtomicU64::new(256);
+
+static SWAP_INS: AtomicU64 = AtomicU64::new(0);
+static SWAP_OUTS: AtomicU64 = AtomicU64::new(0);
+/// Times an address space had to free frames before it could allocate
+static RECLAIMS: AtomicU64 = AtomicU64::new(0);
+/// Reclaims that found nothing to swap out or nowhere to put it
+static RECLAIM_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "vm.min_free_kbytes",
+        description: "With swap on, pages are swapped out once free memory drops below this many KB",
+        kind: Kind::Int { min: 0, max: 65536, get: get_min_free, set: set_min_free },
+    },
+];
+
+fn get_min_free() -> i64 {
+    MIN_FREE_KB.load(Ordering::Relaxed) as i64
+}
+
+fn set_min_free(kb: i64) -> Result<(), &'static str> {
+    MIN_FREE_KB.store(kb as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The swap area and how it is used, and the counters since boot
+#[derive(Debug, Clone)]
+pub struct SwapStats {
+    /// Path of the file or name of the volume, if swap is on
+    pub name: Option<String<64>>,
+    pub kind: Option<SwapKind>,
+    /// Slots in the area, and slots holding a page
+    pub pages: usize,
+    pub used: usize,
+    pub swap_ins: u64,
+    pub swap_outs: u64,
+    pub reclaims: u64,
+    pub reclaim_failures: u64,
+}
+
+/// Use the disk or partition `volume` as swap. Returns its size in pages.
+pub fn swapon_volume(volume: Volume) -> Result<usize, &'static str> {
+    if filesystem::volume_in_use(volume) {
+        return Err("Volume is mounted");
+    }
+    let (start, sectors) = volume.extent().ok_or("No such volume")?;
+    let mut runs = Vec::new();
+    let _ = runs.push((start, sectors));
+    enable(SwapKind::Volume, volume, runs)
+}
+
+/// Use the file `path`, which must already have its full size, as swap.
+/// Returns its size in pages.
+pub fn swapon_file(path: &str) -> Result<usize, &'static str> {
+    let mut runs = Vec::<(u64, u64), MAX_RUNS>::new();
+    let volume = filesystem::file_disk_runs(path, &mut |first, count| {
+        match runs.last_mut() {
+            Some((start, sectors)) if *start + *sectors == first => *sectors += count,
+            _ => runs.push((first, count)).map_err(|_| FilesystemError::NotImplemented)?,
+        }
+        Ok(())
+    }).map_err(|e| match e {
+        FilesystemError::FileNotFound => "No such file",
+        FilesystemError::IsADirectory => "Is a directory",
+        FilesystemError::NotImplemented => "Swap file too fragmented or not a regular file",
+        _ => "Failed to look up the file's sectors",
+    })?;
+    // What the cache holds of the file would be stale from now on
+    for &(first, count) in runs.iter() {
+        filesystem::cache::forget_sectors(volume.device, first, count);
+    }
+    let name = String::try_from(path).map_err(|_| "Path too long")?;
+    enable(SwapKind::File(name), volume, runs)
+}
+
+fn enable(kind: SwapKind, volume: Volume, runs: Vec<(u64, u64), MAX_RUNS>) -> Result<usize, &'static str> {
+    let sectors: u64 = runs.iter().map(|&(_, count)| count).sum();
+    let pages = ((sectors / SECTORS_PER_PAGE) as usize).min(MAX_SWAP_PAGES);
+    if pages == 0 {
+        return Err("Swap area smaller than a page");
+    }
+    let mut swap = SWAP.lock();
+    if swap.is_some() {
+        return Err("Swap is already on");
+    }
+    *swap = Some(SwapArea { kind, volume, runs, pages, users: [0; MAX_SWAP_PAGES], used: 0, draining: false });
+    Ok(pages)
+}
+
+/// Read every page back from swap and stop using the area
+pub fn swapoff() -> Result<(), &'static str> {
+    match SWAP.lock().as_mut() {
+        Some(area) => area.draining = true,
+        None => return Err("Swap is not on"),
+    }
+    let result = super::mmu::swap_in_user_spaces();
+
+    let mut swap = SWAP.lock();
+    let Some(area) = swap.as_mut() else { return Ok(()) };
+    match result {
+        Ok(()) if area.used == 0 => {
+            *swap = None;
+            Ok(())
+        }
+        // Pages of a program still being loaded
+        Ok(()) => {
+            area.draining = false;
+            Err("Pages of a process not yet running are still swapped out")
+        }
+        Err(e) => {
+            area.draining = false;
+            Err(e)
+        }
+    }
+}
+
+/// Whether swap is on and taking pages
+pub fn is_on() -> bool {
+    SWAP.lock().as_ref().is_some_and(|area| !area.draining)
+}
+
+/// Whether free memory is low enough that pages should go to swap first
+pub fn under_pressure() -> bool {
+    if !is_on() {
+        return false;
+    }
+    let (_, _, available) = super::get_heap_usage();
+    (available as u64) < MIN_FREE_KB.load(Ordering::Relaxed) * 1024
+}
+
+/// Whether swap is on a file held by `volume`, or overlaps it
+pub fn uses_volume(volume: Volume) -> bool {
+    SWAP.lock().as_ref().is_some_and(|area| area.volume.overlaps(&volume))
+}
+
+/// Whether the file at `path` is the swap area
+pub fn is_swap_file(path: &str) -> bool {
+    SWAP.lock().as_ref().is_some_and(|area| matches!(&area.kind, SwapKind::File(name) if name == path))
+}
+
+/// Write the page at `frame` to a free slot. Returns the slot, or None
+/// if swap is off, full or failed.
+pub fn swap_out(frame: usize) -> Option<usize> {
+    let mut swap = SWAP.lock();
+    let area = swap.as_mut().filter(|area| !area.draining)?;
+    let slot = area.users[..area.pages].iter().position(|&users| users == 0)?;
+    if let Err(e) = transfer(area, slot, frame, true) {
+        err_println!(target: "mm", "Failed to write page to swap slot {}: {}", slot, e);
+        return None;
+    }
+    area.users[slot] = 1;
+    area.used += 1;
+    SWAP_OUTS.fetch_add(1, Ordering::Relaxed);
+    Some(slot)
+}
+
+/// Read the page in `slot` into `frame`, and drop the caller's use of the
+/// slot
+pub fn swap_in(slot: usize, frame: usize) -> Result<(), &'static str> {
+    let mut swap = SWAP.lock();
+    let area = swap.as_mut().ok_or("Swap is not on")?;
+    if area.users.get(slot).copied().unwrap_or(0) == 0 {
+        return Err("Swap slot not in use");
+    }
+    transfer(area, slot, frame, false)?;
+    release_slot(area, slot);
+    SWAP_INS.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Note one more address space using `slot`, after a fork
+pub fn share(slot: usize) {
+    if let Some(users) = SWAP.lock().as_mut().and_then(|area| area.users.get_mut(slot)) {
+        *users = users.saturating_add(1);
+    }
+}
+
+/// Drop one address space's use of `slot`, whose page is not wanted any more
+pub fn release(slot: usize) {
+    if let Some(area) = SWAP.lock().as_mut() {
+        release_slot(area, slot);
+    }
+}
+
+fn release_slot(area: &mut SwapArea, slot: usize) {
+    if let Some(users) = area.users.get_mut(slot) {
+        if *users == 1 {
+            area.used -= 1;
+        }
+        *users = users.saturating_sub(1);
+    }
+}
+
+/// Count a reclaim that swapped out `freed` pages
+pub fn note_reclaim(freed: usize) {
+    RECLAIMS.fetch_add(1, Ordering::Relaxed);
+    if freed == 0 {
+        RECLAIM_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Read or write the page in `slot` from or to the page at `frame`
+fn transfer(area: &SwapArea, slot: usize, frame: usize, write: bool) -> Result<(), &'static str> {
+    let device = block_device(area.volume.device).ok_or("Swap device gone")?;
+    // The fault being handled may have interrupted the device's own I/O
+    let mut device = device.try_lock().ok_or_else(|| {
+        crate::health::count(crate::health::Event::LockContended);
+        "Swap device busy"
+    })?;
+    if !device.is_initialized() {
+        return Err("Swap device gone");
+    }
+
+    let page = unsafe { core::slice::from_raw_parts_mut(frame as *mut u8, PAGE_SIZE) };
+    // Sector within the area, then within the run it falls in
+    let mut sector = slot as u64 * SECTORS_PER_PAGE;
+    let mut done = 0;
+    for &(first, count) in area.runs.iter() {
+        if done == PAGE_SIZE {
+            break;
+        }
+        if sector >= count {
+            sector -= count;
+            continue;
+        }
+        let take = ((count - sector) as usize * SECTOR_SIZE).min(PAGE_SIZE - done);
+        let bytes = &mut page[done..done + take];
+        let result = if write { device.write_blocks(first + sector, bytes) } else { device.read_blocks(first + sector, bytes) };
+        result.map_err(|_| "Swap I/O error")?;
+        done += take;
+        sector = 0;
+    }
+    if done < PAGE_SIZE {
+        return Err("Swap slot past the end of the area");
+    }
+    Ok(())
+}
+
+pub fn stats() -> SwapStats {
+    let swap = SWAP.lock();
+    let mut stats = SwapStats {
+        name: None,
+        kind: None,
+        pages: 0,
+        used: 0,
+        swap_ins: SWAP_INS.load(Ordering::Relaxed),
+        swap_outs: SWAP_OUTS.load(Ordering::Relaxed),
+        reclaims: RECLAIMS.load(Ordering::Relaxed),
+        reclaim_failures: RECLAIM_FAILURES.load(Ordering::Relaxed),
+    };
+    if let Some(area) = swap.as_ref() {
+        let mut name = String::new();
+        match &area.kind {
+            SwapKind::File(path) => {
+                let _ = name.push_str(path);
+            }
+            SwapKind::Volume => {
+                let _ = write!(name, "{}", area.volume);
+            }
+        }
+        stats.name = Some(name);
+        stats.kind = Some(area.kind.clone());
+        stats.pages = area.pages;
+        stats.used = area.used;
+    }
+    stats
+}
@@ -0,0 +1,271 @@
+//! Message catalog for shell and command output
+//!
+//! User-facing strings live in one compile-time table with a column per
+//! locale. `en` is the default; `ascii` keeps to 7-bit symbols and terse
+//! wording for constrained serial consoles. `{}` marks where arguments go.
+//!
+//! The locale is read from `/etc/locale` at boot and can be changed with the
+//! `locale` command. The file may also override single messages by key:
+//!
+//! ```text
+//! locale=ascii
+//! charset=ascii
+//! unknown_command=what is '{}'?
+//! ```
+//!
+//! `charset` selects whether the console renders UTF-8 or downgrades
+//! non-ASCII output (see `elinos_common::console::ConsoleEncoding`).
+
+use core::fmt::{self, Display, Write};
+use heapless::{String, FnvIndexMap};
+use spin::Mutex;
+use elinos_common::console::{ConsoleEncoding, console_encoding, set_console_encoding};
+
+/// Path of the locale configuration file
+pub const LOCALE_FILE_PATH: &str = "/etc/locale";
+
+/// Maximum number of per-message overrides from the locale file
+const MAX_OVERRIDES: usize = 16;
+
+/// Maximum length of an overridden message
+const MAX_OVERRIDE_LEN: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Locale {
+    En,
+    Ascii,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Ascii];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Ascii => "ascii",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Locale> {
+        Locale::ALL.iter().copied().find(|l| l.name() == name)
+    }
+}
+
+/// Catalog entries. The discriminant indexes `CATALOG`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Msg {
+    UnknownCommand,
+    HelpHint,
+    CommandError,
+    Goodbye,
+    Usage,
+    FileNotFound,
+    NotExecutable,
+    ElfMagicHint,
+    ListingFor,
+    NoFiles,
+    TotalFiles,
+    ListFailed,
+    ReadingFile,
+    BinaryFile,
+    ProcessExited,
+    ProgramKilled,
+    SegmentationFault,
+    HistoryTitle,
+    HistoryEmpty,
+    Separator,
+    LocaleCurrent,
+    LocaleAvailable,
+    LocaleSaved,
+    CharsetCurrent,
+}
+
+/// One catalog row: override key, then the text for each locale
+struct Entry {
+    key: &'static str,
+    en: &'static str,
+    ascii: &'static str,
+}
+
+static CATALOG: [Entry; 24] = [
+    Entry { key: "unknown_command", en: "Unknown command: {}", ascii: "?cmd {}" },
+    Entry { key: "help_hint", en: "Type 'help' for available commands.", ascii: "try: help" },
+    Entry { key: "command_error", en: "Error: {}", ascii: "ERR {}" },
+    Entry { key: "goodbye", en: "Goodbye!", ascii: "bye" },
+    Entry { key: "usage", en: "Usage: {}", ascii: "usage: {}" },
+    Entry { key: "file_not_found", en: "[x] File '{}' not found", ascii: "[x] {}: not found" },
+    Entry { key: "not_executable", en: "[x] Not an executable file: {}", ascii: "[x] {}: not exec" },
+    Entry { key: "elf_magic_hint", en: "(expected ELF magic: 7f 45 4c 46)", ascii: "(want 7f 45 4c 46)" },
+    Entry { key: "listing_for", en: "Listing for target '{}':", ascii: "{}:" },
+    Entry { key: "no_files", en: "(No files found)", ascii: "(empty)" },
+    Entry { key: "total_files", en: "Total files: {}", ascii: "total {}" },
+    Entry { key: "list_failed", en: "Failed to list directory", ascii: "[x] ls failed" },
+    Entry { key: "reading_file", en: "[i] Reading file: {}", ascii: "[i] {}" },
+    Entry { key: "binary_file", en: "(Binary file - {} bytes)", ascii: "(binary {}b)" },
+    Entry { key: "process_exited", en: "[i] Process {} exited with status {}", ascii: "[i] pid {} exit {}" },
+    Entry { key: "program_killed", en: "[x] Program killed: {} at 0x{} (stval=0x{})", ascii: "[x] killed {} pc=0x{} tval=0x{}" },
+    Entry { key: "segmentation_fault", en: "[x] Segmentation fault: {} at 0x{} ({}), pc=0x{}", ascii: "[x] segv {} 0x{} {} pc=0x{}" },
+    Entry { key: "history_title", en: "Command History:", ascii: "history:" },
+    Entry { key: "history_empty", en: "  (no commands in history)", ascii: "  (none)" },
+    Entry { key: "separator", en: "────────────────", ascii: "----------------" },
+    Entry { key: "locale_current", en: "[i] Locale: {}", ascii: "[i] locale {}" },
+    Entry { key: "locale_available", en: "   Available: {}", ascii: "   avail: {}" },
+    Entry { key: "locale_saved", en: "[o] Saved to {}", ascii: "[o] {}" },
+    Entry { key: "charset_current", en: "[i] Console charset: {}", ascii: "[i] charset {}" },
+];
+
+static LOCALE: Mutex<Locale> = Mutex::new(Locale::En);
+static OVERRIDES: Mutex<FnvIndexMap<u8, String<MAX_OVERRIDE_LEN>, MAX_OVERRIDES>> =
+    Mutex::new(FnvIndexMap::new());
+
+impl Msg {
+    fn entry(&self) -> &'static Entry {
+        &CATALOG[*self as usize]
+    }
+
+    /// Catalog text for `locale`, ignoring overrides
+    pub fn text(&self, locale: Locale) -> &'static str {
+        let entry = self.entry();
+        match locale {
+            Locale::En => entry.en,
+            Locale::Ascii => entry.ascii,
+        }
+    }
+}
+
+/// Current output locale
+pub fn current_locale() -> Locale {
+    *LOCALE.lock()
+}
+
+/// Switch the output locale for this session
+pub fn set_locale(locale: Locale) {
+    *LOCALE.lock() = locale;
+}
+
+/// Write `template` with each `{}` replaced by the next argument
+fn write_template<W: Write>(out: &mut W, template: &str, args: &[&dyn Display]) -> fmt::Result {
+    let mut args = args.iter();
+    let mut rest = template;
+
+    while let Some(pos) = rest.find("{}") {
+        out.write_str(&rest[..pos])?;
+        match args.next() {
+            Some(arg) => write!(out, "{}", arg)?,
+            None => out.write_str("{}")?,
+        }
+        rest = &rest[pos + 2..];
+    }
+    out.write_str(rest)
+}
+
+/// A catalog message with its arguments, formatted in the current locale
+pub struct Message<'a> {
+    msg: Msg,
+    args: &'a [&'a dyn Display],
+}
+
+impl<'a> Message<'a> {
+    pub fn new(msg: Msg, args: &'a [&'a dyn Display]) -> Self {
+        Message { msg, args }
+    }
+}
+
+impl<'a> Display for Message<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Copy the override out so no lock is held while arguments format
+        let custom = OVERRIDES.lock().get(&(self.msg as u8)).cloned();
+        match custom {
+            Some(template) => write_template(f, &template, self.args),
+            None => write_template(f, self.msg.text(current_locale()), self.args),
+        }
+    }
+}
+
+/// Print a catalog message and a newline: `msg_println!(Msg::Usage, usage)`
+#[macro_export]
+macro_rules! msg_println {
+    ($msg:expr $(, $arg:expr)* $(,)?) => {
+        $crate::console_println!("{}", $crate::messages::Message::new($msg, &[$(&$arg as &dyn core::fmt::Display),*]))
+    };
+}
+
+/// Apply the contents of a locale file: `locale=<name>` and `<key>=<text>` lines
+pub fn apply_locale_config(content: &str) {
+    let mut overrides = OVERRIDES.lock();
+    overrides.clear();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+
+        if key == "locale" {
+            if let Some(locale) = Locale::from_name(value) {
+                set_locale(locale);
+            }
+            continue;
+        }
+
+        if key == "charset" {
+            if let Some(encoding) = ConsoleEncoding::from_name(value) {
+                set_console_encoding(encoding);
+            }
+            continue;
+        }
+
+        if let Some(index) = CATALOG.iter().position(|e| e.key == key) {
+            if let Ok(text) = String::try_from(value) {
+                let _ = overrides.insert(index as u8, text);
+            }
+        }
+    }
+}
+
+/// Load `/etc/locale` if it exists
+pub fn load_locale_file() {
+    if let Ok(data) = crate::filesystem::read_file(LOCALE_FILE_PATH) {
+        if let Ok(content) = core::str::from_utf8(&data) {
+            apply_locale_config(content);
+        }
+    }
+    // A missing file just means the default locale
+}
+
+/// Record the current locale and charset in `/etc/locale`, keeping any overrides
+pub fn save_locale_file() -> Result<(), &'static str> {
+    let mut content = String::<1024>::new();
+    let _ = writeln!(content, "locale={}", current_locale().name());
+    let _ = writeln!(content, "charset={}", console_encoding().name());
+
+    if let Ok(data) = crate::filesystem::read_file(LOCALE_FILE_PATH) {
+        if let Ok(old) = core::str::from_utf8(&data) {
+            for line in old.lines() {
+                let line_trimmed = line.trim();
+                if line_trimmed.starts_with("locale=") || line_trimmed.starts_with("charset=") || line_trimmed.is_empty() {
+                    continue;
+                }
+                if content.push_str(line).is_err() || content.push('\n').is_err() {
+                    return Err("Locale file too large");
+                }
+            }
+        }
+    }
+
+    if !crate::filesystem::file_exists("/etc") {
+        use crate::filesystem::traits::FileSystem;
+        let _ = crate::filesystem::FILESYSTEM.lock().create_directory("/etc");
+    }
+
+    // Written whole or not at all, so the overrides are never lost
+    let mut transaction = crate::filesystem::transaction::Transaction::new();
+    transaction.write(LOCALE_FILE_PATH, content.as_bytes())
+        .and_then(|_| transaction.commit())
+        .map_err(|_| "Failed to write locale file")
+}
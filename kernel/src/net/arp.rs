@@ -0,0 +1,110 @@
+//! ARP: resolving IPv4 addresses on the local link
+
+use spin::Mutex;
+use heapless::FnvIndexMap;
+use super::{Ipv4Addr, NetError, NetResult, ETHERTYPE_ARP, ETHERTYPE_IPV4, BROADCAST_MAC, POLL_TIMEOUT};
+
+const ARP_HTYPE_ETHERNET: u16 = 1;
+const ARP_OP_REQUEST: u16 = 1;
+const ARP_OP_REPLY: u16 = 2;
+const ARP_PACKET_LEN: usize = 28;
+
+/// Maximum number of cached neighbours
+const ARP_CACHE_SIZE: usize = 16;
+
+static ARP_CACHE: Mutex<FnvIndexMap<Ipv4Addr, [u8; 6], ARP_CACHE_SIZE>> = Mutex::new(FnvIndexMap::new());
+
+/// Cached MAC address for `ip`
+pub fn lookup(ip: Ipv4Addr) -> Option<[u8; 6]> {
+    ARP_CACHE.lock().get(&ip).copied()
+}
+
+/// Remember `ip` is at `mac`, evicting the oldest entry if the cache is full
+fn insert(ip: Ipv4Addr, mac: [u8; 6]) {
+    let mut cache = ARP_CACHE.lock();
+    if cache.len() == ARP_CACHE_SIZE && !cache.contains_key(&ip) {
+        if let Some(oldest) = cache.keys().next().copied() {
+            cache.remove(&oldest);
+        }
+    }
+    let _ = cache.insert(ip, mac);
+}
+
+/// Build and send an ARP packet
+fn send(op: u16, target_mac: [u8; 6], target_ip: Ipv4Addr, dst_mac: [u8; 6]) -> NetResult<()> {
+    let config = super::interface()?;
+
+    let mut packet = [0u8; ARP_PACKET_LEN];
+    packet[0..2].copy_from_slice(&ARP_HTYPE_ETHERNET.to_be_bytes());
+    packet[2..4].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    packet[4] = 6; // hardware address length
+    packet[5] = 4; // protocol address length
+    packet[6..8].copy_from_slice(&op.to_be_bytes());
+    packet[8..14].copy_from_slice(&config.mac);
+    packet[14..18].copy_from_slice(&config.ip.0);
+    packet[18..24].copy_from_slice(&target_mac);
+    packet[24..28].copy_from_slice(&target_ip.0);
+
+    super::send_ethernet(dst_mac, ETHERTYPE_ARP, &packet)
+}
+
+/// Resolve `ip` to a MAC address, asking the network if it is not cached
+pub fn resolve(ip: Ipv4Addr) -> NetResult<[u8; 6]> {
+    if let Some(mac) = lookup(ip) {
+        return Ok(mac);
+    }
+
+    send(ARP_OP_REQUEST, [0; 6], ip, BROADCAST_MAC)?;
+
+    for _ in 0..POLL_TIMEOUT {
+        super::poll();
+        if let Some(mac) = lookup(ip) {
+            return Ok(mac);
+        }
+        core::hint::spin_loop();
+    }
+
+    Err(NetError::HostUnreachable)
+}
+
+/// Handle a received ARP packet: learn the sender and answer requests for us
+pub fn handle_packet(packet: &[u8]) {
+    if packet.len() < ARP_PACKET_LEN {
+        return;
+    }
+
+    let htype = u16::from_be_bytes([packet[0], packet[1]]);
+    let ptype = u16::from_be_bytes([packet[2], packet[3]]);
+    if htype != ARP_HTYPE_ETHERNET || ptype != ETHERTYPE_IPV4 || packet[4] != 6 || packet[5] != 4 {
+        return;
+    }
+
+    let op = u16::from_be_bytes([packet[6], packet[7]]);
+    let mut sender_mac = [0u8; 6];
+    sender_mac.copy_from_slice(&packet[8..14]);
+    let sender_ip = Ipv4Addr([packet[14], packet[15], packet[16], packet[17]]);
+    let target_ip = Ipv4Addr([packet[24], packet[25], packet[26], packet[27]]);
+
+    let config = match super::interface() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+
+    if sender_ip != Ipv4Addr::UNSPECIFIED {
+        insert(sender_ip, sender_mac);
+    }
+
+    if op == ARP_OP_REQUEST && target_ip == config.ip {
+        let _ = send(ARP_OP_REPLY, sender_mac, sender_ip, sender_mac);
+    }
+}
+
+/// Forget every cached neighbour
+pub fn clear() {
+    ARP_CACHE.lock().clear();
+}
+
+/// Snapshot of the ARP cache
+pub fn entries() -> heapless::Vec<(Ipv4Addr, [u8; 6]), ARP_CACHE_SIZE> {
+    ARP_CACHE.lock().iter().map(|(ip, mac)| (*ip, *mac)).collect()
+}
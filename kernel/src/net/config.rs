@@ -0,0 +1,161 @@
+//! Interface address configuration: QEMU defaults, static addresses or DHCP
+
+use core::fmt::Write;
+use heapless::String;
+use spin::Mutex;
+use elinos_common::{console_println, ok_println, warn_println};
+use super::{arp, dhcp, Ipv4Addr, InterfaceConfig, MacAddr, NetError, NetResult};
+use super::dhcp::Lease;
+use crate::filesystem::{configstore, FilesystemResult};
+
+// Config store keys: net.mode is "static" or "dhcp"; the addresses are
+// only kept for static
+const KEY_MODE: &str = "net.mode";
+const KEY_IP: &str = "net.ip";
+const KEY_NETMASK: &str = "net.netmask";
+const KEY_GATEWAY: &str = "net.gateway";
+
+/// How the interface got its addresses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddressSource {
+    /// QEMU user networking defaults, set at boot
+    Default,
+    Static,
+    Dhcp(Lease),
+}
+
+static SOURCE: Mutex<AddressSource> = Mutex::new(AddressSource::Default);
+
+/// How the current addresses were configured
+pub fn source() -> AddressSource {
+    *SOURCE.lock()
+}
+
+/// Use a fixed address
+pub fn set_static(ip: Ipv4Addr, netmask: Ipv4Addr, gateway: Ipv4Addr) -> NetResult<()> {
+    let current = super::interface()?;
+    apply(InterfaceConfig { mac: current.mac, ip, netmask, gateway }, AddressSource::Static);
+    Ok(())
+}
+
+/// Ask a DHCP server for an address. The previous configuration is kept if
+/// negotiation fails.
+pub fn run_dhcp() -> NetResult<Lease> {
+    let previous = super::interface()?;
+
+    super::set_interface(dhcp::unconfigured(previous.mac));
+    match dhcp::request_lease(previous.mac) {
+        Ok(lease) => {
+            let config = InterfaceConfig {
+                mac: previous.mac,
+                ip: lease.ip,
+                netmask: lease.netmask,
+                gateway: lease.gateway,
+            };
+            apply(config, AddressSource::Dhcp(lease));
+            Ok(lease)
+        }
+        Err(e) => {
+            super::set_interface(previous);
+            Err(e)
+        }
+    }
+}
+
+/// Configure the interface as saved in the config store. Without saved
+/// settings the QEMU defaults stay.
+pub fn apply_stored() {
+    if !super::is_up() {
+        return;
+    }
+    match configstore::get(KEY_MODE).as_deref() {
+        None => {}
+        Some("static") => {
+            let address = |key| configstore::get(key).and_then(|value| Ipv4Addr::parse(&value));
+            match (address(KEY_IP), address(KEY_NETMASK), address(KEY_GATEWAY)) {
+                (Some(ip), Some(netmask), Some(gateway)) => {
+                    if set_static(ip, netmask, gateway).is_ok() {
+                        ok_println!(target: "net", "Network: static address {} from the config store", ip);
+                    }
+                }
+                _ => warn_println!(target: "net", "Config store: incomplete static network settings, keeping defaults"),
+            }
+        }
+        Some("dhcp") => match run_dhcp() {
+            Ok(lease) => ok_println!(target: "net", "Network: leased {} with DHCP", lease.ip),
+            Err(e) => warn_println!(target: "net", "DHCP failed: {}, keeping defaults", e),
+        },
+        Some(mode) => warn_println!(target: "net", "Config store: unknown net.mode '{}'", mode),
+    }
+}
+
+/// Save the current configuration to the config store for the next boot,
+/// in one transaction
+pub fn save() -> FilesystemResult<()> {
+    let (mode, addresses) = match source() {
+        AddressSource::Default => (None, None),
+        AddressSource::Static => (Some("static"), super::interface().ok()),
+        AddressSource::Dhcp(_) => (Some("dhcp"), None),
+    };
+    let ip = addresses.map(|config| address_text(config.ip));
+    let netmask = addresses.map(|config| address_text(config.netmask));
+    let gateway = addresses.map(|config| address_text(config.gateway));
+    configstore::update(&[
+        (KEY_MODE, mode),
+        (KEY_IP, ip.as_deref()),
+        (KEY_NETMASK, netmask.as_deref()),
+        (KEY_GATEWAY, gateway.as_deref()),
+    ])
+}
+
+fn address_text(addr: Ipv4Addr) -> String<15> {
+    let mut text = String::new();
+    let _ = write!(text, "{}", addr);
+    text
+}
+
+fn apply(config: InterfaceConfig, source: AddressSource) {
+    super::set_interface(config);
+    *SOURCE.lock() = source;
+    // Neighbours learned on the old subnet may no longer be reachable
+    arp::clear();
+}
+
+/// Print the interface configuration and neighbour cache
+pub fn show() {
+    super::show_status();
+    if !super::is_up() {
+        return;
+    }
+
+    match source() {
+        AddressSource::Default => console_println!("  Configured: QEMU defaults"),
+        AddressSource::Static => console_println!("  Configured: static"),
+        AddressSource::Dhcp(lease) => {
+            console_println!("  Configured: DHCP from {}, lease {} s", lease.server, lease.lease_secs);
+            if let Some(dns) = lease.dns {
+                console_println!("  DNS: {}", dns);
+            }
+        }
+    }
+
+    let entries = arp::entries();
+    if !entries.is_empty() {
+        console_println!("  ARP cache:");
+        for (ip, mac) in entries.iter() {
+            console_println!("    {:<15} {}", ip, MacAddr(*mac));
+        }
+    }
+}
+
+/// Parse a netmask given as dotted quad or prefix length ("24")
+pub fn parse_netmask(s: &str) -> NetResult<Ipv4Addr> {
+    if let Some(mask) = Ipv4Addr::parse(s) {
+        return Ok(mask);
+    }
+    match s.trim_start_matches('/').parse::<u32>() {
+        Ok(0) => Ok(Ipv4Addr::UNSPECIFIED),
+        Ok(bits) if bits <= 32 => Ok(Ipv4Addr::from_u32(u32::MAX << (32 - bits))),
+        _ => Err(NetError::InvalidAddress),
+    }
+}
@@ -0,0 +1,217 @@
+//! DHCP client (RFC 2131): DISCOVER, OFFER, REQUEST, ACK
+
+use heapless::Vec;
+use super::{udp, Ipv4Addr, InterfaceConfig, NetError, NetResult};
+
+const DHCP_SERVER_PORT: u16 = 67;
+const DHCP_CLIENT_PORT: u16 = 68;
+
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+/// Ask the server to broadcast its replies: we cannot receive unicast
+/// before we have an address
+const FLAG_BROADCAST: u16 = 0x8000;
+
+/// Fixed part of a message, up to and including the magic cookie
+const FIXED_LEN: usize = 240;
+/// BOOTP relays expect at least this much
+const MIN_MESSAGE_LEN: usize = 300;
+const MAX_MESSAGE_LEN: usize = 576;
+
+// Message types (option 53)
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+// Options
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMETER_LIST: u8 = 55;
+const OPT_END: u8 = 255;
+
+/// Attempts at each step before giving up
+const RETRIES: usize = 3;
+
+/// Addresses handed out by a DHCP server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lease {
+    pub ip: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub dns: Option<Ipv4Addr>,
+    pub server: Ipv4Addr,
+    pub lease_secs: u32,
+}
+
+/// The parts of a server reply we use
+struct Reply {
+    message_type: u8,
+    yiaddr: Ipv4Addr,
+    netmask: Option<Ipv4Addr>,
+    router: Option<Ipv4Addr>,
+    dns: Option<Ipv4Addr>,
+    server_id: Option<Ipv4Addr>,
+    lease_secs: u32,
+}
+
+/// Obtain a lease for the interface with MAC address `mac`.
+/// The interface must already be configured with an unspecified address,
+/// so replies sent to the broadcast address reach us.
+pub fn request_lease(mac: [u8; 6]) -> NetResult<Lease> {
+    let fd = crate::syscall::file::allocate_fd();
+    udp::open(fd)?;
+    let result = udp::bind(fd, DHCP_CLIENT_PORT).and_then(|_| negotiate(fd, mac));
+    udp::close(fd);
+    result
+}
+
+fn negotiate(fd: i32, mac: [u8; 6]) -> NetResult<Lease> {
+    let xid = u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]]) ^ 0x454c_4e53;
+
+    let mut discover = new_message(xid, mac, DHCPDISCOVER);
+    push_option(&mut discover, OPT_PARAMETER_LIST, &[OPT_SUBNET_MASK, OPT_ROUTER, OPT_DNS]);
+    let offer = exchange(fd, xid, &mut discover, DHCPOFFER)?;
+    let server = offer.server_id.ok_or(NetError::Declined)?;
+
+    let mut request = new_message(xid, mac, DHCPREQUEST);
+    push_option(&mut request, OPT_REQUESTED_IP, &offer.yiaddr.0);
+    push_option(&mut request, OPT_SERVER_ID, &server.0);
+    push_option(&mut request, OPT_PARAMETER_LIST, &[OPT_SUBNET_MASK, OPT_ROUTER, OPT_DNS]);
+    let ack = exchange(fd, xid, &mut request, DHCPACK)?;
+
+    Ok(Lease {
+        ip: ack.yiaddr,
+        netmask: ack.netmask.or(offer.netmask).unwrap_or(Ipv4Addr::new(255, 255, 255, 0)),
+        gateway: ack.router.or(offer.router).unwrap_or(server),
+        dns: ack.dns.or(offer.dns),
+        server,
+        lease_secs: ack.lease_secs,
+    })
+}
+
+/// Broadcast `message` and wait for a reply of type `expected`, resending on timeout
+fn exchange(fd: i32, xid: u32, message: &mut Vec<u8, MAX_MESSAGE_LEN>, expected: u8) -> NetResult<Reply> {
+    finish_message(message);
+    let mut buf = [0u8; MAX_MESSAGE_LEN];
+
+    for _ in 0..RETRIES {
+        udp::send_datagram(DHCP_CLIENT_PORT, Ipv4Addr::BROADCAST, DHCP_SERVER_PORT, message)?;
+
+        // Skip replies meant for other clients until the socket runs dry
+        loop {
+            let len = match udp::recv_from(fd, &mut buf, false) {
+                Ok((len, _, _)) => len,
+                Err(NetError::WouldBlock) => break,
+                Err(e) => return Err(e),
+            };
+            match parse_reply(&buf[..len], xid) {
+                Some(reply) if reply.message_type == expected => return Ok(reply),
+                Some(reply) if reply.message_type == DHCPNAK => return Err(NetError::Declined),
+                _ => {}
+            }
+        }
+    }
+    Err(NetError::Timeout)
+}
+
+/// Start a client message of the given type
+fn new_message(xid: u32, mac: [u8; 6], message_type: u8) -> Vec<u8, MAX_MESSAGE_LEN> {
+    let mut message = Vec::new();
+    let _ = message.resize(FIXED_LEN, 0);
+    message[0] = BOOTREQUEST;
+    message[1] = 1; // Ethernet
+    message[2] = 6; // hardware address length
+    message[4..8].copy_from_slice(&xid.to_be_bytes());
+    message[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+    message[28..34].copy_from_slice(&mac);
+    message[236..240].copy_from_slice(&MAGIC_COOKIE);
+    push_option(&mut message, OPT_MESSAGE_TYPE, &[message_type]);
+    message
+}
+
+fn push_option(message: &mut Vec<u8, MAX_MESSAGE_LEN>, code: u8, data: &[u8]) {
+    let _ = message.push(code);
+    let _ = message.push(data.len() as u8);
+    let _ = message.extend_from_slice(data);
+}
+
+/// Terminate the options and pad to the minimum BOOTP size
+fn finish_message(message: &mut Vec<u8, MAX_MESSAGE_LEN>) {
+    if message.last() != Some(&OPT_END) {
+        let _ = message.push(OPT_END);
+    }
+    if message.len() < MIN_MESSAGE_LEN {
+        let _ = message.resize(MIN_MESSAGE_LEN, 0);
+    }
+}
+
+/// Parse a server reply to our transaction `xid`
+fn parse_reply(data: &[u8], xid: u32) -> Option<Reply> {
+    if data.len() < FIXED_LEN || data[0] != BOOTREPLY || data[236..240] != MAGIC_COOKIE {
+        return None;
+    }
+    if u32::from_be_bytes([data[4], data[5], data[6], data[7]]) != xid {
+        return None;
+    }
+
+    let mut reply = Reply {
+        message_type: 0,
+        yiaddr: Ipv4Addr([data[16], data[17], data[18], data[19]]),
+        netmask: None,
+        router: None,
+        dns: None,
+        server_id: None,
+        lease_secs: 0,
+    };
+
+    let mut options = &data[FIXED_LEN..];
+    while let Some((&code, rest)) = options.split_first() {
+        match code {
+            OPT_PAD => {
+                options = rest;
+                continue;
+            }
+            OPT_END => break,
+            _ => {}
+        }
+        let (&len, rest) = rest.split_first()?;
+        let value = rest.get(..len as usize)?;
+        options = &rest[len as usize..];
+
+        let addr = || value.get(..4).map(|b| Ipv4Addr([b[0], b[1], b[2], b[3]]));
+        match code {
+            OPT_MESSAGE_TYPE => reply.message_type = *value.first()?,
+            OPT_SUBNET_MASK => reply.netmask = addr(),
+            OPT_ROUTER => reply.router = addr(),
+            OPT_DNS => reply.dns = addr(),
+            OPT_SERVER_ID => reply.server_id = addr(),
+            OPT_LEASE_TIME => {
+                if let Some(b) = value.get(..4) {
+                    reply.lease_secs = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(reply)
+}
+
+/// Interface configuration used while negotiating: no address yet
+pub fn unconfigured(mac: [u8; 6]) -> InterfaceConfig {
+    InterfaceConfig {
+        mac,
+        ip: Ipv4Addr::UNSPECIFIED,
+        netmask: Ipv4Addr::UNSPECIFIED,
+        gateway: Ipv4Addr::UNSPECIFIED,
+    }
+}
@@ -0,0 +1,94 @@
+//! ICMP echo (ping): answering requests and measuring round trips
+
+use spin::Mutex;
+use elinos_common::timer;
+use super::{Ipv4Addr, NetError, NetResult, IP_PROTO_ICMP};
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMP_HEADER_LEN: usize = 8;
+
+/// Identifier in our echo requests ("EL")
+const ECHO_IDENTIFIER: u16 = 0x454c;
+/// Payload bytes after the header, as in the usual 64-byte ping
+const ECHO_PAYLOAD_LEN: usize = 56;
+
+/// An echo reply to one of our requests
+#[derive(Debug, Clone, Copy)]
+struct EchoReply {
+    src: Ipv4Addr,
+    sequence: u16,
+    len: usize,
+}
+
+static LAST_REPLY: Mutex<Option<EchoReply>> = Mutex::new(None);
+
+/// Result of one successful echo exchange
+#[derive(Debug, Clone, Copy)]
+pub struct PingReply {
+    pub len: usize,
+    /// Round-trip time in microseconds
+    pub rtt_us: u64,
+}
+
+/// Send an echo request with sequence number `sequence` to `dst` and wait
+/// up to `timeout_ms` for the matching reply
+pub fn ping(dst: Ipv4Addr, sequence: u16, timeout_ms: u64) -> NetResult<PingReply> {
+    let mut message = [0u8; ICMP_HEADER_LEN + ECHO_PAYLOAD_LEN];
+    message[0] = ICMP_ECHO_REQUEST;
+    message[4..6].copy_from_slice(&ECHO_IDENTIFIER.to_be_bytes());
+    message[6..8].copy_from_slice(&sequence.to_be_bytes());
+    for (i, byte) in message[ICMP_HEADER_LEN..].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let sum = super::checksum(&message, 0);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    *LAST_REPLY.lock() = None;
+    // Resolving the next hop may take a while; time from the actual send
+    let start = timer::ticks();
+    super::send_ipv4(dst, IP_PROTO_ICMP, &message)?;
+
+    while timer::elapsed_ms(start) < timeout_ms {
+        super::poll();
+        if let Some(reply) = *LAST_REPLY.lock() {
+            if reply.src == dst && reply.sequence == sequence {
+                return Ok(PingReply { len: reply.len, rtt_us: timer::elapsed_us(start) });
+            }
+        }
+        core::hint::spin_loop();
+    }
+    Err(NetError::Timeout)
+}
+
+/// Handle a received ICMP message: answer echo requests, record echo replies
+pub fn handle_packet(src: Ipv4Addr, payload: &[u8]) {
+    if payload.len() < ICMP_HEADER_LEN || super::checksum(payload, 0) != 0 {
+        return;
+    }
+
+    match payload[0] {
+        ICMP_ECHO_REQUEST => {
+            let mut reply: heapless::Vec<u8, { super::MAX_IP_PAYLOAD }> = heapless::Vec::new();
+            if reply.extend_from_slice(payload).is_err() {
+                return;
+            }
+            reply[0] = ICMP_ECHO_REPLY;
+            reply[2..4].copy_from_slice(&[0, 0]);
+            let sum = super::checksum(&reply, 0);
+            reply[2..4].copy_from_slice(&sum.to_be_bytes());
+            let _ = super::send_ipv4(src, IP_PROTO_ICMP, &reply);
+        }
+        ICMP_ECHO_REPLY => {
+            let identifier = u16::from_be_bytes([payload[4], payload[5]]);
+            if identifier == ECHO_IDENTIFIER {
+                *LAST_REPLY.lock() = Some(EchoReply {
+                    src,
+                    sequence: u16::from_be_bytes([payload[6], payload[7]]),
+                    len: payload.len(),
+                });
+            }
+        }
+        _ => {}
+    }
+}
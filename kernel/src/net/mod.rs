@@ -0,0 +1,351 @@
+//! Minimal network stack for elinOS
+//!
+//! Ethernet, ARP, IPv4, ICMP echo and UDP on top of the VirtIO network driver. There are
+//! no interrupts: received frames are processed whenever something polls the
+//! stack (sending, waiting for ARP replies, or a blocking `recvfrom`).
+//!
+//! The interface defaults to the addresses QEMU's user networking hands out
+//! (10.0.2.15/24, gateway 10.0.2.2).
+
+pub mod arp;
+pub mod udp;
+pub mod icmp;
+pub mod dhcp;
+pub mod config;
+
+use core::fmt;
+use core::fmt::Write;
+use spin::Mutex;
+use heapless::Vec;
+use elinos_common::{ok_println, warn_println};
+use crate::virtio::VIRTIO_NET;
+use crate::virtio::net::MAX_FRAME_SIZE;
+use crate::health::{self, Event};
+
+// EtherTypes
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+// IP protocol numbers
+pub const IP_PROTO_ICMP: u8 = 1;
+pub const IP_PROTO_UDP: u8 = 17;
+
+pub const ETHERNET_HEADER_LEN: usize = 14;
+pub const IPV4_HEADER_LEN: usize = 20;
+const IPV4_DEFAULT_TTL: u8 = 64;
+
+/// Largest IPv4 payload that fits in one frame (no fragmentation)
+pub const MAX_IP_PAYLOAD: usize = MAX_FRAME_SIZE - ETHERNET_HEADER_LEN - IPV4_HEADER_LEN;
+
+/// Number of polls before giving up on a reply
+pub const POLL_TIMEOUT: usize = 2_000_000;
+
+pub const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+
+/// IPv4 address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0, 0, 0, 0]);
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([255, 255, 255, 255]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Ipv4Addr([a, b, c, d])
+    }
+
+    pub fn to_u32(&self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        Ipv4Addr(value.to_be_bytes())
+    }
+
+    /// Parse dotted-quad notation
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut octets = [0u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Ipv4Addr(octets))
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Padded, so addresses line up in tables
+        let mut text = heapless::String::<15>::new();
+        write!(text, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])?;
+        f.pad(&text)
+    }
+}
+
+/// Ethernet MAC address, for display
+pub struct MacAddr(pub [u8; 6]);
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = &self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5])
+    }
+}
+
+/// Network stack errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetError {
+    NoDevice,
+    NotConfigured,
+    HostUnreachable,
+    PacketTooLarge,
+    DeviceError,
+    NoSocket,
+    TooManySockets,
+    AddressInUse,
+    NotConnected,
+    WouldBlock,
+    Timeout,
+    Declined,
+    InvalidAddress,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::NoDevice => write!(f, "No network device"),
+            NetError::NotConfigured => write!(f, "Interface not configured"),
+            NetError::HostUnreachable => write!(f, "Host unreachable"),
+            NetError::PacketTooLarge => write!(f, "Packet too large"),
+            NetError::DeviceError => write!(f, "Network device error"),
+            NetError::NoSocket => write!(f, "No such socket"),
+            NetError::TooManySockets => write!(f, "Too many sockets"),
+            NetError::AddressInUse => write!(f, "Address in use"),
+            NetError::NotConnected => write!(f, "Socket not connected"),
+            NetError::WouldBlock => write!(f, "No data available"),
+            NetError::Timeout => write!(f, "Timed out"),
+            NetError::Declined => write!(f, "Request declined by server"),
+            NetError::InvalidAddress => write!(f, "Invalid address"),
+        }
+    }
+}
+
+pub type NetResult<T> = Result<T, NetError>;
+
+/// Addresses of the (single) network interface
+#[derive(Debug, Clone, Copy)]
+pub struct InterfaceConfig {
+    pub mac: [u8; 6],
+    pub ip: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+}
+
+impl InterfaceConfig {
+    /// QEMU user networking defaults
+    pub const fn qemu_default(mac: [u8; 6]) -> Self {
+        InterfaceConfig {
+            mac,
+            ip: Ipv4Addr::new(10, 0, 2, 15),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            gateway: Ipv4Addr::new(10, 0, 2, 2),
+        }
+    }
+
+    /// Whether `addr` is on the local subnet
+    pub fn is_local(&self, addr: Ipv4Addr) -> bool {
+        let mask = self.netmask.to_u32();
+        addr.to_u32() & mask == self.ip.to_u32() & mask
+    }
+}
+
+static INTERFACE: Mutex<Option<InterfaceConfig>> = Mutex::new(None);
+
+/// Bring up the network stack on the VirtIO network device
+pub fn init() -> NetResult<()> {
+    let mac = {
+        let dev = VIRTIO_NET.lock();
+        if !dev.is_initialized() {
+            return Err(NetError::NoDevice);
+        }
+        dev.mac_address()
+    };
+
+    let config = InterfaceConfig::qemu_default(mac);
+    set_interface(config);
+    ok_println!(target: "net", "Network interface up: {} ({})", config.ip, MacAddr(config.mac));
+    Ok(())
+}
+
+/// Replace the interface configuration (see `config` for the public API)
+fn set_interface(config: InterfaceConfig) {
+    *INTERFACE.lock() = Some(config);
+}
+
+/// Current interface configuration
+pub fn interface() -> NetResult<InterfaceConfig> {
+    INTERFACE.lock().ok_or(NetError::NotConfigured)
+}
+
+/// Whether the stack is up
+pub fn is_up() -> bool {
+    INTERFACE.lock().is_some()
+}
+
+/// Internet checksum over `data`, continuing from `initial` (a partial sum)
+pub fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    let mut chunks = data.chunks_exact(2);
+    for pair in &mut chunks {
+        sum += u16::from_be_bytes([pair[0], pair[1]]) as u32;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u32) << 8;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Send an Ethernet frame carrying `payload`
+pub fn send_ethernet(dst_mac: [u8; 6], ethertype: u16, payload: &[u8]) -> NetResult<()> {
+    let config = interface()?;
+    if payload.len() > MAX_FRAME_SIZE - ETHERNET_HEADER_LEN {
+        return Err(NetError::PacketTooLarge);
+    }
+
+    let mut frame: Vec<u8, MAX_FRAME_SIZE> = Vec::new();
+    let _ = frame.extend_from_slice(&dst_mac);
+    let _ = frame.extend_from_slice(&config.mac);
+    let _ = frame.extend_from_slice(&ethertype.to_be_bytes());
+    let _ = frame.extend_from_slice(payload);
+
+    VIRTIO_NET.lock().transmit(&frame).map_err(|_| {
+        health::count(Event::TransmitError);
+        NetError::DeviceError
+    })
+}
+
+/// Send an IPv4 packet, resolving the next hop with ARP
+pub fn send_ipv4(dst: Ipv4Addr, protocol: u8, payload: &[u8]) -> NetResult<()> {
+    let config = interface()?;
+    if payload.len() > MAX_IP_PAYLOAD {
+        return Err(NetError::PacketTooLarge);
+    }
+
+    let dst_mac = if dst == Ipv4Addr::BROADCAST {
+        BROADCAST_MAC
+    } else if config.is_local(dst) {
+        arp::resolve(dst)?
+    } else {
+        arp::resolve(config.gateway)?
+    };
+
+    let total_len = (IPV4_HEADER_LEN + payload.len()) as u16;
+    let mut packet: Vec<u8, { MAX_FRAME_SIZE - ETHERNET_HEADER_LEN }> = Vec::new();
+    let _ = packet.extend_from_slice(&[0x45, 0]);               // version 4, IHL 5, DSCP 0
+    let _ = packet.extend_from_slice(&total_len.to_be_bytes());
+    let _ = packet.extend_from_slice(&[0, 0, 0x40, 0]);         // id 0, don't fragment
+    let _ = packet.extend_from_slice(&[IPV4_DEFAULT_TTL, protocol, 0, 0]);
+    let _ = packet.extend_from_slice(&config.ip.0);
+    let _ = packet.extend_from_slice(&dst.0);
+    let sum = checksum(&packet[..IPV4_HEADER_LEN], 0);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    let _ = packet.extend_from_slice(payload);
+
+    send_ethernet(dst_mac, ETHERTYPE_IPV4, &packet)
+}
+
+/// Process every frame the device has received
+pub fn poll() {
+    if !is_up() {
+        return;
+    }
+
+    let mut frame = [0u8; MAX_FRAME_SIZE];
+    loop {
+        let len = match VIRTIO_NET.lock().receive(&mut frame) {
+            Some(len) => len,
+            None => break,
+        };
+        handle_frame(&frame[..len]);
+    }
+}
+
+/// Dispatch one received Ethernet frame
+fn handle_frame(frame: &[u8]) {
+    if frame.len() < ETHERNET_HEADER_LEN {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    let payload = &frame[ETHERNET_HEADER_LEN..];
+
+    match ethertype {
+        ETHERTYPE_ARP => arp::handle_packet(payload),
+        ETHERTYPE_IPV4 => handle_ipv4(payload),
+        _ => {}
+    }
+}
+
+/// Validate an IPv4 packet addressed to us and pass its payload on
+fn handle_ipv4(packet: &[u8]) {
+    if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    let header_len = ((packet[0] & 0x0f) as usize) * 4;
+    let total_len = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        health::count(Event::PacketDropped);
+        return;
+    }
+    if checksum(&packet[..header_len], 0) != 0 {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    // Fragments are not reassembled
+    let flags_offset = u16::from_be_bytes([packet[6], packet[7]]);
+    if flags_offset & 0x3fff != 0 {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    let config = match interface() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    let dst = Ipv4Addr([packet[16], packet[17], packet[18], packet[19]]);
+    if dst != config.ip && dst != Ipv4Addr::BROADCAST {
+        return;
+    }
+
+    let src = Ipv4Addr([packet[12], packet[13], packet[14], packet[15]]);
+    let protocol = packet[9];
+    let payload = &packet[header_len..total_len];
+
+    match protocol {
+        IP_PROTO_ICMP => icmp::handle_packet(src, payload),
+        IP_PROTO_UDP => udp::handle_packet(src, dst, payload),
+        _ => {}
+    }
+}
+
+/// Print interface status
+pub fn show_status() {
+    match interface() {
+        Ok(config) => {
+            elinos_common::console_println!("  Address: {} netmask {} gateway {}", config.ip, config.netmask, config.gateway);
+            elinos_common::console_println!("  MAC: {}", MacAddr(config.mac));
+            VIRTIO_NET.lock().show_info();
+        }
+        Err(_) => warn_println!(target: "net", "Network is down"),
+    }
+}
@@ -0,0 +1,218 @@
+//! UDP datagrams and sockets
+
+use spin::Mutex;
+use heapless::{Vec, Deque};
+use crate::health::{self, Event};
+use super::{Ipv4Addr, NetError, NetResult, IP_PROTO_UDP, MAX_IP_PAYLOAD, POLL_TIMEOUT};
+
+pub const UDP_HEADER_LEN: usize = 8;
+
+/// Largest UDP payload that fits in one frame
+pub const MAX_DATAGRAM: usize = MAX_IP_PAYLOAD - UDP_HEADER_LEN;
+
+/// Maximum number of open UDP sockets
+const MAX_SOCKETS: usize = 8;
+
+/// Datagrams queued per socket before new ones are dropped
+const SOCKET_QUEUE_DEPTH: usize = 4;
+
+/// First port handed out to sockets that send without binding
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// A received datagram waiting in a socket queue
+pub struct Datagram {
+    pub src: Ipv4Addr,
+    pub src_port: u16,
+    pub data: Vec<u8, MAX_DATAGRAM>,
+}
+
+/// An open UDP socket, identified by its file descriptor
+struct UdpSocket {
+    fd: i32,
+    local_port: u16, // 0 while unbound
+    remote: Option<(Ipv4Addr, u16)>,
+    queue: Deque<Datagram, SOCKET_QUEUE_DEPTH>,
+}
+
+static SOCKETS: Mutex<Vec<UdpSocket, MAX_SOCKETS>> = Mutex::new(Vec::new());
+static NEXT_EPHEMERAL_PORT: Mutex<u16> = Mutex::new(EPHEMERAL_PORT_START);
+
+/// Whether `fd` is an open UDP socket
+pub fn is_socket(fd: i32) -> bool {
+    SOCKETS.lock().iter().any(|s| s.fd == fd)
+}
+
+/// Whether socket `fd` has a datagram waiting
+pub fn readable(fd: i32) -> bool {
+    SOCKETS.lock().iter().any(|s| s.fd == fd && !s.queue.is_empty())
+}
+
+/// Create an unbound socket for descriptor `fd`
+pub fn open(fd: i32) -> NetResult<()> {
+    SOCKETS.lock().push(UdpSocket {
+        fd,
+        local_port: 0,
+        remote: None,
+        queue: Deque::new(),
+    }).map_err(|_| NetError::TooManySockets)
+}
+
+/// Close the socket for `fd`. Returns false if there is none.
+pub fn close(fd: i32) -> bool {
+    let mut sockets = SOCKETS.lock();
+    match sockets.iter().position(|s| s.fd == fd) {
+        Some(index) => {
+            sockets.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Pick an unused ephemeral port
+fn ephemeral_port(sockets: &[UdpSocket]) -> NetResult<u16> {
+    let mut next = NEXT_EPHEMERAL_PORT.lock();
+    for _ in 0..(u16::MAX - EPHEMERAL_PORT_START) {
+        let port = *next;
+        *next = if port == u16::MAX { EPHEMERAL_PORT_START } else { port + 1 };
+        if !sockets.iter().any(|s| s.local_port == port) {
+            return Ok(port);
+        }
+    }
+    Err(NetError::AddressInUse)
+}
+
+/// Bind the socket to a local port (0 picks an ephemeral port)
+pub fn bind(fd: i32, port: u16) -> NetResult<u16> {
+    let mut sockets = SOCKETS.lock();
+    if port != 0 && sockets.iter().any(|s| s.local_port == port && s.fd != fd) {
+        return Err(NetError::AddressInUse);
+    }
+    let port = if port == 0 { ephemeral_port(&sockets)? } else { port };
+
+    let socket = sockets.iter_mut().find(|s| s.fd == fd).ok_or(NetError::NoSocket)?;
+    socket.local_port = port;
+    Ok(port)
+}
+
+/// Set the default destination, and only accept datagrams from it
+pub fn connect(fd: i32, addr: Ipv4Addr, port: u16) -> NetResult<()> {
+    let mut sockets = SOCKETS.lock();
+    let socket = sockets.iter_mut().find(|s| s.fd == fd).ok_or(NetError::NoSocket)?;
+    socket.remote = Some((addr, port));
+    Ok(())
+}
+
+/// Send a datagram from socket `fd` to `dest`, or to its connected peer
+pub fn send_to(fd: i32, dest: Option<(Ipv4Addr, u16)>, data: &[u8]) -> NetResult<usize> {
+    let (src_port, (dst, dst_port)) = {
+        let mut sockets = SOCKETS.lock();
+        let index = sockets.iter().position(|s| s.fd == fd).ok_or(NetError::NoSocket)?;
+        if sockets[index].local_port == 0 {
+            let port = ephemeral_port(&sockets)?;
+            sockets[index].local_port = port;
+        }
+        let socket = &sockets[index];
+        (socket.local_port, dest.or(socket.remote).ok_or(NetError::NotConnected)?)
+    };
+
+    // The socket table stays unlocked while ARP polls the network
+    send_datagram(src_port, dst, dst_port, data)?;
+    Ok(data.len())
+}
+
+/// Receive a datagram on socket `fd`. Waits for one unless `nonblocking`.
+/// Returns the bytes copied and the sender; excess data is discarded.
+pub fn recv_from(fd: i32, buf: &mut [u8], nonblocking: bool) -> NetResult<(usize, Ipv4Addr, u16)> {
+    let mut polls = 0;
+    loop {
+        {
+            let mut sockets = SOCKETS.lock();
+            let socket = sockets.iter_mut().find(|s| s.fd == fd).ok_or(NetError::NoSocket)?;
+            if let Some(datagram) = socket.queue.pop_front() {
+                let len = datagram.data.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram.data[..len]);
+                return Ok((len, datagram.src, datagram.src_port));
+            }
+        }
+
+        if nonblocking || polls >= POLL_TIMEOUT {
+            return Err(NetError::WouldBlock);
+        }
+        super::poll();
+        polls += 1;
+        core::hint::spin_loop();
+    }
+}
+
+/// Checksum over the IPv4 pseudo-header and the UDP segment
+fn udp_checksum(src: Ipv4Addr, dst: Ipv4Addr, segment: &[u8]) -> u16 {
+    let pseudo = (src.to_u32() >> 16) + (src.to_u32() & 0xffff)
+        + (dst.to_u32() >> 16) + (dst.to_u32() & 0xffff)
+        + IP_PROTO_UDP as u32 + segment.len() as u32;
+    super::checksum(segment, pseudo)
+}
+
+/// Send one UDP datagram
+pub fn send_datagram(src_port: u16, dst: Ipv4Addr, dst_port: u16, data: &[u8]) -> NetResult<()> {
+    if data.len() > MAX_DATAGRAM {
+        return Err(NetError::PacketTooLarge);
+    }
+    let config = super::interface()?;
+
+    let length = (UDP_HEADER_LEN + data.len()) as u16;
+    let mut segment: Vec<u8, MAX_IP_PAYLOAD> = Vec::new();
+    let _ = segment.extend_from_slice(&src_port.to_be_bytes());
+    let _ = segment.extend_from_slice(&dst_port.to_be_bytes());
+    let _ = segment.extend_from_slice(&length.to_be_bytes());
+    let _ = segment.extend_from_slice(&[0, 0]);
+    let _ = segment.extend_from_slice(data);
+
+    let sum = match udp_checksum(config.ip, dst, &segment) {
+        0 => 0xffff, // 0 means "no checksum"
+        sum => sum,
+    };
+    segment[6..8].copy_from_slice(&sum.to_be_bytes());
+
+    super::send_ipv4(dst, IP_PROTO_UDP, &segment)
+}
+
+/// Queue a received UDP segment on the socket bound to its port
+pub fn handle_packet(src: Ipv4Addr, dst: Ipv4Addr, segment: &[u8]) {
+    if segment.len() < UDP_HEADER_LEN {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    let src_port = u16::from_be_bytes([segment[0], segment[1]]);
+    let dst_port = u16::from_be_bytes([segment[2], segment[3]]);
+    let length = u16::from_be_bytes([segment[4], segment[5]]) as usize;
+    let sum = u16::from_be_bytes([segment[6], segment[7]]);
+    if length < UDP_HEADER_LEN || length > segment.len() {
+        health::count(Event::PacketDropped);
+        return;
+    }
+    let segment = &segment[..length];
+    if sum != 0 && udp_checksum(src, dst, segment) != 0 {
+        health::count(Event::PacketDropped);
+        return;
+    }
+
+    let mut sockets = SOCKETS.lock();
+    let socket = match sockets.iter_mut().find(|s| {
+        s.local_port == dst_port && s.remote.map_or(true, |r| r == (src, src_port))
+    }) {
+        Some(socket) => socket,
+        None => {
+            health::count(Event::PacketDropped);
+            return;
+        }
+    };
+
+    let mut data = Vec::new();
+    let _ = data.extend_from_slice(&segment[UDP_HEADER_LEN..]);
+    // A full queue drops the datagram, as UDP allows
+    if socket.queue.push_back(Datagram { src, src_port, data }).is_err() {
+        health::count(Event::PacketDropped);
+    }
+}
@@ -0,0 +1,61 @@
+//! Simple terminal pager for long console output
+//!
+//! Shows text one screen at a time with a `--More--` prompt:
+//!   Space - next page, Enter - next line, q - quit
+
+use elinos_common::{console_print, console_println};
+
+/// Default number of text rows on the terminal
+pub const DEFAULT_PAGE_ROWS: usize = 24;
+
+/// Pager key actions
+enum PagerAction {
+    NextPage,
+    NextLine,
+    Quit,
+}
+
+/// Display text through the pager
+pub fn page_text(text: &str) {
+    page_lines(text.lines());
+}
+
+/// Display a sequence of lines through the pager
+pub fn page_lines<'a, I: Iterator<Item = &'a str>>(lines: I) {
+    // Leave one row for the --More-- prompt
+    let page_size = DEFAULT_PAGE_ROWS - 1;
+    let mut remaining = page_size;
+    let mut lines = lines.peekable();
+    
+    while let Some(line) = lines.next() {
+        console_println!("{}", line);
+        remaining -= 1;
+        
+        if remaining == 0 && lines.peek().is_some() {
+            match prompt_more() {
+                PagerAction::NextPage => remaining = page_size,
+                PagerAction::NextLine => remaining = 1,
+                PagerAction::Quit => return,
+            }
+        }
+    }
+}
+
+/// Show the --More-- prompt and wait for a key
+fn prompt_more() -> PagerAction {
+    console_print!("--More--");
+    
+    let action = loop {
+        let ch = crate::UART.lock().getc();
+        match ch {
+            b' ' => break PagerAction::NextPage,
+            b'\r' | b'\n' => break PagerAction::NextLine,
+            b'q' | b'Q' | b'\x03' => break PagerAction::Quit,
+            _ => {}
+        }
+    };
+    
+    // Erase the prompt
+    console_print!("\r        \r");
+    action
+}
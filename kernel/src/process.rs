@@ -0,0 +1,337 @@
+//! Process Management for elinOS
+//!
+//! Process table, PID allocation and exit status collection. User programs
+//! run one at a time: the shell (PID 1) spawns a child, runs it until it
+//! exits and then reaps its exit status. A program that forks is suspended
+//! in the syscall while its child runs. Programs started with `&` stay in
+//! the table while the shell gives them slices of time (see `jobs`).
+
+use heapless::{String, Vec};
+use spin::Mutex;
+use lazy_static::lazy_static;
+use elinos_common::console_println;
+use crate::elf::{ElfError, LoadedElf};
+use crate::memory::mmu::{self, Vma, MAX_VMAS};
+use crate::memory::slab::{self, ListNode, SlabCache, SlabList};
+use crate::filesystem::path::PathBuf;
+use crate::trap::TrapContext;
+
+/// PID of the init process (the kernel shell)
+pub const INIT_PID: i32 = 1;
+
+/// Exit status reported for a program killed by a fatal exception
+pub const FAULT_EXIT_CODE: i32 = 139; // 128 + SIGSEGV, like a Unix shell
+
+/// Exit status reported for a program ended with `kill`
+pub const KILLED_EXIT_CODE: i32 = 137; // 128 + SIGKILL
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessState {
+    Running,
+    Waiting,
+    Zombie,   // Exited but parent hasn't collected exit status
+    Unused,
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+    pub ppid: i32,  // Parent process ID
+    pub state: ProcessState,
+    pub exit_code: Option<i32>,
+    pub memory_base: Option<usize>,  // Base address of process memory
+    pub memory_size: Option<usize>,  // Size of allocated memory
+    pub name: String<64>,
+    pub entry_point: usize,
+    pub satp: u64,  // Address space root (0 = shares the kernel's)
+    pub heap_start: usize,  // Where the heap begins (0 = no heap)
+    pub brk: usize,         // Current program break
+    pub cwd: PathBuf,       // Working directory, for relative paths
+}
+
+impl Process {
+    pub fn new() -> Self {
+        Self {
+            pid: 0,
+            ppid: 0,
+            state: ProcessState::Unused,
+            exit_code: None,
+            memory_base: None,
+            memory_size: None,
+            name: String::new(),
+            entry_point: 0,
+            satp: 0,
+            heap_start: 0,
+            brk: 0,
+            cwd: PathBuf::try_from("/").unwrap_or_default(),
+        }
+    }
+    
+    pub fn new_with_pid(pid: i32, ppid: i32) -> Self {
+        Self {
+            pid,
+            ppid,
+            state: ProcessState::Running,
+            exit_code: None,
+            memory_base: None,
+            memory_size: None,
+            name: String::new(),
+            entry_point: 0,
+            satp: 0,
+            heap_start: 0,
+            brk: 0,
+            cwd: PathBuf::try_from("/").unwrap_or_default(),
+        }
+    }
+}
+
+/// Result of a wait on child processes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaitStatus {
+    /// A child exited and was reaped: (pid, exit code)
+    Exited(i32, i32),
+    /// Matching children exist but none has exited yet
+    StillRunning,
+    /// No matching child processes
+    NoChildren,
+}
+
+/// Process table entries, which grow with the table instead of capping it
+pub static PROCESS_CACHE: SlabCache = SlabCache::of::<ListNode<Process>>("process");
+
+pub struct ProcessManager {
+    processes: SlabList<Process>,
+    next_pid: i32,
+    current_pid: i32,
+}
+
+impl ProcessManager {
+    pub fn new() -> Self {
+        let mut pm = Self {
+            processes: SlabList::new(&PROCESS_CACHE),
+            next_pid: INIT_PID + 1,
+            current_pid: INIT_PID,  // Start with init process (shell)
+        };
+        
+        // Create init process (the shell)
+        let mut init_process = Process::new_with_pid(INIT_PID, 0);
+        let _ = init_process.name.push_str("shell");
+        pm.processes.push(init_process).ok();
+        
+        pm
+    }
+    
+    pub fn allocate_pid(&mut self) -> i32 {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        pid
+    }
+    
+    pub fn create_process(&mut self, ppid: i32) -> Option<i32> {
+        let pid = self.allocate_pid();
+        let mut process = Process::new_with_pid(pid, ppid);
+        // A child starts in its parent's working directory
+        if let Some(parent) = self.get_process(ppid) {
+            process.cwd = parent.cwd.clone();
+        }
+        
+        self.processes.push(process).ok()?;
+        Some(pid)
+    }
+    
+    /// Create a named child of the current process for a loaded program
+    pub fn spawn(&mut self, name: &str, entry_point: usize) -> Option<i32> {
+        let pid = self.create_process(self.current_pid)?;
+        if let Some(process) = self.get_process_mut(pid) {
+            for ch in name.chars() {
+                if process.name.push(ch).is_err() {
+                    break;
+                }
+            }
+            process.entry_point = entry_point;
+        }
+        Some(pid)
+    }
+    
+    pub fn get_process(&self, pid: i32) -> Option<&Process> {
+        self.processes.iter().find(|p| p.pid == pid)
+    }
+    
+    pub fn get_process_mut(&mut self, pid: i32) -> Option<&mut Process> {
+        self.processes.iter_mut().find(|p| p.pid == pid)
+    }
+    
+    pub fn exit_process(&mut self, pid: i32, exit_code: i32) {
+        if let Some(process) = self.get_process_mut(pid) {
+            if process.state == ProcessState::Zombie {
+                return; // Already exited, keep the first status
+            }
+            process.state = ProcessState::Zombie;
+            process.exit_code = Some(exit_code);
+        }
+        
+        // Nobody is left to wait for this process's exited children
+        self.processes.retain(|p| !(p.ppid == pid && p.state == ProcessState::Zombie));
+    }
+    
+    /// Reap an exited child of `parent_pid`. A `pid` of -1 matches any child.
+    pub fn wait_for_child(&mut self, parent_pid: i32, pid: i32) -> WaitStatus {
+        let mut found_child = false;
+        let mut exited = None;
+        
+        for process in &self.processes {
+            if process.ppid != parent_pid || (pid != -1 && process.pid != pid) {
+                continue;
+            }
+            
+            found_child = true;
+            if process.state == ProcessState::Zombie {
+                exited = Some((process.pid, process.exit_code.unwrap_or(-1)));
+                break;
+            }
+        }
+        
+        if let Some((child_pid, exit_code)) = exited {
+            // Remove the zombie process (reap it) so its entry is freed
+            self.processes.retain(|p| p.pid != child_pid);
+            WaitStatus::Exited(child_pid, exit_code)
+        } else if found_child {
+            WaitStatus::StillRunning
+        } else {
+            WaitStatus::NoChildren
+        }
+    }
+    
+    pub fn get_current_pid(&self) -> i32 {
+        self.current_pid
+    }
+    
+    pub fn set_current_pid(&mut self, pid: i32) {
+        self.current_pid = pid;
+    }
+    
+    pub fn processes(&self) -> slab::Iter<'_, Process> {
+        self.processes.iter()
+    }
+}
+
+// Global process manager
+lazy_static! {
+    pub static ref PROCESS_MANAGER: Mutex<ProcessManager> = Mutex::new(ProcessManager::new());
+}
+
+/// Working directory of the current process
+pub fn current_cwd() -> PathBuf {
+    let pm = PROCESS_MANAGER.lock();
+    pm.get_process(pm.get_current_pid()).map(|process| process.cwd.clone())
+        .unwrap_or_else(|| PathBuf::try_from("/").unwrap_or_default())
+}
+
+/// Change the working directory of the current process to `path`, which
+/// is absolute and normalized
+pub fn set_current_cwd(path: &str) {
+    let mut pm = PROCESS_MANAGER.lock();
+    let pid = pm.get_current_pid();
+    if let Some(process) = pm.get_process_mut(pid) {
+        process.cwd.clear();
+        let _ = process.cwd.push_str(path);
+    }
+}
+
+/// Create the process table, whose init entry comes from `PROCESS_CACHE`,
+/// once the memory manager is up
+pub fn init() {
+    lazy_static::initialize(&PROCESS_MANAGER);
+}
+
+/// Run a loaded program as a child of the current process and wait for it.
+/// Returns the child's PID and exit status.
+pub fn run_program(name: &str, loaded_elf: &LoadedElf) -> Result<(i32, i32), ElfError> {
+    let (parent_pid, child_pid) = {
+        let mut pm = PROCESS_MANAGER.lock();
+        let parent_pid = pm.get_current_pid();
+        let child_pid = pm.spawn(name, loaded_elf.entry_point as usize)
+            .ok_or(ElfError::ExecutionError)?;
+        pm.set_current_pid(child_pid);
+        (parent_pid, child_pid)
+    };
+    
+    let result = crate::elf::execute_elf(loaded_elf);
+    
+    let mut pm = PROCESS_MANAGER.lock();
+    pm.set_current_pid(parent_pid);
+    
+    match result {
+        Ok(exit_code) => {
+            // sys_exit normally records the status; cover programs that faulted
+            pm.exit_process(child_pid, exit_code);
+            match pm.wait_for_child(parent_pid, child_pid) {
+                WaitStatus::Exited(pid, status) => Ok((pid, status)),
+                _ => Ok((child_pid, exit_code)),
+            }
+        }
+        Err(err) => {
+            // The program never ran, drop its table entry
+            pm.exit_process(child_pid, -1);
+            let _ = pm.wait_for_child(parent_pid, child_pid);
+            Err(err)
+        }
+    }
+}
+
+/// Load a program as a child of the current process without running it.
+/// Returns the child's PID, its address space and the registers that
+/// start it, for `jobs` to run.
+pub fn start_program(name: &str, loaded_elf: &LoadedElf) -> Result<(i32, u64, TrapContext), ElfError> {
+    let (parent_pid, child_pid) = {
+        let mut pm = PROCESS_MANAGER.lock();
+        let parent_pid = pm.get_current_pid();
+        let child_pid = pm.spawn(name, loaded_elf.entry_point as usize)
+            .ok_or(ElfError::ExecutionError)?;
+        pm.set_current_pid(child_pid);
+        (parent_pid, child_pid)
+    };
+    
+    let result = crate::elf::prepare_background(loaded_elf);
+    
+    let mut pm = PROCESS_MANAGER.lock();
+    pm.set_current_pid(parent_pid);
+    match result {
+        Ok((satp, frame)) => Ok((child_pid, satp, frame)),
+        Err(err) => {
+            pm.exit_process(child_pid, -1);
+            let _ = pm.wait_for_child(parent_pid, child_pid);
+            Err(err)
+        }
+    }
+}
+
+/// The mappings of the address space of `pid`, or None if there is no such
+/// process or it runs in the kernel's address space
+pub fn process_vmas(pid: i32) -> Option<Vec<Vma, MAX_VMAS>> {
+    let (satp, heap) = {
+        let pm = PROCESS_MANAGER.lock();
+        let process = pm.get_process(pid)?;
+        (process.satp, (process.heap_start, process.brk))
+    };
+    if satp == 0 {
+        return None;
+    }
+    // The process table is released first; the MMU is never locked under it
+    mmu::user_vmas(satp, heap)
+}
+
+/// Print the process table
+pub fn show_processes() {
+    let pm = PROCESS_MANAGER.lock();
+    console_println!("  PID  PPID  STATE    NAME");
+    for process in pm.processes() {
+        let state = match process.state {
+            ProcessState::Running => "running",
+            ProcessState::Waiting => "waiting",
+            ProcessState::Zombie => "zombie",
+            ProcessState::Unused => "unused",
+        };
+        console_println!("  {:>3}  {:>4}  {:<7}  {}", process.pid, process.ppid, state, process.name);
+    }
+}
@@ -0,0 +1,218 @@
+//! Shell scripts: commands read from a file
+//!
+//! `run FILE` (or `source FILE`) reads a file a line at a time and runs
+//! each line as if it were typed at the prompt: `$VARIABLES` are expanded,
+//! and `&&` and `||` run the next command only if the one before succeeded
+//! or failed. Blank lines are skipped and `#` starts a comment, at the start
+//! of a line or after a space. Script lines do not go into the history, but
+//! `$?` follows them, and the script's status is that of the last command
+//! it ran.
+//!
+//! A script carries on after a failing command unless exit-on-error mode
+//! is on, with `run -e` or a `set -e` line (`set +e` turns it off). It then
+//! stops at the first command that fails, as `sh -e` does, except one
+//! tested by `&&` or `||`. `run -x` or a `set -x` line prints each line
+//! before it runs. `exit` ends the script, not the shell.
+//!
+//! Scripts may run other scripts, up to `MAX_DEPTH` deep. The variables
+//! they set are the shell's, as with `source` in other shells.
+//!
+//! At boot, once the filesystem and the shell's environment are loaded, the
+//! first of `RC_PATHS` that exists runs this way before the first prompt,
+//! to set log levels, mount more disks or start a program. It is skipped
+//! if Ctrl-C was typed during boot, so a script that hangs can be got past,
+//! and at every boot after `config set shell.rc off`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use heapless::Vec;
+use elinos_common::{console_println, info_println, warn_println};
+
+use crate::args;
+use crate::commands::STATUS_NOT_FOUND;
+use crate::filesystem::{self, configstore, path, FilesystemError};
+use crate::messages::Msg;
+
+/// Most scripts running inside one another
+const MAX_DEPTH: usize = 4;
+
+/// Startup scripts, in the order they are looked for; only the first found
+/// runs
+pub const RC_PATHS: &[&str] = &["/etc/rc", "/.elinrc"];
+
+/// Config store key that skips the startup script when it is `off`
+const RC_KEY: &str = "shell.rc";
+
+/// Bytes read from the script at a time
+const READ_SIZE: usize = 256;
+
+/// Scripts running now
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+const RUN_SPEC: args::CommandSpec = args::CommandSpec {
+    name: "run",
+    options: &[args::OptSpec::flag('e', "exit-on-error"), args::OptSpec::flag('x', "trace")],
+    min_positional: 1,
+    max_positional: 1,
+};
+
+const SOURCE_SPEC: args::CommandSpec = args::CommandSpec { name: "source", ..RUN_SPEC };
+
+/// Where a script goes after a line
+enum Step {
+    Next,
+    /// `exit` ended the script with this status
+    Exit(i32),
+    /// Exit-on-error mode stopped it at a command that failed with this status
+    Stopped(i32),
+}
+
+/// Modes a script turns on and off as it goes
+struct Options {
+    exit_on_error: bool,
+    trace: bool,
+}
+
+/// The `run` and `source` commands: run the script named in `args`.
+/// Returns the script's status with the result.
+pub fn run(command: &str, args: &str) -> (i32, Result<(), &'static str>) {
+    let spec = if command == "source" { &SOURCE_SPEC } else { &RUN_SPEC };
+    let parsed = match args::parse_command(spec, args) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => return (0, Ok(())),
+        Err(e) => return (1, Err(e)),
+    };
+    let Ok(path) = path::resolve(parsed.arg(0).unwrap_or("")) else {
+        return (1, Err("Path too long"));
+    };
+    let options = Options { exit_on_error: parsed.has("exit-on-error"), trace: parsed.has("trace") };
+    run_nested(&path, options)
+}
+
+/// Run the startup script, if there is one and it is not turned off
+pub fn run_startup() {
+    let Some(path) = RC_PATHS.iter().copied().find(|path| filesystem::file_exists(path)) else {
+        return;
+    };
+    if interrupted() {
+        info_println!("Startup script {} skipped (Ctrl-C)", path);
+        return;
+    }
+    if configstore::get(RC_KEY).as_deref() == Some("off") {
+        info_println!("Startup script {} skipped ({}=off)", path, RC_KEY);
+        return;
+    }
+    info_println!("Running startup script {}", path);
+    match run_nested(path, Options { exit_on_error: false, trace: false }) {
+        (_, Err(e)) => warn_println!("{}: {}", path, e),
+        (0, Ok(())) => {}
+        (status, Ok(())) => warn_println!("{} finished with status {}", path, status),
+    }
+}
+
+/// Whether Ctrl-C was typed during boot. Other keys typed before the
+/// prompt are dropped.
+fn interrupted() -> bool {
+    let mut interrupted = false;
+    while let Some(byte) = elinos_common::tty::read_byte() {
+        interrupted |= byte == b'\x03';
+    }
+    interrupted
+}
+
+/// Run the script at `path`, unless too many are running already
+fn run_nested(path: &str, options: Options) -> (i32, Result<(), &'static str>) {
+    if DEPTH.fetch_add(1, Ordering::Relaxed) >= MAX_DEPTH {
+        DEPTH.fetch_sub(1, Ordering::Relaxed);
+        return (1, Err("Scripts nested too deeply"));
+    }
+    let outcome = run_file(path, options);
+    DEPTH.fetch_sub(1, Ordering::Relaxed);
+    outcome
+}
+
+/// Run the lines of the script at `path` in turn
+fn run_file(path: &str, mut options: Options) -> (i32, Result<(), &'static str>) {
+    let mut buffer = [0u8; READ_SIZE];
+    let mut line: Vec<u8, { crate::MAX_COMMAND_LEN }> = Vec::new();
+    let mut overlong = false;
+    let (mut offset, mut number, mut status) = (0, 0, 0);
+
+    loop {
+        let read = match filesystem::read_file_at(path, offset, &mut buffer) {
+            Ok(read) => read,
+            Err(FilesystemError::FileNotFound) if offset == 0 => return (STATUS_NOT_FOUND, Err("Script not found")),
+            Err(_) => return (1, Err("Failed to read the script")),
+        };
+        offset += read;
+
+        // The last line need not end in a newline
+        let end = read == 0;
+        for &byte in buffer[..read].iter().chain(end.then_some(&b'\n')) {
+            if byte != b'\n' {
+                overlong |= line.push(byte).is_err();
+                continue;
+            }
+            number += 1;
+            if overlong {
+                console_println!("{}:{}: line too long", path, number);
+                return (1, Err("Script line too long"));
+            }
+            let Ok(text) = core::str::from_utf8(&line) else {
+                console_println!("{}:{}: not text", path, number);
+                return (1, Err("Invalid UTF-8 in script"));
+            };
+            match run_line(text, &mut options, &mut status) {
+                Step::Next => {}
+                Step::Exit(status) => return (status, Ok(())),
+                Step::Stopped(status) => {
+                    console_println!("{}:{}: stopped, exit status {}", path, number, status);
+                    return (status, Ok(()));
+                }
+            }
+            line.clear();
+        }
+        if end {
+            return (status, Ok(()));
+        }
+    }
+}
+
+/// Run one line of a script, keeping the status of what it ran in
+/// `status`
+fn run_line(text: &str, options: &mut Options, status: &mut i32) -> Step {
+    let text = strip_comment(text).trim();
+    match text {
+        "" => {}
+        "set -e" => options.exit_on_error = true,
+        "set +e" => options.exit_on_error = false,
+        "set -x" => options.trace = true,
+        "set +x" => options.trace = false,
+        _ => {
+            if options.trace {
+                console_println!("+ {}", text);
+            }
+            let outcome = crate::run_command_list(text);
+            *status = outcome.status;
+            match outcome.result {
+                Err("exit_shell") => return Step::Exit(outcome.status),
+                Err(e) => crate::msg_println!(Msg::CommandError, e),
+                Ok(()) => {}
+            }
+            if options.exit_on_error && outcome.status != 0 && !outcome.tested {
+                return Step::Stopped(outcome.status);
+            }
+        }
+    }
+    Step::Next
+}
+
+/// `text` up to a `#` at its start or after a space or tab
+fn strip_comment(text: &str) -> &str {
+    let bytes = text.as_bytes();
+    let start = (0..bytes.len())
+        .find(|&i| bytes[i] == b'#' && (i == 0 || bytes[i - 1] == b' ' || bytes[i - 1] == b'\t'));
+    match start {
+        Some(start) => &text[..start],
+        None => text,
+    }
+}
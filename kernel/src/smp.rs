@@ -0,0 +1,174 @@
+//! Secondary harts
+//!
+//! The firmware hands the kernel one hart, the boot hart; the others wait
+//! stopped in the SBI. `init` starts each of them through the SBI Hart
+//! State Management extension, on a stack of its own, and parks it: with
+//! only software interrupts enabled it sleeps in `wfi`, and an IPI wakes it
+//! just to go back to sleep. Nothing is scheduled on them yet, but a parked
+//! hart is in a known state instead of wherever a board's firmware left it.
+//!
+//! Device interrupts go to the boot hart's PLIC context, whichever hart
+//! that is; `version` and the boot log report how many harts there are.
+//!
+//! Each hart has a `HartData` area with its stack and state. A parked hart
+//! touches only its own, through atomics; the kernel structures harts will
+//! share once they run kernel code are already behind `spin` locks, which
+//! spin across harts as well as on one.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use heapless::Vec;
+use elinos_common::platform::{self, MAX_HARTS};
+use elinos_common::{sbi, timer};
+use elinos_common::{ok_println, warn_println};
+
+/// Stack of each secondary hart
+const HART_STACK_SIZE: usize = 16 * 1024;
+
+/// How long a started hart gets to reach its park loop
+const START_TIMEOUT_MS: u64 = 100;
+
+/// sie.SSIE: supervisor software interrupts, which IPIs raise
+const SIE_SSIE: usize = 1 << 1;
+
+/// sip.SSIP: an IPI is pending
+const SIP_SSIP: usize = 1 << 1;
+
+/// What a hart is doing
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum HartState {
+    /// Not started, or not there
+    Offline = 0,
+    /// Started, not yet parked
+    Starting = 1,
+    /// Running the kernel: the boot hart
+    Running = 2,
+    /// Sleeping in `wfi`
+    Parked = 3,
+}
+
+/// Per-hart data area
+#[repr(C)]
+struct HartData {
+    /// Top of the hart's stack; `secondary_entry` loads it from offset 0
+    stack_top: AtomicUsize,
+    state: AtomicU8,
+}
+
+impl HartData {
+    const fn new() -> Self {
+        HartData { stack_top: AtomicUsize::new(0), state: AtomicU8::new(HartState::Offline as u8) }
+    }
+
+    fn state(&self) -> HartState {
+        match self.state.load(Ordering::Acquire) {
+            1 => HartState::Starting,
+            2 => HartState::Running,
+            3 => HartState::Parked,
+            _ => HartState::Offline,
+        }
+    }
+
+    fn set_state(&self, state: HartState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+}
+
+static HARTS: [HartData; MAX_HARTS] = [const { HartData::new() }; MAX_HARTS];
+
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// Record the hart the kernel was entered on. Call first thing at boot.
+pub fn set_boot_hart(hart: usize) {
+    if hart < MAX_HARTS {
+        BOOT_HART.store(hart, Ordering::Relaxed);
+        HARTS[hart].set_state(HartState::Running);
+    }
+}
+
+/// The hart running the kernel
+pub fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// Harts running or parked, the boot hart included
+pub fn online_harts() -> usize {
+    HARTS.iter().filter(|data| matches!(data.state(), HartState::Running | HartState::Parked)).count()
+}
+
+/// Start and park every other hart. Needs the memory manager for stacks.
+pub fn init() {
+    if !sbi::probe_extension(sbi::SBI_EXT_HSM) {
+        warn_println!("No SBI hart state management, running on the boot hart only");
+        return;
+    }
+
+    // The harts the device tree lists, or every ID the SBI knows
+    let mut harts: Vec<usize, MAX_HARTS> = Vec::new();
+    if platform::harts().is_empty() {
+        harts.extend((0..MAX_HARTS).filter(|&hart| sbi::hart_status(hart).is_some()));
+    } else {
+        harts.extend(platform::harts().iter().copied());
+    }
+
+    for &hart in harts.iter().filter(|&&hart| hart != boot_hart()) {
+        if sbi::hart_status(hart) != Some(sbi::HartState::Stopped) {
+            continue;
+        }
+        if let Err(e) = start(hart) {
+            warn_println!("Hart {} not started: {}", hart, e);
+        }
+    }
+    ok_println!("{} harts online, boot hart {}, {} parked", online_harts(), boot_hart(), online_harts() - 1);
+}
+
+/// Start hart `hart` on a new stack and wait for it to park
+fn start(hart: usize) -> Result<(), &'static str> {
+    let data = &HARTS[hart];
+    let stack = crate::memory::allocate_kernel_memory(HART_STACK_SIZE, 16).ok_or("no memory for its stack")?;
+    data.stack_top.store(stack + HART_STACK_SIZE, Ordering::Release);
+    data.set_state(HartState::Starting);
+
+    let entry = secondary_entry as *const () as usize;
+    if sbi::hart_start(hart, entry, data as *const HartData as usize).is_err() {
+        data.set_state(HartState::Offline);
+        crate::memory::deallocate_kernel_memory(stack, HART_STACK_SIZE);
+        return Err("the SBI refused to start it");
+    }
+
+    // The stack stays with the hart even if it is slow to come up
+    let started = timer::ticks();
+    while data.state() != HartState::Parked {
+        if timer::elapsed_ms(started) >= START_TIMEOUT_MS {
+            return Err("it did not come up");
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+/// Where a secondary hart starts, translation off, with its hart ID in a0
+/// and its `HartData` in a1
+#[unsafe(naked)]
+unsafe extern "C" fn secondary_entry() -> ! {
+    core::arch::naked_asm!(
+        "ld sp, 0(a1)",  // HartData.stack_top
+        "j {main}",
+        main = sym secondary_main,
+    );
+}
+
+/// Park the hart: sleep, and let an IPI do no more than wake it
+extern "C" fn secondary_main(_hart: usize, data: &'static HartData) -> ! {
+    unsafe {
+        asm!("csrw sie, {}", in(reg) SIE_SSIE, options(nostack));
+    }
+    data.set_state(HartState::Parked);
+    loop {
+        unsafe {
+            asm!("wfi", options(nostack));
+            asm!("csrc sip, {}", in(reg) SIP_SSIP, options(nostack));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Beeps, for a board with nobody watching its screen or serial line
+//!
+//! `beep` plays a tone of a given pitch and length on the first of:
+//!
+//! - a VirtIO sound device (`virtio::sound`), e.g. QEMU's
+//!   `-device virtio-sound-device,audiodev=snd0 -audiodev pa,id=snd0`
+//! - a buzzer on a GPIO line, where the device tree has a `gpio-beeper`
+//!   node on a SiFive GPIO controller; the line is toggled at the pitch, as
+//!   a PC speaker was
+//! - the terminal bell: a BEL sent to the console, whose length and pitch
+//!   the terminal picks
+//!
+//! The kernel beeps once boot is done and when it stops on an error (a
+//! panic, a fault it cannot survive), as `kernel.beep` (`on`, `errors` or
+//! `off`) allows. The bell
+//! is only rung for errors, so a serial console is not beeped at on each
+//! boot. A beep while the sound device is busy, e.g. a panic in its driver,
+//! skips to the buzzer or the bell rather than waiting for it.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use elinos_common::mmio::{Register, RegisterBlock};
+use elinos_common::{platform, sbi, timer};
+
+use crate::sysctl::{Kind, Tunable};
+use crate::virtio::sound::VIRTIO_SOUND;
+
+/// Longest beep, so a typo does not tie up the shell
+pub const MAX_DURATION_MS: u32 = 10_000;
+
+/// Lowest and highest pitch, in Hz
+pub const MIN_FREQUENCY: u32 = 20;
+pub const MAX_FREQUENCY: u32 = 20_000;
+
+// SiFive GPIO registers, one bit per line
+const GPIO_OUTPUT_EN: Register<u32> = Register::at(0x08);
+const GPIO_OUTPUT_VAL: Register<u32> = Register::at(0x0c);
+
+/// Where a beep was played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    VirtioSound,
+    GpioBuzzer,
+    Bell,
+}
+
+impl Output {
+    pub fn name(self) -> &'static str {
+        match self {
+            Output::VirtioSound => "VirtIO sound",
+            Output::GpioBuzzer => "GPIO buzzer",
+            Output::Bell => "terminal bell",
+        }
+    }
+}
+
+/// Why the kernel beeps on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notice {
+    /// The shell is about to start
+    BootComplete,
+    /// The kernel stopped on an error
+    Error,
+}
+
+// kernel.beep: 0 off, 1 errors only, 2 boot and errors
+const BEEP_OFF: u8 = 0;
+const BEEP_ERRORS: u8 = 1;
+const BEEP_ON: u8 = 2;
+
+static BEEP_MODE: AtomicU8 = AtomicU8::new(BEEP_ON);
+
+/// Play a tone of `frequency` Hz for `duration_ms` on the best output
+/// there is, and return which it was
+pub fn beep(frequency: u32, duration_ms: u32) -> Output {
+    if let Some(mut sound) = VIRTIO_SOUND.try_lock() {
+        if sound.is_initialized() && sound.play_tone(frequency, duration_ms).is_ok() {
+            return Output::VirtioSound;
+        }
+    }
+    if let Some(beeper) = platform::beeper() {
+        buzz(&beeper, frequency, duration_ms);
+        return Output::GpioBuzzer;
+    }
+    sbi::console_putchar(0x07);
+    Output::Bell
+}
+
+/// Toggle the buzzer's line at `frequency` for `duration_ms`, leaving it
+/// silent
+fn buzz(beeper: &platform::Beeper, frequency: u32, duration_ms: u32) {
+    let regs = unsafe { RegisterBlock::new(beeper.gpio.base) };
+    let bit = 1u32 << beeper.pin;
+    let silent = |regs: &RegisterBlock| {
+        let value = regs.read(GPIO_OUTPUT_VAL);
+        regs.write(GPIO_OUTPUT_VAL, if beeper.active_low { value | bit } else { value & !bit });
+    };
+    silent(&regs);
+    regs.write(GPIO_OUTPUT_EN, regs.read(GPIO_OUTPUT_EN) | bit);
+
+    let half_wave = (timer::frequency() / (2 * frequency as u64)).max(1);
+    let end = timer::ticks() + timer::ms_to_ticks(duration_ms as u64);
+    let mut next = timer::ticks();
+    while next < end {
+        regs.write(GPIO_OUTPUT_VAL, regs.read(GPIO_OUTPUT_VAL) ^ bit);
+        next += half_wave;
+        while timer::ticks() < next {
+            core::hint::spin_loop();
+        }
+    }
+    silent(&regs);
+}
+
+/// Beep for `notice`, if `kernel.beep` allows and there is an output
+/// that suits it
+pub fn notify(notice: Notice) {
+    let mode = BEEP_MODE.load(Ordering::Relaxed);
+    match notice {
+        Notice::BootComplete if mode == BEEP_ON && has_speaker() => {
+            beep(880, 100);
+        }
+        Notice::Error if mode != BEEP_OFF => {
+            beep(220, 400);
+            beep(220, 400);
+        }
+        _ => {}
+    }
+}
+
+/// Whether there is something better than the terminal bell
+pub fn has_speaker() -> bool {
+    platform::beeper().is_some() || VIRTIO_SOUND.try_lock().is_some_and(|sound| sound.is_initialized())
+}
+
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "kernel.beep",
+        description: "Beep when boot is done and on fatal errors (on), on errors only, or never",
+        kind: Kind::Choice { choices: &["off", "errors", "on"], get: get_beep, set: set_beep },
+    },
+];
+
+fn get_beep() -> &'static str {
+    match BEEP_MODE.load(Ordering::Relaxed) {
+        BEEP_OFF => "off",
+        BEEP_ERRORS => "errors",
+        _ => "on",
+    }
+}
+
+fn set_beep(value: &'static str) -> Result<(), &'static str> {
+    let mode = match value {
+        "off" => BEEP_OFF,
+        "errors" => BEEP_ERRORS,
+        _ => BEEP_ON,
+    };
+    BEEP_MODE.store(mode, Ordering::Relaxed);
+    Ok(())
+}
@@ -0,0 +1,247 @@
+// Syscall ABI self-test
+// Runs the shell's own file, memory and process operations through the
+// syscall entry path (crate::trap::kernel_syscall) and checks the results
+// against the kernel's internal APIs, so ABI bugs show up without having to
+// build and run an external program.
+
+use elinos_common::{console_println, ok_println, err_println, info_println};
+use heapless::String;
+use core::fmt::Write;
+use crate::trap::kernel_syscall;
+use crate::filesystem;
+use super::{EAGAIN, EINVAL, ENOENT, O_RDONLY, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+use super::{CLOCK_MONOTONIC, TFD_NONBLOCK, Itimerspec, Timespec, PollFd, POLLIN, POLLOUT};
+use super::{EFD_NONBLOCK, EFD_SEMAPHORE};
+use elinos_common::syscall::*;
+
+/// dirfd meaning "relative to the current directory"
+const AT_FDCWD: isize = -100;
+
+/// A number no syscall will ever have
+const UNUSED_SYSCALL: usize = 9999;
+
+/// Bytes compared in the file read check
+const READ_CHECK_LEN: usize = 64;
+
+/// Pass and fail counts of a run
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+impl Summary {
+    fn check(&mut self, name: &str, passed: bool, detail: core::fmt::Arguments) {
+        if passed {
+            self.passed += 1;
+            ok_println!("{}", name);
+        } else {
+            self.failed += 1;
+            err_println!("{}: {}", name, detail);
+        }
+    }
+}
+
+fn syscall0(number: usize) -> isize {
+    kernel_syscall(number, [0; 6])
+}
+
+fn syscall3(number: usize, a0: usize, a1: usize, a2: usize) -> isize {
+    kernel_syscall(number, [a0, a1, a2, 0, 0, 0])
+}
+
+/// Run every check. `file` is read through open/read/close; without one the
+/// first regular file in / is used.
+pub fn run(file: Option<&str>) -> Summary {
+    let mut summary = Summary::default();
+
+    info_println!("Syscall ABI test");
+    check_process(&mut summary);
+    check_memory(&mut summary);
+    check_console(&mut summary);
+    check_files(&mut summary, file);
+    check_timers(&mut summary);
+    check_eventfds(&mut summary);
+    check_errors(&mut summary);
+
+    console_println!();
+    console_println!("{} passed, {} failed", summary.passed, summary.failed);
+    summary
+}
+
+fn check_process(summary: &mut Summary) {
+    let expected = super::process::PROCESS_MANAGER.lock().get_current_pid() as isize;
+    let pid = syscall0(SYS_GETPID);
+    summary.check("getpid", pid == expected, format_args!("returned {}, expected {}", pid, expected));
+
+    let ppid = syscall0(SYS_GETPPID);
+    summary.check("getppid", ppid >= 0, format_args!("returned {}", ppid));
+}
+
+fn check_memory(summary: &mut Summary) {
+    const LEN: usize = 4096;
+
+    let addr = kernel_syscall(SYS_MMAP, [0, LEN, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0]);
+    if addr <= 0 {
+        summary.check("mmap", false, format_args!("returned {}", addr));
+        return;
+    }
+
+    // The mapping must be usable memory of the requested length
+    let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, LEN) };
+    for (i, byte) in region.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    let intact = region.iter().enumerate().all(|(i, &byte)| byte == i as u8);
+    summary.check("mmap", intact, format_args!("mapping at 0x{:x} does not hold data", addr));
+
+    let ret = syscall3(SYS_MUNMAP, addr as usize, LEN, 0);
+    summary.check("munmap", ret == 0, format_args!("returned {}", ret));
+
+    let brk = syscall0(SYS_BRK);
+    summary.check("brk", brk > 0, format_args!("returned {}", brk));
+}
+
+fn check_console(summary: &mut Summary) {
+    let message = "abitest: written through SYS_WRITE\n";
+    let ret = syscall3(SYS_WRITE, super::STDOUT_FD as usize, message.as_ptr() as usize, message.len());
+    summary.check("write(stdout)", ret == message.len() as isize,
+        format_args!("returned {}, expected {}", ret, message.len()));
+}
+
+fn check_files(summary: &mut Summary, file: Option<&str>) {
+    let mut path: String<128> = String::new();
+    match file {
+        Some(file) => {
+            let _ = path.push_str(file);
+        }
+        None => {
+            let first = filesystem::list_directory("/").ok()
+                .and_then(|entries| entries.into_iter().find(|(_, size, is_dir)| !is_dir && *size > 0));
+            match first {
+                Some((name, _, _)) => {
+                    let _ = write!(path, "/{}", name);
+                }
+                None => {
+                    console_println!("open/read/close: skipped, no file to read");
+                    return;
+                }
+            }
+        }
+    }
+
+    let mut expected = [0u8; READ_CHECK_LEN];
+    let expected_len = match filesystem::read_file_at(&path, 0, &mut expected) {
+        Ok(len) => len,
+        Err(_) => {
+            summary.check("open/read/close", false, format_args!("cannot read {} directly", path));
+            return;
+        }
+    };
+
+    // The kernel expects NUL-terminated paths, like C programs pass them
+    let mut c_path: String<129> = String::new();
+    let _ = c_path.push_str(&path);
+    let _ = c_path.push('\0');
+
+    let fd = kernel_syscall(SYS_OPENAT, [AT_FDCWD as usize, c_path.as_ptr() as usize, O_RDONLY as usize, 0, 0, 0]);
+    summary.check("openat", fd >= 0, format_args!("{} returned {}", path, fd));
+    if fd < 0 {
+        return;
+    }
+
+    let mut buf = [0u8; READ_CHECK_LEN];
+    let read = syscall3(SYS_READ, fd as usize, buf.as_mut_ptr() as usize, buf.len());
+    let matches = read == expected_len as isize && buf[..expected_len] == expected[..expected_len];
+    summary.check("read", matches, format_args!("returned {} for {}, expected {} matching bytes", read, path, expected_len));
+
+    let ret = syscall3(SYS_CLOSE, fd as usize, 0, 0);
+    summary.check("close", ret == 0, format_args!("returned {}", ret));
+}
+
+fn check_timers(summary: &mut Summary) {
+    const PERIOD: Timespec = Timespec { tv_sec: 0, tv_nsec: 10_000_000 };
+
+    let fd = syscall3(SYS_TIMERFD_CREATE, CLOCK_MONOTONIC, TFD_NONBLOCK as usize, 0);
+    summary.check("timerfd_create", fd >= 0, format_args!("returned {}", fd));
+    if fd < 0 {
+        return;
+    }
+
+    let mut expirations = 0u64;
+    let ret = syscall3(SYS_READ, fd as usize, &mut expirations as *mut u64 as usize, 8);
+    summary.check("read(disarmed timer)", ret == -EAGAIN, format_args!("returned {}, expected {}", ret, -EAGAIN));
+
+    let setting = Itimerspec { it_interval: PERIOD, it_value: PERIOD };
+    let ret = kernel_syscall(SYS_TIMERFD_SETTIME, [fd as usize, 0, &setting as *const Itimerspec as usize, 0, 0, 0]);
+    summary.check("timerfd_settime", ret == 0, format_args!("returned {}", ret));
+
+    let mut current = Itimerspec { it_interval: Timespec { tv_sec: 0, tv_nsec: 0 }, it_value: Timespec { tv_sec: 0, tv_nsec: 0 } };
+    let ret = syscall3(SYS_TIMERFD_GETTIME, fd as usize, &mut current as *mut Itimerspec as usize, 0);
+    let interval = current.it_interval.tv_nsec;
+    summary.check("timerfd_gettime", ret == 0 && interval == PERIOD.tv_nsec,
+        format_args!("returned {} with interval {} ns", ret, interval));
+
+    let mut pollfd = PollFd { fd: fd as i32, events: POLLIN, revents: 0 };
+    let timeout = Timespec { tv_sec: 1, tv_nsec: 0 };
+    let ret = syscall3(SYS_PPOLL, &mut pollfd as *mut PollFd as usize, 1, &timeout as *const Timespec as usize);
+    summary.check("ppoll(timer)", ret == 1 && pollfd.revents == POLLIN,
+        format_args!("returned {} with revents 0x{:x}", ret, pollfd.revents));
+
+    let ret = syscall3(SYS_READ, fd as usize, &mut expirations as *mut u64 as usize, 8);
+    summary.check("read(timer)", ret == 8 && expirations >= 1,
+        format_args!("returned {} with {} expirations", ret, expirations));
+
+    let ret = syscall3(SYS_CLOSE, fd as usize, 0, 0);
+    summary.check("close(timer)", ret == 0, format_args!("returned {}", ret));
+}
+
+fn check_eventfds(summary: &mut Summary) {
+    let fd = syscall3(SYS_EVENTFD2, 0, (EFD_NONBLOCK | EFD_SEMAPHORE) as usize, 0);
+    summary.check("eventfd2", fd >= 0, format_args!("returned {}", fd));
+    if fd < 0 {
+        return;
+    }
+
+    let mut value = 0u64;
+    let ret = syscall3(SYS_READ, fd as usize, &mut value as *mut u64 as usize, 8);
+    summary.check("read(empty eventfd)", ret == -EAGAIN, format_args!("returned {}, expected {}", ret, -EAGAIN));
+
+    let mut pollfd = PollFd { fd: fd as i32, events: POLLIN | POLLOUT, revents: 0 };
+    let timeout = Timespec { tv_sec: 0, tv_nsec: 0 };
+    let ret = syscall3(SYS_PPOLL, &mut pollfd as *mut PollFd as usize, 1, &timeout as *const Timespec as usize);
+    summary.check("ppoll(empty eventfd)", ret == 1 && pollfd.revents == POLLOUT,
+        format_args!("returned {} with revents 0x{:x}", ret, pollfd.revents));
+
+    let added = 2u64;
+    let ret = syscall3(SYS_WRITE, fd as usize, &added as *const u64 as usize, 8);
+    summary.check("write(eventfd)", ret == 8, format_args!("returned {}", ret));
+
+    let ret = syscall3(SYS_PPOLL, &mut pollfd as *mut PollFd as usize, 1, &timeout as *const Timespec as usize);
+    summary.check("ppoll(eventfd)", ret == 1 && pollfd.revents == POLLIN | POLLOUT,
+        format_args!("returned {} with revents 0x{:x}", ret, pollfd.revents));
+
+    // Semaphore mode takes one at a time
+    let first = syscall3(SYS_READ, fd as usize, &mut value as *mut u64 as usize, 8);
+    let second = syscall3(SYS_READ, fd as usize, &mut value as *mut u64 as usize, 8);
+    summary.check("read(eventfd)", first == 8 && second == 8 && value == 1,
+        format_args!("returned {} and {} with value {}", first, second, value));
+
+    let ret = syscall3(SYS_EVENTFD_WATCH, fd as usize, u32::MAX as usize, 0);
+    summary.check("eventfd_watch(bad source)", ret == -EINVAL, format_args!("returned {}, expected {}", ret, -EINVAL));
+
+    let ret = syscall3(SYS_CLOSE, fd as usize, 0, 0);
+    summary.check("close(eventfd)", ret == 0, format_args!("returned {}", ret));
+}
+
+fn check_errors(summary: &mut Summary) {
+    let missing = "/abitest-no-such-file\0";
+    let ret = kernel_syscall(SYS_OPENAT, [AT_FDCWD as usize, missing.as_ptr() as usize, O_RDONLY as usize, 0, 0, 0]);
+    summary.check("openat(missing)", ret == -ENOENT, format_args!("returned {}, expected {}", ret, -ENOENT));
+
+    let ret = syscall3(SYS_CLOSE, i32::MAX as usize, 0, 0);
+    summary.check("close(bad fd)", ret < 0, format_args!("returned {}", ret));
+
+    let ret = syscall0(UNUSED_SYSCALL);
+    summary.check("unknown syscall", ret < 0, format_args!("returned {}", ret));
+}
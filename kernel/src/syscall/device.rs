@@ -2,7 +2,11 @@
 // Following Linux ARM64/RISC-V syscall numbers for compatibility
 
 use super::{SysCallResult, SyscallArgs};
-use crate::{console_println, console_print};
+use super::time::Timespec;
+use crate::eventfd::EventError;
+use elinos_common::syscall::*;
+use elinos_common::timer;
+use crate::{console_print, err_println, info_println};
 use spin::Mutex;
 use heapless::{Vec, String};
 use lazy_static::lazy_static;
@@ -221,6 +225,16 @@ impl TtyDevice {
         None
     }
     
+    /// Whether a read would return something: a whole line in canonical
+    /// mode, or a full buffer, and any byte otherwise
+    pub fn input_ready(&self) -> bool {
+        if self.termios.c_lflag & ICANON != 0 {
+            self.input_buffer.contains(&b'\n') || self.input_buffer.is_full()
+        } else {
+            !self.input_buffer.is_empty()
+        }
+    }
+    
     pub fn read_input(&mut self, buf: &mut [u8]) -> usize {
         let mut count = 0;
         let max_read = buf.len().min(self.input_buffer.len());
@@ -286,6 +300,26 @@ impl TtyDevice {
 }
 
 // Global TTY devices
+// === POLL ===
+
+/// struct pollfd
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+pub const POLLIN: i16 = 0x001;
+pub const POLLOUT: i16 = 0x004;
+pub const POLLERR: i16 = 0x008;
+pub const POLLHUP: i16 = 0x010;
+pub const POLLNVAL: i16 = 0x020;
+
+/// Most descriptors one ppoll waits on
+const MAX_POLL_FDS: usize = 64;
+
 const MAX_TTYS: usize = 16;
 pub static TTY_DEVICES: Mutex<Vec<TtyDevice, MAX_TTYS>> = Mutex::new(Vec::new());
 
@@ -302,7 +336,7 @@ pub fn init_tty_devices() {
         // Create console TTY (tty0)
         devices.push(TtyDevice::new()).ok();
         
-        console_println!("[i] TTY devices initialized");
+        info_println!("TTY devices initialized");
         *initialized = true;
     }
 }
@@ -317,33 +351,14 @@ fn get_tty_for_fd(fd: i32) -> Option<usize> {
     }
 }
 
-// === LINUX COMPATIBLE DEVICE AND I/O MANAGEMENT SYSTEM CALL CONSTANTS ===
-pub const SYS_DUP: usize = 23;         // Linux: dup
-pub const SYS_DUP3: usize = 24;        // Linux: dup3
-pub const SYS_FCNTL: usize = 25;       // Linux: fcntl
-pub const SYS_INOTIFY_INIT1: usize = 26; // Linux: inotify_init1
-pub const SYS_INOTIFY_ADD_WATCH: usize = 27; // Linux: inotify_add_watch
-pub const SYS_INOTIFY_RM_WATCH: usize = 28;  // Linux: inotify_rm_watch
-pub const SYS_IOCTL: usize = 29;       // Linux: ioctl
-pub const SYS_IOPRIO_SET: usize = 30;  // Linux: ioprio_set
-pub const SYS_IOPRIO_GET: usize = 31;  // Linux: ioprio_get
-pub const SYS_FLOCK: usize = 32;       // Linux: flock
-pub const SYS_MKNODAT: usize = 33;     // Linux: mknodat
-pub const SYS_PIPE2: usize = 59;       // Linux: pipe2
-
-// Legacy syscall aliases for backwards compatibility
-pub const SYS_PIPE: usize = SYS_PIPE2; // Map pipe to pipe2
-pub const SYS_DUP2: usize = SYS_DUP3;  // Map dup2 to dup3
-
-// elinOS-specific device syscalls (keeping high numbers to avoid conflicts)
-pub const SYS_GETDEVICES: usize = 950; // elinOS: get device info
-
 // Linux compatible device management syscall handler
 pub fn handle_device_syscall(args: &SyscallArgs) -> SysCallResult {
     match args.syscall_number {
         SYS_IOCTL => sys_ioctl(args.arg0_as_i32(), args.arg1, args.arg2),
         SYS_FCNTL => sys_fcntl(args.arg0_as_i32(), args.arg1_as_i32(), args.arg2),
         SYS_PIPE2 => sys_pipe2(args.arg0_as_mut_ptr::<i32>(), args.arg1_as_i32()),
+        SYS_EVENTFD2 => sys_eventfd2(args.arg0 as u32, args.arg1_as_i32()),
+        SYS_PPOLL => sys_ppoll(args.arg0_as_mut_ptr::<PollFd>(), args.arg1, args.arg2_as_ptr::<Timespec>()),
         SYS_DUP => sys_dup(args.arg0_as_i32()),
         SYS_DUP3 => sys_dup3(args.arg0_as_i32(), args.arg1_as_i32(), args.arg2_as_i32()),
         SYS_FLOCK => sys_flock(args.arg0_as_i32(), args.arg1_as_i32()),
@@ -354,6 +369,7 @@ pub fn handle_device_syscall(args: &SyscallArgs) -> SysCallResult {
         SYS_IOPRIO_SET => sys_ioprio_set(args.arg0_as_i32(), args.arg1_as_i32(), args.arg2_as_i32()),
         SYS_IOPRIO_GET => sys_ioprio_get(args.arg0_as_i32(), args.arg1_as_i32()),
         SYS_GETDEVICES => sys_getdevices(),
+        SYS_SYSLOG => sys_syslog(args.arg0_as_i32(), args.arg1_as_mut_ptr::<u8>(), args.arg2_as_i32()),
         _ => SysCallResult::Error(crate::syscall::ENOSYS),
     }
 }
@@ -361,7 +377,7 @@ pub fn handle_device_syscall(args: &SyscallArgs) -> SysCallResult {
 // === SYSTEM CALL IMPLEMENTATIONS ===
 
 fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
-    console_println!("[i] SYS_IOCTL: fd={}, request=0x{:x}, arg=0x{:x}", fd, request, arg);
+    info_println!("SYS_IOCTL: fd={}, request=0x{:x}, arg=0x{:x}", fd, request, arg);
     
     // Initialize TTY devices if not already done
     init_tty_devices();
@@ -369,7 +385,7 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
     let tty_index = match get_tty_for_fd(fd) {
         Some(index) => index,
         None => {
-            console_println!("[x] No TTY device for fd {}", fd);
+            err_println!("No TTY device for fd {}", fd);
             return SysCallResult::Error(crate::syscall::ENOTTY);
         }
     };
@@ -378,7 +394,7 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
     if let Some(tty) = devices.get_mut(tty_index) {
         match request {
             TCGETS => {
-                console_println!("[i] TCGETS: Getting terminal attributes");
+                info_println!("TCGETS: Getting terminal attributes");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -390,7 +406,7 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
             }
             
             TCSETS | TCSETSW | TCSETSF => {
-                console_println!("[i] TCSETS: Setting terminal attributes");
+                info_println!("TCSETS: Setting terminal attributes");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -406,13 +422,13 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
                 }
                 
                 tty.termios = new_termios;
-                console_println!("[i] Terminal attributes updated: lflag=0x{:x}, iflag=0x{:x}", 
+                info_println!("Terminal attributes updated: lflag=0x{:x}, iflag=0x{:x}", 
                     tty.termios.c_lflag, tty.termios.c_iflag);
                 SysCallResult::Success(0)
             }
             
             TIOCGWINSZ => {
-                console_println!("[i] TIOCGWINSZ: Getting window size");
+                info_println!("TIOCGWINSZ: Getting window size");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -420,12 +436,12 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
                 unsafe {
                     core::ptr::write(arg as *mut Winsize, tty.winsize);
                 }
-                console_println!("[i] Window size: {}x{}", tty.winsize.ws_row, tty.winsize.ws_col);
+                info_println!("Window size: {}x{}", tty.winsize.ws_row, tty.winsize.ws_col);
                 SysCallResult::Success(0)
             }
             
             TIOCSWINSZ => {
-                console_println!("[i] TIOCSWINSZ: Setting window size");
+                info_println!("TIOCSWINSZ: Setting window size");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -435,12 +451,12 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
                 };
                 
                 tty.winsize = new_winsize;
-                console_println!("[i] Window size set to: {}x{}", tty.winsize.ws_row, tty.winsize.ws_col);
+                info_println!("Window size set to: {}x{}", tty.winsize.ws_row, tty.winsize.ws_col);
                 SysCallResult::Success(0)
             }
             
             TIOCGPGRP => {
-                console_println!("[i] TIOCGPGRP: Getting process group");
+                info_println!("TIOCGPGRP: Getting process group");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -452,7 +468,7 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
             }
             
             TIOCSPGRP => {
-                console_println!("[i] TIOCSPGRP: Setting process group");
+                info_println!("TIOCSPGRP: Setting process group");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -462,18 +478,18 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
                 };
                 
                 tty.pgrp = new_pgrp;
-                console_println!("[i] Process group set to: {}", tty.pgrp);
+                info_println!("Process group set to: {}", tty.pgrp);
                 SysCallResult::Success(0)
             }
             
             TIOCSCTTY => {
-                console_println!("[i] TIOCSCTTY: Setting controlling terminal");
+                info_println!("TIOCSCTTY: Setting controlling terminal");
                 tty.is_controlling = true;
                 SysCallResult::Success(0)
             }
             
             FIONREAD => {
-                console_println!("[i] FIONREAD: Getting input buffer size");
+                info_println!("FIONREAD: Getting input buffer size");
                 if arg == 0 {
                     return SysCallResult::Error(crate::syscall::EINVAL);
                 }
@@ -486,7 +502,7 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
             }
             
             TCFLSH => {
-                console_println!("[i] TCFLSH: Flushing terminal");
+                info_println!("TCFLSH: Flushing terminal");
                 match arg {
                     0 => tty.input_buffer.clear(),  // TCIFLUSH
                     1 => tty.output_buffer.clear(), // TCOFLUSH
@@ -500,12 +516,12 @@ fn sys_ioctl(fd: i32, request: usize, arg: usize) -> SysCallResult {
             }
             
             _ => {
-                console_println!("[x] Unsupported ioctl request: 0x{:x}", request);
+                err_println!("Unsupported ioctl request: 0x{:x}", request);
                 SysCallResult::Error(crate::syscall::ENOSYS)
             }
         }
     } else {
-        console_println!("[x] TTY device {} not found", tty_index);
+        err_println!("TTY device {} not found", tty_index);
         SysCallResult::Error(crate::syscall::ENODEV)
     }
 }
@@ -520,6 +536,108 @@ fn sys_pipe2(_pipefd: *mut i32, _flags: i32) -> SysCallResult {
     SysCallResult::Error(crate::syscall::ENOSYS)
 }
 
+// eventfd2 flags
+pub const EFD_SEMAPHORE: i32 = 1;
+pub const EFD_NONBLOCK: i32 = 0o4000;
+pub const EFD_CLOEXEC: i32 = 0o2000000;
+
+fn sys_eventfd2(initval: u32, flags: i32) -> SysCallResult {
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return SysCallResult::Error(crate::syscall::EINVAL);
+    }
+    let fd = super::file::allocate_fd();
+    match crate::eventfd::create(fd, initval, flags & EFD_SEMAPHORE != 0, flags & EFD_NONBLOCK != 0) {
+        Ok(()) => SysCallResult::Success(fd as isize),
+        Err(e) => SysCallResult::Error(eventfd_errno(e)),
+    }
+}
+
+/// errno for an eventfd error
+pub fn eventfd_errno(error: EventError) -> isize {
+    match error {
+        EventError::NoEventFd => crate::syscall::EBADF,
+        EventError::TooManyEventFds => crate::syscall::EMFILE,
+        EventError::WouldBlock => crate::syscall::EAGAIN,
+        EventError::Invalid => crate::syscall::EINVAL,
+    }
+}
+
+/// Wait until one of `fds` is ready or `timeout` passes (NULL waits for
+/// ever); the signal mask is ignored, as there are no signals. Timers,
+/// eventfds and sockets become ready; the console, files and stdout always
+/// are.
+fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const Timespec) -> SysCallResult {
+    if nfds > MAX_POLL_FDS {
+        return SysCallResult::Error(crate::syscall::EINVAL);
+    }
+    if fds.is_null() && nfds > 0 {
+        return SysCallResult::Error(crate::syscall::EFAULT);
+    }
+    let deadline = if timeout.is_null() {
+        u64::MAX
+    } else {
+        match unsafe { core::ptr::read_unaligned(timeout) }.to_ns() {
+            Some(ns) => timer::ticks().saturating_add(timer::ns_to_ticks(ns)),
+            None => return SysCallResult::Error(crate::syscall::EINVAL),
+        }
+    };
+    let fds: &mut [PollFd] = if nfds == 0 { &mut [] } else { unsafe { core::slice::from_raw_parts_mut(fds, nfds) } };
+    let sockets = fds.iter().any(|pollfd| crate::net::udp::is_socket(pollfd.fd));
+
+    loop {
+        // Sleep until the next timer in the set is due at the latest
+        let wake = fds.iter().filter_map(|pollfd| crate::timerfd::next_expiry(pollfd.fd)).fold(deadline, u64::min);
+        let ready = crate::trap::wait_until(wake, || {
+            if sockets {
+                crate::net::poll();
+            }
+            let ready = poll_once(fds);
+            (ready > 0).then_some(ready)
+        });
+        if let Some(ready) = ready {
+            return SysCallResult::Success(ready as isize);
+        }
+        if timer::ticks() >= deadline {
+            return SysCallResult::Success(0);
+        }
+    }
+}
+
+/// Fill in the `revents` of `fds`; returns how many have any
+fn poll_once(fds: &mut [PollFd]) -> usize {
+    let mut ready = 0;
+    for pollfd in fds.iter_mut() {
+        pollfd.revents = if pollfd.fd < 0 { 0 } else { fd_events(pollfd.fd) & (pollfd.events | POLLERR | POLLHUP | POLLNVAL) };
+        if pollfd.revents != 0 {
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// The poll events descriptor `fd` has now
+fn fd_events(fd: i32) -> i16 {
+    if crate::timerfd::is_timer(fd) {
+        if crate::timerfd::readable(fd) { POLLIN } else { 0 }
+    } else if crate::eventfd::is_eventfd(fd) {
+        (if crate::eventfd::readable(fd) { POLLIN } else { 0 })
+            | (if crate::eventfd::writable(fd) { POLLOUT } else { 0 })
+    } else if crate::net::udp::is_socket(fd) {
+        POLLOUT | if crate::net::udp::readable(fd) { POLLIN } else { 0 }
+    } else if fd == 0 {
+        match TTY_DEVICES.lock().first() {
+            Some(tty) if !tty.input_buffer.is_empty() => POLLIN,
+            _ => 0,
+        }
+    } else if fd == super::STDOUT_FD || fd == super::STDERR_FD {
+        POLLOUT
+    } else if super::file::is_open_file(fd) {
+        POLLIN | POLLOUT
+    } else {
+        POLLNVAL
+    }
+}
+
 fn sys_dup(_oldfd: i32) -> SysCallResult {
     // TODO: Implement file descriptor duplication
     SysCallResult::Error(crate::syscall::ENOSYS)
@@ -565,14 +683,88 @@ fn sys_ioprio_get(_which: i32, _who: i32) -> SysCallResult {
     SysCallResult::Error(crate::syscall::ENOSYS)
 }
 
+// syslog(2) actions
+const SYSLOG_ACTION_CLOSE: i32 = 0;
+const SYSLOG_ACTION_OPEN: i32 = 1;
+const SYSLOG_ACTION_READ: i32 = 2;
+const SYSLOG_ACTION_READ_ALL: i32 = 3;
+const SYSLOG_ACTION_READ_CLEAR: i32 = 4;
+const SYSLOG_ACTION_CLEAR: i32 = 5;
+const SYSLOG_ACTION_CONSOLE_OFF: i32 = 6;
+const SYSLOG_ACTION_CONSOLE_ON: i32 = 7;
+const SYSLOG_ACTION_CONSOLE_LEVEL: i32 = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: i32 = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: i32 = 10;
+
+/// Read or clear the kernel log, or set the console log level, as Linux's
+/// syslog(2); lines read start with `<level>` and the time since boot
+fn sys_syslog(action: i32, buf: *mut u8, len: i32) -> SysCallResult {
+    use crate::klog;
+
+    let buffer = || -> Result<&mut [u8], isize> {
+        if buf.is_null() || len < 0 {
+            return Err(crate::syscall::EINVAL);
+        }
+        Ok(unsafe { core::slice::from_raw_parts_mut(buf, len as usize) })
+    };
+    match action {
+        SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => SysCallResult::Success(0),
+        SYSLOG_ACTION_READ => {
+            let out = match buffer() {
+                Ok(out) => out,
+                Err(errno) => return SysCallResult::Error(errno),
+            };
+            if out.is_empty() {
+                return SysCallResult::Success(0);
+            }
+            // Wait for something new to be logged, as Linux does
+            crate::trap::wait_until(u64::MAX, || (klog::unread_len() > 0).then_some(()));
+            SysCallResult::Success(klog::read_unread(out) as isize)
+        }
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            let out = match buffer() {
+                Ok(out) => out,
+                Err(errno) => return SysCallResult::Error(errno),
+            };
+            let count = klog::read_all(out);
+            if action == SYSLOG_ACTION_READ_CLEAR {
+                klog::clear();
+            }
+            SysCallResult::Success(count as isize)
+        }
+        SYSLOG_ACTION_CLEAR => {
+            klog::clear();
+            SysCallResult::Success(0)
+        }
+        SYSLOG_ACTION_CONSOLE_OFF => {
+            klog::console_off();
+            SysCallResult::Success(0)
+        }
+        SYSLOG_ACTION_CONSOLE_ON => {
+            klog::console_on();
+            SysCallResult::Success(0)
+        }
+        SYSLOG_ACTION_CONSOLE_LEVEL => match u8::try_from(len) {
+            Ok(level @ 1..=8) => {
+                elinos_common::console::set_log_level(level);
+                SysCallResult::Success(0)
+            }
+            _ => SysCallResult::Error(crate::syscall::EINVAL),
+        },
+        SYSLOG_ACTION_SIZE_UNREAD => SysCallResult::Success(klog::unread_len() as isize),
+        SYSLOG_ACTION_SIZE_BUFFER => SysCallResult::Success(klog::LOG_BUFFER_SIZE as isize),
+        _ => SysCallResult::Error(crate::syscall::EINVAL),
+    }
+}
+
 fn sys_getdevices() -> SysCallResult {
     init_tty_devices();
     
     let devices = TTY_DEVICES.lock();
-    console_println!("[i] TTY devices: {}", devices.len());
+    info_println!("TTY devices: {}", devices.len());
     
     for (i, tty) in devices.iter().enumerate() {
-        console_println!("[i] TTY{}: pgrp={}, controlling={}", i, tty.pgrp, tty.is_controlling);
+        info_println!("TTY{}: pgrp={}, controlling={}", i, tty.pgrp, tty.is_controlling);
     }
     
     SysCallResult::Success(devices.len() as isize)
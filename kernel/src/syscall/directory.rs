@@ -2,13 +2,9 @@
 // Handles directory operations like mkdir, rmdir, chdir, etc.
 
 use super::{SysCallResult, SyscallArgs};
-
-// === DIRECTORY OPERATIONS SYSTEM CALL CONSTANTS (51-70) ===
-pub const SYS_MKDIR: usize = 51;
-pub const SYS_RMDIR: usize = 52;
-pub const SYS_CHDIR: usize = 53;
-pub const SYS_GETCWD: usize = 54;
-// Reserved for future directory operations: 55-70
+use super::file::{errno, resolve_user_path};
+use crate::filesystem::path;
+use elinos_common::syscall::*;
 
 // Standardized directory syscall handler
 pub fn handle_directory_syscall(args: &SyscallArgs) -> SysCallResult {
@@ -33,12 +29,30 @@ fn sys_rmdir(_pathname: *const u8) -> SysCallResult {
     SysCallResult::Error(crate::syscall::ENOSYS)
 }
 
-fn sys_chdir(_path: *const u8) -> SysCallResult {
-    // TODO: Implement change directory
-    SysCallResult::Error(crate::syscall::ENOSYS)
+fn sys_chdir(path: *const u8) -> SysCallResult {
+    let path = match resolve_user_path(path) {
+        Ok(path) => path,
+        Err(e) => return SysCallResult::Error(e),
+    };
+    match path::change_directory(&path) {
+        Ok(()) => SysCallResult::Success(0),
+        Err(e) => SysCallResult::Error(errno(e)),
+    }
 }
 
-fn sys_getcwd(_buf: *mut u8, _size: usize) -> SysCallResult {
-    // TODO: Implement get current working directory
-    SysCallResult::Error(crate::syscall::ENOSYS)
+/// Copy the working directory into `buf` with a NUL after it; returns the
+/// length with the NUL, as Linux's getcwd does
+fn sys_getcwd(buf: *mut u8, size: usize) -> SysCallResult {
+    if buf.is_null() {
+        return SysCallResult::Error(crate::syscall::EFAULT);
+    }
+    let cwd = crate::process::current_cwd();
+    if cwd.len() + 1 > size {
+        return SysCallResult::Error(crate::syscall::ERANGE);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(cwd.as_ptr(), buf, cwd.len());
+        *buf.add(cwd.len()) = 0;
+    }
+    SysCallResult::Success(cwd.len() as isize + 1)
 } 
\ No newline at end of file
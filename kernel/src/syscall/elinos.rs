@@ -1,19 +1,9 @@
 // elinOS-Specific System Calls (900-999)
 // Handles elinOS-specific operations like debug, version, stats, etc.
 
-use elinos_common::{sbi, console_println};
+use elinos_common::{sbi, console_println, info_println, warn_println};
 use super::{SysCallResult, SyscallArgs};
-
-// === ELINOS-SPECIFIC SYSTEM CALL CONSTANTS (900-999) ===
-pub const SYS_ELINOS_DEBUG: usize = 900;
-pub const SYS_ELINOS_STATS: usize = 901;
-pub const SYS_ELINOS_VERSION: usize = 902;
-pub const SYS_ELINOS_SHUTDOWN: usize = 903;
-pub const SYS_ELINOS_REBOOT: usize = 904;
-pub const SYS_LOAD_ELF: usize = 905;
-pub const SYS_EXEC_ELF: usize = 906;
-pub const SYS_ELF_INFO: usize = 907;
-// Reserved for elinOS-specific: 905-999
+use elinos_common::syscall::*;
 
 // elinOS-specific syscall handler
 pub fn handle_elinos_syscall(args: &SyscallArgs) -> SysCallResult {
@@ -25,6 +15,7 @@ pub fn handle_elinos_syscall(args: &SyscallArgs) -> SysCallResult {
         SYS_LOAD_ELF => super::process::sys_load_elf(args.arg0_as_ptr::<u8>(), args.arg1),
         SYS_EXEC_ELF => super::process::sys_exec_elf(args.arg0_as_ptr::<u8>(), args.arg1),
         SYS_ELF_INFO => super::process::sys_elf_info(args.arg0_as_ptr::<u8>(), args.arg1),
+        SYS_EVENTFD_WATCH => sys_eventfd_watch(args.arg0_as_i32(), args.arg1 as u32),
         _ => SysCallResult::Error(crate::syscall::ENOSYS),
     }
 }
@@ -44,6 +35,7 @@ pub fn sys_elinos_version() -> SysCallResult {
     console_println!("  Target: riscv64gc-unknown-none-elf");
     console_println!("  Memory Model: sv39 (future)");
     console_println!("  Privilege Level: Machine Mode");
+    console_println!("  Harts: {} online, booted on hart {}", crate::smp::online_harts(), crate::smp::boot_hart());
     console_println!();
     
     console_println!("Features:");
@@ -83,8 +75,9 @@ fn sys_elinos_debug(msg_ptr: *const u8) -> SysCallResult {
 }
 
 pub fn sys_elinos_shutdown() -> SysCallResult {
-    console_println!("[i] System shutdown requested");
-    console_println!("[i] Goodbye from elinOS!");
+    info_println!("System shutdown requested");
+    flush_disk_cache();
+    info_println!("Goodbye from elinOS!");
     
     // Call the SBI shutdown function
     sbi::system_shutdown();
@@ -92,9 +85,32 @@ pub fn sys_elinos_shutdown() -> SysCallResult {
 
 /// SYS_REBOOT - reboot the system  
 pub fn sys_elinos_reboot() -> SysCallResult {
-    console_println!("[i] System reboot requested");
-    console_println!("[i] Rebooting elinOS...");
+    info_println!("System reboot requested");
+    flush_disk_cache();
+    info_println!("Rebooting elinOS...");
     
     // Call the SBI reboot function
     sbi::system_reset();
-} 
\ No newline at end of file
+}
+
+/// Write cached filesystem data to disk before power goes away
+fn flush_disk_cache() {
+    if crate::klog::flush_to_disk().is_err() {
+        warn_println!("Failed to write the kernel log");
+    }
+    if crate::filesystem::append::flush_all().is_err() {
+        warn_println!("Failed to write pending appends");
+    }
+    if crate::filesystem::cache::flush().is_err() {
+        warn_println!("Failed to flush the block cache");
+    }
+}
+
+/// Have eventfd `fd` signalled by the kernel event sources in `sources`
+/// (`crate::eventfd::source`), replacing any it watched; 0 stops watching
+fn sys_eventfd_watch(fd: i32, sources: u32) -> SysCallResult {
+    match crate::eventfd::watch(fd, sources) {
+        Ok(()) => SysCallResult::Success(0),
+        Err(e) => SysCallResult::Error(super::device::eventfd_errno(e)),
+    }
+}
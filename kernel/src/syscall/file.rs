@@ -1,57 +1,106 @@
 // File I/O System Calls - Linux Compatible Numbers
 // Following Linux ARM64/RISC-V syscall numbers for compatibility
 
-use crate::filesystem;
-use crate::{console_print, console_println};
+use crate::filesystem::{self, FileStat, FilesystemError};
+use crate::memory::mmu;
+use crate::{console_print, console_println, ok_println, err_println, warn_println, info_println};
 use super::{SysCallResult, SyscallArgs, STDOUT_FD, STDERR_FD};
+use elinos_common::syscall::*;
 use spin::Mutex;
 use heapless::{FnvIndexMap, Vec};
 use crate::filesystem::traits::FileSystem;
+use crate::filesystem::path::{self, PathBuf};
 
-// Simple file descriptor table
-static FILE_TABLE: Mutex<FnvIndexMap<i32, heapless::String<64>, 16>> = Mutex::new(FnvIndexMap::new());
-static NEXT_FD: Mutex<i32> = Mutex::new(10); // File descriptors start at 10
+/// Files that can be open at once, across all programs
+pub const MAX_OPEN_FILES: usize = 16;
+
+/// An open file: its path, how it was opened and where the next read or
+/// write starts
+#[derive(Clone)]
+struct OpenFile {
+    path: heapless::String<64>,
+    flags: i32,
+    offset: usize,
+}
+
+impl OpenFile {
+    fn readable(&self) -> bool {
+        self.flags & O_ACCMODE != O_WRONLY
+    }
 
-// === LINUX COMPATIBLE FILE I/O SYSTEM CALL CONSTANTS ===
-pub const SYS_OPENAT: usize = 56;     // Linux: openat
-pub const SYS_CLOSE: usize = 57;      // Linux: close  
-pub const SYS_READ: usize = 63;       // Linux: read
-pub const SYS_WRITE: usize = 64;      // Linux: write
-pub const SYS_READV: usize = 65;      // Linux: readv
-pub const SYS_WRITEV: usize = 66;     // Linux: writev
-pub const SYS_PREAD64: usize = 67;    // Linux: pread64
-pub const SYS_PWRITE64: usize = 68;   // Linux: pwrite64
-pub const SYS_PREADV: usize = 69;     // Linux: preadv
-pub const SYS_PWRITEV: usize = 70;    // Linux: pwritev
-pub const SYS_SENDFILE: usize = 71;   // Linux: sendfile
-pub const SYS_PSELECT6: usize = 72;   // Linux: pselect6
-pub const SYS_PPOLL: usize = 73;      // Linux: ppoll
-pub const SYS_READLINKAT: usize = 78; // Linux: readlinkat
-pub const SYS_NEWFSTATAT: usize = 79; // Linux: newfstatat (stat)
-pub const SYS_FSTAT: usize = 80;      // Linux: fstat
-pub const SYS_SYNC: usize = 81;       // Linux: sync
-pub const SYS_FSYNC: usize = 82;      // Linux: fsync
-pub const SYS_FDATASYNC: usize = 83;  // Linux: fdatasync
-pub const SYS_LSEEK: usize = 62;      // Linux: lseek
-pub const SYS_GETDENTS64: usize = 61; // Linux: getdents64
-
-// Legacy syscall aliases for backwards compatibility
-pub const SYS_OPEN: usize = SYS_OPENAT;      // Map to openat
-pub const SYS_UNLINK: usize = 35;            // Linux: unlinkat (we'll handle as unlink)
-pub const SYS_GETDENTS: usize = SYS_GETDENTS64; // Map to getdents64
-pub const SYS_STAT: usize = SYS_NEWFSTATAT;  // Map to newfstatat
-pub const SYS_TRUNCATE: usize = 45;          // Linux: truncate
-pub const SYS_FTRUNCATE: usize = 46;         // Linux: ftruncate
-pub const SYS_FALLOCATE: usize = 47;         // Linux: fallocate
+    fn writable(&self) -> bool {
+        self.flags & O_ACCMODE != O_RDONLY
+    }
+}
+
+// File descriptor table, shared by all programs until processes get their own
+static FILE_TABLE: Mutex<FnvIndexMap<i32, OpenFile, MAX_OPEN_FILES>> = Mutex::new(FnvIndexMap::new());
+static NEXT_FD: Mutex<i32> = Mutex::new(10); // File descriptors start at 10
 
 // File operation flags
 pub const O_RDONLY: i32 = 0;
 pub const O_WRONLY: i32 = 1;
 pub const O_RDWR: i32 = 2;
+pub const O_ACCMODE: i32 = 3;
 pub const O_CREAT: i32 = 64;
+pub const O_EXCL: i32 = 128;
 pub const O_TRUNC: i32 = 512;
 pub const O_APPEND: i32 = 1024;
 
+// lseek origins
+pub const SEEK_SET: i32 = 0;
+pub const SEEK_CUR: i32 = 1;
+pub const SEEK_END: i32 = 2;
+
+// newfstatat flags
+pub const AT_SYMLINK_NOFOLLOW: i32 = 0x100;
+pub const AT_EMPTY_PATH: i32 = 0x1000;
+
+/// struct stat, as RISC-V Linux lays it out
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: i64,
+    pub st_atime_nsec: u64,
+    pub st_mtime: i64,
+    pub st_mtime_nsec: u64,
+    pub st_ctime: i64,
+    pub st_ctime_nsec: u64,
+    __unused: [u32; 2],
+}
+
+impl From<FileStat> for Stat {
+    fn from(stat: FileStat) -> Self {
+        Stat {
+            st_dev: stat.device,
+            st_ino: stat.inode,
+            st_mode: stat.mode,
+            st_nlink: stat.links,
+            st_uid: stat.uid,
+            st_gid: stat.gid,
+            st_size: stat.size as i64,
+            st_blksize: stat.block_size as i32,
+            st_blocks: stat.blocks as i64,
+            st_atime: stat.atime as i64,
+            st_mtime: stat.mtime as i64,
+            st_ctime: stat.ctime as i64,
+            ..Stat::default()
+        }
+    }
+}
+
 // Linux compatible file I/O syscall handler
 pub fn handle_file_syscall(args: &SyscallArgs) -> SysCallResult {
     match args.syscall_number {
@@ -59,9 +108,11 @@ pub fn handle_file_syscall(args: &SyscallArgs) -> SysCallResult {
         SYS_READ => sys_read(args.arg0_as_i32(), args.arg1_as_mut_ptr::<u8>(), args.arg2),
         SYS_OPENAT => sys_openat(*args),
         SYS_CLOSE => sys_close(args.arg0_as_i32()),
-        35 => sys_unlinkat(*args), // unlinkat
+        SYS_UNLINKAT => sys_unlinkat(*args),
         SYS_GETDENTS64 => sys_getdents64(*args),
+        SYS_READLINKAT => sys_readlinkat(args.arg0_as_i32(), args.arg1_as_ptr::<u8>(), args.arg2_as_mut_ptr::<u8>(), args.arg3),
         SYS_NEWFSTATAT => sys_newfstatat(args.arg0_as_i32(), args.arg1_as_ptr::<u8>(), args.arg2_as_mut_ptr::<u8>(), args.arg3_as_i32()),
+        SYS_FSTAT => sys_fstat(args.arg0_as_i32(), args.arg1_as_mut_ptr::<u8>()),
         SYS_LSEEK => sys_lseek(args.arg0_as_i32(), args.arg1 as isize, args.arg2_as_i32()),
         SYS_TRUNCATE => sys_truncate(args.arg0_as_ptr::<u8>(), args.arg1),
         SYS_FTRUNCATE => sys_ftruncate(args.arg0_as_i32(), args.arg1),
@@ -71,6 +122,27 @@ pub fn handle_file_syscall(args: &SyscallArgs) -> SysCallResult {
     }
 }
 
+/// errno for a filesystem error
+pub(super) fn errno(error: FilesystemError) -> isize {
+    match error {
+        FilesystemError::FileNotFound
+        | FilesystemError::PathNotFound
+        | FilesystemError::DirectoryNotFound => crate::syscall::ENOENT,
+        FilesystemError::FileAlreadyExists => crate::syscall::EEXIST,
+        FilesystemError::FilenameTooLong => crate::syscall::ENAMETOOLONG,
+        FilesystemError::FilesystemFull => crate::syscall::ENOSPC,
+        FilesystemError::IsADirectory => crate::syscall::EISDIR,
+        FilesystemError::NotADirectory => crate::syscall::ENOTDIR,
+        FilesystemError::ReadOnly => crate::syscall::EROFS,
+        FilesystemError::Busy => crate::syscall::EBUSY,
+        FilesystemError::SymlinkLoop => crate::syscall::ELOOP,
+        FilesystemError::TooManyLinks => crate::syscall::EMLINK,
+        FilesystemError::NotMounted => crate::syscall::ENODEV,
+        FilesystemError::InvalidPath | FilesystemError::InvalidFileNameCharacter => crate::syscall::EINVAL,
+        _ => crate::syscall::EIO,
+    }
+}
+
 // === SYSTEM CALL IMPLEMENTATIONS ===
 
 fn sys_write(fd: i32, buf: *const u8, count: usize) -> SysCallResult {
@@ -105,16 +177,102 @@ fn sys_write(fd: i32, buf: *const u8, count: usize) -> SysCallResult {
             }
             SysCallResult::Success(count as isize)
         }
+    } else if crate::eventfd::is_eventfd(fd) {
+        // A u64 to add to the counter
+        if buf.is_null() || count < 8 {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        let value = unsafe { core::ptr::read_unaligned(buf as *const u64) };
+        match crate::eventfd::write(fd, value) {
+            Ok(()) => SysCallResult::Success(8),
+            Err(e) => SysCallResult::Error(crate::syscall::device::eventfd_errno(e)),
+        }
     } else {
-        // TODO: File write support with proper file descriptor management
-                    SysCallResult::Error(crate::syscall::ENOSYS)
+        let file = match open_file(fd) {
+            Some(file) if file.writable() => file,
+            _ => return SysCallResult::Error(crate::syscall::EBADF),
+        };
+        if buf.is_null() {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        if count == 0 {
+            return SysCallResult::Success(0);
+        }
+
+        // A page not yet read in would fault under the filesystem lock
+        if mmu::fault_in_current(buf as usize, count).is_err() {
+            return SysCallResult::Error(crate::syscall::EFAULT);
+        }
+        let data = unsafe { core::slice::from_raw_parts(buf, count) };
+
+        // Small appends to disk files are gathered and written together
+        if file.flags & O_APPEND != 0 && !filesystem::is_pseudo_path(&file.path)
+            && count <= filesystem::append::APPEND_BUFFER_SIZE
+        {
+            return match filesystem::append_file(&file.path, data)
+                .and_then(|()| filesystem::append::end_of_file(&file.path))
+            {
+                Ok(end) => {
+                    set_offset(fd, end);
+                    SysCallResult::Success(count as isize)
+                }
+                Err(e) => SysCallResult::Error(errno(e)),
+            };
+        }
+
+        // Appends go at the end, wherever the offset is
+        let offset = if file.flags & O_APPEND != 0 {
+            match filesystem::get_file_size(&file.path) {
+                Ok(size) => size,
+                Err(e) => return SysCallResult::Error(errno(e)),
+            }
+        } else {
+            file.offset
+        };
+
+        match filesystem::write_file_at(&file.path, offset, data) {
+            Ok(written) => {
+                set_offset(fd, offset + written);
+                SysCallResult::Success(written as isize)
+            }
+            Err(e) => SysCallResult::Error(errno(e)),
+        }
+    }
+}
+
+/// A copy of the entry of open file `fd`
+fn open_file(fd: i32) -> Option<OpenFile> {
+    FILE_TABLE.lock().get(&fd).cloned()
+}
+
+/// Whether `fd` is an open file
+pub fn is_open_file(fd: i32) -> bool {
+    FILE_TABLE.lock().contains_key(&fd)
+}
+
+/// Path of open file `fd` for mmap, which needs to read it
+pub fn mapped_file_path(fd: i32) -> Result<heapless::String<64>, isize> {
+    match open_file(fd) {
+        Some(file) if file.readable() => Ok(file.path),
+        Some(_) => Err(crate::syscall::EACCES),
+        None => Err(crate::syscall::EBADF),
+    }
+}
+
+/// Move the offset of open file `fd` to `offset`
+fn set_offset(fd: i32, offset: usize) {
+    if let Some(file) = FILE_TABLE.lock().get_mut(&fd) {
+        file.offset = offset;
     }
 }
 
 fn sys_read(fd: i32, buf: *mut u8, count: usize) -> SysCallResult {
-    console_println!("[+] SYSCALL: sys_read(fd={}, buf={:p}, count={})", fd, buf, count);
-    
     if fd == 0 { // stdin
+        // The keys are the shell's while a program runs in the background
+        if crate::jobs::in_background() {
+            return SysCallResult::Success(0);
+        }
+        
         // Read from TTY device
         crate::syscall::device::init_tty_devices();
         
@@ -124,101 +282,169 @@ fn sys_read(fd: i32, buf: *mut u8, count: usize) -> SysCallResult {
                 return SysCallResult::Error(crate::syscall::EINVAL);
             }
             
+            // Take console input until there is enough to return
+            while !tty.input_ready() {
+                match elinos_common::tty::read_byte() {
+                    Some(byte) => match tty.process_input(byte) {
+                        Some(b'\n') => console_println!(),
+                        Some(echo) => console_print!("{}", echo as char),
+                        None => {}
+                    },
+                    None => {
+                        crate::watchdog::pet(crate::watchdog::Progress::Input);
+                        elinos_common::uart::wait_for_input();
+                    }
+                }
+            }
+            
             let slice = unsafe { core::slice::from_raw_parts_mut(buf, count) };
             let bytes_read = tty.read_input(slice);
-            
-            console_println!("[i] Read {} bytes from TTY", bytes_read);
             SysCallResult::Success(bytes_read as isize)
         } else {
-            console_println!("[x] TTY device not available");
+            err_println!("TTY device not available");
             SysCallResult::Error(crate::syscall::ENODEV)
         }
-    } else if fd >= 10 { // File descriptors start at 10
-        console_println!("[i] SYSCALL: Looking up file descriptor {}", fd);
-        
-        // Look up filename from file descriptor table
-        let file_table = FILE_TABLE.lock();
-        let filename = match file_table.get(&fd) {
-            Some(name) => {
-                console_println!("[o] SYSCALL: Found filename '{}' for fd {}", name.as_str(), fd);
-                name.clone()
-            },
-            None => {
-                console_println!("[!] SYSCALL: Invalid file descriptor {}", fd);
-                drop(file_table);
-                return SysCallResult::Error(crate::syscall::EBADF);
-            }
-        };
-        drop(file_table);
-        
-        console_println!("[i] SYSCALL: Reading file '{}'", filename.as_str());
-        
-        // Read the file content using the filesystem API
-        let fs = filesystem::FILESYSTEM.lock();
-        
-        // Try to read the file using the filesystem trait
-        match fs.read_file(&filename) {
-            Ok(content) => {
-                let bytes_to_copy = core::cmp::min(count, content.len());
-                console_println!("[i] SYSCALL: Will output {} bytes (requested={}, available={})", 
-                    bytes_to_copy, count, content.len());
-                
-                // If buffer is provided, copy to user buffer
-                if !buf.is_null() {
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(
-                            content.as_ptr(),
-                            buf,
-                            bytes_to_copy
-                        );
-                    }
-                }
-                
-                // Always print to console so user can see the file contents
-                let uart = crate::UART.lock();
-                for &byte in &content[..bytes_to_copy] {
-                    uart.putchar(byte);
-                }
-                drop(uart);
-                
-                console_println!("[o] SYSCALL: File output complete");
-                SysCallResult::Success(bytes_to_copy as isize)
+    } else if crate::eventfd::is_eventfd(fd) {
+        // The counter, or 1 of it in semaphore mode, as a u64
+        if buf.is_null() || count < 8 {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        match crate::eventfd::read(fd) {
+            Ok(value) => {
+                unsafe { core::ptr::write_unaligned(buf as *mut u64, value) };
+                SysCallResult::Success(8)
             }
-            Err(_) => {
-                console_println!("[x] File not found: {}", filename);
-                SysCallResult::Error(crate::syscall::ENOENT)
+            Err(e) => SysCallResult::Error(crate::syscall::device::eventfd_errno(e)),
+        }
+    } else if crate::timerfd::is_timer(fd) {
+        // Expirations since the last read, as a u64
+        if buf.is_null() || count < 8 {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        match crate::timerfd::read(fd) {
+            Ok(expirations) => {
+                unsafe { core::ptr::write_unaligned(buf as *mut u64, expirations) };
+                SysCallResult::Success(8)
             }
+            Err(e) => SysCallResult::Error(crate::syscall::time::timer_errno(e)),
         }
     } else {
-        console_println!("[x] SYSCALL: Invalid file descriptor {}", fd);
-        SysCallResult::Error(crate::syscall::EINVAL)
+        let file = match open_file(fd) {
+            Some(file) if file.readable() => file,
+            _ => return SysCallResult::Error(crate::syscall::EBADF),
+        };
+        if buf.is_null() {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+
+        // A page not yet read in would fault under the filesystem lock
+        if mmu::fault_in_current(buf as usize, count).is_err() {
+            return SysCallResult::Error(crate::syscall::EFAULT);
+        }
+        let slice = unsafe { core::slice::from_raw_parts_mut(buf, count) };
+        match filesystem::FILESYSTEM.lock().read_at(&file.path, file.offset, slice) {
+            Ok(read) => {
+                set_offset(fd, file.offset + read);
+                SysCallResult::Success(read as isize)
+            }
+            Err(e) => SysCallResult::Error(errno(e)),
+        }
     }
 }
 
+/// NUL-terminated path passed by a program, up to 256 bytes
+fn path_from_user(pathname: *const u8) -> Option<&'static str> {
+    if pathname.is_null() {
+        return None;
+    }
+    unsafe {
+        let mut len = 0;
+        while *pathname.add(len) != 0 && len < 256 {
+            len += 1;
+        }
+        core::str::from_utf8(core::slice::from_raw_parts(pathname, len)).ok()
+    }
+}
+
+/// A path passed by a program, taken from its working directory if it is
+/// relative
+pub fn resolve_user_path(pathname: *const u8) -> Result<PathBuf, isize> {
+    let path = path_from_user(pathname).ok_or(crate::syscall::EINVAL)?;
+    path::resolve(path).map_err(errno)
+}
+
 pub fn sys_openat(args: SyscallArgs) -> SysCallResult {
-    // For demo purposes, just check if file exists
-    let filename = "hello.txt";  // Hardcoded for now
-    
-    console_println!("[i] Sys_openat: opening file '{}'", filename);
-    
-    let fs = filesystem::FILESYSTEM.lock();
-    
-    if !fs.is_mounted() {
-        console_println!("[x] Filesystem not mounted");
-                    return SysCallResult::Error(crate::syscall::ENODEV);
+    // Relative paths are taken from the working directory; the directory
+    // fd is not used
+    let filename = match resolve_user_path(args.arg1_as_ptr::<u8>()) {
+        Ok(filename) => filename,
+        Err(e) => return SysCallResult::Error(e),
+    };
+    let filename = filename.as_str();
+    let flags = args.arg2_as_i32();
+
+    let path = match heapless::String::try_from(filename) {
+        Ok(path) => path,
+        Err(_) => return SysCallResult::Error(crate::syscall::ENAMETOOLONG),
+    };
+
+    let mut fs = filesystem::FILESYSTEM.lock();
+
+    // Device and /proc files are there whether or not a disk is mounted
+    if !fs.is_mounted() && !filesystem::is_pseudo_path(filename) {
+        return SysCallResult::Error(crate::syscall::ENODEV);
     }
-    
-    // Check if file exists using the trait method
-    if fs.file_exists(filename) {
-        console_println!("[o] File '{}' found, returning fd=3", filename);
-        SysCallResult::Success(3)  // Return a fake file descriptor
+
+    let exists = fs.file_exists(filename);
+    if exists && flags & O_CREAT != 0 && flags & O_EXCL != 0 {
+        return SysCallResult::Error(crate::syscall::EEXIST);
+    }
+    let entry = if exists {
+        fs.get_file_entry(filename)
+    } else if flags & O_CREAT != 0 {
+        fs.create_file(filename)
     } else {
-        console_println!("[x] File '{}' not found", filename);
-        SysCallResult::Error(crate::syscall::ENOENT)
+        return SysCallResult::Error(crate::syscall::ENOENT);
+    };
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => return SysCallResult::Error(errno(e)),
+    };
+
+    let writable = flags & O_ACCMODE != O_RDONLY;
+    if entry.is_directory && writable {
+        return SysCallResult::Error(crate::syscall::EISDIR);
+    }
+    if exists && writable && flags & O_TRUNC != 0 {
+        if let Err(e) = fs.truncate_file(&entry, 0) {
+            return SysCallResult::Error(errno(e));
+        }
+    }
+    drop(fs);
+
+    let mut file_table = FILE_TABLE.lock();
+    if file_table.len() == file_table.capacity() {
+        return SysCallResult::Error(crate::syscall::EMFILE);
     }
+    let fd = allocate_fd();
+    let _ = file_table.insert(fd, OpenFile { path, flags, offset: 0 });
+    SysCallResult::Success(fd as isize)
+}
+
+/// Allocate a new file descriptor number (files and sockets, including the
+/// kernel's own, share the space)
+pub fn allocate_fd() -> i32 {
+    let mut next_fd = NEXT_FD.lock();
+    let fd = *next_fd;
+    *next_fd += 1;
+    fd
 }
 
 fn sys_close(fd: i32) -> SysCallResult {
+    if crate::net::udp::close(fd) || crate::timerfd::close(fd) || crate::eventfd::close(fd) {
+        return SysCallResult::Success(0);
+    }
+    
     if fd >= 10 {
         let mut file_table = FILE_TABLE.lock();
         if file_table.remove(&fd).is_some() {
@@ -226,7 +452,7 @@ fn sys_close(fd: i32) -> SysCallResult {
             SysCallResult::Success(0)
         } else {
             drop(file_table);
-            SysCallResult::Error(crate::syscall::EINVAL)
+            SysCallResult::Error(crate::syscall::EBADF)
         }
     } else {
         SysCallResult::Error(crate::syscall::EPERM)
@@ -240,91 +466,166 @@ pub fn sys_unlinkat(args: SyscallArgs) -> SysCallResult {
     let fs = filesystem::FILESYSTEM.lock();
     
     if !fs.file_exists(filename) {
-        console_println!("[x] File '{}' doesn't exist", filename);
+        err_println!("File '{}' doesn't exist", filename);
         return SysCallResult::Error(crate::syscall::ENOENT);
     }
     
     // We don't actually implement file deletion yet
-    console_println!("[!] File deletion not implemented");
+    warn_println!("File deletion not implemented");
             SysCallResult::Error(crate::syscall::ENOSYS)
 }
 
 pub fn sys_getdents64(args: SyscallArgs) -> SysCallResult {
     let fd = args.arg0 as i32;
     
-    console_println!("[i] Sys_getdents64: listing directory for fd={}", fd);
+    info_println!("Sys_getdents64: listing directory for fd={}", fd);
     
     let fs = filesystem::FILESYSTEM.lock();
     
     match fs.list_files() {
         Ok(files) => {
-            console_println!("[o] Found {} files:", files.len());
-            for (name, size) in &files {
+            ok_println!("Found {} files:", files.len());
+            for (name, size, _) in &files {
                 console_println!("  [i] {} ({} bytes)", name.as_str(), size);
             }
             SysCallResult::Success(files.len() as isize)
         }
         Err(_) => {
-            console_println!("[x] Failed to list files");
+            err_println!("Failed to list files");
             SysCallResult::Error(crate::syscall::EIO)
         }
     }
 }
 
-fn sys_newfstatat(dirfd: i32, pathname: *const u8, statbuf: *mut u8, _flags: i32) -> SysCallResult {
-    let _ = dirfd; // Ignore dirfd for now
+/// Fill the program's `statbuf` with `stat`
+fn put_stat(statbuf: *mut u8, stat: FileStat) -> SysCallResult {
+    if statbuf.is_null() {
+        return SysCallResult::Error(crate::syscall::EFAULT);
+    }
     unsafe {
-        // Convert C string to Rust string
-        let mut len = 0;
-        let mut ptr = pathname;
-        while *ptr != 0 && len < 256 {
-            len += 1;
-            ptr = ptr.add(1);
-        }
-        
-        let slice = core::slice::from_raw_parts(pathname, len);
-        if let Ok(filename) = core::str::from_utf8(slice) {
-            let fs = filesystem::FILESYSTEM.lock();
-            match fs.read_file(filename) {
-                Ok(content) => {
-                    // Simple stat structure: just file size as usize
-                    let size = content.len();
-                    core::ptr::write(statbuf as *mut usize, size);
-                    SysCallResult::Success(0)
-                }
-                Err(_) => SysCallResult::Error(crate::syscall::ENOENT)
+        core::ptr::write_unaligned(statbuf as *mut Stat, Stat::from(stat));
+    }
+    SysCallResult::Success(0)
+}
+
+fn sys_newfstatat(dirfd: i32, pathname: *const u8, statbuf: *mut u8, flags: i32) -> SysCallResult {
+    // An empty path with AT_EMPTY_PATH means `dirfd` itself; otherwise
+    // dirfd is ignored for now
+    if flags & AT_EMPTY_PATH != 0 && !pathname.is_null() && unsafe { *pathname } == 0 {
+        return sys_fstat(dirfd, statbuf);
+    }
+    let path = match resolve_user_path(pathname) {
+        Ok(path) => path,
+        Err(e) => return SysCallResult::Error(e),
+    };
+    match filesystem::stat(&path, flags & AT_SYMLINK_NOFOLLOW == 0) {
+        Ok(stat) => put_stat(statbuf, stat),
+        Err(e) => SysCallResult::Error(errno(e)),
+    }
+}
+
+fn sys_fstat(fd: i32, statbuf: *mut u8) -> SysCallResult {
+    use crate::filesystem::traits::S_IFCHR;
+
+    // Standard input and output are the console
+    if (0..=STDERR_FD).contains(&fd) {
+        let console = FileStat { mode: S_IFCHR | 0o620, links: 1, block_size: 1024, ..FileStat::default() };
+        return put_stat(statbuf, console);
+    }
+    let file = match open_file(fd) {
+        Some(file) => file,
+        None => return SysCallResult::Error(crate::syscall::EBADF),
+    };
+    match filesystem::stat(&file.path, true) {
+        Ok(stat) => put_stat(statbuf, stat),
+        Err(e) => SysCallResult::Error(errno(e)),
+    }
+}
+
+/// Copies the link's target into `buf` with no NUL after it, cut to
+/// `bufsiz` bytes as Linux does, and returns how many bytes it copied
+fn sys_readlinkat(dirfd: i32, pathname: *const u8, buf: *mut u8, bufsiz: usize) -> SysCallResult {
+    let _ = dirfd; // Ignore dirfd for now
+    if buf.is_null() {
+        return SysCallResult::Error(crate::syscall::EFAULT);
+    }
+    if bufsiz == 0 {
+        return SysCallResult::Error(crate::syscall::EINVAL);
+    }
+    let path = match resolve_user_path(pathname) {
+        Ok(path) => path,
+        Err(e) => return SysCallResult::Error(e),
+    };
+    match filesystem::read_link(&path) {
+        Ok(target) => {
+            let len = target.len().min(bufsiz);
+            unsafe {
+                core::ptr::copy_nonoverlapping(target.as_ptr(), buf, len);
             }
-        } else {
-            SysCallResult::Error(crate::syscall::EINVAL)
+            SysCallResult::Success(len as isize)
         }
+        Err(e) => SysCallResult::Error(errno(e)),
     }
 }
 
-// === TODO: IMPLEMENT ADDITIONAL FILE OPERATIONS ===
-
-fn sys_lseek(_fd: i32, _offset: isize, _whence: i32) -> SysCallResult {
-    // TODO: Implement file seek
-    SysCallResult::Error(crate::syscall::ENOSYS)
+fn sys_lseek(fd: i32, offset: isize, whence: i32) -> SysCallResult {
+    let file = match open_file(fd) {
+        Some(file) => file,
+        None => return SysCallResult::Error(crate::syscall::EBADF),
+    };
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => file.offset as isize,
+        SEEK_END => match filesystem::get_file_size(&file.path) {
+            Ok(size) => size as isize,
+            Err(e) => return SysCallResult::Error(errno(e)),
+        },
+        _ => return SysCallResult::Error(crate::syscall::EINVAL),
+    };
+    // Past the end is allowed; the file grows when written there
+    match base.checked_add(offset) {
+        Some(position) if position >= 0 => {
+            set_offset(fd, position as usize);
+            SysCallResult::Success(position)
+        }
+        _ => SysCallResult::Error(crate::syscall::EINVAL),
+    }
 }
 
-fn sys_truncate(_path: *const u8, _length: usize) -> SysCallResult {
-    // TODO: Implement file truncation
-    SysCallResult::Error(crate::syscall::ENOSYS)
+fn sys_truncate(path: *const u8, length: usize) -> SysCallResult {
+    match resolve_user_path(path) {
+        Ok(path) => match filesystem::truncate_file(&path, length) {
+            Ok(()) => SysCallResult::Success(0),
+            Err(e) => SysCallResult::Error(errno(e)),
+        },
+        Err(e) => SysCallResult::Error(e),
+    }
 }
 
-fn sys_ftruncate(_fd: i32, _length: usize) -> SysCallResult {
-    // TODO: Implement file descriptor truncation
-    SysCallResult::Error(crate::syscall::ENOSYS)
+fn sys_ftruncate(fd: i32, length: usize) -> SysCallResult {
+    let file = match open_file(fd) {
+        Some(file) if file.writable() => file,
+        _ => return SysCallResult::Error(crate::syscall::EBADF),
+    };
+    match filesystem::truncate_file(&file.path, length) {
+        Ok(()) => SysCallResult::Success(0),
+        Err(e) => SysCallResult::Error(errno(e)),
+    }
 }
 
 fn sys_sync() -> SysCallResult {
-    // TODO: Implement filesystem sync
-    SysCallResult::Error(crate::syscall::ENOSYS)
+    let flushed = crate::klog::flush_to_disk()
+        .and_then(|()| filesystem::append::flush_all())
+        .and_then(|()| filesystem::cache::flush());
+    match flushed {
+        Ok(()) => SysCallResult::Success(0),
+        Err(_) => SysCallResult::Error(crate::syscall::EIO),
+    }
 }
 
 fn sys_fsync(_fd: i32) -> SysCallResult {
-    // TODO: Implement file sync
-    SysCallResult::Error(crate::syscall::ENOSYS)
+    // No per-file tracking in the block cache: flush everything
+    sys_sync()
 }
 
 // Helper function to read file with path (for testing)
@@ -0,0 +1,130 @@
+// Graphics System Calls - elinOS-specific (920-939)
+// Drawing on the framebuffer for user programs: shapes, copies and images
+// go to the back buffer, and gfx_present shows them: gfx_fill (solid or
+// 8x8 pattern), gfx_line, gfx_circle, gfx_blit (scaled copies within the
+// screen) and gfx_image (with alpha blending)
+
+use super::{SysCallResult, SyscallArgs, EFAULT, EINVAL, ENODEV, ENOSYS};
+use crate::graphics::{self, draw::{self, Area, Pattern}};
+use elinos_common::syscall::*;
+
+// gfx_circle flags
+pub const GFX_FILLED: usize = 1;
+// gfx_image flags
+pub const GFX_BLEND: usize = 1;
+
+/// Largest image gfx_image takes, in pixels (16 MiB)
+const MAX_IMAGE_PIXELS: usize = 4 * 1024 * 1024;
+
+/// What gfx_info reports
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GfxInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row
+    pub pitch: u32,
+    pub bpp: u32,
+}
+
+pub fn handle_graphics_syscall(args: &SyscallArgs) -> SysCallResult {
+    match args.syscall_number {
+        SYS_GFX_INFO => sys_gfx_info(args.arg0_as_mut_ptr::<GfxInfo>()),
+        SYS_GFX_FILL => sys_gfx_fill(args.arg0_as_ptr::<Area>(), args.arg1 as u32, args.arg2 as u32, args.arg3 as *const Pattern),
+        SYS_GFX_LINE => sys_gfx_line(args.arg0 as i32, args.arg1 as i32, args.arg2 as i32, args.arg3 as i32, args.arg4 as u32),
+        SYS_GFX_CIRCLE => sys_gfx_circle(args.arg0 as i32, args.arg1 as i32, args.arg2 as u32, args.arg3 as u32, args.arg4),
+        SYS_GFX_BLIT => sys_gfx_blit(args.arg0_as_ptr::<Area>(), args.arg1_as_ptr::<Area>()),
+        SYS_GFX_IMAGE => sys_gfx_image(args.arg0_as_ptr::<u32>(), args.arg1, args.arg2, args.arg3 as i32, args.arg4 as i32, args.arg5),
+        SYS_GFX_PRESENT => sys_gfx_present(),
+        _ => SysCallResult::Error(ENOSYS),
+    }
+}
+
+/// Run `f` on the framebuffer, or fail with ENODEV if there is none
+fn draw_with(f: impl FnOnce(&mut graphics::SimpleFramebuffer)) -> SysCallResult {
+    match graphics::with_framebuffer(f) {
+        Ok(()) => SysCallResult::Success(0),
+        Err(_) => SysCallResult::Error(ENODEV),
+    }
+}
+
+/// Read an area from user memory and check it is within the limits
+fn read_area(ptr: *const Area) -> Result<Area, isize> {
+    if ptr.is_null() {
+        return Err(EFAULT);
+    }
+    let area = unsafe { core::ptr::read_unaligned(ptr) };
+    if !area.in_limits() {
+        return Err(EINVAL);
+    }
+    Ok(area)
+}
+
+// === SYSTEM CALL IMPLEMENTATIONS ===
+
+fn sys_gfx_info(info: *mut GfxInfo) -> SysCallResult {
+    if info.is_null() {
+        return SysCallResult::Error(EFAULT);
+    }
+    let Ok((width, height)) = graphics::get_dimensions() else {
+        return SysCallResult::Error(ENODEV);
+    };
+    let reply = GfxInfo { width, height, pitch: width * 4, bpp: 32 };
+    unsafe { core::ptr::write_unaligned(info, reply) };
+    SysCallResult::Success(0)
+}
+
+/// Fill an area with an 8x8 pattern, or solidly if `pattern` is null
+fn sys_gfx_fill(area: *const Area, foreground: u32, background: u32, pattern: *const Pattern) -> SysCallResult {
+    let area = match read_area(area) {
+        Ok(area) => area,
+        Err(errno) => return SysCallResult::Error(errno),
+    };
+    let pattern = if pattern.is_null() { draw::SOLID } else { unsafe { core::ptr::read_unaligned(pattern) } };
+    draw_with(|fb| fb.fill_pattern(area, &pattern, foreground, background))
+}
+
+fn sys_gfx_line(x0: i32, y0: i32, x1: i32, y1: i32, color: u32) -> SysCallResult {
+    if !draw::in_limits(&[x0.into(), y0.into(), x1.into(), y1.into()]) {
+        return SysCallResult::Error(EINVAL);
+    }
+    draw_with(|fb| fb.draw_line(x0, y0, x1, y1, color))
+}
+
+fn sys_gfx_circle(cx: i32, cy: i32, radius: u32, color: u32, flags: usize) -> SysCallResult {
+    if flags & !GFX_FILLED != 0 || !draw::in_limits(&[cx.into(), cy.into(), radius.into()]) {
+        return SysCallResult::Error(EINVAL);
+    }
+    draw_with(|fb| fb.draw_circle(cx, cy, radius, color, flags & GFX_FILLED != 0))
+}
+
+fn sys_gfx_blit(src: *const Area, dst: *const Area) -> SysCallResult {
+    let (src, dst) = match (read_area(src), read_area(dst)) {
+        (Ok(src), Ok(dst)) => (src, dst),
+        (Err(errno), _) | (_, Err(errno)) => return SysCallResult::Error(errno),
+    };
+    draw_with(|fb| fb.blit(src, dst))
+}
+
+/// Draw a `width` x `height` image of 0xAARRGGBB pixels at (x, y)
+fn sys_gfx_image(pixels: *const u32, width: usize, height: usize, x: i32, y: i32, flags: usize) -> SysCallResult {
+    if pixels.is_null() || !pixels.is_aligned() {
+        return SysCallResult::Error(EFAULT);
+    }
+    let count = width.saturating_mul(height);
+    let limit = draw::COORD_LIMIT as usize;
+    if flags & !GFX_BLEND != 0 || width > limit || height > limit || count > MAX_IMAGE_PIXELS
+        || !draw::in_limits(&[x.into(), y.into()])
+    {
+        return SysCallResult::Error(EINVAL);
+    }
+    let pixels = unsafe { core::slice::from_raw_parts(pixels, count) };
+    draw_with(|fb| fb.draw_image(pixels, width as u32, height as u32, x, y, flags & GFX_BLEND != 0))
+}
+
+fn sys_gfx_present() -> SysCallResult {
+    match graphics::present() {
+        Ok(()) => SysCallResult::Success(0),
+        Err(_) => SysCallResult::Error(ENODEV),
+    }
+}
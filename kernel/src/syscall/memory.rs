@@ -1,44 +1,10 @@
 // Memory Management System Calls - Linux Compatible Numbers
 // Following Linux ARM64/RISC-V syscall numbers for compatibility
 
-use crate::{memory, console_println};
+use crate::{memory, console_println, ok_println, err_println, info_println};
+use crate::memory::mmu::{self, PAGE_SIZE, PTE_R, PTE_W, PTE_X};
 use super::{SysCallResult, SyscallArgs};
-
-// === LINUX COMPATIBLE MEMORY MANAGEMENT SYSTEM CALL CONSTANTS ===
-pub const SYS_BRK: usize = 214;           // Linux: brk
-pub const SYS_MUNMAP: usize = 215;        // Linux: munmap
-pub const SYS_MREMAP: usize = 216;        // Linux: mremap
-pub const SYS_ADD_KEY: usize = 217;       // Linux: add_key
-pub const SYS_REQUEST_KEY: usize = 218;   // Linux: request_key
-pub const SYS_KEYCTL: usize = 219;        // Linux: keyctl
-pub const SYS_CLONE: usize = 220;         // Linux: clone (defined in process.rs)
-pub const SYS_EXECVE: usize = 221;        // Linux: execve (defined in process.rs)
-pub const SYS_MMAP: usize = 222;          // Linux: mmap
-pub const SYS_FADVISE64: usize = 223;     // Linux: fadvise64
-pub const SYS_SWAPON: usize = 224;        // Linux: swapon
-pub const SYS_SWAPOFF: usize = 225;       // Linux: swapoff
-pub const SYS_MPROTECT: usize = 226;      // Linux: mprotect
-pub const SYS_MSYNC: usize = 227;         // Linux: msync
-pub const SYS_MLOCK: usize = 228;         // Linux: mlock
-pub const SYS_MUNLOCK: usize = 229;       // Linux: munlock
-pub const SYS_MLOCKALL: usize = 230;      // Linux: mlockall
-pub const SYS_MUNLOCKALL: usize = 231;    // Linux: munlockall
-pub const SYS_MINCORE: usize = 232;       // Linux: mincore
-pub const SYS_MADVISE: usize = 233;       // Linux: madvise
-pub const SYS_REMAP_FILE_PAGES: usize = 234; // Linux: remap_file_pages
-pub const SYS_MBIND: usize = 235;         // Linux: mbind
-pub const SYS_GET_MEMPOLICY: usize = 236; // Linux: get_mempolicy
-pub const SYS_SET_MEMPOLICY: usize = 237; // Linux: set_mempolicy
-pub const SYS_MIGRATE_PAGES: usize = 238; // Linux: migrate_pages
-pub const SYS_MOVE_PAGES: usize = 239;    // Linux: move_pages
-
-// Legacy syscall aliases for backwards compatibility
-pub const SYS_SBRK: usize = SYS_BRK;      // Map sbrk to brk
-
-// elinOS-specific memory syscalls (keeping high numbers to avoid conflicts)
-pub const SYS_GETMEMINFO: usize = 960;   // elinOS: get memory info
-pub const SYS_ALLOC_TEST: usize = 961;   // elinOS: test allocator
-pub const SYS_BUDDY_STATS: usize = 962;  // elinOS: buddy allocator stats
+use elinos_common::syscall::*;
 
 // Memory protection flags
 pub const PROT_READ: usize = 1;
@@ -79,10 +45,31 @@ pub fn handle_memory_syscall(args: &SyscallArgs) -> SysCallResult {
 
 // === SYSTEM CALL IMPLEMENTATIONS ===
 
-fn sys_mmap(addr: usize, length: usize, prot: usize, flags: usize, _fd: usize, _offset: usize) -> SysCallResult {
-    console_println!("mmap called: addr=0x{:x}, len={}, prot={}, flags={}", addr, length, prot, flags);
+/// Page table flags for mmap protection bits
+fn prot_pte_flags(prot: usize) -> u64 {
+    let mut pte_flags = 0;
+    if prot & PROT_READ != 0 {
+        pte_flags |= PTE_R;
+    }
+    if prot & PROT_WRITE != 0 {
+        pte_flags |= PTE_R | PTE_W; // W without R is a reserved encoding
+    }
+    if prot & PROT_EXEC != 0 {
+        pte_flags |= PTE_X;
+    }
+    pte_flags
+}
+
+fn sys_mmap(addr: usize, length: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> SysCallResult {
+    if length == 0 || flags & (MAP_SHARED | MAP_PRIVATE) == 0 {
+        return SysCallResult::Error(crate::syscall::EINVAL);
+    }
+    
+    if mmu::is_user_space_active() {
+        return mmap_user(addr, length, prot, flags, fd as i32, offset);
+    }
     
-    // For anonymous mappings, use our buddy allocator
+    // Called from the kernel: hand out heap memory
     if flags & MAP_ANONYMOUS != 0 {
         if let Ok(allocated_addr) = memory::allocate_memory(length, 8) {
             let addr = allocated_addr.as_ptr() as usize;
@@ -93,13 +80,77 @@ fn sys_mmap(addr: usize, length: usize, prot: usize, flags: usize, _fd: usize, _
         }
     }
     
-            SysCallResult::Error(crate::syscall::ENOSYS)
+    SysCallResult::Error(crate::syscall::ENOSYS)
+}
+
+/// mmap in the address space of the running program. Files are mapped as
+/// private copies read in now; shared file mappings are only allowed
+/// read-only, as writes could not reach the file.
+fn mmap_user(addr: usize, length: usize, prot: usize, flags: usize, fd: i32, offset: usize) -> SysCallResult {
+    let fixed = flags & MAP_FIXED != 0;
+    if fixed && addr % PAGE_SIZE != 0 {
+        return SysCallResult::Error(crate::syscall::EINVAL);
+    }
+    
+    let path = if flags & MAP_ANONYMOUS != 0 {
+        None
+    } else {
+        if offset % PAGE_SIZE != 0 {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        if flags & MAP_SHARED != 0 && prot & PROT_WRITE != 0 {
+            return SysCallResult::Error(crate::syscall::EACCES);
+        }
+        match super::file::mapped_file_path(fd) {
+            Ok(path) => Some(path),
+            Err(errno) => return SysCallResult::Error(errno),
+        }
+    };
+    
+    let fill = |contents: &mut [u8]| {
+        let path = match &path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // Past the end of the file the mapping reads as zeros
+        let mut done = 0;
+        while done < contents.len() {
+            match crate::filesystem::read_file_at(path, offset + done, &mut contents[done..]) {
+                Ok(0) => break,
+                Ok(read) => done += read,
+                Err(_) => return Err("Failed to read mapped file"),
+            }
+        }
+        Ok(())
+    };
+    
+    let pte_flags = prot_pte_flags(prot);
+    // A hint is taken if that range is free; MAP_FIXED insists on it
+    let source = path.as_deref();
+    let mut result = mmu::map_current_mmap((addr != 0 && addr % PAGE_SIZE == 0).then_some(addr), length, pte_flags, source, fill);
+    if result.is_err() && !fixed && addr != 0 {
+        result = mmu::map_current_mmap(None, length, pte_flags, source, fill);
+    }
+    match result {
+        Ok(vaddr) => SysCallResult::Success(vaddr as isize),
+        Err("Failed to read mapped file") => SysCallResult::Error(crate::syscall::EIO),
+        Err(_) if fixed => SysCallResult::Error(crate::syscall::EINVAL),
+        Err(_) => SysCallResult::Error(crate::syscall::ENOMEM),
+    }
 }
 
 fn sys_munmap(addr: usize, length: usize) -> SysCallResult {
-    console_println!("munmap called: addr=0x{:x}, len={}", addr, length);
+    if mmu::is_user_space_active() {
+        if addr % PAGE_SIZE != 0 || length == 0 {
+            return SysCallResult::Error(crate::syscall::EINVAL);
+        }
+        return match mmu::unmap_current_mmap(addr, length) {
+            Ok(()) => SysCallResult::Success(0),
+            Err(_) => SysCallResult::Error(crate::syscall::EINVAL),
+        };
+    }
     
-    // Use our deallocator
+    // Heap memory handed out by a kernel mmap
     if let Some(ptr) = core::ptr::NonNull::new(addr as *mut u8) {
         memory::deallocate_memory(ptr, length);
     }
@@ -137,9 +188,31 @@ fn sys_munlockall() -> SysCallResult {
     SysCallResult::Success(0) // Pretend success for now
 }
 
+/// brk for the running program. Like Linux, it returns the new break, or
+/// the old one if the heap cannot be moved there (C libraries build sbrk on
+/// that).
+fn brk_user(addr: usize) -> SysCallResult {
+    let mut pm = crate::process::PROCESS_MANAGER.lock();
+    let pid = pm.get_current_pid();
+    let process = match pm.get_process_mut(pid) {
+        Some(process) if process.heap_start != 0 => process,
+        _ => return SysCallResult::Error(crate::syscall::ENOMEM),
+    };
+    
+    if addr >= process.heap_start && addr != process.brk
+        && mmu::move_current_break(process.brk, addr).is_ok()
+    {
+        process.brk = addr;
+    }
+    SysCallResult::Success(process.brk as isize)
+}
+
 fn sys_brk(addr: usize) -> SysCallResult {
-    console_println!("brk called: addr=0x{:x}", addr);
+    if mmu::is_user_space_active() {
+        return brk_user(addr);
+    }
     
+    // Called from the kernel: the break is a kernel heap allocation
     unsafe {
         if addr == 0 {
             // Query current break
@@ -214,7 +287,7 @@ fn sys_alloc_test(size: usize) -> SysCallResult {
     
     if let Ok(addr) = memory::allocate_memory(size, 8) {
         let addr = addr.as_ptr() as usize;
-        console_println!("[o] Allocated {} bytes at 0x{:x}", size, addr);
+        ok_println!("Allocated {} bytes at 0x{:x}", size, addr);
         
         // Test writing to the memory
         unsafe {
@@ -222,20 +295,20 @@ fn sys_alloc_test(size: usize) -> SysCallResult {
             *ptr = 0xAA; // Write test pattern
             let read_val = *ptr;
             if read_val == 0xAA {
-                console_println!("[o] Memory write/read test passed");
+                ok_println!("Memory write/read test passed");
             } else {
-                console_println!("[x] Memory write/read test failed: wrote 0xAA, read 0x{:x}", read_val);
+                err_println!("Memory write/read test failed: wrote 0xAA, read 0x{:x}", read_val);
             }
         }
         
         // Show updated stats
         let stats = memory::get_memory_stats();
-        console_println!("[i] Updated stats: {} allocations, {} bytes allocated", 
+        info_println!("Updated stats: {} allocations, {} bytes allocated", 
                         stats.allocation_count, stats.allocated_bytes);
         
         SysCallResult::Success(addr as isize)
     } else {
-        console_println!("[x] Allocation failed");
+        err_println!("Allocation failed");
         SysCallResult::Error(crate::syscall::ENOMEM)
     }
 }
@@ -36,7 +36,9 @@ pub const EMLINK: isize = 31;    // Too many links
 pub const EPIPE: isize = 32;     // Broken pipe
 pub const EDOM: isize = 33;      // Math argument out of domain of func
 pub const ERANGE: isize = 34;    // Math result not representable
+pub const ENAMETOOLONG: isize = 36; // File name too long
 pub const ENOSYS: isize = 38;    // Function not implemented
+pub const ELOOP: isize = 40;     // Too many symbolic links encountered
 
 // Import all syscall category modules
 pub mod file;
@@ -45,20 +47,23 @@ pub mod memory;
 pub mod process;
 pub mod device;
 pub mod network;
-pub mod time;
-pub mod sysinfo;
 pub mod elinos;
+pub mod abitest;
+pub mod time;
+pub mod graphics;
+
+// Syscall numbers and the table they come from
+pub use elinos_common::syscall::*;
 
-// Re-export all syscall constants for easy access
+// Re-export the handlers
 pub use file::*;
 pub use directory::*;
 pub use memory::*;
 pub use process::*;
 pub use device::*;
 pub use network::*;
-pub use time::*;
-pub use sysinfo::*;
 pub use elinos::*;
+pub use time::*;
 
 // System call results
 #[derive(Debug)]
@@ -153,101 +158,26 @@ pub const STDERR_FD: i32 = 2;
 
 // System call categorization for debugging and documentation
 pub fn get_syscall_category(syscall_num: usize) -> &'static str {
-    match syscall_num {
-        // File I/O operations (Linux numbers)
-        35 | 45..=47 | 56..=64 | 78..=83 => "File I/O Operations",
-        
-        // Directory operations (Linux numbers)  
-        34 | 49..=55 => "Directory Operations",
-        
-        // Device and I/O management (Linux numbers)
-        23..=33 | 59 => "Device and I/O Management",
-        
-        // Process management (Linux numbers) - non-overlapping ranges
-        93..=100 | 129..=178 | 220..=221 | 260 => "Process Management",
-        
-        // Time operations (Linux numbers) - non-overlapping ranges  
-        101..=115 => "Time and Timer Operations",
-        
-        // System information (Linux numbers) - non-overlapping ranges
-        160..=168 | 169..=171 | 179 => "System Information",
-        
-        // Network operations (Linux numbers)
-        198..=213 => "Network Operations", 
-        
-        // Memory management (Linux numbers)
-        214..=239 => "Memory Management",
-        
-        // elinOS-specific operations
-        900..=999 => "elinOS-Specific Operations",
-        
-        _ => "Unknown Category",
-    }
+    lookup(syscall_num).map_or("Unknown Category", |syscall| syscall.category.name())
 }
 
 /// Unified system call handler - dispatches all syscalls to appropriate modules
 pub fn handle_syscall(args: SyscallArgs) -> SysCallResult {
     let syscall_num = args.syscall_number;
     
-    match syscall_num {
-        // === DEVICE AND I/O MANAGEMENT (Linux numbers) ===
-        23..=33 |      // dup, dup3, fcntl, ioctl, etc.
-        59 |           // pipe2
-        950            // elinOS: getdevices
-        => device::handle_device_syscall(&args),
-        
-        // === DIRECTORY OPERATIONS (Linux numbers) ===
-        34 |           // mkdirat
-        49..=55        // chdir, fchdir, chroot, fchmod, fchmodat, fchownat, fchown
-        => directory::handle_directory_syscall(&args),
-        
-        // === FILE I/O OPERATIONS (Linux numbers) ===
-        35 |           // unlinkat
-        45..=47 |      // truncate, ftruncate, fallocate  
-        56..=64 |      // openat, close, read, write, readv, writev, etc.
-        78..=83        // readlinkat, newfstatat, fstat, sync, fsync, fdatasync
-        => file::handle_file_syscall(&args),
-        
-        // === PROCESS MANAGEMENT (Linux numbers - first range) ===
-        93..=100       // exit, exit_group, waitid, futex, etc.
-        => process::handle_process_syscall(syscall_num, &args),
-        
-        // === TIME AND TIMER OPERATIONS (Linux numbers) ===
-        101..=115      // nanosleep, getitimer, setitimer, timer_*, clock_*
-        => time::handle_time_syscall(&args),
-        
-        // === PROCESS MANAGEMENT (Linux numbers - second range) ===
-        129..=178      // kill, getpid, getppid, etc.
-        => process::handle_process_syscall(syscall_num, &args),
-        
-        // === NETWORK OPERATIONS (Linux numbers) ===
-        198..=213      // socket, socketpair, bind, listen, accept, connect, etc.
-        => network::handle_network_syscall(&args),
-        
-        // === MEMORY MANAGEMENT (Linux numbers) ===
-        214..=239 |    // brk, munmap, mremap, mmap, mprotect, msync, mlock, etc.
-        960            // elinOS: getmeminfo
-        => memory::handle_memory_syscall(&args),
-        
-        // === PROCESS MANAGEMENT (Linux numbers - third range) ===
-        220..=221 |    // clone, execve
-        260            // wait4
-        => process::handle_process_syscall(syscall_num, &args),
-        
-        // === SYSTEM INFORMATION (Linux numbers) ===
-        970..=979      // elinOS: getsysinfo, getversion, etc.
-        => sysinfo::handle_sysinfo_syscall(&args),
-        
-        // === elinOS-SPECIFIC OPERATIONS ===
-        900..=949 |    // elinOS: load_elf, exec_elf, elf_info, etc.
-        980..=999      // elinOS: misc operations
-        => elinos::handle_elinos_syscall(&args),
-        
-        // === UNKNOWN SYSCALLS ===
-        _ => {
-            crate::console_println!("❓ Unknown syscall: {} (category: {})", 
-                syscall_num, get_syscall_category(syscall_num));
-            SysCallResult::Error(-1)
+    match lookup(syscall_num).map(|syscall| syscall.category) {
+        Some(Category::Device) => device::handle_device_syscall(&args),
+        Some(Category::Directory) => directory::handle_directory_syscall(&args),
+        Some(Category::File) => file::handle_file_syscall(&args),
+        Some(Category::Process) => process::handle_process_syscall(syscall_num, &args),
+        Some(Category::Network) => network::handle_network_syscall(&args),
+        Some(Category::Memory) => memory::handle_memory_syscall(&args),
+        Some(Category::Elinos) => elinos::handle_elinos_syscall(&args),
+        Some(Category::Time) => time::handle_time_syscall(&args),
+        Some(Category::Graphics) => graphics::handle_graphics_syscall(&args),
+        None => {
+            crate::warn_println!("Unknown syscall: {}", syscall_num);
+            SysCallResult::Error(ENOSYS)
         }
     }
 }
@@ -275,7 +205,7 @@ pub fn sys_print(s: &str) -> Result<(), &'static str> {
 
 // Utility function for memory info using SYS_GETMEMINFO  
 pub fn sys_memory_info() -> Result<(), &'static str> {
-    let result = handle_syscall(SyscallArgs::new(memory::SYS_GETMEMINFO, 0, 0, 0, 0));
+    let result = handle_syscall(SyscallArgs::new(SYS_GETMEMINFO, 0, 0, 0, 0));
     match result {
         SysCallResult::Success(_) => Ok(()),
         SysCallResult::Error(_) => Err("Syscall failed"),
@@ -284,7 +214,7 @@ pub fn sys_memory_info() -> Result<(), &'static str> {
 
 // Utility function for device info using SYS_GETDEVICES
 pub fn sys_device_info() -> Result<(), &'static str> {
-    let result = handle_syscall(SyscallArgs::new(device::SYS_GETDEVICES, 0, 0, 0, 0));
+    let result = handle_syscall(SyscallArgs::new(SYS_GETDEVICES, 0, 0, 0, 0));
     match result {
         SysCallResult::Success(_) => Ok(()),
         SysCallResult::Error(_) => Err("Syscall failed"),
@@ -293,36 +223,27 @@ pub fn sys_device_info() -> Result<(), &'static str> {
 
 // Debug function to show syscall categories
 pub fn sys_show_categories() -> Result<(), &'static str> {
-    crate::console_println!("System Call Categories (Linux Compatible Numbers):");
-    crate::console_println!("  File I/O Operations:");
-    crate::console_println!("    35: unlinkat, 45-47: truncate/ftruncate/fallocate");
-    crate::console_println!("    56-64: openat/close/read/write/readv/writev/sendfile/etc");
-    crate::console_println!("    78-83: readlinkat/newfstatat/fstat/sync/fsync/fdatasync");
-    crate::console_println!("  Directory Operations:");
-    crate::console_println!("    34: mkdirat, 49-55: chdir/fchdir/chroot/fchmod/etc");
-    crate::console_println!("  Memory Management:");
-    crate::console_println!("    214-239: brk/munmap/mremap/mmap/mprotect/mlock/etc");
-    crate::console_println!("    960: getmeminfo (elinOS-specific)");
-    crate::console_println!("  Process Management:");
-    crate::console_println!("    93-100: exit/waitid/futex/getpid/getppid/kill/etc");
-    crate::console_println!("    129-178: kill/getpid/getppid/etc");
-    crate::console_println!("    220-221: clone/execve, 260: wait4");
-    crate::console_println!("  Device and I/O Management:");
-    crate::console_println!("    23-33: dup/dup3/fcntl/ioctl/flock/mknodat/etc");
-    crate::console_println!("    59: pipe2, 950: getdevices (elinOS-specific)");
-    crate::console_println!("  Network Operations:");
-    crate::console_println!("    198-213: socket/bind/listen/accept/connect/etc");
-    crate::console_println!("  Time and Timer Operations:");
-    crate::console_println!("    101-115: nanosleep/getitimer/timer_*/clock_*");
-    crate::console_println!("  System Information:");
-    crate::console_println!("    160-168: uname/sethostname/getrlimit/setrlimit/etc");
-    crate::console_println!("    169-171: gettimeofday/settimeofday/adjtimex");
-    crate::console_println!("    179: sysinfo");
-    crate::console_println!("  elinOS-Specific Operations:");
-    crate::console_println!("    900-999: debug/version/shutdown/load_elf/exec_elf/etc");
+    crate::console_println!("System Calls (Linux Compatible Numbers):");
+    for category in Category::ALL.iter() {
+        crate::console_println!("  {}:", category.name());
+        for syscall in SYSCALLS.iter().filter(|syscall| syscall.category == *category) {
+            crate::console_println!("    {:>4}  {}", syscall.number, syscall.name);
+        }
+    }
     Ok(())
 }
 
+/// Print the table as C `#define`s for user programs
+pub fn sys_print_c_header() {
+    crate::console_println!("/* elinOS system call numbers, generated by `syscalls --header` */");
+    crate::console_println!("#ifndef ELINOS_SYSCALLS_H");
+    crate::console_println!("#define ELINOS_SYSCALLS_H");
+    for syscall in SYSCALLS.iter() {
+        crate::console_println!("#define {:<24}{}", syscall.constant, syscall.number);
+    }
+    crate::console_println!("#endif");
+}
+
 // Utility functions for printing numbers and formatting
 pub fn sys_print_num(num: u64) -> Result<(), &'static str> {
     // Convert number to string
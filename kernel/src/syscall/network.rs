@@ -1,23 +1,174 @@
-// Network Operations System Calls (221-270)
-// Handles network operations like socket, bind, listen, etc.
-
-use super::{SysCallResult, SyscallArgs};
-
-// === NETWORK OPERATIONS SYSTEM CALL CONSTANTS (221-270) ===
-pub const SYS_SOCKET: usize = 221;
-pub const SYS_BIND: usize = 222;
-pub const SYS_LISTEN: usize = 223;
-pub const SYS_ACCEPT: usize = 224;
-pub const SYS_CONNECT: usize = 225;
-pub const SYS_SEND: usize = 226;
-pub const SYS_RECV: usize = 227;
-pub const SYS_SENDTO: usize = 228;
-pub const SYS_RECVFROM: usize = 229;
-pub const SYS_SHUTDOWN: usize = 230;
-// Reserved for future network operations: 231-270
+// Network Operations System Calls (198-213, Linux numbers)
+// UDP sockets on top of the kernel network stack (crate::net)
+
+use super::{SysCallResult, SyscallArgs, EBADF, EINVAL, EFAULT, EIO, ENODEV, EMFILE, EAGAIN, ENOSYS};
+use elinos_common::syscall::*;
+use crate::net::{self, Ipv4Addr, NetError};
+use crate::net::udp;
+
+// Socket constants
+pub const AF_INET: usize = 2;
+pub const SOCK_DGRAM: usize = 2;
+pub const IPPROTO_UDP: usize = 17;
+const SOCK_TYPE_MASK: usize = 0xf; // Linux ORs SOCK_NONBLOCK/SOCK_CLOEXEC into the type
+pub const MSG_DONTWAIT: usize = 0x40;
+
+// Network error codes (Linux-compatible)
+pub const EMSGSIZE: isize = 90;
+pub const EPROTONOSUPPORT: isize = 93;
+pub const EAFNOSUPPORT: isize = 97;
+pub const EADDRINUSE: isize = 98;
+pub const ENETDOWN: isize = 100;
+pub const EDESTADDRREQ: isize = 89;
+pub const ETIMEDOUT: isize = 110;
+pub const EHOSTUNREACH: isize = 113;
+
+/// Size of struct sockaddr_in
+const SOCKADDR_IN_LEN: usize = 16;
 
 // Standardized network syscall handler
-pub fn handle_network_syscall(_args: &SyscallArgs) -> SysCallResult {
-    // TODO: Implement network operations
-    SysCallResult::Error(crate::syscall::ENOSYS)
-} 
\ No newline at end of file
+pub fn handle_network_syscall(args: &SyscallArgs) -> SysCallResult {
+    match args.syscall_number {
+        SYS_SOCKET => sys_socket(args.arg0, args.arg1, args.arg2),
+        SYS_BIND => sys_bind(args.arg0_as_i32(), args.arg1_as_ptr::<u8>(), args.arg2),
+        SYS_CONNECT => sys_connect(args.arg0_as_i32(), args.arg1_as_ptr::<u8>(), args.arg2),
+        SYS_SENDTO => sys_sendto(args.arg0_as_i32(), args.arg1_as_ptr::<u8>(), args.arg2, args.arg3,
+                                 args.arg4 as *const u8, args.arg5),
+        SYS_RECVFROM => sys_recvfrom(args.arg0_as_i32(), args.arg1_as_mut_ptr::<u8>(), args.arg2, args.arg3,
+                                     args.arg4 as *mut u8, args.arg5 as *mut u32),
+        _ => SysCallResult::Error(ENOSYS),
+    }
+}
+
+/// Errno for a network stack error
+fn net_errno(err: NetError) -> isize {
+    match err {
+        NetError::NoDevice => ENODEV,
+        NetError::NotConfigured => ENETDOWN,
+        NetError::HostUnreachable => EHOSTUNREACH,
+        NetError::PacketTooLarge => EMSGSIZE,
+        NetError::DeviceError => EIO,
+        NetError::NoSocket => EBADF,
+        NetError::TooManySockets => EMFILE,
+        NetError::AddressInUse => EADDRINUSE,
+        NetError::NotConnected => EDESTADDRREQ,
+        NetError::WouldBlock => EAGAIN,
+        NetError::Timeout => ETIMEDOUT,
+        NetError::Declined => EIO,
+        NetError::InvalidAddress => EINVAL,
+    }
+}
+
+/// Read a struct sockaddr_in from user memory
+fn read_sockaddr_in(addr: *const u8, len: usize) -> Result<(Ipv4Addr, u16), isize> {
+    if addr.is_null() {
+        return Err(EFAULT);
+    }
+    if len < SOCKADDR_IN_LEN {
+        return Err(EINVAL);
+    }
+
+    let raw = unsafe { core::slice::from_raw_parts(addr, SOCKADDR_IN_LEN) };
+    let family = u16::from_ne_bytes([raw[0], raw[1]]) as usize;
+    if family != AF_INET {
+        return Err(EAFNOSUPPORT);
+    }
+    let port = u16::from_be_bytes([raw[2], raw[3]]);
+    Ok((Ipv4Addr([raw[4], raw[5], raw[6], raw[7]]), port))
+}
+
+/// Write a struct sockaddr_in to user memory
+fn write_sockaddr_in(addr: *mut u8, ip: Ipv4Addr, port: u16) {
+    let mut raw = [0u8; SOCKADDR_IN_LEN];
+    raw[0..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+    raw[2..4].copy_from_slice(&port.to_be_bytes());
+    raw[4..8].copy_from_slice(&ip.0);
+    unsafe {
+        core::ptr::copy_nonoverlapping(raw.as_ptr(), addr, SOCKADDR_IN_LEN);
+    }
+}
+
+fn sys_socket(domain: usize, sock_type: usize, protocol: usize) -> SysCallResult {
+    if domain != AF_INET {
+        return SysCallResult::Error(EAFNOSUPPORT);
+    }
+    if sock_type & SOCK_TYPE_MASK != SOCK_DGRAM || (protocol != 0 && protocol != IPPROTO_UDP) {
+        return SysCallResult::Error(EPROTONOSUPPORT);
+    }
+    if !net::is_up() {
+        return SysCallResult::Error(ENETDOWN);
+    }
+
+    let fd = super::file::allocate_fd();
+    match udp::open(fd) {
+        Ok(()) => SysCallResult::Success(fd as isize),
+        Err(e) => SysCallResult::Error(net_errno(e)),
+    }
+}
+
+fn sys_bind(fd: i32, addr: *const u8, addrlen: usize) -> SysCallResult {
+    let (_, port) = match read_sockaddr_in(addr, addrlen) {
+        Ok(addr) => addr,
+        Err(e) => return SysCallResult::Error(e),
+    };
+
+    match udp::bind(fd, port) {
+        Ok(_) => SysCallResult::Success(0),
+        Err(e) => SysCallResult::Error(net_errno(e)),
+    }
+}
+
+fn sys_connect(fd: i32, addr: *const u8, addrlen: usize) -> SysCallResult {
+    let (ip, port) = match read_sockaddr_in(addr, addrlen) {
+        Ok(addr) => addr,
+        Err(e) => return SysCallResult::Error(e),
+    };
+
+    match udp::connect(fd, ip, port) {
+        Ok(()) => SysCallResult::Success(0),
+        Err(e) => SysCallResult::Error(net_errno(e)),
+    }
+}
+
+fn sys_sendto(fd: i32, buf: *const u8, len: usize, _flags: usize, addr: *const u8, addrlen: usize) -> SysCallResult {
+    if buf.is_null() && len > 0 {
+        return SysCallResult::Error(EFAULT);
+    }
+
+    let dest = if addr.is_null() {
+        None
+    } else {
+        match read_sockaddr_in(addr, addrlen) {
+            Ok(dest) => Some(dest),
+            Err(e) => return SysCallResult::Error(e),
+        }
+    };
+
+    let data = unsafe { core::slice::from_raw_parts(buf, len) };
+    match udp::send_to(fd, dest, data) {
+        Ok(sent) => SysCallResult::Success(sent as isize),
+        Err(e) => SysCallResult::Error(net_errno(e)),
+    }
+}
+
+fn sys_recvfrom(fd: i32, buf: *mut u8, len: usize, flags: usize, addr: *mut u8, addrlen: *mut u32) -> SysCallResult {
+    if buf.is_null() && len > 0 {
+        return SysCallResult::Error(EFAULT);
+    }
+
+    let data = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+    match udp::recv_from(fd, data, flags & MSG_DONTWAIT != 0) {
+        Ok((received, src, src_port)) => {
+            if !addr.is_null() && !addrlen.is_null() {
+                unsafe {
+                    if *addrlen as usize >= SOCKADDR_IN_LEN {
+                        write_sockaddr_in(addr, src, src_port);
+                    }
+                    *addrlen = SOCKADDR_IN_LEN as u32;
+                }
+            }
+            SysCallResult::Success(received as isize)
+        }
+        Err(e) => SysCallResult::Error(net_errno(e)),
+    }
+}
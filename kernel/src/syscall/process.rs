@@ -1,221 +1,15 @@
 // Process Management System Calls - Linux Compatible Numbers
 // Following Linux ARM64/RISC-V syscall numbers for compatibility
 
-use crate::{elf::{ElfLoader, ElfError}, console_println};
+use crate::{elf::{ElfLoader, ElfError}, console_println, ok_println, err_println, warn_println, info_println};
 use super::{SysCallResult, SyscallArgs};
+use elinos_common::syscall::*;
 use crate::trap::USER_PROGRAM_EXITED;
-use super::{ENOSYS, EINVAL, ENOEXEC};
-use heapless::Vec;
-use spin::Mutex;
-use lazy_static::lazy_static;
+use super::{ENOSYS, EINVAL, ENOEXEC, EACCES, EAGAIN, ECHILD, ENOMEM};
 
 // === PROCESS MANAGEMENT STRUCTURES ===
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ProcessState {
-    Running,
-    Waiting,
-    Zombie,   // Exited but parent hasn't collected exit status
-    Unused,
-}
-
-#[derive(Debug, Clone)]
-pub struct Process {
-    pub pid: i32,
-    pub ppid: i32,  // Parent process ID
-    pub state: ProcessState,
-    pub exit_code: Option<i32>,
-    pub memory_base: Option<usize>,  // Base address of process memory
-    pub memory_size: Option<usize>,  // Size of allocated memory
-}
-
-impl Process {
-    pub fn new() -> Self {
-        Self {
-            pid: 0,
-            ppid: 0,
-            state: ProcessState::Unused,
-            exit_code: None,
-            memory_base: None,
-            memory_size: None,
-        }
-    }
-    
-    pub fn new_with_pid(pid: i32, ppid: i32) -> Self {
-        Self {
-            pid,
-            ppid,
-            state: ProcessState::Running,
-            exit_code: None,
-            memory_base: None,
-            memory_size: None,
-        }
-    }
-}
-
-// Simple process table - support up to 64 processes
-const MAX_PROCESSES: usize = 64;
-
-pub struct ProcessManager {
-    processes: Vec<Process, MAX_PROCESSES>,
-    next_pid: i32,
-    current_pid: i32,
-}
-
-impl ProcessManager {
-    pub fn new() -> Self {
-        let mut pm = Self {
-            processes: Vec::new(),
-            next_pid: 1,
-            current_pid: 1,  // Start with init process (shell)
-        };
-        
-        // Create init process (the shell)
-        let init_process = Process::new_with_pid(1, 0);
-        pm.processes.push(init_process).ok();
-        
-        pm
-    }
-    
-    pub fn allocate_pid(&mut self) -> i32 {
-        let pid = self.next_pid;
-        self.next_pid += 1;
-        pid
-    }
-    
-    pub fn create_process(&mut self, ppid: i32) -> Option<i32> {
-        if self.processes.len() >= MAX_PROCESSES {
-            return None;
-        }
-        
-        let pid = self.allocate_pid();
-        let process = Process::new_with_pid(pid, ppid);
-        
-        self.processes.push(process).ok()?;
-        Some(pid)
-    }
-    
-    pub fn get_process(&self, pid: i32) -> Option<&Process> {
-        self.processes.iter().find(|p| p.pid == pid)
-    }
-    
-    pub fn get_process_mut(&mut self, pid: i32) -> Option<&mut Process> {
-        self.processes.iter_mut().find(|p| p.pid == pid)
-    }
-    
-    pub fn exit_process(&mut self, pid: i32, exit_code: i32) {
-        if let Some(process) = self.get_process_mut(pid) {
-            process.state = ProcessState::Zombie;
-            process.exit_code = Some(exit_code);
-            console_println!("[i] Process {} exited with code {}", pid, exit_code);
-        }
-    }
-    
-    pub fn wait_for_child(&mut self, parent_pid: i32) -> Option<(i32, i32)> {
-        // Find a zombie child process
-        for process in self.processes.iter_mut() {
-            if process.ppid == parent_pid && process.state == ProcessState::Zombie {
-                let child_pid = process.pid;
-                let exit_code = process.exit_code.unwrap_or(-1);
-                
-                // Remove the zombie process (reap it)
-                process.state = ProcessState::Unused;
-                
-                return Some((child_pid, exit_code));
-            }
-        }
-        None
-    }
-    
-    pub fn get_current_pid(&self) -> i32 {
-        self.current_pid
-    }
-    
-    pub fn set_current_pid(&mut self, pid: i32) {
-        self.current_pid = pid;
-    }
-}
-
-// Global process manager
-lazy_static! {
-    pub static ref PROCESS_MANAGER: Mutex<ProcessManager> = Mutex::new(ProcessManager::new());
-}
-
-// === LINUX COMPATIBLE PROCESS MANAGEMENT SYSTEM CALL CONSTANTS ===
-pub const SYS_EXIT: usize = 93;        // Linux: exit
-pub const SYS_EXIT_GROUP: usize = 94;  // Linux: exit_group
-pub const SYS_WAITID: usize = 95;      // Linux: waitid
-pub const SYS_SET_TID_ADDRESS: usize = 96; // Linux: set_tid_address
-pub const SYS_UNSHARE: usize = 97;     // Linux: unshare
-pub const SYS_FUTEX: usize = 98;       // Linux: futex
-pub const SYS_SET_ROBUST_LIST: usize = 99;  // Linux: set_robust_list
-pub const SYS_GET_ROBUST_LIST: usize = 100; // Linux: get_robust_list
-pub const SYS_NANOSLEEP: usize = 101;  // Linux: nanosleep
-
-pub const SYS_GETITIMER: usize = 102;  // Linux: getitimer
-pub const SYS_SETITIMER: usize = 103;  // Linux: setitimer
-pub const SYS_KEXEC_LOAD: usize = 104; // Linux: kexec_load
-pub const SYS_INIT_MODULE: usize = 105; // Linux: init_module
-pub const SYS_DELETE_MODULE: usize = 106; // Linux: delete_module
-
-pub const SYS_KILL: usize = 129;       // Linux: kill
-pub const SYS_TKILL: usize = 130;      // Linux: tkill
-pub const SYS_TGKILL: usize = 131;     // Linux: tgkill
-
-pub const SYS_RT_SIGSUSPEND: usize = 133;   // Linux: rt_sigsuspend
-pub const SYS_RT_SIGACTION: usize = 134;    // Linux: rt_sigaction
-pub const SYS_RT_SIGPROCMASK: usize = 135;  // Linux: rt_sigprocmask
-pub const SYS_RT_SIGPENDING: usize = 136;   // Linux: rt_sigpending
-pub const SYS_RT_SIGTIMEDWAIT: usize = 137; // Linux: rt_sigtimedwait
-pub const SYS_RT_SIGQUEUEINFO: usize = 138; // Linux: rt_sigqueueinfo
-pub const SYS_RT_SIGRETURN: usize = 139;    // Linux: rt_sigreturn
-
-pub const SYS_SETPRIORITY: usize = 140; // Linux: setpriority
-pub const SYS_GETPRIORITY: usize = 141; // Linux: getpriority
-pub const SYS_REBOOT: usize = 142;      // Linux: reboot
-
-pub const SYS_SETREGID: usize = 143;    // Linux: setregid
-pub const SYS_SETGID: usize = 144;      // Linux: setgid
-pub const SYS_SETREUID: usize = 145;    // Linux: setreuid
-pub const SYS_SETUID: usize = 146;      // Linux: setuid
-pub const SYS_SETRESUID: usize = 147;   // Linux: setresuid
-pub const SYS_GETRESUID: usize = 148;   // Linux: getresuid
-pub const SYS_SETRESGID: usize = 149;   // Linux: setresgid
-pub const SYS_GETRESGID: usize = 150;   // Linux: getresgid
-pub const SYS_SETFSUID: usize = 151;    // Linux: setfsuid
-pub const SYS_SETFSGID: usize = 152;    // Linux: setfsgid
-pub const SYS_TIMES: usize = 153;       // Linux: times
-pub const SYS_SETPGID: usize = 154;     // Linux: setpgid
-pub const SYS_GETPGID: usize = 155;     // Linux: getpgid
-pub const SYS_GETSID: usize = 156;      // Linux: getsid
-pub const SYS_SETSID: usize = 157;      // Linux: setsid
-pub const SYS_GETGROUPS: usize = 158;   // Linux: getgroups
-pub const SYS_SETGROUPS: usize = 159;   // Linux: setgroups
-
-pub const SYS_GETPID: usize = 172;      // Linux: getpid
-pub const SYS_GETPPID: usize = 173;     // Linux: getppid
-pub const SYS_GETUID: usize = 174;      // Linux: getuid
-pub const SYS_GETEUID: usize = 175;     // Linux: geteuid
-pub const SYS_GETGID: usize = 176;      // Linux: getgid
-pub const SYS_GETEGID: usize = 177;     // Linux: getegid
-pub const SYS_GETTID: usize = 178;      // Linux: gettid
-
-pub const SYS_CLONE: usize = 220;       // Linux: clone
-pub const SYS_EXECVE: usize = 221;      // Linux: execve
-
-// Additional syscalls
-pub const SYS_WAIT4: usize = 260;       // Linux: wait4
-
-// Legacy syscall aliases for backwards compatibility
-pub const SYS_FORK: usize = SYS_CLONE;  // Map fork to clone
-pub const SYS_WAIT: usize = SYS_WAITID; // Map wait to waitid
-pub const SYS_WAITPID: usize = SYS_WAITID; // Map waitpid to waitid
-pub const SYS_SIGNAL: usize = SYS_RT_SIGACTION; // Map signal to rt_sigaction
-
-// ELF loading syscalls - elinOS specific (keeping high numbers to avoid conflicts)
-pub const SYS_LOAD_ELF: usize = 900;    // elinOS: load ELF binary
-pub const SYS_EXEC_ELF: usize = 901;    // elinOS: execute ELF binary
-pub const SYS_ELF_INFO: usize = 902;    // elinOS: ELF binary info
+// The process table lives in crate::process; re-exported here for syscall users
+pub use crate::process::{Process, ProcessState, ProcessManager, WaitStatus, PROCESS_MANAGER};
 
 // Linux compatible process management syscall handler
 pub fn handle_process_syscall(syscall_num: usize, args: &SyscallArgs) -> SysCallResult {
@@ -224,7 +18,6 @@ pub fn handle_process_syscall(syscall_num: usize, args: &SyscallArgs) -> SysCall
         SYS_EXIT_GROUP => sys_exit_group(args.arg0 as i32),
         SYS_GETPID => sys_getpid(),
         SYS_GETPPID => sys_getppid(),
-        SYS_FORK => sys_fork(),
         SYS_CLONE => sys_clone(),
         SYS_EXECVE => sys_execve(),
         SYS_WAITID => sys_waitid(args.arg0 as i32, args.arg1 as i32, args.arg2 as *mut i32, args.arg3 as i32),
@@ -236,14 +29,11 @@ pub fn handle_process_syscall(syscall_num: usize, args: &SyscallArgs) -> SysCall
         SYS_SETGID => sys_setgid(args.arg0 as u32),
         SYS_GETEUID => sys_geteuid(),
         SYS_GETEGID => sys_getegid(),
+        SYS_GETTID => sys_gettid(),
         SYS_SETSID => sys_setsid(),
         SYS_GETPGID => sys_getpgid(args.arg0 as i32),
         SYS_SETPGID => sys_setpgid(args.arg0 as i32, args.arg1 as i32),
-        SYS_GETPGID => sys_getpgrp(),
         SYS_SCHED_YIELD => sys_sched_yield(),
-        SYS_NANOSLEEP => sys_nanosleep(args.arg0 as *const u8, args.arg1 as *mut u8),
-        SYS_ALARM => sys_alarm(args.arg0 as u32),
-        SYS_PAUSE => sys_pause(),
         SYS_PRCTL => sys_prctl(args.arg0 as i32, args.arg1 as u64, args.arg2 as u64, args.arg3 as u64, args.arg4 as u64),
         _ => SysCallResult::Error(ENOSYS), // Function not implemented
     }
@@ -252,77 +42,113 @@ pub fn handle_process_syscall(syscall_num: usize, args: &SyscallArgs) -> SysCall
 // === SYSTEM CALL IMPLEMENTATIONS ===
 
 pub fn sys_exit(exit_code: isize) -> SysCallResult {
-    console_println!("[i] SYS_EXIT: Process exiting with code {}", exit_code);
-    
-    // Update process state in process manager
+    // Record the exit status; the parent collects it with wait
     {
         let mut pm = PROCESS_MANAGER.lock();
         let current_pid = pm.get_current_pid();
         pm.exit_process(current_pid, exit_code as i32);
-        
-        // If this is not the init process (PID 1), return to parent
-        if current_pid != 1 {
-            // Find the parent process and make it current
-            if let Some(process) = pm.get_process(current_pid) {
-                let parent_pid = process.ppid;
-                pm.set_current_pid(parent_pid);
-                console_println!("[i] Returning control to parent process {}", parent_pid);
-            }
-        }
     }
     
-    // Set the global exit flag so the trap handler knows to jump to shell_loop
-    // instead of returning to user mode
+    // Set the global exit flag so the trap handler returns to the kernel
+    // context that started the program instead of resuming user mode
     {
         let mut exit_flag = USER_PROGRAM_EXITED.lock();
         *exit_flag = Some(exit_code as i32);
     }
     
-    console_println!("[i] Process cleanup complete, returning to shell...");
     SysCallResult::Success(exit_code)
 }
 
 fn sys_exit_group(status: i32) -> SysCallResult {
-    console_println!("[i] Process group exited with status: {}", status);
+    info_println!("Process group exited with status: {}", status);
     // For now, treat this the same as regular exit
     sys_exit(status as isize)
 }
 
+/// Fork the calling program. There is no scheduler, so the child runs to
+/// completion first (sharing the parent's pages copy-on-write) and the
+/// parent resumes afterwards with the child's PID; the child's exit status
+/// is collected with wait4.
 fn sys_fork() -> SysCallResult {
-    console_println!("[i] SYS_FORK: Creating child process");
+    use crate::memory::mmu;
     
-    let mut pm = PROCESS_MANAGER.lock();
-    let current_pid = pm.get_current_pid();
+    let child_frame = match crate::trap::fork_child_frame() {
+        Some(frame) => frame,
+        None => return SysCallResult::Error(EINVAL), // Only user programs can fork
+    };
     
-    // Create a new child process
-    match pm.create_process(current_pid) {
-        Some(child_pid) => {
-            console_println!("[o] Fork successful: parent={}, child={}", current_pid, child_pid);
-            
-            // In a real fork, we would:
-            // 1. Copy the parent's memory space to the child
-            // 2. Set up child's execution context
-            // 3. Return 0 to child, child_pid to parent
-            
-            // For now, we'll simulate this by returning the child PID to the parent
-            // The child process will be created when execve is called
-            SysCallResult::Success(child_pid as isize)
+    if !mmu::is_hardware_paging() {
+        // Copy-on-write needs real page tables
+        return SysCallResult::Error(ENOSYS);
+    }
+    
+    let (parent_pid, child_pid) = {
+        let mut pm = PROCESS_MANAGER.lock();
+        let parent_pid = pm.get_current_pid();
+        let (name, entry_point, heap_start, brk) = match pm.get_process(parent_pid) {
+            Some(parent) => (parent.name.clone(), parent.entry_point, parent.heap_start, parent.brk),
+            None => (heapless::String::new(), 0, 0, 0),
+        };
+        
+        match pm.spawn(&name, entry_point) {
+            Some(child_pid) => {
+                // The child's heap is the parent's, copy-on-write
+                if let Some(child) = pm.get_process_mut(child_pid) {
+                    child.heap_start = heap_start;
+                    child.brk = brk;
+                }
+                (parent_pid, child_pid)
+            }
+            None => {
+                err_println!("Fork failed: too many processes");
+                return SysCallResult::Error(EAGAIN);
+            }
         }
-        None => {
-            console_println!("[x] Fork failed: too many processes");
-            SysCallResult::Error(crate::syscall::ENOMEM)
+    };
+    
+    let child_satp = match mmu::fork_current_user_space() {
+        Ok(satp) => satp,
+        Err(e) => {
+            err_println!("Fork failed: {}", e);
+            let mut pm = PROCESS_MANAGER.lock();
+            pm.exit_process(child_pid, -1);
+            let _ = pm.wait_for_child(parent_pid, child_pid);
+            return SysCallResult::Error(ENOMEM);
+        }
+    };
+    
+    {
+        let mut pm = PROCESS_MANAGER.lock();
+        if let Some(child) = pm.get_process_mut(child_pid) {
+            child.satp = child_satp;
         }
+        pm.set_current_pid(child_pid);
     }
+    
+    let parent_satp = mmu::read_satp();
+    let exit_code = unsafe {
+        mmu::write_satp(child_satp as usize);
+        let exit_code = crate::trap::run_user_context(&child_frame);
+        mmu::write_satp(parent_satp);
+        exit_code
+    };
+    mmu::release_user_space(child_satp);
+    
+    let mut pm = PROCESS_MANAGER.lock();
+    pm.set_current_pid(parent_pid);
+    // sys_exit normally records the status; cover a child that faulted
+    pm.exit_process(child_pid, exit_code);
+    
+    SysCallResult::Success(child_pid as isize)
 }
 
 fn sys_clone() -> SysCallResult {
-    console_println!("[i] SYS_CLONE: Redirecting to fork");
-    // For simplicity, treat clone as fork
+    // Threads are not supported: every clone is a fork
     sys_fork()
 }
 
 fn sys_execve() -> SysCallResult {
-    console_println!("[i] SYS_EXECVE: Replacing process image");
+    info_println!("SYS_EXECVE: Replacing process image");
     
     // For now, we'll implement a simple version that works with our ELF loader
     // In a real implementation, we would:
@@ -331,22 +157,27 @@ fn sys_execve() -> SysCallResult {
     // 3. Replace the current process's memory space
     // 4. Jump to the new program's entry point
     
-    console_println!("[!] EXECVE: Current implementation uses direct ELF execution");
-    console_println!("[!] Use the existing ELF execution system instead");
+    warn_println!("EXECVE: Current implementation uses direct ELF execution");
+    warn_println!("Use the existing ELF execution system instead");
     
     // Return success for now - real implementation would not return
     SysCallResult::Success(0)
 }
 
-fn sys_waitid(_which: i32, _pid: i32, _status: *mut i32, _options: i32) -> SysCallResult {
-    // TODO: Implement wait for child process
-    SysCallResult::Error(ENOSYS)
+fn sys_waitid(which: i32, pid: i32, status: *mut i32, options: i32) -> SysCallResult {
+    // P_ALL = 0, P_PID = 1
+    let target = match which {
+        0 => -1,
+        1 => pid,
+        _ => return SysCallResult::Error(EINVAL),
+    };
+    sys_wait4(target, status, options, core::ptr::null_mut())
 }
 
 fn sys_getpid() -> SysCallResult {
     let pm = PROCESS_MANAGER.lock();
     let current_pid = pm.get_current_pid();
-    console_println!("[i] SYS_GETPID: returning PID {}", current_pid);
+    info_println!("SYS_GETPID: returning PID {}", current_pid);
     SysCallResult::Success(current_pid as isize)
 }
 
@@ -355,10 +186,10 @@ fn sys_getppid() -> SysCallResult {
     let current_pid = pm.get_current_pid();
     
     if let Some(process) = pm.get_process(current_pid) {
-        console_println!("[i] SYS_GETPPID: returning PPID {}", process.ppid);
+        info_println!("SYS_GETPPID: returning PPID {}", process.ppid);
         SysCallResult::Success(process.ppid as isize)
     } else {
-        console_println!("[x] SYS_GETPPID: current process not found");
+        err_println!("SYS_GETPPID: current process not found");
         SysCallResult::Success(0) // Return init as default parent
     }
 }
@@ -382,7 +213,7 @@ fn sys_gettid() -> SysCallResult {
 }
 
 fn sys_kill(_pid: i32, _sig: i32) -> SysCallResult {
-    console_println!("[x] Kill not implemented");
+    err_println!("Kill not implemented");
     SysCallResult::Error(ENOSYS)
 }
 
@@ -417,13 +248,13 @@ pub fn sys_load_elf(data_ptr: *const u8, size: usize) -> SysCallResult {
     
     match loader.load_elf(elf_data) {
         Ok(loaded_elf) => {
-            console_println!("[o] ELF loaded successfully with {} segments", loaded_elf.segments.len());
-            console_println!("[i] Entry point: 0x{:x}", loaded_elf.entry_point);
+            ok_println!("ELF loaded successfully with {} segments", loaded_elf.segments.len());
+            info_println!("Entry point: 0x{:x}", loaded_elf.entry_point);
             
             // Display segment information
             for (i, segment) in loaded_elf.segments.iter().enumerate() {
                 let perms = crate::elf::segment_permissions(segment.flags);
-                console_println!("[i] Segment {}: 0x{:x} ({} bytes) [{}]", 
+                info_println!("Segment {}: 0x{:x} ({} bytes) [{}]", 
                     i, segment.vaddr, segment.memsz, perms);
             }
             
@@ -442,6 +273,10 @@ pub fn sys_load_elf(data_ptr: *const u8, size: usize) -> SysCallResult {
                 ElfError::ExecutionError => "Error executing ELF binary",
                 ElfError::MemoryAllocationFailed => "Memory allocation failed",
                 ElfError::InvalidEntryPoint => "Invalid entry point",
+                ElfError::SignatureMissing => "Binary is not signed",
+                ElfError::SignatureInvalid => "Binary signature verification failed",
+                ElfError::KeyUnavailable => "Signing key not available",
+                ElfError::UnsupportedRelocation => "Needs a dynamic linker",
             };
             SysCallResult::Error(ENOEXEC)
         }
@@ -458,18 +293,26 @@ pub fn sys_exec_elf(data_ptr: *const u8, size: usize) -> SysCallResult {
         core::slice::from_raw_parts(data_ptr, size)
     };
 
+    // Enforce the secure exec policy before loading anything
+    let elf_data = match crate::elf::verify::check_exec_policy(elf_data) {
+        Ok(image) => image,
+        Err(err) => {
+            err_println!("Exec denied: {}", err);
+            return SysCallResult::Error(EACCES);
+        }
+    };
+
     let loader = crate::elf::ElfLoader::new();
     
     // Load the ELF binary
     match loader.load_elf(elf_data) {
         Ok(loaded_elf) => {
-            console_println!("[o] ELF loaded, attempting execution...");
+            ok_println!("ELF loaded, attempting execution...");
             
-            // Execute the loaded ELF
-            match crate::elf::execute_elf(&loaded_elf) {
-                Ok(()) => {
-                    console_println!("[o] ELF execution completed successfully!");
-                    SysCallResult::Success(loaded_elf.entry_point as isize)
+            // Run as a child process and collect its exit status
+            match crate::process::run_program("exec", &loaded_elf) {
+                Ok((_pid, exit_code)) => {
+                    SysCallResult::Success(exit_code as isize)
                 }
                 Err(err) => {
                     let error_msg = match err {
@@ -492,6 +335,10 @@ pub fn sys_exec_elf(data_ptr: *const u8, size: usize) -> SysCallResult {
                 crate::elf::ElfError::ExecutionError => "Error executing ELF binary",
                 crate::elf::ElfError::MemoryAllocationFailed => "Memory allocation failed",
                 crate::elf::ElfError::InvalidEntryPoint => "Invalid entry point",
+                crate::elf::ElfError::SignatureMissing => "Binary is not signed",
+                crate::elf::ElfError::SignatureInvalid => "Binary signature verification failed",
+                crate::elf::ElfError::KeyUnavailable => "Signing key not available",
+                crate::elf::ElfError::UnsupportedRelocation => "Needs a dynamic linker",
             };
             SysCallResult::Error(ENOEXEC)
         }
@@ -523,101 +370,92 @@ pub fn sys_elf_info(data_ptr: *const u8, size: usize) -> SysCallResult {
                 ElfError::ExecutionError => "Error executing ELF binary",
                 ElfError::MemoryAllocationFailed => "Memory allocation failed",
                 ElfError::InvalidEntryPoint => "Invalid entry point",
+                ElfError::SignatureMissing => "Binary is not signed",
+                ElfError::SignatureInvalid => "Binary signature verification failed",
+                ElfError::KeyUnavailable => "Signing key not available",
+                ElfError::UnsupportedRelocation => "Needs a dynamic linker",
             };
             SysCallResult::Error(ENOEXEC)
         }
     }
 }
 
-fn sys_wait4(pid: i32, status: *mut i32, _options: i32, _rusage: *mut u8) -> SysCallResult {
-    console_println!("[i] SYS_WAIT4: Waiting for child process (pid={})", pid);
+// wait4 option flags
+pub const WNOHANG: i32 = 1;
+
+fn sys_wait4(pid: i32, status: *mut i32, options: i32, _rusage: *mut u8) -> SysCallResult {
+    if pid == 0 || pid < -1 {
+        // Process groups are not supported
+        return SysCallResult::Error(EINVAL);
+    }
     
     let mut pm = PROCESS_MANAGER.lock();
     let current_pid = pm.get_current_pid();
     
     // Wait for any child if pid == -1, or specific child if pid > 0
-    match pm.wait_for_child(current_pid) {
-        Some((child_pid, exit_code)) => {
-            console_println!("[o] Child process {} exited with code {}", child_pid, exit_code);
-            
-            // Write exit status to the status pointer if provided
+    match pm.wait_for_child(current_pid, pid) {
+        WaitStatus::Exited(child_pid, exit_code) => {
+            // Encode like Linux so WEXITSTATUS() works in user space
             if !status.is_null() {
                 unsafe {
-                    *status = exit_code;
+                    *status = (exit_code & 0xff) << 8;
                 }
             }
-            
-            // Return the PID of the child that exited
             SysCallResult::Success(child_pid as isize)
         }
-        None => {
-            // No zombie children available
-            console_println!("[i] No zombie children to wait for");
-            SysCallResult::Error(crate::syscall::ECHILD) // No child processes
+        WaitStatus::StillRunning => {
+            if options & WNOHANG != 0 {
+                SysCallResult::Success(0)
+            } else {
+                // Only one program runs at a time, so a running child can't
+                // finish while we block here
+                SysCallResult::Error(EAGAIN)
+            }
         }
+        WaitStatus::NoChildren => SysCallResult::Error(ECHILD),
     }
 }
 
 fn sys_setuid(_uid: u32) -> SysCallResult {
-    console_println!("[x] Setuid not implemented");
+    err_println!("Setuid not implemented");
     SysCallResult::Error(ENOSYS)
 }
 
 fn sys_setgid(_gid: u32) -> SysCallResult {
-    console_println!("[x] Setgid not implemented");
+    err_println!("Setgid not implemented");
     SysCallResult::Error(ENOSYS)
 }
 
 fn sys_geteuid() -> SysCallResult {
-    console_println!("[x] Geteuid not implemented");
+    err_println!("Geteuid not implemented");
     SysCallResult::Success(0) // Return root
 }
 
 fn sys_getegid() -> SysCallResult {
-    console_println!("[x] Getegid not implemented");
+    err_println!("Getegid not implemented");
     SysCallResult::Success(0) // Return root
 }
 
 fn sys_setsid() -> SysCallResult {
-    console_println!("[x] Setsid not implemented");
+    err_println!("Setsid not implemented");
     SysCallResult::Error(ENOSYS)
 }
 
 fn sys_getpgid(_pid: i32) -> SysCallResult {
-    console_println!("[x] Getpgid not implemented");
+    err_println!("Getpgid not implemented");
     SysCallResult::Success(1) // Return process group 1
 }
 
 fn sys_setpgid(_pid: i32, _pgid: i32) -> SysCallResult {
-    console_println!("[x] Setpgid not implemented");
+    err_println!("Setpgid not implemented");
     SysCallResult::Error(ENOSYS)
 }
 
-fn sys_getpgrp() -> SysCallResult {
-    console_println!("[x] Getpgrp not implemented");
-    SysCallResult::Success(1) // Return process group 1
-}
-
 fn sys_sched_yield() -> SysCallResult {
-    console_println!("[x] Sched_yield not implemented");
-    SysCallResult::Success(0)
-}
-
-fn sys_nanosleep(_req: *const u8, _rem: *mut u8) -> SysCallResult {
-    console_println!("[x] Nanosleep not implemented");
-    SysCallResult::Error(ENOSYS)
-}
-
-fn sys_alarm(_seconds: u32) -> SysCallResult {
-    console_println!("Alarm not implemented");
+    err_println!("Sched_yield not implemented");
     SysCallResult::Success(0)
 }
 
-fn sys_pause() -> SysCallResult {
-    console_println!("Pause not implemented");
-    SysCallResult::Error(ENOSYS)
-}
-
 fn sys_prctl(_option: i32, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64) -> SysCallResult {
     console_println!("Prctl not implemented");
     SysCallResult::Error(ENOSYS)
@@ -1,19 +0,0 @@
-// System Information System Calls (301-350)
-// Handles system information like uname, sysinfo, getuid, etc.
-
-use super::{SysCallResult, SyscallArgs};
-
-// === SYSTEM INFORMATION SYSTEM CALL CONSTANTS (301-350) ===
-pub const SYS_UNAME: usize = 301;
-pub const SYS_SYSINFO: usize = 302;
-pub const SYS_GETUID: usize = 303;
-pub const SYS_GETGID: usize = 304;
-pub const SYS_SETUID: usize = 305;
-pub const SYS_SETGID: usize = 306;
-// Reserved for future system info: 307-350
-
-// Standardized system info syscall handler
-pub fn handle_sysinfo_syscall(_args: &SyscallArgs) -> SysCallResult {
-    // TODO: Implement system information operations
-    SysCallResult::Error(crate::syscall::ENOSYS)
-} 
\ No newline at end of file
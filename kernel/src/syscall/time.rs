@@ -1,20 +1,216 @@
-// Time and Timer Operations System Calls (271-300)
-// Handles time operations like gettimeofday, nanosleep, etc.
+// Time System Calls - Linux Compatible Numbers
+// Clocks come from the `time` CSR, the kernel tick and the goldfish RTC;
+// timer descriptors are kept in `crate::timerfd`
 
-use super::{SysCallResult, SyscallArgs};
+use super::{SysCallResult, SyscallArgs, EBADF, EFAULT, EINVAL, EMFILE, ENOSYS};
+use crate::timerfd::{self, TimerError};
+use elinos_common::syscall::*;
+use elinos_common::{rtc, timer};
 
-// === TIME AND TIMER OPERATIONS SYSTEM CALL CONSTANTS (271-300) ===
-pub const SYS_TIME: usize = 271;
-pub const SYS_GETTIMEOFDAY: usize = 272;
-pub const SYS_SETTIMEOFDAY: usize = 273;
-pub const SYS_CLOCK_GETTIME: usize = 274;
-pub const SYS_CLOCK_SETTIME: usize = 275;
-pub const SYS_NANOSLEEP: usize = 276;
-pub const SYS_ALARM: usize = 277;
-// Reserved for future time operations: 278-300
+// Clock IDs (Linux values)
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+pub const CLOCK_MONOTONIC_RAW: usize = 4;
+pub const CLOCK_REALTIME_COARSE: usize = 5;
+pub const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub const CLOCK_BOOTTIME: usize = 7;
+
+// timerfd_create and timerfd_settime flags
+pub const TFD_TIMER_ABSTIME: i32 = 1;
+pub const TFD_NONBLOCK: i32 = 0o4000;
+pub const TFD_CLOEXEC: i32 = 0o2000000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// struct timespec
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timespec {
+    pub tv_sec: i64,
+    pub tv_nsec: i64,
+}
+
+impl Timespec {
+    fn from_ns(ns: u64) -> Self {
+        Timespec {
+            tv_sec: (ns / NANOS_PER_SEC) as i64,
+            tv_nsec: (ns % NANOS_PER_SEC) as i64,
+        }
+    }
+
+    /// Nanoseconds, or None if the fields are out of range
+    pub fn to_ns(self) -> Option<u64> {
+        if self.tv_sec < 0 || !(0..NANOS_PER_SEC as i64).contains(&self.tv_nsec) {
+            return None;
+        }
+        (self.tv_sec as u64).checked_mul(NANOS_PER_SEC)?.checked_add(self.tv_nsec as u64)
+    }
+}
+
+/// struct itimerspec
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Itimerspec {
+    pub it_interval: Timespec,
+    pub it_value: Timespec,
+}
+
+impl Itimerspec {
+    fn from_ns(value_ns: u64, interval_ns: u64) -> Self {
+        Itimerspec { it_interval: Timespec::from_ns(interval_ns), it_value: Timespec::from_ns(value_ns) }
+    }
+}
+
+/// struct timeval
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timeval {
+    pub tv_sec: i64,
+    pub tv_usec: i64,
+}
+
+/// struct timezone; always UTC here
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Timezone {
+    pub tz_minuteswest: i32,
+    pub tz_dsttime: i32,
+}
 
 // Standardized time syscall handler
-pub fn handle_time_syscall(_args: &SyscallArgs) -> SysCallResult {
-    // TODO: Implement time operations
-    SysCallResult::Error(crate::syscall::ENOSYS)
-} 
\ No newline at end of file
+pub fn handle_time_syscall(args: &SyscallArgs) -> SysCallResult {
+    match args.syscall_number {
+        SYS_NANOSLEEP => sys_nanosleep(args.arg0_as_ptr::<Timespec>(), args.arg1_as_mut_ptr::<Timespec>()),
+        SYS_CLOCK_GETTIME => sys_clock_gettime(args.arg0, args.arg1_as_mut_ptr::<Timespec>()),
+        SYS_GETTIMEOFDAY => sys_gettimeofday(args.arg0_as_mut_ptr::<Timeval>(), args.arg1_as_mut_ptr::<Timezone>()),
+        SYS_TIMERFD_CREATE => sys_timerfd_create(args.arg0, args.arg1_as_i32()),
+        SYS_TIMERFD_SETTIME => sys_timerfd_settime(args.arg0_as_i32(), args.arg1_as_i32(), args.arg2_as_ptr::<Itimerspec>(), args.arg3 as *mut Itimerspec),
+        SYS_TIMERFD_GETTIME => sys_timerfd_gettime(args.arg0_as_i32(), args.arg1_as_mut_ptr::<Itimerspec>()),
+        _ => SysCallResult::Error(ENOSYS),
+    }
+}
+
+/// Nanoseconds since boot, from the `time` CSR
+pub fn monotonic_ns() -> u64 {
+    timer::ticks_to_ns(timer::ticks())
+}
+
+/// Nanoseconds of clock `clock_id`, or None for an unknown clock
+pub fn clock_ns(clock_id: usize) -> Option<u64> {
+    match clock_id {
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_BOOTTIME => Some(monotonic_ns()),
+        CLOCK_MONOTONIC_COARSE => Some(crate::trap::tick_count() * (NANOS_PER_SEC / crate::trap::TICK_HZ)),
+        // Before the RTC is set, the epoch is boot
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => Some(rtc::unix_time_ns().unwrap_or_else(monotonic_ns)),
+        _ => None,
+    }
+}
+
+/// Sleep for `ns` nanoseconds
+pub fn sleep_ns(ns: u64) {
+    crate::trap::sleep_until(timer::ticks().saturating_add(timer::ns_to_ticks(ns)));
+}
+
+// === SYSTEM CALL IMPLEMENTATIONS ===
+
+fn sys_nanosleep(req: *const Timespec, _rem: *mut Timespec) -> SysCallResult {
+    if req.is_null() {
+        return SysCallResult::Error(EFAULT);
+    }
+    let req = unsafe { core::ptr::read_unaligned(req) };
+    let Some(ns) = req.to_ns() else {
+        return SysCallResult::Error(EINVAL);
+    };
+    // Nothing interrupts a sleep, so the remaining time is never reported
+    if crate::trap::in_slice() {
+        // A background job sleeps between its slices, not in them
+        crate::jobs::sleep_until(timer::ticks().saturating_add(timer::ns_to_ticks(ns)));
+    } else {
+        sleep_ns(ns);
+    }
+    SysCallResult::Success(0)
+}
+
+fn sys_clock_gettime(clock_id: usize, tp: *mut Timespec) -> SysCallResult {
+    let Some(ns) = clock_ns(clock_id) else {
+        return SysCallResult::Error(EINVAL);
+    };
+    if tp.is_null() {
+        return SysCallResult::Error(EFAULT);
+    }
+    unsafe { core::ptr::write_unaligned(tp, Timespec::from_ns(ns)) };
+    SysCallResult::Success(0)
+}
+
+fn sys_gettimeofday(tv: *mut Timeval, tz: *mut Timezone) -> SysCallResult {
+    if !tv.is_null() {
+        let now = clock_ns(CLOCK_REALTIME).unwrap_or(0);
+        let value = Timeval {
+            tv_sec: (now / NANOS_PER_SEC) as i64,
+            tv_usec: (now % NANOS_PER_SEC / 1000) as i64,
+        };
+        unsafe { core::ptr::write_unaligned(tv, value) };
+    }
+    if !tz.is_null() {
+        unsafe { core::ptr::write_unaligned(tz, Timezone { tz_minuteswest: 0, tz_dsttime: 0 }) };
+    }
+    SysCallResult::Success(0)
+}
+
+fn sys_timerfd_create(clock_id: usize, flags: i32) -> SysCallResult {
+    if !matches!(clock_id, CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME) {
+        return SysCallResult::Error(EINVAL);
+    }
+    if flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0 {
+        return SysCallResult::Error(EINVAL);
+    }
+    let fd = super::file::allocate_fd();
+    match timerfd::create(fd, clock_id, flags & TFD_NONBLOCK != 0) {
+        Ok(()) => SysCallResult::Success(fd as isize),
+        Err(_) => SysCallResult::Error(EMFILE),
+    }
+}
+
+fn sys_timerfd_settime(fd: i32, flags: i32, new_value: *const Itimerspec, old_value: *mut Itimerspec) -> SysCallResult {
+    if flags & !TFD_TIMER_ABSTIME != 0 {
+        return SysCallResult::Error(EINVAL);
+    }
+    if new_value.is_null() {
+        return SysCallResult::Error(EFAULT);
+    }
+    let new_value = unsafe { core::ptr::read_unaligned(new_value) };
+    let (Some(value_ns), Some(interval_ns)) = (new_value.it_value.to_ns(), new_value.it_interval.to_ns()) else {
+        return SysCallResult::Error(EINVAL);
+    };
+    match timerfd::set(fd, value_ns, interval_ns, flags & TFD_TIMER_ABSTIME != 0) {
+        Ok((old_ns, old_interval_ns)) => {
+            if !old_value.is_null() {
+                unsafe { core::ptr::write_unaligned(old_value, Itimerspec::from_ns(old_ns, old_interval_ns)) };
+            }
+            SysCallResult::Success(0)
+        }
+        Err(e) => SysCallResult::Error(timer_errno(e)),
+    }
+}
+
+fn sys_timerfd_gettime(fd: i32, curr_value: *mut Itimerspec) -> SysCallResult {
+    if curr_value.is_null() {
+        return SysCallResult::Error(EFAULT);
+    }
+    match timerfd::get(fd) {
+        Ok((value_ns, interval_ns)) => {
+            unsafe { core::ptr::write_unaligned(curr_value, Itimerspec::from_ns(value_ns, interval_ns)) };
+            SysCallResult::Success(0)
+        }
+        Err(e) => SysCallResult::Error(timer_errno(e)),
+    }
+}
+
+/// errno for a timer error
+pub fn timer_errno(error: TimerError) -> isize {
+    match error {
+        TimerError::NoTimer => EBADF,
+        TimerError::TooManyTimers => EMFILE,
+        TimerError::WouldBlock => super::EAGAIN,
+    }
+}
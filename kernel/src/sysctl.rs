@@ -0,0 +1,162 @@
+//! Kernel parameters that can be changed at run time
+//!
+//! Subsystems list their tunables in a `TUNABLES` table, each with a dotted
+//! name, a type and the functions that read and change it. The `sysctl`
+//! command and the files under /proc/sys go through here, so a value is
+//! checked against its type and range the same way whichever is used.
+//! Values saved with `sysctl -s` live in the config store as
+//! `sysctl.<name>` and are applied again at boot.
+
+use core::fmt::{self, Write};
+use heapless::String;
+use elinos_common::console;
+use elinos_common::warn_println;
+
+use crate::filesystem::{self, configstore, FilesystemResult};
+
+/// Prefix of tunables in the config store
+const STORE_PREFIX: &str = "sysctl.";
+
+/// The type of a tunable, with how to read and change it
+#[derive(Clone, Copy)]
+pub enum Kind {
+    /// A whole number from `min` to `max`
+    Int {
+        min: i64,
+        max: i64,
+        get: fn() -> i64,
+        set: fn(i64) -> Result<(), &'static str>,
+    },
+    /// One of a fixed set of names
+    Choice {
+        choices: &'static [&'static str],
+        get: fn() -> &'static str,
+        set: fn(&'static str) -> Result<(), &'static str>,
+    },
+}
+
+/// A runtime-tunable kernel parameter
+#[derive(Clone, Copy)]
+pub struct Tunable {
+    /// Dotted name, subsystem first, e.g. `fs.blockcache.sectors`
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: Kind,
+}
+
+/// The current value of a tunable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Choice(&'static str),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Choice(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Tunable {
+    pub fn value(&self) -> Value {
+        match self.kind {
+            Kind::Int { get, .. } => Value::Int(get()),
+            Kind::Choice { get, .. } => Value::Choice(get()),
+        }
+    }
+
+    /// Parse `text` as a value of this tunable and apply it
+    pub fn set(&self, text: &str) -> Result<(), &'static str> {
+        match self.kind {
+            Kind::Int { min, max, set, .. } => {
+                let n = text.parse::<i64>().map_err(|_| "Value is not a number")?;
+                if n < min || n > max {
+                    return Err("Value out of range");
+                }
+                set(n)
+            }
+            Kind::Choice { choices, set, .. } => {
+                let choice = choices.iter().copied().find(|&choice| choice == text).ok_or("Not one of the allowed values")?;
+                set(choice)
+            }
+        }
+    }
+
+    /// Values it takes, as `min..max` or `a|b|c`
+    pub fn range(&self) -> String<64> {
+        let mut out = String::new();
+        match self.kind {
+            Kind::Int { min, max, .. } => {
+                let _ = write!(out, "{}..{}", min, max);
+            }
+            Kind::Choice { choices, .. } => {
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        let _ = out.push('|');
+                    }
+                    let _ = out.push_str(choice);
+                }
+            }
+        }
+        out
+    }
+
+    /// The config store key its saved value goes under
+    fn store_key(&self) -> String<{ configstore::MAX_KEY_LEN }> {
+        let mut key = String::new();
+        let _ = key.push_str(STORE_PREFIX);
+        let _ = key.push_str(self.name);
+        key
+    }
+}
+
+const KERNEL_TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "kernel.printk",
+        description: "Status lines below this level are printed (3 errors, 4 warnings, 5 ok, 6 info, 7 debug, 8 trace)",
+        kind: Kind::Int { min: 0, max: console::MAX_LOG_LEVEL as i64, get: get_log_level, set: set_log_level },
+    },
+];
+
+fn get_log_level() -> i64 {
+    console::log_level() as i64
+}
+
+fn set_log_level(level: i64) -> Result<(), &'static str> {
+    console::set_log_level(level as u8);
+    Ok(())
+}
+
+/// Every subsystem's table
+const TABLES: &[&[Tunable]] = &[KERNEL_TUNABLES, crate::klog::TUNABLES, filesystem::cache::TUNABLES, crate::memory::swap::TUNABLES, crate::memory::memdebug::TUNABLES, crate::graphics::font::TUNABLES, crate::lineedit::TUNABLES, crate::watchdog::TUNABLES, crate::sound::TUNABLES];
+
+/// All tunables, in a fixed order
+pub fn tunables() -> impl Iterator<Item = &'static Tunable> {
+    TABLES.iter().flat_map(|table| table.iter())
+}
+
+/// The tunable called `name`
+pub fn find(name: &str) -> Option<&'static Tunable> {
+    tunables().find(|tunable| tunable.name == name)
+}
+
+/// Keep the current value of `tunable` across reboots
+pub fn save(tunable: &Tunable) -> FilesystemResult<()> {
+    let mut value: String<{ configstore::MAX_VALUE_LEN }> = String::new();
+    let _ = write!(value, "{}", tunable.value());
+    configstore::set(&tunable.store_key(), &value)
+}
+
+/// Apply the values saved in the config store; call after it is loaded
+pub fn load_persisted() {
+    for tunable in tunables() {
+        if let Some(value) = configstore::get(&tunable.store_key()) {
+            if let Err(e) = tunable.set(&value) {
+                warn_println!("Saved {}={} not applied: {}", tunable.name, value, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Timer file descriptors
+//!
+//! A timerfd expires once at a set time and then, if it has an interval,
+//! every interval after that. Reading it returns the number of expirations
+//! since the last read as a native-endian u64, waiting for the first one
+//! unless the descriptor is non-blocking; `ppoll` reports it readable once
+//! it has expired. Nothing runs when a timer expires: expirations are
+//! counted from the `time` CSR whenever the timer is read, polled or set,
+//! and waits sleep in `wfi` until the next one is due.
+
+use heapless::Vec;
+use spin::Mutex;
+use elinos_common::timer;
+
+/// Timer descriptors that can be open at once
+const MAX_TIMERS: usize = 8;
+
+/// An armed timer's schedule, in `time` CSR counts
+#[derive(Clone, Copy)]
+struct Schedule {
+    next: u64,
+    /// 0 for a one-shot timer
+    interval: u64,
+}
+
+/// An open timer descriptor
+struct Timer {
+    fd: i32,
+    /// Clock absolute settings are given in
+    clock: usize,
+    nonblocking: bool,
+    /// None while disarmed
+    schedule: Option<Schedule>,
+    /// Expirations not yet read
+    expirations: u64,
+}
+
+impl Timer {
+    /// Count the expirations due by `now`
+    fn update(&mut self, now: u64) {
+        let Some(schedule) = self.schedule.as_mut() else { return };
+        if now < schedule.next {
+            return;
+        }
+        if schedule.interval == 0 {
+            self.expirations += 1;
+            self.schedule = None;
+        } else {
+            let due = (now - schedule.next) / schedule.interval + 1;
+            self.expirations += due;
+            schedule.next += due * schedule.interval;
+        }
+    }
+}
+
+/// Why a timer operation failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimerError {
+    NoTimer,
+    TooManyTimers,
+    /// A non-blocking read found no expirations
+    WouldBlock,
+}
+
+static TIMERS: Mutex<Vec<Timer, MAX_TIMERS>> = Mutex::new(Vec::new());
+
+/// Run `f` on timer `fd`, brought up to date
+fn with_timer<T>(fd: i32, f: impl FnOnce(&mut Timer) -> T) -> Result<T, TimerError> {
+    let mut timers = TIMERS.lock();
+    let timer = timers.iter_mut().find(|t| t.fd == fd).ok_or(TimerError::NoTimer)?;
+    timer.update(timer::ticks());
+    Ok(f(timer))
+}
+
+/// Whether `fd` is an open timer
+pub fn is_timer(fd: i32) -> bool {
+    TIMERS.lock().iter().any(|t| t.fd == fd)
+}
+
+/// Create a disarmed timer on `clock` for descriptor `fd`
+pub fn create(fd: i32, clock: usize, nonblocking: bool) -> Result<(), TimerError> {
+    TIMERS.lock().push(Timer { fd, clock, nonblocking, schedule: None, expirations: 0 })
+        .map_err(|_| TimerError::TooManyTimers)
+}
+
+/// Close the timer for `fd`. Returns false if there is none.
+pub fn close(fd: i32) -> bool {
+    let mut timers = TIMERS.lock();
+    match timers.iter().position(|t| t.fd == fd) {
+        Some(index) => {
+            timers.swap_remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Nanoseconds until timer `fd` next expires (0 if disarmed) and its
+/// interval
+pub fn get(fd: i32) -> Result<(u64, u64), TimerError> {
+    with_timer(fd, |timer| match timer.schedule {
+        Some(schedule) => (
+            timer::ticks_to_ns(schedule.next.saturating_sub(timer::ticks())),
+            timer::ticks_to_ns(schedule.interval),
+        ),
+        None => (0, 0),
+    })
+}
+
+/// Arm timer `fd` to expire after `value_ns`, or at `value_ns` on its clock
+/// if `absolute`, then every `interval_ns`; a `value_ns` of 0 disarms it.
+/// Expirations not yet read are dropped. Returns the old setting as `get`
+/// would.
+pub fn set(fd: i32, value_ns: u64, interval_ns: u64, absolute: bool) -> Result<(u64, u64), TimerError> {
+    let old = get(fd)?;
+    with_timer(fd, |timer| {
+        timer.expirations = 0;
+        if value_ns == 0 {
+            timer.schedule = None;
+            return old;
+        }
+        let now = timer::ticks();
+        let delay_ns = if absolute {
+            let clock_now = crate::syscall::time::clock_ns(timer.clock).unwrap_or(0);
+            value_ns.saturating_sub(clock_now)
+        } else {
+            value_ns
+        };
+        timer.schedule = Some(Schedule {
+            next: now.saturating_add(timer::ns_to_ticks(delay_ns)),
+            interval: timer::ns_to_ticks(interval_ns),
+        });
+        old
+    })
+}
+
+/// Take the expirations of timer `fd`, waiting for one unless it is
+/// non-blocking
+pub fn read(fd: i32) -> Result<u64, TimerError> {
+    loop {
+        let (expirations, nonblocking, next) = with_timer(fd, |timer| {
+            (core::mem::take(&mut timer.expirations), timer.nonblocking, timer.schedule.map(|s| s.next))
+        })?;
+        if expirations > 0 {
+            return Ok(expirations);
+        }
+        // A disarmed timer never expires; Linux blocks forever
+        match (nonblocking, next) {
+            (true, _) => return Err(TimerError::WouldBlock),
+            (false, Some(next)) => crate::trap::sleep_until(next),
+            (false, None) => crate::trap::sleep_until(u64::MAX),
+        }
+    }
+}
+
+/// Whether timer `fd` has expirations to read
+pub fn readable(fd: i32) -> bool {
+    with_timer(fd, |timer| timer.expirations > 0).unwrap_or(false)
+}
+
+/// `time` CSR value at which timer `fd` next expires, if it is armed
+pub fn next_expiry(fd: i32) -> Option<u64> {
+    with_timer(fd, |timer| timer.schedule.map(|s| s.next)).ok().flatten()
+}
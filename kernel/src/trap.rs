@@ -2,10 +2,20 @@
 //! 
 //! This module provides exception and interrupt handling for the elinOS kernel.
 //! It includes proper trap vector setup and detailed crash information dumping.
+//!
+//! Device interrupts arrive through the PLIC. Console input is taken in the
+//! interrupt into a ring, so the shell sleeps in `wfi` instead of polling
+//! the UART, and VirtIO block and GPU requests sleep until their used-ring
+//! interrupt (`wait_until`). Every `scause` value is decoded by name and
+//! counted for `traps`. An exception in a user program kills only that
+//! program, as does a kernel fault inside one of its system calls.
 
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use spin::Mutex;
-use elinos_common::console_println;
+use elinos_common::{platform, sbi, timer, uart};
+use elinos_common::plic::Plic;
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println};
 use elinos_common::console_print;
 
 /// RISC-V trap causes
@@ -27,6 +37,9 @@ pub enum TrapCause {
     InstructionPageFault = 12,
     LoadPageFault = 13,
     StorePageFault = 15,
+    DoubleTrap = 16,
+    SoftwareCheck = 18,
+    HardwareError = 19,
     
     // Interrupts (scause MSB = 1)
     SupervisorSoftwareInterrupt = 1 | (1 << 63),
@@ -35,6 +48,7 @@ pub enum TrapCause {
     MachineTimerInterrupt = 7 | (1 << 63),
     SupervisorExternalInterrupt = 9 | (1 << 63),
     MachineExternalInterrupt = 11 | (1 << 63),
+    CounterOverflowInterrupt = 13 | (1 << 63),
     
     Unknown = 0xFFFFFFFFFFFFFFFF,
 }
@@ -56,6 +70,9 @@ impl From<u64> for TrapCause {
             12 => TrapCause::InstructionPageFault,
             13 => TrapCause::LoadPageFault,
             15 => TrapCause::StorePageFault,
+            16 => TrapCause::DoubleTrap,
+            18 => TrapCause::SoftwareCheck,
+            19 => TrapCause::HardwareError,
             v if v & (1 << 63) != 0 => match v & !((1u64) << 63) {
                 1 => TrapCause::SupervisorSoftwareInterrupt,
                 3 => TrapCause::MachineSoftwareInterrupt,
@@ -63,6 +80,7 @@ impl From<u64> for TrapCause {
                 7 => TrapCause::MachineTimerInterrupt,
                 9 => TrapCause::SupervisorExternalInterrupt,
                 11 => TrapCause::MachineExternalInterrupt,
+                13 => TrapCause::CounterOverflowInterrupt,
                 _ => TrapCause::Unknown,
             },
             _ => TrapCause::Unknown,
@@ -70,9 +88,54 @@ impl From<u64> for TrapCause {
     }
 }
 
+/// What scause `scause` means, for every value: the causes the privileged
+/// spec defines, and for the rest whether it reserves them or leaves them
+/// to custom or platform use
+pub fn describe(scause: u64) -> &'static str {
+    let code = scause & !(1 << 63);
+    if scause & (1 << 63) != 0 {
+        return match code {
+            1 => "supervisor software interrupt",
+            3 => "machine software interrupt",
+            5 => "supervisor timer interrupt",
+            7 => "machine timer interrupt",
+            9 => "supervisor external interrupt",
+            11 => "machine external interrupt",
+            13 => "counter overflow interrupt",
+            16.. => "platform interrupt",
+            _ => "reserved interrupt",
+        };
+    }
+    match code {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store/AMO address misaligned",
+        7 => "store/AMO access fault",
+        8 => "environment call from U-mode",
+        9 => "environment call from S-mode",
+        11 => "environment call from M-mode",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store/AMO page fault",
+        16 => "double trap",
+        18 => "software check",
+        19 => "hardware error",
+        20 => "instruction guest-page fault",
+        21 => "load guest-page fault",
+        22 => "virtual instruction",
+        23 => "store/AMO guest-page fault",
+        24..=31 | 48..=63 => "custom exception",
+        _ => "reserved exception",
+    }
+}
+
 /// Trap context - registers saved during trap
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TrapContext {
     pub x: [u64; 32],  // General purpose registers x0-x31
     pub sstatus: u64,  // Supervisor status
@@ -82,7 +145,7 @@ pub struct TrapContext {
 }
 
 impl TrapContext {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             x: [0; 32],
             sstatus: 0,
@@ -100,6 +163,7 @@ pub fn init_trap_handling() {
         asm!(
             "la t0, {trap_vector}",
             "csrw stvec, t0",
+            "csrw sscratch, zero",  // Running in the kernel
             trap_vector = sym trap_vector,
             options(nostack)
         );
@@ -114,24 +178,168 @@ pub fn init_trap_handling() {
     }
 }
 
+/// Kernel ticks per second
+pub const TICK_HZ: u64 = 100;
+
+/// `time` CSR counts between ticks, from the timebase frequency
+fn tick_interval() -> u64 {
+    (timer::frequency() / TICK_HZ).max(1)
+}
+
+/// sie.STIE: supervisor timer interrupts
+const SIE_STIE: usize = 1 << 5;
+
+// Ticks since the timer was started
+static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+// `time` value at which the next tick is due
+static NEXT_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// Start the periodic kernel tick from the SBI timer
+pub fn init_timer() {
+    NEXT_TICK.store(timer::ticks() + tick_interval(), Ordering::Relaxed);
+    sbi::set_timer(NEXT_TICK.load(Ordering::Relaxed));
+    unsafe {
+        asm!("csrs sie, {}", in(reg) SIE_STIE, options(nostack));
+    }
+}
+
+/// Ticks since the timer was started: a monotonic clock of `TICK_HZ`
+/// resolution
+pub fn tick_count() -> u64 {
+    TICK_COUNT.load(Ordering::Relaxed)
+}
+
+/// Count the ticks that are due and arm the timer for the next one. Ticks
+/// missed while interrupts were off are counted too, so the count keeps up
+/// with the time CSR.
+fn handle_timer_interrupt() {
+    let now = timer::ticks();
+    let mut next = NEXT_TICK.load(Ordering::Relaxed);
+    if now >= next {
+        let interval = tick_interval();
+        let due = (now - next) / interval + 1;
+        if due > 1 {
+            crate::health::count(crate::health::Event::LateTick);
+        }
+        TICK_COUNT.fetch_add(due, Ordering::Relaxed);
+        next += due * interval;
+        NEXT_TICK.store(next, Ordering::Relaxed);
+    }
+    // Also clears the pending interrupt
+    sbi::set_timer(next);
+}
+
+/// Wait with `wfi` until the `time` CSR reaches `deadline`
+pub fn sleep_until(deadline: u64) {
+    wait_until(deadline, || None::<()>);
+}
+
+/// Wait with `wfi` until `poll` returns a value, or give None once the
+/// `time` CSR reaches `deadline`. Interrupts are held off meanwhile and
+/// served here as they come in: a tick cannot re-arm the timer past the
+/// deadline, and the wait works the same in a system call, where they are
+/// off anyway.
+pub fn wait_until<T>(deadline: u64, mut poll: impl FnMut() -> Option<T>) -> Option<T> {
+    let sstatus: usize;
+    unsafe {
+        asm!("csrrci {}, sstatus, 2", out(reg) sstatus, options(nostack));
+    }
+    let result = loop {
+        handle_timer_interrupt();
+        if external_interrupts_enabled() {
+            handle_external_interrupt();
+        }
+        crate::watchdog::pet(crate::watchdog::Progress::Sleep);
+        if let Some(value) = poll() {
+            break Some(value);
+        }
+        if timer::ticks() >= deadline {
+            break None;
+        }
+        // Wake at whichever comes first; wfi returns once an interrupt is
+        // pending even with sstatus.SIE clear
+        if deadline < NEXT_TICK.load(Ordering::Relaxed) {
+            sbi::set_timer(deadline);
+        }
+        unsafe {
+            asm!("wfi", options(nostack));
+        }
+    };
+    if sstatus & 2 != 0 {
+        unsafe {
+            asm!("csrsi sstatus, 2", options(nostack));
+        }
+    }
+    result
+}
+
+/// sie.SEIE: supervisor external interrupts
+const SIE_SEIE: usize = 1 << 9;
+
+/// The supervisor PLIC context of the boot hart, which takes the device
+/// interrupts; the other harts are parked
+fn plic() -> Plic {
+    Plic::supervisor(crate::smp::boot_hart())
+}
+
+/// Set once device interrupts are routed; until then drivers poll
+static EXTERNAL_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+
+/// Take device interrupts through the PLIC. UART input moves to its
+/// interrupt, and VirtIO requests are waited for with `wait_until`, so
+/// readers sleep instead of polling. Call after `init_timer`.
+pub fn init_external_interrupts() {
+    let plic = plic();
+    plic.set_threshold(0);
+    for irq in core::iter::once(platform::uart().irq).chain(crate::virtio::irq_lines()) {
+        plic.set_priority(irq, 1);
+        plic.enable(irq);
+    }
+    crate::UART.lock().enable_rx_interrupt();
+    EXTERNAL_INTERRUPTS.store(true, Ordering::Release);
+    unsafe {
+        asm!("csrs sie, {}", in(reg) SIE_SEIE, options(nostack));
+    }
+}
+
+/// Whether device interrupts are routed, so a `wfi` will end when a
+/// device has something
+pub fn external_interrupts_enabled() -> bool {
+    EXTERNAL_INTERRUPTS.load(Ordering::Acquire)
+}
+
+/// Serve every device interrupt the PLIC has pending
+fn handle_external_interrupt() {
+    let plic = plic();
+    let uart_irq = platform::uart().irq;
+    while let Some(irq) = plic.claim() {
+        match irq {
+            irq if irq == uart_irq => uart::handle_interrupt(),
+            // The VirtIO slots are the only other lines enabled
+            _ => crate::virtio::handle_interrupt(irq),
+        }
+        plic.complete(irq);
+    }
+}
+
 /// Dump detailed crash information
 pub fn dump_crash_info(ctx: &TrapContext) {
     let cause = TrapCause::from(ctx.scause);
     let is_interrupt = (ctx.scause & (1 << 63)) != 0;
     
     console_println!("=====================================");
-    console_println!("[x] KERNEL TRAP/CRASH DETECTED! [x]");
+    err_println!("KERNEL TRAP/CRASH DETECTED! [x]");
     console_println!("=====================================");
     console_println!();
-    console_println!("[i] Trap Type: {}", if is_interrupt { "INTERRUPT" } else { "EXCEPTION" });
-    console_println!("[i] Cause: {:?} (0x{:016x})", cause, ctx.scause);
-    console_println!("[i] PC (sepc): 0x{:016x}", ctx.sepc);
-    console_println!("[i] Trap Value (stval): 0x{:016x}", ctx.stval);
-    console_println!("[i] Status (sstatus): 0x{:016x}", ctx.sstatus);
+    info_println!("Trap Type: {}", if is_interrupt { "INTERRUPT" } else { "EXCEPTION" });
+    info_println!("Cause: {} (0x{:016x})", describe(ctx.scause), ctx.scause);
+    info_println!("PC (sepc): 0x{:016x}", ctx.sepc);
+    info_println!("Trap Value (stval): 0x{:016x}", ctx.stval);
+    info_println!("Status (sstatus): 0x{:016x}", ctx.sstatus);
     console_println!();
     
     // Detailed register dump
-    console_println!("[i] REGISTER DUMP:");
+    info_println!("REGISTER DUMP:");
     console_println!("─────────────────────────────────────");
     for i in 0..32 {
         let reg_name = match i {
@@ -181,14 +389,17 @@ pub fn dump_crash_info(ctx: &TrapContext) {
     // Additional context based on trap type
     match cause {
         TrapCause::IllegalInstruction => {
-            console_println!("[x] ILLEGAL INSTRUCTION at PC: 0x{:016x}", ctx.sepc);
+            err_println!("ILLEGAL INSTRUCTION at PC: 0x{:016x}", ctx.sepc);
+            if ctx.stval != 0 {
+                console_println!("   Instruction bits: 0x{:08x}", ctx.stval);
+            }
             console_println!("   This usually indicates:");
             console_println!("   - Corrupted code");
             console_println!("   - Jump to invalid address");
             console_println!("   - Unsupported instruction");
         }
         TrapCause::LoadAccessFault | TrapCause::StoreAccessFault => {
-            console_println!("[x] MEMORY ACCESS FAULT");
+            err_println!("MEMORY ACCESS FAULT");
             console_println!("   Faulting address: 0x{:016x}", ctx.stval);
             console_println!("   PC: 0x{:016x}", ctx.sepc);
             console_println!("   This usually indicates:");
@@ -197,28 +408,67 @@ pub fn dump_crash_info(ctx: &TrapContext) {
             console_println!("   - Hardware fault");
         }
         TrapCause::LoadAddressMisaligned | TrapCause::StoreAddressMisaligned => {
-            console_println!("[x] MISALIGNED MEMORY ACCESS");
+            err_println!("MISALIGNED MEMORY ACCESS");
             console_println!("   Faulting address: 0x{:016x}", ctx.stval);
             console_println!("   PC: 0x{:016x}", ctx.sepc);
         }
         TrapCause::InstructionAddressMisaligned => {
-            console_println!("[x] MISALIGNED INSTRUCTION FETCH");
+            err_println!("MISALIGNED INSTRUCTION FETCH");
             console_println!("   Faulting PC: 0x{:016x}", ctx.stval);
         }
+        TrapCause::InstructionPageFault | TrapCause::LoadPageFault | TrapCause::StorePageFault => {
+            err_println!("PAGE FAULT");
+            console_println!("   Faulting address: 0x{:016x} ({})", ctx.stval,
+                crate::memory::mmu::describe_fault(ctx.stval as usize));
+            console_println!("   PC: 0x{:016x}", ctx.sepc);
+        }
         TrapCause::Breakpoint => {
-            console_println!("[i] BREAKPOINT HIT at PC: 0x{:016x}", ctx.sepc);
+            info_println!("BREAKPOINT HIT at PC: 0x{:016x}", ctx.sepc);
+        }
+        TrapCause::SoftwareCheck => {
+            err_println!("SOFTWARE CHECK FAILED at PC: 0x{:016x}", ctx.sepc);
+            console_println!("   A control-flow integrity check failed (code {})", ctx.stval);
+        }
+        TrapCause::HardwareError => {
+            err_println!("HARDWARE ERROR (machine check) at PC: 0x{:016x}", ctx.sepc);
+            console_println!("   The hart reported a corrupted or uncorrectable state");
+            console_println!("   Trap value: 0x{:016x}", ctx.stval);
+        }
+        TrapCause::DoubleTrap => {
+            err_println!("DOUBLE TRAP: a trap was taken while handling another");
         }
         _ => {
-            console_println!("[i] Additional debugging info:");
+            info_println!("Additional debugging info:");
             console_println!("   Raw scause: 0x{:016x}", ctx.scause);
             console_println!("   Raw stval: 0x{:016x}", ctx.stval);
         }
     }
     
     console_println!();
+}
+
+/// Report an exception the kernel took in its own code. If it came while
+/// serving a user program's system call, that program is killed and the
+/// kernel goes back to whoever started it; otherwise there is nothing to
+/// go back to, and the hart halts. A lock the system call held stays held,
+/// so what follows a recovered fault may still hang on it.
+fn kernel_fault(ctx: &TrapContext) -> ! {
+    dump_crash_info(ctx);
+    if unsafe { USER_PROGRAM_ACTIVE } {
+        crate::health::count(crate::health::Event::KernelFault);
+        warn_println!("Fault in a system call, killing the program");
+        console_println!("=====================================");
+        exit_to_kernel(crate::process::FAULT_EXIT_CODE);
+    }
     console_println!("=====================================");
     console_println!("System halted. Reset required.");
     console_println!("=====================================");
+    crate::sound::notify(crate::sound::Notice::Error);
+    loop {
+        unsafe {
+            asm!("wfi");
+        }
+    }
 }
 
 /// Handle system calls by dispatching to the unified syscall module
@@ -232,8 +482,18 @@ fn handle_syscall(ctx: &mut TrapContext) {
     let arg4 = ctx.x[14] as usize; // a4
     let arg5 = ctx.x[15] as usize; // a5
     
-    console_println!("[o] syscall: {} (a0={}, a1={}, a2={}, a3={})", 
+    ok_println!("syscall: {} (a0={}, a1={}, a2={}, a3={})", 
         syscall_num, arg0, arg1, arg2, arg3);
+    crate::watchdog::pet_syscall(syscall_num);
+    
+    // Remember the user registers in case the call is fork
+    unsafe {
+        CURRENT_SYSCALL_FRAME = if is_user_trap(ctx) {
+            ctx as *const TrapContext
+        } else {
+            core::ptr::null()
+        };
+    }
     
     // Create syscall args structure
     let args = crate::syscall::SyscallArgs {
@@ -253,28 +513,42 @@ fn handle_syscall(ctx: &mut TrapContext) {
     match result {
         crate::syscall::SysCallResult::Success(value) => {
             ctx.x[10] = value as u64; // Return value in a0
-            // console_println!("[o] Syscall {} completed successfully: {}", syscall_num, value);
+            // ok_println!("Syscall {} completed successfully: {}", syscall_num, value);
         }
         crate::syscall::SysCallResult::Error(code) => {
             ctx.x[10] = (-code as i64) as u64; // Error code in a0 (negative)
-            console_println!("[x] Syscall {} failed with error code: {}", syscall_num, code);
+            err_println!("Syscall {} failed with error code: {}", syscall_num, code);
         }
     }
     
     // Check if a user program has exited (e.g., via sys_exit)
     if let Some(exit_code) = check_user_program_exit() {
-        // console_println!("[i] User program exited with code {} - restarting shell", exit_code);
-        
-        // Instead of returning to user mode (which would crash), 
-        // jump directly to shell_loop to restart the shell
-        crate::enhanced_shell_loop();
-        
-        // This should never be reached since shell_loop never returns
-        return;
+        // Instead of returning to user mode, resume the kernel code that
+        // started the program so it can collect the exit status
+        exit_to_kernel(exit_code);
     }
     
     // Skip the ecall instruction (advance PC by 4 bytes) for all syscalls
     ctx.sepc += 4;
+    
+    if SLICE_YIELD.swap(false, Ordering::Relaxed) && is_user_trap(ctx) && in_slice() {
+        preempt(ctx);
+    }
+}
+
+/// Make a syscall from kernel code along the path a user ecall takes:
+/// arguments in a0-a5 and the number in a7, the result (negative errno on
+/// failure) back in a0. An ecall in S-mode would go to the SBI firmware
+/// rather than to our trap handler, so the frame is built here instead.
+pub fn kernel_syscall(number: usize, args: [usize; 6]) -> isize {
+    let mut ctx = TrapContext::new();
+    ctx.sstatus = SSTATUS_SPP;
+    ctx.x[17] = number as u64;
+    for (i, &arg) in args.iter().enumerate() {
+        ctx.x[10 + i] = arg as u64;
+    }
+    handle_syscall(&mut ctx);
+    ctx.x[10] as isize
 }
 
 /// Main trap handler (called from assembly)
@@ -296,87 +570,83 @@ pub extern "C" fn trap_handler(ctx: &mut TrapContext) {
     
     let cause = TrapCause::from(ctx.scause);
     let is_interrupt = (ctx.scause & (1 << 63)) != 0;
+    crate::health::count_trap(ctx.scause);
     
     if is_interrupt {
         // Handle interrupts
-        let uart = crate::UART.lock();
         match cause {
             TrapCause::SupervisorTimerInterrupt => {
-                console_println!("[i] Timer interrupt");
+                // sepc and sstatus are as read; nothing to write back or log
+                handle_timer_interrupt();
+                crate::watchdog::check(ctx, is_user_trap(ctx));
+                if is_user_trap(ctx) && slice_over() {
+                    preempt(ctx);
+                }
+                return;
             }
             TrapCause::SupervisorExternalInterrupt => {
-                console_println!("[i] External interrupt");
+                handle_external_interrupt();
+                // A key typed while a program runs in the background goes
+                // to the shell at once
+                if is_user_trap(ctx) && in_slice() {
+                    preempt(ctx);
+                }
+                return;
             }
             _ => {
-                console_println!("[x] Unknown interrupt: {:?}", cause);
+                err_println!("Unknown interrupt: {:?}", cause);
             }
         }
     } else {
         // Handle exceptions
-        //console_println!("[x] Exception occurred: cause={}, sepc=0x{:x}", ctx.scause, ctx.sepc);
+        //err_println!("Exception occurred: cause={}, sepc=0x{:x}", ctx.scause, ctx.sepc);
         
         match cause {
             TrapCause::EnvironmentCallFromUMode => {
-                // console_println!("[i] Handling user mode syscall");
+                // info_println!("Handling user mode syscall");
                 // Handle system calls from user mode - dispatch to unified syscall module
                 handle_syscall(ctx);
             }
             TrapCause::EnvironmentCallFromSMode => {
-                // console_println!("[i] Handling supervisor mode syscall");
+                // info_println!("Handling supervisor mode syscall");
                 // Handle system calls from supervisor mode - dispatch to unified syscall module
                 handle_syscall(ctx);
             }
             TrapCause::Breakpoint => {
                 // Check if this breakpoint is from our exit stub
                 if let Some(exit_code) = check_user_program_exit() {
-                    //console_println!("[i] Exit stub breakpoint hit - returning to kernel with code {}", exit_code);
-                    
-                    // Set up return to kernel
-                    ctx.x[10] = exit_code as u64; // a0 = exit code
-                    
-                    // Set supervisor mode
-                    ctx.sstatus |= 0x00000100; // Set SPP bit for supervisor mode
-                    
-                    // Instead of trying to figure out the return address,
-                    // let's just halt the system cleanly for now
-                    // console_println!("[i] Program completed successfully with exit code: {}", exit_code);
-                    // console_println!("[i] Returning to shell...");
-                    
-                    // For now, let's just return to a safe location
-                    // We'll improve this later to properly return to the shell
-                    ctx.sepc = 0x80200000; // Return to a safe kernel location
-                    
-                    // console_println!("[i] Setting sepc to safe kernel location: 0x{:x}", ctx.sepc);
-                    
-                    return;
+                    exit_to_kernel(exit_code);
+                } else if is_user_trap(ctx) {
+                    kill_user_program(ctx);
                 } else {
                     // Regular breakpoint - dump crash info
-                    dump_crash_info(ctx);
-                    
-                    // Halt the system
-                    loop {
-                        unsafe {
-                            asm!("wfi");
-                        }
-                    }
+                    kernel_fault(ctx);
                 }
             }
+            TrapCause::InstructionPageFault | TrapCause::LoadPageFault | TrapCause::StorePageFault
+                if crate::memory::mmu::handle_demand_fault(ctx.stval as usize) => {
+                // Page read in from the program file; retry the access
+            }
+            TrapCause::StorePageFault if crate::memory::mmu::handle_cow_fault(ctx.stval as usize) => {
+                // Copy-on-write page duplicated; retry the store
+            }
+            TrapCause::InstructionPageFault | TrapCause::LoadPageFault | TrapCause::StorePageFault
+                if is_user_trap(ctx) => {
+                handle_user_page_fault(ctx);
+            }
+            _ if is_user_trap(ctx) => {
+                // A faulting user program only takes itself down
+                kill_user_program(ctx);
+            }
             _ => {
                 // Other exceptions are usually fatal
-                dump_crash_info(ctx);
-                
-                // Halt the system
-                loop {
-                    unsafe {
-                        asm!("wfi");
-                    }
-                }
+                kernel_fault(ctx);
             }
         }
     }
     
     // Write back CSR values before returning
-    console_println!("[i] Writing back CSRs: sepc=0x{:x}, sstatus=0x{:x}", ctx.sepc, ctx.sstatus);
+    info_println!("Writing back CSRs: sepc=0x{:x}, sstatus=0x{:x}", ctx.sepc, ctx.sstatus);
     unsafe {
         asm!(
             "csrw sepc, {}",
@@ -387,17 +657,25 @@ pub extern "C" fn trap_handler(ctx: &mut TrapContext) {
     }
 }
 
-/// Assembly trap vector - saves context and calls trap_handler
+/// Assembly trap vector - saves context and calls trap_handler.
+///
+/// While a user program runs, sscratch holds the kernel stack pointer to use
+/// for its traps; in the kernel it is zero, and traps stay on the current stack.
 #[unsafe(naked)]
 #[no_mangle]
 pub unsafe extern "C" fn trap_vector() {
     core::arch::naked_asm!(
+        // Switch to the kernel stack if the trap came from user mode
+        "csrrw sp, sscratch, sp",
+        "bnez sp, 1f",
+        "csrrw sp, sscratch, sp",  // Trap from the kernel: keep its stack
+        "1:",
+        
         // Save all registers to stack
-        "addi sp, sp, -256",  // Make room for TrapContext
+        "addi sp, sp, -288",  // Make room for TrapContext (x0-x31 + 4 CSRs)
         
-        // Save x1-x31 (x0 is always 0)
+        // Save x1 and x3-x31 (x0 is always 0, x2 below)
         "sd x1, 8(sp)",
-        "sd x2, 16(sp)",
         "sd x3, 24(sp)",
         "sd x4, 32(sp)",
         "sd x5, 40(sp)",
@@ -428,13 +706,27 @@ pub unsafe extern "C" fn trap_vector() {
         "sd x30, 240(sp)",
         "sd x31, 248(sp)",
         
+        // Save the interrupted sp and mark the kernel as running (sscratch = 0)
+        "csrrw t0, sscratch, zero",
+        "bnez t0, 2f",
+        "addi t0, sp, 288",
+        "2:",
+        "sd t0, 16(sp)",
+        
         // Call trap handler with context pointer
         "mv a0, sp",
         "call {trap_handler}",
         
-        // Restore registers
+        // Returning to user mode: its next trap starts at this stack depth
+        "csrr t0, sstatus",
+        "andi t0, t0, 0x100",
+        "bnez t0, 3f",
+        "addi t0, sp, 288",
+        "csrw sscratch, t0",
+        "3:",
+        
+        // Restore registers, sp last
         "ld x1, 8(sp)",
-        "ld x2, 16(sp)",
         "ld x3, 24(sp)",
         "ld x4, 32(sp)",
         "ld x5, 40(sp)",
@@ -464,8 +756,7 @@ pub unsafe extern "C" fn trap_vector() {
         "ld x29, 232(sp)",
         "ld x30, 240(sp)",
         "ld x31, 248(sp)",
-        
-        "addi sp, sp, 256",
+        "ld x2, 16(sp)",
         "sret",
         
         trap_handler = sym trap_handler
@@ -478,4 +769,260 @@ pub static USER_PROGRAM_EXITED: Mutex<Option<i32>> = Mutex::new(None);
 pub fn check_user_program_exit() -> Option<i32> {
     let mut exit_code = USER_PROGRAM_EXITED.lock();
     exit_code.take()
-} 
\ No newline at end of file
+} 
+
+/// Callee-saved kernel state captured before entering user mode
+#[repr(C)]
+pub struct KernelContext {
+    pub ra: u64,
+    pub sp: u64,
+    pub s: [u64; 12],  // s0-s11
+}
+
+// Kernel context to resume when the running user program exits
+static mut USER_RETURN_CONTEXT: KernelContext = KernelContext { ra: 0, sp: 0, s: [0; 12] };
+static mut USER_PROGRAM_ACTIVE: bool = false;
+
+// User registers of the syscall being handled (null for kernel ecalls)
+static mut CURRENT_SYSCALL_FRAME: *const TrapContext = core::ptr::null();
+
+// Tick at which the running program's slice ends (0 = it runs until it exits)
+static SLICE_END: AtomicU64 = AtomicU64::new(0);
+// Set to end the slice once the current system call returns
+static SLICE_YIELD: AtomicBool = AtomicBool::new(false);
+// Registers of the program whose slice ended last
+static mut PREEMPTED_FRAME: TrapContext = TrapContext::new();
+
+/// Returned to `enter` in place of an exit status when a slice ends
+const PREEMPTED: isize = isize::MIN;
+
+/// How a run of a user program ended
+pub enum UserExit {
+    /// It exited with this status
+    Exited(i32),
+    /// Its slice ended; these registers resume it
+    Preempted(TrapContext),
+}
+
+/// SPP bit in sstatus: previous privilege was supervisor
+const SSTATUS_SPP: u64 = 1 << 8;
+
+/// Run a user program until it exits and return its exit status.
+pub unsafe fn run_user_program(entry_point: usize, user_sp: usize, return_addr: usize) -> i32 {
+    run_user_context(&entry_frame(entry_point, user_sp, return_addr))
+}
+
+/// Registers that start a user program at `entry_point`
+pub fn entry_frame(entry_point: usize, user_sp: usize, return_addr: usize) -> TrapContext {
+    let mut frame = TrapContext::new();
+    frame.sepc = entry_point as u64;
+    frame.x[1] = return_addr as u64;  // ra
+    frame.x[2] = user_sp as u64;      // sp
+    // a0 = argc = 0, a1 = argv = NULL
+    frame
+}
+
+/// Enter user mode with the registers in `frame` and return the exit status
+/// once the program exits.
+///
+/// The kernel's callee-saved registers are stored in `USER_RETURN_CONTEXT`
+/// so that `exit_to_kernel` can resume here from the trap handler. The
+/// previous context is kept, so a forked child can run while its parent is
+/// inside a syscall.
+pub unsafe fn run_user_context(frame: &TrapContext) -> i32 {
+    enter(frame, 0) as i32
+}
+
+/// Run the user program in `frame` for up to `ticks` kernel ticks. The
+/// slice ends early at a device interrupt, or when a system call asks with
+/// `yield_slice`. A program it forks runs to completion inside it.
+pub unsafe fn run_user_slice(frame: &TrapContext, ticks: u64) -> UserExit {
+    SLICE_YIELD.store(false, Ordering::Relaxed);
+    match enter(frame, tick_count() + ticks.max(1)) {
+        PREEMPTED => UserExit::Preempted(core::ptr::read(core::ptr::addr_of!(PREEMPTED_FRAME))),
+        exit_code => UserExit::Exited(exit_code as i32),
+    }
+}
+
+/// Enter user mode, preempting the program at tick `slice_end` unless it
+/// is 0, and return what `return_to_kernel` passes back
+unsafe fn enter(frame: &TrapContext, slice_end: u64) -> isize {
+    let outer_context = core::ptr::read(core::ptr::addr_of!(USER_RETURN_CONTEXT));
+    let outer_active = USER_PROGRAM_ACTIVE;
+    let outer_slice = SLICE_END.swap(slice_end, Ordering::Relaxed);
+    
+    USER_PROGRAM_ACTIVE = true;
+    let exit_code = enter_user_mode(core::ptr::addr_of_mut!(USER_RETURN_CONTEXT), frame);
+    
+    USER_RETURN_CONTEXT = outer_context;
+    USER_PROGRAM_ACTIVE = outer_active;
+    SLICE_END.store(outer_slice, Ordering::Relaxed);
+    exit_code
+}
+
+/// Whether the running program was given a slice of time, rather than
+/// running until it exits
+pub fn in_slice() -> bool {
+    unsafe { USER_PROGRAM_ACTIVE && SLICE_END.load(Ordering::Relaxed) != 0 }
+}
+
+/// End the slice of the running program once its current system call returns
+pub fn yield_slice() {
+    SLICE_YIELD.store(true, Ordering::Relaxed);
+}
+
+fn slice_over() -> bool {
+    in_slice() && tick_count() >= SLICE_END.load(Ordering::Relaxed)
+}
+
+/// Keep the registers of the program in its slice and resume the kernel
+/// code that gave it the slice
+fn preempt(ctx: &TrapContext) -> ! {
+    unsafe {
+        PREEMPTED_FRAME = *ctx;
+        return_to_kernel(core::ptr::addr_of!(USER_RETURN_CONTEXT), PREEMPTED);
+    }
+}
+
+/// Registers for the child of a fork: the caller's registers, returning 0
+/// from the ecall. None if the current syscall did not come from user mode.
+pub fn fork_child_frame() -> Option<TrapContext> {
+    let parent = unsafe { CURRENT_SYSCALL_FRAME.as_ref()? };
+    let mut child = *parent;
+    child.x[10] = 0;     // fork() returns 0 in the child
+    child.sepc += 4;     // Continue after the ecall
+    Some(child)
+}
+
+/// Abandon the current user program and resume the kernel code that started it
+pub fn exit_to_kernel(exit_code: i32) -> ! {
+    unsafe {
+        if USER_PROGRAM_ACTIVE {
+            return_to_kernel(core::ptr::addr_of!(USER_RETURN_CONTEXT), exit_code as isize);
+        }
+    }
+    
+    // No kernel context to return to - restart the shell
+    let _ = crate::memory::mmu::switch_to_kernel_space();
+    crate::enhanced_shell_loop();
+}
+
+/// Check whether the trap was taken from user mode
+fn is_user_trap(ctx: &TrapContext) -> bool {
+    unsafe { USER_PROGRAM_ACTIVE && (ctx.sstatus & SSTATUS_SPP) == 0 }
+}
+
+/// Terminate the running user program after a fatal exception
+fn kill_user_program(ctx: &TrapContext) -> ! {
+    crate::health::count(crate::health::Event::ProgramKilled);
+    crate::msg_println!(crate::messages::Msg::ProgramKilled,
+        describe(ctx.scause), format_args!("{:x}", ctx.sepc), format_args!("{:x}", ctx.stval));
+    exit_to_kernel(crate::process::FAULT_EXIT_CODE);
+}
+
+/// A user program touched memory its address space does not allow
+fn handle_user_page_fault(ctx: &TrapContext) -> ! {
+    crate::health::count(crate::health::Event::ProgramKilled);
+    let access = match TrapCause::from(ctx.scause) {
+        TrapCause::InstructionPageFault => "execute",
+        TrapCause::LoadPageFault => "read",
+        _ => "write",
+    };
+    crate::msg_println!(crate::messages::Msg::SegmentationFault, access, format_args!("{:x}", ctx.stval),
+        crate::memory::mmu::describe_fault(ctx.stval as usize), format_args!("{:x}", ctx.sepc));
+    exit_to_kernel(crate::process::FAULT_EXIT_CODE);
+}
+
+/// Save kernel callee-saved registers and drop to user mode with the
+/// registers in `frame`. Returns (via `return_to_kernel`) the program's exit status.
+#[unsafe(naked)]
+unsafe extern "C" fn enter_user_mode(ctx: *mut KernelContext, frame: *const TrapContext) -> isize {
+    core::arch::naked_asm!(
+        "sd ra, 0(a0)",
+        "sd sp, 8(a0)",
+        "sd s0, 16(a0)",
+        "sd s1, 24(a0)",
+        "sd s2, 32(a0)",
+        "sd s3, 40(a0)",
+        "sd s4, 48(a0)",
+        "sd s5, 56(a0)",
+        "sd s6, 64(a0)",
+        "sd s7, 72(a0)",
+        "sd s8, 80(a0)",
+        "sd s9, 88(a0)",
+        "sd s10, 96(a0)",
+        "sd s11, 104(a0)",
+        
+        // No interrupts until sret: sscratch must not be seen by a kernel trap
+        "csrci sstatus, 2",
+        "csrw sscratch, sp",  // User traps run on the stack below this point
+        
+        // sret to user mode with interrupts enabled
+        "ld t0, 264(a1)",     // frame.sepc
+        "csrw sepc, t0",
+        "li t0, 0x100",       // SPP = 0 (user)
+        "csrc sstatus, t0",
+        "li t0, 0x20",        // SPIE = 1
+        "csrs sstatus, t0",
+        
+        // Load the user registers, a1 (the frame pointer) last
+        "ld x1, 8(a1)",
+        "ld x2, 16(a1)",
+        "ld x3, 24(a1)",
+        "ld x4, 32(a1)",
+        "ld x5, 40(a1)",
+        "ld x6, 48(a1)",
+        "ld x7, 56(a1)",
+        "ld x8, 64(a1)",
+        "ld x9, 72(a1)",
+        "ld x10, 80(a1)",
+        "ld x12, 96(a1)",
+        "ld x13, 104(a1)",
+        "ld x14, 112(a1)",
+        "ld x15, 120(a1)",
+        "ld x16, 128(a1)",
+        "ld x17, 136(a1)",
+        "ld x18, 144(a1)",
+        "ld x19, 152(a1)",
+        "ld x20, 160(a1)",
+        "ld x21, 168(a1)",
+        "ld x22, 176(a1)",
+        "ld x23, 184(a1)",
+        "ld x24, 192(a1)",
+        "ld x25, 200(a1)",
+        "ld x26, 208(a1)",
+        "ld x27, 216(a1)",
+        "ld x28, 224(a1)",
+        "ld x29, 232(a1)",
+        "ld x30, 240(a1)",
+        "ld x31, 248(a1)",
+        "ld x11, 88(a1)",
+        "sret",
+    );
+}
+
+/// Restore the kernel context saved by `enter_user_mode` and return `exit_code` from it
+#[unsafe(naked)]
+unsafe extern "C" fn return_to_kernel(ctx: *const KernelContext, exit_code: isize) -> ! {
+    core::arch::naked_asm!(
+        "ld ra, 0(a0)",
+        "ld sp, 8(a0)",
+        "ld s0, 16(a0)",
+        "ld s1, 24(a0)",
+        "ld s2, 32(a0)",
+        "ld s3, 40(a0)",
+        "ld s4, 48(a0)",
+        "ld s5, 56(a0)",
+        "ld s6, 64(a0)",
+        "ld s7, 72(a0)",
+        "ld s8, 80(a0)",
+        "ld s9, 88(a0)",
+        "ld s10, 96(a0)",
+        "ld s11, 104(a0)",
+        
+        // The trap cleared SIE; re-enable it as init_trap_handling did
+        "csrsi sstatus, 2",
+        "mv a0, a1",
+        "ret",
+    );
+}
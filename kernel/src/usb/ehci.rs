@@ -0,0 +1,393 @@
+//! EHCI (USB 2.0) host controller
+//!
+//! Only high-speed devices are driven: a full- or low-speed device is
+//! handed to the port's companion controller, which has no driver, and is
+//! not seen. Transfers go through the asynchronous schedule with a single
+//! queue head. For each transfer the schedule is stopped, the queue head
+//! set up for the endpoint with the transfer's qTDs (transfer descriptors)
+//! behind it, the schedule started, and the last qTD polled until the
+//! controller is done with it. That is slow next to keeping a queue head
+//! per endpoint, but it needs no doorbell handshake to take queue heads out
+//! of the schedule, and storage is the only user.
+//!
+//! Data toggles are kept by the qTDs (`QH_DTC`) and, for bulk endpoints, by
+//! the caller between transfers. Data goes through a bounce buffer, so a
+//! transfer moves at most `BOUNCE_SIZE` bytes. The structures and the
+//! buffer are in RAM below 4 GB, as the 32-bit pointers need.
+
+use core::ptr::{read_volatile, write_volatile};
+use elinos_common::mmio::{io_fence, Register, RegisterBlock};
+
+use super::{Data, HostController, Pipe, SetupPacket, Speed, UsbError, UsbResult};
+
+// Capability registers: the first word holds CAPLENGTH and HCIVERSION,
+// read whole as some controllers take only 32-bit reads
+const CAP_LENGTH_VERSION: Register<u32> = Register::at(0x00);
+const HCSPARAMS: Register<u32> = Register::at(0x04);
+
+// Operational registers, CAPLENGTH bytes in
+const USBCMD: Register<u32> = Register::at(0x00);
+const USBSTS: Register<u32> = Register::at(0x04);
+const USBINTR: Register<u32> = Register::at(0x08);
+const CTRLDSSEGMENT: Register<u32> = Register::at(0x10);
+const ASYNCLISTADDR: Register<u32> = Register::at(0x18);
+const CONFIGFLAG: Register<u32> = Register::at(0x40);
+const PORTSC: Register<u32> = Register::at(0x44);
+
+const HCSPARAMS_N_PORTS: u32 = 0xf;
+const HCSPARAMS_PPC: u32 = 1 << 4;
+
+const CMD_RUN: u32 = 1 << 0;
+const CMD_RESET: u32 = 1 << 1;
+const CMD_ASYNC_ENABLE: u32 = 1 << 5;
+/// Interrupt threshold of 8 micro-frames, the default
+const CMD_ITC_DEFAULT: u32 = 8 << 16;
+
+const STS_HALTED: u32 = 1 << 12;
+const STS_ASYNC: u32 = 1 << 15;
+
+const PORT_CONNECTED: u32 = 1 << 0;
+const PORT_ENABLED: u32 = 1 << 2;
+const PORT_RESET: u32 = 1 << 8;
+const PORT_LINE_STATUS: u32 = 3 << 10;
+/// Line status of a low-speed device before reset
+const PORT_LINE_K_STATE: u32 = 1 << 10;
+const PORT_POWER: u32 = 1 << 12;
+const PORT_OWNER: u32 = 1 << 13;
+/// Status change bits, cleared by writing 1; kept 0 when writing the rest
+const PORT_CHANGE_BITS: u32 = (1 << 1) | (1 << 3) | (1 << 5);
+
+/// Link pointer: nothing follows
+const LINK_TERMINATE: u32 = 1;
+/// Link pointer type: queue head
+const LINK_QH: u32 = 1 << 1;
+
+// Queue head endpoint characteristics
+const QH_EPS_HIGH: u32 = 2 << 12;
+/// Data toggle comes from each qTD
+const QH_DTC: u32 = 1 << 14;
+/// Head of the reclamation list
+const QH_HEAD: u32 = 1 << 15;
+/// One transaction per micro-frame
+const QH_MULT_ONE: u32 = 1 << 30;
+
+// qTD token
+const TOKEN_ACTIVE: u32 = 1 << 7;
+const TOKEN_HALTED: u32 = 1 << 6;
+const TOKEN_BUFFER_ERROR: u32 = 1 << 5;
+const TOKEN_BABBLE: u32 = 1 << 4;
+const TOKEN_XACT_ERROR: u32 = 1 << 3;
+const TOKEN_PID_OUT: u32 = 0 << 8;
+const TOKEN_PID_IN: u32 = 1 << 8;
+const TOKEN_PID_SETUP: u32 = 2 << 8;
+/// Three tries before an error halts the queue
+const TOKEN_CERR: u32 = 3 << 10;
+const TOKEN_IOC: u32 = 1 << 15;
+const TOKEN_BYTES_SHIFT: u32 = 16;
+const TOKEN_BYTES_MASK: u32 = 0x7fff;
+const TOKEN_TOGGLE: u32 = 1 << 31;
+
+/// Layout of the structures page: a queue head and three qTDs, 64 bytes
+/// each so the 64-bit layouts fit, and the setup packet
+const QH_OFFSET: usize = 0;
+const TD_OFFSETS: [usize; 3] = [64, 128, 192];
+const SETUP_OFFSET: usize = 256;
+const STRUCTURES_SIZE: usize = 4096;
+
+/// Bytes a transfer moves at most: four pages, which one qTD covers
+pub const BOUNCE_SIZE: usize = 16 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// How long a transfer may take
+const TRANSFER_TIMEOUT_MS: u64 = 5000;
+
+/// How long the controller gets to halt, reset or start
+const CONTROLLER_TIMEOUT_MS: u64 = 250;
+
+pub struct Ehci {
+    base: usize,
+    op: RegisterBlock,
+    ports: usize,
+    /// Queue head, qTDs and setup packet
+    structures: usize,
+    bounce: usize,
+}
+
+/// Wait until `done` or `timeout_ms` passes
+fn wait_for(timeout_ms: u64, mut done: impl FnMut() -> bool) -> UsbResult<()> {
+    let start = elinos_common::timer::ticks();
+    while !done() {
+        if elinos_common::timer::elapsed_ms(start) >= timeout_ms {
+            return Err(UsbError::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+impl Ehci {
+    /// Reset the controller at `base`, take every port from the companion
+    /// controllers and start it
+    pub fn new(base: usize) -> UsbResult<Self> {
+        let cap = unsafe { RegisterBlock::new(base) };
+        let cap_length = (cap.read(CAP_LENGTH_VERSION) & 0xff) as usize;
+        let params = cap.read(HCSPARAMS);
+        let op = unsafe { RegisterBlock::new(base + cap_length) };
+
+        let structures = crate::memory::allocate_kernel_memory(STRUCTURES_SIZE, PAGE_SIZE).ok_or(UsbError::NoMemory)?;
+        let bounce = match crate::memory::allocate_kernel_memory(BOUNCE_SIZE, PAGE_SIZE) {
+            Some(bounce) => bounce,
+            None => {
+                crate::memory::deallocate_kernel_memory(structures, STRUCTURES_SIZE);
+                return Err(UsbError::NoMemory);
+            }
+        };
+        if structures + STRUCTURES_SIZE > u32::MAX as usize || bounce + BOUNCE_SIZE > u32::MAX as usize {
+            crate::memory::deallocate_kernel_memory(structures, STRUCTURES_SIZE);
+            crate::memory::deallocate_kernel_memory(bounce, BOUNCE_SIZE);
+            return Err(UsbError::Unsupported);
+        }
+        unsafe {
+            core::ptr::write_bytes(structures as *mut u8, 0, STRUCTURES_SIZE);
+        }
+
+        let ehci = Ehci { base, op, ports: (params & HCSPARAMS_N_PORTS) as usize, structures, bounce };
+        ehci.reset()?;
+        if params & HCSPARAMS_PPC != 0 {
+            for port in 0..ehci.ports {
+                ehci.write_port(port, ehci.read_port(port) | PORT_POWER);
+            }
+        }
+        // Ports settle after power and routing
+        super::delay_ms(20);
+        Ok(ehci)
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    fn reset(&self) -> UsbResult<()> {
+        self.op.write(USBCMD, self.op.read(USBCMD) & !CMD_RUN);
+        wait_for(CONTROLLER_TIMEOUT_MS, || self.op.read(USBSTS) & STS_HALTED != 0)?;
+        self.op.write(USBCMD, CMD_RESET);
+        wait_for(CONTROLLER_TIMEOUT_MS, || self.op.read(USBCMD) & CMD_RESET == 0)?;
+
+        self.op.write(USBINTR, 0);
+        self.op.write(CTRLDSSEGMENT, 0);
+        self.op.write(USBCMD, CMD_ITC_DEFAULT | CMD_RUN);
+        wait_for(CONTROLLER_TIMEOUT_MS, || self.op.read(USBSTS) & STS_HALTED == 0)?;
+        // Route every port to this controller rather than the companions
+        self.op.write(CONFIGFLAG, 1);
+        Ok(())
+    }
+
+    fn read_port(&self, port: usize) -> u32 {
+        self.op.read(PORTSC.offset_by(4 * port))
+    }
+
+    /// Write a port's status, leaving its change bits alone
+    fn write_port(&self, port: usize, value: u32) {
+        self.op.write(PORTSC.offset_by(4 * port), value & !PORT_CHANGE_BITS);
+    }
+
+    fn word(&self, offset: usize, index: usize) -> *mut u32 {
+        (self.structures + offset + 4 * index) as *mut u32
+    }
+
+    fn address(&self, offset: usize) -> u32 {
+        (self.structures + offset) as u32
+    }
+
+    /// Fill in the qTD at `offset`
+    fn set_td(&self, offset: usize, next: Option<usize>, alternate: Option<usize>, token: u32, buffer: usize) {
+        let link = |td: Option<usize>| td.map_or(LINK_TERMINATE, |td| self.address(td));
+        unsafe {
+            write_volatile(self.word(offset, 0), link(next));
+            write_volatile(self.word(offset, 1), link(alternate));
+            write_volatile(self.word(offset, 2), token);
+            write_volatile(self.word(offset, 3), buffer as u32);
+            for page in 1..5 {
+                let pointer = (buffer & !(PAGE_SIZE - 1)) + page * PAGE_SIZE;
+                write_volatile(self.word(offset, 3 + page), pointer as u32);
+            }
+            // High halves of the buffer pointers, for 64-bit controllers
+            for index in 8..13 {
+                write_volatile(self.word(offset, index), 0);
+            }
+        }
+    }
+
+    fn token(&self, offset: usize) -> u32 {
+        unsafe { read_volatile(self.word(offset, 2)) }
+    }
+
+    /// Run the qTDs at `tds` on `pipe`'s endpoint and wait for the last
+    fn run(&self, pipe: &Pipe, tds: &[usize]) -> UsbResult<()> {
+        let characteristics = pipe.address as u32
+            | (pipe.endpoint as u32) << 8
+            | QH_EPS_HIGH
+            | QH_DTC
+            | QH_HEAD
+            | (pipe.max_packet as u32) << 16;
+        unsafe {
+            // The only queue head, linked to itself
+            write_volatile(self.word(QH_OFFSET, 0), self.address(QH_OFFSET) | LINK_QH);
+            write_volatile(self.word(QH_OFFSET, 1), characteristics);
+            write_volatile(self.word(QH_OFFSET, 2), QH_MULT_ONE);
+            write_volatile(self.word(QH_OFFSET, 3), 0);
+            // Overlay: idle, with the first qTD next
+            write_volatile(self.word(QH_OFFSET, 4), self.address(tds[0]));
+            write_volatile(self.word(QH_OFFSET, 5), LINK_TERMINATE);
+            for index in 6..17 {
+                write_volatile(self.word(QH_OFFSET, index), 0);
+            }
+        }
+
+        self.op.write_fenced(ASYNCLISTADDR, self.address(QH_OFFSET));
+        self.op.write(USBCMD, self.op.read(USBCMD) | CMD_ASYNC_ENABLE);
+        let last = tds[tds.len() - 1];
+        let result = wait_for(TRANSFER_TIMEOUT_MS, || {
+            tds.iter().any(|&td| self.token(td) & TOKEN_HALTED != 0) || self.token(last) & TOKEN_ACTIVE == 0
+        });
+        self.op.write(USBCMD, self.op.read(USBCMD) & !CMD_ASYNC_ENABLE);
+        let stopped = wait_for(CONTROLLER_TIMEOUT_MS, || self.op.read(USBSTS) & STS_ASYNC == 0);
+        io_fence();
+        result?;
+        stopped?;
+
+        for &td in tds {
+            let token = self.token(td);
+            if token & TOKEN_HALTED != 0 {
+                return Err(if token & (TOKEN_BABBLE | TOKEN_XACT_ERROR | TOKEN_BUFFER_ERROR) != 0 {
+                    UsbError::Transfer
+                } else {
+                    UsbError::Stall
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes a completed qTD of `length` bytes moved
+    fn moved(&self, td: usize, length: usize) -> usize {
+        let left = (self.token(td) >> TOKEN_BYTES_SHIFT & TOKEN_BYTES_MASK) as usize;
+        length.saturating_sub(left)
+    }
+
+    /// Put `data` in the bounce buffer if it goes out. Returns the token
+    /// bits for its direction.
+    fn stage_out(&self, data: &Data) -> UsbResult<u32> {
+        if data.len() > BOUNCE_SIZE {
+            return Err(UsbError::Unsupported);
+        }
+        Ok(match data {
+            Data::Out(buffer) => {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(buffer.as_ptr(), self.bounce as *mut u8, buffer.len());
+                }
+                TOKEN_PID_OUT
+            }
+            _ => TOKEN_PID_IN,
+        })
+    }
+
+    /// Copy `moved` bytes that came in from the bounce buffer
+    fn finish_in(&self, data: Data, moved: usize) {
+        if let Data::In(buffer) = data {
+            unsafe {
+                core::ptr::copy_nonoverlapping(self.bounce as *const u8, buffer.as_mut_ptr(), moved.min(buffer.len()));
+            }
+        }
+    }
+}
+
+impl HostController for Ehci {
+    fn name(&self) -> &'static str {
+        "EHCI"
+    }
+
+    fn port_count(&self) -> usize {
+        self.ports
+    }
+
+    fn reset_port(&mut self, port: usize) -> UsbResult<Option<Speed>> {
+        let status = self.read_port(port);
+        if status & PORT_CONNECTED == 0 {
+            return Ok(None);
+        }
+        if status & PORT_LINE_STATUS == PORT_LINE_K_STATE {
+            // Low speed: the companion controller's
+            self.write_port(port, status | PORT_OWNER);
+            return Ok(None);
+        }
+
+        self.write_port(port, (status & !PORT_ENABLED) | PORT_RESET);
+        super::delay_ms(50);
+        self.write_port(port, self.read_port(port) & !PORT_RESET);
+        wait_for(CONTROLLER_TIMEOUT_MS, || self.read_port(port) & PORT_RESET == 0)?;
+        super::delay_ms(2);
+
+        let status = self.read_port(port);
+        if status & PORT_ENABLED == 0 {
+            // Full speed: the companion controller's
+            self.write_port(port, status | PORT_OWNER);
+            return Ok(None);
+        }
+        // Recovery time before the first request
+        super::delay_ms(10);
+        Ok(Some(Speed::High))
+    }
+
+    fn control(&mut self, pipe: &Pipe, setup: &SetupPacket, data: Data) -> UsbResult<usize> {
+        let direction = self.stage_out(&data)?;
+        let length = data.len();
+        unsafe {
+            core::ptr::copy_nonoverlapping(setup.to_bytes().as_ptr(), (self.structures + SETUP_OFFSET) as *mut u8, 8);
+        }
+
+        let [setup_td, data_td, status_td] = TD_OFFSETS;
+        // The status stage goes the other way from the data, IN without data
+        let status_pid = if length > 0 && direction == TOKEN_PID_IN { TOKEN_PID_OUT } else { TOKEN_PID_IN };
+        let base = TOKEN_ACTIVE | TOKEN_CERR;
+        let tds: &[usize] = if length > 0 {
+            self.set_td(setup_td, Some(data_td), None, base | TOKEN_PID_SETUP | 8 << TOKEN_BYTES_SHIFT, self.structures + SETUP_OFFSET);
+            // A short IN packet goes on to the status stage
+            self.set_td(data_td, Some(status_td), Some(status_td),
+                base | direction | TOKEN_TOGGLE | (length as u32) << TOKEN_BYTES_SHIFT, self.bounce);
+            self.set_td(status_td, None, None, base | status_pid | TOKEN_TOGGLE | TOKEN_IOC, 0);
+            &TD_OFFSETS
+        } else {
+            self.set_td(setup_td, Some(status_td), None, base | TOKEN_PID_SETUP | 8 << TOKEN_BYTES_SHIFT, self.structures + SETUP_OFFSET);
+            self.set_td(status_td, None, None, base | status_pid | TOKEN_TOGGLE | TOKEN_IOC, 0);
+            &[setup_td, status_td]
+        };
+        self.run(pipe, tds)?;
+
+        let moved = if length > 0 { self.moved(data_td, length) } else { 0 };
+        self.finish_in(data, moved);
+        Ok(moved)
+    }
+
+    fn bulk(&mut self, pipe: &Pipe, data: Data, toggle: &mut bool) -> UsbResult<usize> {
+        let direction = self.stage_out(&data)?;
+        let length = data.len();
+        let td = TD_OFFSETS[0];
+        let toggle_bit = if *toggle { TOKEN_TOGGLE } else { 0 };
+        self.set_td(td, None, None,
+            TOKEN_ACTIVE | TOKEN_CERR | TOKEN_IOC | direction | toggle_bit | (length as u32) << TOKEN_BYTES_SHIFT, self.bounce);
+        let result = self.run(pipe, &[td]);
+        // The controller writes back the toggle for the next packet
+        *toggle = self.token(td) & TOKEN_TOGGLE != 0;
+        result?;
+
+        let moved = self.moved(td, length);
+        self.finish_in(data, moved);
+        Ok(moved)
+    }
+
+    fn max_transfer(&self) -> usize {
+        BOUNCE_SIZE
+    }
+}
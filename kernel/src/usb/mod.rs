@@ -0,0 +1,465 @@
+//! USB host support
+//!
+//! Host controllers on the memory bus, as the device tree lists them
+//! (`platform::usb_hosts`), are driven through the `HostController` trait,
+//! so enumeration and class drivers do not care which kind they talk to.
+//! EHCI has a driver (`ehci`); an xHCI controller is found and its ports
+//! counted, but it has no transfer code yet (`xhci`).
+//!
+//! At boot each root port with something on it is reset and its device
+//! enumerated: the device descriptor read, an address given, the first
+//! configuration read and selected. Class drivers then look at its
+//! interfaces. The one so far is mass storage (`storage`), which turns a
+//! USB stick into a disk named usb0, usb1, ...
+//!
+//! Transfers are synchronous and polled, one at a time per controller.
+//! There is no hub support, so only devices plugged straight into a root
+//! port are found, and no hotplug: a device plugged in later is not seen.
+
+pub mod ehci;
+pub mod xhci;
+pub mod storage;
+
+use core::fmt;
+use heapless::Vec;
+use spin::Mutex;
+use elinos_common::platform::{self, UsbHostKind, MAX_USB_HOSTS};
+use elinos_common::{info_println, ok_println, timer, warn_println};
+
+/// Most devices enumerated, over all controllers
+pub const MAX_DEVICES: usize = 8;
+
+/// Most interfaces of a configuration looked at
+pub const MAX_INTERFACES: usize = 4;
+
+/// Most endpoints of an interface looked at
+pub const MAX_ENDPOINTS: usize = 4;
+
+/// Longest configuration descriptor read, its interfaces and endpoints
+/// included
+const CONFIG_BUFFER: usize = 256;
+
+/// Descriptor types
+const DESCRIPTOR_DEVICE: u8 = 1;
+const DESCRIPTOR_CONFIGURATION: u8 = 2;
+const DESCRIPTOR_INTERFACE: u8 = 4;
+const DESCRIPTOR_ENDPOINT: u8 = 5;
+
+/// Standard requests
+const REQUEST_CLEAR_FEATURE: u8 = 1;
+const REQUEST_SET_ADDRESS: u8 = 5;
+const REQUEST_GET_DESCRIPTOR: u8 = 6;
+const REQUEST_SET_CONFIGURATION: u8 = 9;
+
+/// CLEAR_FEATURE selector for a halted endpoint
+const FEATURE_ENDPOINT_HALT: u16 = 0;
+
+/// Why a USB operation failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbError {
+    /// The controller did not finish in time
+    Timeout,
+    /// The device stalled the endpoint
+    Stall,
+    /// CRC, babble, buffer or protocol error
+    Transfer,
+    /// The controller or device needs something not written yet
+    Unsupported,
+    NoMemory,
+    /// A descriptor or status the device sent made no sense
+    BadResponse,
+    NoDevice,
+}
+
+impl fmt::Display for UsbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UsbError::Timeout => "timed out",
+            UsbError::Stall => "stalled",
+            UsbError::Transfer => "transfer error",
+            UsbError::Unsupported => "not supported",
+            UsbError::NoMemory => "out of memory",
+            UsbError::BadResponse => "bad response from device",
+            UsbError::NoDevice => "no such device",
+        })
+    }
+}
+
+pub type UsbResult<T> = Result<T, UsbError>;
+
+/// Bus speed of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Low,
+    Full,
+    High,
+    Super,
+}
+
+impl Speed {
+    pub fn name(self) -> &'static str {
+        match self {
+            Speed::Low => "1.5M",
+            Speed::Full => "12M",
+            Speed::High => "480M",
+            Speed::Super => "5G",
+        }
+    }
+}
+
+/// An endpoint of a device, as the controller addresses it
+#[derive(Debug, Clone, Copy)]
+pub struct Pipe {
+    pub address: u8,
+    /// Endpoint number, without the direction bit
+    pub endpoint: u8,
+    pub max_packet: u16,
+    pub speed: Speed,
+}
+
+/// The data stage of a transfer and its direction
+pub enum Data<'a> {
+    None,
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+impl Data<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Data::None => 0,
+            Data::In(buffer) => buffer.len(),
+            Data::Out(buffer) => buffer.len(),
+        }
+    }
+}
+
+/// The 8-byte request that starts a control transfer
+#[derive(Debug, Clone, Copy)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    /// bmRequestType: data from the device
+    pub const DEVICE_TO_HOST: u8 = 0x80;
+    /// bmRequestType: class request to an interface
+    pub const CLASS_INTERFACE: u8 = 0x21;
+    /// bmRequestType: standard request to an endpoint
+    pub const STANDARD_ENDPOINT: u8 = 0x02;
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let [value_lo, value_hi] = self.value.to_le_bytes();
+        let [index_lo, index_hi] = self.index.to_le_bytes();
+        let [length_lo, length_hi] = self.length.to_le_bytes();
+        [self.request_type, self.request, value_lo, value_hi, index_lo, index_hi, length_lo, length_hi]
+    }
+}
+
+/// What a USB host controller driver provides
+pub trait HostController {
+    /// Kind of controller, for listings
+    fn name(&self) -> &'static str;
+
+    /// Root ports, numbered from 0
+    fn port_count(&self) -> usize;
+
+    /// Reset root port `port` and enable it. Returns the speed of the
+    /// device on it, or None if there is none this controller can drive.
+    fn reset_port(&mut self, port: usize) -> UsbResult<Option<Speed>>;
+
+    /// Run a control transfer on endpoint 0 of `pipe`'s device. Returns the
+    /// bytes of the data stage moved.
+    fn control(&mut self, pipe: &Pipe, setup: &SetupPacket, data: Data) -> UsbResult<usize>;
+
+    /// Run a bulk transfer, at most `max_transfer` bytes. `toggle` is the
+    /// endpoint's data toggle, updated for the packets sent. Returns the
+    /// bytes moved, fewer than asked if an IN transfer ended short.
+    fn bulk(&mut self, pipe: &Pipe, data: Data, toggle: &mut bool) -> UsbResult<usize>;
+
+    /// Most bytes one `bulk` or control data stage moves
+    fn max_transfer(&self) -> usize;
+}
+
+/// A controller driver, whichever kind
+pub enum Controller {
+    Ehci(ehci::Ehci),
+    Xhci(xhci::Xhci),
+}
+
+impl Controller {
+    pub fn host(&mut self) -> &mut dyn HostController {
+        match self {
+            Controller::Ehci(ehci) => ehci,
+            Controller::Xhci(xhci) => xhci,
+        }
+    }
+
+    /// MMIO base, for listings
+    pub fn base(&self) -> usize {
+        match self {
+            Controller::Ehci(ehci) => ehci.base(),
+            Controller::Xhci(xhci) => xhci.base(),
+        }
+    }
+}
+
+/// The controllers driven, by index
+pub static CONTROLLERS: Mutex<Vec<Controller, MAX_USB_HOSTS>> = Mutex::new(Vec::new());
+
+/// An enumerated device
+#[derive(Debug, Clone, Copy)]
+pub struct UsbDevice {
+    pub controller: usize,
+    pub port: usize,
+    pub address: u8,
+    pub speed: Speed,
+    pub vendor: u16,
+    pub product: u16,
+    /// Device class; 0 means each interface gives its own
+    pub class: u8,
+    pub max_packet0: u16,
+}
+
+impl UsbDevice {
+    /// Endpoint 0
+    pub fn control_pipe(&self) -> Pipe {
+        Pipe { address: self.address, endpoint: 0, max_packet: self.max_packet0, speed: self.speed }
+    }
+}
+
+static DEVICES: Mutex<Vec<UsbDevice, MAX_DEVICES>> = Mutex::new(Vec::new());
+
+/// An endpoint descriptor
+#[derive(Debug, Clone, Copy)]
+pub struct Endpoint {
+    /// Endpoint number and direction (bit 7 set for IN)
+    pub address: u8,
+    /// Transfer type in bits 0-1: 2 is bulk
+    pub attributes: u8,
+    pub max_packet: u16,
+}
+
+impl Endpoint {
+    pub fn is_in(&self) -> bool {
+        self.address & 0x80 != 0
+    }
+
+    pub fn is_bulk(&self) -> bool {
+        self.attributes & 0x3 == 2
+    }
+
+    pub fn number(&self) -> u8 {
+        self.address & 0x0f
+    }
+}
+
+/// An interface descriptor and its endpoints
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub number: u8,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub endpoints: Vec<Endpoint, MAX_ENDPOINTS>,
+}
+
+/// The interfaces of a configuration descriptor, alternate settings other
+/// than 0 skipped
+pub fn parse_interfaces(config: &[u8]) -> Vec<Interface, MAX_INTERFACES> {
+    let mut interfaces: Vec<Interface, MAX_INTERFACES> = Vec::new();
+    let mut in_alternate = false;
+    let mut offset = 0;
+    while offset + 2 <= config.len() {
+        let length = config[offset] as usize;
+        if length < 2 || offset + length > config.len() {
+            break;
+        }
+        let descriptor = &config[offset..offset + length];
+        match descriptor[1] {
+            DESCRIPTOR_INTERFACE if length >= 9 => {
+                in_alternate = descriptor[3] != 0;
+                if !in_alternate {
+                    let _ = interfaces.push(Interface {
+                        number: descriptor[2],
+                        class: descriptor[5],
+                        subclass: descriptor[6],
+                        protocol: descriptor[7],
+                        endpoints: Vec::new(),
+                    });
+                }
+            }
+            DESCRIPTOR_ENDPOINT if length >= 7 && !in_alternate => {
+                if let Some(interface) = interfaces.last_mut() {
+                    let _ = interface.endpoints.push(Endpoint {
+                        address: descriptor[2],
+                        attributes: descriptor[3],
+                        max_packet: u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x7ff,
+                    });
+                }
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    interfaces
+}
+
+/// Spin for `ms` milliseconds; USB resets and recoveries are timed in them
+pub fn delay_ms(ms: u64) {
+    let start = timer::ticks();
+    while timer::elapsed_ms(start) < ms {
+        core::hint::spin_loop();
+    }
+}
+
+/// Read descriptor `kind` number `index` into `buffer`. Returns its length.
+fn get_descriptor(host: &mut dyn HostController, pipe: &Pipe, kind: u8, index: u8, buffer: &mut [u8]) -> UsbResult<usize> {
+    let setup = SetupPacket {
+        request_type: SetupPacket::DEVICE_TO_HOST,
+        request: REQUEST_GET_DESCRIPTOR,
+        value: (kind as u16) << 8 | index as u16,
+        index: 0,
+        length: buffer.len() as u16,
+    };
+    host.control(pipe, &setup, Data::In(buffer))
+}
+
+/// Clear a halt (stall) on endpoint `endpoint` (with its direction bit)
+pub fn clear_halt(host: &mut dyn HostController, device: &UsbDevice, endpoint: u8) -> UsbResult<()> {
+    let setup = SetupPacket {
+        request_type: SetupPacket::STANDARD_ENDPOINT,
+        request: REQUEST_CLEAR_FEATURE,
+        value: FEATURE_ENDPOINT_HALT,
+        index: endpoint as u16,
+        length: 0,
+    };
+    host.control(&device.control_pipe(), &setup, Data::None).map(|_| ())
+}
+
+/// Give the device on a freshly reset port `address`, select its first
+/// configuration and let the class drivers have its interfaces
+fn enumerate(host: &mut dyn HostController, controller: usize, port: usize, speed: Speed, address: u8) -> UsbResult<UsbDevice> {
+    // Only the first 8 bytes are safe to ask for before the packet size
+    // of endpoint 0 is known
+    let default_packet = if speed == Speed::Low { 8 } else { 64 };
+    let mut pipe = Pipe { address: 0, endpoint: 0, max_packet: default_packet, speed };
+    let mut descriptor = [0u8; 18];
+    if get_descriptor(host, &pipe, DESCRIPTOR_DEVICE, 0, &mut descriptor[..8])? < 8 {
+        return Err(UsbError::BadResponse);
+    }
+    pipe.max_packet = match descriptor[7] {
+        8 | 16 | 32 | 64 => descriptor[7] as u16,
+        // SuperSpeed gives it as a power of two
+        9 if speed == Speed::Super => 512,
+        _ => return Err(UsbError::BadResponse),
+    };
+
+    let setup = SetupPacket { request_type: 0, request: REQUEST_SET_ADDRESS, value: address as u16, index: 0, length: 0 };
+    host.control(&pipe, &setup, Data::None)?;
+    delay_ms(2);
+    pipe.address = address;
+
+    if get_descriptor(host, &pipe, DESCRIPTOR_DEVICE, 0, &mut descriptor)? < descriptor.len() {
+        return Err(UsbError::BadResponse);
+    }
+    let device = UsbDevice {
+        controller,
+        port,
+        address,
+        speed,
+        vendor: u16::from_le_bytes([descriptor[8], descriptor[9]]),
+        product: u16::from_le_bytes([descriptor[10], descriptor[11]]),
+        class: descriptor[4],
+        max_packet0: pipe.max_packet,
+    };
+
+    let mut config = [0u8; CONFIG_BUFFER];
+    if get_descriptor(host, &pipe, DESCRIPTOR_CONFIGURATION, 0, &mut config[..9])? < 9 {
+        return Err(UsbError::BadResponse);
+    }
+    let total = (u16::from_le_bytes([config[2], config[3]]) as usize).clamp(9, CONFIG_BUFFER);
+    let length = get_descriptor(host, &pipe, DESCRIPTOR_CONFIGURATION, 0, &mut config[..total])?;
+    let setup = SetupPacket { request_type: 0, request: REQUEST_SET_CONFIGURATION, value: config[5] as u16, index: 0, length: 0 };
+    host.control(&pipe, &setup, Data::None)?;
+
+    for interface in parse_interfaces(&config[..length]) {
+        if storage::probe(host, &device, &interface) {
+            continue;
+        }
+        info_println!(target: "usb", "{:04x}:{:04x}: no driver for interface {} (class {:02x})",
+            device.vendor, device.product, interface.number, interface.class);
+    }
+    Ok(device)
+}
+
+/// Start the controllers the device tree lists and enumerate what is
+/// plugged into them
+pub fn init() {
+    let mut controllers = CONTROLLERS.lock();
+    for host in platform::usb_hosts() {
+        let controller = match host.kind {
+            UsbHostKind::Ehci => ehci::Ehci::new(host.device.base).map(Controller::Ehci),
+            UsbHostKind::Xhci => xhci::Xhci::new(host.device.base).map(Controller::Xhci),
+        };
+        match controller {
+            Ok(controller) => {
+                let _ = controllers.push(controller);
+            }
+            Err(e) => warn_println!(target: "usb", "{:?} controller at 0x{:x} not usable: {}", host.kind, host.device.base, e),
+        }
+    }
+
+    let mut devices = DEVICES.lock();
+    for (index, controller) in controllers.iter_mut().enumerate() {
+        let base = controller.base();
+        let host = controller.host();
+        ok_println!(target: "usb", "{} controller at 0x{:x}, {} ports", host.name(), base, host.port_count());
+        for port in 0..host.port_count() {
+            let speed = match host.reset_port(port) {
+                Ok(Some(speed)) => speed,
+                Ok(None) => continue,
+                Err(UsbError::Unsupported) => {
+                    info_println!(target: "usb", "{} at 0x{:x}: no transfer support yet, devices not enumerated", host.name(), base);
+                    break;
+                }
+                Err(e) => {
+                    warn_println!(target: "usb", "Port {} reset failed: {}", port, e);
+                    continue;
+                }
+            };
+            // Addresses are 1-127; one per device across controllers is plenty
+            let address = devices.len() as u8 + 1;
+            match enumerate(host, index, port, speed, address) {
+                Ok(device) => {
+                    ok_println!(target: "usb", "Port {}: {:04x}:{:04x} at {}", port, device.vendor, device.product, speed.name());
+                    if devices.push(device).is_err() {
+                        warn_println!(target: "usb", "More than {} devices, ignoring the rest", MAX_DEVICES);
+                        return;
+                    }
+                }
+                Err(e) => warn_println!(target: "usb", "Port {}: enumeration failed: {}", port, e),
+            }
+        }
+    }
+}
+
+/// The controllers driven: name, MMIO base and root ports
+pub fn controllers() -> Vec<(&'static str, usize, usize), MAX_USB_HOSTS> {
+    CONTROLLERS.lock().iter_mut()
+        .map(|controller| {
+            let base = controller.base();
+            let host = controller.host();
+            (host.name(), base, host.port_count())
+        })
+        .collect()
+}
+
+/// The enumerated devices
+pub fn devices() -> Vec<UsbDevice, MAX_DEVICES> {
+    DEVICES.lock().clone()
+}
@@ -0,0 +1,365 @@
+//! USB mass storage: Bulk-Only Transport carrying SCSI commands
+//!
+//! An interface of class 08h (mass storage), subclass 06h (SCSI) and
+//! protocol 50h (Bulk-Only) with a bulk endpoint each way becomes a disk,
+//! usb0, usb1, ... Each command goes out in a 31-byte Command Block
+//! Wrapper, its data follows on the bulk endpoint its direction says, and
+//! a 13-byte Command Status Wrapper comes back. Only LUN 0 is used, and
+//! only disks with 512-byte blocks are taken, as the sector cache and the
+//! partition reader work in those.
+//!
+//! A disk is a `SectorDevice` through `with_disk`. The block layer gives
+//! usbN a device index after the VirtIO disks (see
+//! `virtio::block::partition`), so it goes through the sector cache and
+//! can be mounted, listed by `lsblk` and automounted like those. A disk
+//! found is queued for the automounter, which takes it with `take_new`.
+
+use heapless::{String, Vec};
+use spin::Mutex;
+use elinos_common::blockcache::{SectorDevice, SECTOR_SIZE};
+use elinos_common::virtio::storage::{read_partition_table, PartitionTable};
+use elinos_common::{ok_println, warn_println};
+
+use super::{Data, HostController, Interface, Pipe, SetupPacket, UsbDevice, UsbError, UsbResult, CONTROLLERS};
+
+/// Most USB disks
+pub const MAX_DISKS: usize = 4;
+
+const CLASS_MASS_STORAGE: u8 = 0x08;
+const SUBCLASS_SCSI: u8 = 0x06;
+const PROTOCOL_BULK_ONLY: u8 = 0x50;
+
+/// Class request: reset the Bulk-Only state machine
+const REQUEST_BULK_ONLY_RESET: u8 = 0xff;
+
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CBW_LENGTH: usize = 31;
+const CSW_LENGTH: usize = 13;
+const CBW_FLAG_IN: u8 = 0x80;
+
+const CSW_PASSED: u8 = 0;
+const CSW_FAILED: u8 = 1;
+
+// SCSI commands
+const SCSI_TEST_UNIT_READY: u8 = 0x00;
+const SCSI_REQUEST_SENSE: u8 = 0x03;
+const SCSI_INQUIRY: u8 = 0x12;
+const SCSI_READ_CAPACITY_10: u8 = 0x25;
+const SCSI_READ_10: u8 = 0x28;
+const SCSI_WRITE_10: u8 = 0x2a;
+
+/// Times to ask a disk that is spinning up or has just been plugged in
+/// whether it is ready
+const READY_TRIES: usize = 5;
+
+/// Sectors one READ(10) or WRITE(10) moves at most, also limited by the
+/// controller's transfer size
+const MAX_SECTORS_PER_COMMAND: usize = 32;
+
+/// A mass storage interface
+pub struct MassStorage {
+    pub device: UsbDevice,
+    interface: u8,
+    bulk_in: Pipe,
+    bulk_out: Pipe,
+    /// Endpoint addresses with their direction bits, for clearing a halt
+    in_endpoint: u8,
+    out_endpoint: u8,
+    toggle_in: bool,
+    toggle_out: bool,
+    tag: u32,
+    pub blocks: u64,
+    /// "vendor product" from INQUIRY
+    pub model: String<32>,
+}
+
+/// A disk and its partition table
+pub struct Disk {
+    pub storage: MassStorage,
+    pub partitions: Option<PartitionTable>,
+}
+
+static DISKS: Mutex<Vec<Disk, MAX_DISKS>> = Mutex::new(Vec::new());
+
+/// Disks found and not yet seen by the automounter
+static NEW_DISKS: Mutex<Vec<usize, MAX_DISKS>> = Mutex::new(Vec::new());
+
+/// Trailing spaces off an INQUIRY text field
+fn inquiry_text(bytes: &[u8]) -> &str {
+    core::str::from_utf8(bytes).unwrap_or("?").trim_end()
+}
+
+impl MassStorage {
+    /// Send one SCSI command, its data moved through `data`
+    fn command(&mut self, host: &mut dyn HostController, cdb: &[u8], mut data: Data) -> UsbResult<usize> {
+        self.tag = self.tag.wrapping_add(1);
+        let mut cbw = [0u8; CBW_LENGTH];
+        cbw[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        cbw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        cbw[8..12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        cbw[12] = if matches!(data, Data::In(_)) { CBW_FLAG_IN } else { 0 };
+        cbw[14] = cdb.len() as u8;
+        cbw[15..15 + cdb.len()].copy_from_slice(cdb);
+        host.bulk(&self.bulk_out, Data::Out(&cbw), &mut self.toggle_out)?;
+
+        // A stalled data stage is cleared and the status read anyway
+        let mut moved = 0;
+        let result = match &mut data {
+            Data::None => Ok(0),
+            Data::In(buffer) => host.bulk(&self.bulk_in, Data::In(buffer), &mut self.toggle_in),
+            Data::Out(buffer) => host.bulk(&self.bulk_out, Data::Out(buffer), &mut self.toggle_out),
+        };
+        match result {
+            Ok(count) => moved = count,
+            Err(UsbError::Stall) => {
+                let endpoint = if matches!(data, Data::In(_)) { self.in_endpoint } else { self.out_endpoint };
+                self.clear_halt(host, endpoint)?;
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut csw = [0u8; CSW_LENGTH];
+        match host.bulk(&self.bulk_in, Data::In(&mut csw), &mut self.toggle_in) {
+            Err(UsbError::Stall) => {
+                self.clear_halt(host, self.in_endpoint)?;
+                host.bulk(&self.bulk_in, Data::In(&mut csw), &mut self.toggle_in)?;
+            }
+            result => {
+                result?;
+            }
+        }
+        let signature = u32::from_le_bytes([csw[0], csw[1], csw[2], csw[3]]);
+        let tag = u32::from_le_bytes([csw[4], csw[5], csw[6], csw[7]]);
+        if signature != CSW_SIGNATURE || tag != self.tag {
+            self.reset_recovery(host);
+            return Err(UsbError::BadResponse);
+        }
+        match csw[12] {
+            CSW_PASSED => Ok(moved),
+            CSW_FAILED => Err(UsbError::Transfer),
+            // Phase error
+            _ => {
+                self.reset_recovery(host);
+                Err(UsbError::BadResponse)
+            }
+        }
+    }
+
+    fn clear_halt(&mut self, host: &mut dyn HostController, endpoint: u8) -> UsbResult<()> {
+        super::clear_halt(host, &self.device, endpoint)?;
+        // A cleared endpoint starts over at DATA0
+        if endpoint == self.in_endpoint {
+            self.toggle_in = false;
+        } else {
+            self.toggle_out = false;
+        }
+        Ok(())
+    }
+
+    /// Bring the device back in step after it lost track of the protocol
+    fn reset_recovery(&mut self, host: &mut dyn HostController) {
+        let setup = SetupPacket {
+            request_type: SetupPacket::CLASS_INTERFACE,
+            request: REQUEST_BULK_ONLY_RESET,
+            value: 0,
+            index: self.interface as u16,
+            length: 0,
+        };
+        let _ = host.control(&self.device.control_pipe(), &setup, Data::None);
+        let _ = self.clear_halt(host, self.in_endpoint);
+        let _ = self.clear_halt(host, self.out_endpoint);
+    }
+
+    /// Ask who the disk is, wait for it to be ready and read its size
+    fn start(&mut self, host: &mut dyn HostController) -> UsbResult<()> {
+        let mut inquiry = [0u8; 36];
+        self.command(host, &[SCSI_INQUIRY, 0, 0, 0, inquiry.len() as u8, 0], Data::In(&mut inquiry))?;
+        let _ = self.model.push_str(inquiry_text(&inquiry[8..16]));
+        let _ = self.model.push(' ');
+        let _ = self.model.push_str(inquiry_text(&inquiry[16..32]));
+
+        let mut ready = false;
+        for _ in 0..READY_TRIES {
+            if self.command(host, &[SCSI_TEST_UNIT_READY, 0, 0, 0, 0, 0], Data::None).is_ok() {
+                ready = true;
+                break;
+            }
+            // Fetching the sense data clears the condition that failed it
+            let mut sense = [0u8; 18];
+            let _ = self.command(host, &[SCSI_REQUEST_SENSE, 0, 0, 0, sense.len() as u8, 0], Data::In(&mut sense));
+            super::delay_ms(100);
+        }
+        if !ready {
+            return Err(UsbError::Timeout);
+        }
+
+        let mut capacity = [0u8; 8];
+        self.command(host, &[SCSI_READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0], Data::In(&mut capacity))?;
+        let last_block = u32::from_be_bytes([capacity[0], capacity[1], capacity[2], capacity[3]]);
+        let block_size = u32::from_be_bytes([capacity[4], capacity[5], capacity[6], capacity[7]]);
+        if block_size as usize != SECTOR_SIZE {
+            return Err(UsbError::Unsupported);
+        }
+        self.blocks = last_block as u64 + 1;
+        Ok(())
+    }
+
+    /// READ(10) or WRITE(10) of the sectors filling `data` from `sector`
+    fn transfer(&mut self, host: &mut dyn HostController, sector: u64, data: Data) -> UsbResult<()> {
+        let sectors = data.len() / SECTOR_SIZE;
+        if sector + sectors as u64 > self.blocks {
+            return Err(UsbError::Transfer);
+        }
+        let chunk = (host.max_transfer() / SECTOR_SIZE).clamp(1, MAX_SECTORS_PER_COMMAND) * SECTOR_SIZE;
+        let lba = |offset: usize| (sector + (offset / SECTOR_SIZE) as u64) as u32;
+        let cdb = |opcode: u8, lba: u32, count: usize| {
+            let [a, b, c, d] = lba.to_be_bytes();
+            let [hi, lo] = (count as u16).to_be_bytes();
+            [opcode, 0, a, b, c, d, 0, hi, lo, 0]
+        };
+        match data {
+            Data::In(buffer) => {
+                for (index, part) in buffer.chunks_mut(chunk).enumerate() {
+                    let count = part.len() / SECTOR_SIZE;
+                    let command = cdb(SCSI_READ_10, lba(index * chunk), count);
+                    if self.command(host, &command, Data::In(part))? < count * SECTOR_SIZE {
+                        return Err(UsbError::Transfer);
+                    }
+                }
+            }
+            Data::Out(buffer) => {
+                for (index, part) in buffer.chunks(chunk).enumerate() {
+                    let command = cdb(SCSI_WRITE_10, lba(index * chunk), part.len() / SECTOR_SIZE);
+                    self.command(host, &command, Data::Out(part))?;
+                }
+            }
+            Data::None => {}
+        }
+        Ok(())
+    }
+}
+
+/// A disk with the controller it is on, as the sector reader sees it
+struct DiskIo<'a> {
+    storage: &'a mut MassStorage,
+    host: &'a mut dyn HostController,
+}
+
+impl SectorDevice for DiskIo<'_> {
+    type Error = UsbError;
+
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> UsbResult<()> {
+        self.storage.transfer(self.host, sector, Data::In(buf))
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> UsbResult<()> {
+        self.storage.transfer(self.host, sector, Data::Out(buf))
+    }
+
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> UsbResult<()> {
+        self.storage.transfer(self.host, sector, Data::In(buf))
+    }
+
+    fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> UsbResult<()> {
+        self.storage.transfer(self.host, sector, Data::Out(buf))
+    }
+}
+
+/// Take `interface` of `device` if it is Bulk-Only mass storage. Returns
+/// whether it was taken, even if the disk then did not start.
+pub fn probe(host: &mut dyn HostController, device: &UsbDevice, interface: &Interface) -> bool {
+    if interface.class != CLASS_MASS_STORAGE || interface.subclass != SUBCLASS_SCSI || interface.protocol != PROTOCOL_BULK_ONLY {
+        return false;
+    }
+    let bulk_in = interface.endpoints.iter().find(|endpoint| endpoint.is_bulk() && endpoint.is_in());
+    let bulk_out = interface.endpoints.iter().find(|endpoint| endpoint.is_bulk() && !endpoint.is_in());
+    let (Some(bulk_in), Some(bulk_out)) = (bulk_in, bulk_out) else {
+        warn_println!(target: "usb", "{:04x}:{:04x}: mass storage without bulk endpoints", device.vendor, device.product);
+        return true;
+    };
+
+    let pipe = |endpoint: &super::Endpoint| Pipe {
+        address: device.address,
+        endpoint: endpoint.number(),
+        max_packet: endpoint.max_packet,
+        speed: device.speed,
+    };
+    let mut storage = MassStorage {
+        device: *device,
+        interface: interface.number,
+        bulk_in: pipe(bulk_in),
+        bulk_out: pipe(bulk_out),
+        in_endpoint: bulk_in.address,
+        out_endpoint: bulk_out.address,
+        toggle_in: false,
+        toggle_out: false,
+        tag: 0,
+        blocks: 0,
+        model: String::new(),
+    };
+    if let Err(e) = storage.start(host) {
+        warn_println!(target: "usb", "{:04x}:{:04x}: disk not usable: {}", device.vendor, device.product, e);
+        return true;
+    }
+
+    let partitions = {
+        let blocks = storage.blocks;
+        let mut io = DiskIo { storage: &mut storage, host };
+        read_partition_table(&mut io, blocks).unwrap_or_else(|e| {
+            warn_println!(target: "usb", "Could not read partition table: {}", e);
+            None
+        })
+    };
+    let mut disks = DISKS.lock();
+    let index = disks.len();
+    ok_println!(target: "usb", "usb{}: {}, {} sectors, {} partitions", index, storage.model, storage.blocks,
+        partitions.as_ref().map_or(0, |table| table.partitions.len()));
+    if disks.push(Disk { storage, partitions }).is_err() {
+        warn_println!(target: "usb", "More than {} disks, ignoring usb{}", MAX_DISKS, index);
+    } else {
+        let _ = NEW_DISKS.lock().push(index);
+    }
+    true
+}
+
+/// Disks found, by index: (model, sectors, partitions)
+pub fn disks() -> Vec<(String<32>, u64, Option<PartitionTable>), MAX_DISKS> {
+    DISKS.lock().iter()
+        .map(|disk| (disk.storage.model.clone(), disk.storage.blocks, disk.partitions.clone()))
+        .collect()
+}
+
+/// Sectors on disk `index`, if there is one
+pub fn capacity(index: usize) -> Option<u64> {
+    DISKS.lock().get(index).map(|disk| disk.storage.blocks)
+}
+
+/// Partition table of disk `index`, if it has one
+pub fn partition_table(index: usize) -> Option<PartitionTable> {
+    DISKS.lock().get(index)?.partitions.clone()
+}
+
+/// First sector and length of partition `number` of disk `index`
+pub fn partition_extent(index: usize, number: u8) -> Option<(u64, u64)> {
+    let disks = DISKS.lock();
+    let partition = disks.get(index)?.partitions.as_ref()?.get(number)?;
+    Some((partition.start, partition.sectors))
+}
+
+/// A disk found since the last call, to mount
+pub fn take_new() -> Option<usize> {
+    let mut new = NEW_DISKS.lock();
+    (!new.is_empty()).then(|| new.remove(0))
+}
+
+/// Run `f` with disk `index` as a `SectorDevice`
+pub fn with_disk<T>(index: usize, f: impl FnOnce(&mut dyn SectorDevice<Error = UsbError>) -> T) -> UsbResult<T> {
+    // Controllers before disks, as at enumeration
+    let mut controllers = CONTROLLERS.lock();
+    let mut disks = DISKS.lock();
+    let disk = disks.get_mut(index).ok_or(UsbError::NoDevice)?;
+    let controller = controllers.get_mut(disk.storage.device.controller).ok_or(UsbError::NoDevice)?;
+    let mut io = DiskIo { storage: &mut disk.storage, host: controller.host() };
+    Ok(f(&mut io))
+}
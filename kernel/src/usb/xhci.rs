@@ -0,0 +1,76 @@
+//! xHCI (USB 3.x) host controller
+//!
+//! Recognized, not yet driven: the controller is left as the firmware set
+//! it up, its ports are counted and their connection status read, but
+//! enumeration needs the command and event rings and device contexts, which
+//! are not written. `reset_port` says so, and `usb::init` moves on.
+
+use elinos_common::mmio::{Register, RegisterBlock};
+
+use super::{Data, HostController, Pipe, SetupPacket, Speed, UsbError, UsbResult};
+
+// Capability registers
+const CAP_LENGTH_VERSION: Register<u32> = Register::at(0x00);
+const HCSPARAMS1: Register<u32> = Register::at(0x04);
+
+/// First port's PORTSC, in the operational registers; each port has 16 bytes
+const PORTSC: Register<u32> = Register::at(0x400);
+
+const PORT_CONNECTED: u32 = 1 << 0;
+
+pub struct Xhci {
+    base: usize,
+    op: RegisterBlock,
+    ports: usize,
+}
+
+impl Xhci {
+    pub fn new(base: usize) -> UsbResult<Self> {
+        let cap = unsafe { RegisterBlock::new(base) };
+        let cap_length = (cap.read(CAP_LENGTH_VERSION) & 0xff) as usize;
+        if cap_length == 0 {
+            return Err(UsbError::NoDevice);
+        }
+        let ports = (cap.read(HCSPARAMS1) >> 24) as usize;
+        let op = unsafe { RegisterBlock::new(base + cap_length) };
+        Ok(Xhci { base, op, ports })
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Whether something is plugged into root port `port`
+    pub fn is_connected(&self, port: usize) -> bool {
+        self.op.read(PORTSC.offset_by(0x10 * port)) & PORT_CONNECTED != 0
+    }
+}
+
+impl HostController for Xhci {
+    fn name(&self) -> &'static str {
+        "xHCI"
+    }
+
+    fn port_count(&self) -> usize {
+        self.ports
+    }
+
+    fn reset_port(&mut self, port: usize) -> UsbResult<Option<Speed>> {
+        if !self.is_connected(port) {
+            return Ok(None);
+        }
+        Err(UsbError::Unsupported)
+    }
+
+    fn control(&mut self, _pipe: &Pipe, _setup: &SetupPacket, _data: Data) -> UsbResult<usize> {
+        Err(UsbError::Unsupported)
+    }
+
+    fn bulk(&mut self, _pipe: &Pipe, _data: Data, _toggle: &mut bool) -> UsbResult<usize> {
+        Err(UsbError::Unsupported)
+    }
+
+    fn max_transfer(&self) -> usize {
+        0
+    }
+}
@@ -1,47 +1,91 @@
 //! VirtIO Block Device implementation
+//!
+//! Single-sector reads and writes wait for their request before returning.
+//! `read_blocks` and `write_blocks` run a batch instead: each request moves
+//! up to `SECTORS_PER_REQUEST` consecutive sectors in one transfer, up to
+//! `BATCH_SLOTS` requests are put on the queue before the device is
+//! notified, and each request is finished as its completion comes back, in
+//! whatever order the device completes them. Sectors of a request that
+//! fails are tried again one by one, under the retry policy.
 
 use spin::Mutex;
-use elinos_common::console_println;
-use core::{convert::TryInto, result::Result::{Ok, Err}};
+use elinos_common::{ok_println, err_println, warn_println, trace_println};
+use core::{convert::TryInto, fmt::Write, result::Result::{Ok, Err}};
 
 use super::super::{DiskResult, DiskError, VirtqDesc, VirtioQueue};
+use super::super::queue::preferred_queue_size;
 use super::super::mmio::*;
-use super::{VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT, VIRTIO_BLK_S_OK, VIRTIO_BLK_REQUEST_QUEUE_IDX};
+use elinos_common::mmio::{Register, RegisterBlock};
+use super::{VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT, VIRTIO_BLK_T_DISCARD, VIRTIO_BLK_S_OK, VIRTIO_BLK_REQUEST_QUEUE_IDX, VIRTIO_BLK_F_DISCARD};
+use super::health::{self, DeviceHealth, RetryPolicy};
+use crate::memory::slab::{SlabBox, SlabCache};
 
 
-/// VirtIO buffer structure for proper memory management
+/// Devices the block driver can drive at once, named blk0, blk1, ...
+pub const MAX_BLOCK_DEVICES: usize = 4;
+
+/// Requests a batch keeps on the queue at once
+pub const BATCH_SLOTS: usize = 8;
+
+/// Most sectors one request transfers: a 4 KB ext2 block
+pub const SECTORS_PER_REQUEST: usize = 8;
+
+/// Sectors handed to one batch; the failed ones are noted in a `u64`
+const BATCH_SECTORS: usize = 64;
+
+/// Data bytes of one request slot
+const REQUEST_BYTES: usize = SECTORS_PER_REQUEST * 512;
+
+/// Data buffers of one device, one for each of `BATCH_SLOTS` requests.
+/// Single-sector requests use slot 0. Each request's header and status
+/// come from `REQUEST_HEADER_CACHE` instead.
+#[derive(Clone, Copy)]
 struct VirtioBuffers {
     base_addr: usize,
-    request_offset: usize,
-    data_offset: usize,
-    status_offset: usize,
 }
 
 impl VirtioBuffers {
     fn new(base_addr: usize) -> Self {
-        VirtioBuffers {
-            base_addr,
-            request_offset: 0,
-            data_offset: 16,      // After 16-byte request
-            status_offset: 528,   // After 16-byte request + 512-byte data
-        }
+        VirtioBuffers { base_addr }
     }
     
-    fn get_request_buffer(&self) -> *mut VirtioBlkReq {
-        (self.base_addr + self.request_offset) as *mut VirtioBlkReq
+    fn get_data_buffer(&self, slot: usize) -> *mut [u8; REQUEST_BYTES] {
+        (self.base_addr + slot * REQUEST_BYTES) as *mut [u8; REQUEST_BYTES]
     }
-    
-    fn get_data_buffer(&self) -> *mut [u8; 512] {
-        (self.base_addr + self.data_offset) as *mut [u8; 512]
+}
+
+/// The header of a request, which the device reads, and the status byte it
+/// writes back
+#[repr(C)]
+pub struct RequestHeader {
+    request: VirtioBlkReq,
+    status: u8,
+}
+
+/// Request headers of every block device, one taken for each request in
+/// flight
+pub static REQUEST_HEADER_CACHE: SlabCache = SlabCache::of::<RequestHeader>("virtio_blk_req");
+
+impl RequestHeader {
+    /// A header for `request` from the cache, its status not yet written
+    fn new(request: VirtioBlkReq) -> DiskResult<SlabBox<RequestHeader>> {
+        SlabBox::new_in(&REQUEST_HEADER_CACHE, RequestHeader { request, status: 0xFF })
+            .map_err(|_| DiskError::OutOfMemory)
     }
-    
-    fn get_status_buffer(&self) -> *mut u8 {
-        (self.base_addr + self.status_offset) as *mut u8
+
+    fn request_addr(&self) -> u64 {
+        &self.request as *const VirtioBlkReq as u64
     }
-}
 
-/// VirtIO buffer addresses (will be set during initialization)
-static mut VIRTIO_BUFFERS: Option<VirtioBuffers> = None;
+    fn status_addr(&self) -> u64 {
+        &self.status as *const u8 as u64
+    }
+
+    /// The status the device wrote
+    fn status(&self) -> u8 {
+        unsafe { core::ptr::read_volatile(&self.status) }
+    }
+}
 
 /// VirtIO block request header
 #[repr(C)]
@@ -68,17 +112,40 @@ impl VirtioBlkReq {
             sector,
         }
     }
+
+    pub fn new_discard() -> Self {
+        VirtioBlkReq {
+            type_: VIRTIO_BLK_T_DISCARD,
+            reserved: 0,
+            sector: 0,
+        }
+    }
+}
+
+/// One range of a discard request
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct VirtioBlkDiscard {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
 }
 
 /// VirtIO Block Device implementation
 pub struct RustVmmVirtIOBlock {
     initialized: bool,
     capacity_sectors: u64,
+    /// Most sectors one discard request may cover; 0 if the device cannot
+    /// discard
+    max_discard_sectors: u32,
     mmio_base: usize,
     queue: VirtioQueue,
     device_features: u64,
     driver_features: u64,
     is_legacy: bool,
+    buffers: Option<VirtioBuffers>,
+    retry: RetryPolicy,
+    health: DeviceHealth,
 }
 
 impl RustVmmVirtIOBlock {
@@ -86,19 +153,31 @@ impl RustVmmVirtIOBlock {
         RustVmmVirtIOBlock {
             initialized: false,
             capacity_sectors: 0,
+            max_discard_sectors: 0,
             mmio_base: 0,
             queue: VirtioQueue::new(),
             device_features: 0,
             driver_features: 0,
             is_legacy: false,
+            buffers: None,
+            retry: RetryPolicy::DEFAULT,
+            health: DeviceHealth::new(),
         }
     }
 
-    pub fn init(&mut self) -> DiskResult<()> {
-        if !self.discover_device()? {
+    /// Initialize the block device at `base`
+    pub fn init(&mut self, base: usize) -> DiskResult<()> {
+        if !self.probe_mmio_device(base)? {
             return Err(DiskError::DeviceNotFound);
         }
-        
+        self.mmio_base = base;
+        self.retry = RetryPolicy::from_cmdline();
+
+        // Register the device MMIO region using our memory mapping API
+        if super::super::register_virtio_device(base, VIRTIO_MMIO_SIZE, "VirtIO-Block").is_err() {
+            warn_println!(target: "virtio", "Failed to register VirtIO MMIO region");
+        }
+
         self.init_device()?;
         self.setup_queue()?;
         self.set_driver_ok()?;
@@ -107,40 +186,16 @@ impl RustVmmVirtIOBlock {
         Ok(())
     }
 
-    fn discover_device(&mut self) -> DiskResult<bool> {
-        let mmio_addresses = [
-            0x10001000, 0x10002000, 0x10003000, 0x10004000,
-            0x10005000, 0x10006000, 0x10007000, 0x10008000,
-        ];
-        
-        for &addr in &mmio_addresses {
-            if self.probe_mmio_device(addr)? {
-                self.mmio_base = addr;
-                
-                // Register the device MMIO region using our memory mapping API
-                const VIRTIO_MMIO_SIZE: usize = 0x1000; // 4KB MMIO region
-                match super::super::register_virtio_device(addr, VIRTIO_MMIO_SIZE, "VirtIO-Block") {
-                    Ok(_) => {},
-                    Err(_) => console_println!("[!] Failed to register VirtIO MMIO region"),
-                }
-                
-                return Ok(true);
-            }
-        }
-        
-        console_println!("[x] No VirtIO block device found");
-        Ok(false)
-    }
-
     fn probe_mmio_device(&mut self, base: usize) -> DiskResult<bool> {
         unsafe {
-            let magic = core::ptr::read_volatile((base + VIRTIO_MMIO_MAGIC_VALUE) as *const u32);
-            if magic != 0x74726976 {
+            let regs = RegisterBlock::new(base);
+            let magic = regs.read(VIRTIO_MMIO_MAGIC_VALUE);
+            if magic != VIRTIO_MMIO_MAGIC {
                 return Ok(false);
             }
             
-            let version = core::ptr::read_volatile((base + VIRTIO_MMIO_VERSION) as *const u32);
-            let device_id = core::ptr::read_volatile((base + VIRTIO_MMIO_DEVICE_ID) as *const u32);
+            let version = regs.read(VIRTIO_MMIO_VERSION);
+            let device_id = regs.read(VIRTIO_MMIO_DEVICE_ID);
             if device_id != 2 {
                 return Ok(false);
             }
@@ -158,163 +213,65 @@ impl RustVmmVirtIOBlock {
     }
 
     fn init_device(&mut self) -> DiskResult<()> {
-        unsafe {
-            self.write_reg_u32(VIRTIO_MMIO_STATUS, 0);
-            self.set_status(VIRTIO_STATUS_ACKNOWLEDGE as u8);
-            self.set_status(VIRTIO_STATUS_DRIVER as u8);
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, 0);
+        self.set_status(VIRTIO_STATUS_ACKNOWLEDGE as u8);
+        self.set_status(VIRTIO_STATUS_DRIVER as u8);
+        
+        if self.is_legacy {
+            self.device_features = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+            self.driver_features = self.device_features & (VIRTIO_RING_F_INDIRECT_DESC | VIRTIO_BLK_F_DISCARD);
+            self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, self.driver_features as u32);
+        } else {
+            self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 0);
+            let features_lo = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
+            self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+            let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
             
-            if self.is_legacy {
-                self.device_features = core::ptr::read_volatile((self.mmio_base + VIRTIO_MMIO_DEVICE_FEATURES) as *const u32) as u64;
-                self.driver_features = 0;
-                self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, self.driver_features as u32);
-            } else {
-                self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 0);
-                let features_lo = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
-                self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
-                let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
-                
-                self.device_features = ((features_hi as u64) << 32) | (features_lo as u64);
-                self.driver_features = 0;
-                
-                self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
-                self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, self.driver_features as u32);
-                self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
-                self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, (self.driver_features >> 32) as u32);
-                
-                self.set_status(VIRTIO_STATUS_FEATURES_OK as u8);
-                
-                let status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
-                if (status & VIRTIO_STATUS_FEATURES_OK) == 0 {
-                    return Err(DiskError::VirtIOError);
-                }
-            }
+            self.device_features = ((features_hi as u64) << 32) | (features_lo as u64);
+            self.driver_features = self.device_features & (VIRTIO_RING_F_INDIRECT_DESC | VIRTIO_F_VERSION_1 | VIRTIO_BLK_F_DISCARD);
             
-            let capacity_low = self.read_reg_u32(VIRTIO_MMIO_CONFIG);
-            let capacity_high = self.read_reg_u32(VIRTIO_MMIO_CONFIG + 4);
-            self.capacity_sectors = ((capacity_high as u64) << 32) | (capacity_low as u64);
+            self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+            self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, self.driver_features as u32);
+            self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+            self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, (self.driver_features >> 32) as u32);
             
+            self.set_status(VIRTIO_STATUS_FEATURES_OK as u8);
+            
+            let status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
+            if (status & VIRTIO_STATUS_FEATURES_OK) == 0 {
+                return Err(DiskError::VirtIOError);
+            }
+        }
+        
+        let capacity_low = self.read_reg_u32(config_register(0));
+        let capacity_high = self.read_reg_u32(config_register(4));
+        self.capacity_sectors = ((capacity_high as u64) << 32) | (capacity_low as u64);
+
+        if self.driver_features & VIRTIO_BLK_F_DISCARD != 0 {
+            self.max_discard_sectors = self.read_reg_u32(config_register(36));
         }
         
         Ok(())
     }
 
     fn setup_queue(&mut self) -> DiskResult<()> {
+        self.queue = VirtioQueue::setup(
+            self.regs(),
+            VIRTIO_BLK_REQUEST_QUEUE_IDX,
+            preferred_queue_size(),
+            self.is_legacy,
+        )?;
+        if self.driver_features & VIRTIO_RING_F_INDIRECT_DESC != 0 {
+            self.queue.enable_indirect()?;
+        }
+
+        // Data buffers
+        const BUFFER_AREA_SIZE: usize = BATCH_SLOTS * REQUEST_BYTES;
+        let buffer_area_addr = super::super::allocate_virtio_memory(BUFFER_AREA_SIZE)?;
         unsafe {
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_SEL, 0);
-            
-            let max_queue_size = self.read_reg_u32(VIRTIO_MMIO_QUEUE_NUM_MAX);
-            
-            let queue_size = 64.min(max_queue_size as u16);
-            if !queue_size.is_power_of_two() {
-                return Err(DiskError::VirtIOError);
-            }
-            
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NUM, queue_size as u32);
-            
-            if self.is_legacy {
-                // Step 1: Set guest page size (REQUIRED for legacy VirtIO)
-                self.write_reg_u32(VIRTIO_MMIO_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
-                
-                // Step 2: Calculate memory layout following VirtIO spec
-                // Legacy VirtIO requires ALL rings to be contiguous and page-aligned
-                let desc_table_size = 16 * queue_size as usize; // 16 bytes per descriptor
-                let avail_ring_size = 6 + 2 * queue_size as usize; // 6 bytes header + 2 bytes per entry
-                let used_ring_size = 6 + 8 * queue_size as usize; // 6 bytes header + 8 bytes per entry
-                
-                // Calculate aligned layout exactly like rcore-os
-                let driver_area_offset = desc_table_size;
-                let device_area_offset = align_up(desc_table_size + avail_ring_size);
-                let buffer_area_offset = align_up(device_area_offset + used_ring_size);
-                let buffer_area_size = 1024; // Space for request + data + status buffers
-                let total_size = align_up(buffer_area_offset + buffer_area_size);
-                
-                
-                // Allocate page-aligned memory using VirtIO memory manager
-                let desc_table_addr = super::super::allocate_virtio_memory(total_size)?;
-                let avail_ring_addr = desc_table_addr + driver_area_offset;
-                let used_ring_addr = desc_table_addr + device_area_offset;
-                let buffer_area_addr = desc_table_addr + buffer_area_offset;
-                
-                // Validate memory layout (like rcore-os does)
-                if desc_table_addr % PAGE_SIZE != 0 {
-                    return Err(DiskError::VirtIOError);
-                }
-                
-                
-                // Zero out the queue memory region before use
-                unsafe {
-                    core::ptr::write_bytes(desc_table_addr as *mut u8, 0, total_size);
-                }
-                
-                // Initialize queue structures
-                self.queue.init(queue_size, VIRTIO_BLK_REQUEST_QUEUE_IDX, desc_table_addr, avail_ring_addr, used_ring_addr)?;
-                
-                // Set up buffer area for VirtIO operations
-                unsafe {
-                    VIRTIO_BUFFERS = Some(VirtioBuffers::new(buffer_area_addr));
-                }
-                
-                // Step 3: Set queue alignment (power of 2, typically page size)
-                let queue_align = PAGE_SIZE as u32;
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_ALIGN, queue_align);
-                
-                // Step 4: Set queue PFN (Page Frame Number)
-                let pfn = (desc_table_addr / PAGE_SIZE) as u32;
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_PFN, pfn);
-                
-                // Verify the PFN was accepted
-                let read_pfn = self.read_reg_u32(VIRTIO_MMIO_QUEUE_PFN);
-                
-            } else {
-                // Modern VirtIO: Uses separate registers for each ring
-                let desc_table_size = 16 * queue_size as usize;
-                let avail_ring_size = 6 + 2 * queue_size as usize;
-                let used_ring_size = 6 + 8 * queue_size as usize;
-                
-                // Calculate the total span of memory used by the modern queue setup
-                let modern_used_ring_content_size = 4 + (8 * queue_size as usize);
-                let total_size = desc_table_size + avail_ring_size + modern_used_ring_content_size + 64; // Add padding
-                
-                // Allocate memory using VirtIO memory manager
-                let desc_table_addr = super::super::allocate_virtio_memory(total_size)?;
-                let avail_ring_addr = desc_table_addr + desc_table_size;
-                let used_ring_addr = (avail_ring_addr + avail_ring_size + 3) & !3; // 4-byte aligned
-
-                // Allocate buffer area for VirtIO operations (same as legacy)
-                const BUFFER_AREA_SIZE: usize = 4096; // Request + Data + Status buffers
-                let buffer_area_addr = super::super::allocate_virtio_memory(BUFFER_AREA_SIZE)?;
-
-                // Zero out the queue memory region before use
-                unsafe {
-                    core::ptr::write_bytes(desc_table_addr as *mut u8, 0, total_size);
-                }
-                
-                // Initialize the queue structure
-                self.queue.init(queue_size, VIRTIO_BLK_REQUEST_QUEUE_IDX, desc_table_addr, avail_ring_addr, used_ring_addr)?;
-                
-                // Set up buffer area for VirtIO operations
-                unsafe {
-                    VIRTIO_BUFFERS = Some(VirtioBuffers::new(buffer_area_addr));
-                }
-                
-                // Modern VirtIO uses separate registers for each ring
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DESC_LOW, desc_table_addr as u32);
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DESC_HIGH, (desc_table_addr >> 32) as u32);
-                
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DRIVER_LOW, avail_ring_addr as u32);
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DRIVER_HIGH, (avail_ring_addr >> 32) as u32);
-                
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DEVICE_LOW, used_ring_addr as u32);
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_DEVICE_HIGH, (used_ring_addr >> 32) as u32);
-                
-                // Mark queue as ready (modern VirtIO only)
-                self.write_reg_u32(VIRTIO_MMIO_QUEUE_READY, 1);
-            }
-            
+            core::ptr::write_bytes(buffer_area_addr as *mut u8, 0, BUFFER_AREA_SIZE);
         }
-        
-        // CRITICAL: Set queue ready AFTER all hardware setup is complete
-        self.queue.ready = true;
+        self.buffers = Some(VirtioBuffers::new(buffer_area_addr));
         Ok(())
     }
 
@@ -337,112 +294,198 @@ impl RustVmmVirtIOBlock {
             return Err(DiskError::InvalidSector);
         }
         
-        self.virtio_read_sector(sector, buffer)
+        self.with_retries(false, |device| device.virtio_read_sector(sector, buffer))
+    }
+
+    /// Run one request, trying again as the retry policy says while it
+    /// fails in a way that may pass, and account for the outcome
+    fn with_retries(&mut self, write: bool, mut request: impl FnMut(&mut Self) -> DiskResult<()>) -> DiskResult<()> {
+        if self.health.offline {
+            return Err(DiskError::DeviceOffline);
+        }
+        let mut retry = 0;
+        loop {
+            match request(self) {
+                Ok(()) => {
+                    self.health.succeeded(write);
+                    return Ok(());
+                }
+                Err(e) if health::is_transient(e) && retry < self.retry.retries => {
+                    self.health.retries += 1;
+                    for _ in 0..self.retry.backoff(retry) {
+                        core::hint::spin_loop();
+                    }
+                    retry += 1;
+                }
+                Err(e) => {
+                    if health::is_transient(e) && self.health.failed(write) {
+                        err_println!(target: "virtio", "Block device at 0x{:x}: {} requests in a row failed, taking it offline",
+                            self.mmio_base, self.health.failures_in_row);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Error counters and state since the device was attached
+    pub fn health(&self) -> &DeviceHealth {
+        &self.health
+    }
+
+    /// The retry policy the device runs with
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
     }
 
     fn virtio_read_sector(&mut self, sector: u64, buffer: &mut [u8; 512]) -> DiskResult<()> {
+        let buffers = self.buffers.as_ref().ok_or(DiskError::NotInitialized)?;
+        let data_ptr = buffers.get_data_buffer(0);
+        let header = RequestHeader::new(VirtioBlkReq::new_read(sector))?;
+        let desc_chain = [
+            VirtqDesc {
+                addr: header.request_addr(),
+                len: core::mem::size_of::<VirtioBlkReq>() as u32,
+                flags: VIRTQ_DESC_F_NEXT,
+                next: 1,
+            },
+            VirtqDesc {
+                addr: data_ptr as u64,
+                len: 512,
+                flags: VIRTQ_DESC_F_WRITE | VIRTQ_DESC_F_NEXT,
+                next: 2,
+            },
+            VirtqDesc {
+                addr: header.status_addr(),
+                len: 1,
+                flags: VIRTQ_DESC_F_WRITE,
+                next: 0,
+            },
+        ];
+        
+        trace_println!(target: "virtio", "Block request 0x{:x} ({}), data 0x{:x} ({}), status 0x{:x}",
+            desc_chain[0].addr, desc_chain[0].len, desc_chain[1].addr, desc_chain[1].len, desc_chain[2].addr);
+        
+        let head_index = self.queue.add_descriptor_chain(&desc_chain)?;
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32);
+            
+        if self.wait_for_request(head_index, header)? == VIRTIO_BLK_S_OK {
+            let data_buffer = unsafe { &*data_ptr };
+            buffer.copy_from_slice(&data_buffer[..512]);
+            Ok(())
+        } else {
+            Err(DiskError::ReadError)
+        }
+    }
+
+    /// Wait for the request with chain head `head_index` and return the
+    /// status the device wrote into `header`. If the device never answers,
+    /// the header is leaked rather than freed, as it may still write to it.
+    fn wait_for_request(&mut self, head_index: u16, header: SlabBox<RequestHeader>) -> DiskResult<u8> {
+        match self.queue.wait_for_used(head_index) {
+            Some(_) => Ok(header.status()),
+            None => {
+                core::mem::forget(header);
+                Err(DiskError::IoError)
+            }
+        }
+    }
+
+    pub fn write_sector(&mut self, sector: u64, buffer: &[u8; 512]) -> DiskResult<()> {
+        if !self.initialized {
+            return Err(DiskError::NotInitialized);
+        }
+        self.with_retries(true, |device| device.virtio_write_sector(sector, buffer))
+    }
+
+    fn virtio_write_sector(&mut self, sector: u64, buffer: &[u8; 512]) -> DiskResult<()> {
+        let buffers = self.buffers.as_ref().ok_or(DiskError::NotInitialized)?;
+        let data_ptr = buffers.get_data_buffer(0);
+        let header = RequestHeader::new(VirtioBlkReq::new_write(sector))?;
         let head_index;
         unsafe {
-            // Initialize request in virtual buffer
-            let request_ptr = get_request_buffer();
-            *request_ptr = VirtioBlkReq::new_read(sector);
-            
-            // Initialize status in virtual buffer
-            let status_ptr = get_status_buffer();
-            *status_ptr = 0xFF;
-            
+            // Copy data to virtual buffer
+            let data_buffer = &mut *data_ptr;
+            data_buffer[..512].copy_from_slice(buffer);
+
             let desc_chain = [
                 VirtqDesc {
-                    addr: request_ptr as u64,
+                    addr: header.request_addr(),
                     len: core::mem::size_of::<VirtioBlkReq>() as u32,
                     flags: VIRTQ_DESC_F_NEXT,
                     next: 1,
                 },
                 VirtqDesc {
-                    addr: get_data_buffer() as u64,
+                    addr: data_buffer.as_ptr() as u64,
                     len: 512,
-                    flags: VIRTQ_DESC_F_WRITE | VIRTQ_DESC_F_NEXT,
+                    flags: VIRTQ_DESC_F_NEXT,
                     next: 2,
                 },
                 VirtqDesc {
-                    addr: status_ptr as u64,
+                    addr: header.status_addr(),
                     len: 1,
                     flags: VIRTQ_DESC_F_WRITE,
                     next: 0,
                 },
             ];
-            
-            // Debug: Show descriptor addresses
-            // console_println!("[DEBUG] VirtIO descriptors (virtual buffers):");
-            // console_println!("  Request: 0x{:x} (len={})", desc_chain[0].addr, desc_chain[0].len);
-            // console_println!("  Data:    0x{:x} (len={})", desc_chain[1].addr, desc_chain[1].len);
-            // console_println!("  Status:  0x{:x} (len={})", desc_chain[2].addr, desc_chain[2].len);
-            
+
             head_index = self.queue.add_descriptor_chain(&desc_chain)?;
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32);
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32); 
         }
-            
-        let mut timeout = 2000000;
-        
-        loop {
-            if timeout <= 0 {
-                return Err(DiskError::IoError);
-            }
 
-            if let Some(_) = self.queue.wait_for_completion(head_index) {
-                unsafe {
-                    if *get_status_buffer() == VIRTIO_BLK_S_OK {
-                        let data_buffer = &*get_data_buffer();
-                        buffer.copy_from_slice(data_buffer);
-                        return Ok(());
-                    } else {
-                        return Err(DiskError::ReadError);
-                    }
-                }
-            }
-            
-            timeout -= 1;
-            core::hint::spin_loop();
+        if self.wait_for_request(head_index, header)? == VIRTIO_BLK_S_OK {
+            Ok(())
+        } else {
+            Err(DiskError::WriteError)
         }
     }
+    
+    /// Whether the device takes discard requests
+    pub fn supports_discard(&self) -> bool {
+        self.initialized && self.max_discard_sectors > 0
+    }
 
-    pub fn write_sector(&mut self, sector: u64, buffer: &[u8; 512]) -> DiskResult<()> {
-        if !self.initialized {
-            return Err(DiskError::NotInitialized);
+    /// Tell the device that `count` sectors from `sector` no longer hold
+    /// data, so flash can erase them. Devices that cannot discard refuse.
+    pub fn discard(&mut self, sector: u64, count: u64) -> DiskResult<()> {
+        if !self.supports_discard() {
+            return Err(DiskError::InvalidParameter);
+        }
+        if sector.saturating_add(count) > self.capacity_sectors {
+            return Err(DiskError::InvalidSector);
+        }
+        let mut done = 0;
+        while done < count {
+            let chunk = (count - done).min(self.max_discard_sectors as u64) as u32;
+            self.with_retries(true, |device| device.virtio_discard(sector + done, chunk))?;
+            done += chunk as u64;
         }
-        self.virtio_write_sector(sector, buffer)
+        Ok(())
     }
 
-    fn virtio_write_sector(&mut self, sector: u64, buffer: &[u8; 512]) -> DiskResult<()> {
+    fn virtio_discard(&mut self, sector: u64, count: u32) -> DiskResult<()> {
+        let buffers = self.buffers.as_ref().ok_or(DiskError::NotInitialized)?;
+        let range_ptr = buffers.get_data_buffer(0) as *mut VirtioBlkDiscard;
+        let header = RequestHeader::new(VirtioBlkReq::new_discard())?;
         let head_index;
         unsafe {
-            // Initialize request in virtual buffer
-            let request_ptr = get_request_buffer();
-            *request_ptr = VirtioBlkReq::new_write(sector);
-            
-            // Copy data to virtual buffer
-            let data_buffer = &mut *get_data_buffer();
-            data_buffer.copy_from_slice(buffer);
-            
-            // Initialize status in virtual buffer
-            let status_ptr = get_status_buffer();
-            *status_ptr = 0xFF;
+            *range_ptr = VirtioBlkDiscard { sector, num_sectors: count, flags: 0 };
 
             let desc_chain = [
                 VirtqDesc {
-                    addr: request_ptr as u64,
+                    addr: header.request_addr(),
                     len: core::mem::size_of::<VirtioBlkReq>() as u32,
                     flags: VIRTQ_DESC_F_NEXT,
                     next: 1,
                 },
                 VirtqDesc {
-                    addr: data_buffer.as_ptr() as u64,
-                    len: 512,
+                    addr: range_ptr as u64,
+                    len: core::mem::size_of::<VirtioBlkDiscard>() as u32,
                     flags: VIRTQ_DESC_F_NEXT,
                     next: 2,
                 },
                 VirtqDesc {
-                    addr: status_ptr as u64,
+                    addr: header.status_addr(),
                     len: 1,
                     flags: VIRTQ_DESC_F_WRITE,
                     next: 0,
@@ -450,85 +493,182 @@ impl RustVmmVirtIOBlock {
             ];
 
             head_index = self.queue.add_descriptor_chain(&desc_chain)?;
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32); 
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32);
         }
 
-        let mut timeout = 2000000;
-        
-        loop {
-            if timeout <= 0 {
-                return Err(DiskError::IoError);
-            }
-
-            if let Some(_) = self.queue.wait_for_completion(head_index) {
-                unsafe {
-                    if *get_status_buffer() == VIRTIO_BLK_S_OK {
-                        return Ok(());
-                    } else {
-                        return Err(DiskError::WriteError); 
-                    }
-                }
-            }
-            
-            timeout -= 1;
-            core::hint::spin_loop(); 
+        match self.wait_for_request(head_index, header)? {
+            VIRTIO_BLK_S_OK => Ok(()),
+            _ => Err(DiskError::WriteError),
         }
     }
-    
+
     pub fn is_initialized(&self) -> bool {
         self.initialized
     }
     
+    /// MMIO base of the device in use, if initialized
+    pub fn mmio_base(&self) -> Option<usize> {
+        if self.initialized { Some(self.mmio_base) } else { None }
+    }
+
     pub fn get_capacity(&self) -> u64 {
         self.capacity_sectors
     }
     
+    /// Read the sectors from `start_sector` that fill `buffer`, in batches
     pub fn read_blocks(&mut self, start_sector: u64, buffer: &mut [u8]) -> DiskResult<()> {
-        if buffer.len() == 0 {
-            return Ok(());
-        }
-        if buffer.len() % 512 != 0 {
-            return Err(DiskError::BufferTooSmall); 
-        }
-        let num_sectors = buffer.len() / 512;
-        for i in 0..num_sectors {
-            let sector = start_sector + i as u64;
-            let offset = i * 512;
-            let sector_buffer_slice = &mut buffer[offset..offset + 512];
-            let sector_buffer_array: &mut [u8; 512] = sector_buffer_slice.try_into()
-                .expect("Slice to array conversion failed in read_blocks despite checks");
-            self.read_sector(sector, sector_buffer_array)?;
+        self.check_range(start_sector, buffer.len())?;
+        for (i, chunk) in buffer.chunks_mut(BATCH_SECTORS * 512).enumerate() {
+            let first = start_sector + (i * BATCH_SECTORS) as u64;
+            let failed = match self.run_batch(first, chunk.len() / 512, false,
+                |_, _| {},
+                |index, data| chunk[index * 512..index * 512 + data.len()].copy_from_slice(data))
+            {
+                Ok(failed) => failed,
+                // The batch as a whole went wrong; go sector by sector
+                Err(_) => u64::MAX,
+            };
+            for (index, sector_buffer) in chunk.chunks_exact_mut(512).enumerate() {
+                if failed & (1 << index) != 0 {
+                    let sector_buffer: &mut [u8; 512] = sector_buffer.try_into().map_err(|_| DiskError::BufferTooSmall)?;
+                    self.read_sector(first + index as u64, sector_buffer)?;
+                }
+            }
         }
         Ok(())
     }
 
+    /// Write the sectors in `buffer` from `start_sector`, in batches
     pub fn write_blocks(&mut self, start_sector: u64, buffer: &[u8]) -> DiskResult<()> {
-        if buffer.len() == 0 {
-            return Ok(());
+        self.check_range(start_sector, buffer.len())?;
+        for (i, chunk) in buffer.chunks(BATCH_SECTORS * 512).enumerate() {
+            let first = start_sector + (i * BATCH_SECTORS) as u64;
+            let failed = self.run_batch(first, chunk.len() / 512, true,
+                |index, data| data.copy_from_slice(&chunk[index * 512..index * 512 + data.len()]),
+                |_, _| {})
+                .unwrap_or(u64::MAX);
+            for (index, sector_buffer) in chunk.chunks_exact(512).enumerate() {
+                if failed & (1 << index) != 0 {
+                    let sector_buffer: &[u8; 512] = sector_buffer.try_into().map_err(|_| DiskError::BufferTooSmall)?;
+                    self.write_sector(first + index as u64, sector_buffer)?;
+                }
+            }
         }
-        if buffer.len() % 512 != 0 {
+        Ok(())
+    }
+
+    /// Whether a transfer of `len` bytes from `start_sector` can be done
+    fn check_range(&self, start_sector: u64, len: usize) -> DiskResult<()> {
+        if !self.initialized {
+            return Err(DiskError::NotInitialized);
+        }
+        if self.health.offline {
+            return Err(DiskError::DeviceOffline);
+        }
+        if len % 512 != 0 {
             return Err(DiskError::BufferTooSmall);
         }
-        let num_sectors = buffer.len() / 512;
-        for i in 0..num_sectors {
-            let sector = start_sector + i as u64;
-            let offset = i * 512;
-            let sector_buffer_slice = &buffer[offset..offset + 512];
-            let sector_buffer_array: &[u8; 512] = sector_buffer_slice.try_into()
-                .expect("Slice to array conversion failed in write_blocks despite checks");
-            self.write_sector(sector, sector_buffer_array)?;
+        if start_sector.saturating_add((len / 512) as u64) > self.capacity_sectors {
+            return Err(DiskError::InvalidSector);
         }
         Ok(())
     }
 
-    fn read_reg_u32(&self, offset: usize) -> u32 {
-        let ptr = (self.mmio_base + offset) as *const u32;
-        unsafe { core::ptr::read_volatile(ptr) }
+    /// Transfer `count` sectors, at most `BATCH_SECTORS`, from
+    /// `start_sector`. Requests are numbered by the index in the batch of
+    /// their first sector. `fill` puts the data of a request into its slot
+    /// before a write is queued; `on_complete` is called with the slot data
+    /// of each request the device finished without error, in completion
+    /// order. Returns the sectors that failed, bit `index` set for each.
+    fn run_batch(
+        &mut self,
+        start_sector: u64,
+        count: usize,
+        write: bool,
+        mut fill: impl FnMut(usize, &mut [u8]),
+        mut on_complete: impl FnMut(usize, &[u8]),
+    ) -> DiskResult<u64> {
+        let buffers = self.buffers.ok_or(DiskError::NotInitialized)?;
+        let count = count.min(BATCH_SECTORS);
+        // First sector index, sectors and chain head of the request in each slot
+        let mut in_flight: [Option<(usize, usize, u16)>; BATCH_SLOTS] = [None; BATCH_SLOTS];
+        let mut headers: [Option<SlabBox<RequestHeader>>; BATCH_SLOTS] = Default::default();
+        let (mut next, mut done, mut failed) = (0, 0, 0u64);
+
+        while done < count {
+            let mut queued = false;
+            for (slot, request) in in_flight.iter_mut().enumerate() {
+                if next == count {
+                    break;
+                }
+                if request.is_some() {
+                    continue;
+                }
+                let data_ptr = buffers.get_data_buffer(slot);
+                let sector = start_sector + next as u64;
+                let sectors = (count - next).min(SECTORS_PER_REQUEST);
+                let header = match RequestHeader::new(if write { VirtioBlkReq::new_write(sector) } else { VirtioBlkReq::new_read(sector) }) {
+                    Ok(header) => header,
+                    // Wait for a request in flight to give its header back
+                    Err(_) if next > done => break,
+                    Err(e) => return Err(e),
+                };
+                if write {
+                    fill(next, unsafe { &mut (&mut *data_ptr)[..sectors * 512] });
+                }
+                let data_flags = if write { 0 } else { VIRTQ_DESC_F_WRITE };
+                let desc_chain = [
+                    VirtqDesc { addr: header.request_addr(), len: core::mem::size_of::<VirtioBlkReq>() as u32, flags: 0, next: 0 },
+                    VirtqDesc { addr: data_ptr as u64, len: (sectors * 512) as u32, flags: data_flags, next: 0 },
+                    VirtqDesc { addr: header.status_addr(), len: 1, flags: VIRTQ_DESC_F_WRITE, next: 0 },
+                ];
+                match self.queue.add_descriptor_chain(&desc_chain) {
+                    Ok(head) => {
+                        *request = Some((next, sectors, head));
+                        headers[slot] = Some(header);
+                        next += sectors;
+                        queued = true;
+                    }
+                    // A small queue takes fewer at a time
+                    Err(_) if next > done => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            if queued {
+                self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, self.queue.queue_index as u32);
+            }
+
+            let Some(elem) = self.queue.wait_for_any_used() else {
+                // The device may still write the status of what it holds
+                headers.into_iter().flatten().for_each(core::mem::forget);
+                return Err(DiskError::IoError);
+            };
+            let Some(slot) = in_flight.iter().position(|request| matches!(request, Some((_, _, head)) if *head as u32 == elem.id)) else {
+                continue; // Not one of ours
+            };
+            let Some((index, sectors, _)) = in_flight[slot].take() else { continue };
+            let status = headers[slot].take().map_or(0xFF, |header| header.status());
+            done += sectors;
+            if status == VIRTIO_BLK_S_OK {
+                self.health.succeeded(write);
+                on_complete(index, unsafe { &(&*buffers.get_data_buffer(slot))[..sectors * 512] });
+            } else {
+                failed |= ((1u64 << sectors) - 1) << index;
+            }
+        }
+        Ok(failed)
+    }
+
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(self.mmio_base) }
+    }
+
+    fn read_reg_u32(&self, reg: Register<u32>) -> u32 {
+        self.regs().read(reg)
     }
 
-    fn write_reg_u32(&mut self, offset: usize, value: u32) {
-        let ptr = (self.mmio_base + offset) as *mut u32;
-        unsafe { core::ptr::write_volatile(ptr, value) }
+    fn write_reg_u32(&mut self, reg: Register<u32>, value: u32) {
+        self.regs().write(reg, value)
     }
 
     fn set_status(&mut self, status_val: u8) {
@@ -537,50 +677,81 @@ impl RustVmmVirtIOBlock {
     }
 }
 
-// Helper functions that use proper buffer management
-unsafe fn get_request_buffer() -> *mut VirtioBlkReq {
-    VIRTIO_BUFFERS.as_ref().unwrap().get_request_buffer()
-}
+const NO_DEVICE: Mutex<RustVmmVirtIOBlock> = Mutex::new(RustVmmVirtIOBlock::new());
+
+/// Block devices by index; uninitialized entries are free
+pub static VIRTIO_BLK_DEVICES: [Mutex<RustVmmVirtIOBlock>; MAX_BLOCK_DEVICES] = [NO_DEVICE; MAX_BLOCK_DEVICES];
 
-unsafe fn get_data_buffer() -> *mut [u8; 512] {
-    VIRTIO_BUFFERS.as_ref().unwrap().get_data_buffer()
+/// Block device `index`, initialized or not
+pub fn block_device(index: usize) -> Option<&'static Mutex<RustVmmVirtIOBlock>> {
+    VIRTIO_BLK_DEVICES.get(index)
 }
 
-unsafe fn get_status_buffer() -> *mut u8 {
-    VIRTIO_BUFFERS.as_ref().unwrap().get_status_buffer()
+/// Index of a device name such as "blk1", or "usb0" for a USB disk
+pub fn parse_device_name(name: &str) -> Option<usize> {
+    if let Some(disk) = name.strip_prefix("usb") {
+        let disk = disk.parse::<usize>().ok()?;
+        return (disk < crate::usb::storage::MAX_DISKS).then_some(super::partition::USB_DISK_BASE + disk);
+    }
+    let index = name.strip_prefix("blk")?.parse::<usize>().ok()?;
+    if index < MAX_BLOCK_DEVICES { Some(index) } else { None }
 }
 
-// Global instance
-pub static VIRTIO_BLK: Mutex<RustVmmVirtIOBlock> = Mutex::new(RustVmmVirtIOBlock::new());
+/// Index of the initialized device at MMIO base `base`
+pub fn find_by_base(base: usize) -> Option<usize> {
+    VIRTIO_BLK_DEVICES.iter().position(|device| device.lock().mmio_base() == Some(base))
+}
 
-/// Initialize the VirtIO block device
+/// Initialize every block device on the MMIO bus
 pub fn init_virtio_blk() -> DiskResult<()> {
-    let mut device = VIRTIO_BLK.lock();
-    device.init()
+    let mut found = 0;
+    for slot in enumerate().filter(|slot| slot.device_id == VIRTIO_ID_BLOCK) {
+        let mut stage = heapless::String::<32>::new();
+        let _ = write!(stage, "Probe 0x{:x}", slot.base);
+        crate::bootchart::begin(&stage);
+        let result = init_with_address(slot.base);
+        crate::bootchart::end();
+        match result {
+            Ok(_) => found += 1,
+            Err(e) => warn_println!(target: "virtio", "VirtIO block device at 0x{:x} not usable: {:?}", slot.base, e),
+        }
+    }
+
+    if found == 0 {
+        err_println!(target: "virtio", "No VirtIO block device found");
+        return Err(DiskError::DeviceNotFound);
+    }
+    Ok(())
 }
 
-/// Initialize VirtIO block device with specific address
-pub fn init_with_address(base_addr: usize) -> bool {
-    
-    unsafe {
-        let magic = core::ptr::read_volatile(base_addr as *const u32);
-        if magic != 0x74726976 {
-            return false;
-        }
-        
-        let device_id = core::ptr::read_volatile((base_addr + VIRTIO_MMIO_DEVICE_ID) as *const u32);
-        
-        if device_id != 2 {
-            return false;
-        }
-        
-        let mut device = RustVmmVirtIOBlock::new();
-        device.mmio_base = base_addr;
-        if device.init().is_ok() {
-            *VIRTIO_BLK.lock() = device;
-            return true;
-        }
+/// Initialize the block device at `base_addr` in the first free index and
+/// return that index
+pub fn init_with_address(base_addr: usize) -> DiskResult<usize> {
+    if find_by_base(base_addr).is_some() {
+        return Err(DiskError::InvalidParameter);
     }
-    
-    false
-} 
\ No newline at end of file
+    let index = VIRTIO_BLK_DEVICES.iter()
+        .position(|device| !device.lock().is_initialized())
+        .ok_or(DiskError::DeviceNotReady)?;
+
+    let mut device = RustVmmVirtIOBlock::new();
+    device.init(base_addr)?;
+    ok_println!(target: "virtio", "blk{}: {} sectors at 0x{:x}", index, device.get_capacity(), base_addr);
+    *VIRTIO_BLK_DEVICES[index].lock() = device;
+
+    match super::partition::scan(index) {
+        Ok(0) => {}
+        Ok(count) => ok_println!(target: "virtio", "blk{}: {} partitions", index, count),
+        Err(e) => warn_println!(target: "virtio", "blk{}: could not read partition table: {:?}", index, e),
+    }
+    Ok(index)
+}
+
+/// Forget the device at `base_addr` after it disappeared
+pub fn detach(base_addr: usize) {
+    if let Some(index) = find_by_base(base_addr) {
+        *VIRTIO_BLK_DEVICES[index].lock() = RustVmmVirtIOBlock::new();
+        super::partition::forget(index);
+        warn_println!(target: "virtio", "blk{} detached", index);
+    }
+}
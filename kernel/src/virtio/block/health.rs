@@ -0,0 +1,157 @@
+//! Retries and error accounting for the block devices
+//!
+//! Removable media and flaky hardware fail now and then. A failed sector
+//! read or write is retried with a growing pause between tries before the
+//! error reaches the filesystem. A device whose requests keep failing even
+//! so is marked offline: further I/O fails at once instead of spinning on
+//! a dead disk, until the device is attached again.
+//!
+//! The kernel command line sets the policy: `virtio.blk_retries=N` tries
+//! each request N more times (0 turns retrying off) and
+//! `virtio.blk_backoff=N` is the pause before the first retry, in spins.
+
+use core::fmt;
+use elinos_common::warn_println;
+
+use crate::virtio::DiskError;
+
+/// Retries of a failed request when the command line does not say
+pub const DEFAULT_RETRIES: u8 = 3;
+
+/// Most retries the command line may ask for
+pub const MAX_RETRIES: u8 = 16;
+
+/// Spins before the first retry; each further retry waits twice as long
+pub const DEFAULT_BACKOFF_SPINS: u32 = 10_000;
+
+/// Requests in a row that must fail, retries and all, to take a device
+/// offline
+pub const OFFLINE_AFTER_FAILURES: u32 = 3;
+
+/// How hard to try a failed request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub retries: u8,
+    pub backoff_spins: u32,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: RetryPolicy = RetryPolicy { retries: DEFAULT_RETRIES, backoff_spins: DEFAULT_BACKOFF_SPINS };
+
+    /// The policy the kernel command line asks for
+    pub fn from_cmdline() -> Self {
+        let cmdline = crate::boot_cmdline();
+        let mut policy = RetryPolicy::DEFAULT;
+        for arg in cmdline.split_whitespace() {
+            if let Some(value) = arg.strip_prefix("virtio.blk_retries=") {
+                match value.parse::<u8>() {
+                    Ok(retries) if retries <= MAX_RETRIES => policy.retries = retries,
+                    _ => warn_println!(target: "virtio", "Invalid virtio.blk_retries '{}', using {}", value, DEFAULT_RETRIES),
+                }
+            } else if let Some(value) = arg.strip_prefix("virtio.blk_backoff=") {
+                match value.parse::<u32>() {
+                    Ok(spins) => policy.backoff_spins = spins,
+                    Err(_) => warn_println!(target: "virtio", "Invalid virtio.blk_backoff '{}', using {}", value, DEFAULT_BACKOFF_SPINS),
+                }
+            }
+        }
+        policy
+    }
+
+    /// Spins to wait before retry number `retry`, counting from 0
+    pub fn backoff(&self, retry: u8) -> u32 {
+        self.backoff_spins.saturating_mul(1 << retry.min(31))
+    }
+}
+
+/// Whether trying again may help: the device reported an error or did not
+/// answer, rather than the request being wrong
+pub fn is_transient(error: DiskError) -> bool {
+    matches!(error, DiskError::ReadError | DiskError::WriteError | DiskError::IoError | DiskError::QueueFull)
+}
+
+/// How a device is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthState {
+    /// No request has failed
+    Ok,
+    /// Some requests needed retries or failed
+    Degraded,
+    /// Too many requests failed in a row; I/O is refused
+    Offline,
+}
+
+impl fmt::Display for HealthState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HealthState::Ok => write!(f, "ok"),
+            HealthState::Degraded => write!(f, "degraded"),
+            HealthState::Offline => write!(f, "offline"),
+        }
+    }
+}
+
+/// I/O counters of one device since it was attached
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceHealth {
+    /// Sectors read and written
+    pub reads: u64,
+    pub writes: u64,
+    /// Requests that failed after all their retries
+    pub read_errors: u64,
+    pub write_errors: u64,
+    /// Retries made, successful or not
+    pub retries: u64,
+    /// Requests that failed since the last one that succeeded
+    pub failures_in_row: u32,
+    pub offline: bool,
+}
+
+impl DeviceHealth {
+    pub const fn new() -> Self {
+        DeviceHealth {
+            reads: 0,
+            writes: 0,
+            read_errors: 0,
+            write_errors: 0,
+            retries: 0,
+            failures_in_row: 0,
+            offline: false,
+        }
+    }
+
+    pub fn state(&self) -> HealthState {
+        if self.offline {
+            HealthState::Offline
+        } else if self.read_errors + self.write_errors + self.retries > 0 {
+            HealthState::Degraded
+        } else {
+            HealthState::Ok
+        }
+    }
+
+    pub fn succeeded(&mut self, write: bool) {
+        if write {
+            self.writes += 1;
+        } else {
+            self.reads += 1;
+        }
+        self.failures_in_row = 0;
+    }
+
+    /// Count a request that failed for good; returns true if that took the
+    /// device offline
+    pub fn failed(&mut self, write: bool) -> bool {
+        if write {
+            self.write_errors += 1;
+        } else {
+            self.read_errors += 1;
+        }
+        self.failures_in_row += 1;
+        if !self.offline && self.failures_in_row >= OFFLINE_AFTER_FAILURES {
+            self.offline = true;
+            return true;
+        }
+        false
+    }
+}
@@ -1,19 +1,27 @@
 //! VirtIO block device implementation
 
 pub mod device;
+pub mod partition;
+pub mod health;
 
 // Re-export main types
-pub use device::{RustVmmVirtIOBlock, VirtioBlkReq, VIRTIO_BLK};
+pub use device::{RustVmmVirtIOBlock, VirtioBlkReq, VIRTIO_BLK_DEVICES, MAX_BLOCK_DEVICES};
+pub use device::{block_device, parse_device_name, find_by_base, REQUEST_HEADER_CACHE};
+pub use partition::{Volume, parse_volume_name};
 
 // Re-export initialization functions
-pub use device::{init_virtio_blk, init_with_address};
+pub use device::{init_virtio_blk, init_with_address, detach};
 
 // Block device specific constants
 pub const VIRTIO_BLK_T_IN: u32 = 0;     // Read
 pub const VIRTIO_BLK_T_OUT: u32 = 1;    // Write  
 pub const VIRTIO_BLK_T_FLUSH: u32 = 4;  // Flush
+pub const VIRTIO_BLK_T_DISCARD: u32 = 11; // Discard
 pub const VIRTIO_BLK_S_OK: u8 = 0;      // Success
 pub const VIRTIO_BLK_S_IOERR: u8 = 1;   // I/O error
 pub const VIRTIO_BLK_S_UNSUPP: u8 = 2;  // Unsupported
 
-pub const VIRTIO_BLK_REQUEST_QUEUE_IDX: u16 = 0; 
\ No newline at end of file
+pub const VIRTIO_BLK_REQUEST_QUEUE_IDX: u16 = 0;
+
+/// The device can be told sectors no longer hold data
+pub const VIRTIO_BLK_F_DISCARD: u64 = 1 << 13; 
\ No newline at end of file
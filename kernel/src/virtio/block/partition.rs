@@ -0,0 +1,217 @@
+//! Partitions of the block devices
+//!
+//! A device's partition table is read when the device comes up and kept
+//! until it goes away. Partitions are named after their disk: blk0p1 is
+//! partition 1 of blk0. A `Volume` is a whole disk or one of its partitions,
+//! whichever a filesystem is mounted from.
+//!
+//! Device indices past the VirtIO ones are USB disks: usbN is device
+//! `USB_DISK_BASE + N`, and its partition table is the one the USB driver
+//! read when the disk was found. `capacity` and `with_device` reach either
+//! kind.
+
+use core::fmt;
+use heapless::Vec;
+use spin::Mutex;
+use elinos_common::blockcache::{SectorDevice, SECTOR_SIZE};
+use elinos_common::virtio::storage::{read_partition_table, PartitionTable, MAX_PARTITIONS};
+
+use super::device::{block_device, parse_device_name, RustVmmVirtIOBlock, MAX_BLOCK_DEVICES};
+use crate::usb::{self, UsbError};
+use crate::virtio::{DiskError, DiskResult};
+
+/// Device index of usb0
+pub const USB_DISK_BASE: usize = MAX_BLOCK_DEVICES;
+
+/// Block devices of both kinds, VirtIO then USB
+pub const MAX_DISKS: usize = USB_DISK_BASE + usb::storage::MAX_DISKS;
+
+/// The USB disk number of device `index`, if it is a USB disk
+fn usb_disk(index: usize) -> Option<usize> {
+    index.checked_sub(USB_DISK_BASE)
+}
+
+impl SectorDevice for RustVmmVirtIOBlock {
+    type Error = DiskError;
+
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        RustVmmVirtIOBlock::read_sector(self, sector, buf)
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        RustVmmVirtIOBlock::write_sector(self, sector, buf)
+    }
+
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), DiskError> {
+        self.read_blocks(sector, buf)
+    }
+
+    fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), DiskError> {
+        self.write_blocks(sector, buf)
+    }
+}
+
+/// A USB disk, its errors reported as disk errors
+struct UsbDisk<'a>(&'a mut dyn SectorDevice<Error = UsbError>);
+
+impl SectorDevice for UsbDisk<'_> {
+    type Error = DiskError;
+
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        self.0.read_sector(sector, buf).map_err(|_| DiskError::ReadError)
+    }
+
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        self.0.write_sector(sector, buf).map_err(|_| DiskError::WriteError)
+    }
+
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), DiskError> {
+        self.0.read_sectors(sector, buf).map_err(|_| DiskError::ReadError)
+    }
+
+    fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), DiskError> {
+        self.0.write_sectors(sector, buf).map_err(|_| DiskError::WriteError)
+    }
+}
+
+/// Sectors on device `index`, if it is up
+pub fn capacity(index: usize) -> Option<u64> {
+    if let Some(disk) = usb_disk(index) {
+        return usb::storage::capacity(disk);
+    }
+    let device = block_device(index)?.lock();
+    device.is_initialized().then(|| device.get_capacity())
+}
+
+/// Run `f` with device `index` as a `SectorDevice`
+pub fn with_device<T>(index: usize, f: impl FnOnce(&mut dyn SectorDevice<Error = DiskError>) -> Result<T, DiskError>) -> Result<T, DiskError> {
+    if let Some(disk) = usb_disk(index) {
+        return usb::storage::with_disk(disk, |disk| f(&mut UsbDisk(disk)))
+            .map_err(|_| DiskError::DeviceNotFound)?;
+    }
+    let mut device = block_device(index).ok_or(DiskError::DeviceNotFound)?.lock();
+    if !device.is_initialized() {
+        return Err(DiskError::NotInitialized);
+    }
+    f(&mut *device)
+}
+
+const NO_TABLE: Option<PartitionTable> = None;
+
+/// Partition table of each device, if it has one
+static TABLES: Mutex<[Option<PartitionTable>; MAX_BLOCK_DEVICES]> = Mutex::new([NO_TABLE; MAX_BLOCK_DEVICES]);
+
+/// A whole disk or one partition of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    /// Block device index
+    pub device: usize,
+    /// Partition number, 0 for the whole disk
+    pub partition: u8,
+}
+
+impl Volume {
+    /// The whole of block device `device`
+    pub const fn disk(device: usize) -> Self {
+        Volume { device, partition: 0 }
+    }
+
+    /// First sector and length in sectors, if the device is up and has
+    /// the partition
+    pub fn extent(&self) -> Option<(u64, u64)> {
+        let capacity = capacity(self.device)?;
+        if self.partition == 0 {
+            return Some((0, capacity));
+        }
+        if let Some(disk) = usb_disk(self.device) {
+            return usb::storage::partition_extent(disk, self.partition);
+        }
+        let tables = TABLES.lock();
+        let partition = tables[self.device].as_ref()?.get(self.partition)?;
+        Some((partition.start, partition.sectors))
+    }
+
+    /// Whether the two share sectors: the same partition, or a disk and
+    /// anything on it
+    pub fn overlaps(&self, other: &Volume) -> bool {
+        self.device == other.device
+            && (self.partition == other.partition || self.partition == 0 || other.partition == 0)
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match usb_disk(self.device) {
+            Some(disk) => write!(f, "usb{}", disk)?,
+            None => write!(f, "blk{}", self.device)?,
+        }
+        match self.partition {
+            0 => Ok(()),
+            partition => write!(f, "p{}", partition),
+        }
+    }
+}
+
+/// Volume named like "blk1", "blk0p2" or "usb0p1"
+pub fn parse_volume_name(name: &str) -> Option<Volume> {
+    match name.split_once('p') {
+        Some((disk, partition)) => {
+            let partition = partition.parse::<u8>().ok().filter(|&number| number > 0)?;
+            Some(Volume { device: parse_device_name(disk)?, partition })
+        }
+        None => Some(Volume::disk(parse_device_name(name)?)),
+    }
+}
+
+/// Read the partition table of device `index`. Returns the number of
+/// partitions found, 0 for a disk without a table. A USB disk's was read
+/// when it was found.
+pub fn scan(index: usize) -> DiskResult<usize> {
+    if usb_disk(index).is_some() {
+        return Ok(partition_table(index).map_or(0, |table| table.partitions.len()));
+    }
+    let device = block_device(index).ok_or(DiskError::DeviceNotFound)?;
+    let table = {
+        let mut device = device.lock();
+        if !device.is_initialized() {
+            return Err(DiskError::NotInitialized);
+        }
+        let capacity = device.get_capacity();
+        read_partition_table(&mut *device, capacity)?
+    };
+    let count = table.as_ref().map_or(0, |table| table.partitions.len());
+    TABLES.lock()[index] = table;
+    Ok(count)
+}
+
+/// Forget the partition table of device `index`
+pub fn forget(index: usize) {
+    if let Some(table) = TABLES.lock().get_mut(index) {
+        *table = None;
+    }
+}
+
+/// Partition table of device `index`, if it has one
+pub fn partition_table(index: usize) -> Option<PartitionTable> {
+    if let Some(disk) = usb_disk(index) {
+        return usb::storage::partition_table(disk);
+    }
+    TABLES.lock().get(index)?.clone()
+}
+
+/// Volumes of device `index` that may hold a filesystem: its partitions,
+/// or the whole disk if it has none
+pub fn volumes(index: usize) -> Vec<Volume, MAX_PARTITIONS> {
+    let mut volumes = Vec::new();
+    match partition_table(index) {
+        Some(table) if !table.partitions.is_empty() => {
+            for partition in &table.partitions {
+                let _ = volumes.push(Volume { device: index, partition: partition.number });
+            }
+        }
+        _ => {
+            let _ = volumes.push(Volume::disk(index));
+        }
+    }
+    volumes
+}
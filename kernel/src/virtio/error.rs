@@ -19,6 +19,8 @@ pub enum DiskError {
     IoError,
     InvalidDescriptor,
     DeviceNotReady,
+    DeviceOffline,
+    OutOfMemory,
 }
 
 impl fmt::Display for DiskError {
@@ -38,7 +40,9 @@ impl fmt::Display for DiskError {
             DiskError::QueueFull => write!(f, "VirtIO queue full"),
             DiskError::InvalidDescriptor => write!(f, "Invalid descriptor"),
             DiskError::DeviceNotReady => write!(f, "Device not ready"),
+            DiskError::DeviceOffline => write!(f, "Device offline after repeated errors"),
             DiskError::InvalidParameter => write!(f, "Invalid parameter"),
+            DiskError::OutOfMemory => write!(f, "Out of memory for the request"),
         }
     }
 }
@@ -1,13 +1,14 @@
 //! VirtIO GPU Device implementation for elinOS
 //! Provides hardware-accelerated graphics output through VirtIO GPU
 
-use elinos_common::console_println;
+use elinos_common::{ok_println, err_println, warn_println, debug_println};
 use spin::Mutex;
-use core::ptr::{read_volatile, write_volatile};
+use elinos_common::mmio::{Register, RegisterBlock};
+use elinos_common::timer;
 
 use super::{DiskResult, DiskError};
 use super::mmio::*;
-use super::queue::{VirtioQueue, VirtqDesc};
+use super::queue::{VirtioQueue, VirtqDesc, preferred_queue_size};
 
 // All VirtIO GPU constants are imported from super::mmio::*
 
@@ -131,17 +132,17 @@ impl VirtioGpu {
 
     /// Initialize VirtIO GPU device
     pub fn init(&mut self, framebuffer_addr: usize, framebuffer_size: usize) -> DiskResult<()> {
-        console_println!("[i] Searching for VirtIO GPU device...");
+        debug_println!(target: "virtio", "Searching for VirtIO GPU device...");
         
         if !self.discover_device()? {
-            console_println!("[!] No VirtIO GPU device found - using software framebuffer");
+            warn_println!(target: "virtio", "No VirtIO GPU device found - using software framebuffer");
             return Err(DiskError::DeviceNotFound);
         }
 
         self.framebuffer_addr = framebuffer_addr;
         self.framebuffer_size = framebuffer_size;
 
-        console_println!("[i] Initializing VirtIO GPU device...");
+        debug_println!(target: "virtio", "Initializing VirtIO GPU device...");
         self.init_device()?;
         self.setup_queues()?;
         self.get_display_info()?;
@@ -149,67 +150,56 @@ impl VirtioGpu {
         self.set_driver_ok()?;
 
         self.initialized = true;
-        console_println!("[o] VirtIO GPU device initialized successfully!");
+        ok_println!(target: "virtio", "VirtIO GPU device initialized successfully!");
         Ok(())
     }
 
     /// Discover VirtIO GPU device
     fn discover_device(&mut self) -> DiskResult<bool> {
-        const VIRTIO_MMIO_BASES: &[usize] = &[
-            0x10001000, // VirtIO MMIO device 0
-            0x10002000, // VirtIO MMIO device 1
-            0x10003000, // VirtIO MMIO device 2
-            0x10004000, // VirtIO MMIO device 3
-            0x10005000, // VirtIO MMIO device 4
-            0x10006000, // VirtIO MMIO device 5
-            0x10007000, // VirtIO MMIO device 6
-            0x10008000, // VirtIO MMIO device 7
-        ];
-
-        console_println!("[i] Scanning for VirtIO GPU devices...");
-        for &addr in VIRTIO_MMIO_BASES {
-            console_println!("[i] Probing MMIO address 0x{:x}...", addr);
+        debug_println!(target: "virtio", "Scanning for VirtIO GPU devices...");
+        for addr in slot_bases() {
+            debug_println!(target: "virtio", "Probing MMIO address 0x{:x}...", addr);
             if self.probe_mmio_device(addr)? {
                 self.mmio_base = addr;
-                console_println!("[o] VirtIO GPU device found at 0x{:x}", addr);
+                ok_println!(target: "virtio", "VirtIO GPU device found at 0x{:x}", addr);
                 
                 // Register the device MMIO region
-                const VIRTIO_MMIO_SIZE: usize = 0x1000; // 4KB MMIO region
                 match super::register_virtio_device(addr, VIRTIO_MMIO_SIZE, "VirtIO-GPU") {
-                    Ok(_) => console_println!("[i] VirtIO GPU device MMIO region registered"),
-                    Err(_) => console_println!("[!] Failed to register VirtIO GPU MMIO region"),
+                    Ok(_) => debug_println!(target: "virtio", "VirtIO GPU device MMIO region registered"),
+                    Err(_) => warn_println!(target: "virtio", "Failed to register VirtIO GPU MMIO region"),
                 }
                 
                 return Ok(true);
             }
         }
 
-        console_println!("[!] No VirtIO GPU device found in MMIO scan");
-        console_println!("[i] Note: VirtIO GPU PCI devices are not yet supported");
+        warn_println!(target: "virtio", "No VirtIO GPU device found in MMIO scan");
+        debug_println!(target: "virtio", "Note: VirtIO GPU PCI devices are not yet supported");
         Ok(false)
     }
 
     /// Probe MMIO device for VirtIO GPU
     fn probe_mmio_device(&mut self, base: usize) -> DiskResult<bool> {
         unsafe {
-            let magic = read_volatile((base + VIRTIO_MMIO_MAGIC_VALUE) as *const u32);
-            console_println!("[i]   Magic: 0x{:x} (expected: 0x74726976)", magic);
-            if magic != 0x74726976 {
+            let regs = RegisterBlock::new(base);
+            let magic = regs.read(VIRTIO_MMIO_MAGIC_VALUE);
+            debug_println!(target: "virtio", "Magic: 0x{:x} (expected: 0x{:x})", magic, VIRTIO_MMIO_MAGIC);
+            if magic != VIRTIO_MMIO_MAGIC {
                 return Ok(false);
             }
 
-            let version = read_volatile((base + VIRTIO_MMIO_VERSION) as *const u32);
-            let device_id = read_volatile((base + VIRTIO_MMIO_DEVICE_ID) as *const u32);
-            console_println!("[i]   Version: {}, Device ID: {} (GPU=16)", version, device_id);
+            let version = regs.read(VIRTIO_MMIO_VERSION);
+            let device_id = regs.read(VIRTIO_MMIO_DEVICE_ID);
+            debug_println!(target: "virtio", "Version: {}, Device ID: {} (GPU=16)", version, device_id);
             
             if device_id != VIRTIO_ID_GPU {
                 if device_id != 0 {
-                    console_println!("[i]   Found VirtIO device ID {} (not GPU)", device_id);
+                    debug_println!(target: "virtio", "Found VirtIO device ID {} (not GPU)", device_id);
                 }
                 return Ok(false);
             }
 
-            console_println!("[i] VirtIO GPU device detected (version: {})", version);
+            debug_println!(target: "virtio", "VirtIO GPU device detected (version: {})", version);
             Ok(true)
         }
     }
@@ -231,7 +221,7 @@ impl VirtioGpu {
             let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
             
             let device_features = ((features_hi as u64) << 32) | (features_lo as u64);
-            console_println!("[i] VirtIO GPU device features: 0x{:x}", device_features);
+            debug_println!(target: "virtio", "VirtIO GPU device features: 0x{:x}", device_features);
 
             // Set driver features (none for basic operation)
             self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
@@ -244,7 +234,7 @@ impl VirtioGpu {
             // Verify features OK
             let status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
             if (status & VIRTIO_STATUS_FEATURES_OK) == 0 {
-                console_println!("[x] VirtIO GPU features not accepted by device");
+                err_println!(target: "virtio", "VirtIO GPU features not accepted by device");
                 return Err(DiskError::VirtIOError);
             }
         }
@@ -255,142 +245,23 @@ impl VirtioGpu {
     /// Setup VirtIO GPU queues
     fn setup_queues(&mut self) -> DiskResult<()> {
         // Check if this is a Legacy VirtIO device (version 1)
-        let version = unsafe { self.read_reg_u32(VIRTIO_MMIO_VERSION) };
-        
-        if version == 1 {
-            console_println!("[i] Setting up Legacy VirtIO GPU queues...");
-            self.setup_legacy_queues()
-        } else {
-            console_println!("[i] Setting up Modern VirtIO GPU queues...");
-            self.setup_modern_queues()
-        }
-    }
+        let version = self.read_reg_u32(VIRTIO_MMIO_VERSION);
 
-    /// Setup Legacy VirtIO GPU queues (version 1)
-    fn setup_legacy_queues(&mut self) -> DiskResult<()> {
-        unsafe {
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_SEL, VIRTIO_GPU_CONTROLQ as u32);
-            
-            let max_queue_size = self.read_reg_u32(VIRTIO_MMIO_QUEUE_NUM_MAX);
-            console_println!("[i] Queue {} max size: {}", VIRTIO_GPU_CONTROLQ, max_queue_size);
-            
-            let queue_size = 64.min(max_queue_size as u16);
-            if !queue_size.is_power_of_two() {
-                return Err(DiskError::VirtIOError);
-            }
-            
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NUM, queue_size as u32);
-
-            // Set guest page size for legacy VirtIO
-            self.write_reg_u32(VIRTIO_MMIO_GUEST_PAGE_SIZE, 4096);
-            console_println!("[i] Set guest page size: 4096 bytes");
-
-            // Legacy VirtIO queue setup - calculate memory layout
-            let desc_table_size = 16 * queue_size as usize;
-            let driver_area_offset = desc_table_size;
-            let device_area_offset = ((driver_area_offset + 6 + 2 * queue_size as usize) + 4095) & !4095; // Page aligned
-            let total_size = device_area_offset + 6 + 8 * queue_size as usize;
-
-            console_println!("[i] Legacy memory layout calculation:");
-            console_println!("  Descriptor table: {} bytes", desc_table_size);
-            console_println!("  Driver area offset: {} bytes", driver_area_offset);
-            console_println!("  Device area offset: {} bytes", device_area_offset);
-            console_println!("  Total queue size: {} bytes", total_size);
-
-            // Allocate memory for queue
-            let queue_mem = super::allocate_virtio_memory(total_size)?;
-            let desc_table_addr = queue_mem;
-            let avail_ring_addr = queue_mem + driver_area_offset;
-            let used_ring_addr = queue_mem + device_area_offset;
-
-            console_println!("[i] Legacy queue memory layout:");
-            console_println!("  Descriptors: 0x{:x}", desc_table_addr);
-            console_println!("  Available:   0x{:x}", avail_ring_addr);
-            console_println!("  Used:        0x{:x}", used_ring_addr);
-
-            // Zero out the queue memory
-            core::ptr::write_bytes(queue_mem as *mut u8, 0, total_size);
-
-            // Initialize queue structure
-            self.control_queue.init(queue_size, VIRTIO_GPU_CONTROLQ, desc_table_addr, avail_ring_addr, used_ring_addr)?;
-
-            // Set queue alignment for legacy VirtIO
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_ALIGN, 4096);
-            console_println!("[i] Set queue alignment: 4096 bytes");
-
-            // Set queue PFN (Page Frame Number) for legacy VirtIO
-            let queue_pfn = desc_table_addr / 4096;
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_PFN, queue_pfn as u32);
-            console_println!("[i] Setting queue PFN: {} (addr=0x{:x})", queue_pfn, desc_table_addr);
-
-            // Verify the PFN was set correctly
-            let read_pfn = self.read_reg_u32(VIRTIO_MMIO_QUEUE_PFN);
-            console_println!("[i] Queue PFN read back: {} (expected: {})", read_pfn, queue_pfn);
-
-            self.control_queue.set_ready(true);
-        }
+        self.control_queue = VirtioQueue::setup(
+            self.regs(),
+            VIRTIO_GPU_CONTROLQ,
+            preferred_queue_size(),
+            version == 1,
+        )?;
 
-        console_println!("[o] VirtIO GPU queue {} ready", VIRTIO_GPU_CONTROLQ);
-        console_println!("[o] VirtIO GPU queues initialized");
-        Ok(())
-    }
-
-    /// Setup Modern VirtIO GPU queues (version 2+)
-    fn setup_modern_queues(&mut self) -> DiskResult<()> {
-        unsafe {
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_SEL, VIRTIO_GPU_CONTROLQ as u32);
-            
-            let max_queue_size = self.read_reg_u32(VIRTIO_MMIO_QUEUE_NUM_MAX);
-            console_println!("[i] Queue {} max size: {}", VIRTIO_GPU_CONTROLQ, max_queue_size);
-            
-            let queue_size = 64.min(max_queue_size as u16);
-            if !queue_size.is_power_of_two() {
-                return Err(DiskError::VirtIOError);
-            }
-            
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NUM, queue_size as u32);
-
-            // Modern VirtIO queue setup
-            let desc_table_size = 16 * queue_size as usize;
-            let avail_ring_size = 6 + 2 * queue_size as usize;
-            let used_ring_size = 6 + 8 * queue_size as usize;
-            let total_size = desc_table_size + avail_ring_size + used_ring_size + 64;
-
-            // Allocate memory for queue
-            let desc_table_addr = super::allocate_virtio_memory(total_size)?;
-            let avail_ring_addr = desc_table_addr + desc_table_size;
-            let used_ring_addr = (avail_ring_addr + avail_ring_size + 3) & !3; // 4-byte aligned
-
-            // Zero out the queue memory
-            core::ptr::write_bytes(desc_table_addr as *mut u8, 0, total_size);
-
-            // Initialize queue structure
-            self.control_queue.init(queue_size, VIRTIO_GPU_CONTROLQ, desc_table_addr, avail_ring_addr, used_ring_addr)?;
-
-            // Set queue addresses
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DESC_LOW, desc_table_addr as u32);
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DESC_HIGH, (desc_table_addr >> 32) as u32);
-            
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DRIVER_LOW, avail_ring_addr as u32);
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DRIVER_HIGH, (avail_ring_addr >> 32) as u32);
-            
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DEVICE_LOW, used_ring_addr as u32);
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_DEVICE_HIGH, (used_ring_addr >> 32) as u32);
-
-            // Mark queue as ready
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_READY, 1);
-            
-            self.control_queue.set_ready(true);
-        }
-
-        console_println!("[o] VirtIO GPU queue {} ready", VIRTIO_GPU_CONTROLQ);
-        console_println!("[o] VirtIO GPU queues initialized");
+        debug_println!(target: "virtio", "VirtIO GPU queue {} ready ({} entries)", VIRTIO_GPU_CONTROLQ, self.control_queue.size());
+        debug_println!(target: "virtio", "VirtIO GPU queues initialized");
         Ok(())
     }
 
     /// Get display information from VirtIO GPU
     fn get_display_info(&mut self) -> DiskResult<()> {
-        console_println!("[i] Getting VirtIO GPU display information...");
+        debug_println!(target: "virtio", "Getting VirtIO GPU display information...");
         
         // Send GET_DISPLAY_INFO command to get actual display capabilities
         let cmd = VirtioGpuCtrlHdr {
@@ -405,7 +276,7 @@ impl VirtioGpu {
         // In a full implementation, we'd parse the response
         match self.send_command(&cmd) {
             Ok(()) => {
-                console_println!("[o] VirtIO GPU display info retrieved");
+                debug_println!(target: "virtio", "VirtIO GPU display info retrieved");
                 self.display_info = Some(VirtioGpuDisplayInfo {
                     enabled: 1,
                     x: 0,
@@ -413,11 +284,11 @@ impl VirtioGpu {
                     width: 640,
                     height: 480,
                 });
-                console_println!("[o] VirtIO GPU display: 640x480");
+                ok_println!(target: "virtio", "VirtIO GPU display: 640x480");
                 Ok(())
             }
             Err(_) => {
-                console_println!("[!] Failed to get display info, using defaults");
+                warn_println!(target: "virtio", "Failed to get display info, using defaults");
                 self.display_info = Some(VirtioGpuDisplayInfo {
                     enabled: 1,
                     x: 0,
@@ -425,7 +296,7 @@ impl VirtioGpu {
                     width: 640,
                     height: 480,
                 });
-                console_println!("[o] VirtIO GPU display: 640x480 (default)");
+                ok_println!(target: "virtio", "VirtIO GPU display: 640x480 (default)");
                 Ok(())
             }
         }
@@ -433,7 +304,7 @@ impl VirtioGpu {
 
     /// Setup framebuffer with VirtIO GPU
     fn setup_framebuffer(&mut self) -> DiskResult<()> {
-        console_println!("[i] Setting up VirtIO GPU framebuffer...");
+        debug_println!(target: "virtio", "Setting up VirtIO GPU framebuffer...");
         
         // Step 1: Create 2D resource
         self.create_2d_resource()?;
@@ -444,8 +315,8 @@ impl VirtioGpu {
         // Step 3: Set scanout to connect resource to display
         self.set_scanout()?;
         
-        console_println!("[o] VirtIO GPU framebuffer setup complete");
-        console_println!("[i] Framebuffer at 0x{:x}, size: {} KB", 
+        debug_println!(target: "virtio", "VirtIO GPU framebuffer setup complete");
+        debug_println!(target: "virtio", "Framebuffer at 0x{:x}, size: {} KB", 
                         self.framebuffer_addr, self.framebuffer_size / 1024);
         Ok(())
     }
@@ -454,7 +325,7 @@ impl VirtioGpu {
     fn create_2d_resource(&mut self) -> DiskResult<()> {
         self.resource_id = 1; // Use resource ID 1
         
-        console_println!("[i] Creating VirtIO GPU 2D resource...");
+        debug_println!(target: "virtio", "Creating VirtIO GPU 2D resource...");
         let cmd = VirtioGpuResourceCreate2d {
             hdr: VirtioGpuCtrlHdr {
                 type_: VIRTIO_GPU_CMD_RESOURCE_CREATE_2D,
@@ -471,11 +342,11 @@ impl VirtioGpu {
 
         match self.send_command(&cmd) {
             Ok(()) => {
-                console_println!("[o] VirtIO GPU 2D resource created successfully (ID: {}, format: XRGB)", self.resource_id);
+                debug_println!(target: "virtio", "VirtIO GPU 2D resource created successfully (ID: {}, format: XRGB)", self.resource_id);
                 Ok(())
             }
             Err(e) => {
-                console_println!("[x] Failed to create VirtIO GPU 2D resource: {:?}", e);
+                err_println!(target: "virtio", "Failed to create VirtIO GPU 2D resource: {:?}", e);
                 Err(e)
             }
         }
@@ -483,7 +354,7 @@ impl VirtioGpu {
 
     /// Attach backing store to resource
     fn attach_backing_store(&mut self) -> DiskResult<()> {
-        console_println!("[i] Attaching backing store to VirtIO GPU resource...");
+        debug_println!(target: "virtio", "Attaching backing store to VirtIO GPU resource...");
         
         // Note: Framebuffer should already be initialized by graphics system
         // We don't zero it here to preserve the drawn content
@@ -506,20 +377,20 @@ impl VirtioGpu {
             length: aligned_size as u32,
             padding: 0,
         };
-        console_println!("[i] Memory entry: addr=0x{:x}, length={} (aligned from {})", 
+        debug_println!(target: "virtio", "Memory entry: addr=0x{:x}, length={} (aligned from {})", 
                         mem_entry.addr, mem_entry.length, self.framebuffer_size);
 
-        console_println!("[i] Backing store: addr=0x{:x}, size={} bytes", self.framebuffer_addr, self.framebuffer_size);
-        console_println!("[i] Memory region check: framebuffer at 0x{:x} (should be in RAM 0x80000000-0x88000000)", self.framebuffer_addr);
-        console_println!("[i] Alignment check: addr=0x{:x} % 4096 = {}", self.framebuffer_addr, self.framebuffer_addr % 4096);
-        console_println!("[i] Size check: calculated={}x{}x4={}, actual={}", 640, 480, 640*480*4, self.framebuffer_size);
+        debug_println!(target: "virtio", "Backing store: addr=0x{:x}, size={} bytes", self.framebuffer_addr, self.framebuffer_size);
+        debug_println!(target: "virtio", "Memory region check: framebuffer at 0x{:x} (should be in RAM 0x80000000-0x88000000)", self.framebuffer_addr);
+        debug_println!(target: "virtio", "Alignment check: addr=0x{:x} % 4096 = {}", self.framebuffer_addr, self.framebuffer_addr % 4096);
+        debug_println!(target: "virtio", "Size check: calculated={}x{}x4={}, actual={}", 640, 480, 640*480*4, self.framebuffer_size);
         match self.send_command_with_data(&cmd, &mem_entry) {
             Ok(()) => {
-                console_println!("[o] VirtIO GPU backing store attached successfully");
+                debug_println!(target: "virtio", "VirtIO GPU backing store attached successfully");
                 Ok(())
             }
             Err(e) => {
-                console_println!("[x] Failed to attach VirtIO GPU backing store: {:?}", e);
+                err_println!(target: "virtio", "Failed to attach VirtIO GPU backing store: {:?}", e);
                 Err(e)
             }
         }
@@ -527,7 +398,7 @@ impl VirtioGpu {
 
     /// Set scanout to connect resource to display
     fn set_scanout(&mut self) -> DiskResult<()> {
-        console_println!("[i] Setting VirtIO GPU scanout...");
+        debug_println!(target: "virtio", "Setting VirtIO GPU scanout...");
         let cmd = VirtioGpuSetScanout {
             hdr: VirtioGpuCtrlHdr {
                 type_: VIRTIO_GPU_CMD_SET_SCANOUT,
@@ -548,11 +419,11 @@ impl VirtioGpu {
 
         match self.send_command(&cmd) {
             Ok(()) => {
-                console_println!("[o] VirtIO GPU scanout configured successfully");
+                debug_println!(target: "virtio", "VirtIO GPU scanout configured successfully");
                 Ok(())
             }
             Err(e) => {
-                console_println!("[x] Failed to configure VirtIO GPU scanout: {:?}", e);
+                err_println!(target: "virtio", "Failed to configure VirtIO GPU scanout: {:?}", e);
                 Err(e)
             }
         }
@@ -583,31 +454,24 @@ impl VirtioGpu {
             ];
 
             let head_index = self.control_queue.add_descriptor_chain(&desc_chain)?;
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_GPU_CONTROLQ as u32);
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_GPU_CONTROLQ as u32);
             
             // Wait for completion
-            let mut timeout = 1000000;
-            while timeout > 0 {
-                if let Some(_) = self.control_queue.wait_for_completion(head_index) {
-                    // Check response status (first 4 bytes should be response type)
-                    let response_type = u32::from_le_bytes([
-                        response_buffer[0], response_buffer[1], 
-                        response_buffer[2], response_buffer[3]
-                    ]);
-                    
-                    if response_type == VIRTIO_GPU_RESP_OK_NODATA || 
-                       response_type == VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
-                        return Ok(());
-                    } else {
-                        console_println!("[!] VirtIO GPU command failed, response: 0x{:x}", response_type);
-                        return Err(DiskError::VirtIOError);
-                    }
-                }
-                timeout -= 1;
-                core::hint::spin_loop();
-            }
+            self.control_queue.wait_for_used(head_index).ok_or(DiskError::IoError)?;
+
+            // Check response status (first 4 bytes should be response type)
+            let response_type = u32::from_le_bytes([
+                response_buffer[0], response_buffer[1], 
+                response_buffer[2], response_buffer[3]
+            ]);
             
-            Err(DiskError::IoError)
+            if response_type == VIRTIO_GPU_RESP_OK_NODATA || 
+               response_type == VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
+                Ok(())
+            } else {
+                warn_println!(target: "virtio", "VirtIO GPU command failed, response: 0x{:x}", response_type);
+                Err(DiskError::VirtIOError)
+            }
         }
     }
 
@@ -644,40 +508,33 @@ impl VirtioGpu {
             ];
 
             let head_index = self.control_queue.add_descriptor_chain(&desc_chain)?;
-            self.write_reg_u32(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_GPU_CONTROLQ as u32);
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_GPU_CONTROLQ as u32);
             
             // Wait for completion
-            let mut timeout = 1000000;
-            while timeout > 0 {
-                if let Some(_) = self.control_queue.wait_for_completion(head_index) {
-                    // Check response status
-                    let response_type = u32::from_le_bytes([
-                        response_buffer[0], response_buffer[1], 
-                        response_buffer[2], response_buffer[3]
-                    ]);
-                    
-                    if response_type == VIRTIO_GPU_RESP_OK_NODATA || 
-                       response_type == VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
-                        return Ok(());
-                    } else {
-                        let error_msg = match response_type {
-                            VIRTIO_GPU_RESP_ERR_UNSPEC => "Unspecified error",
-                            VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY => "Out of memory",
-                            VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID => "Invalid scanout ID",
-                            VIRTIO_GPU_RESP_ERR_INVALID_RESOURCE_ID => "Invalid resource ID",
-                            VIRTIO_GPU_RESP_ERR_INVALID_CONTEXT_ID => "Invalid context ID",
-                            VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER => "Invalid parameter",
-                            _ => "Unknown error",
-                        };
-                        console_println!("[!] VirtIO GPU command with data failed: {} (0x{:x})", error_msg, response_type);
-                        return Err(DiskError::VirtIOError);
-                    }
-                }
-                timeout -= 1;
-                core::hint::spin_loop();
-            }
+            self.control_queue.wait_for_used(head_index).ok_or(DiskError::IoError)?;
+
+            // Check response status
+            let response_type = u32::from_le_bytes([
+                response_buffer[0], response_buffer[1], 
+                response_buffer[2], response_buffer[3]
+            ]);
             
-            Err(DiskError::IoError)
+            if response_type == VIRTIO_GPU_RESP_OK_NODATA || 
+               response_type == VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
+                Ok(())
+            } else {
+                let error_msg = match response_type {
+                    VIRTIO_GPU_RESP_ERR_UNSPEC => "Unspecified error",
+                    VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY => "Out of memory",
+                    VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID => "Invalid scanout ID",
+                    VIRTIO_GPU_RESP_ERR_INVALID_RESOURCE_ID => "Invalid resource ID",
+                    VIRTIO_GPU_RESP_ERR_INVALID_CONTEXT_ID => "Invalid context ID",
+                    VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER => "Invalid parameter",
+                    _ => "Unknown error",
+                };
+                warn_println!(target: "virtio", "VirtIO GPU command with data failed: {} (0x{:x})", error_msg, response_type);
+                Err(DiskError::VirtIOError)
+            }
         }
     }
 
@@ -689,32 +546,26 @@ impl VirtioGpu {
             VIRTIO_STATUS_FEATURES_OK as u32 | 
             VIRTIO_STATUS_DRIVER_OK as u32);
         
-        console_println!("[o] VirtIO GPU driver ready");
+        ok_println!(target: "virtio", "VirtIO GPU driver ready");
         Ok(())
     }
 
-    /// Flush framebuffer to display
-    pub fn flush_framebuffer(&mut self) -> DiskResult<()> {
+    /// Flush the framebuffer pixels in `rect` to the display
+    pub fn flush_rect(&mut self, rect: VirtioGpuRect) -> DiskResult<()> {
         if !self.initialized {
-            console_println!("[!] VirtIO GPU not initialized, cannot flush");
+            warn_println!(target: "virtio", "VirtIO GPU not initialized, cannot flush");
             return Err(DiskError::NotInitialized);
         }
 
-        console_println!("[i] Starting VirtIO GPU framebuffer flush...");
-
         // Step 1: Transfer framebuffer data to host
-        self.transfer_to_host()?;
+        self.transfer_to_host(rect)?;
         
         // Step 2: Flush the resource to make it visible
-        self.flush_resource()?;
-        
-        console_println!("[o] VirtIO GPU framebuffer flush completed successfully");
-        Ok(())
+        self.flush_resource(rect)
     }
 
-    /// Transfer framebuffer data to host
-    fn transfer_to_host(&mut self) -> DiskResult<()> {
-        console_println!("[i] Transferring framebuffer data to VirtIO GPU host...");
+    /// Transfer the framebuffer data in `rect` to host
+    fn transfer_to_host(&mut self, rect: VirtioGpuRect) -> DiskResult<()> {
         let cmd = VirtioGpuTransferToHost2d {
             hdr: VirtioGpuCtrlHdr {
                 type_: VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D,
@@ -723,32 +574,21 @@ impl VirtioGpu {
                 ctx_id: 0,
                 padding: 0,
             },
-            r: VirtioGpuRect {
-                x: 0,
-                y: 0,
-                width: 640,
-                height: 480,
-            },
-            offset: 0,
+            r: rect,
+            // Where the rectangle starts in the backing store
+            offset: (rect.y as u64 * 640 + rect.x as u64) * 4,
             resource_id: self.resource_id,
             padding: 0,
         };
 
-        match self.send_command(&cmd) {
-            Ok(()) => {
-                console_println!("[o] VirtIO GPU transfer to host completed successfully");
-                Ok(())
-            }
-            Err(e) => {
-                console_println!("[x] VirtIO GPU transfer to host failed: {:?}", e);
-                Err(e)
-            }
-        }
+        self.send_command(&cmd).map_err(|e| {
+            err_println!(target: "virtio", "VirtIO GPU transfer to host failed: {:?}", e);
+            e
+        })
     }
 
-    /// Flush resource to display
-    fn flush_resource(&mut self) -> DiskResult<()> {
-        console_println!("[i] Flushing VirtIO GPU resource to display...");
+    /// Flush `rect` of the resource to display
+    fn flush_resource(&mut self, rect: VirtioGpuRect) -> DiskResult<()> {
         let cmd = VirtioGpuResourceFlush {
             hdr: VirtioGpuCtrlHdr {
                 type_: VIRTIO_GPU_CMD_RESOURCE_FLUSH,
@@ -757,36 +597,30 @@ impl VirtioGpu {
                 ctx_id: 0,
                 padding: 0,
             },
-            r: VirtioGpuRect {
-                x: 0,
-                y: 0,
-                width: 640,
-                height: 480,
-            },
+            r: rect,
             resource_id: self.resource_id,
             padding: 0,
         };
 
-        match self.send_command(&cmd) {
-            Ok(()) => {
-                console_println!("[o] VirtIO GPU resource flush completed successfully");
-                Ok(())
-            }
-            Err(e) => {
-                console_println!("[x] VirtIO GPU resource flush failed: {:?}", e);
-                Err(e)
-            }
-        }
+        self.send_command(&cmd).map_err(|e| {
+            err_println!(target: "virtio", "VirtIO GPU resource flush failed: {:?}", e);
+            e
+        })
+    }
+
+    /// This device's register window
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(self.mmio_base) }
     }
 
     /// Read 32-bit register
-    fn read_reg_u32(&self, offset: usize) -> u32 {
-        unsafe { read_volatile((self.mmio_base + offset) as *const u32) }
+    fn read_reg_u32(&self, reg: Register<u32>) -> u32 {
+        self.regs().read(reg)
     }
 
     /// Write 32-bit register
-    fn write_reg_u32(&self, offset: usize, value: u32) {
-        unsafe { write_volatile((self.mmio_base + offset) as *mut u32, value) }
+    fn write_reg_u32(&self, reg: Register<u32>, value: u32) {
+        self.regs().write(reg, value)
     }
 
     /// Set device status
@@ -798,6 +632,11 @@ impl VirtioGpu {
     pub fn is_initialized(&self) -> bool {
         self.initialized
     }
+
+    /// MMIO base of the device in use, if initialized
+    pub fn mmio_base(&self) -> Option<usize> {
+        if self.initialized { Some(self.mmio_base) } else { None }
+    }
 }
 
 // Global VirtIO GPU device
@@ -809,8 +648,69 @@ pub fn init_virtio_gpu(framebuffer_addr: usize, framebuffer_size: usize) -> Disk
     gpu.init(framebuffer_addr, framebuffer_size)
 }
 
-/// Flush framebuffer to display
+/// Timing of the frames flushed to the display since boot
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub frames: u64,
+    /// Pixels sent by the frames; only what changed is sent
+    pub pixels: u64,
+    /// Flushes the device failed or did not finish
+    pub failed: u64,
+    /// Time spent in flushes, failed ones included
+    pub total_ns: u64,
+    pub last_ns: u64,
+    pub min_ns: u64,
+    pub max_ns: u64,
+}
+
+impl FrameStats {
+    fn record(&mut self, ns: u64, pixels: Option<u64>) {
+        if let Some(pixels) = pixels {
+            self.frames += 1;
+            self.pixels += pixels;
+        } else {
+            self.failed += 1;
+        }
+        self.min_ns = if self.frames + self.failed == 1 { ns } else { self.min_ns.min(ns) };
+        self.max_ns = self.max_ns.max(ns);
+        self.last_ns = ns;
+        self.total_ns += ns;
+    }
+
+    /// Mean time of a flush
+    pub fn average_ns(&self) -> u64 {
+        self.total_ns / (self.frames + self.failed).max(1)
+    }
+}
+
+static FRAME_STATS: Mutex<FrameStats> = Mutex::new(FrameStats {
+    frames: 0, pixels: 0, failed: 0, total_ns: 0, last_ns: 0, min_ns: 0, max_ns: 0,
+});
+
+/// Flush the part of the framebuffer drawn since the last flush to the
+/// display, with the pointer cursor over it, timing it as a frame. Does
+/// nothing if nothing was drawn.
 pub fn flush_display() -> DiskResult<()> {
-    let mut gpu = VIRTIO_GPU.lock();
-    gpu.flush_framebuffer()
-} 
\ No newline at end of file
+    let start = timer::ticks();
+    let result = crate::graphics::cursor::with_cursor(|| {
+        let Some(rect) = crate::graphics::take_damage() else { return Ok(None) };
+        let r = VirtioGpuRect { x: rect.x, y: rect.y, width: rect.width, height: rect.height };
+        VIRTIO_GPU.lock().flush_rect(r).map(|()| Some(rect.pixels()))
+    });
+    match result {
+        Ok(None) => Ok(()),
+        Ok(Some(pixels)) => {
+            FRAME_STATS.lock().record(timer::ticks_to_ns(timer::ticks() - start), Some(pixels));
+            Ok(())
+        }
+        Err(e) => {
+            FRAME_STATS.lock().record(timer::ticks_to_ns(timer::ticks() - start), None);
+            Err(e)
+        }
+    }
+}
+
+/// Frame timing counters since boot
+pub fn frame_stats() -> FrameStats {
+    *FRAME_STATS.lock()
+}
@@ -0,0 +1,220 @@
+//! VirtIO driver registry and device hotplug
+//!
+//! Every MMIO transport slot is tracked with the device it last held.
+//! `scan` re-reads the slots: a device that appeared is handed to the
+//! registered driver for its device ID, one that vanished is detached, and
+//! either way an event is queued for whoever wants to react (e.g. mounting
+//! a newly attached disk). The shell scans while it waits for input and on
+//! `rescan`.
+
+use elinos_common::{console_println, ok_println, warn_println, info_println};
+use elinos_common::platform::MAX_VIRTIO_SLOTS;
+use heapless::{Deque, Vec};
+use spin::Mutex;
+
+use super::{DiskResult, DiskError};
+use super::mmio::*;
+use super::{block, VIRTIO_GPU, VIRTIO_INPUT, VIRTIO_NET, VIRTIO_SOUND};
+
+/// A driver that can take devices of one VirtIO device ID
+pub struct VirtioDriver {
+    pub name: &'static str,
+    pub device_id: u32,
+    /// Bring up the device at this MMIO base. Fails if the driver has no
+    /// room for another device.
+    pub probe: fn(usize) -> DiskResult<()>,
+    /// Whether the driver is using the device at this MMIO base
+    pub owns: fn(usize) -> bool,
+    /// Forget the device at this MMIO base after it disappeared
+    pub detach: fn(usize),
+}
+
+/// Registered drivers
+pub static DRIVERS: &[VirtioDriver] = &[
+    VirtioDriver {
+        name: "virtio-blk",
+        device_id: VIRTIO_ID_BLOCK,
+        probe: |base| block::init_with_address(base).map(|_| ()),
+        owns: |base| block::find_by_base(base).is_some(),
+        detach: block::detach,
+    },
+    VirtioDriver {
+        name: "virtio-net",
+        device_id: VIRTIO_ID_NET,
+        probe: probe_net,
+        owns: |base| VIRTIO_NET.lock().mmio_base() == Some(base),
+        detach: |_| *VIRTIO_NET.lock() = super::net::VirtioNet::new(),
+    },
+    VirtioDriver {
+        name: "virtio-input",
+        device_id: VIRTIO_ID_INPUT,
+        probe: super::input::init_with_address,
+        owns: |base| VIRTIO_INPUT.lock().mmio_base() == Some(base),
+        detach: |_| *VIRTIO_INPUT.lock() = super::input::VirtioInput::new(),
+    },
+    VirtioDriver {
+        name: "virtio-sound",
+        device_id: VIRTIO_ID_SOUND,
+        probe: super::sound::init_with_address,
+        owns: |base| VIRTIO_SOUND.lock().mmio_base() == Some(base),
+        detach: |_| *VIRTIO_SOUND.lock() = super::sound::VirtioSound::new(),
+    },
+    VirtioDriver {
+        name: "virtio-gpu",
+        device_id: VIRTIO_ID_GPU,
+        // The framebuffer is set up once at boot
+        probe: |_| Err(DiskError::DeviceNotReady),
+        owns: |base| VIRTIO_GPU.lock().mmio_base() == Some(base),
+        detach: |_| {},
+    },
+];
+
+fn probe_net(base: usize) -> DiskResult<()> {
+    if VIRTIO_NET.lock().mmio_base().is_some() {
+        return Err(DiskError::DeviceNotReady);
+    }
+    super::net::init_with_address(base)?;
+    if let Err(e) = crate::net::init() {
+        warn_println!(target: "virtio", "Network stack not started: {}", e);
+    }
+    Ok(())
+}
+
+fn driver_for(device_id: u32) -> Option<&'static VirtioDriver> {
+    DRIVERS.iter().find(|driver| driver.device_id == device_id)
+}
+
+/// Something that changed on the MMIO bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugEvent {
+    /// A device appeared, or a driver took a device it could not before
+    Added { base: usize, device_id: u32, driver: Option<&'static str> },
+    /// A device disappeared
+    Removed { base: usize, device_id: u32, driver: Option<&'static str> },
+}
+
+/// Events not yet taken; the oldest are dropped when full
+const MAX_EVENTS: usize = 16;
+
+/// What one transport slot held at the last scan
+#[derive(Clone, Copy)]
+struct Slot {
+    device_id: u32,
+    driver: Option<&'static str>,
+}
+
+struct Hotplug {
+    initialized: bool,
+    /// By slot, the first `platform::virtio_slots().len()` used
+    slots: [Slot; MAX_VIRTIO_SLOTS],
+    events: Deque<HotplugEvent, MAX_EVENTS>,
+}
+
+static HOTPLUG: Mutex<Hotplug> = Mutex::new(Hotplug {
+    initialized: false,
+    slots: [Slot { device_id: 0, driver: None }; MAX_VIRTIO_SLOTS],
+    events: Deque::new(),
+});
+
+/// Device ID in the slot at `base`, 0 when the slot is empty
+fn read_device_id(base: usize) -> u32 {
+    probe_slot(base).map_or(0, |slot| slot.device_id)
+}
+
+/// Driver currently using the device at `base`
+fn bound_driver(base: usize) -> Option<&'static str> {
+    DRIVERS.iter().find(|driver| (driver.owns)(base)).map(|driver| driver.name)
+}
+
+/// Record what the boot-time drivers found, without queueing events
+pub fn init() {
+    let mut hotplug = HOTPLUG.lock();
+    for (slot, base) in hotplug.slots.iter_mut().zip(slot_bases()) {
+        *slot = Slot { device_id: read_device_id(base), driver: bound_driver(base) };
+    }
+    hotplug.initialized = true;
+}
+
+fn push_event(hotplug: &mut Hotplug, event: HotplugEvent) {
+    if hotplug.events.is_full() {
+        hotplug.events.pop_front();
+    }
+    let _ = hotplug.events.push_back(event);
+}
+
+/// Re-read every slot, probing new devices and detaching vanished ones.
+/// Returns the number of events queued.
+pub fn scan() -> usize {
+    let mut hotplug = HOTPLUG.lock();
+    if !hotplug.initialized {
+        return 0;
+    }
+
+    let mut queued = 0;
+    for (index, base) in slot_bases().enumerate() {
+        let device_id = read_device_id(base);
+        let slot = hotplug.slots[index];
+
+        if slot.device_id != 0 && slot.device_id != device_id {
+            if slot.driver.is_some() {
+                if let Some(driver) = driver_for(slot.device_id) {
+                    (driver.detach)(base);
+                }
+            }
+            warn_println!(target: "virtio", "VirtIO device {} at 0x{:x} removed", slot.device_id, base);
+            push_event(&mut hotplug, HotplugEvent::Removed { base, device_id: slot.device_id, driver: slot.driver });
+            hotplug.slots[index] = Slot { device_id: 0, driver: None };
+            queued += 1;
+        }
+
+        if device_id == 0 {
+            continue;
+        }
+        let appeared = hotplug.slots[index].device_id != device_id;
+        if !appeared && hotplug.slots[index].driver.is_some() {
+            continue;
+        }
+
+        // New devices, and known ones whose driver may have become free
+        let mut driver = None;
+        if let Some(candidate) = driver_for(device_id) {
+            if (candidate.probe)(base).is_ok() {
+                driver = Some(candidate.name);
+            }
+        }
+        hotplug.slots[index] = Slot { device_id, driver };
+
+        if appeared || driver.is_some() {
+            match driver {
+                Some(name) => ok_println!(target: "virtio", "VirtIO device {} at 0x{:x} attached to {}", device_id, base, name),
+                None => info_println!(target: "virtio", "VirtIO device {} at 0x{:x} found, no driver available", device_id, base),
+            }
+            push_event(&mut hotplug, HotplugEvent::Added { base, device_id, driver });
+            queued += 1;
+        }
+    }
+    queued
+}
+
+/// Take the oldest event not yet handled
+pub fn take_event() -> Option<HotplugEvent> {
+    HOTPLUG.lock().events.pop_front()
+}
+
+/// Print every occupied slot and its driver
+pub fn show() {
+    console_println!("VirtIO MMIO slots:");
+    for (base, device_id, driver) in occupied_slots() {
+        console_println!("  0x{:x}: device {:<3} {}", base, device_id, driver.unwrap_or("(no driver)"));
+    }
+}
+
+/// The slots holding a device at the last scan: base, device ID and the
+/// driver that took it
+pub fn occupied_slots() -> Vec<(usize, u32, Option<&'static str>), MAX_VIRTIO_SLOTS> {
+    let hotplug = HOTPLUG.lock();
+    hotplug.slots.iter().zip(slot_bases())
+        .filter(|(slot, _)| slot.device_id != 0)
+        .map(|(slot, base)| (base, slot.device_id, slot.driver))
+        .collect()
+}
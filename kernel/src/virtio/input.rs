@@ -0,0 +1,280 @@
+//! VirtIO input device implementation for elinOS
+//! Takes the evdev-style events of a mouse, tablet or keyboard off the
+//! event queue; what they mean is worked out in `crate::input`
+
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println};
+use heapless::String;
+use spin::Mutex;
+use elinos_common::mmio::{Register, RegisterBlock};
+
+use super::{DiskResult, DiskError};
+use super::mmio::*;
+use super::queue::{VirtioQueue, VirtqDesc};
+
+// Queue indices
+pub const VIRTIO_INPUT_EVENT_QUEUE: u16 = 0;
+
+/// Number of event buffers (one descriptor each)
+const INPUT_QUEUE_SIZE: u16 = 64;
+
+// Config space: select and subsel pick what the rest shows
+const VIRTIO_INPUT_CFG_SELECT: usize = 0;
+const VIRTIO_INPUT_CFG_SUBSEL: usize = 1;
+const VIRTIO_INPUT_CFG_SIZE: usize = 2;
+const VIRTIO_INPUT_CFG_DATA: usize = 8;
+
+const VIRTIO_INPUT_CFG_ID_NAME: u8 = 0x01;
+const VIRTIO_INPUT_CFG_ABS_INFO: u8 = 0x12;
+
+/// One event as the device posts it, the tail of Linux's `struct input_event`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputEvent {
+    pub type_: u16,
+    pub code: u16,
+    pub value: u32,
+}
+
+/// Range of an absolute axis
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AbsRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+/// VirtIO input device
+pub struct VirtioInput {
+    initialized: bool,
+    mmio_base: usize,
+    version: u32,
+    name: String<32>,
+    /// Ranges of ABS_X and ABS_Y, for devices that report them
+    abs: [Option<AbsRange>; 2],
+    queue: VirtioQueue,
+    buffers: usize,
+    /// Event buffer of each descriptor handed to the device
+    buffer_of: [u8; INPUT_QUEUE_SIZE as usize],
+    events: u64,
+}
+
+impl VirtioInput {
+    pub const fn new() -> Self {
+        VirtioInput {
+            initialized: false,
+            mmio_base: 0,
+            version: 0,
+            name: String::new(),
+            abs: [None; 2],
+            queue: VirtioQueue::new(),
+            buffers: 0,
+            buffer_of: [0; INPUT_QUEUE_SIZE as usize],
+            events: 0,
+        }
+    }
+
+    /// Initialize the first VirtIO input device
+    pub fn init(&mut self) -> DiskResult<()> {
+        let base = slot_bases().find(|&base| probe_mmio_device(base))
+            .ok_or(DiskError::DeviceNotFound)?;
+        self.mmio_base = base;
+        if super::register_virtio_device(base, VIRTIO_MMIO_SIZE, "VirtIO-Input").is_err() {
+            warn_println!(target: "virtio", "Failed to register VirtIO input MMIO region");
+        }
+        self.bring_up()
+    }
+
+    /// Initialize the device at `mmio_base`, which must be an input device
+    fn bring_up(&mut self) -> DiskResult<()> {
+        self.init_device()?;
+        self.read_config();
+        self.setup_queue()?;
+        self.set_status(VIRTIO_STATUS_DRIVER_OK as u8);
+        self.fill_queue()?;
+
+        self.initialized = true;
+        ok_println!(target: "virtio", "VirtIO input device ready: {}", self.name);
+        Ok(())
+    }
+
+    /// Reset the device and negotiate features; it has none we need
+    fn init_device(&mut self) -> DiskResult<()> {
+        self.version = self.read_reg_u32(VIRTIO_MMIO_VERSION);
+
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, 0);
+        self.set_status(VIRTIO_STATUS_ACKNOWLEDGE as u8);
+        self.set_status(VIRTIO_STATUS_DRIVER as u8);
+
+        self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+        let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
+        let device_features = (features_hi as u64) << 32;
+        let driver_features = if self.version != 1 { device_features & VIRTIO_F_VERSION_1 } else { 0 };
+
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, driver_features as u32);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, (driver_features >> 32) as u32);
+
+        self.set_status(VIRTIO_STATUS_FEATURES_OK as u8);
+        if self.read_reg_u32(VIRTIO_MMIO_STATUS) & VIRTIO_STATUS_FEATURES_OK == 0 {
+            err_println!(target: "virtio", "VirtIO input features not accepted by device");
+            return Err(DiskError::VirtIOError);
+        }
+        Ok(())
+    }
+
+    /// Read the device name and the ranges of its absolute axes
+    fn read_config(&mut self) {
+        let size = self.select_config(VIRTIO_INPUT_CFG_ID_NAME, 0);
+        self.name.clear();
+        for i in 0..size.min(self.name.capacity()) {
+            let byte: u8 = self.regs().read(config_register(VIRTIO_INPUT_CFG_DATA + i));
+            let _ = self.name.push(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '?' });
+        }
+
+        for axis in 0..self.abs.len() {
+            let size = self.select_config(VIRTIO_INPUT_CFG_ABS_INFO, axis as u8);
+            self.abs[axis] = (size > 0).then(|| AbsRange {
+                min: self.regs().read(config_register(VIRTIO_INPUT_CFG_DATA)),
+                max: self.regs().read(config_register(VIRTIO_INPUT_CFG_DATA + 4)),
+            });
+        }
+    }
+
+    /// Show config `select`/`subsel`; returns the size of what it holds
+    fn select_config(&self, select: u8, subsel: u8) -> usize {
+        self.regs().write(config_register::<u8>(VIRTIO_INPUT_CFG_SELECT), select);
+        self.regs().write(config_register::<u8>(VIRTIO_INPUT_CFG_SUBSEL), subsel);
+        self.regs().read(config_register::<u8>(VIRTIO_INPUT_CFG_SIZE)) as usize
+    }
+
+    /// Set up the event queue and its buffers
+    fn setup_queue(&mut self) -> DiskResult<()> {
+        self.queue = VirtioQueue::setup(self.regs(), VIRTIO_INPUT_EVENT_QUEUE, INPUT_QUEUE_SIZE, self.version == 1)?;
+        let buffers_size = INPUT_QUEUE_SIZE as usize * core::mem::size_of::<InputEvent>();
+        self.buffers = super::allocate_virtio_memory(buffers_size)?;
+        unsafe {
+            core::ptr::write_bytes(self.buffers as *mut u8, 0, buffers_size);
+        }
+        Ok(())
+    }
+
+    /// Give every event buffer the queue has room for to the device
+    fn fill_queue(&mut self) -> DiskResult<()> {
+        for buffer in 0..self.queue.size().min(INPUT_QUEUE_SIZE) {
+            self.post_buffer(buffer as u8)?;
+        }
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_INPUT_EVENT_QUEUE as u32);
+        Ok(())
+    }
+
+    fn post_buffer(&mut self, buffer: u8) -> DiskResult<()> {
+        let desc = [VirtqDesc {
+            addr: self.buffer_addr(buffer) as u64,
+            len: core::mem::size_of::<InputEvent>() as u32,
+            flags: VIRTQ_DESC_F_WRITE,
+            next: 0,
+        }];
+        let head = self.queue.add_descriptor_chain(&desc)?;
+        self.buffer_of[head as usize % INPUT_QUEUE_SIZE as usize] = buffer;
+        Ok(())
+    }
+
+    fn buffer_addr(&self, buffer: u8) -> usize {
+        self.buffers + buffer as usize * core::mem::size_of::<InputEvent>()
+    }
+
+    /// Take the next event the device posted, if any, and hand its buffer
+    /// back
+    pub fn next_event(&mut self) -> Option<InputEvent> {
+        if !self.initialized {
+            return None;
+        }
+        let elem = self.queue.get_used_elem()?;
+        let buffer = self.buffer_of[elem.id as usize % INPUT_QUEUE_SIZE as usize];
+        let event = unsafe { core::ptr::read_volatile(self.buffer_addr(buffer) as *const InputEvent) };
+        self.events += 1;
+
+        if self.post_buffer(buffer).is_ok() {
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_INPUT_EVENT_QUEUE as u32);
+        }
+        Some(event)
+    }
+
+    /// Range of absolute axis `axis` (0 for X, 1 for Y), if the device has it
+    pub fn abs_range(&self, axis: usize) -> Option<AbsRange> {
+        self.abs.get(axis).copied().flatten()
+    }
+
+    /// This device's register window
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(self.mmio_base) }
+    }
+
+    /// Read 32-bit register
+    fn read_reg_u32(&self, reg: Register<u32>) -> u32 {
+        self.regs().read(reg)
+    }
+
+    /// Write 32-bit register
+    fn write_reg_u32(&self, reg: Register<u32>, value: u32) {
+        self.regs().write(reg, value)
+    }
+
+    /// Set device status
+    fn set_status(&self, status: u8) {
+        let current_status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, current_status | (status as u32));
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// MMIO base of the device in use, if initialized
+    pub fn mmio_base(&self) -> Option<usize> {
+        if self.initialized { Some(self.mmio_base) } else { None }
+    }
+
+    /// Print device information
+    pub fn show_info(&self) {
+        console_println!("  Name: {}", self.name);
+        console_println!("  MMIO base: 0x{:x} (version {})", self.mmio_base, self.version);
+        console_println!("  Events: {}", self.events);
+    }
+}
+
+/// Probe MMIO device for VirtIO input
+fn probe_mmio_device(base: usize) -> bool {
+    let regs = unsafe { RegisterBlock::new(base) };
+    regs.read(VIRTIO_MMIO_MAGIC_VALUE) == VIRTIO_MMIO_MAGIC && regs.read(VIRTIO_MMIO_DEVICE_ID) == VIRTIO_ID_INPUT
+}
+
+// Global VirtIO input device
+pub static VIRTIO_INPUT: Mutex<VirtioInput> = Mutex::new(VirtioInput::new());
+
+/// Initialize the VirtIO input device
+pub fn init_virtio_input() -> DiskResult<()> {
+    let result = VIRTIO_INPUT.lock().init();
+    if result.is_err() {
+        info_println!(target: "virtio", "No VirtIO input device");
+    }
+    result
+}
+
+/// Initialize the input device at `base_addr`, e.g. one attached after boot
+pub fn init_with_address(base_addr: usize) -> DiskResult<()> {
+    if VIRTIO_INPUT.lock().is_initialized() {
+        return Err(DiskError::DeviceNotReady);
+    }
+    if !probe_mmio_device(base_addr) {
+        return Err(DiskError::DeviceNotFound);
+    }
+    let mut device = VirtioInput::new();
+    device.mmio_base = base_addr;
+    if super::register_virtio_device(base_addr, VIRTIO_MMIO_SIZE, "VirtIO-Input").is_err() {
+        warn_println!(target: "virtio", "Failed to register VirtIO input MMIO region");
+    }
+    device.bring_up()?;
+    *VIRTIO_INPUT.lock() = device;
+    Ok(())
+}
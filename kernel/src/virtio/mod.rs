@@ -8,16 +8,24 @@ pub use error::{DiskError, DiskResult};
 pub use queue::{VirtqDesc, VirtqAvail, VirtqUsed, VirtqUsedElem, VirtioQueue};
 
 // Re-export from sub-modules
-pub use block::{RustVmmVirtIOBlock, VirtioBlkReq, VIRTIO_BLK};
-pub use block::{init_virtio_blk, init_with_address};
-pub use gpu::{VIRTIO_GPU, init_virtio_gpu, flush_display};
+pub use block::{RustVmmVirtIOBlock, VirtioBlkReq, VIRTIO_BLK_DEVICES, MAX_BLOCK_DEVICES};
+pub use block::{block_device, parse_device_name, init_virtio_blk};
+pub use block::{Volume, parse_volume_name};
+pub use gpu::{VIRTIO_GPU, init_virtio_gpu, flush_display, frame_stats, FrameStats};
+pub use net::{VIRTIO_NET, init_virtio_net};
+pub use input::{VIRTIO_INPUT, init_virtio_input};
+pub use sound::{VIRTIO_SOUND, init_virtio_sound};
 
 // Modules
 pub mod error;
-pub mod mmio;
+pub use elinos_common::virtio::mmio;
 pub mod queue;
 pub mod block;
 pub mod gpu;
+pub mod net;
+pub mod input;
+pub mod sound;
+pub mod hotplug;
 
 use spin::Mutex;
 
@@ -40,6 +48,7 @@ impl VirtioMemoryManager {
     }
     
     /// Allocate DMA-safe memory for VirtIO queue operations
+    #[track_caller]
     pub fn allocate_queue_memory(&self, size: usize) -> Result<usize, DiskError> {
         if !self.initialized {
             return Err(DiskError::NotInitialized);
@@ -67,6 +76,7 @@ pub fn init_virtio_memory() -> Result<(), DiskError> {
 }
 
 /// Allocate memory for VirtIO operations
+#[track_caller]
 pub fn allocate_virtio_memory(size: usize) -> Result<usize, DiskError> {
     let memory_mgr = VIRTIO_MEMORY.lock();
     memory_mgr.allocate_queue_memory(size)
@@ -85,4 +95,20 @@ pub fn unregister_virtio_device(base_addr: usize, size: usize) -> Result<(), Dis
     // TODO: Implement device memory unmapping
     // For now, just return success as devices typically aren't unregistered
     Ok(())
-} 
\ No newline at end of file
+} 
+/// PLIC line of each VirtIO MMIO slot, in slot order
+pub fn irq_lines() -> impl Iterator<Item = u32> {
+    elinos_common::platform::virtio_slots().iter().map(|slot| slot.irq)
+}
+
+/// Acknowledge the interrupt of the VirtIO slot on line `irq`, so the line
+/// drops. The driver waiting on the request reads the used ring itself;
+/// it holds the device lock, so none is taken here.
+pub fn handle_interrupt(irq: u32) {
+    let Some(slot) = elinos_common::platform::virtio_slots().iter().find(|slot| slot.irq == irq) else {
+        return;
+    };
+    let regs = unsafe { elinos_common::mmio::RegisterBlock::new(slot.base) };
+    let status = regs.read(mmio::VIRTIO_MMIO_INTERRUPT_STATUS);
+    regs.write(mmio::VIRTIO_MMIO_INTERRUPT_ACK, status);
+}
@@ -0,0 +1,365 @@
+//! VirtIO network device implementation for elinOS
+//! Sends and receives raw Ethernet frames; the protocol stack lives in `crate::net`
+
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println, debug_println};
+use spin::Mutex;
+use elinos_common::mmio::{Register, RegisterBlock};
+
+use super::{DiskResult, DiskError};
+use super::mmio::*;
+use super::queue::{VirtioQueue, VirtqDesc};
+
+// Queue indices
+pub const VIRTIO_NET_RX_QUEUE: u16 = 0;
+pub const VIRTIO_NET_TX_QUEUE: u16 = 1;
+
+// Feature bits
+pub const VIRTIO_NET_F_MAC: u64 = 1 << 5;     // Device has a MAC address in config space
+
+/// Header size without VIRTIO_NET_F_MRG_RXBUF (legacy devices)
+const VIRTIO_NET_HDR_LEN_LEGACY: usize = 10;
+/// Header size with VIRTIO_F_VERSION_1 (num_buffers is always present)
+const VIRTIO_NET_HDR_LEN_MODERN: usize = 12;
+
+/// Number of receive and transmit buffers (one descriptor each)
+const NET_QUEUE_SIZE: u16 = 16;
+/// Size of each packet buffer: header plus a full Ethernet frame
+const NET_BUFFER_SIZE: usize = 2048;
+
+/// Largest Ethernet frame (without FCS) the driver sends or receives
+pub const MAX_FRAME_SIZE: usize = 1514;
+
+/// VirtIO network device
+pub struct VirtioNet {
+    initialized: bool,
+    mmio_base: usize,
+    version: u32,
+    mac: [u8; 6],
+    header_len: usize,
+    rx_queue: VirtioQueue,
+    tx_queue: VirtioQueue,
+    rx_buffers: usize,
+    tx_buffers: usize,
+    /// Descriptor (and buffer) the next receive buffer is posted in
+    rx_post_index: u16,
+    /// Descriptor (and buffer) the next transmitted frame uses
+    tx_index: u16,
+    rx_packets: u64,
+    tx_packets: u64,
+}
+
+impl VirtioNet {
+    pub const fn new() -> Self {
+        VirtioNet {
+            initialized: false,
+            mmio_base: 0,
+            version: 0,
+            mac: [0; 6],
+            header_len: VIRTIO_NET_HDR_LEN_LEGACY,
+            rx_queue: VirtioQueue::new(),
+            tx_queue: VirtioQueue::new(),
+            rx_buffers: 0,
+            tx_buffers: 0,
+            rx_post_index: 0,
+            tx_index: 0,
+            rx_packets: 0,
+            tx_packets: 0,
+        }
+    }
+
+    /// Initialize VirtIO network device
+    pub fn init(&mut self) -> DiskResult<()> {
+        debug_println!(target: "virtio", "Searching for VirtIO network device...");
+
+        if !self.discover_device()? {
+            return Err(DiskError::DeviceNotFound);
+        }
+        self.bring_up()
+    }
+
+    /// Initialize the device at `mmio_base`, which must be a network device
+    fn bring_up(&mut self) -> DiskResult<()> {
+        self.init_device()?;
+        self.setup_queues()?;
+        self.set_driver_ok();
+        self.fill_rx_queue()?;
+
+        self.initialized = true;
+        ok_println!(target: "virtio", "VirtIO network device ready, MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+                    self.mac[0], self.mac[1], self.mac[2], self.mac[3], self.mac[4], self.mac[5]);
+        Ok(())
+    }
+
+    /// Discover VirtIO network device
+    fn discover_device(&mut self) -> DiskResult<bool> {
+        for addr in slot_bases() {
+            if self.probe_mmio_device(addr) {
+                self.mmio_base = addr;
+                ok_println!(target: "virtio", "VirtIO network device found at 0x{:x}", addr);
+
+                if super::register_virtio_device(addr, VIRTIO_MMIO_SIZE, "VirtIO-Net").is_err() {
+                    warn_println!(target: "virtio", "Failed to register VirtIO network MMIO region");
+                }
+                return Ok(true);
+            }
+        }
+
+        info_println!(target: "virtio", "No VirtIO network device found");
+        Ok(false)
+    }
+
+    /// Probe MMIO device for VirtIO network
+    fn probe_mmio_device(&self, base: usize) -> bool {
+        unsafe {
+            let regs = RegisterBlock::new(base);
+            let magic = regs.read(VIRTIO_MMIO_MAGIC_VALUE);
+            let device_id = regs.read(VIRTIO_MMIO_DEVICE_ID);
+            magic == VIRTIO_MMIO_MAGIC && device_id == VIRTIO_ID_NET
+        }
+    }
+
+    /// Reset the device and negotiate features
+    fn init_device(&mut self) -> DiskResult<()> {
+        self.version = self.read_reg_u32(VIRTIO_MMIO_VERSION);
+
+        // Reset device
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, 0);
+
+        // Acknowledge device
+        self.set_status(VIRTIO_STATUS_ACKNOWLEDGE as u8);
+        self.set_status(VIRTIO_STATUS_DRIVER as u8);
+
+        // Read device features
+        self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 0);
+        let features_lo = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
+        self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+        let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
+        let device_features = ((features_hi as u64) << 32) | (features_lo as u64);
+        debug_println!(target: "virtio", "VirtIO net device features: 0x{:x}", device_features);
+
+        // Only the MAC address; no offloads, no merged buffers
+        let mut driver_features = device_features & VIRTIO_NET_F_MAC;
+        if self.version != 1 {
+            driver_features |= device_features & VIRTIO_F_VERSION_1;
+        }
+        self.header_len = if driver_features & VIRTIO_F_VERSION_1 != 0 {
+            VIRTIO_NET_HDR_LEN_MODERN
+        } else {
+            VIRTIO_NET_HDR_LEN_LEGACY
+        };
+
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, driver_features as u32);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, (driver_features >> 32) as u32);
+
+        self.set_status(VIRTIO_STATUS_FEATURES_OK as u8);
+
+        let status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
+        if (status & VIRTIO_STATUS_FEATURES_OK) == 0 {
+            err_println!(target: "virtio", "VirtIO net features not accepted by device");
+            return Err(DiskError::VirtIOError);
+        }
+
+        if driver_features & VIRTIO_NET_F_MAC != 0 {
+            for i in 0..6 {
+                self.mac[i] = self.regs().read(config_register::<u8>(i));
+            }
+        } else {
+            // Locally administered address
+            self.mac = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+            warn_println!(target: "virtio", "VirtIO net device has no MAC, using a local address");
+        }
+
+        Ok(())
+    }
+
+    /// Set up the receive and transmit queues and their packet buffers
+    fn setup_queues(&mut self) -> DiskResult<()> {
+        self.rx_queue = self.setup_queue(VIRTIO_NET_RX_QUEUE)?;
+        self.tx_queue = self.setup_queue(VIRTIO_NET_TX_QUEUE)?;
+
+        let buffers_size = NET_QUEUE_SIZE as usize * NET_BUFFER_SIZE;
+        self.rx_buffers = super::allocate_virtio_memory(buffers_size)?;
+        self.tx_buffers = super::allocate_virtio_memory(buffers_size)?;
+        unsafe {
+            core::ptr::write_bytes(self.rx_buffers as *mut u8, 0, buffers_size);
+            core::ptr::write_bytes(self.tx_buffers as *mut u8, 0, buffers_size);
+        }
+
+        ok_println!(target: "virtio", "VirtIO net queues initialized ({} buffers each)", NET_QUEUE_SIZE);
+        Ok(())
+    }
+
+    /// Allocate and register one virtqueue with a descriptor per buffer
+    fn setup_queue(&mut self, index: u16) -> DiskResult<VirtioQueue> {
+        let queue = VirtioQueue::setup(self.regs(), index, NET_QUEUE_SIZE, self.version == 1)?;
+        if queue.size() < NET_QUEUE_SIZE {
+            err_println!(target: "virtio", "VirtIO net queue {} too small: {}", index, queue.size());
+            return Err(DiskError::VirtIOError);
+        }
+        Ok(queue)
+    }
+
+    /// Give every receive buffer to the device
+    fn fill_rx_queue(&mut self) -> DiskResult<()> {
+        for _ in 0..NET_QUEUE_SIZE {
+            self.post_rx_buffer()?;
+        }
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_NET_RX_QUEUE as u32);
+        Ok(())
+    }
+
+    /// Post the next receive buffer. Buffers are used in ring order, so the
+    /// buffer index always matches the descriptor the queue writes.
+    fn post_rx_buffer(&mut self) -> DiskResult<()> {
+        let buffer = self.rx_buffers + self.rx_post_index as usize * NET_BUFFER_SIZE;
+        let desc = [VirtqDesc {
+            addr: buffer as u64,
+            len: NET_BUFFER_SIZE as u32,
+            flags: VIRTQ_DESC_F_WRITE,
+            next: 0,
+        }];
+        self.rx_queue.add_descriptor_chain(&desc)?;
+        self.rx_post_index = (self.rx_post_index + 1) % NET_QUEUE_SIZE;
+        Ok(())
+    }
+
+    /// Send one Ethernet frame and wait for the device to take it
+    pub fn transmit(&mut self, frame: &[u8]) -> DiskResult<()> {
+        if !self.initialized {
+            return Err(DiskError::NotInitialized);
+        }
+        if frame.len() > MAX_FRAME_SIZE {
+            return Err(DiskError::BufferTooSmall);
+        }
+
+        let buffer = self.tx_buffers + self.tx_index as usize * NET_BUFFER_SIZE;
+        unsafe {
+            // All-zero header: no checksum offload, no segmentation
+            core::ptr::write_bytes(buffer as *mut u8, 0, self.header_len);
+            core::ptr::copy_nonoverlapping(frame.as_ptr(), (buffer + self.header_len) as *mut u8, frame.len());
+        }
+
+        let desc = [VirtqDesc {
+            addr: buffer as u64,
+            len: (self.header_len + frame.len()) as u32,
+            flags: 0,
+            next: 0,
+        }];
+        let head_index = self.tx_queue.add_descriptor_chain(&desc)?;
+        self.tx_index = (self.tx_index + 1) % NET_QUEUE_SIZE;
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_NET_TX_QUEUE as u32);
+
+        let mut timeout = 1000000;
+        while timeout > 0 {
+            if self.tx_queue.wait_for_completion(head_index).is_some() {
+                self.tx_packets += 1;
+                return Ok(());
+            }
+            timeout -= 1;
+            core::hint::spin_loop();
+        }
+
+        warn_println!(target: "virtio", "VirtIO net transmit timed out");
+        Err(DiskError::IoError)
+    }
+
+    /// Copy the next received frame into `frame`. Returns its length, or
+    /// None if nothing arrived. Frames larger than `frame` are truncated.
+    pub fn receive(&mut self, frame: &mut [u8]) -> Option<usize> {
+        if !self.initialized {
+            return None;
+        }
+
+        let elem = self.rx_queue.get_used_elem()?;
+        let buffer = self.rx_buffers + (elem.id as usize % NET_QUEUE_SIZE as usize) * NET_BUFFER_SIZE;
+        let len = (elem.len as usize).saturating_sub(self.header_len).min(frame.len());
+        unsafe {
+            core::ptr::copy_nonoverlapping((buffer + self.header_len) as *const u8, frame.as_mut_ptr(), len);
+        }
+        self.rx_packets += 1;
+
+        // Hand the buffer back to the device
+        if self.post_rx_buffer().is_ok() {
+            self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_NET_RX_QUEUE as u32);
+        }
+
+        Some(len)
+    }
+
+    /// Set driver OK status
+    fn set_driver_ok(&mut self) {
+        self.set_status(VIRTIO_STATUS_DRIVER_OK as u8);
+    }
+
+    /// This device's register window
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(self.mmio_base) }
+    }
+
+    /// Read 32-bit register
+    fn read_reg_u32(&self, reg: Register<u32>) -> u32 {
+        self.regs().read(reg)
+    }
+
+    /// Write 32-bit register
+    fn write_reg_u32(&self, reg: Register<u32>, value: u32) {
+        self.regs().write(reg, value)
+    }
+
+    /// Set device status
+    fn set_status(&self, status: u8) {
+        let current_status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, current_status | (status as u32));
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /// MMIO base of the device in use, if initialized
+    pub fn mmio_base(&self) -> Option<usize> {
+        if self.initialized { Some(self.mmio_base) } else { None }
+    }
+
+    /// Frames received and sent since initialization
+    pub fn packet_counts(&self) -> (u64, u64) {
+        (self.rx_packets, self.tx_packets)
+    }
+
+    /// Print device information
+    pub fn show_info(&self) {
+        console_println!("  MMIO base: 0x{:x} (version {})", self.mmio_base, self.version);
+        console_println!("  Header length: {} bytes", self.header_len);
+        console_println!("  Packets: {} received, {} sent", self.rx_packets, self.tx_packets);
+    }
+}
+
+// Global VirtIO network device
+pub static VIRTIO_NET: Mutex<VirtioNet> = Mutex::new(VirtioNet::new());
+
+/// Initialize the VirtIO network device
+pub fn init_virtio_net() -> DiskResult<()> {
+    let mut net = VIRTIO_NET.lock();
+    net.init()
+}
+
+/// Initialize the network device at `base_addr`, e.g. one attached after boot
+pub fn init_with_address(base_addr: usize) -> DiskResult<()> {
+    let mut device = VirtioNet::new();
+    if !device.probe_mmio_device(base_addr) {
+        return Err(DiskError::DeviceNotFound);
+    }
+    device.mmio_base = base_addr;
+    if super::register_virtio_device(base_addr, VIRTIO_MMIO_SIZE, "VirtIO-Net").is_err() {
+        warn_println!(target: "virtio", "Failed to register VirtIO network MMIO region");
+    }
+    device.bring_up()?;
+    *VIRTIO_NET.lock() = device;
+    Ok(())
+}
@@ -1,10 +1,73 @@
 //! VirtIO queue structures and implementation
 //! Based on rust-vmm virtio-queue implementation
 
-use elinos_common::console_println;
+use elinos_common::{err_println, warn_println, debug_println};
+use elinos_common::mmio::RegisterBlock;
+use elinos_common::timer;
 use core::ptr::read_volatile;
 use super::{DiskResult, DiskError};
-use super::mmio::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+use super::mmio::*;
+
+/// Queue size asked for when the kernel command line does not pick one
+pub const DEFAULT_QUEUE_SIZE: u16 = 128;
+
+/// Largest queue the VirtIO specification allows
+pub const MAX_QUEUE_SIZE: u16 = 32768;
+
+/// Entries in each indirect descriptor table
+pub const INDIRECT_TABLE_LEN: usize = 16;
+
+/// Time a device has to complete a request before it is given up on
+pub const REQUEST_TIMEOUT_MS: u64 = 1000;
+
+/// Queue size requested by `virtio.queue_size=` on the kernel command line
+pub fn preferred_queue_size() -> u16 {
+    let cmdline = crate::boot_cmdline();
+    match cmdline.split_whitespace().find_map(|arg| arg.strip_prefix("virtio.queue_size=")) {
+        None => DEFAULT_QUEUE_SIZE,
+        Some(value) => match value.parse::<u16>() {
+            Ok(size) if size > 0 && size <= MAX_QUEUE_SIZE => size,
+            _ => {
+                warn_println!(target: "virtio", "Invalid virtio.queue_size '{}', using {}", value, DEFAULT_QUEUE_SIZE);
+                DEFAULT_QUEUE_SIZE
+            }
+        },
+    }
+}
+
+/// Largest power of two no bigger than `preferred` or the device's limit
+pub fn negotiate_size(preferred: u16, max: u32) -> Option<u16> {
+    let limit = (preferred as u32).min(max).min(MAX_QUEUE_SIZE as u32);
+    if limit == 0 {
+        return None;
+    }
+    Some(1 << (31 - limit.leading_zeros()))
+}
+
+/// Byte offsets of the three rings within one queue allocation
+#[derive(Debug, Clone, Copy)]
+pub struct QueueLayout {
+    pub avail_offset: usize,
+    pub used_offset: usize,
+    pub total_size: usize,
+}
+
+impl QueueLayout {
+    /// Legacy devices find the rings from a single page frame number, so
+    /// the used ring starts on its own page; modern devices only need it
+    /// 4-byte aligned
+    pub fn new(size: u16, legacy: bool) -> Self {
+        let size = size as usize;
+        let avail_offset = 16 * size;
+        let avail_end = avail_offset + 6 + 2 * size;
+        let used_offset = if legacy { align_up(avail_end) } else { (avail_end + 3) & !3 };
+        QueueLayout {
+            avail_offset,
+            used_offset,
+            total_size: used_offset + 6 + 8 * size,
+        }
+    }
+}
 
 /// VirtIO descriptor table entry
 /// This is the exact layout from the VirtIO specification
@@ -48,7 +111,9 @@ impl VirtqDesc {
 }
 
 /// VirtIO available ring structure
-/// This is where the guest puts available descriptor indices
+/// This is where the guest puts available descriptor indices. Only the
+/// header is accessed through this type; ring entries are addressed by
+/// offset, as a queue may hold more than 256 of them.
 #[repr(C)]
 pub struct VirtqAvail {
     /// Flags for available ring
@@ -83,6 +148,7 @@ pub struct VirtqUsedElem {
 }
 
 /// VirtIO used ring structure
+/// As with the available ring, entries are addressed by offset.
 #[repr(C)]
 pub struct VirtqUsed {
     /// Flags for used ring
@@ -126,6 +192,12 @@ pub struct VirtioQueue {
     last_used_idx: u16,
     /// Queue select index (usually 0 for block device)
     pub queue_index: u16,
+    /// First descriptor of the free list, threaded through `next`
+    free_head: u16,
+    /// Descriptors on the free list
+    num_free: u16,
+    /// Indirect tables, one per descriptor slot, or 0 when not in use
+    indirect_tables: usize,
 }
 
 impl VirtioQueue {
@@ -139,8 +211,75 @@ impl VirtioQueue {
             next_avail: 0,
             last_used_idx: 0,
             queue_index: 0,
+            free_head: 0,
+            num_free: 0,
+            indirect_tables: 0,
         }
     }
+
+    /// Select queue `index` on the device behind `regs`, negotiate its
+    /// size, allocate its rings and hand them to the device
+    pub fn setup(regs: RegisterBlock, index: u16, preferred: u16, legacy: bool) -> DiskResult<Self> {
+        regs.write(VIRTIO_MMIO_QUEUE_SEL, index as u32);
+
+        let max_queue_size = regs.read(VIRTIO_MMIO_QUEUE_NUM_MAX);
+        let size = match negotiate_size(preferred, max_queue_size) {
+            Some(size) => size,
+            None => {
+                err_println!(target: "virtio", "VirtIO queue {} is not available", index);
+                return Err(DiskError::VirtIOError);
+            }
+        };
+        regs.write(VIRTIO_MMIO_QUEUE_NUM, size as u32);
+
+        let layout = QueueLayout::new(size, legacy);
+        let base = super::allocate_virtio_memory(layout.total_size)?;
+        unsafe {
+            core::ptr::write_bytes(base as *mut u8, 0, layout.total_size);
+        }
+
+        let mut queue = VirtioQueue::new();
+        queue.init(size, index, base, base + layout.avail_offset, base + layout.used_offset)?;
+
+        if legacy {
+            if base % PAGE_SIZE != 0 {
+                return Err(DiskError::VirtIOError);
+            }
+            regs.write(VIRTIO_MMIO_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_ALIGN, PAGE_SIZE as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_PFN, (base / PAGE_SIZE) as u32);
+        } else {
+            let avail = base + layout.avail_offset;
+            let used = base + layout.used_offset;
+            regs.write(VIRTIO_MMIO_QUEUE_DESC_LOW, base as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_DESC_HIGH, (base >> 32) as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_DRIVER_LOW, avail as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_DEVICE_LOW, used as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+            regs.write(VIRTIO_MMIO_QUEUE_READY, 1);
+        }
+
+        queue.set_ready(true);
+        Ok(queue)
+    }
+
+    /// Allocate an indirect table for every descriptor slot. Only call this
+    /// once VIRTIO_RING_F_INDIRECT_DESC has been negotiated; afterwards
+    /// chains of up to INDIRECT_TABLE_LEN buffers take one ring descriptor.
+    pub fn enable_indirect(&mut self) -> DiskResult<()> {
+        let table_size = self.size as usize * INDIRECT_TABLE_LEN * core::mem::size_of::<VirtqDesc>();
+        let tables = super::allocate_virtio_memory(table_size)?;
+        unsafe {
+            core::ptr::write_bytes(tables as *mut u8, 0, table_size);
+        }
+        self.indirect_tables = tables;
+        Ok(())
+    }
+
+    pub fn indirect_enabled(&self) -> bool {
+        self.indirect_tables != 0
+    }
     
     // Make ready field accessible to block device
     pub fn set_ready(&mut self, ready: bool) {
@@ -149,7 +288,7 @@ impl VirtioQueue {
 
     pub fn init(&mut self, size: u16, queue_idx: u16, desc_table: usize, avail_ring: usize, used_ring: usize) -> DiskResult<()> {
         if size == 0 || (size & (size - 1)) != 0 {
-            console_println!("[x] VirtioQueue init error: size {} is not a power of two or is zero.", size);
+            err_println!(target: "virtio", "VirtioQueue init error: size {} is not a power of two or is zero.", size);
             return Err(DiskError::InvalidParameter);
         }
 
@@ -161,6 +300,14 @@ impl VirtioQueue {
         self.next_avail = 0;
         self.last_used_idx = 0;
         self.ready = false;
+        self.indirect_tables = 0;
+
+        // Every descriptor starts on the free list
+        for i in 0..size {
+            self.write_desc(i, VirtqDesc { addr: 0, len: 0, flags: 0, next: (i + 1) % size });
+        }
+        self.free_head = 0;
+        self.num_free = size;
 
         // CRITICAL: Explicitly initialize the available and used ring headers
         unsafe {
@@ -182,103 +329,161 @@ impl VirtioQueue {
             self.last_used_idx = post_used_idx;
         }
 
-        console_println!("[o] VirtioQueue initialized: size={}, idx={}, desc_base=0x{:x}, avail_base=0x{:x}, used_base=0x{:x}",
+        debug_println!(target: "virtio", "VirtioQueue initialized: size={}, idx={}, desc_base=0x{:x}, avail_base=0x{:x}, used_base=0x{:x}",
                         size, queue_idx, desc_table, avail_ring, used_ring);
 
         Ok(())
     }
 
+    fn read_desc(&self, index: u16) -> VirtqDesc {
+        unsafe { read_volatile((self.desc_table as *const VirtqDesc).add(index as usize)) }
+    }
+
+    fn write_desc(&mut self, index: u16, desc: VirtqDesc) {
+        unsafe { core::ptr::write_volatile((self.desc_table as *mut VirtqDesc).add(index as usize), desc) }
+    }
+
     /// Add a descriptor chain to the available ring
-    /// Returns the head descriptor index
+    /// Returns the head descriptor index. The chain is linked in slice
+    /// order: `next` fields are filled in here, and NEXT flags are set on
+    /// every descriptor but the last. With indirect descriptors enabled,
+    /// multi-buffer chains go through the head slot's indirect table.
     pub fn add_descriptor_chain(&mut self, chain: &[VirtqDesc]) -> DiskResult<u16> {
         if !self.ready {
             return Err(DiskError::QueueFull);
         }
-        
+
         if chain.is_empty() || chain.len() > self.size as usize {
             return Err(DiskError::InvalidParameter);
         }
-        
-        // Simple check: ensure we don't wrap around too much
-        let available_space = self.size.saturating_sub(8); // Keep some buffer
-        if chain.len() as u16 > available_space {
+
+        let head_index = if self.indirect_enabled() && chain.len() > 1 && chain.len() <= INDIRECT_TABLE_LEN {
+            self.add_indirect(chain)?
+        } else {
+            self.add_direct(chain)?
+        };
+
+        // Add to available ring
+        unsafe {
+            let avail_ring_ptr = self.avail_ring as *mut VirtqAvail;
+
+            let avail_idx = read_volatile(&(*avail_ring_ptr).idx);
+            let ring_idx = (avail_idx % self.size) as usize;
+            let entry = (self.avail_ring + 4 + 2 * ring_idx) as *mut u16;
+            core::ptr::write_volatile(entry, head_index);
+
+            // The device may read the ring entry as soon as it sees the new index
+            elinos_common::mmio::io_fence();
+
+            core::ptr::write_volatile(&mut (*avail_ring_ptr).idx, avail_idx.wrapping_add(1));
+        }
+
+        self.next_avail = self.next_avail.wrapping_add(1);
+
+        Ok(head_index)
+    }
+
+    /// Take descriptors for `chain` off the free list
+    fn add_direct(&mut self, chain: &[VirtqDesc]) -> DiskResult<u16> {
+        if chain.len() > self.num_free as usize {
             return Err(DiskError::QueueFull);
         }
-        
-        let head_index = self.next_avail; 
-        let desc_table_ptr = self.desc_table as *mut VirtqDesc;
-
-        // Place descriptors into the descriptor table
-        for i in 0..chain.len() {
-            let actual_table_idx = (head_index + i as u16) % self.size;
-            let mut desc_to_write = chain[i];
-
-            // Fix the next field to point to the correct absolute descriptor index
-            if (desc_to_write.flags & VIRTQ_DESC_F_NEXT) != 0 {
-                // Calculate the absolute index of the next descriptor in the chain
-                let next_chain_idx = desc_to_write.next as usize;
-                if next_chain_idx < chain.len() {
-                    desc_to_write.next = (head_index + next_chain_idx as u16) % self.size;
-                } else {
-                    // Invalid next index - clear the NEXT flag to prevent corruption
-                    desc_to_write.flags &= !VIRTQ_DESC_F_NEXT;
-                    desc_to_write.next = 0;
-                }
+
+        let head_index = self.free_head;
+        let mut index = head_index;
+        for (i, desc) in chain.iter().enumerate() {
+            let next_free = self.read_desc(index).next;
+            let mut desc = *desc;
+            if i + 1 < chain.len() {
+                desc.flags |= VIRTQ_DESC_F_NEXT;
+                desc.next = next_free;
+            } else {
+                desc.flags &= !VIRTQ_DESC_F_NEXT;
+                desc.next = 0;
+            }
+            self.write_desc(index, desc);
+            index = next_free;
+        }
+
+        self.free_head = index;
+        self.num_free -= chain.len() as u16;
+        Ok(head_index)
+    }
+
+    /// Copy `chain` into the indirect table of one free descriptor
+    fn add_indirect(&mut self, chain: &[VirtqDesc]) -> DiskResult<u16> {
+        if self.num_free == 0 {
+            return Err(DiskError::QueueFull);
+        }
+
+        let head_index = self.free_head;
+        let table = self.indirect_tables + head_index as usize * INDIRECT_TABLE_LEN * core::mem::size_of::<VirtqDesc>();
+        for (i, desc) in chain.iter().enumerate() {
+            let mut desc = *desc;
+            if i + 1 < chain.len() {
+                desc.flags |= VIRTQ_DESC_F_NEXT;
+                desc.next = (i + 1) as u16;
+            } else {
+                desc.flags &= !VIRTQ_DESC_F_NEXT;
+                desc.next = 0;
             }
-            
             unsafe {
-                core::ptr::write_volatile(desc_table_ptr.add(actual_table_idx as usize), desc_to_write);
+                core::ptr::write_volatile((table as *mut VirtqDesc).add(i), desc);
             }
         }
 
-        // Add to available ring
-        unsafe {
-            let avail_ring_ptr = self.avail_ring as *mut VirtqAvail;
-            
-            // Read current available index from device (should be initialized to 0)
-            let device_avail_idx = read_volatile(&(*avail_ring_ptr).idx);
-            let ring_idx = device_avail_idx % self.size; 
-            
-            // Debug: Show queue state before adding
-            //  console_println!("[DEBUG] Queue add: device_avail_idx={}, ring_idx={}, head_index={}, size={}", 
-            //    device_avail_idx, ring_idx, head_index, self.size);
-            
-            // Write the head descriptor index to the available ring
-            core::ptr::write_volatile(&mut (*avail_ring_ptr).ring[ring_idx as usize], head_index);
-            
-            // Increment the available index
-            let new_avail_idx = device_avail_idx.wrapping_add(1);
-            core::ptr::write_volatile(&mut (*avail_ring_ptr).idx, new_avail_idx);
-            
-            // Debug: Verify what we wrote
-            let written_head = read_volatile(&(*avail_ring_ptr).ring[ring_idx as usize]);
-            let final_avail_idx = read_volatile(&(*avail_ring_ptr).idx);
-            // console_println!("[DEBUG] Queue after: written_head={}, final_avail_idx={}", 
-            //     written_head, final_avail_idx);
+        self.free_head = self.read_desc(head_index).next;
+        self.num_free -= 1;
+        self.write_desc(head_index, VirtqDesc {
+            addr: table as u64,
+            len: (chain.len() * core::mem::size_of::<VirtqDesc>()) as u32,
+            flags: VIRTQ_DESC_F_INDIRECT,
+            next: 0,
+        });
+        Ok(head_index)
+    }
+
+    /// Put the chain starting at `head` back on the free list
+    fn free_chain(&mut self, head: u16) {
+        if head >= self.size {
+            return;
         }
-        
-        self.next_avail = (self.next_avail + chain.len() as u16) % self.size;
 
-        Ok(head_index)
+        let mut index = head;
+        for _ in 0..self.size {
+            let mut desc = self.read_desc(index);
+            self.num_free += 1;
+            if desc.flags & VIRTQ_DESC_F_NEXT == 0 || desc.next >= self.size {
+                desc.flags = 0;
+                desc.next = self.free_head;
+                self.write_desc(index, desc);
+                break;
+            }
+            index = desc.next;
+        }
+        self.free_head = head;
     }
 
-    /// Get the next used element from the used ring
+    /// Get the next used element from the used ring, returning its
+    /// descriptors to the free list
     pub fn get_used_elem(&mut self) -> Option<VirtqUsedElem> {
-        unsafe {
+        let elem = unsafe {
             let used_ring_ptr = self.used_ring as *const VirtqUsed;
             let device_current_used_idx = read_volatile(&(*used_ring_ptr).idx);
-            
+
             if self.last_used_idx == device_current_used_idx {
                 return None;
             }
-            
-            let elem_array_idx = self.last_used_idx % self.size;
-            let elem = read_volatile(&(*used_ring_ptr).ring[elem_array_idx as usize]);
-            
+
+            let elem_array_idx = (self.last_used_idx % self.size) as usize;
+            let elem = read_volatile((self.used_ring + 4 + 8 * elem_array_idx) as *const VirtqUsedElem);
+
             self.last_used_idx = self.last_used_idx.wrapping_add(1);
-            
-            Some(elem)
-        }
+            elem
+        };
+
+        self.free_chain(elem.id as u16);
+        Some(elem)
     }
 
     /// Wait for completion of a specific descriptor chain
@@ -296,6 +501,35 @@ impl VirtioQueue {
         None
     }
 
+    /// Wait for the device to hand back the chain at `expected_head`; None
+    /// if it takes longer than `REQUEST_TIMEOUT_MS`. Once device interrupts
+    /// are on the hart sleeps until the used-ring interrupt instead of
+    /// spinning on the used index.
+    pub fn wait_for_used(&mut self, expected_head: u16) -> Option<VirtqUsedElem> {
+        self.wait_until_used(|queue| queue.wait_for_completion(expected_head))
+    }
+
+    /// Wait for whichever chain the device hands back next, with several
+    /// outstanding; None after `REQUEST_TIMEOUT_MS` without one
+    pub fn wait_for_any_used(&mut self) -> Option<VirtqUsedElem> {
+        self.wait_until_used(Self::get_used_elem)
+    }
+
+    fn wait_until_used(&mut self, mut poll: impl FnMut(&mut Self) -> Option<VirtqUsedElem>) -> Option<VirtqUsedElem> {
+        let deadline = timer::ticks() + timer::ms_to_ticks(REQUEST_TIMEOUT_MS);
+        if crate::trap::external_interrupts_enabled() {
+            return crate::trap::wait_until(deadline, || poll(self));
+        }
+        // Early in boot nothing would wake a wfi
+        while timer::ticks() < deadline {
+            if let Some(elem) = poll(self) {
+                return Some(elem);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+
     pub fn is_ready(&self) -> bool {
         self.ready
     }
@@ -303,6 +537,11 @@ impl VirtioQueue {
     pub fn size(&self) -> u16 {
         self.size
     }
+
+    /// Descriptors not currently handed to the device
+    pub fn num_free(&self) -> u16 {
+        self.num_free
+    }
     
     pub fn queue_index(&self) -> u16 {
         self.queue_index
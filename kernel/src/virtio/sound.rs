@@ -0,0 +1,429 @@
+//! VirtIO sound device implementation for elinOS
+//! Plays tones on the device's first output PCM stream: one request on the
+//! control queue each to set the stream up, start it and tear it down, and
+//! square-wave periods on the transmit queue in between. There is no
+//! mixing and no capture; `crate::sound` decides what to play.
+
+use elinos_common::{console_println, ok_println, err_println, warn_println, info_println};
+use spin::Mutex;
+use elinos_common::mmio::{Register, RegisterBlock};
+
+use super::{DiskResult, DiskError};
+use super::mmio::*;
+use super::queue::{VirtioQueue, VirtqDesc};
+
+// Queue indices; the event and capture queues are not used
+pub const VIRTIO_SND_CONTROLQ: u16 = 0;
+pub const VIRTIO_SND_TXQ: u16 = 2;
+
+const CONTROL_QUEUE_SIZE: u16 = 8;
+const TX_QUEUE_SIZE: u16 = 16;
+
+// Config space
+const VIRTIO_SND_CFG_STREAMS: usize = 4;
+
+// Control requests
+const VIRTIO_SND_R_PCM_INFO: u32 = 0x0100;
+const VIRTIO_SND_R_PCM_SET_PARAMS: u32 = 0x0101;
+const VIRTIO_SND_R_PCM_PREPARE: u32 = 0x0102;
+const VIRTIO_SND_R_PCM_RELEASE: u32 = 0x0103;
+const VIRTIO_SND_R_PCM_START: u32 = 0x0104;
+const VIRTIO_SND_R_PCM_STOP: u32 = 0x0105;
+
+const VIRTIO_SND_S_OK: u32 = 0x8000;
+
+const VIRTIO_SND_D_OUTPUT: u8 = 0;
+const VIRTIO_SND_PCM_FMT_S16: u8 = 5;
+
+/// Rates tried, best first, with their index in the device's rate bitmap
+const RATES: [(u32, u8); 5] = [(48000, 7), (44100, 6), (22050, 4), (16000, 3), (8000, 1)];
+
+/// Length of a `virtio_snd_pcm_info`
+const PCM_INFO_SIZE: usize = 32;
+
+/// Periods in flight at once, each in its own page of `buffers`
+const TX_SLOTS: usize = 4;
+const SLOT_SIZE: usize = 4096;
+/// Offset of the status the device writes back, at the end of a slot
+const STATUS_OFFSET: usize = SLOT_SIZE - 8;
+/// Offset of the samples; the stream ID is at the start of the slot
+const DATA_OFFSET: usize = 8;
+
+/// Periods are this long
+const PERIOD_MS: u32 = 10;
+
+/// Peak of the square wave, a quarter of full scale
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+/// `virtio_snd_pcm_hdr`: a request about one stream
+#[repr(C)]
+struct PcmHeader {
+    code: u32,
+    stream_id: u32,
+}
+
+/// `virtio_snd_query_info`
+#[repr(C)]
+struct QueryInfo {
+    code: u32,
+    start_id: u32,
+    count: u32,
+    size: u32,
+}
+
+/// `virtio_snd_pcm_set_params`
+#[repr(C)]
+struct SetParams {
+    header: PcmHeader,
+    buffer_bytes: u32,
+    period_bytes: u32,
+    features: u32,
+    channels: u8,
+    format: u8,
+    rate: u8,
+    padding: u8,
+}
+
+/// VirtIO sound device
+pub struct VirtioSound {
+    initialized: bool,
+    mmio_base: usize,
+    version: u32,
+    control_queue: VirtioQueue,
+    tx_queue: VirtioQueue,
+    /// `TX_SLOTS` pages for the periods in flight
+    buffers: usize,
+    /// Slot of each descriptor chain handed to the device
+    slot_of: [u8; TX_QUEUE_SIZE as usize],
+    stream_id: u32,
+    channels: u8,
+    rate_hz: u32,
+    rate_index: u8,
+    tones: u64,
+}
+
+impl VirtioSound {
+    pub const fn new() -> Self {
+        VirtioSound {
+            initialized: false,
+            mmio_base: 0,
+            version: 0,
+            control_queue: VirtioQueue::new(),
+            tx_queue: VirtioQueue::new(),
+            buffers: 0,
+            slot_of: [0; TX_QUEUE_SIZE as usize],
+            stream_id: 0,
+            channels: 0,
+            rate_hz: 0,
+            rate_index: 0,
+            tones: 0,
+        }
+    }
+
+    /// Initialize the first VirtIO sound device
+    pub fn init(&mut self) -> DiskResult<()> {
+        let base = slot_bases().find(|&base| probe_mmio_device(base))
+            .ok_or(DiskError::DeviceNotFound)?;
+        self.mmio_base = base;
+        if super::register_virtio_device(base, VIRTIO_MMIO_SIZE, "VirtIO-Sound").is_err() {
+            warn_println!(target: "virtio", "Failed to register VirtIO sound MMIO region");
+        }
+        self.bring_up()
+    }
+
+    /// Initialize the device at `mmio_base`, which must be a sound device
+    fn bring_up(&mut self) -> DiskResult<()> {
+        self.init_device()?;
+        self.setup_queues()?;
+        self.set_status(VIRTIO_STATUS_DRIVER_OK as u8);
+        self.find_output_stream()?;
+
+        self.initialized = true;
+        ok_println!(target: "virtio", "VirtIO sound device ready: stream {}, {} Hz, {} channel(s)",
+            self.stream_id, self.rate_hz, self.channels);
+        Ok(())
+    }
+
+    /// Reset the device and negotiate features; it has none we need
+    fn init_device(&mut self) -> DiskResult<()> {
+        self.version = self.read_reg_u32(VIRTIO_MMIO_VERSION);
+
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, 0);
+        self.set_status(VIRTIO_STATUS_ACKNOWLEDGE as u8);
+        self.set_status(VIRTIO_STATUS_DRIVER as u8);
+
+        self.write_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+        let features_hi = self.read_reg_u32(VIRTIO_MMIO_DEVICE_FEATURES);
+        let device_features = (features_hi as u64) << 32;
+        let driver_features = if self.version != 1 { device_features & VIRTIO_F_VERSION_1 } else { 0 };
+
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, driver_features as u32);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+        self.write_reg_u32(VIRTIO_MMIO_DRIVER_FEATURES, (driver_features >> 32) as u32);
+
+        self.set_status(VIRTIO_STATUS_FEATURES_OK as u8);
+        if self.read_reg_u32(VIRTIO_MMIO_STATUS) & VIRTIO_STATUS_FEATURES_OK == 0 {
+            err_println!(target: "virtio", "VirtIO sound features not accepted by device");
+            return Err(DiskError::VirtIOError);
+        }
+        Ok(())
+    }
+
+    /// Set up the control and transmit queues and the period buffers
+    fn setup_queues(&mut self) -> DiskResult<()> {
+        let legacy = self.version == 1;
+        self.control_queue = VirtioQueue::setup(self.regs(), VIRTIO_SND_CONTROLQ, CONTROL_QUEUE_SIZE, legacy)?;
+        self.tx_queue = VirtioQueue::setup(self.regs(), VIRTIO_SND_TXQ, TX_QUEUE_SIZE, legacy)?;
+        self.buffers = super::allocate_virtio_memory(TX_SLOTS * SLOT_SIZE)?;
+        unsafe {
+            core::ptr::write_bytes(self.buffers as *mut u8, 0, TX_SLOTS * SLOT_SIZE);
+        }
+        Ok(())
+    }
+
+    /// Pick the first output stream that takes 16-bit samples, and the
+    /// best rate and fewest channels it has
+    fn find_output_stream(&mut self) -> DiskResult<()> {
+        let streams: u32 = self.regs().read(config_register(VIRTIO_SND_CFG_STREAMS));
+        for stream_id in 0..streams {
+            let query = QueryInfo { code: VIRTIO_SND_R_PCM_INFO, start_id: stream_id, count: 1, size: PCM_INFO_SIZE as u32 };
+            let mut response = [0u8; 4 + PCM_INFO_SIZE];
+            self.request(&query, &mut response)?;
+            let info = &response[4..];
+            let formats = u64::from_le_bytes(info[8..16].try_into().unwrap_or_default());
+            let rates = u64::from_le_bytes(info[16..24].try_into().unwrap_or_default());
+            let (direction, channels_min) = (info[24], info[25]);
+            if direction != VIRTIO_SND_D_OUTPUT || formats & (1 << VIRTIO_SND_PCM_FMT_S16) == 0 {
+                continue;
+            }
+            let Some(&(rate_hz, rate_index)) = RATES.iter().find(|&&(_, index)| rates & (1 << index) != 0) else {
+                continue;
+            };
+            // A period of the most channels a slot holds
+            if channels_min == 0 || (rate_hz * PERIOD_MS / 1000) as usize * 2 * channels_min as usize > STATUS_OFFSET - DATA_OFFSET {
+                continue;
+            }
+            self.stream_id = stream_id;
+            self.channels = channels_min;
+            self.rate_hz = rate_hz;
+            self.rate_index = rate_index;
+            return Ok(());
+        }
+        warn_println!(target: "virtio", "VirtIO sound device has no usable output stream");
+        Err(DiskError::DeviceNotFound)
+    }
+
+    /// Send a request on the control queue and wait for its answer;
+    /// `response` starts with the status the device writes
+    fn request<T>(&mut self, request: &T, response: &mut [u8]) -> DiskResult<()> {
+        let desc = [
+            VirtqDesc {
+                addr: request as *const T as u64,
+                len: core::mem::size_of::<T>() as u32,
+                flags: VIRTQ_DESC_F_NEXT,
+                next: 1,
+            },
+            VirtqDesc {
+                addr: response.as_mut_ptr() as u64,
+                len: response.len() as u32,
+                flags: VIRTQ_DESC_F_WRITE,
+                next: 0,
+            },
+        ];
+        let head = self.control_queue.add_descriptor_chain(&desc)?;
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_SND_CONTROLQ as u32);
+        self.control_queue.wait_for_used(head).ok_or(DiskError::IoError)?;
+
+        let status = u32::from_le_bytes(response[..4].try_into().unwrap_or_default());
+        if status != VIRTIO_SND_S_OK {
+            warn_println!(target: "virtio", "VirtIO sound request failed, status: 0x{:x}", status);
+            return Err(DiskError::VirtIOError);
+        }
+        Ok(())
+    }
+
+    /// A request about our stream that answers with a status only
+    fn stream_request(&mut self, code: u32) -> DiskResult<()> {
+        let mut status = [0u8; 4];
+        self.request(&PcmHeader { code, stream_id: self.stream_id }, &mut status)
+    }
+
+    /// Play a square wave of `frequency` Hz for `duration_ms`, returning
+    /// once the device has taken the last period
+    pub fn play_tone(&mut self, frequency: u32, duration_ms: u32) -> DiskResult<()> {
+        if !self.initialized {
+            return Err(DiskError::NotInitialized);
+        }
+        if frequency == 0 || frequency > self.rate_hz / 2 {
+            return Err(DiskError::InvalidParameter);
+        }
+        let frame_bytes = 2 * self.channels as u32;
+        let period_frames = self.rate_hz * PERIOD_MS / 1000;
+        let params = SetParams {
+            header: PcmHeader { code: VIRTIO_SND_R_PCM_SET_PARAMS, stream_id: self.stream_id },
+            buffer_bytes: period_frames * frame_bytes * TX_SLOTS as u32,
+            period_bytes: period_frames * frame_bytes,
+            features: 0,
+            channels: self.channels,
+            format: VIRTIO_SND_PCM_FMT_S16,
+            rate: self.rate_index,
+            padding: 0,
+        };
+        let mut status = [0u8; 4];
+        self.request(&params, &mut status)?;
+        self.stream_request(VIRTIO_SND_R_PCM_PREPARE)?;
+
+        let total_frames = (self.rate_hz as u64 * duration_ms as u64 / 1000) as u32;
+        let result = self.stream_tone(frequency, total_frames, period_frames);
+
+        // Tear the stream down whatever happened, so the next tone can start
+        let _ = self.stream_request(VIRTIO_SND_R_PCM_STOP);
+        let _ = self.stream_request(VIRTIO_SND_R_PCM_RELEASE);
+        if result.is_ok() {
+            self.tones += 1;
+        }
+        result
+    }
+
+    /// Queue periods until `total_frames` are sent: fill every free slot,
+    /// start the stream once the first ones are queued, then refill slots
+    /// as the device hands them back
+    fn stream_tone(&mut self, frequency: u32, total_frames: u32, period_frames: u32) -> DiskResult<()> {
+        let half_wave = (self.rate_hz / (2 * frequency)).max(1);
+        let frame_bytes = 2 * self.channels as u32;
+        let mut free: heapless::Vec<u8, TX_SLOTS> = (0..TX_SLOTS as u8).collect();
+        let mut queued = 0;
+        let mut outstanding = 0;
+        let mut started = false;
+
+        while queued < total_frames || outstanding > 0 {
+            if queued < total_frames {
+                if let Some(slot) = free.pop() {
+                    let frames = (total_frames - queued).min(period_frames);
+                    self.fill_slot(slot, queued, frames, half_wave);
+                    self.submit_slot(slot, frames * frame_bytes)?;
+                    queued += frames;
+                    outstanding += 1;
+                    continue;
+                }
+            }
+            if !started {
+                self.stream_request(VIRTIO_SND_R_PCM_START)?;
+                started = true;
+            }
+            let elem = self.tx_queue.wait_for_any_used().ok_or(DiskError::IoError)?;
+            outstanding -= 1;
+            let _ = free.push(self.slot_of[elem.id as usize % TX_QUEUE_SIZE as usize]);
+        }
+        Ok(())
+    }
+
+    /// Write `frames` frames of the square wave, starting `first` frames
+    /// into the tone, into `slot`
+    fn fill_slot(&self, slot: u8, first: u32, frames: u32, half_wave: u32) {
+        let base = self.slot_addr(slot);
+        unsafe {
+            core::ptr::write_volatile(base as *mut u32, self.stream_id);
+            let samples = (base + DATA_OFFSET) as *mut i16;
+            for frame in 0..frames {
+                let sample = if ((first + frame) / half_wave) % 2 == 0 { AMPLITUDE } else { -AMPLITUDE };
+                for channel in 0..self.channels as usize {
+                    core::ptr::write_volatile(samples.add(frame as usize * self.channels as usize + channel), sample);
+                }
+            }
+        }
+    }
+
+    /// Hand `slot` to the device: stream ID, `len` bytes of samples, and
+    /// room for the status
+    fn submit_slot(&mut self, slot: u8, len: u32) -> DiskResult<()> {
+        let base = self.slot_addr(slot);
+        let desc = [
+            VirtqDesc { addr: base as u64, len: 4, flags: VIRTQ_DESC_F_NEXT, next: 1 },
+            VirtqDesc { addr: (base + DATA_OFFSET) as u64, len, flags: VIRTQ_DESC_F_NEXT, next: 2 },
+            VirtqDesc { addr: (base + STATUS_OFFSET) as u64, len: 8, flags: VIRTQ_DESC_F_WRITE, next: 0 },
+        ];
+        let head = self.tx_queue.add_descriptor_chain(&desc)?;
+        self.slot_of[head as usize % TX_QUEUE_SIZE as usize] = slot;
+        self.regs().write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, VIRTIO_SND_TXQ as u32);
+        Ok(())
+    }
+
+    fn slot_addr(&self, slot: u8) -> usize {
+        self.buffers + slot as usize * SLOT_SIZE
+    }
+
+    /// This device's register window
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(self.mmio_base) }
+    }
+
+    /// Read 32-bit register
+    fn read_reg_u32(&self, reg: Register<u32>) -> u32 {
+        self.regs().read(reg)
+    }
+
+    /// Write 32-bit register
+    fn write_reg_u32(&self, reg: Register<u32>, value: u32) {
+        self.regs().write(reg, value)
+    }
+
+    /// Set device status
+    fn set_status(&self, status: u8) {
+        let current_status = self.read_reg_u32(VIRTIO_MMIO_STATUS);
+        self.write_reg_u32(VIRTIO_MMIO_STATUS, current_status | (status as u32));
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// MMIO base of the device in use, if initialized
+    pub fn mmio_base(&self) -> Option<usize> {
+        if self.initialized { Some(self.mmio_base) } else { None }
+    }
+
+    /// Print device information
+    pub fn show_info(&self) {
+        console_println!("  MMIO base: 0x{:x} (version {})", self.mmio_base, self.version);
+        console_println!("  Stream {}: {} Hz, {} channel(s), 16-bit", self.stream_id, self.rate_hz, self.channels);
+        console_println!("  Tones played: {}", self.tones);
+    }
+}
+
+/// Probe MMIO device for VirtIO sound
+fn probe_mmio_device(base: usize) -> bool {
+    let regs = unsafe { RegisterBlock::new(base) };
+    regs.read(VIRTIO_MMIO_MAGIC_VALUE) == VIRTIO_MMIO_MAGIC && regs.read(VIRTIO_MMIO_DEVICE_ID) == VIRTIO_ID_SOUND
+}
+
+// Global VirtIO sound device
+pub static VIRTIO_SOUND: Mutex<VirtioSound> = Mutex::new(VirtioSound::new());
+
+/// Initialize the VirtIO sound device
+pub fn init_virtio_sound() -> DiskResult<()> {
+    let result = VIRTIO_SOUND.lock().init();
+    if result.is_err() {
+        info_println!(target: "virtio", "No VirtIO sound device");
+    }
+    result
+}
+
+/// Initialize the sound device at `base_addr`, e.g. one attached after boot
+pub fn init_with_address(base_addr: usize) -> DiskResult<()> {
+    if VIRTIO_SOUND.lock().is_initialized() {
+        return Err(DiskError::DeviceNotReady);
+    }
+    if !probe_mmio_device(base_addr) {
+        return Err(DiskError::DeviceNotFound);
+    }
+    let mut device = VirtioSound::new();
+    device.mmio_base = base_addr;
+    if super::register_virtio_device(base_addr, VIRTIO_MMIO_SIZE, "VirtIO-Sound").is_err() {
+        warn_println!(target: "virtio", "Failed to register VirtIO sound MMIO region");
+    }
+    device.bring_up()?;
+    *VIRTIO_SOUND.lock() = device;
+    Ok(())
+}
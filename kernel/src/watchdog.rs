@@ -0,0 +1,187 @@
+//! Software watchdog for hung kernel code and programs
+//!
+//! The kernel has one thread of control, so a loop that never ends (a
+//! driver polling a device that will not answer, a spin lock that is never
+//! released) leaves the shell dead with nothing said. The places where the
+//! kernel is known to be getting somewhere call `pet`: the shell waiting at
+//! its prompt, each system call, a console read waiting for a key, and each
+//! wake-up of a wait in `trap::wait_until`, which only ever sleeps on a
+//! device or a deadline.
+//!
+//! Each timer interrupt calls `check`. Once `kernel.watchdog.timeout_secs`
+//! seconds (default 30, 0 turns the watchdog off) pass without a `pet`, it
+//! reports what it knows: how long it has been, the last progress made,
+//! and where the hart was interrupted. A user program computing that long
+//! without a system call is reported the same way. With
+//! `kernel.watchdog.action` set to `reset` the machine is then reset
+//! through the SBI; otherwise the report is made once per stall and the
+//! kernel is left to get on with it.
+//!
+//! The report goes straight to the SBI console rather than through the
+//! console's locks, which the stuck code may hold. The check runs in the
+//! timer interrupt, so code running with interrupts off, as system calls
+//! do, is only caught once it turns them on again or returns to the
+//! program. The watchdog is armed by the first `pet`, so boot is not
+//! watched.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use elinos_common::sbi;
+
+use crate::sysctl::{Kind, Tunable};
+use crate::trap::{self, TrapContext};
+
+/// Seconds without progress before the watchdog fires, unless changed
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// What was going on when the kernel last made progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Progress {
+    /// Nothing yet: the watchdog is not armed
+    None,
+    /// The shell waited for input at its prompt
+    Shell,
+    /// A program made a system call
+    Syscall,
+    /// A console read waited for input
+    Input,
+    /// A wait for a device or a deadline woke up
+    Sleep,
+}
+
+impl Progress {
+    fn from_u8(value: u8) -> Progress {
+        match value {
+            1 => Progress::Shell,
+            2 => Progress::Syscall,
+            3 => Progress::Input,
+            4 => Progress::Sleep,
+            _ => Progress::None,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Progress::None => "none",
+            Progress::Shell => "shell at its prompt",
+            Progress::Syscall => "system call",
+            Progress::Input => "console read waiting",
+            Progress::Sleep => "wait for a device or deadline",
+        }
+    }
+}
+
+static TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_SECS);
+static RESET: AtomicBool = AtomicBool::new(false);
+
+// Tick of the last `pet`, what it was for, and the system call number if
+// it was one
+static LAST_PET: AtomicU64 = AtomicU64::new(0);
+static LAST_PROGRESS: AtomicU8 = AtomicU8::new(Progress::None as u8);
+static LAST_SYSCALL: AtomicU64 = AtomicU64::new(0);
+
+// Set once a stall is reported, until the next `pet`
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Record that the kernel is getting somewhere
+pub fn pet(progress: Progress) {
+    LAST_PET.store(trap::tick_count(), Ordering::Relaxed);
+    LAST_PROGRESS.store(progress as u8, Ordering::Relaxed);
+    REPORTED.store(false, Ordering::Relaxed);
+}
+
+/// Record a system call, `number` kept for the report
+pub fn pet_syscall(number: usize) {
+    LAST_SYSCALL.store(number as u64, Ordering::Relaxed);
+    pet(Progress::Syscall);
+}
+
+/// Called from the timer interrupt with the registers it interrupted:
+/// report a stall once the timeout has passed, and reset if asked to
+pub fn check(ctx: &TrapContext, from_user: bool) {
+    let timeout = TIMEOUT_SECS.load(Ordering::Relaxed);
+    if timeout == 0 || REPORTED.load(Ordering::Relaxed) {
+        return;
+    }
+    let progress = Progress::from_u8(LAST_PROGRESS.load(Ordering::Relaxed));
+    if progress == Progress::None {
+        return;
+    }
+    let stalled = trap::tick_count().saturating_sub(LAST_PET.load(Ordering::Relaxed));
+    if stalled < timeout * trap::TICK_HZ {
+        return;
+    }
+
+    REPORTED.store(true, Ordering::Relaxed);
+    crate::health::count(crate::health::Event::WatchdogStall);
+    let _ = report(&mut SbiConsole, ctx, from_user, progress, stalled / trap::TICK_HZ);
+    if RESET.load(Ordering::Relaxed) {
+        let _ = write!(SbiConsole, "[!] watchdog: resetting\r\n");
+        sbi::system_reset();
+    }
+}
+
+fn report(out: &mut impl Write, ctx: &TrapContext, from_user: bool, progress: Progress, secs: u64) -> fmt::Result {
+    write!(out, "\r\n[!] watchdog: no progress for {} s, last progress: {}", secs, progress.description())?;
+    if progress == Progress::Syscall {
+        write!(out, " ({})", LAST_SYSCALL.load(Ordering::Relaxed))?;
+    }
+    write!(out, "\r\n")?;
+
+    // The stuck code may hold the process table
+    let pid = crate::process::PROCESS_MANAGER.try_lock().map(|manager| manager.get_current_pid());
+    match (from_user, pid) {
+        (true, Some(pid)) => write!(out, "    in user program {} without a system call\r\n", pid)?,
+        (true, None) => write!(out, "    in a user program without a system call\r\n")?,
+        (false, Some(pid)) if pid != crate::process::INIT_PID => write!(out, "    in kernel code, serving program {}\r\n", pid)?,
+        (false, _) => write!(out, "    in kernel code\r\n")?,
+    }
+    write!(out, "    pc 0x{:016x}  ra 0x{:016x}  sp 0x{:016x}\r\n", ctx.sepc, ctx.x[1], ctx.x[2])?;
+    write!(out, "    tick {}, {} late ticks since boot\r\n",
+        trap::tick_count(), crate::health::events(crate::health::Event::LateTick))
+}
+
+/// Writes with the SBI console call, which takes no kernel locks
+struct SbiConsole;
+
+impl Write for SbiConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            sbi::console_putchar(byte as usize);
+        }
+        Ok(())
+    }
+}
+
+pub const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "kernel.watchdog.timeout_secs",
+        description: "Seconds without progress before the watchdog reports a stall (0: off)",
+        kind: Kind::Int { min: 0, max: 3600, get: get_timeout, set: set_timeout },
+    },
+    Tunable {
+        name: "kernel.watchdog.action",
+        description: "What the watchdog does after its report: nothing more, or reset the machine",
+        kind: Kind::Choice { choices: &["report", "reset"], get: get_action, set: set_action },
+    },
+];
+
+fn get_timeout() -> i64 {
+    TIMEOUT_SECS.load(Ordering::Relaxed) as i64
+}
+
+fn set_timeout(secs: i64) -> Result<(), &'static str> {
+    TIMEOUT_SECS.store(secs as u64, Ordering::Relaxed);
+    Ok(())
+}
+
+fn get_action() -> &'static str {
+    if RESET.load(Ordering::Relaxed) { "reset" } else { "report" }
+}
+
+fn set_action(action: &'static str) -> Result<(), &'static str> {
+    RESET.store(action == "reset", Ordering::Relaxed);
+    Ok(())
+}
@@ -0,0 +1,408 @@
+//! LRU sector cache between filesystems and a block device
+//!
+//! Recently used 512-byte sectors stay in memory, so the repeated superblock,
+//! group descriptor, inode table and indirect block reads a filesystem makes
+//! are served without a device round trip. In write-through mode writes go
+//! to the device at once and update the cached copy; in write-back mode they
+//! only mark the cached sector dirty, and it reaches the device when it is
+//! evicted, on `flush`, or once it has been dirty for a while if the owner
+//! calls `flush_expired` from time to time.
+//!
+//! Cached sectors can carry a CRC-32, taken when they are filled or written
+//! and checked before they are used or written back, so memory or DMA
+//! corruption of the cache is caught rather than passed on to the disk. A
+//! clean sector that fails the check is read again from the device; a dirty
+//! one cannot be recovered.
+//!
+//! `read_many` reads a range of sectors and hands each run of them missing
+//! from the cache to the device in one `read_sectors` call, so a device
+//! that can transfer several sectors per request, or keep several requests
+//! going, is asked for them together. `write_many` does the same for
+//! write-through writes.
+
+use crate::crc32::crc32;
+use crate::warn_println;
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// A device the cache reads and writes whole sectors on
+pub trait SectorDevice {
+    type Error;
+
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), Self::Error>;
+    fn write_sector(&mut self, sector: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), Self::Error>;
+
+    /// Read the whole sectors from `sector` that fill `buf`; devices that
+    /// can batch requests do so here
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), Self::Error> {
+        for (i, chunk) in buf.chunks_exact_mut(SECTOR_SIZE).enumerate() {
+            if let Ok(sector_buf) = chunk.try_into() {
+                self.read_sector(sector + i as u64, sector_buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the whole sectors in `buf` from `sector`
+    fn write_sectors(&mut self, sector: u64, buf: &[u8]) -> Result<(), Self::Error> {
+        for (i, chunk) in buf.chunks_exact(SECTOR_SIZE).enumerate() {
+            if let Ok(sector_buf) = chunk.try_into() {
+                self.write_sector(sector + i as u64, sector_buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// When writes reach the device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    WriteThrough,
+    WriteBack,
+}
+
+impl CacheMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheMode::WriteThrough => "write-through",
+            CacheMode::WriteBack => "write-back",
+        }
+    }
+}
+
+/// What to do about a cached sector that no longer matches its checksum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VerifyPolicy {
+    /// Keep no checksums
+    Off,
+    /// Count and report the mismatch, then carry on
+    Log,
+    /// Stop the system before bad data goes anywhere
+    Panic,
+}
+
+impl VerifyPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VerifyPolicy::Off => "off",
+            VerifyPolicy::Log => "log",
+            VerifyPolicy::Panic => "panic",
+        }
+    }
+}
+
+/// Counters since the cache was created
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Dirty sectors written to the device on eviction or flush
+    pub writebacks: u64,
+    /// Cached sectors found not to match their checksum
+    pub checksum_mismatches: u64,
+}
+
+struct Entry {
+    sector: u64,
+    valid: bool,
+    dirty: bool,
+    /// Timer ticks when the sector last went from clean to dirty
+    dirtied: u64,
+    /// Value of the cache clock when last used
+    last_used: u64,
+    /// CRC-32 of `data`, while checksums are on
+    crc: u32,
+    data: [u8; SECTOR_SIZE],
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry { sector: 0, valid: false, dirty: false, dirtied: 0, last_used: 0, crc: 0, data: [0; SECTOR_SIZE] };
+}
+
+/// A cache of up to `N` sectors
+pub struct BlockCache<const N: usize> {
+    entries: [Entry; N],
+    /// Entries in use; the rest stay empty
+    limit: usize,
+    mode: CacheMode,
+    verify: VerifyPolicy,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<const N: usize> BlockCache<N> {
+    pub const fn new(mode: CacheMode) -> Self {
+        BlockCache {
+            entries: [Entry::EMPTY; N],
+            limit: N,
+            mode,
+            verify: VerifyPolicy::Off,
+            clock: 0,
+            stats: CacheStats { hits: 0, misses: 0, writebacks: 0, checksum_mismatches: 0 },
+        }
+    }
+
+    pub fn verify_policy(&self) -> VerifyPolicy {
+        self.verify
+    }
+
+    /// Change what happens on checksum mismatches. Turning checksums on
+    /// takes them of the sectors already cached.
+    pub fn set_verify_policy(&mut self, policy: VerifyPolicy) {
+        if self.verify == VerifyPolicy::Off {
+            for entry in self.entries.iter_mut().filter(|e| e.valid) {
+                entry.crc = crc32(&entry.data);
+            }
+        }
+        self.verify = policy;
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    /// Change mode. Leaving write-back writes out dirty sectors first.
+    pub fn set_mode<D: SectorDevice>(&mut self, dev: &mut D, mode: CacheMode) -> Result<(), D::Error> {
+        if mode == CacheMode::WriteThrough {
+            self.flush(dev)?;
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Number of sectors waiting to be written back
+    pub fn dirty_count(&self) -> usize {
+        self.entries.iter().filter(|e| e.valid && e.dirty).count()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Sectors the cache holds at most, `capacity` or fewer
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Hold at most `limit` sectors, between 1 and `capacity`. Shrinking
+    /// writes back and drops the entries given up.
+    pub fn set_limit<D: SectorDevice>(&mut self, dev: &mut D, limit: usize) -> Result<(), D::Error> {
+        let limit = limit.clamp(1, N);
+        for index in limit..self.limit {
+            self.write_back(dev, index)?;
+            self.entries[index].valid = false;
+        }
+        self.limit = limit;
+        Ok(())
+    }
+
+    /// Read `sector`, from the cache if possible
+    pub fn read<D: SectorDevice>(&mut self, dev: &mut D, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), D::Error> {
+        let index = match self.find(sector) {
+            Some(index) => {
+                self.stats.hits += 1;
+                self.verify(dev, index)?;
+                index
+            }
+            None => {
+                self.stats.misses += 1;
+                let index = self.evict(dev)?;
+                let entry = &mut self.entries[index];
+                dev.read_sector(sector, &mut entry.data)?;
+                entry.sector = sector;
+                entry.valid = true;
+                entry.dirty = false;
+                self.seal(index);
+                index
+            }
+        };
+
+        self.touch(index);
+        buf.copy_from_slice(&self.entries[index].data);
+        Ok(())
+    }
+
+    /// Read the whole sectors from `sector` that fill `buf`, from the cache
+    /// where possible. Each run of sectors not cached is read with one
+    /// `read_sectors` call, then cached.
+    pub fn read_many<D: SectorDevice>(&mut self, dev: &mut D, sector: u64, buf: &mut [u8]) -> Result<(), D::Error> {
+        let count = buf.len() / SECTOR_SIZE;
+        let mut i = 0;
+        while i < count {
+            if let Some(index) = self.find(sector + i as u64) {
+                self.stats.hits += 1;
+                self.verify(dev, index)?;
+                self.touch(index);
+                buf[i * SECTOR_SIZE..(i + 1) * SECTOR_SIZE].copy_from_slice(&self.entries[index].data);
+                i += 1;
+                continue;
+            }
+
+            let end = (i + 1..count).find(|&j| self.find(sector + j as u64).is_some()).unwrap_or(count);
+            dev.read_sectors(sector + i as u64, &mut buf[i * SECTOR_SIZE..end * SECTOR_SIZE])?;
+            for j in i..end {
+                self.stats.misses += 1;
+                let index = self.evict(dev)?;
+                let entry = &mut self.entries[index];
+                entry.data.copy_from_slice(&buf[j * SECTOR_SIZE..(j + 1) * SECTOR_SIZE]);
+                entry.sector = sector + j as u64;
+                entry.valid = true;
+                entry.dirty = false;
+                self.seal(index);
+                self.touch(index);
+            }
+            i = end;
+        }
+        Ok(())
+    }
+
+    /// Write `sector` according to the cache mode
+    pub fn write<D: SectorDevice>(&mut self, dev: &mut D, sector: u64, buf: &[u8; SECTOR_SIZE]) -> Result<(), D::Error> {
+        if self.mode == CacheMode::WriteThrough {
+            dev.write_sector(sector, buf)?;
+        }
+        self.store(dev, sector, buf)
+    }
+
+    /// Write the whole sectors in `buf` from `sector` according to the
+    /// cache mode; in write-through mode with one `write_sectors` call
+    pub fn write_many<D: SectorDevice>(&mut self, dev: &mut D, sector: u64, buf: &[u8]) -> Result<(), D::Error> {
+        if self.mode == CacheMode::WriteThrough {
+            dev.write_sectors(sector, buf)?;
+        }
+        for (i, chunk) in buf.chunks_exact(SECTOR_SIZE).enumerate() {
+            self.store(dev, sector + i as u64, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Put a written sector in the cache, dirty in write-back mode
+    fn store<D: SectorDevice>(&mut self, dev: &mut D, sector: u64, buf: &[u8]) -> Result<(), D::Error> {
+        let index = match self.find(sector) {
+            Some(index) => index,
+            None => self.evict(dev)?,
+        };
+        let entry = &mut self.entries[index];
+        entry.data.copy_from_slice(buf);
+        entry.sector = sector;
+        entry.valid = true;
+        let dirty = self.mode == CacheMode::WriteBack;
+        if dirty && !entry.dirty {
+            entry.dirtied = crate::timer::ticks();
+        }
+        entry.dirty = dirty;
+        self.seal(index);
+        self.touch(index);
+        Ok(())
+    }
+
+    /// Write every dirty sector to the device
+    pub fn flush<D: SectorDevice>(&mut self, dev: &mut D) -> Result<(), D::Error> {
+        for index in 0..N {
+            self.write_back(dev, index)?;
+        }
+        Ok(())
+    }
+
+    /// Write the sectors that have been dirty for at least `age` timer
+    /// ticks; returns how many were written
+    pub fn flush_expired<D: SectorDevice>(&mut self, dev: &mut D, age: u64) -> Result<usize, D::Error> {
+        let now = crate::timer::ticks();
+        let mut written = 0;
+        for index in 0..N {
+            let entry = &self.entries[index];
+            if entry.valid && entry.dirty && now.wrapping_sub(entry.dirtied) >= age {
+                self.write_back(dev, index)?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Forget every cached sector, including unwritten ones; `flush` first
+    /// unless the device contents changed underneath the cache
+    pub fn invalidate(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.valid = false;
+            entry.dirty = false;
+        }
+    }
+
+    /// Forget the cached sectors `f` picks, including unwritten ones
+    pub fn invalidate_matching(&mut self, f: impl Fn(u64) -> bool) {
+        for entry in self.entries.iter_mut().filter(|e| e.valid && f(e.sector)) {
+            entry.valid = false;
+            entry.dirty = false;
+        }
+    }
+
+    fn find(&self, sector: u64) -> Option<usize> {
+        self.entries.iter().position(|e| e.valid && e.sector == sector)
+    }
+
+    fn touch(&mut self, index: usize) {
+        self.clock += 1;
+        self.entries[index].last_used = self.clock;
+    }
+
+    /// Free the least recently used entry, writing it back if dirty
+    fn evict<D: SectorDevice>(&mut self, dev: &mut D) -> Result<usize, D::Error> {
+        let index = match self.entries[..self.limit].iter().position(|e| !e.valid) {
+            Some(index) => index,
+            None => (0..self.limit).min_by_key(|&i| self.entries[i].last_used).unwrap_or(0),
+        };
+        self.write_back(dev, index)?;
+        self.entries[index].valid = false;
+        Ok(index)
+    }
+
+    /// Take the checksum of entry `index` after its data changed
+    fn seal(&mut self, index: usize) {
+        if self.verify != VerifyPolicy::Off {
+            let entry = &mut self.entries[index];
+            entry.crc = crc32(&entry.data);
+        }
+    }
+
+    /// Check entry `index` against its checksum. A clean sector that fails
+    /// is read again; a dirty one is kept as it is, damage and all.
+    fn verify<D: SectorDevice>(&mut self, dev: &mut D, index: usize) -> Result<(), D::Error> {
+        if self.verify == VerifyPolicy::Off {
+            return Ok(());
+        }
+        let entry = &mut self.entries[index];
+        if crc32(&entry.data) == entry.crc {
+            return Ok(());
+        }
+
+        self.stats.checksum_mismatches += 1;
+        if self.verify == VerifyPolicy::Panic {
+            panic!("Block cache: sector {:#x} does not match its checksum", entry.sector);
+        }
+        if entry.dirty {
+            warn_println!("Block cache: dirty sector {:#x} does not match its checksum, writing it anyway", entry.sector);
+        } else {
+            warn_println!("Block cache: sector {:#x} does not match its checksum, reading it again", entry.sector);
+            dev.read_sector(entry.sector, &mut entry.data)?;
+        }
+        self.seal(index);
+        Ok(())
+    }
+
+    fn write_back<D: SectorDevice>(&mut self, dev: &mut D, index: usize) -> Result<(), D::Error> {
+        if self.entries[index].valid && self.entries[index].dirty {
+            self.verify(dev, index)?;
+        }
+        let entry = &mut self.entries[index];
+        if entry.valid && entry.dirty {
+            dev.write_sector(entry.sector, &entry.data)?;
+            entry.dirty = false;
+            self.stats.writebacks += 1;
+        }
+        Ok(())
+    }
+}
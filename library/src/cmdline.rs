@@ -0,0 +1,40 @@
+//! Kernel command line arguments both the bootloader and the kernel know
+//!
+//! `secexec.*` arguments configure secure exec (see the kernel's
+//! `elf::verify`). The bootloader only takes them from the boot.cfg built
+//! into it, never from the config store, and `secexec.key=` is hidden
+//! wherever a command line is printed or logged.
+
+use core::fmt;
+
+/// Prefix of the secure exec arguments
+const SECEXEC_PREFIX: &str = "secexec";
+
+/// Argument giving the exec signing key, in hex
+pub const SECEXEC_KEY_ARG: &str = "secexec.key=";
+
+/// Whether `arg` configures secure exec
+pub fn is_secexec_arg(arg: &str) -> bool {
+    arg.strip_prefix(SECEXEC_PREFIX)
+        .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('='))
+}
+
+/// `arg` with the signing key in it hidden
+pub fn redact_arg(arg: &str) -> &str {
+    if arg.starts_with(SECEXEC_KEY_ARG) { "secexec.key=(hidden)" } else { arg }
+}
+
+/// A command line shown with the signing key hidden
+pub struct Redacted<'a>(pub &'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, arg) in self.0.split_whitespace().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(redact_arg(arg))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! On-disk format of the key-value config store
+//!
+//! The store is a file of `STORE_SIZE` bytes holding a commit record in its
+//! first sector and two slots after it, each big enough for the whole
+//! `key=value` text. An update writes the new text to the slot not in use
+//! and makes it reach the disk, then writes the commit record naming that
+//! slot. The record is one sector, which the disk writes whole or not at
+//! all, so the switch is atomic: power lost before it leaves the old text
+//! committed, power lost after it the new.
+//!
+//! Slots carry their own sequence number and checksum, so a record torn
+//! anyway, or naming a slot that no longer checks out, is recovered from
+//! at load time by taking the newest slot that is intact.
+//!
+//! The kernel writes the store; the bootloader only reads it.
+
+use crate::blockcache::SECTOR_SIZE;
+use crate::crc32::crc32;
+
+/// Where the store lives on the root filesystem
+pub const STORE_PATH: &str = "/.config";
+
+/// Sectors in each slot
+pub const SLOT_SECTORS: usize = 4;
+
+/// Bytes in each slot, header included
+pub const SLOT_SIZE: usize = SLOT_SECTORS * SECTOR_SIZE;
+
+/// Size of the store file: the record sector and two slots
+pub const STORE_SIZE: usize = SECTOR_SIZE + 2 * SLOT_SIZE;
+
+/// Bytes of slot header before the text
+const SLOT_HEADER_SIZE: usize = 16;
+
+/// Most bytes of `key=value` text the store holds
+pub const MAX_DATA: usize = SLOT_SIZE - SLOT_HEADER_SIZE;
+
+/// Longest key, and longest value
+pub const MAX_KEY_LEN: usize = 32;
+pub const MAX_VALUE_LEN: usize = 128;
+
+const RECORD_MAGIC: u32 = u32::from_le_bytes(*b"ELCR");
+const SLOT_MAGIC: u32 = u32::from_le_bytes(*b"ELCS");
+
+/// The commit record: which slot holds the committed text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    /// Sequence number of the committed slot
+    pub seq: u32,
+    pub slot: usize,
+}
+
+impl Record {
+    pub fn encode(&self) -> [u8; SECTOR_SIZE] {
+        let mut sector = [0u8; SECTOR_SIZE];
+        put_u32(&mut sector, 0, RECORD_MAGIC);
+        put_u32(&mut sector, 4, self.seq);
+        put_u32(&mut sector, 8, self.slot as u32);
+        let crc = crc32(&sector[..12]);
+        put_u32(&mut sector, 12, crc);
+        sector
+    }
+
+    /// The record in `sector`, or None if it is blank or torn
+    pub fn decode(sector: &[u8]) -> Option<Self> {
+        if sector.len() < 16 || get_u32(sector, 0) != RECORD_MAGIC || get_u32(sector, 12) != crc32(&sector[..12]) {
+            return None;
+        }
+        let slot = get_u32(sector, 8) as usize;
+        (slot < 2).then_some(Record { seq: get_u32(sector, 4), slot })
+    }
+}
+
+/// Sector of the store file where `slot` starts
+pub fn slot_sector(slot: usize) -> usize {
+    1 + slot * SLOT_SECTORS
+}
+
+/// Fill `out` with slot contents for `data` at sequence `seq`. False if the
+/// text does not fit.
+pub fn encode_slot(seq: u32, data: &[u8], out: &mut [u8; SLOT_SIZE]) -> bool {
+    if data.len() > MAX_DATA {
+        return false;
+    }
+    out.fill(0);
+    put_u32(out, 0, SLOT_MAGIC);
+    put_u32(out, 4, seq);
+    put_u32(out, 8, data.len() as u32);
+    put_u32(out, 12, crc32(data));
+    out[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + data.len()].copy_from_slice(data);
+    true
+}
+
+/// Sequence number and text of the slot in `slot`, if it is intact
+pub fn decode_slot(slot: &[u8]) -> Option<(u32, &[u8])> {
+    if slot.len() < SLOT_SIZE || get_u32(slot, 0) != SLOT_MAGIC {
+        return None;
+    }
+    let len = get_u32(slot, 8) as usize;
+    if len > MAX_DATA {
+        return None;
+    }
+    let data = &slot[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + len];
+    (crc32(data) == get_u32(slot, 12)).then_some((get_u32(slot, 4), data))
+}
+
+/// The committed contents of a store
+#[derive(Debug, Clone, Copy)]
+pub struct Committed<'a> {
+    pub seq: u32,
+    pub slot: usize,
+    pub data: &'a [u8],
+    /// The record was torn or named a damaged slot, and the newest intact
+    /// slot was taken instead; the record should be written again
+    pub recovered: bool,
+}
+
+/// Find the committed text in `image`, the whole store file. None if the
+/// store has never been written, or nothing in it survived.
+pub fn select(image: &[u8]) -> Option<Committed<'_>> {
+    if image.len() < STORE_SIZE {
+        return None;
+    }
+    let slot = |index: usize| {
+        let start = slot_sector(index) * SECTOR_SIZE;
+        decode_slot(&image[start..start + SLOT_SIZE])
+    };
+
+    if let Some(record) = Record::decode(&image[..SECTOR_SIZE]) {
+        if let Some((seq, data)) = slot(record.slot) {
+            if seq == record.seq {
+                return Some(Committed { seq, slot: record.slot, data, recovered: false });
+            }
+        }
+    }
+
+    // Sequence numbers only grow, so the newer slot was written last
+    (0..2)
+        .filter_map(|index| slot(index).map(|(seq, data)| Committed { seq, slot: index, data, recovered: true }))
+        .max_by_key(|committed| committed.seq)
+}
+
+/// Whether `key` may name an entry: letters, digits, '.', '_' and '-'
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN
+        && key.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+/// Whether `value` may be stored: one line of at most `MAX_VALUE_LEN` bytes
+pub fn is_valid_value(value: &str) -> bool {
+    value.len() <= MAX_VALUE_LEN && !value.contains('\n')
+}
+
+/// The (key, value) entries of store text
+pub fn entries(data: &[u8]) -> impl Iterator<Item = (&str, &str)> {
+    core::str::from_utf8(data).unwrap_or("").lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| is_valid_key(key))
+}
+
+/// The value of `key` in store text
+pub fn lookup<'a>(data: &'a [u8], key: &str) -> Option<&'a str> {
+    entries(data).find(|(name, _)| *name == key).map(|(_, value)| value)
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]])
+}
@@ -1,7 +1,12 @@
 // Console Management System for elinOS
-// Framebuffer-focused with minimal UART fallback
+// Output goes through the TTY layer to the UART and whatever sinks the
+// kernel registered, such as the framebuffer console; see `tty`
+// Status lines run from error, warn, notice and info down to debug and
+// trace. One global level decides which reach the console, and a subsystem
+// (virtio, ext2, fs, mm, net) can be given its own, set with `loglevel`
 
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
 use spin::Mutex;
 use lazy_static::lazy_static;
 use heapless::String;
@@ -11,36 +16,96 @@ use heapless::String;
 #[macro_export]
 macro_rules! console_print {
     ($($arg:tt)*) => {{
-        let console = $crate::console::CONSOLE_MANAGER.lock();
-        let _ = console.print(format_args!($($arg)*));
+        let _ = $crate::console::print_fmt(format_args!($($arg)*));
     }};
 }
 
+/// Print a line and pass it to the log hook; see `print_line`
 #[macro_export]
 macro_rules! console_println {
     () => {
-        $crate::console_print!("\r\n")
+        $crate::console::print_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::print_line(format_args!($($arg)*))
     };
-    ($($arg:tt)*) => {{
-        $crate::console_print!($($arg)*);
-        $crate::console_print!("\r\n");
-    }};
 }
 
+/// Print a status line with the `[o]` prefix; see `status_line`.
+/// Starting with `target: "virtio",` files it under that subsystem.
 #[macro_export]
-macro_rules! debug_print {
-    ($($arg:tt)*) => {{
-        // Always goes to UART for debugging
-        let mut uart = $crate::uart::UART.lock();
-        let _ = uart.write_fmt(format_args!($($arg)*));
-    }};
+macro_rules! ok_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Ok, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Ok, format_args!($($arg)*))
+    };
+}
+
+/// Print a status line with the `[x]` prefix; see `status_line`
+#[macro_export]
+macro_rules! err_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Err, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Err, format_args!($($arg)*))
+    };
+}
+
+/// Print a status line with the `[!]` prefix; see `status_line`
+#[macro_export]
+macro_rules! warn_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Warn, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Warn, format_args!($($arg)*))
+    };
 }
 
+/// Print a status line with the `[i]` prefix; see `status_line`
+#[macro_export]
+macro_rules! info_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Info, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Info, format_args!($($arg)*))
+    };
+}
+
+/// Print a status line with the `[d]` prefix, only when the log level of
+/// its subsystem is above the debug level; see `status_line_for`
 #[macro_export]
 macro_rules! debug_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Debug, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Debug, format_args!($($arg)*))
+    };
+}
+
+/// Print a status line with the `[t]` prefix, only when the log level of
+/// its subsystem is the trace level; see `status_line_for`
+#[macro_export]
+macro_rules! trace_println {
+    (target: $target:expr, $($arg:tt)*) => {
+        $crate::console::status_line_for($target, $crate::console::Status::Trace, format_args!($($arg)*))
+    };
+    ($($arg:tt)*) => {
+        $crate::console::status_line($crate::console::Status::Trace, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! debug_print {
     ($($arg:tt)*) => {{
-        $crate::debug_print!($($arg)*);
-        $crate::debug_print!("\r\n");
+        // Always goes to UART for debugging
+        let mut uart = $crate::uart::UART.lock();
+        let _ = uart.write_fmt(format_args!($($arg)*));
     }};
 }
 
@@ -48,20 +113,230 @@ macro_rules! debug_println {
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OutputDevice {
-    Framebuffer,   // Primary: Text/graphics output (can be redirected to UART in QEMU)
+    Framebuffer,   // Primary: every TTY sink, the UART and the framebuffer console
     DebugUart,     // Secondary: Simple UART for debugging only
 }
 
+/// Characters the console can display
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleEncoding {
+    Utf8,   // Terminal renders Unicode (emoji, box drawing)
+    Ascii,  // Plain serial terminal: non-ASCII output is downgraded
+}
+
+impl ConsoleEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConsoleEncoding::Utf8 => "utf8",
+            ConsoleEncoding::Ascii => "ascii",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "utf8" | "utf-8" => Some(ConsoleEncoding::Utf8),
+            "ascii" => Some(ConsoleEncoding::Ascii),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of status line, printed as a short prefix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Ok,
+    Err,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Status {
+    /// Prefix for this status. Plain ASCII so it reads the same on every console.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Status::Ok => "[o]",
+            Status::Err => "[x]",
+            Status::Warn => "[!]",
+            Status::Info => "[i]",
+            Status::Debug => "[d]",
+            Status::Trace => "[t]",
+        }
+    }
+
+    /// Severity as a printk level: lower is more severe
+    pub fn level(&self) -> u8 {
+        match self {
+            Status::Err => 3,
+            Status::Warn => 4,
+            Status::Ok => 5,
+            Status::Info => 6,
+            Status::Debug => 7,
+            Status::Trace => 8,
+        }
+    }
+
+    /// Whether lines of this status are printed at the current log level
+    pub fn enabled(&self) -> bool {
+        self.level() < log_level()
+    }
+
+    /// Whether lines of this status from subsystem `target` are printed
+    pub fn enabled_for(&self, target: &str) -> bool {
+        self.level() < target_log_level(target)
+    }
+
+    /// Debug and trace lines are only kept when they are printed
+    fn verbose(&self) -> bool {
+        matches!(self, Status::Debug | Status::Trace)
+    }
+}
+
+/// Highest log level, at which every status line down to trace is printed
+pub const MAX_LOG_LEVEL: u8 = 9;
+
+/// Log level at boot: everything up to info, no debug or trace lines
+pub const DEFAULT_LOG_LEVEL: u8 = 7;
+
+/// Status lines print when their level is below this, as with the Linux
+/// console log level
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(DEFAULT_LOG_LEVEL);
+
+pub fn log_level() -> u8 {
+    LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Print only status lines of a level below `level`, at most `MAX_LOG_LEVEL`
+pub fn set_log_level(level: u8) {
+    LOG_LEVEL.store(level.min(MAX_LOG_LEVEL), Ordering::Relaxed);
+}
+
+/// Names for log levels, each the level at which lines of that kind and
+/// everything more severe are printed
+pub const LEVEL_NAMES: &[(&str, u8)] = &[
+    ("quiet", 1),
+    ("error", 4),
+    ("warn", 5),
+    ("notice", 6),
+    ("info", 7),
+    ("debug", 8),
+    ("trace", 9),
+];
+
+/// Parse a log level given by name or number
+pub fn parse_log_level(text: &str) -> Option<u8> {
+    LEVEL_NAMES.iter()
+        .find(|(name, _)| *name == text)
+        .map(|(_, level)| *level)
+        .or_else(|| text.parse::<u8>().ok().filter(|&level| level <= MAX_LOG_LEVEL))
+}
+
+/// Name of the most verbose level `level` lets through
+pub fn log_level_name(level: u8) -> &'static str {
+    LEVEL_NAMES.iter()
+        .rev()
+        .find(|(_, at)| *at <= level)
+        .map_or("off", |(name, _)| *name)
+}
+
+/// Subsystems whose lines can have a log level of their own, given as the
+/// `target` of the status macros
+pub const SUBSYSTEMS: &[&str] = &["virtio", "ext2", "fs", "mm", "net"];
+
+/// Stored for a subsystem that follows the global log level
+const FOLLOW_GLOBAL: u8 = u8::MAX;
+
+/// Log level of each of `SUBSYSTEMS`, by position
+static SUBSYSTEM_LEVELS: [AtomicU8; SUBSYSTEMS.len()] = [const { AtomicU8::new(FOLLOW_GLOBAL) }; SUBSYSTEMS.len()];
+
+fn subsystem_index(name: &str) -> Option<usize> {
+    SUBSYSTEMS.iter().position(|&subsystem| subsystem == name)
+}
+
+/// Log level set for subsystem `name`, None when it follows the global one
+pub fn subsystem_log_level(name: &str) -> Option<u8> {
+    let level = SUBSYSTEM_LEVELS[subsystem_index(name)?].load(Ordering::Relaxed);
+    (level != FOLLOW_GLOBAL).then_some(level)
+}
+
+/// Give subsystem `name` a log level of its own, or with None have it
+/// follow the global one again. False if there is no such subsystem.
+pub fn set_subsystem_log_level(name: &str, level: Option<u8>) -> bool {
+    match subsystem_index(name) {
+        Some(index) => {
+            let level = level.map_or(FOLLOW_GLOBAL, |level| level.min(MAX_LOG_LEVEL));
+            SUBSYSTEM_LEVELS[index].store(level, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Log level that applies to lines from `target`; an unknown target
+/// follows the global level
+pub fn target_log_level(target: &str) -> u8 {
+    subsystem_log_level(target).unwrap_or_else(log_level)
+}
+
+/// ASCII stand-ins for the Unicode markers found in console output
+const ASCII_REPLACEMENTS: &[(char, &str)] = &[
+    ('\u{2705}', "[o]"),  // white heavy check mark
+    ('\u{2714}', "[o]"),  // heavy check mark
+    ('\u{2713}', "v"),    // check mark
+    ('\u{274C}', "[x]"),  // cross mark
+    ('\u{2717}', "x"),    // ballot x
+    ('\u{26A0}', "[!]"),  // warning sign
+    ('\u{2139}', "[i]"),  // information source
+    ('\u{2753}', "[?]"),  // question mark ornament
+    ('\u{2500}', "-"),    // box drawings light horizontal
+    ('\u{2502}', "|"),    // box drawings light vertical
+    ('\u{2192}', "->"),   // rightwards arrow
+    ('\u{2022}', "*"),    // bullet
+    ('\u{FE0F}', ""),     // emoji presentation selector
+];
+
+/// Writer adapter that replaces non-ASCII characters for ASCII-only consoles.
+/// Known markers get an equivalent, other symbols become '?'.
+pub struct AsciiFilter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> AsciiFilter<W> {
+    pub fn new(inner: W) -> Self {
+        AsciiFilter { inner }
+    }
+}
+
+impl<W: Write> Write for AsciiFilter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(pos) = rest.find(|c: char| !c.is_ascii()) {
+            self.inner.write_str(&rest[..pos])?;
+            let ch = rest[pos..].chars().next().unwrap_or('?');
+            let replacement = ASCII_REPLACEMENTS.iter()
+                .find(|(c, _)| *c == ch)
+                .map(|(_, r)| *r)
+                .unwrap_or("?");
+            self.inner.write_str(replacement)?;
+            rest = &rest[pos + ch.len_utf8()..];
+        }
+        self.inner.write_str(rest)
+    }
+}
+
 // === MINIMAL CONSOLE MANAGER ===
 
 pub struct ConsoleManager {
     primary_device: OutputDevice,
+    encoding: ConsoleEncoding,
 }
 
 impl ConsoleManager {
     pub const fn new() -> Self {
         ConsoleManager {
             primary_device: OutputDevice::Framebuffer,
+            encoding: ConsoleEncoding::Utf8,
         }
     }
 
@@ -72,35 +347,20 @@ impl ConsoleManager {
     }
 
     pub fn print(&self, args: fmt::Arguments) -> fmt::Result {
-        match self.primary_device {
-            OutputDevice::Framebuffer => {
-                // Output to both UART and framebuffer for full visibility
-                let mut uart = crate::uart::UART.lock();
-                let uart_result = uart.write_fmt(args);
-                
-                // Framebuffer bridge temporarily disabled to fix hanging issue
-                // TODO: Re-enable once recursion protection is working
-                // #[cfg(feature = "framebuffer-bridge")]
-                // {
-                //     let mut buffer = String::<1024>::new();
-                //     if buffer.write_fmt(args).is_ok() {
-                //         // Forward to kernel graphics system via bridge function
-                //         forward_to_framebuffer(&buffer);
-                //     }
-                // }
-                
-                uart_result
-            }
-            OutputDevice::DebugUart => {
-                let mut uart = crate::uart::UART.lock();
-                uart.write_fmt(args)
-            }
-        }
+        print_with(self.primary_device, self.encoding, args)
     }
 
     pub fn set_primary_device(&mut self, device: OutputDevice) {
         self.primary_device = device;
     }
+
+    pub fn encoding(&self) -> ConsoleEncoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: ConsoleEncoding) {
+        self.encoding = encoding;
+    }
 }
 
 // Global console manager instance
@@ -117,22 +377,100 @@ pub fn init_console() -> Result<(), &'static str> {
 
 // === HIGH-LEVEL FUNCTIONS ===
 
+/// Print to the primary device in the console's encoding. The console
+/// manager is not held while printing, so a TTY sink may print in turn.
+pub fn print_fmt(args: fmt::Arguments) -> fmt::Result {
+    let (device, encoding) = {
+        let console = CONSOLE_MANAGER.lock();
+        (console.primary_device, console.encoding)
+    };
+    print_with(device, encoding, args)
+}
+
+fn print_with(device: OutputDevice, encoding: ConsoleEncoding, args: fmt::Arguments) -> fmt::Result {
+    match device {
+        OutputDevice::Framebuffer => match encoding {
+            ConsoleEncoding::Utf8 => crate::tty::write_fmt(args),
+            ConsoleEncoding::Ascii => AsciiFilter::new(crate::tty::Tty).write_fmt(args),
+        },
+        OutputDevice::DebugUart => {
+            let mut uart = crate::uart::UART.lock();
+            match encoding {
+                ConsoleEncoding::Utf8 => uart.write_fmt(args),
+                ConsoleEncoding::Ascii => AsciiFilter::new(&mut *uart).write_fmt(args),
+            }
+        }
+    }
+}
+
+/// What the console can currently display
+pub fn console_encoding() -> ConsoleEncoding {
+    CONSOLE_MANAGER.lock().encoding()
+}
+
+/// Select UTF-8 or ASCII-only console output
+pub fn set_console_encoding(encoding: ConsoleEncoding) {
+    CONSOLE_MANAGER.lock().set_encoding(encoding);
+}
+
+/// Called with every line printed by `console_println!` and every status
+/// line, printed or not; plain lines come with no status
+static LOG_HOOK: Mutex<Option<fn(Option<Status>, fmt::Arguments)>> = Mutex::new(None);
+
+/// Have `hook` see every console line, e.g. to keep a log of them
+pub fn set_log_hook(hook: fn(Option<Status>, fmt::Arguments)) {
+    *LOG_HOOK.lock() = Some(hook);
+}
+
+fn call_log_hook(status: Option<Status>, args: fmt::Arguments) {
+    // A line printed while the hook is being set is not seen
+    let hook = LOG_HOOK.try_lock().and_then(|hook| *hook);
+    if let Some(hook) = hook {
+        hook(status, args);
+    }
+}
+
+/// Print a line and pass it to the log hook, unless it is empty
+pub fn print_line(args: fmt::Arguments) {
+    let _ = print_fmt(format_args!("{}\r\n", args));
+    if args.as_str() != Some("") {
+        call_log_hook(None, args);
+    }
+}
+
+/// Print a status line if the log level allows, and pass it to the log
+/// hook either way. Debug and trace lines that are not printed are dropped,
+/// so turning them off also keeps them out of the log.
+pub fn status_line(status: Status, args: fmt::Arguments) {
+    print_status(status.enabled(), status, args);
+}
+
+/// Like `status_line`, at the log level of subsystem `target`
+pub fn status_line_for(target: &str, status: Status, args: fmt::Arguments) {
+    print_status(status.enabled_for(target), status, args);
+}
+
+fn print_status(enabled: bool, status: Status, args: fmt::Arguments) {
+    if enabled {
+        let _ = print_fmt(format_args!("{} {}\r\n", status.prefix(), args));
+    } else if status.verbose() {
+        return;
+    }
+    call_log_hook(Some(status), args);
+}
+
 pub fn print(s: &str) {
-    let console = CONSOLE_MANAGER.lock();
-    let _ = console.print(format_args!("{}", s));
+    let _ = print_fmt(format_args!("{}", s));
 }
 
 pub fn println(s: &str) {
-    let console = CONSOLE_MANAGER.lock();
-    let _ = console.print(format_args!("{}\r\n", s));
+    let _ = print_fmt(format_args!("{}\r\n", s));
 }
 
 pub fn print_to_device(device: OutputDevice, s: &str) {
-    // For simplified approach, just use debug output for now
     match device {
         OutputDevice::Framebuffer => {
-            let console = CONSOLE_MANAGER.lock();
-            let _ = console.print(format_args!("{}", s));
+            let _ = print_fmt(format_args!("{}", s));
         }
         OutputDevice::DebugUart => {
             let mut uart = crate::uart::UART.lock();
@@ -140,18 +478,3 @@ pub fn print_to_device(device: OutputDevice, s: &str) {
         }
     }
 }
-
-// === KERNEL BRIDGE FUNCTIONS ===
-
-#[cfg(feature = "framebuffer-bridge")]
-extern "C" {
-    fn kernel_console_to_framebuffer(text: *const u8, len: usize);
-}
-
-/// Forward text to kernel framebuffer (safe wrapper)
-#[cfg(feature = "framebuffer-bridge")]
-fn forward_to_framebuffer(text: &str) {
-    unsafe {
-        kernel_console_to_framebuffer(text.as_ptr(), text.len());
-    }
-} 
\ No newline at end of file
@@ -0,0 +1,186 @@
+//! AES-128 block cipher and CTR mode
+//!
+//! The S-box is computed on the fly (GF(2^8) inversion followed by the
+//! affine transform) instead of using a lookup table, so no memory access
+//! depends on secret data. This is slow compared to table AES, but the
+//! kernel only encrypts small amounts of data.
+
+use super::{BlockCipher, StreamCipher, zeroize};
+
+const ROUNDS: usize = 10;
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+/// Multiply by x in GF(2^8) without branching on the input
+#[inline]
+fn xtime(a: u8) -> u8 {
+    (a << 1) ^ (0x1b & 0u8.wrapping_sub(a >> 7))
+}
+
+/// Constant-time multiplication in GF(2^8)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut result = 0u8;
+    for _ in 0..8 {
+        result ^= a & 0u8.wrapping_sub(b & 1);
+        a = xtime(a);
+        b >>= 1;
+    }
+    result
+}
+
+/// Multiplicative inverse as a^254 (maps 0 to 0 as AES requires)
+fn gf_inv(a: u8) -> u8 {
+    let a2 = gf_mul(a, a);
+    let a3 = gf_mul(a2, a);
+    let a6 = gf_mul(a3, a3);
+    let a12 = gf_mul(a6, a6);
+    let a15 = gf_mul(a12, a3);
+    let a30 = gf_mul(a15, a15);
+    let a60 = gf_mul(a30, a30);
+    let a120 = gf_mul(a60, a60);
+    let a126 = gf_mul(a120, a6);
+    let a252 = gf_mul(a126, a126);
+    gf_mul(a252, a2)
+}
+
+fn sub_byte(a: u8) -> u8 {
+    let x = gf_inv(a);
+    x ^ x.rotate_left(1) ^ x.rotate_left(2) ^ x.rotate_left(3) ^ x.rotate_left(4) ^ 0x63
+}
+
+fn sub_bytes(state: &mut [u8; 16]) {
+    for byte in state.iter_mut() {
+        *byte = sub_byte(*byte);
+    }
+}
+
+fn shift_rows(state: &mut [u8; 16]) {
+    let s = *state;
+    for col in 0..4 {
+        for row in 0..4 {
+            state[col * 4 + row] = s[((col + row) % 4) * 4 + row];
+        }
+    }
+}
+
+fn mix_columns(state: &mut [u8; 16]) {
+    for col in 0..4 {
+        let c = &mut state[col * 4..col * 4 + 4];
+        let (a0, a1, a2, a3) = (c[0], c[1], c[2], c[3]);
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        c[0] ^= all ^ xtime(a0 ^ a1);
+        c[1] ^= all ^ xtime(a1 ^ a2);
+        c[2] ^= all ^ xtime(a2 ^ a3);
+        c[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+fn add_round_key(state: &mut [u8; 16], key: &[u8; 16]) {
+    for (s, k) in state.iter_mut().zip(key.iter()) {
+        *s ^= k;
+    }
+}
+
+/// AES-128 with an expanded key schedule
+pub struct Aes128 {
+    round_keys: [[u8; 16]; ROUNDS + 1],
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; 16]) -> Self {
+        let mut round_keys = [[0u8; 16]; ROUNDS + 1];
+        round_keys[0] = *key;
+
+        for round in 1..=ROUNDS {
+            let prev = round_keys[round - 1];
+            let mut temp = [prev[13], prev[14], prev[15], prev[12]];
+            for byte in temp.iter_mut() {
+                *byte = sub_byte(*byte);
+            }
+            temp[0] ^= RCON[round - 1];
+
+            let mut next = [0u8; 16];
+            for i in 0..4 {
+                next[i] = prev[i] ^ temp[i];
+            }
+            for i in 4..16 {
+                next[i] = prev[i] ^ next[i - 4];
+            }
+            round_keys[round] = next;
+        }
+
+        Aes128 { round_keys }
+    }
+}
+
+impl BlockCipher for Aes128 {
+    fn encrypt_block(&self, block: &mut [u8; 16]) {
+        add_round_key(block, &self.round_keys[0]);
+        for round in 1..ROUNDS {
+            sub_bytes(block);
+            shift_rows(block);
+            mix_columns(block);
+            add_round_key(block, &self.round_keys[round]);
+        }
+        sub_bytes(block);
+        shift_rows(block);
+        add_round_key(block, &self.round_keys[ROUNDS]);
+    }
+}
+
+impl Drop for Aes128 {
+    fn drop(&mut self) {
+        for key in self.round_keys.iter_mut() {
+            zeroize(key);
+        }
+    }
+}
+
+/// AES-128 in counter mode with a 128-bit big-endian counter block
+pub struct Aes128Ctr {
+    cipher: Aes128,
+    counter: [u8; 16],
+    keystream: [u8; 16],
+    keystream_pos: usize,
+}
+
+impl Aes128Ctr {
+    /// Create a CTR stream from a key and initial counter block (nonce || counter)
+    pub fn new(key: &[u8; 16], iv: &[u8; 16]) -> Self {
+        Aes128Ctr {
+            cipher: Aes128::new(key),
+            counter: *iv,
+            keystream: [0; 16],
+            keystream_pos: 16,
+        }
+    }
+
+    fn increment_counter(&mut self) {
+        let mut carry = 1u16;
+        for byte in self.counter.iter_mut().rev() {
+            let sum = *byte as u16 + carry;
+            *byte = sum as u8;
+            carry = sum >> 8;
+        }
+    }
+}
+
+impl StreamCipher for Aes128Ctr {
+    fn apply_keystream(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            if self.keystream_pos == 16 {
+                self.keystream = self.counter;
+                self.cipher.encrypt_block(&mut self.keystream);
+                self.increment_counter();
+                self.keystream_pos = 0;
+            }
+            *byte ^= self.keystream[self.keystream_pos];
+            self.keystream_pos += 1;
+        }
+    }
+}
+
+impl Drop for Aes128Ctr {
+    fn drop(&mut self) {
+        zeroize(&mut self.keystream);
+    }
+}
@@ -0,0 +1,61 @@
+//! HMAC-SHA256 (RFC 2104)
+
+use super::{Digest, Mac, zeroize};
+use super::sha256::{Sha256, SHA256_BLOCK_SIZE, SHA256_OUTPUT_SIZE};
+
+pub struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        // Keys longer than a block are hashed first
+        let mut block_key = [0u8; SHA256_BLOCK_SIZE];
+        if key.len() > SHA256_BLOCK_SIZE {
+            block_key[..SHA256_OUTPUT_SIZE].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut ipad = [0x36u8; SHA256_BLOCK_SIZE];
+        let mut opad = [0x5cu8; SHA256_BLOCK_SIZE];
+        for i in 0..SHA256_BLOCK_SIZE {
+            ipad[i] ^= block_key[i];
+            opad[i] ^= block_key[i];
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&ipad);
+        let mut outer = Sha256::new();
+        outer.update(&opad);
+
+        zeroize(&mut block_key);
+        zeroize(&mut ipad);
+        zeroize(&mut opad);
+
+        HmacSha256 { inner, outer }
+    }
+
+    /// Compute the MAC of a complete message in one call
+    pub fn mac(key: &[u8], data: &[u8]) -> [u8; SHA256_OUTPUT_SIZE] {
+        let mut hmac = HmacSha256::new(key);
+        Mac::update(&mut hmac, data);
+        Mac::finalize(hmac)
+    }
+}
+
+impl Mac for HmacSha256 {
+    type Output = [u8; SHA256_OUTPUT_SIZE];
+
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finalize(self) -> Self::Output {
+        let inner_hash = self.inner.finalize();
+        let mut outer = self.outer;
+        outer.update(&inner_hash);
+        outer.finalize()
+    }
+}
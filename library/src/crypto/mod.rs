@@ -0,0 +1,71 @@
+//! Crypto Primitives
+//!
+//! Small software implementations of AES-128-CTR, SHA-256 and HMAC-SHA256
+//! shared between bootloader and kernel. Everything here avoids secret
+//! dependent branches and table lookups so timing does not leak key material.
+
+pub mod aes;
+pub mod sha256;
+pub mod hmac;
+pub mod selftest;
+
+pub use aes::{Aes128, Aes128Ctr};
+pub use sha256::Sha256;
+pub use hmac::HmacSha256;
+
+/// Block cipher operating on fixed 16-byte blocks
+pub trait BlockCipher {
+    const BLOCK_SIZE: usize = 16;
+
+    /// Encrypt a single block in place
+    fn encrypt_block(&self, block: &mut [u8; 16]);
+}
+
+/// Cipher producing a keystream that is XORed over the data
+pub trait StreamCipher {
+    /// Encrypt or decrypt `data` in place (the operation is symmetric)
+    fn apply_keystream(&mut self, data: &mut [u8]);
+}
+
+/// Incremental hash function
+pub trait Digest {
+    const OUTPUT_SIZE: usize;
+    type Output: AsRef<[u8]>;
+
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Self::Output;
+}
+
+/// Keyed message authentication code
+pub trait Mac: Sized {
+    type Output: AsRef<[u8]>;
+
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Self::Output;
+
+    /// Compare the computed tag against `tag` in constant time
+    fn verify(self, tag: &[u8]) -> bool {
+        constant_time_eq(self.finalize().as_ref(), tag)
+    }
+}
+
+/// Compare two byte slices without early exit on the first mismatch
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    core::hint::black_box(diff) == 0
+}
+
+/// Overwrite sensitive data with zeros in a way the optimizer keeps
+pub fn zeroize(data: &mut [u8]) {
+    for byte in data.iter_mut() {
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
@@ -0,0 +1,102 @@
+//! Known-answer tests for the crypto primitives
+//!
+//! Published test vectors for each primitive, so a regression shows up as
+//! a failed check instead of as signatures that no longer verify. The
+//! kernel runs them with `selftest`.
+//!
+//! - AES-128: FIPS-197 appendix C.1
+//! - AES-128-CTR: NIST SP 800-38A F.5.1
+//! - SHA-256: FIPS 180-4 ("abc" and the empty message)
+//! - HMAC-SHA256: RFC 4231 test cases 1-4 and 6
+
+use heapless::Vec;
+use super::{Aes128, Aes128Ctr, BlockCipher, Digest, HmacSha256, Mac, Sha256, StreamCipher};
+
+/// Most checks `run` makes
+pub const MAX_CHECKS: usize = 16;
+
+/// Outcome of one known-answer test
+#[derive(Debug, Clone, Copy)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+}
+
+/// The bytes of a hex string of `N` bytes
+fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = core::str::from_utf8(pair).unwrap_or("");
+        *byte = u8::from_str_radix(digits, 16).unwrap_or(0);
+    }
+    bytes
+}
+
+fn aes128_block() -> bool {
+    let cipher = Aes128::new(&unhex("000102030405060708090a0b0c0d0e0f"));
+    let mut block = unhex("00112233445566778899aabbccddeeff");
+    cipher.encrypt_block(&mut block);
+    block == unhex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a")
+}
+
+fn aes128_ctr() -> bool {
+    let key = unhex("2b7e151628aed2a6abf7158809cf4f3c");
+    let counter = unhex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+    let mut data: [u8; 64] = unhex(
+        "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+         30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710");
+    let expected: [u8; 64] = unhex(
+        "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff\
+         5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee");
+
+    // In uneven pieces, so keystream carried between calls is covered too
+    let mut ctr = Aes128Ctr::new(&key, &counter);
+    let (first, rest) = data.split_at_mut(7);
+    ctr.apply_keystream(first);
+    ctr.apply_keystream(rest);
+    data == expected
+}
+
+fn sha256(message: &[u8], expected: &str) -> bool {
+    // Fed in two pieces, to cover `update` across calls
+    let mut hash = Sha256::new();
+    let (first, rest) = message.split_at(message.len() / 2);
+    hash.update(first);
+    hash.update(rest);
+    hash.finalize() == unhex::<32>(expected) && Sha256::digest(message) == unhex::<32>(expected)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8], expected: &str) -> bool {
+    let mut mac = HmacSha256::new(key);
+    mac.update(data);
+    mac.verify(&unhex::<32>(expected)) && HmacSha256::mac(key, data) == unhex::<32>(expected)
+}
+
+/// Run every known-answer test
+pub fn run() -> Vec<Check, MAX_CHECKS> {
+    let key_25: [u8; 25] = core::array::from_fn(|i| i as u8 + 1);
+    let checks = [
+        ("AES-128 (FIPS-197 C.1)", aes128_block()),
+        ("AES-128-CTR (SP 800-38A F.5.1)", aes128_ctr()),
+        ("SHA-256 \"abc\" (FIPS 180-4)",
+            sha256(b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")),
+        ("SHA-256 empty message",
+            sha256(b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")),
+        ("HMAC-SHA256 (RFC 4231 case 1)",
+            hmac_sha256(&[0x0b; 20], b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")),
+        ("HMAC-SHA256 (RFC 4231 case 2)",
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")),
+        ("HMAC-SHA256 (RFC 4231 case 3)",
+            hmac_sha256(&[0xaa; 20], &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe")),
+        ("HMAC-SHA256 (RFC 4231 case 4)",
+            hmac_sha256(&key_25, &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b")),
+        ("HMAC-SHA256 (RFC 4231 case 6)",
+            hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54")),
+    ];
+    checks.iter().map(|&(name, passed)| Check { name, passed }).collect()
+}
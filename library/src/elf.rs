@@ -45,6 +45,19 @@ pub const EM_RISCV: u16 = 243; // RISC-V
 pub const ET_EXEC: u16 = 2; // Executable file
 pub const ET_DYN: u16 = 3;  // Shared object file
 pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+
+// Dynamic section tags
+pub const DT_NULL: i64 = 0;
+pub const DT_NEEDED: i64 = 1;
+pub const DT_PLTRELSZ: i64 = 2;
+pub const DT_RELA: i64 = 7;
+pub const DT_RELASZ: i64 = 8;
+pub const DT_RELAENT: i64 = 9;
+
+// RISC-V relocation types
+pub const R_RISCV_NONE: u32 = 0;
+pub const R_RISCV_RELATIVE: u32 = 3;
 
 // Program header flags
 pub const PF_X: u32 = 1; // Execute
@@ -0,0 +1,5 @@
+//! ext2 on-disk format and a read-only reader, shared by the bootloader and
+//! kernel
+
+pub mod structures;
+pub mod reader;
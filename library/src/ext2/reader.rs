@@ -0,0 +1,238 @@
+//! Read-only ext2 reader
+//!
+//! Path lookup, directory listing and streaming file reads through direct,
+//! indirect, double- and triple-indirect blocks, on top of anything that can
+//! read 512-byte sectors. The reader itself caches nothing; small metadata
+//! reads are cheap only with a `blockcache` in front of the device.
+
+use core::mem::size_of;
+use super::structures::*;
+
+const MAX_BLOCK_SIZE: usize = 4096;
+
+/// Longest path component
+const MAX_NAME_LEN: usize = 255;
+
+/// A disk the reader can read sectors from
+pub trait SectorRead {
+    fn read_sector(&self, sector: u64, buf: &mut [u8; SECTOR_SIZE]) -> Result<(), &'static str>;
+}
+
+/// An inode, as far as reading goes
+pub struct Inode {
+    pub mode: u16,
+    pub size: u64,
+    flags: u32,
+    block: [u32; 15],
+}
+
+impl Inode {
+    pub fn is_dir(&self) -> bool {
+        self.mode & EXT2_S_IFMT == EXT2_S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & EXT2_S_IFMT == EXT2_S_IFREG
+    }
+}
+
+/// A mounted ext2 filesystem
+pub struct Ext2Reader<D: SectorRead> {
+    disk: D,
+    block_size: usize,
+    inodes_per_group: u32,
+    inode_size: usize,
+    group_desc_block: u64,
+}
+
+/// Reinterpret the start of `bytes` as an on-disk structure
+fn from_bytes<T: Copy>(bytes: &[u8]) -> T {
+    assert!(bytes.len() >= size_of::<T>());
+    unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+impl<D: SectorRead> Ext2Reader<D> {
+    /// Read the superblock and check this is an ext2 filesystem we can read
+    pub fn mount(disk: D) -> Result<Self, &'static str> {
+        let mut reader = Ext2Reader { disk, block_size: 0, inodes_per_group: 0, inode_size: 0, group_desc_block: 0 };
+
+        let mut raw = [0u8; size_of::<Ext2Superblock>()];
+        reader.read_bytes(EXT2_SUPERBLOCK_OFFSET as u64, &mut raw)?;
+        let sb: Ext2Superblock = from_bytes(&raw);
+
+        if sb.s_magic != EXT2_MAGIC {
+            return Err("No ext2 filesystem on disk");
+        }
+        if sb.s_feature_incompat & EXT4_FEATURE_INCOMPAT_EXTENTS != 0 {
+            return Err("Filesystem uses extents (ext4), not supported here");
+        }
+
+        let block_size = 1024usize << sb.s_log_block_size;
+        if block_size > MAX_BLOCK_SIZE {
+            return Err("Unsupported block size");
+        }
+
+        reader.block_size = block_size;
+        reader.inodes_per_group = sb.s_inodes_per_group;
+        reader.inode_size = if sb.s_rev_level == 0 { 128 } else { sb.s_inode_size as usize };
+        reader.group_desc_block = sb.s_first_data_block as u64 + 1;
+        Ok(reader)
+    }
+
+    /// Read `buf.len()` bytes starting at byte `offset` of the disk
+    fn read_bytes(&self, offset: u64, buf: &mut [u8]) -> Result<(), &'static str> {
+        let mut sector_buf = [0u8; SECTOR_SIZE];
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let sector = pos / SECTOR_SIZE as u64;
+            let within = (pos % SECTOR_SIZE as u64) as usize;
+            let count = (SECTOR_SIZE - within).min(buf.len() - done);
+            self.disk.read_sector(sector, &mut sector_buf)?;
+            buf[done..done + count].copy_from_slice(&sector_buf[within..within + count]);
+            done += count;
+        }
+        Ok(())
+    }
+
+    fn read_block(&self, block: u32, buf: &mut [u8]) -> Result<(), &'static str> {
+        self.read_bytes(block as u64 * self.block_size as u64, &mut buf[..self.block_size])
+    }
+
+    /// Read inode `number`
+    pub fn read_inode(&self, number: u32) -> Result<Inode, &'static str> {
+        if number == 0 || self.inodes_per_group == 0 {
+            return Err("Bad inode number");
+        }
+        let group = (number - 1) / self.inodes_per_group;
+        let index = (number - 1) % self.inodes_per_group;
+
+        let mut raw_desc = [0u8; EXT2_GROUP_DESC_SIZE];
+        let desc_offset = self.group_desc_block * self.block_size as u64 + group as u64 * EXT2_GROUP_DESC_SIZE as u64;
+        self.read_bytes(desc_offset, &mut raw_desc)?;
+        let desc: Ext2GroupDesc = from_bytes(&raw_desc);
+
+        // Inodes may be smaller on disk than the structure; the rest stays zero
+        let mut raw = [0u8; size_of::<Ext2Inode>()];
+        let len = self.inode_size.min(raw.len());
+        let inode_offset = desc.bg_inode_table_lo as u64 * self.block_size as u64 + index as u64 * self.inode_size as u64;
+        self.read_bytes(inode_offset, &mut raw[..len])?;
+        let inode: Ext2Inode = from_bytes(&raw);
+
+        let size = if inode.is_regular_file() { inode.get_size() } else { inode.i_size_lo as u64 };
+        Ok(Inode { mode: inode.i_mode, size, flags: inode.i_flags, block: inode.i_block })
+    }
+
+    /// Entry `index` of the block-number table in indirect block `table`
+    fn indirect_entry(&self, table: u32, index: usize) -> Result<u32, &'static str> {
+        if table == 0 {
+            return Ok(0);
+        }
+        let mut entry = [0u8; 4];
+        self.read_bytes(table as u64 * self.block_size as u64 + index as u64 * 4, &mut entry)?;
+        Ok(u32::from_le_bytes(entry))
+    }
+
+    /// Disk block holding logical block `logical` of `inode` (0 for a hole)
+    fn map_block(&self, inode: &Inode, logical: usize) -> Result<u32, &'static str> {
+        let per_block = self.block_size / 4;
+
+        if logical < EXT2_NDIR_BLOCKS {
+            return Ok(inode.block[logical]);
+        }
+        let logical = logical - EXT2_NDIR_BLOCKS;
+        if logical < per_block {
+            return self.indirect_entry(inode.block[EXT2_IND_BLOCK], logical);
+        }
+        let logical = logical - per_block;
+        if logical < per_block * per_block {
+            let table = self.indirect_entry(inode.block[EXT2_DIND_BLOCK], logical / per_block)?;
+            return self.indirect_entry(table, logical % per_block);
+        }
+        let logical = logical - per_block * per_block;
+        if logical < per_block * per_block * per_block {
+            let outer = self.indirect_entry(inode.block[EXT2_TIND_BLOCK], logical / (per_block * per_block))?;
+            let table = self.indirect_entry(outer, (logical / per_block) % per_block)?;
+            return self.indirect_entry(table, logical % per_block);
+        }
+        Err("File too large")
+    }
+
+    /// Call `f` with consecutive chunks of the file's contents
+    pub fn read_file(&self, inode: &Inode, mut f: impl FnMut(&[u8]) -> Result<(), &'static str>) -> Result<(), &'static str> {
+        if inode.flags & EXT2_EXTENTS_FL != 0 {
+            return Err("File uses extents, not supported here");
+        }
+
+        let mut buf = [0u8; MAX_BLOCK_SIZE];
+        let mut remaining = inode.size;
+        let mut logical = 0;
+        while remaining > 0 {
+            let block = self.map_block(inode, logical)?;
+            if block == 0 {
+                buf[..self.block_size].fill(0);
+            } else {
+                self.read_block(block, &mut buf)?;
+            }
+            let len = remaining.min(self.block_size as u64) as usize;
+            f(&buf[..len])?;
+            remaining -= len as u64;
+            logical += 1;
+        }
+        Ok(())
+    }
+
+    /// Call `f` with (name, inode number, file type) for each entry of `dir`
+    pub fn for_each_entry(&self, dir: &Inode, mut f: impl FnMut(&str, u32, u8)) -> Result<(), &'static str> {
+        if !dir.is_dir() {
+            return Err("Not a directory");
+        }
+
+        let mut buf = [0u8; MAX_BLOCK_SIZE];
+        let blocks = (dir.size as usize).div_ceil(self.block_size);
+        for logical in 0..blocks {
+            let block = self.map_block(dir, logical)?;
+            if block == 0 {
+                continue;
+            }
+            self.read_block(block, &mut buf)?;
+
+            let mut offset = 0;
+            while offset + size_of::<Ext2DirEntry>() <= self.block_size {
+                let entry: Ext2DirEntry = from_bytes(&buf[offset..]);
+                let rec_len = entry.rec_len as usize;
+                let name_len = entry.name_len as usize;
+                let header_len = size_of::<Ext2DirEntry>();
+                if rec_len < header_len || offset + rec_len > self.block_size {
+                    break;
+                }
+                if entry.inode != 0 && name_len <= rec_len - header_len {
+                    let name = &buf[offset + header_len..offset + header_len + name_len];
+                    if let Ok(name) = core::str::from_utf8(name) {
+                        f(name, entry.inode, entry.file_type);
+                    }
+                }
+                offset += rec_len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve an absolute path (relative paths start at the root too)
+    pub fn lookup(&self, path: &str) -> Result<Inode, &'static str> {
+        let mut inode = self.read_inode(EXT2_ROOT_INODE)?;
+        for component in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            if component.len() > MAX_NAME_LEN {
+                return Err("Name too long");
+            }
+            let mut found = None;
+            self.for_each_entry(&inode, |name, number, _| {
+                if found.is_none() && name == component {
+                    found = Some(number);
+                }
+            })?;
+            inode = self.read_inode(found.ok_or("No such file or directory")?)?;
+        }
+        Ok(inode)
+    }
+}
@@ -1,4 +1,4 @@
-// ext2 data structures
+// ext2 on-disk data structures
 
 /// ext2 constants
 pub const SECTOR_SIZE: usize = 512;
@@ -7,8 +7,38 @@ pub const EXT2_MAGIC: u16 = 0xEF53;
 pub const EXT2_ROOT_INODE: u32 = 2;
 pub const EXT2_FT_REG_FILE: u8 = 1;
 pub const EXT2_FT_DIR: u8 = 2;
+pub const EXT2_FT_SYMLINK: u8 = 7;
 pub const EXT2_EXTENTS_FL: u32 = 0x00080000;
 pub const EXT2_EXT_MAGIC: u16 = 0xF30A;
+pub const EXT2_GROUP_DESC_SIZE: usize = 32;
+pub const EXT4_FEATURE_INCOMPAT_EXTENTS: u32 = 0x0040;
+pub const EXT4_FEATURE_INCOMPAT_64BIT: u32 = 0x0080;
+pub const EXT3_FEATURE_COMPAT_HAS_JOURNAL: u32 = 0x0004;
+pub const EXT3_FEATURE_INCOMPAT_RECOVER: u32 = 0x0004;
+pub const EXT3_FEATURE_INCOMPAT_JOURNAL_DEV: u32 = 0x0008;
+pub const EXT4_FEATURE_RO_COMPAT_GDT_CSUM: u32 = 0x0010;
+pub const EXT4_FEATURE_RO_COMPAT_METADATA_CSUM: u32 = 0x0400;
+pub const EXT4_BG_BLOCK_UNINIT: u16 = 0x0002;
+/// Longest initialized extent; longer `ee_len` values mark uninitialized ones
+pub const EXT4_EXT_INIT_MAX_LEN: u16 = 32768;
+
+/// Inode mode file types
+pub const EXT2_S_IFMT: u16 = 0o170000;
+pub const EXT2_S_IFDIR: u16 = 0o040000;
+pub const EXT2_S_IFREG: u16 = 0o100000;
+pub const EXT2_S_IFLNK: u16 = 0o120000;
+
+/// Longest symbolic link target kept in `i_block` itself
+pub const EXT2_FAST_SYMLINK_MAX: usize = 59;
+
+/// Most names one inode can have
+pub const EXT2_LINK_MAX: u16 = 32000;
+
+/// Block pointer slots in an inode
+pub const EXT2_NDIR_BLOCKS: usize = 12;
+pub const EXT2_IND_BLOCK: usize = 12;
+pub const EXT2_DIND_BLOCK: usize = 13;
+pub const EXT2_TIND_BLOCK: usize = 14;
 
 /// Simplified ext2 Superblock - only essential fields
 #[repr(C, packed)]
@@ -43,7 +73,26 @@ pub struct Ext2Superblock {
     pub s_first_ino: u32,          // 0x54
     pub s_inode_size: u16,         // 0x58
     pub s_block_group_nr: u16,     // 0x5A
-    pub _reserved: [u8; 932],          // Padding to 1024 bytes
+    pub s_feature_compat: u32,     // 0x5C
+    pub s_feature_incompat: u32,   // 0x60
+    pub s_feature_ro_compat: u32,  // 0x64
+    pub s_uuid: [u8; 16],          // 0x68
+    pub s_volume_name: [u8; 16],   // 0x78
+    pub s_last_mounted: [u8; 64],  // 0x88
+    pub s_algorithm_usage_bitmap: u32, // 0xC8
+    pub s_prealloc_blocks: u8,     // 0xCC
+    pub s_prealloc_dir_blocks: u8, // 0xCD
+    pub s_reserved_gdt_blocks: u16, // 0xCE
+    // Journaling fields (ext3/ext4)
+    pub s_journal_uuid: [u8; 16],  // 0xD0
+    pub s_journal_inum: u32,       // 0xE0
+    pub s_journal_dev: u32,        // 0xE4
+    pub s_last_orphan: u32,        // 0xE8
+    pub s_hash_seed: [u32; 4],     // 0xEC
+    pub s_def_hash_version: u8,    // 0xFC
+    pub s_jnl_backup_type: u8,     // 0xFD
+    pub s_desc_size: u16,          // 0xFE
+    pub _reserved: [u8; 768],          // Padding to 1024 bytes
 }
 
 /// Simplified Group Descriptor
@@ -105,6 +154,16 @@ pub struct Ext2ExtentHeader {
     pub eh_generation: u32,     // Generation
 }
 
+/// Extent Index (interior node entry)
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Ext2ExtentIdx {
+    pub ei_block: u32,          // First logical block the subtree covers
+    pub ei_leaf_lo: u32,        // Low 32 bits of the child node's block
+    pub ei_leaf_hi: u16,        // High 16 bits of the child node's block
+    pub ei_unused: u16,
+}
+
 /// Extent Entry
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -122,7 +181,7 @@ impl Ext2Inode {
             i_mode: mode,
             i_uid: uid,
             i_size_lo: 0,
-            i_atime: 0, // TODO: Use current time
+            i_atime: 0, // Times are set by the caller, which has a clock
             i_ctime: 0,
             i_mtime: 0,
             i_dtime: 0,
@@ -141,12 +200,25 @@ impl Ext2Inode {
     
     /// Check if this inode is a directory
     pub fn is_directory(&self) -> bool {
-        (self.i_mode & 0o170000) == 0o040000
+        (self.i_mode & EXT2_S_IFMT) == EXT2_S_IFDIR
     }
     
     /// Check if this inode is a regular file
     pub fn is_regular_file(&self) -> bool {
-        (self.i_mode & 0o170000) == 0o100000
+        (self.i_mode & EXT2_S_IFMT) == EXT2_S_IFREG
+    }
+    
+    /// Check if this inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        (self.i_mode & EXT2_S_IFMT) == EXT2_S_IFLNK
+    }
+    
+    /// Check if this is a symbolic link whose target is kept in `i_block`
+    /// rather than a data block: it has no blocks but an extended
+    /// attribute block, as Linux decides
+    pub fn is_fast_symlink(&self, block_size: usize) -> bool {
+        let acl_sectors = if self.i_file_acl_lo != 0 { (block_size / SECTOR_SIZE) as u32 } else { 0 };
+        self.is_symlink() && self.i_blocks_lo == acl_sectors
     }
     
     /// Get file size (combining low and high parts)
@@ -166,7 +238,47 @@ impl Ext2Inode {
     }
 }
 
+impl Ext2Superblock {
+    /// Size of one group descriptor on disk
+    pub fn group_desc_size(&self) -> usize {
+        let incompat = self.s_feature_incompat;
+        let desc_size = self.s_desc_size as usize;
+        if incompat & EXT4_FEATURE_INCOMPAT_64BIT != 0 && desc_size >= EXT2_GROUP_DESC_SIZE {
+            desc_size
+        } else {
+            EXT2_GROUP_DESC_SIZE
+        }
+    }
+
+    /// Number of block groups
+    pub fn group_count(&self) -> u32 {
+        let blocks = self.s_blocks_count_lo - self.s_first_data_block;
+        blocks.div_ceil(self.s_blocks_per_group)
+    }
+}
+
+impl Ext2ExtentIdx {
+    /// Get the child node's block number
+    pub fn get_leaf_block(&self) -> u64 {
+        ((self.ei_leaf_hi as u64) << 32) | (self.ei_leaf_lo as u64)
+    }
+}
+
 impl Ext2Extent {
+    /// Whether the extent is allocated but not yet written (reads as zeros)
+    pub fn is_uninitialized(&self) -> bool {
+        self.ee_len > EXT4_EXT_INIT_MAX_LEN
+    }
+
+    /// Number of blocks covered, for initialized and uninitialized extents
+    pub fn block_count(&self) -> u32 {
+        if self.is_uninitialized() {
+            (self.ee_len - EXT4_EXT_INIT_MAX_LEN) as u32
+        } else {
+            self.ee_len as u32
+        }
+    }
+
     /// Get the full physical block number
     pub fn get_start_block(&self) -> u64 {
         ((self.ee_start_hi as u64) << 32) | (self.ee_start_lo as u64)
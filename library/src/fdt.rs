@@ -0,0 +1,340 @@
+//! Flattened device tree reader
+//!
+//! Firmware passes the boot hart a device tree blob describing the
+//! machine. Only what elinOS needs is read from it: properties looked up by
+//! node path, such as the `timebase-frequency` of `/cpus`, which gives the
+//! rate of the `time` CSR, and the nodes `nodes` walks in order, each with
+//! its properties and `reg` ranges, from which `platform` learns where RAM
+//! and the devices are. The blob is walked in place; nothing is copied.
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+// Structure block tokens
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Size of the header fields read here
+const HEADER_SIZE: usize = 40;
+
+/// Largest blob accepted, a guard against reading a bad header's size
+const MAX_FDT_SIZE: usize = 1024 * 1024;
+
+/// Deepest node `nodes` goes into
+const MAX_DEPTH: usize = 16;
+
+/// `#address-cells` and `#size-cells` where a node does not give them
+const DEFAULT_CELLS: (u32, u32) = (2, 1);
+
+/// A device tree blob
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+/// Big-endian u32 at `offset` of `data`
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+/// `offset` rounded up to the 4-byte alignment of tokens
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// The NUL-terminated string at the start of `data`
+fn c_str(data: &[u8]) -> Option<&str> {
+    let len = data.iter().position(|&byte| byte == 0)?;
+    core::str::from_utf8(&data[..len]).ok()
+}
+
+/// Big-endian number of `cells` 32-bit cells at the start of `data`
+fn cells_value(data: &[u8], cells: u32) -> Option<u64> {
+    match cells {
+        1 => Some(be32(data, 0)? as u64),
+        2 => Some(((be32(data, 0)? as u64) << 32) | be32(data, 4)? as u64),
+        _ => None,
+    }
+}
+
+/// Whether node `name` is what path component `component` asks for: the
+/// same name, or the same name before the unit address if the component
+/// has none
+fn node_matches(name: &str, component: &str) -> bool {
+    name == component || (!component.contains('@') && name.split('@').next() == Some(component))
+}
+
+impl<'a> Fdt<'a> {
+    /// The blob in `data`, if it has a valid header
+    pub fn new(data: &'a [u8]) -> Option<Fdt<'a>> {
+        if be32(data, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total = be32(data, 4)? as usize;
+        let off_struct = be32(data, 8)? as usize;
+        let off_strings = be32(data, 12)? as usize;
+        let size_strings = be32(data, 32)? as usize;
+        let size_struct = be32(data, 36)? as usize;
+        let data = data.get(..total)?;
+        Some(Fdt {
+            structs: data.get(off_struct..off_struct.checked_add(size_struct)?)?,
+            strings: data.get(off_strings..off_strings.checked_add(size_strings)?)?,
+        })
+    }
+
+    /// The blob at `addr`, if there is a valid one
+    ///
+    /// # Safety
+    /// `addr` must be 0 or readable for the size its header gives
+    pub unsafe fn from_addr(addr: usize) -> Option<Fdt<'static>> {
+        if addr == 0 || addr % 4 != 0 {
+            return None;
+        }
+        let header = unsafe { core::slice::from_raw_parts(addr as *const u8, HEADER_SIZE) };
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total = be32(header, 4)? as usize;
+        if !(HEADER_SIZE..=MAX_FDT_SIZE).contains(&total) {
+            return None;
+        }
+        Fdt::new(unsafe { core::slice::from_raw_parts(addr as *const u8, total) })
+    }
+
+    /// Value of property `name` of the node at `path`, e.g. "/cpus"
+    pub fn property(&self, path: &str, name: &str) -> Option<&'a [u8]> {
+        let components = path.split('/').filter(|component| !component.is_empty());
+        let wanted = components.clone().count();
+        // Nodes open, the root included, and how many of them are on the path
+        let mut depth = 0;
+        let mut matched = 0;
+        let mut offset = 0;
+        loop {
+            let token = be32(self.structs, offset)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let node = c_str(self.structs.get(offset..)?)?;
+                    offset = align4(offset + node.len() + 1);
+                    depth += 1;
+                    // The root is depth 1; its children are the first component
+                    if depth >= 2 && matched == depth - 2 && matched < wanted
+                        && components.clone().nth(matched).is_some_and(|c| node_matches(node, c))
+                    {
+                        matched += 1;
+                    }
+                }
+                FDT_END_NODE => {
+                    if depth >= 2 && matched == depth - 1 {
+                        matched -= 1;
+                    }
+                    depth -= 1;
+                    if depth == 0 {
+                        return None;
+                    }
+                }
+                FDT_PROP => {
+                    let len = be32(self.structs, offset)? as usize;
+                    let name_offset = be32(self.structs, offset + 4)? as usize;
+                    let value = self.structs.get(offset + 8..offset + 8 + len)?;
+                    offset = align4(offset + 8 + len);
+                    if matched == wanted && depth == wanted + 1
+                        && c_str(self.strings.get(name_offset..)?)? == name
+                    {
+                        return Some(value);
+                    }
+                }
+                FDT_NOP => {}
+                FDT_END => return None,
+                // A token this reader does not know
+                _ => return None,
+            }
+        }
+    }
+
+    /// The `model` of the root node, e.g. "riscv-virtio,qemu"
+    pub fn model(&self) -> Option<&'a str> {
+        c_str(self.property("/", "model")?)
+    }
+
+    /// Rate of the `time` CSR in Hz. It is usually a property of /cpus,
+    /// sometimes of each CPU node instead; one or two cells.
+    pub fn timebase_frequency(&self) -> Option<u64> {
+        let value = self.property("/cpus", "timebase-frequency")
+            .or_else(|| self.property("/cpus/cpu", "timebase-frequency"))?;
+        cells_value(value, value.len() as u32 / 4)
+    }
+
+    /// Every node of the tree, the root first, parents before children
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes { fdt: *self, offset: 0, depth: 0, cells: [DEFAULT_CELLS; MAX_DEPTH] }
+    }
+
+    /// Enabled nodes compatible with `compatible`, e.g. "virtio,mmio"
+    pub fn compatible_nodes<'c>(&self, compatible: &'c str) -> impl Iterator<Item = Node<'a>> + 'c
+    where
+        'a: 'c,
+    {
+        self.nodes().filter(move |node| node.is_compatible(compatible) && node.is_enabled())
+    }
+
+    /// The node whose phandle property is `phandle`, as other nodes refer
+    /// to it
+    pub fn node_by_phandle(&self, phandle: u32) -> Option<Node<'a>> {
+        self.nodes().find(|node| node.cell("phandle", 0) == Some(phandle))
+    }
+
+    /// Address and size of each RAM bank, from the memory nodes
+    pub fn memory(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.nodes()
+            .filter(|node| node.device_type() == Some("memory") && node.is_enabled())
+            .flat_map(|node| node.reg())
+    }
+
+    /// The node the firmware's console is on: `stdout-path` of /chosen,
+    /// given as a path or an alias, without its `:options`
+    pub fn stdout(&self) -> Option<Node<'a>> {
+        let path = c_str(self.property("/chosen", "stdout-path")?)?;
+        let path = path.split(':').next()?;
+        let path = if path.starts_with('/') { path } else { c_str(self.property("/aliases", path)?)? };
+        let name = path.rsplit('/').next()?;
+        self.nodes().find(|node| node_matches(node.name(), name))
+    }
+}
+
+/// A node of the tree, with what is needed to read its `reg`
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset of the node's first property in the structure block
+    offset: usize,
+    /// `#address-cells` and `#size-cells` of the parent, which its `reg` uses
+    cells: (u32, u32),
+}
+
+impl<'a> Node<'a> {
+    /// Name with the unit address, e.g. "serial@10000000"; "" for the root
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Name and value of each property of the node
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let fdt = self.fdt;
+        let mut offset = self.offset;
+        core::iter::from_fn(move || loop {
+            match be32(fdt.structs, offset)? {
+                FDT_PROP => {
+                    let len = be32(fdt.structs, offset + 4)? as usize;
+                    let name_offset = be32(fdt.structs, offset + 8)? as usize;
+                    let value = fdt.structs.get(offset + 12..offset + 12 + len)?;
+                    offset = align4(offset + 12 + len);
+                    return Some((c_str(fdt.strings.get(name_offset..)?)?, value));
+                }
+                FDT_NOP => offset += 4,
+                // Properties come before the first child
+                _ => return None,
+            }
+        })
+    }
+
+    /// Value of property `name`
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties().find(|&(property, _)| property == name).map(|(_, value)| value)
+    }
+
+    /// The device_type property, e.g. "memory" or "cpu"
+    pub fn device_type(&self) -> Option<&'a str> {
+        self.property("device_type").and_then(c_str)
+    }
+
+    /// Whether `compatible` is one of the strings of the compatible property
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|value| {
+            value.split(|&byte| byte == 0).any(|entry| entry == compatible.as_bytes())
+        })
+    }
+
+    /// Whether the status property, if any, says the device is usable
+    pub fn is_enabled(&self) -> bool {
+        self.property("status").and_then(c_str).is_none_or(|status| status == "okay" || status == "ok")
+    }
+
+    /// Address and size of each range of the reg property
+    pub fn reg(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let (address_cells, size_cells) = self.cells;
+        let entry = (address_cells + size_cells) as usize * 4;
+        let value = self.property("reg").unwrap_or(&[]);
+        value.chunks_exact(entry.max(1)).filter_map(move |chunk| {
+            let address = cells_value(chunk, address_cells)?;
+            let size = if size_cells == 0 { 0 } else { cells_value(&chunk[address_cells as usize * 4..], size_cells)? };
+            Some((address, size))
+        })
+    }
+
+    /// First interrupt line of the interrupts property
+    pub fn interrupt(&self) -> Option<u32> {
+        self.cell("interrupts", 0)
+    }
+
+    /// Cell `index` of property `name`, e.g. a phandle or a line number
+    pub fn cell(&self, name: &str, index: usize) -> Option<u32> {
+        be32(self.property(name)?, index * 4)
+    }
+}
+
+/// Iterator over the nodes of a tree; see `Fdt::nodes`
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    /// Nodes open, the root included
+    depth: usize,
+    /// Cells each open node gives its children, by depth
+    cells: [(u32, u32); MAX_DEPTH],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        loop {
+            let token = be32(self.fdt.structs, self.offset)?;
+            self.offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(self.fdt.structs.get(self.offset..)?)?;
+                    self.offset = align4(self.offset + name.len() + 1);
+                    if self.depth >= MAX_DEPTH {
+                        return None;
+                    }
+                    let parent = if self.depth == 0 { DEFAULT_CELLS } else { self.cells[self.depth - 1] };
+                    let node = Node { fdt: self.fdt, name, offset: self.offset, cells: parent };
+                    let cell = |property| node.property(property).and_then(|value| be32(value, 0));
+                    self.cells[self.depth] = (
+                        cell("#address-cells").unwrap_or(DEFAULT_CELLS.0),
+                        cell("#size-cells").unwrap_or(DEFAULT_CELLS.1),
+                    );
+                    self.depth += 1;
+                    return Some(node);
+                }
+                FDT_END_NODE => {
+                    self.depth = self.depth.checked_sub(1)?;
+                    if self.depth == 0 {
+                        return None;
+                    }
+                }
+                FDT_PROP => {
+                    let len = be32(self.fdt.structs, self.offset)? as usize;
+                    self.offset = align4(self.offset + 8 + len);
+                }
+                FDT_NOP => {}
+                // FDT_END, or a token this reader does not know
+                _ => return None,
+            }
+        }
+    }
+}
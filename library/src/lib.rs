@@ -5,10 +5,25 @@
 //! Shared components between bootloader and kernel
 
 pub mod sbi;
+pub mod mmio;
+pub mod plic;
 pub mod uart;
 pub mod console;
+pub mod tty;
 pub mod memory;
 pub mod elf;
+pub mod crypto;
+pub mod timer;
+pub mod virtio;
+pub mod ext2;
+pub mod blockcache;
+pub mod crc32;
+pub mod configstore;
+pub mod cmdline;
+pub mod rtc;
+pub mod fdt;
+pub mod platform;
+pub mod syscall;
 
 // Re-export commonly used items
 pub use sbi::*;
@@ -73,7 +73,7 @@ pub enum SystemType {
 /// Get standard MMIO regions for RISC-V QEMU
 pub fn get_standard_mmio_regions() -> [MemoryRegion; 3] {
     [
-        MemoryRegion::new(0x10000000, 0x1000, false, MemoryZone::DMA),    // UART
+        MemoryRegion::new(crate::platform::uart().base, 0x1000, false, MemoryZone::DMA),    // UART
         MemoryRegion::new(0x02000000, 0x10000, false, MemoryZone::DMA),   // CLINT  
         MemoryRegion::new(0x0c000000, 0x400000, false, MemoryZone::DMA),  // PLIC
     ]
@@ -1,7 +1,7 @@
 // Dynamic Memory Layout Manager for elinOS
 // Replaces hardcoded memory allocations with intelligent detection
 
-use crate::console_println;
+use crate::{console_println, ok_println, warn_println, info_println};
 use heapless::Vec;
 use super::regions::{MemoryRegion, MemoryZone};
 use super::hardware::{get_kernel_boundaries, get_stack_boundaries, detect_main_ram, get_fallback_ram, get_standard_mmio_regions, calculate_heap_start, validate_memory_layout};
@@ -42,7 +42,7 @@ pub struct MemoryLayout {
 impl MemoryLayout {
     /// Calculate memory layout dynamically from linker symbols
     pub fn detect() -> Self {
-        console_println!("[i] Detecting memory layout via OpenSBI...");
+        info_println!("Detecting memory layout via OpenSBI...");
         
         // Calculate kernel and stack boundaries using shared functions
         let (kernel_start, kernel_end, kernel_size) = get_kernel_boundaries();
@@ -74,12 +74,12 @@ impl MemoryLayout {
         // Detect main RAM using shared hardware detection
         if let Some(ram_region) = detect_main_ram() {
             layout.add_region(ram_region.start, ram_region.size, ram_region.is_ram, ram_region.zone_type);
-            console_println!("[o] Detected {} MB RAM at 0x{:x}", ram_region.size / (1024 * 1024), ram_region.start);
+            ok_println!("Detected {} MB RAM at 0x{:x}", ram_region.size / (1024 * 1024), ram_region.start);
         } else {
             // Fallback to default QEMU layout
             let fallback = get_fallback_ram();
             layout.add_region(fallback.start, fallback.size, fallback.is_ram, fallback.zone_type);
-            console_println!("[!]  Using fallback memory layout: 128MB at 0x80000000");
+            warn_println!("Using fallback memory layout: 128MB at 0x80000000");
         }
         
         // Add standard MMIO regions using shared function
@@ -107,7 +107,7 @@ impl MemoryLayout {
         layout.device_memory_used = 0;
         
         // Debug output to see the conflict
-        console_println!("[i] Memory layout debug:");
+        info_println!("Memory layout debug:");
         console_println!("   Kernel start: 0x{:08x}", kernel_start);
         console_println!("   Kernel end: 0x{:08x}", kernel_end);
         console_println!("   Kernel size: {} KB", kernel_size / 1024);
@@ -149,11 +149,11 @@ impl MemoryLayout {
         
         // Skip static heap validation for direct kernel boot since memory manager 
         // handles dynamic allocation properly
-        console_println!("[i] Memory layout validation (dynamic allocation mode):");
+        info_println!("Memory layout validation (dynamic allocation mode):");
         console_println!("   Kernel: 0x{:08x} - 0x{:08x}", self.kernel_start, self.kernel_end);
         console_println!("   Dynamic memory manager will handle heap allocation");
         
-        console_println!("[o] Memory layout validation passed");
+        ok_println!("Memory layout validation passed");
         Ok(())
     }
     
@@ -2,12 +2,14 @@
 // Consolidates all memory management functionality with dynamic allocation
 
 // Removed unused imports: GlobalAlloc, Layout
+use core::panic::Location;
 use core::ptr::NonNull;
 use spin::Mutex;
 use heapless::Vec;
-use crate::console_println;
+use crate::{console_println, ok_println, err_println, warn_println, info_println};
 use super::regions::MemoryRegion;
 use super::hardware::{detect_main_ram, get_fallback_ram_for_system, get_kernel_boundaries, SystemType};
+use super::tracker::{AllocationTracker, Caller};
 
 /// Memory allocation modes based on available system memory
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -48,6 +50,14 @@ pub struct MemoryStats {
     pub detected_ram_size: usize,
     pub allocated_bytes: usize,
     pub allocation_count: usize,
+    /// Allocations refused for want of memory or a bad size
+    pub allocation_failures: usize,
+    /// Compaction passes run after a failed allocation, and the bytes they
+    /// gave back to the allocator
+    pub compactions: usize,
+    pub compacted_bytes: usize,
+    /// Bytes of pages moved out of the way by the migrate hook
+    pub migrated_bytes: usize,
     pub allocator_mode: AllocationMode,
     pub heap_size: usize,
     pub heap_used: usize,
@@ -79,16 +89,16 @@ pub struct MemoryConfig {
 impl MemoryConfig {
     /// Create dynamic memory configuration based on detected hardware
     pub fn detect() -> Self {
-        console_println!("[i] Detecting memory configuration...");
+        info_println!("Detecting memory configuration...");
         
         // Detect available RAM with smart fallback
         let memory_region = detect_main_ram().unwrap_or_else(|| {
             // Try to detect system type for better fallback
-            console_println!("[!] RAM detection failed, using intelligent fallback");
+            warn_println!("RAM detection failed, using intelligent fallback");
             get_fallback_ram_for_system(SystemType::QEMU) // Default to QEMU for testing
         });
         let total_ram = memory_region.size;
-        console_println!("[i] Total RAM detected: {} MB", total_ram / (1024 * 1024));
+        info_println!("Total RAM detected: {} MB", total_ram / (1024 * 1024));
         
         // Get kernel boundaries
         let (kernel_start, kernel_end, _kernel_size) = get_kernel_boundaries();
@@ -112,7 +122,7 @@ impl MemoryConfig {
             AllocationMode::Advanced => buddy_heap_size,    // Can use full buddy capacity
         };
         
-        console_println!("[i] Memory configuration:");
+        info_println!("Memory configuration:");
         console_println!("    Mode: {:?}", mode);
         console_println!("    Heap: {} KB", heap_size / 1024);
         console_println!("    Buddy heap: {} KB", buddy_heap_size / 1024);
@@ -174,6 +184,11 @@ pub struct UnifiedMemoryManager {
     // Allocation tracking
     total_allocated: usize,
     allocation_count: usize,
+    allocation_failures: usize,
+    compactions: usize,
+    compacted_bytes: usize,
+    migrated_bytes: usize,
+    tracker: AllocationTracker,
     
     // Memory layout
     heap_start: usize,
@@ -209,6 +224,11 @@ impl UnifiedMemoryManager {
             regions: Vec::new(),
             total_allocated: 0,
             allocation_count: 0,
+            allocation_failures: 0,
+            compactions: 0,
+            compacted_bytes: 0,
+            migrated_bytes: 0,
+            tracker: AllocationTracker::new(),
             heap_start: 0,
             heap_end: 0,
             buddy_start: 0,
@@ -227,7 +247,7 @@ impl UnifiedMemoryManager {
     
     /// Initialize the memory manager with proper memory layout
     pub fn initialize(&mut self) -> AllocResult<()> {
-        console_println!("[i] Initializing unified memory manager...");
+        info_println!("Initializing unified memory manager...");
         
         // Calculate memory layout based on configuration
         self.calculate_memory_layout()?;
@@ -248,7 +268,7 @@ impl UnifiedMemoryManager {
             }
         }
         
-        console_println!("[o] Unified memory manager initialized successfully!");
+        ok_println!("Unified memory manager initialized successfully!");
         self.display_layout();
         Ok(())
     }
@@ -280,7 +300,7 @@ impl UnifiedMemoryManager {
         // Validate layout doesn't exceed available memory
         let total_usage = self.device_end - self.config.kernel_start;
         if total_usage > self.config.total_ram {
-            console_println!("[x] Memory layout exceeds available RAM!");
+            err_println!("Memory layout exceeds available RAM!");
             return Err(AllocationError::SystemError);
         }
         
@@ -293,7 +313,7 @@ impl UnifiedMemoryManager {
     /// Initialize minimal bump allocator
     fn init_minimal_allocator(&mut self) -> AllocResult<()> {
         self.bump_position = self.heap_start;
-        console_println!("[o] Minimal bump allocator initialized: 0x{:x}-0x{:x}", 
+        ok_println!("Minimal bump allocator initialized: 0x{:x}-0x{:x}", 
                          self.heap_start, self.heap_end);
         Ok(())
     }
@@ -310,7 +330,7 @@ impl UnifiedMemoryManager {
         let bitmap_size = (total_blocks + 7) / 8; // Round up to byte boundary
         
         if bitmap_size > 65536 {
-            console_println!("[x] Buddy allocator bitmap too large: {} bytes", bitmap_size);
+            err_println!("Buddy allocator bitmap too large: {} bytes", bitmap_size);
             return Err(AllocationError::SystemError);
         }
         
@@ -318,7 +338,7 @@ impl UnifiedMemoryManager {
         self.buddy_free_lists = Some(Vec::new());
         self.buddy_bitmap = Some(Vec::new());
         
-        console_println!("[o] Buddy allocator initialized: 0x{:x}-0x{:x} (bitmap: {} bytes)", 
+        ok_println!("Buddy allocator initialized: 0x{:x}-0x{:x} (bitmap: {} bytes)", 
                          self.buddy_start, self.buddy_end, bitmap_size);
         Ok(())
     }
@@ -330,13 +350,35 @@ impl UnifiedMemoryManager {
         }
         
         self.small_bins = Some(Vec::new());
-        console_println!("[o] Small object allocator initialized: 0x{:x}-0x{:x}", 
+        ok_println!("Small object allocator initialized: 0x{:x}-0x{:x}", 
                          self.small_start, self.small_end);
         Ok(())
     }
     
     /// Allocate memory using the most appropriate allocator
+    #[track_caller]
     pub fn allocate(&mut self, size: usize, align: usize) -> AllocResult<NonNull<u8>> {
+        self.allocate_from(size, align, Location::caller())
+    }
+
+    /// Allocate on behalf of `caller`, whom the tracker records
+    pub fn allocate_from(&mut self, size: usize, align: usize, caller: Caller) -> AllocResult<NonNull<u8>> {
+        let mut result = self.try_allocate(size, align);
+        if result == Err(AllocationError::OutOfMemory) {
+            // Freed memory is only reused once compacted; large requests
+            // are the first to run out
+            self.compact();
+            result = self.try_allocate(size, align)
+                .or_else(|_| self.allocate_first_fit(size, align, self.bump_position));
+        }
+        match result {
+            Ok(ptr) => self.tracker.allocated(ptr.as_ptr() as usize, size, caller),
+            Err(_) => self.allocation_failures += 1,
+        }
+        result
+    }
+
+    fn try_allocate(&mut self, size: usize, align: usize) -> AllocResult<NonNull<u8>> {
         if size == 0 {
             return Err(AllocationError::InvalidSize);
         }
@@ -391,6 +433,66 @@ impl UnifiedMemoryManager {
         }
     }
     
+    /// Allocate from a freed hole below the bump position, the first one
+    /// large enough, ending by `limit`
+    fn allocate_first_fit(&mut self, size: usize, align: usize, limit: usize) -> AllocResult<NonNull<u8>> {
+        let found = self.free_ranges.iter().find_map(|&(start, end)| {
+            let aligned = (start + align - 1) & !(align - 1);
+            (end <= self.bump_position && aligned + size <= end.min(limit)).then_some(aligned)
+        });
+        let addr = found.ok_or(AllocationError::OutOfMemory)?;
+        self.total_allocated += size;
+        self.allocation_count += 1;
+        self.update_free_ranges_after_allocation(addr, size);
+        unsafe {
+            Ok(NonNull::new_unchecked(addr as *mut u8))
+        }
+    }
+
+    /// Allocate on behalf of `caller` from a freed hole ending by `limit`,
+    /// never from the bump allocator, so a page moved there ends up lower
+    /// in memory
+    pub fn allocate_below(&mut self, size: usize, align: usize, limit: usize, caller: Caller) -> AllocResult<NonNull<u8>> {
+        let result = self.allocate_first_fit(size, align, limit);
+        if let Ok(ptr) = result {
+            self.tracker.allocated(ptr.as_ptr() as usize, size, caller);
+        }
+        result
+    }
+
+    /// Merge free ranges that touch into single ranges, and hand a freed
+    /// block just below the bump position back to it, so the free end of
+    /// the heap grows. Nothing is moved here: allocations are handed out
+    /// by physical address, and only their owner can move them, through
+    /// the migrate hook. Returns the bytes given back to the bump
+    /// allocator.
+    pub fn compact(&mut self) -> usize {
+        self.free_ranges.sort_unstable_by_key(|&(start, _)| start);
+        let mut merged = Vec::<(usize, usize), 256>::new();
+        for &(start, end) in self.free_ranges.iter() {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => {
+                    let _ = merged.push((start, end));
+                }
+            }
+        }
+        self.free_ranges = merged;
+
+        // The range running to the end of the heap is what the bump
+        // allocator has left; one merged into it from below moves it down
+        let mut reclaimed = 0;
+        if let Some(&(start, end)) = self.free_ranges.last() {
+            if end >= self.heap_end && start < self.bump_position && start >= self.heap_start {
+                reclaimed = self.bump_position - start;
+                self.bump_position = start;
+            }
+        }
+        self.compactions += 1;
+        self.compacted_bytes += reclaimed;
+        reclaimed
+    }
+    
     /// Buddy allocator implementation (simplified)
     fn allocate_buddy(&mut self, size: usize, align: usize) -> AllocResult<NonNull<u8>> {
         // For now, fall back to minimal allocator
@@ -411,6 +513,7 @@ impl UnifiedMemoryManager {
         
         // Update statistics
         self.total_allocated = self.total_allocated.saturating_sub(size);
+        self.tracker.freed(addr, size);
         
         // Add to free ranges
         self.add_free_range(addr, size);
@@ -514,12 +617,26 @@ impl UnifiedMemoryManager {
         let _ = self.free_ranges.push((self.heap_start, self.heap_end));
     }
 
+    /// Allocation tracking and poisoning; see `tracker`
+    pub fn tracker(&mut self) -> &mut AllocationTracker {
+        &mut self.tracker
+    }
+
+    /// The sizes and mode chosen at boot for the RAM found
+    pub fn config(&self) -> &MemoryConfig {
+        &self.config
+    }
+
     /// Get memory statistics
     pub fn get_memory_stats(&self) -> MemoryStats {
         MemoryStats {
             detected_ram_size: self.config.total_ram,
             allocated_bytes: self.total_allocated,
             allocation_count: self.allocation_count,
+            allocation_failures: self.allocation_failures,
+            compactions: self.compactions,
+            compacted_bytes: self.compacted_bytes,
+            migrated_bytes: self.migrated_bytes,
             allocator_mode: self.config.mode,
             heap_size: self.config.heap_size,
             heap_used: self.total_allocated,
@@ -616,9 +733,44 @@ where
     f(manager)
 }
 
+/// Called when an allocation of the given size fails even after
+/// compaction, to move pages the caller owns into freed holes lower down.
+/// Returns the bytes moved.
+static MIGRATE_HOOK: Mutex<Option<fn(usize) -> usize>> = Mutex::new(None);
+
+/// Have `hook` move movable pages out of the way when an allocation fails
+pub fn set_migrate_hook(hook: fn(usize) -> usize) {
+    *MIGRATE_HOOK.lock() = Some(hook);
+}
+
 /// Allocate memory using the global manager
+#[track_caller]
 pub fn allocate_memory(size: usize, align: usize) -> AllocResult<NonNull<u8>> {
-    with_memory_manager(|mgr| mgr.allocate(size, align))
+    let caller = Location::caller();
+    let result = with_memory_manager(|mgr| mgr.allocate_from(size, align, caller));
+    if result != Err(AllocationError::OutOfMemory) {
+        return result;
+    }
+
+    // The hook allocates and frees through the manager, so it runs with
+    // the manager unlocked; the pages it frees are compacted on the retry
+    let hook = MIGRATE_HOOK.try_lock().and_then(|hook| *hook);
+    match hook.map(|hook| hook(size)) {
+        Some(moved) if moved > 0 => with_memory_manager(|mgr| {
+            mgr.migrated_bytes += moved;
+            // One failure for the request, whatever the retry does
+            mgr.allocation_failures = mgr.allocation_failures.saturating_sub(1);
+            mgr.allocate_from(size, align, caller)
+        }),
+        _ => result,
+    }
+}
+
+/// Allocate from a freed hole ending by `limit`, to move a page lower
+#[track_caller]
+pub fn allocate_memory_below(size: usize, align: usize, limit: usize) -> AllocResult<NonNull<u8>> {
+    let caller = Location::caller();
+    with_memory_manager(|mgr| mgr.allocate_below(size, align, limit, caller))
 }
 
 /// Deallocate memory using the global manager
@@ -651,6 +803,11 @@ pub fn get_memory_stats() -> MemoryStats {
     with_memory_manager(|mgr| mgr.get_memory_stats())
 }
 
+/// Get the boot-time memory configuration using global manager
+pub fn get_memory_config() -> MemoryConfig {
+    with_memory_manager(|mgr| mgr.config().clone())
+}
+
 /// Get maximum file size using global manager
 pub fn get_max_file_size() -> usize {
     with_memory_manager(|mgr| mgr.get_max_file_size())
@@ -2,6 +2,7 @@ pub mod regions;
 pub mod hardware; 
 pub mod layout;
 pub mod manager;
+pub mod tracker;
 
 // Re-export commonly used types and functions
 pub use regions::{MemoryRegion, MemoryZone};
@@ -11,7 +12,8 @@ pub use layout::*;
 // Re-export the unified memory manager
 pub use manager::{
     UnifiedMemoryManager, MemoryConfig, AllocationMode, AllocationError, AllocResult, BufferUsage, MemoryStats,
-    init_unified_memory_manager, with_memory_manager, allocate_memory, deallocate_memory,
+    init_unified_memory_manager, with_memory_manager, allocate_memory, allocate_memory_below, deallocate_memory,
+    set_migrate_hook,
     is_memory_range_free, get_total_free_memory, display_memory_layout, get_optimal_buffer_size, get_memory_stats,
-    get_max_file_size, get_heap_usage, reset_heap_for_testing
+    get_memory_config, get_max_file_size, get_heap_usage, reset_heap_for_testing
 };
\ No newline at end of file
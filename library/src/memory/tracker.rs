@@ -0,0 +1,189 @@
+//! Allocation tracking and poisoning for the unified memory manager
+//!
+//! Both are off unless asked for, as they cost a table search on every
+//! allocation and free. With tracking on, each allocation is recorded with
+//! the source location that asked for it (passed down with
+//! `#[track_caller]` through `allocate_memory`), and for each location the
+//! manager keeps how many blocks and bytes it has live and how many it
+//! allocated and freed; a location whose live count only grows is leaking.
+//! With poisoning on, freed memory is filled with `POISON_FREE` before it
+//! goes back to the free ranges, so a use after free reads an unmistakable
+//! pattern instead of stale data.
+//!
+//! The tables are fixed in size. Allocations past `MAX_TRACKED` live ones
+//! are counted but not recorded, and locations past `MAX_TAGS` share one
+//! overflow row. A free that matches no record is counted as unmatched:
+//! a double free, a block allocated before tracking was turned on, or one
+//! that did not fit in the table.
+
+use core::panic::Location;
+use heapless::Vec;
+
+/// Most live allocations recorded
+pub const MAX_TRACKED: usize = 256;
+
+/// Most allocating locations with a row of their own
+pub const MAX_TAGS: usize = 48;
+
+/// What freed memory is filled with, as Linux's slab poisoning does
+pub const POISON_FREE: u8 = 0x6b;
+
+/// Where an allocation was asked for
+pub type Caller = &'static Location<'static>;
+
+/// Row index of the overflow row
+const OVERFLOW_TAG: u8 = u8::MAX;
+
+/// Counts for one allocating location
+#[derive(Debug, Clone, Copy)]
+pub struct TagStats {
+    /// None for the overflow row
+    pub caller: Option<Caller>,
+    pub live: usize,
+    pub live_bytes: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+impl TagStats {
+    const fn new(caller: Option<Caller>) -> Self {
+        TagStats { caller, live: 0, live_bytes: 0, allocations: 0, frees: 0 }
+    }
+}
+
+/// A live allocation
+#[derive(Debug, Clone, Copy)]
+pub struct TrackedAllocation {
+    pub addr: usize,
+    pub size: usize,
+    /// `time` CSR when it was made
+    pub allocated_at: u64,
+    tag: u8,
+}
+
+pub struct AllocationTracker {
+    tracking: bool,
+    poisoning: bool,
+    live: Vec<TrackedAllocation, MAX_TRACKED>,
+    tags: Vec<TagStats, MAX_TAGS>,
+    overflow: TagStats,
+    /// Allocations not recorded because the table was full
+    untracked: usize,
+    unmatched_frees: usize,
+    /// Frees whose size differs from the allocation's
+    size_mismatches: usize,
+}
+
+impl AllocationTracker {
+    pub const fn new() -> Self {
+        AllocationTracker {
+            tracking: false,
+            poisoning: false,
+            live: Vec::new(),
+            tags: Vec::new(),
+            overflow: TagStats::new(None),
+            untracked: 0,
+            unmatched_frees: 0,
+            size_mismatches: 0,
+        }
+    }
+
+    pub fn tracking(&self) -> bool {
+        self.tracking
+    }
+
+    pub fn poisoning(&self) -> bool {
+        self.poisoning
+    }
+
+    /// Start or stop tracking. Starting begins with empty tables.
+    pub fn set_tracking(&mut self, on: bool) {
+        if on && !self.tracking {
+            *self = AllocationTracker { poisoning: self.poisoning, ..AllocationTracker::new() };
+        }
+        self.tracking = on;
+    }
+
+    pub fn set_poisoning(&mut self, on: bool) {
+        self.poisoning = on;
+    }
+
+    /// Record a block `caller` was given
+    pub(super) fn allocated(&mut self, addr: usize, size: usize, caller: Caller) {
+        if !self.tracking {
+            return;
+        }
+        let tag = self.tag_for(caller);
+        let stats = self.stats_mut(tag);
+        stats.live += 1;
+        stats.live_bytes += size;
+        stats.allocations += 1;
+        let allocation = TrackedAllocation { addr, size, allocated_at: crate::timer::ticks(), tag };
+        if self.live.push(allocation).is_err() {
+            self.untracked += 1;
+        }
+    }
+
+    /// Record a block given back, and poison it if asked to
+    pub(super) fn freed(&mut self, addr: usize, size: usize) {
+        if self.tracking {
+            match self.live.iter().position(|allocation| allocation.addr == addr) {
+                Some(index) => {
+                    let allocation = self.live.swap_remove(index);
+                    if allocation.size != size {
+                        self.size_mismatches += 1;
+                    }
+                    let stats = self.stats_mut(allocation.tag);
+                    stats.live = stats.live.saturating_sub(1);
+                    stats.live_bytes = stats.live_bytes.saturating_sub(allocation.size);
+                    stats.frees += 1;
+                }
+                None => self.unmatched_frees += 1,
+            }
+        }
+        if self.poisoning {
+            unsafe {
+                core::ptr::write_bytes(addr as *mut u8, POISON_FREE, size);
+            }
+        }
+    }
+
+    fn tag_for(&mut self, caller: Caller) -> u8 {
+        if let Some(index) = self.tags.iter().position(|stats| stats.caller == Some(caller)) {
+            return index as u8;
+        }
+        match self.tags.push(TagStats::new(Some(caller))) {
+            Ok(()) => (self.tags.len() - 1) as u8,
+            Err(_) => OVERFLOW_TAG,
+        }
+    }
+
+    fn stats_mut(&mut self, tag: u8) -> &mut TagStats {
+        match self.tags.get_mut(tag as usize) {
+            Some(stats) => stats,
+            None => &mut self.overflow,
+        }
+    }
+
+    /// Counts by location, the overflow row last if it was used
+    pub fn tags(&self) -> impl Iterator<Item = &TagStats> {
+        self.tags.iter().chain((self.overflow.allocations > 0).then_some(&self.overflow))
+    }
+
+    /// The recorded live allocations with the location that made each
+    pub fn live(&self) -> impl Iterator<Item = (&TrackedAllocation, Option<Caller>)> {
+        self.live.iter().map(|allocation| (allocation, self.tags.get(allocation.tag as usize).and_then(|stats| stats.caller)))
+    }
+
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    pub fn unmatched_frees(&self) -> usize {
+        self.unmatched_frees
+    }
+
+    pub fn size_mismatches(&self) -> usize {
+        self.size_mismatches
+    }
+}
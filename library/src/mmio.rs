@@ -0,0 +1,97 @@
+//! Memory-mapped device registers
+//!
+//! A `RegisterBlock` is a device's register window; each register is a
+//! `Register<T>` constant giving its offset and width, so a 32-bit register
+//! cannot be read as a byte by mistake. Every access is volatile.
+//!
+//! Volatile accesses are not ordered against ordinary memory accesses, and
+//! on RISC-V a plain `fence rw, rw` (what `atomic::fence` emits) does not
+//! cover device I/O either. Anything the device reads by DMA (descriptors,
+//! ring indices, buffers) must be made visible with `io_fence` before the
+//! register write that tells the device to look; `write_fenced` does both.
+
+use core::marker::PhantomData;
+
+/// Types a register can hold
+pub trait RegisterValue: Copy {}
+
+impl RegisterValue for u8 {}
+impl RegisterValue for u16 {}
+impl RegisterValue for u32 {}
+impl RegisterValue for u64 {}
+
+/// A register of type `T` at a fixed offset in its block
+#[derive(Debug)]
+pub struct Register<T: RegisterValue> {
+    offset: usize,
+    _width: PhantomData<T>,
+}
+
+// Derived impls would require `T: Clone`/`T: Copy` on the marker only
+impl<T: RegisterValue> Clone for Register<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: RegisterValue> Copy for Register<T> {}
+
+impl<T: RegisterValue> Register<T> {
+    pub const fn at(offset: usize) -> Self {
+        Register { offset, _width: PhantomData }
+    }
+
+    /// The register `bytes` further into the block, of the same type
+    pub const fn offset_by(self, bytes: usize) -> Self {
+        Register::at(self.offset + bytes)
+    }
+
+    pub const fn offset(self) -> usize {
+        self.offset
+    }
+}
+
+/// A device's memory-mapped register window
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterBlock {
+    base: usize,
+}
+
+impl RegisterBlock {
+    /// # Safety
+    /// `base` must be the address of a device register window that stays
+    /// mapped for as long as the block is used.
+    pub const unsafe fn new(base: usize) -> Self {
+        RegisterBlock { base }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn read<T: RegisterValue>(&self, reg: Register<T>) -> T {
+        unsafe { core::ptr::read_volatile((self.base + reg.offset) as *const T) }
+    }
+
+    pub fn write<T: RegisterValue>(&self, reg: Register<T>, value: T) {
+        unsafe { core::ptr::write_volatile((self.base + reg.offset) as *mut T, value) }
+    }
+
+    /// Write after all earlier memory accesses, e.g. to notify a device of
+    /// queue entries it will fetch from memory
+    pub fn write_fenced<T: RegisterValue>(&self, reg: Register<T>, value: T) {
+        io_fence();
+        self.write(reg, value);
+    }
+
+    /// Set `bits` in a register, leaving the others as they are
+    pub fn set_bits(&self, reg: Register<u32>, bits: u32) {
+        self.write(reg, self.read(reg) | bits);
+    }
+}
+
+/// Order all earlier memory and device accesses before all later ones
+#[inline(always)]
+pub fn io_fence() {
+    unsafe { core::arch::asm!("fence iorw, iorw", options(nostack, preserves_flags)) }
+}
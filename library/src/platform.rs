@@ -0,0 +1,269 @@
+//! The machine elinOS runs on
+//!
+//! Where RAM is, which harts there are, and where the UART, the VirtIO
+//! MMIO transports, any USB host controllers and any buzzer are with the
+//! interrupt lines they raise. `init` reads them from the device tree
+//! the firmware passed; until then, and for whatever the tree does not
+//! describe, the layout of the QEMU virt machine is assumed. Drivers ask
+//! here rather than hard-coding addresses, so the same image boots on a
+//! machine with more memory or a different set of devices.
+
+use heapless::String;
+use spin::Once;
+
+use crate::fdt::{Fdt, Node};
+
+/// Most VirtIO MMIO transports used
+pub const MAX_VIRTIO_SLOTS: usize = 8;
+
+/// Most harts used; hart IDs run below this
+pub const MAX_HARTS: usize = 8;
+
+/// Most USB host controllers used
+pub const MAX_USB_HOSTS: usize = 2;
+
+/// QEMU virt's RAM, where boot expects it when there is no device tree
+pub const DEFAULT_RAM_BASE: usize = 0x8000_0000;
+pub const DEFAULT_RAM_SIZE: usize = 128 * 1024 * 1024;
+
+/// QEMU virt's UART
+pub const DEFAULT_UART: Device = Device { base: 0x1000_0000, size: 0x100, irq: 10 };
+
+/// QEMU virt's VirtIO MMIO transports: 0x10001000 up, lines 1 up
+const DEFAULT_VIRTIO_SLOTS: [Device; MAX_VIRTIO_SLOTS] = {
+    let mut slots = [Device { base: 0, size: 0x1000, irq: 0 }; MAX_VIRTIO_SLOTS];
+    let mut slot = 0;
+    while slot < MAX_VIRTIO_SLOTS {
+        slots[slot].base = 0x1000_1000 + slot * 0x1000;
+        slots[slot].irq = 1 + slot as u32;
+        slot += 1;
+    }
+    slots
+};
+
+/// A memory-mapped device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    pub base: usize,
+    pub size: usize,
+    /// PLIC line
+    pub irq: u32,
+}
+
+/// Register interface of a USB host controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbHostKind {
+    /// USB 2.0
+    Ehci,
+    /// USB 3.x
+    Xhci,
+}
+
+/// A USB host controller on the memory bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbHost {
+    pub kind: UsbHostKind,
+    /// `irq` is 0 if the tree gives no line
+    pub device: Device,
+}
+
+/// A buzzer on a GPIO line, from a `gpio-beeper` node whose line is on a
+/// SiFive GPIO controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beeper {
+    /// The controller's registers; `irq` is not used
+    pub gpio: Device,
+    pub pin: u32,
+    /// The buzzer sounds while the line is low
+    pub active_low: bool,
+}
+
+const NO_USB_HOST: UsbHost = UsbHost { kind: UsbHostKind::Ehci, device: Device { base: 0, size: 0, irq: 0 } };
+
+/// What was found, set once by `init`
+struct Platform {
+    ram: (usize, usize),
+    uart: Device,
+    virtio: [Device; MAX_VIRTIO_SLOTS],
+    /// Transports in use, the first ones of `virtio`
+    virtio_count: usize,
+    harts: [usize; MAX_HARTS],
+    /// Harts listed, the first ones of `harts`; 0 if not known
+    hart_count: usize,
+    usb_hosts: [UsbHost; MAX_USB_HOSTS],
+    /// Controllers found, the first ones of `usb_hosts`
+    usb_host_count: usize,
+    beeper: Option<Beeper>,
+    /// The tree's `model`, empty without one
+    model: String<64>,
+}
+
+/// QEMU virt, for before `init` and for boots without a device tree
+static QEMU_VIRT: Platform = Platform {
+    ram: (DEFAULT_RAM_BASE, DEFAULT_RAM_SIZE),
+    uart: DEFAULT_UART,
+    virtio: DEFAULT_VIRTIO_SLOTS,
+    virtio_count: MAX_VIRTIO_SLOTS,
+    harts: [0; MAX_HARTS],
+    hart_count: 0,
+    usb_hosts: [NO_USB_HOST; MAX_USB_HOSTS],
+    usb_host_count: 0,
+    beeper: None,
+    model: String::new(),
+};
+
+static PLATFORM: Once<Platform> = Once::new();
+
+/// The device at the first `reg` range of `node` on its first interrupt
+/// line, if it has both
+fn device(node: &Node) -> Option<Device> {
+    let (base, size) = node.reg().next()?;
+    Some(Device { base: base as usize, size: size as usize, irq: node.interrupt()? })
+}
+
+/// Learn the machine from `fdt`. Only the first call counts; without one
+/// the QEMU virt layout stays.
+pub fn init(fdt: &Fdt) {
+    PLATFORM.call_once(|| from_fdt(fdt));
+}
+
+fn from_fdt(fdt: &Fdt) -> Platform {
+    // The bank boot loaded us into; the memory manager handles one
+    let ram = fdt.memory()
+        .map(|(base, size)| (base as usize, size as usize))
+        .find(|&(base, size)| (base..base + size).contains(&DEFAULT_RAM_BASE))
+        .or_else(|| fdt.memory().next().map(|(base, size)| (base as usize, size as usize)))
+        .unwrap_or((DEFAULT_RAM_BASE, DEFAULT_RAM_SIZE));
+
+    // The firmware's console if it is a 16550, else the first one
+    let uart = fdt.stdout()
+        .filter(|node| node.is_compatible("ns16550a") || node.is_compatible("ns16550"))
+        .or_else(|| fdt.compatible_nodes("ns16550a").next())
+        .or_else(|| fdt.compatible_nodes("ns16550").next())
+        .and_then(|node| device(&node))
+        .unwrap_or(DEFAULT_UART);
+
+    // QEMU lists the transports from the top down; number them upwards
+    let mut virtio = [Device { base: 0, size: 0, irq: 0 }; MAX_VIRTIO_SLOTS];
+    let mut virtio_count = 0;
+    for device in fdt.compatible_nodes("virtio,mmio").filter_map(|node| device(&node)).take(MAX_VIRTIO_SLOTS) {
+        virtio[virtio_count] = device;
+        virtio_count += 1;
+    }
+    virtio[..virtio_count].sort_unstable_by_key(|device| device.base);
+
+    // The cpu nodes under /cpus, whose reg is the hart ID
+    let mut harts = [0; MAX_HARTS];
+    let mut hart_count = 0;
+    let cpus = fdt.nodes()
+        .filter(|node| node.device_type() == Some("cpu") && node.is_enabled())
+        .filter_map(|node| node.reg().next())
+        .map(|(hart, _)| hart as usize)
+        .filter(|&hart| hart < MAX_HARTS);
+    for hart in cpus.take(MAX_HARTS) {
+        harts[hart_count] = hart;
+        hart_count += 1;
+    }
+
+    // Memory-mapped USB host controllers; QEMU virt has none
+    let mut usb_hosts = [NO_USB_HOST; MAX_USB_HOSTS];
+    let mut usb_host_count = 0;
+    let hosts = fdt.nodes()
+        .filter(|node| node.is_enabled())
+        .filter_map(|node| {
+            let kind = if node.is_compatible("generic-ehci") || node.is_compatible("usb-ehci") {
+                UsbHostKind::Ehci
+            } else if node.is_compatible("generic-xhci") || node.is_compatible("xhci-platform") {
+                UsbHostKind::Xhci
+            } else {
+                return None;
+            };
+            let (base, size) = node.reg().next()?;
+            let device = Device { base: base as usize, size: size as usize, irq: node.interrupt().unwrap_or(0) };
+            Some(UsbHost { kind, device })
+        });
+    for host in hosts.take(MAX_USB_HOSTS) {
+        usb_hosts[usb_host_count] = host;
+        usb_host_count += 1;
+    }
+
+    // A GPIO buzzer, as boards without a screen have; QEMU virt has none.
+    // The gpios cells are the controller's phandle, the line and, with two
+    // #gpio-cells, flags whose bit 0 means active low.
+    let beeper = fdt.compatible_nodes("gpio-beeper").next().and_then(|node| {
+        let controller = fdt.node_by_phandle(node.cell("gpios", 0)?)?;
+        if !controller.is_compatible("sifive,gpio0") {
+            return None;
+        }
+        let (base, size) = controller.reg().next()?;
+        let flags = match controller.cell("#gpio-cells", 0) {
+            Some(2) => node.cell("gpios", 2).unwrap_or(0),
+            _ => 0,
+        };
+        Some(Beeper {
+            gpio: Device { base: base as usize, size: size as usize, irq: 0 },
+            pin: node.cell("gpios", 1)?,
+            active_low: flags & 1 != 0,
+        })
+    });
+
+    // Longer names are cut short rather than dropped
+    let mut model = String::new();
+    for ch in fdt.model().unwrap_or("").chars() {
+        if model.push(ch).is_err() {
+            break;
+        }
+    }
+
+    Platform { ram, uart, virtio, virtio_count, harts, hart_count, usb_hosts, usb_host_count, beeper, model }
+}
+
+fn platform() -> &'static Platform {
+    PLATFORM.get().unwrap_or(&QEMU_VIRT)
+}
+
+/// Base and size of RAM
+pub fn ram() -> (usize, usize) {
+    platform().ram
+}
+
+/// Whether `init` read a device tree, rather than the QEMU virt layout
+/// being assumed
+pub fn from_device_tree() -> bool {
+    PLATFORM.get().is_some()
+}
+
+/// The board's name from the device tree's `model`, if it gave one
+pub fn model() -> Option<&'static str> {
+    PLATFORM.get().map(|platform| platform.model.as_str()).filter(|model| !model.is_empty())
+}
+
+/// The console UART
+pub fn uart() -> Device {
+    platform().uart
+}
+
+/// IDs of the harts the device tree lists; empty without one, in which
+/// case the SBI has to be asked
+pub fn harts() -> &'static [usize] {
+    let platform = platform();
+    &platform.harts[..platform.hart_count]
+}
+
+/// The USB host controllers the device tree lists; empty without one
+pub fn usb_hosts() -> &'static [UsbHost] {
+    let platform = platform();
+    &platform.usb_hosts[..platform.usb_host_count]
+}
+
+/// The GPIO buzzer the device tree describes, if any
+pub fn beeper() -> Option<Beeper> {
+    platform().beeper
+}
+
+/// The VirtIO MMIO transports, in address order; a device's slot is its
+/// position here
+pub fn virtio_slots() -> &'static [Device] {
+    let platform = platform();
+    &platform.virtio[..platform.virtio_count]
+}
@@ -0,0 +1,75 @@
+//! Platform-Level Interrupt Controller of the QEMU virt machine
+//!
+//! The PLIC routes device interrupt lines to harts. A line reaches a hart
+//! context when it is enabled for that context and its priority is above
+//! the context's threshold. The hart then claims the highest-priority
+//! pending line, serves the device, and completes the claim so the line
+//! can fire again. Each hart has a machine-mode context and a
+//! supervisor-mode context; the kernel uses the supervisor ones.
+
+use crate::mmio::{Register, RegisterBlock};
+
+/// Where the QEMU virt machine puts the PLIC
+pub const PLIC_BASE: usize = 0x0c00_0000;
+
+const PRIORITY: Register<u32> = Register::at(0x00_0000);
+const ENABLE: Register<u32> = Register::at(0x00_2000);
+const THRESHOLD: Register<u32> = Register::at(0x20_0000);
+const CLAIM: Register<u32> = Register::at(0x20_0004);
+
+/// Bytes between the enable bits of consecutive contexts
+const ENABLE_STRIDE: usize = 0x80;
+/// Bytes between the threshold and claim registers of consecutive contexts
+const CONTEXT_STRIDE: usize = 0x1000;
+
+/// The PLIC as one supervisor-mode hart context sees it
+#[derive(Debug, Clone, Copy)]
+pub struct Plic {
+    regs: RegisterBlock,
+    context: usize,
+}
+
+impl Plic {
+    /// The supervisor-mode context of `hart`
+    pub const fn supervisor(hart: usize) -> Self {
+        Plic {
+            regs: unsafe { RegisterBlock::new(PLIC_BASE) },
+            context: 2 * hart + 1,
+        }
+    }
+
+    /// Set the priority of line `irq`, 1 (lowest) to 7; 0 never fires
+    pub fn set_priority(&self, irq: u32, priority: u32) {
+        self.regs.write(PRIORITY.offset_by(4 * irq as usize), priority);
+    }
+
+    /// Take interrupts of line `irq` on this context
+    pub fn enable(&self, irq: u32) {
+        self.regs.set_bits(self.enable_word(irq), 1 << (irq % 32));
+    }
+
+    pub fn disable(&self, irq: u32) {
+        let word = self.enable_word(irq);
+        self.regs.write(word, self.regs.read(word) & !(1 << (irq % 32)));
+    }
+
+    /// Only lines of a priority above `threshold` reach this context
+    pub fn set_threshold(&self, threshold: u32) {
+        self.regs.write(THRESHOLD.offset_by(self.context * CONTEXT_STRIDE), threshold);
+    }
+
+    /// Claim the highest-priority pending line, if any
+    pub fn claim(&self) -> Option<u32> {
+        let irq = self.regs.read(CLAIM.offset_by(self.context * CONTEXT_STRIDE));
+        (irq != 0).then_some(irq)
+    }
+
+    /// Tell the PLIC line `irq` has been served, so it can fire again
+    pub fn complete(&self, irq: u32) {
+        self.regs.write(CLAIM.offset_by(self.context * CONTEXT_STRIDE), irq);
+    }
+
+    fn enable_word(&self, irq: u32) -> Register<u32> {
+        ENABLE.offset_by(self.context * ENABLE_STRIDE + 4 * (irq as usize / 32))
+    }
+}
@@ -0,0 +1,74 @@
+//! Wall-clock time from the Goldfish RTC of the QEMU virt machine
+//!
+//! The RTC counts nanoseconds since the Unix epoch. Reading the low half of
+//! the counter latches the high half, so the halves are read in that order.
+
+use core::fmt;
+use crate::mmio::{Register, RegisterBlock};
+
+/// Where the QEMU virt machine puts the RTC
+pub const GOLDFISH_RTC_BASE: usize = 0x0010_1000;
+
+const TIME_LOW: Register<u32> = Register::at(0x00);
+const TIME_HIGH: Register<u32> = Register::at(0x04);
+
+/// Seconds since the Unix epoch, or None if the RTC has not been set
+pub fn unix_time() -> Option<u64> {
+    unix_time_ns().map(|nanos| nanos / 1_000_000_000)
+}
+
+/// Nanoseconds since the Unix epoch, or None if the RTC has not been set
+pub fn unix_time_ns() -> Option<u64> {
+    let regs = unsafe { RegisterBlock::new(GOLDFISH_RTC_BASE) };
+    let low = regs.read(TIME_LOW) as u64;
+    let high = regs.read(TIME_HIGH) as u64;
+    let nanos = (high << 32) | low;
+    (nanos != 0).then_some(nanos)
+}
+
+/// A UTC date and time, shown as `YYYY-MM-DD HH:MM:SS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// The date and time `seconds` after the Unix epoch
+    pub fn from_unix(seconds: u64) -> Self {
+        let days = seconds / 86_400;
+        let rest = seconds % 86_400;
+
+        // Civil date from a day count, counting years from March so the
+        // leap day falls at the end
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z % 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+        DateTime {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (rest / 3600) as u8,
+            minute: (rest / 60 % 60) as u8,
+            second: (rest % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+               self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
@@ -2,7 +2,7 @@
 // This provides the interface between the kernel and the SBI firmware
 
 use core::arch::asm;
-use crate::console_println;
+use crate::{console_println, err_println, info_println};
 
 // SBI function IDs
 const SBI_CONSOLE_PUTCHAR: usize = 0x1;
@@ -13,7 +13,8 @@ const SBI_SHUTDOWN: usize = 0x8;
 const SBI_EXT_BASE: usize = 0x10;
 const SBI_EXT_TIMER: usize = 0x54494D45;
 const SBI_EXT_IPI: usize = 0x735049;
-// Removed unused SBI extensions: RFENCE, HSM
+pub const SBI_EXT_HSM: usize = 0x48534D;
+// Removed unused SBI extensions: RFENCE
 const SBI_EXT_SRST: usize = 0x53525354;
 
 // SBI reset types
@@ -24,6 +25,23 @@ const SBI_SRST_RESET_TYPE_COLD_REBOOT: u32 = 1;
 // SBI reset reasons
 const SBI_SRST_RESET_REASON_NONE: u32 = 0;
 
+// SBI HSM functions
+const SBI_HSM_HART_START: usize = 0;
+const SBI_HSM_HART_STOP: usize = 1;
+const SBI_HSM_HART_GET_STATUS: usize = 2;
+
+/// What the SBI says a hart is doing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HartState {
+    Started,
+    Stopped,
+    StartPending,
+    StopPending,
+    Suspended,
+    SuspendPending,
+    ResumePending,
+}
+
 // SBI return values
 #[derive(Debug, Clone, Copy)]
 pub struct SbiRet {
@@ -78,7 +96,7 @@ pub fn console_getchar() -> Option<usize> {
 // System shutdown
 pub fn system_shutdown() -> ! {
     // Use console print fallback since console_println might not be available here
-    console_println!("[i] Initiating system shutdown via SBI...\n");
+    info_println!("Initiating system shutdown via SBI...\n");
     
     // Try newer SBI system reset extension first
     let ret = sbi_call(SBI_EXT_SRST, 0, SBI_SRST_RESET_TYPE_SHUTDOWN as usize, SBI_SRST_RESET_REASON_NONE as usize, 0);
@@ -100,12 +118,12 @@ pub fn system_shutdown() -> ! {
 // System reset/reboot
 pub fn system_reset() -> ! {
     // Use console print fallback since console_println might not be available here
-    console_println!("[i] Initiating system reboot via SBI...\n");
+    info_println!("Initiating system reboot via SBI...\n");
     
     // Try SBI system reset extension
     let ret = sbi_call(SBI_EXT_SRST, 0, SBI_SRST_RESET_TYPE_COLD_REBOOT as usize, SBI_SRST_RESET_REASON_NONE as usize, 0);
     
-    console_println!("[x] SBI reset failed (error: {}), halting\n", ret.error);
+    err_println!("SBI reset failed (error: {}), halting\n", ret.error);
     loop {
         unsafe {
             asm!("wfi");
@@ -115,15 +133,8 @@ pub fn system_reset() -> ! {
 
 // Get memory information
 pub fn get_memory_info() -> (usize, usize) {
-    // For QEMU virt machine, we know the standard memory layout
-    // In a real implementation, this would query the SBI or device tree
-    
-    // Standard QEMU virt memory layout:
-    // RAM: 0x80000000 - varies (usually 128MB)
-    let base = 0x80000000;
-    let size = 128 * 1024 * 1024; // 128MB default
-    
-    (base, size)
+    // From the device tree, or QEMU virt's 128MB at 0x80000000 without one
+    crate::platform::ram()
 }
 
 // Get memory regions (for compatibility with memory detection)
@@ -134,16 +145,17 @@ pub fn get_memory_regions() -> SbiMemoryInfo {
     };
     
     // Add main RAM region
+    let (ram_base, ram_size) = crate::platform::ram();
     info.regions[0] = SbiMemoryRegion {
-        start: 0x80000000,
-        size: 128 * 1024 * 1024, // 128MB
+        start: ram_base,
+        size: ram_size,
         flags: 1, // RAM
     };
     info.count = 1;
     
     // Add MMIO regions
     info.regions[1] = SbiMemoryRegion {
-        start: 0x10000000,
+        start: crate::platform::uart().base,
         size: 0x1000, // UART
         flags: 0, // MMIO
     };
@@ -176,6 +188,42 @@ pub fn send_ipi(hart_mask: usize) {
     sbi_call(SBI_EXT_IPI, 0, hart_mask, 0, 0);
 }
 
+/// Start hart `hartid` in supervisor mode at `start_addr`, with the hart ID
+/// in a0 and `opaque` in a1, translation off and interrupts disabled
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> Result<(), isize> {
+    let ret = sbi_call(SBI_EXT_HSM, SBI_HSM_HART_START, hartid, start_addr, opaque);
+    if ret.error == 0 { Ok(()) } else { Err(ret.error) }
+}
+
+/// Stop the calling hart; it can be started again with `hart_start`
+pub fn hart_stop() -> ! {
+    sbi_call(SBI_EXT_HSM, SBI_HSM_HART_STOP, 0, 0, 0);
+    // Only returns if the SBI refused
+    loop {
+        unsafe {
+            asm!("wfi");
+        }
+    }
+}
+
+/// State of hart `hartid`, None if there is no such hart
+pub fn hart_status(hartid: usize) -> Option<HartState> {
+    let ret = sbi_call(SBI_EXT_HSM, SBI_HSM_HART_GET_STATUS, hartid, 0, 0);
+    if ret.error != 0 {
+        return None;
+    }
+    match ret.value {
+        0 => Some(HartState::Started),
+        1 => Some(HartState::Stopped),
+        2 => Some(HartState::StartPending),
+        3 => Some(HartState::StopPending),
+        4 => Some(HartState::Suspended),
+        5 => Some(HartState::SuspendPending),
+        6 => Some(HartState::ResumePending),
+        _ => None,
+    }
+}
+
 // Get SBI implementation ID
 pub fn get_sbi_impl_id() -> usize {
     let ret = sbi_call(SBI_EXT_BASE, 1, 0, 0, 0);
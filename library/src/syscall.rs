@@ -0,0 +1,209 @@
+//! System call numbers
+//!
+//! The single table of syscalls elinOS implements. The kernel dispatches on
+//! it, user programs take their `SYS_*` constants from it and the shell's
+//! `syscalls` command lists it, so none of them can drift apart. Numbers
+//! follow the Linux RISC-V ABI; elinOS-specific calls live at 900 and up.
+
+/// Which group of handlers a syscall belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Device,
+    Directory,
+    File,
+    Process,
+    Network,
+    Memory,
+    Elinos,
+    Time,
+    Graphics,
+}
+
+impl Category {
+    pub const ALL: [Category; 9] = [
+        Category::File,
+        Category::Directory,
+        Category::Memory,
+        Category::Process,
+        Category::Device,
+        Category::Network,
+        Category::Time,
+        Category::Graphics,
+        Category::Elinos,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Device => "Device and I/O Management",
+            Category::Directory => "Directory Operations",
+            Category::File => "File I/O Operations",
+            Category::Process => "Process Management",
+            Category::Network => "Network Operations",
+            Category::Memory => "Memory Management",
+            Category::Elinos => "elinOS-Specific Operations",
+            Category::Time => "Time and Sleep",
+            Category::Graphics => "Framebuffer Drawing",
+        }
+    }
+}
+
+/// One implemented system call
+#[derive(Debug, Clone, Copy)]
+pub struct Syscall {
+    pub number: usize,
+    pub name: &'static str,
+    pub constant: &'static str,
+    pub category: Category,
+}
+
+/// Define a `SYS_*` constant per entry plus the `SYSCALLS` table itself
+macro_rules! syscall_table {
+    ($($category:ident { $($constant:ident = $number:literal => $name:literal,)* })*) => {
+        $($(pub const $constant: usize = $number;)*)*
+
+        /// Every implemented syscall, grouped by category
+        pub const SYSCALLS: &[Syscall] = &[
+            $($(Syscall {
+                number: $number,
+                name: $name,
+                constant: stringify!($constant),
+                category: Category::$category,
+            },)*)*
+        ];
+    };
+}
+
+syscall_table! {
+    Device {
+        SYS_EVENTFD2 = 19 => "eventfd2",
+        SYS_DUP = 23 => "dup",
+        SYS_DUP3 = 24 => "dup3",
+        SYS_FCNTL = 25 => "fcntl",
+        SYS_INOTIFY_INIT1 = 26 => "inotify_init1",
+        SYS_INOTIFY_ADD_WATCH = 27 => "inotify_add_watch",
+        SYS_INOTIFY_RM_WATCH = 28 => "inotify_rm_watch",
+        SYS_IOCTL = 29 => "ioctl",
+        SYS_IOPRIO_SET = 30 => "ioprio_set",
+        SYS_IOPRIO_GET = 31 => "ioprio_get",
+        SYS_FLOCK = 32 => "flock",
+        SYS_MKNODAT = 33 => "mknodat",
+        SYS_PIPE2 = 59 => "pipe2",
+        SYS_PPOLL = 73 => "ppoll",
+        SYS_SYSLOG = 116 => "syslog",
+        SYS_GETDEVICES = 950 => "getdevices",
+    }
+    Directory {
+        SYS_MKDIR = 51 => "mkdir",
+        SYS_RMDIR = 52 => "rmdir",
+        SYS_CHDIR = 53 => "chdir",
+        SYS_GETCWD = 54 => "getcwd",
+    }
+    File {
+        SYS_UNLINKAT = 35 => "unlinkat",
+        SYS_TRUNCATE = 45 => "truncate",
+        SYS_FTRUNCATE = 46 => "ftruncate",
+        SYS_OPENAT = 56 => "openat",
+        SYS_CLOSE = 57 => "close",
+        SYS_GETDENTS64 = 61 => "getdents64",
+        SYS_LSEEK = 62 => "lseek",
+        SYS_READ = 63 => "read",
+        SYS_WRITE = 64 => "write",
+        SYS_READLINKAT = 78 => "readlinkat",
+        SYS_NEWFSTATAT = 79 => "newfstatat",
+        SYS_FSTAT = 80 => "fstat",
+        SYS_SYNC = 81 => "sync",
+        SYS_FSYNC = 82 => "fsync",
+    }
+    Process {
+        SYS_EXIT = 93 => "exit",
+        SYS_EXIT_GROUP = 94 => "exit_group",
+        SYS_WAITID = 95 => "waitid",
+        SYS_SCHED_YIELD = 124 => "sched_yield",
+        SYS_KILL = 129 => "kill",
+        SYS_SETGID = 144 => "setgid",
+        SYS_SETUID = 146 => "setuid",
+        SYS_SETPGID = 154 => "setpgid",
+        SYS_GETPGID = 155 => "getpgid",
+        SYS_SETSID = 157 => "setsid",
+        SYS_PRCTL = 167 => "prctl",
+        SYS_GETPID = 172 => "getpid",
+        SYS_GETPPID = 173 => "getppid",
+        SYS_GETUID = 174 => "getuid",
+        SYS_GETEUID = 175 => "geteuid",
+        SYS_GETGID = 176 => "getgid",
+        SYS_GETEGID = 177 => "getegid",
+        SYS_GETTID = 178 => "gettid",
+        SYS_CLONE = 220 => "clone",
+        SYS_EXECVE = 221 => "execve",
+        SYS_WAIT4 = 260 => "wait4",
+    }
+    Network {
+        SYS_SOCKET = 198 => "socket",
+        SYS_BIND = 200 => "bind",
+        SYS_CONNECT = 203 => "connect",
+        SYS_SENDTO = 206 => "sendto",
+        SYS_RECVFROM = 207 => "recvfrom",
+    }
+    Memory {
+        SYS_BRK = 214 => "brk",
+        SYS_MUNMAP = 215 => "munmap",
+        SYS_MREMAP = 216 => "mremap",
+        SYS_MMAP = 222 => "mmap",
+        SYS_MPROTECT = 226 => "mprotect",
+        SYS_MSYNC = 227 => "msync",
+        SYS_MLOCK = 228 => "mlock",
+        SYS_MUNLOCK = 229 => "munlock",
+        SYS_MLOCKALL = 230 => "mlockall",
+        SYS_MUNLOCKALL = 231 => "munlockall",
+        SYS_MINCORE = 232 => "mincore",
+        SYS_MADVISE = 233 => "madvise",
+        SYS_GETMEMINFO = 960 => "getmeminfo",
+        SYS_ALLOC_TEST = 961 => "alloc_test",
+        SYS_BUDDY_STATS = 962 => "buddy_stats",
+    }
+    Time {
+        SYS_TIMERFD_CREATE = 85 => "timerfd_create",
+        SYS_TIMERFD_SETTIME = 86 => "timerfd_settime",
+        SYS_TIMERFD_GETTIME = 87 => "timerfd_gettime",
+        SYS_NANOSLEEP = 101 => "nanosleep",
+        SYS_CLOCK_GETTIME = 113 => "clock_gettime",
+        SYS_GETTIMEOFDAY = 169 => "gettimeofday",
+    }
+    Elinos {
+        SYS_ELINOS_DEBUG = 900 => "elinos_debug",
+        SYS_ELINOS_VERSION = 902 => "elinos_version",
+        SYS_ELINOS_SHUTDOWN = 903 => "elinos_shutdown",
+        SYS_ELINOS_REBOOT = 904 => "elinos_reboot",
+        SYS_LOAD_ELF = 905 => "load_elf",
+        SYS_EXEC_ELF = 906 => "exec_elf",
+        SYS_ELF_INFO = 907 => "elf_info",
+        SYS_EVENTFD_WATCH = 908 => "eventfd_watch",
+    }
+    Graphics {
+        SYS_GFX_INFO = 920 => "gfx_info",
+        SYS_GFX_FILL = 921 => "gfx_fill",
+        SYS_GFX_LINE = 922 => "gfx_line",
+        SYS_GFX_CIRCLE = 923 => "gfx_circle",
+        SYS_GFX_BLIT = 924 => "gfx_blit",
+        SYS_GFX_IMAGE = 925 => "gfx_image",
+        SYS_GFX_PRESENT = 926 => "gfx_present",
+    }
+}
+
+// Two entries sharing a number would make one of them unreachable
+const _: () = {
+    let mut i = 0;
+    while i < SYSCALLS.len() {
+        let mut j = i + 1;
+        while j < SYSCALLS.len() {
+            assert!(SYSCALLS[i].number != SYSCALLS[j].number, "duplicate syscall number");
+            j += 1;
+        }
+        i += 1;
+    }
+};
+
+/// The table entry for syscall `number`, if it is implemented
+pub fn lookup(number: usize) -> Option<&'static Syscall> {
+    SYSCALLS.iter().find(|syscall| syscall.number == number)
+}
@@ -0,0 +1,59 @@
+//! Time keeping for timeouts and round-trip measurements, from the `time` CSR
+//!
+//! The CSR counts at the timebase frequency the device tree gives for
+//! `/cpus`. Until the boot code reads it and calls `set_frequency`, the
+//! 10 MHz of the QEMU virt machine is assumed. Conversions go through u128
+//! so they stay exact at any frequency, not only divisors of 1 GHz.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Timebase frequency of the QEMU virt machine, used when none is known
+pub const DEFAULT_TIMEBASE_HZ: u64 = 10_000_000;
+
+const NS_PER_SEC: u128 = 1_000_000_000;
+
+static TIMEBASE_HZ: AtomicU64 = AtomicU64::new(DEFAULT_TIMEBASE_HZ);
+
+/// Use `hz` as the rate of the `time` CSR; 0 is ignored
+pub fn set_frequency(hz: u64) {
+    if hz != 0 {
+        TIMEBASE_HZ.store(hz, Ordering::Relaxed);
+    }
+}
+
+/// Rate of the `time` CSR in Hz
+pub fn frequency() -> u64 {
+    TIMEBASE_HZ.load(Ordering::Relaxed)
+}
+
+/// Current tick count
+pub fn ticks() -> u64 {
+    riscv::register::time::read64()
+}
+
+/// Nanoseconds in `ticks` ticks, rounded down
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let ns = ticks as u128 * NS_PER_SEC / frequency() as u128;
+    ns.min(u64::MAX as u128) as u64
+}
+
+/// Ticks in `ns` nanoseconds, rounded up so waits are never short
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    let ticks = (ns as u128 * frequency() as u128).div_ceil(NS_PER_SEC);
+    ticks.min(u64::MAX as u128) as u64
+}
+
+/// Ticks in `ms` milliseconds
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ns_to_ticks(ms.saturating_mul(1_000_000))
+}
+
+/// Milliseconds elapsed since `start` (a value from `ticks`)
+pub fn elapsed_ms(start: u64) -> u64 {
+    ticks_to_ns(ticks().wrapping_sub(start)) / 1_000_000
+}
+
+/// Microseconds elapsed since `start` (a value from `ticks`)
+pub fn elapsed_us(start: u64) -> u64 {
+    ticks_to_ns(ticks().wrapping_sub(start)) / 1_000
+}
@@ -0,0 +1,139 @@
+//! One terminal over every console device
+//!
+//! Everything printed goes through `write_str`, which sends it to the UART
+//! and then to each sink the kernel registered, such as the framebuffer
+//! console. Input is read with `read_byte`, which asks each registered
+//! source in turn, such as pasted text or a keyboard, and then the UART.
+//! The UART is built in so the bootloader and early boot need nothing
+//! registered; the rest are plain functions, as the drivers behind them
+//! live in the kernel.
+//!
+//! A sink that prints while it is being written to, e.g. a driver reporting
+//! an error from inside the framebuffer console, is not entered again: text
+//! written meanwhile goes to the UART alone. Sinks may hold back what they
+//! draw until `flush`, which `read_byte` calls when there is no input, so a
+//! screen is brought up to date once the reader has to wait.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::Vec;
+use spin::Mutex;
+
+/// Sinks and sources that can be registered
+pub const MAX_SINKS: usize = 4;
+pub const MAX_SOURCES: usize = 4;
+
+/// Where console output goes besides the UART
+#[derive(Debug, Clone, Copy)]
+pub struct Sink {
+    pub name: &'static str,
+    pub write: fn(&str),
+    /// Bring what was written up to date, e.g. present the screen
+    pub flush: fn(),
+}
+
+/// Where console input comes from besides the UART
+#[derive(Debug, Clone, Copy)]
+pub struct Source {
+    pub name: &'static str,
+    /// The next byte, if one is waiting; must not block
+    pub read: fn() -> Option<u8>,
+}
+
+static SINKS: Mutex<Vec<Sink, MAX_SINKS>> = Mutex::new(Vec::new());
+static SOURCES: Mutex<Vec<Source, MAX_SOURCES>> = Mutex::new(Vec::new());
+
+/// Set while the sinks are being written to or flushed
+static IN_SINK: AtomicBool = AtomicBool::new(false);
+
+/// Send output to `sink` from now on; a sink of the same name is replaced
+pub fn add_sink(sink: Sink) -> Result<(), &'static str> {
+    let mut sinks = SINKS.lock();
+    match sinks.iter_mut().find(|s| s.name == sink.name) {
+        Some(existing) => *existing = sink,
+        None => sinks.push(sink).map_err(|_| "Too many console sinks")?,
+    }
+    Ok(())
+}
+
+/// Read input from `source` from now on, before the UART; a source of the
+/// same name is replaced
+pub fn add_source(source: Source) -> Result<(), &'static str> {
+    let mut sources = SOURCES.lock();
+    match sources.iter_mut().find(|s| s.name == source.name) {
+        Some(existing) => *existing = source,
+        None => sources.push(source).map_err(|_| "Too many console sources")?,
+    }
+    Ok(())
+}
+
+/// Names of the registered sinks, then of the sources, the UART first
+pub fn devices() -> (Vec<&'static str, { MAX_SINKS + 1 }>, Vec<&'static str, { MAX_SOURCES + 1 }>) {
+    let mut sinks = Vec::new();
+    let _ = sinks.push("uart");
+    for sink in SINKS.lock().iter() {
+        let _ = sinks.push(sink.name);
+    }
+    let mut sources = Vec::new();
+    for source in SOURCES.lock().iter() {
+        let _ = sources.push(source.name);
+    }
+    let _ = sources.push("uart");
+    (sinks, sources)
+}
+
+/// Write `s` to the UART and to every sink
+pub fn write_str(s: &str) {
+    {
+        let mut uart = crate::uart::UART.lock();
+        let _ = uart.write_str(s);
+    }
+    with_sinks(|sink| (sink.write)(s));
+}
+
+/// Write formatted text as `write_str` does
+pub fn write_fmt(args: fmt::Arguments) -> fmt::Result {
+    Tty.write_fmt(args)
+}
+
+/// Bring every sink up to date
+pub fn flush() {
+    with_sinks(|sink| (sink.flush)());
+}
+
+/// The next input byte from the sources, then the UART; flushes the sinks
+/// if there is none, as the reader is about to wait
+pub fn read_byte() -> Option<u8> {
+    // Copied out so a source may print, or register another
+    let sources = SOURCES.lock().clone();
+    let byte = sources.iter()
+        .find_map(|source| (source.read)())
+        .or_else(|| crate::uart::UART.lock().getchar());
+    if byte.is_none() {
+        flush();
+    }
+    byte
+}
+
+/// Call `f` with each sink, unless a sink is already being used further
+/// up the stack
+fn with_sinks(mut f: impl FnMut(&Sink)) {
+    if IN_SINK.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let sinks = SINKS.lock().clone();
+    for sink in sinks.iter() {
+        f(sink);
+    }
+    IN_SINK.store(false, Ordering::Release);
+}
+
+/// `fmt::Write` over `write_str`
+pub struct Tty;
+
+impl Write for Tty {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_str(s);
+        Ok(())
+    }
+}
@@ -1,21 +1,77 @@
 // Minimal UART Driver for RISC-V
-// Simple implementation for early boot and debugging only
+// Output is polled; input is polled until `enable_rx_interrupt`, then the
+// interrupt handler moves received bytes into a ring buffer
 
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use spin::Mutex;
+use crate::mmio::{Register, RegisterBlock};
 
-// UART memory-mapped register addresses for QEMU virt machine
-pub const UART_BASE: usize = 0x10000000;
+// 16550 registers
+const THR: Register<u8> = Register::at(0); // Transmit holding (write)
+const RBR: Register<u8> = Register::at(0); // Receive buffer (read)
+const IER: Register<u8> = Register::at(1); // Interrupt enable
+const LSR: Register<u8> = Register::at(5); // Line status
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const LSR_DATA_READY: u8 = 1 << 0;
 
-pub struct Uart {
-    base_addr: usize,
+/// Received bytes waiting to be read; a power of two
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Bytes received and not yet read. Filling it only advances `head` and
+/// reading only `tail`, so neither needs a lock: the interrupt handler may
+/// come in while a writer holds the UART lock.
+struct RxRing {
+    data: [AtomicU8; RX_BUFFER_SIZE],
+    head: AtomicUsize,
+    tail: AtomicUsize,
 }
 
+impl RxRing {
+    fn push(&self, byte: u8) {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == RX_BUFFER_SIZE {
+            return;
+        }
+        self.data[head % RX_BUFFER_SIZE].store(byte, Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let byte = self.data[tail % RX_BUFFER_SIZE].load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tail.load(Ordering::Relaxed) == self.head.load(Ordering::Acquire)
+    }
+}
+
+static RX_RING: RxRing = RxRing {
+    data: [const { AtomicU8::new(0) }; RX_BUFFER_SIZE],
+    head: AtomicUsize::new(0),
+    tail: AtomicUsize::new(0),
+};
+
+/// Input arrives through the ring buffer rather than by polling
+static RX_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+
+/// The console UART, wherever `platform` says it is: QEMU virt's until
+/// the device tree has been read
+pub struct Uart;
+
 impl Uart {
     pub const fn new() -> Self {
-        Uart {
-            base_addr: UART_BASE,
-        }
+        Uart
+    }
+
+    fn regs(&self) -> RegisterBlock {
+        unsafe { RegisterBlock::new(crate::platform::uart().base) }
     }
 
     pub fn init(&self) {
@@ -25,37 +81,47 @@ impl Uart {
 
     // Write a single character (minimal implementation)
     pub fn putchar(&self, ch: u8) {
-        unsafe {
-            let ptr = self.base_addr as *mut u8;
-            // Simple write - QEMU handles the rest
-            ptr.write_volatile(ch);
-        }
+        // Simple write - QEMU handles the rest
+        self.regs().write(THR, ch);
+    }
+
+    /// Raise an interrupt for received data, and read input from the ring
+    /// buffer `handle_interrupt` fills from then on
+    pub fn enable_rx_interrupt(&self) {
+        RX_INTERRUPTS.store(true, Ordering::Release);
+        self.regs().write(IER, IER_RX_AVAILABLE);
     }
 
     // Read a single character (blocking)
     pub fn getc(&self) -> u8 {
-        unsafe {
-            let ptr = self.base_addr as *mut u8;
-            // Simple polling read
-            loop {
-                let status = ptr.add(5).read_volatile();
-                if status & 1 != 0 {
-                    return ptr.read_volatile();
-                }
+        loop {
+            if let Some(ch) = self.getchar() {
+                return ch;
             }
+            wait_for_input();
         }
     }
 
     // Try to read a character (non-blocking)
     pub fn getchar(&self) -> Option<u8> {
-        unsafe {
-            let ptr = self.base_addr as *mut u8;
-            let status = ptr.add(5).read_volatile();
-            if status & 1 != 0 {
-                Some(ptr.read_volatile())
-            } else {
-                None
-            }
+        if !RX_INTERRUPTS.load(Ordering::Acquire) {
+            return self.read_received();
+        }
+        // Bytes the interrupt has not picked up yet go behind the buffered
+        // ones; with interrupts off, e.g. in a system call, this is the
+        // only way they arrive
+        without_interrupts(|| {
+            handle_interrupt();
+            RX_RING.pop()
+        })
+    }
+
+    fn read_received(&self) -> Option<u8> {
+        let regs = self.regs();
+        if regs.read(LSR) & LSR_DATA_READY != 0 {
+            Some(regs.read(RBR))
+        } else {
+            None
         }
     }
 }
@@ -70,4 +136,41 @@ impl Write for Uart {
 }
 
 // Global UART instance
-pub static UART: Mutex<Uart> = Mutex::new(Uart::new()); 
\ No newline at end of file
+pub static UART: Mutex<Uart> = Mutex::new(Uart::new());
+
+/// Move every received byte into the ring buffer; call on the UART
+/// interrupt. Takes no lock, as the interrupted code may hold the UART.
+/// Bytes that do not fit are dropped.
+pub fn handle_interrupt() {
+    let uart = Uart::new();
+    while let Some(byte) = uart.read_received() {
+        RX_RING.push(byte);
+    }
+}
+
+/// Sleep in `wfi` until input or another interrupt arrives, unless input
+/// is already waiting. Returns at once while input is polled.
+pub fn wait_for_input() {
+    if !RX_INTERRUPTS.load(Ordering::Acquire) {
+        return;
+    }
+    // Checked with interrupts off, so input arriving after the check still
+    // wakes the wfi, which does not need them on
+    without_interrupts(|| {
+        if RX_RING.is_empty() && Uart::new().regs().read(LSR) & LSR_DATA_READY == 0 {
+            unsafe { core::arch::asm!("wfi", options(nostack)) };
+        }
+    });
+}
+
+/// Run `f` with supervisor interrupts held off, then restore them
+fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let sstatus: usize;
+    unsafe { core::arch::asm!("csrrci {}, sstatus, 2", out(reg) sstatus, options(nostack)) };
+    let result = f();
+    if sstatus & 2 != 0 {
+        unsafe { core::arch::asm!("csrsi sstatus, 2", options(nostack)) };
+    }
+    result
+}
+
@@ -0,0 +1,153 @@
+//! VirtIO MMIO register definitions and constants
+//! Based on VirtIO 1.1 specification
+
+use crate::mmio::{Register, RegisterBlock, RegisterValue};
+
+// === VIRTIO MMIO REGISTER OFFSETS ===
+pub const VIRTIO_MMIO_MAGIC_VALUE: Register<u32> = Register::at(0x000);      // 0x74726976
+pub const VIRTIO_MMIO_VERSION: Register<u32> = Register::at(0x004);          // Version (1=legacy, 2=modern)
+pub const VIRTIO_MMIO_DEVICE_ID: Register<u32> = Register::at(0x008);        // Device ID (2=block, 16=gpu)
+pub const VIRTIO_MMIO_VENDOR_ID: Register<u32> = Register::at(0x00c);        // Vendor ID
+pub const VIRTIO_MMIO_DEVICE_FEATURES: Register<u32> = Register::at(0x010);  // Device features
+pub const VIRTIO_MMIO_DEVICE_FEATURES_SEL: Register<u32> = Register::at(0x014); // Device features select
+pub const VIRTIO_MMIO_DRIVER_FEATURES: Register<u32> = Register::at(0x020);  // Driver features
+pub const VIRTIO_MMIO_DRIVER_FEATURES_SEL: Register<u32> = Register::at(0x024); // Driver features select
+pub const VIRTIO_MMIO_GUEST_PAGE_SIZE: Register<u32> = Register::at(0x028);  // Guest page size (legacy only)
+pub const VIRTIO_MMIO_QUEUE_SEL: Register<u32> = Register::at(0x030);        // Queue select
+pub const VIRTIO_MMIO_QUEUE_NUM_MAX: Register<u32> = Register::at(0x034);    // Queue size max
+pub const VIRTIO_MMIO_QUEUE_NUM: Register<u32> = Register::at(0x038);        // Queue size
+pub const VIRTIO_MMIO_QUEUE_ALIGN: Register<u32> = Register::at(0x03c);      // Queue alignment (legacy only)
+pub const VIRTIO_MMIO_QUEUE_PFN: Register<u32> = Register::at(0x040);        // Queue PFN (legacy only)
+pub const VIRTIO_MMIO_QUEUE_READY: Register<u32> = Register::at(0x044);      // Queue ready
+pub const VIRTIO_MMIO_QUEUE_NOTIFY: Register<u32> = Register::at(0x050);     // Queue notify
+pub const VIRTIO_MMIO_INTERRUPT_STATUS: Register<u32> = Register::at(0x060); // Interrupt status
+pub const VIRTIO_MMIO_INTERRUPT_ACK: Register<u32> = Register::at(0x064);    // Interrupt acknowledge
+pub const VIRTIO_MMIO_STATUS: Register<u32> = Register::at(0x070);           // Device status
+pub const VIRTIO_MMIO_QUEUE_DESC_LOW: Register<u32> = Register::at(0x080);   // Queue descriptor low
+pub const VIRTIO_MMIO_QUEUE_DESC_HIGH: Register<u32> = Register::at(0x084);  // Queue descriptor high
+pub const VIRTIO_MMIO_QUEUE_DRIVER_LOW: Register<u32> = Register::at(0x090); // Queue driver low
+pub const VIRTIO_MMIO_QUEUE_DRIVER_HIGH: Register<u32> = Register::at(0x094); // Queue driver high
+pub const VIRTIO_MMIO_QUEUE_DEVICE_LOW: Register<u32> = Register::at(0x0a0); // Queue device low
+pub const VIRTIO_MMIO_QUEUE_DEVICE_HIGH: Register<u32> = Register::at(0x0a4); // Queue device high
+pub const VIRTIO_MMIO_CONFIG: usize = 0x100;           // Configuration space
+
+/// A register in the device-specific configuration space
+pub const fn config_register<T: RegisterValue>(offset: usize) -> Register<T> {
+    Register::at(VIRTIO_MMIO_CONFIG + offset)
+}
+
+/// Value of the magic register ("virt")
+pub const VIRTIO_MMIO_MAGIC: u32 = 0x74726976;
+
+/// Size of one device's MMIO register window
+pub const VIRTIO_MMIO_SIZE: usize = 0x1000;
+
+/// A VirtIO MMIO slot with a device behind it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioSlot {
+    pub base: usize,
+    pub device_id: u32,
+    pub version: u32,
+}
+
+/// Read the slot at `base`; None when it is not a VirtIO transport or
+/// has no device (device ID 0)
+pub fn probe_slot(base: usize) -> Option<MmioSlot> {
+    let regs = unsafe { RegisterBlock::new(base) };
+    if regs.read(VIRTIO_MMIO_MAGIC_VALUE) != VIRTIO_MMIO_MAGIC {
+        return None;
+    }
+    let device_id = regs.read(VIRTIO_MMIO_DEVICE_ID);
+    if device_id == 0 {
+        return None;
+    }
+    Some(MmioSlot { base, device_id, version: regs.read(VIRTIO_MMIO_VERSION) })
+}
+
+/// Every populated slot, in address order
+pub fn enumerate() -> impl Iterator<Item = MmioSlot> {
+    slot_bases().filter_map(probe_slot)
+}
+
+/// Base address of every VirtIO MMIO slot the machine has, populated or
+/// not; see `platform::virtio_slots`
+pub fn slot_bases() -> impl Iterator<Item = usize> {
+    crate::platform::virtio_slots().iter().map(|slot| slot.base)
+}
+
+// === VIRTIO DEVICE IDS ===
+pub const VIRTIO_ID_NET: u32 = 1;
+pub const VIRTIO_ID_BLOCK: u32 = 2;
+pub const VIRTIO_ID_CONSOLE: u32 = 3;
+pub const VIRTIO_ID_RNG: u32 = 4;
+pub const VIRTIO_ID_BALLOON: u32 = 5;
+pub const VIRTIO_ID_RPMSG: u32 = 7;
+pub const VIRTIO_ID_SCSI: u32 = 8;
+pub const VIRTIO_ID_9P: u32 = 9;
+pub const VIRTIO_ID_RPROC_SERIAL: u32 = 11;
+pub const VIRTIO_ID_CAIF: u32 = 12;
+pub const VIRTIO_ID_GPU: u32 = 16;
+pub const VIRTIO_ID_INPUT: u32 = 18;
+pub const VIRTIO_ID_SOUND: u32 = 25;
+
+// === VIRTIO STATUS BITS ===
+pub const VIRTIO_STATUS_ACKNOWLEDGE: u32 = 1;
+pub const VIRTIO_STATUS_DRIVER: u32 = 2;
+pub const VIRTIO_STATUS_DRIVER_OK: u32 = 4;
+pub const VIRTIO_STATUS_FEATURES_OK: u32 = 8;
+pub const VIRTIO_STATUS_DEVICE_NEEDS_RESET: u32 = 64;
+pub const VIRTIO_STATUS_FAILED: u32 = 128;
+
+// === FEATURE BITS ===
+pub const VIRTIO_RING_F_INDIRECT_DESC: u64 = 1 << 28;
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// === MEMORY CONSTANTS ===
+pub const PAGE_SIZE: usize = 4096;
+
+/// Align up to the next page boundary
+pub fn align_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+// === VIRTQ DESCRIPTOR FLAGS ===
+pub const VIRTQ_DESC_F_NEXT: u16 = 1;       // This descriptor continues via next field
+pub const VIRTQ_DESC_F_WRITE: u16 = 2;      // Device writes to this descriptor
+pub const VIRTQ_DESC_F_INDIRECT: u16 = 4;   // Points to indirect table
+
+// === VIRTQ RING FLAGS ===
+pub const VIRTQ_AVAIL_F_NO_INTERRUPT: u16 = 1;
+pub const VIRTQ_USED_F_NO_NOTIFY: u16 = 1;
+
+// === VIRTIO GPU CONSTANTS ===
+pub const VIRTIO_GPU_CONTROLQ: u16 = 0;
+pub const VIRTIO_GPU_CURSORQ: u16 = 1;
+
+// VirtIO GPU Commands
+pub const VIRTIO_GPU_CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+pub const VIRTIO_GPU_CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+pub const VIRTIO_GPU_CMD_RESOURCE_UNREF: u32 = 0x0102;
+pub const VIRTIO_GPU_CMD_SET_SCANOUT: u32 = 0x0103;
+pub const VIRTIO_GPU_CMD_RESOURCE_FLUSH: u32 = 0x0104;
+pub const VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+pub const VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+
+// VirtIO GPU Response Types
+pub const VIRTIO_GPU_RESP_OK_NODATA: u32 = 0x1100;
+pub const VIRTIO_GPU_RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+pub const VIRTIO_GPU_RESP_ERR_UNSPEC: u32 = 0x1200;
+pub const VIRTIO_GPU_RESP_ERR_OUT_OF_MEMORY: u32 = 0x1201;
+pub const VIRTIO_GPU_RESP_ERR_INVALID_SCANOUT_ID: u32 = 0x1202;
+pub const VIRTIO_GPU_RESP_ERR_INVALID_RESOURCE_ID: u32 = 0x1203;
+pub const VIRTIO_GPU_RESP_ERR_INVALID_CONTEXT_ID: u32 = 0x1204;
+pub const VIRTIO_GPU_RESP_ERR_INVALID_PARAMETER: u32 = 0x1205;
+
+// VirtIO GPU Formats
+pub const VIRTIO_GPU_FORMAT_B8G8R8A8_UNORM: u32 = 1;
+pub const VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM: u32 = 2;
+pub const VIRTIO_GPU_FORMAT_A8R8G8B8_UNORM: u32 = 3;
+pub const VIRTIO_GPU_FORMAT_X8R8G8B8_UNORM: u32 = 4;
+pub const VIRTIO_GPU_FORMAT_R8G8B8A8_UNORM: u32 = 67;  // Format used in working examples
+pub const VIRTIO_GPU_FORMAT_X8B8G8R8_UNORM: u32 = 68;
+pub const VIRTIO_GPU_FORMAT_A8B8G8R8_UNORM: u32 = 121;
+pub const VIRTIO_GPU_FORMAT_R8G8B8X8_UNORM: u32 = 134; 
\ No newline at end of file
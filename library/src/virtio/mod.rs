@@ -0,0 +1,5 @@
+//! VirtIO definitions shared by the bootloader and kernel drivers
+
+pub mod mmio;
+pub mod transport;
+pub mod storage;
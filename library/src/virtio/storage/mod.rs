@@ -0,0 +1,5 @@
+//! Storage layer shared by the block drivers: how a disk is divided up
+
+pub mod partition;
+
+pub use partition::{Partition, PartitionKind, PartitionScheme, PartitionTable, MAX_PARTITIONS, read_partition_table};
@@ -0,0 +1,288 @@
+//! MBR and GPT partition tables
+//!
+//! A GPT is used when the MBR holds a protective 0xEE entry and the GPT
+//! header and entry array pass their CRC checks. Otherwise the MBR's
+//! primary partitions are listed, followed by the logical partitions of an
+//! extended partition, numbered from 5 as Linux does. A disk without a boot
+//! signature, such as a bare filesystem image, has no partition table.
+
+use heapless::{String, Vec};
+use crate::blockcache::{SectorDevice, SECTOR_SIZE};
+use crate::crc32::crc32_update;
+
+/// Most partitions kept per disk
+pub const MAX_PARTITIONS: usize = 16;
+
+/// Most logical partitions followed, so a looping EBR chain ends
+const MAX_LOGICAL: usize = 12;
+
+/// Largest GPT entry array read, in bytes
+const MAX_GPT_ENTRY_BYTES: u64 = 256 * 1024;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// Partition table format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionScheme {
+    Mbr,
+    Gpt,
+}
+
+impl PartitionScheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PartitionScheme::Mbr => "MBR",
+            PartitionScheme::Gpt => "GPT",
+        }
+    }
+}
+
+/// A GUID in its on-disk byte order
+pub type Guid = [u8; 16];
+
+/// Build a GUID from its textual fields, e.g.
+/// 0FC63DAF-8483-4772-8E79-3D69D8477DE4 is
+/// `guid(0x0FC63DAF, 0x8483, 0x4772, [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4])`
+pub const fn guid(a: u32, b: u16, c: u16, d: [u8; 8]) -> Guid {
+    let a = a.to_le_bytes();
+    let b = b.to_le_bytes();
+    let c = c.to_le_bytes();
+    [a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]]
+}
+
+pub const GPT_TYPE_LINUX_FS: Guid = guid(0x0FC63DAF, 0x8483, 0x4772, [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4]);
+pub const GPT_TYPE_EFI_SYSTEM: Guid = guid(0xC12A7328, 0xF81F, 0x11D2, [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B]);
+pub const GPT_TYPE_LINUX_SWAP: Guid = guid(0x0657FD6D, 0xA4AB, 0x43C4, [0x84, 0xE5, 0x09, 0x33, 0xC8, 0x4B, 0x4F, 0x4F]);
+pub const GPT_TYPE_BASIC_DATA: Guid = guid(0xEBD0A0A2, 0xB9E5, 0x4433, [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7]);
+
+/// Partition type, as the table records it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionKind {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+impl PartitionKind {
+    /// Common name of the type, "unknown" if it is not a well-known one
+    pub fn description(&self) -> &'static str {
+        match *self {
+            PartitionKind::Mbr(0x83) => "Linux",
+            PartitionKind::Mbr(0x82) => "Linux swap",
+            PartitionKind::Mbr(0x0B | 0x0C) => "FAT32",
+            PartitionKind::Mbr(0x07) => "NTFS/exFAT",
+            PartitionKind::Mbr(0xEF) => "EFI System",
+            PartitionKind::Mbr(MBR_TYPE_GPT_PROTECTIVE) => "GPT protective",
+            PartitionKind::Gpt(GPT_TYPE_LINUX_FS) => "Linux filesystem",
+            PartitionKind::Gpt(GPT_TYPE_EFI_SYSTEM) => "EFI System",
+            PartitionKind::Gpt(GPT_TYPE_LINUX_SWAP) => "Linux swap",
+            PartitionKind::Gpt(GPT_TYPE_BASIC_DATA) => "Basic data",
+            _ => "unknown",
+        }
+    }
+}
+
+/// One partition of a disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// Partition number as Linux counts them, from 1
+    pub number: u8,
+    /// First sector
+    pub start: u64,
+    /// Length in sectors
+    pub sectors: u64,
+    pub kind: PartitionKind,
+    /// GPT partition name; empty for MBR partitions
+    pub name: String<36>,
+}
+
+/// The partitions found on a disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionTable {
+    pub scheme: PartitionScheme,
+    pub partitions: Vec<Partition, MAX_PARTITIONS>,
+}
+
+impl PartitionTable {
+    /// Partition `number`, if the table has it
+    pub fn get(&self, number: u8) -> Option<&Partition> {
+        self.partitions.iter().find(|partition| partition.number == number)
+    }
+}
+
+fn le_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn le_u64(bytes: &[u8], offset: usize) -> u64 {
+    (le_u32(bytes, offset) as u64) | ((le_u32(bytes, offset + 4) as u64) << 32)
+}
+
+/// Read the partition table of a disk of `disk_sectors` sectors. Entries
+/// that do not fit on the disk are left out.
+pub fn read_partition_table<D: SectorDevice>(dev: &mut D, disk_sectors: u64) -> Result<Option<PartitionTable>, D::Error> {
+    let mut mbr = [0u8; SECTOR_SIZE];
+    dev.read_sector(0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Ok(None);
+    }
+
+    let entries: [&[u8]; 4] = core::array::from_fn(|i| &mbr[MBR_TABLE_OFFSET + 16 * i..MBR_TABLE_OFFSET + 16 * (i + 1)]);
+    // Boot sectors of bare FAT volumes also end in 55 AA, but their bytes
+    // here are code, which rarely passes for four valid boot flags
+    if entries.iter().any(|entry| entry[0] != 0x00 && entry[0] != 0x80) {
+        return Ok(None);
+    }
+
+    if entries.iter().any(|entry| entry[4] == MBR_TYPE_GPT_PROTECTIVE) {
+        if let Some(table) = read_gpt(dev, disk_sectors)? {
+            return Ok(Some(table));
+        }
+    }
+
+    let mut partitions = Vec::new();
+    let mut extended = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let kind = entry[4];
+        let start = le_u32(entry, 8) as u64;
+        let sectors = le_u32(entry, 12) as u64;
+        if kind == 0 || sectors == 0 || start + sectors > disk_sectors {
+            continue;
+        }
+        if matches!(kind, 0x05 | 0x0F | 0x85) {
+            extended.get_or_insert(start);
+        }
+        let partition = Partition { number: i as u8 + 1, start, sectors, kind: PartitionKind::Mbr(kind), name: String::new() };
+        let _ = partitions.push(partition);
+    }
+
+    if let Some(extended_start) = extended {
+        read_logical(dev, disk_sectors, extended_start, &mut partitions)?;
+    }
+
+    Ok(Some(PartitionTable { scheme: PartitionScheme::Mbr, partitions }))
+}
+
+/// Follow the EBR chain of the extended partition at `extended_start`
+fn read_logical<D: SectorDevice>(
+    dev: &mut D,
+    disk_sectors: u64,
+    extended_start: u64,
+    partitions: &mut Vec<Partition, MAX_PARTITIONS>,
+) -> Result<(), D::Error> {
+    let mut ebr_sector = extended_start;
+    let mut ebr = [0u8; SECTOR_SIZE];
+
+    for number in 5..5 + MAX_LOGICAL as u8 {
+        if ebr_sector >= disk_sectors || partitions.is_full() {
+            break;
+        }
+        dev.read_sector(ebr_sector, &mut ebr)?;
+        if ebr[510..512] != MBR_SIGNATURE {
+            break;
+        }
+
+        let logical = &ebr[MBR_TABLE_OFFSET..MBR_TABLE_OFFSET + 16];
+        let kind = logical[4];
+        let start = ebr_sector + le_u32(logical, 8) as u64;
+        let sectors = le_u32(logical, 12) as u64;
+        if kind != 0 && sectors != 0 && start + sectors <= disk_sectors {
+            let partition = Partition { number, start, sectors, kind: PartitionKind::Mbr(kind), name: String::new() };
+            let _ = partitions.push(partition);
+        }
+
+        // The link to the next EBR is relative to the extended partition
+        let next = &ebr[MBR_TABLE_OFFSET + 16..MBR_TABLE_OFFSET + 32];
+        let offset = le_u32(next, 8) as u64;
+        if next[4] == 0 || offset == 0 {
+            break;
+        }
+        ebr_sector = extended_start + offset;
+    }
+    Ok(())
+}
+
+/// Read the primary GPT; `None` if it is missing or fails its checks
+fn read_gpt<D: SectorDevice>(dev: &mut D, disk_sectors: u64) -> Result<Option<PartitionTable>, D::Error> {
+    let mut header = [0u8; SECTOR_SIZE];
+    dev.read_sector(1, &mut header)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+
+    let header_size = le_u32(&header, 12) as usize;
+    if !(92..=SECTOR_SIZE).contains(&header_size) {
+        return Ok(None);
+    }
+    let header_crc = le_u32(&header, 16);
+    let mut check = header;
+    check[16..20].fill(0);
+    if crc32_update(0, &check[..header_size]) != header_crc {
+        return Ok(None);
+    }
+
+    let entries_lba = le_u64(&header, 72);
+    let entry_count = le_u32(&header, 80) as u64;
+    let entry_size = le_u32(&header, 84) as u64;
+    let entries_crc = le_u32(&header, 88);
+    if entry_size < 128 || !entry_size.is_power_of_two() || entry_count * entry_size > MAX_GPT_ENTRY_BYTES {
+        return Ok(None);
+    }
+
+    let total = entry_count * entry_size;
+    let mut partitions = Vec::new();
+    let mut crc = 0;
+    let mut sector = [0u8; SECTOR_SIZE];
+
+    for index in 0..total.div_ceil(SECTOR_SIZE as u64) {
+        dev.read_sector(entries_lba + index, &mut sector)?;
+        let sector_pos = index * SECTOR_SIZE as u64;
+        let take = (total - sector_pos).min(SECTOR_SIZE as u64) as usize;
+        crc = crc32_update(crc, &sector[..take]);
+
+        // Entries starting in this sector; all are at least 128 bytes and
+        // a power of two long, so their first 128 bytes never straddle two
+        let first = sector_pos.div_ceil(entry_size) * entry_size;
+        let mut pos = first;
+        while pos < sector_pos + take as u64 {
+            let entry = &sector[(pos - sector_pos) as usize..][..128];
+            let number = pos / entry_size + 1;
+            pos += entry_size;
+
+            let mut type_guid = [0u8; 16];
+            type_guid.copy_from_slice(&entry[0..16]);
+            if type_guid == [0; 16] {
+                continue;
+            }
+            let first_lba = le_u64(entry, 32);
+            let last_lba = le_u64(entry, 40);
+            if first_lba > last_lba || last_lba >= disk_sectors || number > u8::MAX as u64 {
+                continue;
+            }
+
+            let mut name = String::new();
+            let units = (0..36).map(|i| u16::from_le_bytes([entry[56 + 2 * i], entry[57 + 2 * i]]));
+            for c in core::char::decode_utf16(units.take_while(|&unit| unit != 0)) {
+                if name.push(c.unwrap_or(char::REPLACEMENT_CHARACTER)).is_err() {
+                    break;
+                }
+            }
+
+            let partition = Partition {
+                number: number as u8,
+                start: first_lba,
+                sectors: last_lba - first_lba + 1,
+                kind: PartitionKind::Gpt(type_guid),
+                name,
+            };
+            let _ = partitions.push(partition);
+        }
+    }
+
+    if crc != entries_crc {
+        return Ok(None);
+    }
+    Ok(Some(PartitionTable { scheme: PartitionScheme::Gpt, partitions }))
+}
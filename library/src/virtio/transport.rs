@@ -0,0 +1,212 @@
+//! Minimal polled VirtIO MMIO transport
+//!
+//! Just enough for simple block and network drivers: device discovery,
+//! feature negotiation and small split virtqueues in static memory.
+//! Everything is synchronous and interrupt free, and buffer addresses are
+//! handed to the device as-is, so callers must run with physical addresses
+//! (the bootloader does).
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+use super::mmio::*;
+use crate::mmio::RegisterBlock;
+
+/// Entries per virtqueue
+pub const QUEUE_SIZE: u16 = 8;
+
+/// Backing memory for one virtqueue: descriptors and available ring in the
+/// first page, used ring in the second (the legacy layout; modern devices
+/// accept it too)
+#[repr(C, align(4096))]
+pub struct QueueMemory([u8; 2 * PAGE_SIZE]);
+
+impl QueueMemory {
+    pub const fn new() -> Self {
+        QueueMemory([0; 2 * PAGE_SIZE])
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A buffer in a descriptor chain
+pub struct Buffer {
+    pub addr: usize,
+    pub len: usize,
+    /// Device writes into the buffer
+    pub writable: bool,
+}
+
+/// A split virtqueue
+pub struct Queue {
+    index: u16,
+    desc: usize,
+    avail: usize,
+    used: usize,
+    avail_idx: u16,
+    used_idx: u16,
+}
+
+impl Queue {
+    /// Place `chain` in the descriptor table starting at descriptor `head`
+    /// and make it available. Returns `head`, which the used ring reports.
+    pub fn submit(&mut self, head: u16, chain: &[Buffer]) -> u16 {
+        let desc = self.desc as *mut Desc;
+        for (i, buffer) in chain.iter().enumerate() {
+            let index = (head as usize + i) % QUEUE_SIZE as usize;
+            let mut flags = if buffer.writable { VIRTQ_DESC_F_WRITE } else { 0 };
+            let mut next = 0;
+            if i + 1 < chain.len() {
+                flags |= VIRTQ_DESC_F_NEXT;
+                next = ((index + 1) % QUEUE_SIZE as usize) as u16;
+            }
+            unsafe {
+                write_volatile(desc.add(index), Desc { addr: buffer.addr as u64, len: buffer.len as u32, flags, next });
+            }
+        }
+
+        // avail: flags (u16), idx (u16), ring[QUEUE_SIZE] (u16)
+        unsafe {
+            let ring = (self.avail + 4) as *mut u16;
+            write_volatile(ring.add((self.avail_idx % QUEUE_SIZE) as usize), head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            write_volatile((self.avail + 2) as *mut u16, self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+        head
+    }
+
+    /// Next completed chain: (head descriptor, bytes written by the device)
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        // used: flags (u16), idx (u16), ring[QUEUE_SIZE] of (id: u32, len: u32)
+        unsafe {
+            fence(Ordering::SeqCst);
+            let device_idx = read_volatile((self.used + 2) as *const u16);
+            if device_idx == self.used_idx {
+                return None;
+            }
+            let elem = (self.used + 4 + (self.used_idx % QUEUE_SIZE) as usize * 8) as *const u32;
+            let id = read_volatile(elem);
+            let len = read_volatile(elem.add(1));
+            self.used_idx = self.used_idx.wrapping_add(1);
+            Some((id as u16, len))
+        }
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+}
+
+/// A VirtIO MMIO device
+pub struct Device {
+    regs: RegisterBlock,
+    version: u32,
+}
+
+impl Device {
+    /// Find the first device with `device_id`
+    pub fn find(device_id: u32) -> Option<Device> {
+        Self::find_all(device_id).next()
+    }
+
+    /// Every device with `device_id`, in slot order
+    pub fn find_all(device_id: u32) -> impl Iterator<Item = Device> {
+        enumerate()
+            .filter(move |slot| slot.device_id == device_id)
+            .map(|slot| Device { regs: unsafe { RegisterBlock::new(slot.base) }, version: slot.version })
+    }
+
+    pub fn base(&self) -> usize {
+        self.regs.base()
+    }
+
+    /// Reset the device and accept the subset of `wanted` features it offers.
+    /// Returns the negotiated features, or None if the device refused them.
+    pub fn negotiate(&self, wanted: u64) -> Option<u64> {
+        self.regs.write(VIRTIO_MMIO_STATUS, 0);
+        self.regs.write(VIRTIO_MMIO_STATUS, VIRTIO_STATUS_ACKNOWLEDGE);
+        self.regs.write(VIRTIO_MMIO_STATUS, VIRTIO_STATUS_ACKNOWLEDGE | VIRTIO_STATUS_DRIVER);
+
+        self.regs.write(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 0);
+        let lo = self.regs.read(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+        self.regs.write(VIRTIO_MMIO_DEVICE_FEATURES_SEL, 1);
+        let hi = self.regs.read(VIRTIO_MMIO_DEVICE_FEATURES) as u64;
+
+        let mut features = ((hi << 32) | lo) & wanted;
+        if self.version != 1 {
+            features |= ((hi << 32) | lo) & VIRTIO_F_VERSION_1;
+        }
+
+        self.regs.write(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 0);
+        self.regs.write(VIRTIO_MMIO_DRIVER_FEATURES, features as u32);
+        self.regs.write(VIRTIO_MMIO_DRIVER_FEATURES_SEL, 1);
+        self.regs.write(VIRTIO_MMIO_DRIVER_FEATURES, (features >> 32) as u32);
+
+        self.regs.write(VIRTIO_MMIO_STATUS, VIRTIO_STATUS_ACKNOWLEDGE | VIRTIO_STATUS_DRIVER | VIRTIO_STATUS_FEATURES_OK);
+        if self.regs.read(VIRTIO_MMIO_STATUS) & VIRTIO_STATUS_FEATURES_OK == 0 {
+            return None;
+        }
+        Some(features)
+    }
+
+    /// Set up queue `index` in `memory`
+    pub fn setup_queue(&self, index: u16, memory: &'static mut QueueMemory) -> Option<Queue> {
+        self.regs.write(VIRTIO_MMIO_QUEUE_SEL, index as u32);
+        if self.regs.read(VIRTIO_MMIO_QUEUE_NUM_MAX) < QUEUE_SIZE as u32 {
+            return None;
+        }
+        self.regs.write(VIRTIO_MMIO_QUEUE_NUM, QUEUE_SIZE as u32);
+
+        memory.0.fill(0);
+        let desc = memory.0.as_ptr() as usize;
+        let avail = desc + 16 * QUEUE_SIZE as usize;
+        let used = desc + PAGE_SIZE;
+
+        if self.version == 1 {
+            self.regs.write(VIRTIO_MMIO_GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_PFN, (desc / PAGE_SIZE) as u32);
+        } else {
+            self.regs.write(VIRTIO_MMIO_QUEUE_DESC_LOW, desc as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_DESC_HIGH, (desc >> 32) as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_DRIVER_LOW, avail as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_DRIVER_HIGH, (avail >> 32) as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_DEVICE_LOW, used as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_DEVICE_HIGH, (used >> 32) as u32);
+            self.regs.write(VIRTIO_MMIO_QUEUE_READY, 1);
+        }
+
+        Some(Queue { index, desc, avail, used, avail_idx: 0, used_idx: 0 })
+    }
+
+    /// Tell the device the driver is ready
+    pub fn driver_ok(&self) {
+        // The queues set up in memory must be visible before the device starts
+        crate::mmio::io_fence();
+        self.regs.set_bits(VIRTIO_MMIO_STATUS, VIRTIO_STATUS_DRIVER_OK);
+    }
+
+    /// Tell the device new buffers are available in `queue`
+    pub fn notify(&self, queue: &Queue) {
+        self.regs.write_fenced(VIRTIO_MMIO_QUEUE_NOTIFY, queue.index() as u32);
+    }
+
+    /// Read a byte of device configuration space
+    pub fn config_u8(&self, offset: usize) -> u8 {
+        self.regs.read(config_register(offset))
+    }
+
+    /// Read a 32-bit word of device configuration space
+    pub fn config_u32(&self, offset: usize) -> u32 {
+        self.regs.read(config_register(offset))
+    }
+}
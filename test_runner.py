@@ -79,6 +79,7 @@ class ElinOSTestRunner:
             ("ls", "Total files:"),
             ("touch aaa", "Created file"),
             ("ls", "FILE  aaa"),
+            ("touch aaa", None),
             ("rm aaa", "Removed file"),
             ("touch ccc", "Created file"),
             ("mkdir aaa", "Created directory"),
@@ -88,14 +89,63 @@ class ElinOSTestRunner:
 
             # File operations
             ("cat test.txt", "This is a test file for the elinOS filesystem"),  # Just check it doesn't crash
+            ("update /test.copy=/test.txt", "Replaced 1 file(s)"),
+            ("rm /test.copy", "Removed file"),
+            ("ln -s test.txt /test.link", "Linked"),
+            ("cat /test.link", "This is a test file"),
+            ("rm /test.link", "Removed file"),
+            ("cat test.txt", "This is a test file"),
+            ("ln /test.txt /test.hard", "Linked"),
+            ("ls -l /", "drwx"),
+            ("mv /test.hard /test.moved", "Moved"),
+            ("rm /test.moved", "Removed file"),
+            ("mkdir /mvdir", "Created directory"),
+            ("mv /mvdir /mvdir2", "Moved"),
+            ("rmdir /mvdir2", "Removed directory"),
+            ("cat test.txt", "This is a test file"),
             
             # ELF execution
             ("./hello_world", "Hello World from C on elinOS!"),
+            ("./timer_test", "Timer test done"),
+            ("./read_test", "Read test done"),
+            ("cat /read_test.out", "written from a page not yet read in"),
+            ("rm /read_test.out", "Removed file"),
+            ("./gfx_demo", "Graphics demo done"),
             
             # System commands
             ("help", "Program Execution"),
             ("memory", "Memory Regions"),
+            ("health", "Status:"),
+            ("traps", "supervisor timer interrupt"),
+            ("usb", "No USB host controller"),
+            ("beep -l 50", "440 Hz for 50 ms on the terminal bell"),
+            ("memdebug", "Allocation tracking is off"),
+            ("kconfig dump", "# elinOS configuration"),
+            ("cat /proc/slabinfo", "virtio_blk_req"),
+            ("cd /proc//./sys/../", None),
+            ("pwd", "/proc"),
+            ("cat meminfo", "MemTotal"),
+            ("cd /", None),
+            ("sysctl kernel.watchdog.timeout_secs", "kernel.watchdog.timeout_secs = 30"),
+            ("bootchart", "Shell started at"),
+            ("pmap 1", "kernel's address space"),
+            ("swapon", "Swapped out:"),
+            ("loglevel", "virtio   global"),
             ("version", "elinOS"),
+            ("selftest", "9 passed, 0 failed"),
+            ("at +3600 sync", "Job 1 runs in 3600s"),
+            ("at -d 1", "Removed job 1"),
+            ("false || echo chained", "chained"),
+            ("test -d / && echo isdir", "isdir"),
+            ("run /missing.sh", "Script not found"),
+            ("alias ll='ls -l'", None),
+            ("ll /", "drwx"),
+            ("unalias ll", None),
+            ("fg", "No background jobs"),
+            ("kill 1", "Cannot kill the shell"),
+            ("ls &", "Only programs can run in the background"),
+            ("./hello_world &", "[1] "),
+            ("fg", None),
             ("mmap", "Total mapped"),
         ]
 
@@ -103,6 +153,8 @@ class ElinOSTestRunner:
         if self.runtype == 'fb':
             tests.extend([
                 ("graphics", "Total pixels:"),
+                ("screenshot shot.ppm", "screenshot to"),
+                ("rm shot.ppm", "Removed file"),
                 # ("gfxtest", "[o] Graphics tests completed successfully"), # Removed - simple TTY console doesn't need graphics tests
             ])
         